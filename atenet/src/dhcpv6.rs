@@ -0,0 +1,421 @@
+//! Minimal DHCPv6 (RFC 8415) messages as used by the DHCPv6 server of the
+//! switch (smoltcp only implements DHCPv4)
+use byteorder::BigEndian;
+use byteorder::ByteOrder;
+use smoltcp::wire::EthernetAddress;
+use smoltcp::wire::Ipv6Address;
+
+pub const DHCPV6_CLIENT_PORT: u16 = 546;
+pub const DHCPV6_SERVER_PORT: u16 = 547;
+
+/// Leases never expire (the same as the DHCPv4 server)
+pub const DHCPV6_INFINITE_LIFETIME: u32 = 0xffff_ffff;
+
+const OPTION_CLIENTID: u16 = 1;
+const OPTION_SERVERID: u16 = 2;
+const OPTION_IA_NA: u16 = 3;
+const OPTION_IAADDR: u16 = 5;
+const OPTION_STATUS_CODE: u16 = 13;
+const OPTION_RAPID_COMMIT: u16 = 14;
+const OPTION_DNS_SERVERS: u16 = 23;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dhcpv6MessageType
+{
+    Solicit,
+    Advertise,
+    Request,
+    Confirm,
+    Renew,
+    Rebind,
+    Reply,
+    Release,
+    Decline,
+    InformationRequest,
+    Unknown(u8),
+}
+
+impl From<u8>
+for Dhcpv6MessageType
+{
+    fn from(val: u8) -> Dhcpv6MessageType {
+        use Dhcpv6MessageType::*;
+        match val {
+            1 => Solicit,
+            2 => Advertise,
+            3 => Request,
+            4 => Confirm,
+            5 => Renew,
+            6 => Rebind,
+            7 => Reply,
+            8 => Release,
+            9 => Decline,
+            11 => InformationRequest,
+            a => Unknown(a),
+        }
+    }
+}
+
+impl From<Dhcpv6MessageType>
+for u8
+{
+    fn from(val: Dhcpv6MessageType) -> u8 {
+        use Dhcpv6MessageType::*;
+        match val {
+            Solicit => 1,
+            Advertise => 2,
+            Request => 3,
+            Confirm => 4,
+            Renew => 5,
+            Rebind => 6,
+            Reply => 7,
+            Release => 8,
+            Decline => 9,
+            InformationRequest => 11,
+            Unknown(a) => a,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dhcpv6Status
+{
+    Success,
+    UnspecFail,
+    NoAddrsAvail,
+    NoBinding,
+    NotOnLink,
+    Other(u16),
+}
+
+impl From<u16>
+for Dhcpv6Status
+{
+    fn from(val: u16) -> Dhcpv6Status {
+        use Dhcpv6Status::*;
+        match val {
+            0 => Success,
+            1 => UnspecFail,
+            2 => NoAddrsAvail,
+            3 => NoBinding,
+            4 => NotOnLink,
+            a => Other(a),
+        }
+    }
+}
+
+impl From<Dhcpv6Status>
+for u16
+{
+    fn from(val: Dhcpv6Status) -> u16 {
+        use Dhcpv6Status::*;
+        match val {
+            Success => 0,
+            UnspecFail => 1,
+            NoAddrsAvail => 2,
+            NoBinding => 3,
+            NotOnLink => 4,
+            Other(a) => a,
+        }
+    }
+}
+
+/// Identity association for non-temporary addresses (the addresses that
+/// are leased to a particular interface of the client)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dhcpv6IaNa
+{
+    pub iaid: u32,
+    pub t1: u32,
+    pub t2: u32,
+    pub addrs: Vec<Ipv6Address>,
+    pub status: Option<Dhcpv6Status>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dhcpv6Repr
+{
+    pub message_type: Dhcpv6MessageType,
+    /// Only the lower 24 bits are sent
+    pub transaction_id: u32,
+    pub client_id: Option<Vec<u8>>,
+    pub server_id: Option<Vec<u8>>,
+    pub ia_na: Option<Dhcpv6IaNa>,
+    pub rapid_commit: bool,
+    pub dns_servers: Vec<Ipv6Address>,
+    pub status: Option<Dhcpv6Status>,
+}
+
+fn parse_status(data: &[u8]) -> smoltcp::Result<Dhcpv6Status> {
+    if data.len() < 2 {
+        return Err(smoltcp::Error::Truncated);
+    }
+    Ok(BigEndian::read_u16(&data[..2]).into())
+}
+
+/// Iterates through the options in a buffer as (code, data) pairs
+fn parse_options(mut data: &[u8], mut f: impl FnMut(u16, &[u8]) -> smoltcp::Result<()>) -> smoltcp::Result<()> {
+    while data.len() > 0 {
+        if data.len() < 4 {
+            return Err(smoltcp::Error::Truncated);
+        }
+        let code = BigEndian::read_u16(&data[0..2]);
+        let len = BigEndian::read_u16(&data[2..4]) as usize;
+        if data.len() < 4 + len {
+            return Err(smoltcp::Error::Truncated);
+        }
+        f(code, &data[4..4 + len])?;
+        data = &data[4 + len..];
+    }
+    Ok(())
+}
+
+fn emit_option(buf: &mut Vec<u8>, code: u16, data: &[u8]) {
+    let mut hdr = [0u8; 4];
+    BigEndian::write_u16(&mut hdr[0..2], code);
+    BigEndian::write_u16(&mut hdr[2..4], data.len() as u16);
+    buf.extend_from_slice(&hdr[..]);
+    buf.extend_from_slice(data);
+}
+
+fn emit_status(buf: &mut Vec<u8>, status: Dhcpv6Status) {
+    let mut data = [0u8; 2];
+    BigEndian::write_u16(&mut data[..], status.into());
+    emit_option(buf, OPTION_STATUS_CODE, &data[..]);
+}
+
+impl Dhcpv6IaNa
+{
+    fn parse(data: &[u8]) -> smoltcp::Result<Dhcpv6IaNa> {
+        if data.len() < 12 {
+            return Err(smoltcp::Error::Truncated);
+        }
+        let mut ret = Dhcpv6IaNa {
+            iaid: BigEndian::read_u32(&data[0..4]),
+            t1: BigEndian::read_u32(&data[4..8]),
+            t2: BigEndian::read_u32(&data[8..12]),
+            addrs: Vec::new(),
+            status: None,
+        };
+        parse_options(&data[12..], |code, data| {
+            match code {
+                OPTION_IAADDR => {
+                    if data.len() < 24 {
+                        return Err(smoltcp::Error::Truncated);
+                    }
+                    ret.addrs.push(Ipv6Address::from_bytes(&data[0..16]));
+                }
+                OPTION_STATUS_CODE => {
+                    ret.status = Some(parse_status(data)?);
+                }
+                _ => { }
+            }
+            Ok(())
+        })?;
+        Ok(ret)
+    }
+
+    fn emit(&self, buf: &mut Vec<u8>) {
+        let mut data = vec![0u8; 12];
+        BigEndian::write_u32(&mut data[0..4], self.iaid);
+        BigEndian::write_u32(&mut data[4..8], self.t1);
+        BigEndian::write_u32(&mut data[8..12], self.t2);
+        for addr in self.addrs.iter() {
+            let mut iaaddr = vec![0u8; 24];
+            iaaddr[0..16].copy_from_slice(addr.as_bytes());
+            BigEndian::write_u32(&mut iaaddr[16..20], DHCPV6_INFINITE_LIFETIME);
+            BigEndian::write_u32(&mut iaaddr[20..24], DHCPV6_INFINITE_LIFETIME);
+            emit_option(&mut data, OPTION_IAADDR, &iaaddr[..]);
+        }
+        if let Some(status) = self.status {
+            emit_status(&mut data, status);
+        }
+        emit_option(buf, OPTION_IA_NA, &data[..]);
+    }
+}
+
+impl Dhcpv6Repr
+{
+    pub fn parse(data: &[u8]) -> smoltcp::Result<Dhcpv6Repr> {
+        if data.len() < 4 {
+            return Err(smoltcp::Error::Truncated);
+        }
+        let mut ret = Dhcpv6Repr {
+            message_type: data[0].into(),
+            transaction_id: BigEndian::read_u24(&data[1..4]),
+            client_id: None,
+            server_id: None,
+            ia_na: None,
+            rapid_commit: false,
+            dns_servers: Vec::new(),
+            status: None,
+        };
+        parse_options(&data[4..], |code, data| {
+            match code {
+                OPTION_CLIENTID => {
+                    ret.client_id = Some(data.to_vec());
+                }
+                OPTION_SERVERID => {
+                    ret.server_id = Some(data.to_vec());
+                }
+                OPTION_IA_NA => {
+                    // Only the first identity association is served
+                    if ret.ia_na.is_none() {
+                        ret.ia_na = Some(Dhcpv6IaNa::parse(data)?);
+                    }
+                }
+                OPTION_RAPID_COMMIT => {
+                    ret.rapid_commit = true;
+                }
+                OPTION_DNS_SERVERS => {
+                    if data.len() % 16 != 0 {
+                        return Err(smoltcp::Error::Malformed);
+                    }
+                    ret.dns_servers = data
+                        .chunks(16)
+                        .map(|a| Ipv6Address::from_bytes(a))
+                        .collect();
+                }
+                OPTION_STATUS_CODE => {
+                    ret.status = Some(parse_status(data)?);
+                }
+                _ => { }
+            }
+            Ok(())
+        })?;
+        Ok(ret)
+    }
+
+    pub fn emit(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; 4];
+        buf[0] = self.message_type.into();
+        BigEndian::write_u24(&mut buf[1..4], self.transaction_id & 0x00ff_ffff);
+        if let Some(client_id) = self.client_id.as_ref() {
+            emit_option(&mut buf, OPTION_CLIENTID, &client_id[..]);
+        }
+        if let Some(server_id) = self.server_id.as_ref() {
+            emit_option(&mut buf, OPTION_SERVERID, &server_id[..]);
+        }
+        if let Some(ia_na) = self.ia_na.as_ref() {
+            ia_na.emit(&mut buf);
+        }
+        if self.rapid_commit {
+            emit_option(&mut buf, OPTION_RAPID_COMMIT, &[]);
+        }
+        if self.dns_servers.len() > 0 {
+            let data = self.dns_servers
+                .iter()
+                .flat_map(|a| a.as_bytes().to_vec())
+                .collect::<Vec<_>>();
+            emit_option(&mut buf, OPTION_DNS_SERVERS, &data[..]);
+        }
+        if let Some(status) = self.status {
+            emit_status(&mut buf, status);
+        }
+        buf
+    }
+
+    /// Returns true if the client is asking for addresses to be leased
+    pub fn wants_addrs(&self) -> bool {
+        use Dhcpv6MessageType::*;
+        self.ia_na.is_some() &&
+        match self.message_type {
+            Solicit | Request | Renew | Rebind => true,
+            _ => false
+        }
+    }
+
+    /// Returns the addresses that the client is giving back (Release) or
+    /// refusing because they are already in use on the link (Decline)
+    pub fn returned_addrs(&self) -> Vec<Ipv6Address> {
+        use Dhcpv6MessageType::*;
+        match self.message_type {
+            Release | Decline => {
+                self.ia_na
+                    .as_ref()
+                    .map(|ia_na| ia_na.addrs.clone())
+                    .unwrap_or_default()
+            },
+            _ => Vec::new()
+        }
+    }
+}
+
+/// DUID (based on the link-layer address) that identifies a server or client
+pub fn duid_ll(mac: &EthernetAddress) -> Vec<u8> {
+    let mut ret = vec![0u8, 3u8, 0u8, 1u8];
+    ret.extend_from_slice(mac.as_bytes());
+    ret
+}
+
+/// Builds the reply that the server sends to a message from a client
+/// with the addresses that are leased to it (None when the message is
+/// not meant for this server or needs no reply)
+pub fn server_reply(request: &Dhcpv6Repr, server_id: &[u8], addrs: &[Ipv6Address], dns_servers: &[Ipv6Address]) -> Option<Dhcpv6Repr>
+{
+    use Dhcpv6MessageType::*;
+
+    // Messages that name a server are only answered by that server
+    if let Some(id) = request.server_id.as_ref() {
+        if &id[..] != server_id {
+            return None;
+        }
+    }
+
+    let mut reply = Dhcpv6Repr {
+        message_type: Reply,
+        transaction_id: request.transaction_id,
+        client_id: Some(request.client_id.clone()?),
+        server_id: Some(server_id.to_vec()),
+        ia_na: None,
+        rapid_commit: false,
+        dns_servers: dns_servers.to_vec(),
+        status: None,
+    };
+
+    // Leases the addresses to the identity association of the client
+    let lease = |ia_na: &Dhcpv6IaNa| {
+        Dhcpv6IaNa {
+            iaid: ia_na.iaid,
+            t1: DHCPV6_INFINITE_LIFETIME,
+            t2: DHCPV6_INFINITE_LIFETIME,
+            addrs: addrs.to_vec(),
+            status: match addrs.is_empty() {
+                true => Some(Dhcpv6Status::NoAddrsAvail),
+                false => None,
+            },
+        }
+    };
+
+    match request.message_type {
+        Solicit => {
+            // A rapid commit skips the advertisement and leases straight away
+            if request.rapid_commit {
+                reply.rapid_commit = true;
+            } else {
+                reply.message_type = Advertise;
+            }
+            reply.ia_na = request.ia_na.as_ref().map(lease);
+        }
+        Request | Renew | Rebind => {
+            if request.message_type == Request && request.server_id.is_none() {
+                return None;
+            }
+            reply.ia_na = request.ia_na.as_ref().map(lease);
+        }
+        Confirm | InformationRequest => {
+            reply.status = Some(Dhcpv6Status::Success);
+        }
+        Release | Decline => {
+            if request.server_id.is_none() {
+                return None;
+            }
+            reply.dns_servers = Vec::new();
+            reply.status = Some(Dhcpv6Status::Success);
+        }
+        _ => {
+            return None;
+        }
+    }
+    Some(reply)
+}
@@ -267,6 +267,27 @@ impl Gateway
         false
     }
 
+    pub fn process_ndp_reply(&self, pck: &[u8], switch: &Arc<Switch>, state: &mut MutexGuard<DataPlane>) -> bool
+    {
+        // Neighbor solicitations for the gateway addresses and router
+        // solicitations are answered by the gateway (IPv6 has no ARP)
+        let reply = super::ipv6::answer_neighbor_solicit(pck, Self::MAC, &self.ips[..])
+            .or_else(|| super::ipv6::answer_router_solicit(pck, Self::MAC, &state.cidrs[..]));
+
+        if let Some((dst_mac, pck)) = reply
+        {
+            #[cfg(feature="tcpdump")]
+            super::switch::tcpdump(switch.me_node_addr, switch.name.as_str(), &pck[..]);
+
+            // Snoop the packet and process it
+            switch.process_snoop(state, &pck[..], None);
+            switch.process_unicast_for_ports(state, &Self::MAC, &dst_mac, pck, true);
+            return true;
+        }
+
+        false
+    }
+
     pub fn process_arp_reply(&self, pck: &[u8], switch: &Arc<Switch>, state: &mut MutexGuard<DataPlane>) -> bool
     {
         if let Ok(frame_mac) = EthernetFrame::new_checked(pck)
@@ -0,0 +1,368 @@
+//! Neighbor discovery, router advertisements (for SLAAC) and DHCPv6 framing
+//! for the gateway of the switch - the IPv6 equivalent of its ARP and DHCP
+//! handling
+use std::collections::HashSet;
+use std::net::Ipv6Addr;
+use byteorder::BigEndian;
+use byteorder::ByteOrder;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::wire::EthernetAddress;
+use smoltcp::wire::EthernetFrame;
+use smoltcp::wire::EthernetProtocol;
+use smoltcp::wire::EthernetRepr;
+use smoltcp::wire::Icmpv6Packet;
+use smoltcp::wire::Icmpv6Repr;
+use smoltcp::wire::IpAddress;
+use smoltcp::wire::IpCidr;
+use smoltcp::wire::IpProtocol;
+use smoltcp::wire::Ipv6Address;
+use smoltcp::wire::Ipv6Packet;
+use smoltcp::wire::Ipv6Repr;
+use smoltcp::wire::NdiscRepr;
+use smoltcp::wire::UdpPacket;
+use smoltcp::wire::UdpRepr;
+
+use super::dhcpv6::*;
+
+/// Neighbor discovery packets must not have passed through a router
+pub const NDISC_HOP_LIMIT: u8 = 255;
+
+/// How long (in seconds) the clients may use the gateway as a default router
+pub const ROUTER_LIFETIME: u16 = 1800;
+/// How long (in seconds) the addresses formed from the prefixes remain valid
+pub const PREFIX_VALID_LIFETIME: u32 = 86400;
+/// How long (in seconds) the addresses formed from the prefixes are preferred
+pub const PREFIX_PREFERRED_LIFETIME: u32 = 14400;
+
+const ICMPV6_ROUTER_ADVERT: u8 = 134;
+const ICMPV6_NEIGHBOR_ADVERT: u8 = 136;
+
+const NDISC_OPTION_SOURCE_LLADDR: u8 = 1;
+const NDISC_OPTION_TARGET_LLADDR: u8 = 2;
+const NDISC_OPTION_PREFIX_INFO: u8 = 3;
+const NDISC_OPTION_RDNSS: u8 = 25;
+
+const NDISC_ROUTER_FLAG_MANAGED: u8 = 0x80;
+const NDISC_ROUTER_FLAG_OTHER: u8 = 0x40;
+const NDISC_NEIGHBOR_FLAG_ROUTER: u8 = 0x80;
+const NDISC_NEIGHBOR_FLAG_SOLICITED: u8 = 0x40;
+const NDISC_NEIGHBOR_FLAG_OVERRIDE: u8 = 0x20;
+const NDISC_PREFIX_FLAG_ON_LINK: u8 = 0x80;
+const NDISC_PREFIX_FLAG_ADDRCONF: u8 = 0x40;
+
+/// All the DHCPv6 servers and relay agents on the link (ff02::1:2)
+pub const ALL_DHCP_SERVERS: Ipv6Address = Ipv6Address([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2]);
+
+/// DNS servers that are handed out to the clients (the same servers as DHCPv4)
+pub fn dns_servers() -> Vec<Ipv6Address> {
+    vec![
+        Ipv6Address::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888),
+        Ipv6Address::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8844),
+    ]
+}
+
+/// Link-local address that is derived from a MAC address (modified EUI-64)
+pub fn link_local_addr(mac: &EthernetAddress) -> Ipv6Address {
+    let mac = mac.as_bytes();
+    let mut ip = [0u8; 16];
+    ip[0] = 0xfe;
+    ip[1] = 0x80;
+    ip[8] = mac[0] ^ 0x02;
+    ip[9] = mac[1];
+    ip[10] = mac[2];
+    ip[11] = 0xff;
+    ip[12] = 0xfe;
+    ip[13] = mac[3];
+    ip[14] = mac[4];
+    ip[15] = mac[5];
+    Ipv6Address(ip)
+}
+
+/// MAC address that multicast packets to this IP address are sent to
+pub fn multicast_mac(ip: &Ipv6Address) -> EthernetAddress {
+    let ip = ip.as_bytes();
+    EthernetAddress([0x33, 0x33, ip[12], ip[13], ip[14], ip[15]])
+}
+
+/// Wraps an IPv6 payload in an ethernet frame
+pub fn build_frame(src_mac: EthernetAddress, dst_mac: EthernetAddress, src_addr: Ipv6Address, dst_addr: Ipv6Address, next_header: IpProtocol, hop_limit: u8, payload: &[u8]) -> Vec<u8>
+{
+    // Build the IPv6 payload
+    let ipv6_repr = Ipv6Repr {
+        src_addr,
+        dst_addr,
+        next_header,
+        payload_len: payload.len(),
+        hop_limit,
+    };
+    let mut ip_bytes = vec![0xa5; ipv6_repr.buffer_len() + payload.len()];
+    let mut ip_packet = Ipv6Packet::new_unchecked(&mut ip_bytes[..]);
+    ipv6_repr.emit(&mut ip_packet);
+    ip_packet.payload_mut().copy_from_slice(payload);
+
+    // Build the Ethernet payload
+    let eth_repr = EthernetRepr {
+        src_addr: src_mac,
+        dst_addr: dst_mac,
+        ethertype: EthernetProtocol::Ipv6,
+    };
+    let mut eth_bytes = vec![0x00; eth_repr.buffer_len() + ip_bytes.len()];
+    let mut eth_packet = EthernetFrame::new_unchecked(&mut eth_bytes[..]);
+    eth_repr.emit(&mut eth_packet);
+    eth_packet.payload_mut().copy_from_slice(&ip_bytes[..]);
+    eth_bytes
+}
+
+fn icmpv6_frame(src_mac: EthernetAddress, dst_mac: EthernetAddress, src_addr: Ipv6Address, dst_addr: Ipv6Address, mut icmp_bytes: Vec<u8>) -> Vec<u8>
+{
+    let mut icmp_packet = Icmpv6Packet::new_unchecked(&mut icmp_bytes[..]);
+    icmp_packet.fill_checksum(&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr));
+    build_frame(src_mac, dst_mac, src_addr, dst_addr, IpProtocol::Icmpv6, NDISC_HOP_LIMIT, &icmp_bytes[..])
+}
+
+/// Parses the neighbor discovery message in a frame (returning the source
+/// MAC and IP addresses along with the message)
+fn parse_ndisc(pck: &[u8]) -> Option<(EthernetAddress, Ipv6Address, NdiscRepr)>
+{
+    let frame_mac = EthernetFrame::new_checked(pck).ok()?;
+    if frame_mac.ethertype() != EthernetProtocol::Ipv6 {
+        return None;
+    }
+    let frame_ip = Ipv6Packet::new_checked(frame_mac.payload()).ok()?;
+    if frame_ip.next_header() != IpProtocol::Icmpv6 ||
+       frame_ip.hop_limit() != NDISC_HOP_LIMIT
+    {
+        return None;
+    }
+    let src_addr = frame_ip.src_addr();
+    let dst_addr = frame_ip.dst_addr();
+    let frame_icmp = Icmpv6Packet::new_checked(frame_ip.payload()).ok()?;
+    let repr = Icmpv6Repr::parse(
+        &IpAddress::Ipv6(src_addr),
+        &IpAddress::Ipv6(dst_addr),
+        &frame_icmp,
+        &ChecksumCapabilities::default()
+    ).ok()?;
+    match repr {
+        Icmpv6Repr::Ndisc(ndisc) => Some((frame_mac.src_addr(), src_addr, ndisc)),
+        _ => None
+    }
+}
+
+/// Builds the neighbor advertisement that the gateway sends in reply to a
+/// neighbor solicitation for one of its addresses (returning the MAC
+/// address it is sent to along with the frame)
+pub fn answer_neighbor_solicit(pck: &[u8], gw_mac: EthernetAddress, gw_ips: &[IpAddress]) -> Option<(EthernetAddress, Vec<u8>)>
+{
+    let (src_mac, src_addr, target_addr) = match parse_ndisc(pck)? {
+        (src_mac, src_addr, NdiscRepr::NeighborSolicit { target_addr, .. }) => (src_mac, src_addr, target_addr),
+        _ => { return None; }
+    };
+
+    // Only the addresses of the gateway are answered (the other hosts
+    // answer for themselves)
+    if target_addr != link_local_addr(&gw_mac) &&
+       gw_ips.iter().any(|ip| ip == &IpAddress::Ipv6(target_addr)) == false
+    {
+        return None;
+    }
+
+    // Duplicate address detection comes from an unspecified address and
+    // must be answered to all the nodes instead
+    let mut flags = NDISC_NEIGHBOR_FLAG_ROUTER | NDISC_NEIGHBOR_FLAG_OVERRIDE;
+    let (dst_mac, dst_addr) = if src_addr.is_unspecified() {
+        (multicast_mac(&Ipv6Address::LINK_LOCAL_ALL_NODES), Ipv6Address::LINK_LOCAL_ALL_NODES)
+    } else {
+        flags |= NDISC_NEIGHBOR_FLAG_SOLICITED;
+        (src_mac, src_addr)
+    };
+
+    let mut icmp_bytes = vec![0u8; 32];
+    icmp_bytes[0] = ICMPV6_NEIGHBOR_ADVERT;
+    icmp_bytes[4] = flags;
+    icmp_bytes[8..24].copy_from_slice(target_addr.as_bytes());
+    icmp_bytes[24] = NDISC_OPTION_TARGET_LLADDR;
+    icmp_bytes[25] = 1;
+    icmp_bytes[26..32].copy_from_slice(gw_mac.as_bytes());
+
+    Some((dst_mac, icmpv6_frame(gw_mac, dst_mac, target_addr, dst_addr, icmp_bytes)))
+}
+
+/// Builds the router advertisement that the gateway sends in reply to a
+/// router solicitation (returning the MAC address it is sent to along
+/// with the frame)
+pub fn answer_router_solicit(pck: &[u8], gw_mac: EthernetAddress, cidrs: &[IpCidr]) -> Option<(EthernetAddress, Vec<u8>)>
+{
+    let (src_mac, src_addr) = match parse_ndisc(pck)? {
+        (src_mac, src_addr, NdiscRepr::RouterSolicit { .. }) => (src_mac, src_addr),
+        _ => { return None; }
+    };
+
+    // Solicitations from hosts without an address yet are answered to all the nodes
+    let (dst_mac, dst_addr) = if src_addr.is_unspecified() {
+        (multicast_mac(&Ipv6Address::LINK_LOCAL_ALL_NODES), Ipv6Address::LINK_LOCAL_ALL_NODES)
+    } else {
+        (src_mac, src_addr)
+    };
+    Some((dst_mac, router_advert(gw_mac, dst_mac, dst_addr, cidrs)?))
+}
+
+/// Builds a router advertisement for the IPv6 CIDRs of the switch so that
+/// the clients can configure their addresses with SLAAC (or DHCPv6) and
+/// use the gateway as their default router (None if there are no IPv6 CIDRs)
+pub fn router_advert(gw_mac: EthernetAddress, dst_mac: EthernetAddress, dst_addr: Ipv6Address, cidrs: &[IpCidr]) -> Option<Vec<u8>>
+{
+    let prefixes = cidrs
+        .iter()
+        .filter_map(|cidr| {
+            match cidr.address() {
+                IpAddress::Ipv6(ip) => Some((ip, cidr.prefix_len())),
+                _ => None
+            }
+        })
+        .collect::<Vec<_>>();
+    if prefixes.is_empty() {
+        return None;
+    }
+
+    let mut icmp_bytes = vec![0u8; 16];
+    icmp_bytes[0] = ICMPV6_ROUTER_ADVERT;
+    icmp_bytes[4] = 64; // current hop limit
+    icmp_bytes[5] = NDISC_ROUTER_FLAG_MANAGED | NDISC_ROUTER_FLAG_OTHER;
+    BigEndian::write_u16(&mut icmp_bytes[6..8], ROUTER_LIFETIME);
+
+    // Source link-layer address
+    icmp_bytes.extend_from_slice(&[NDISC_OPTION_SOURCE_LLADDR, 1]);
+    icmp_bytes.extend_from_slice(gw_mac.as_bytes());
+
+    // Prefix information (only /64 prefixes can be used for SLAAC, the
+    // others are still on-link and addressed by DHCPv6)
+    for (ip, prefix_len) in prefixes {
+        let ip: Ipv6Addr = ip.into();
+        let mask = match prefix_len {
+            0 => 0u128,
+            a => u128::MAX << (128 - a as u32),
+        };
+        let prefix: Ipv6Addr = (u128::from(ip) & mask).into();
+
+        let mut flags = NDISC_PREFIX_FLAG_ON_LINK;
+        if prefix_len == 64 {
+            flags |= NDISC_PREFIX_FLAG_ADDRCONF;
+        }
+
+        let mut option = vec![0u8; 32];
+        option[0] = NDISC_OPTION_PREFIX_INFO;
+        option[1] = 4;
+        option[2] = prefix_len;
+        option[3] = flags;
+        BigEndian::write_u32(&mut option[4..8], PREFIX_VALID_LIFETIME);
+        BigEndian::write_u32(&mut option[8..12], PREFIX_PREFERRED_LIFETIME);
+        option[16..32].copy_from_slice(&prefix.octets()[..]);
+        icmp_bytes.extend_from_slice(&option[..]);
+    }
+
+    // Recursive DNS servers
+    let dns_servers = dns_servers();
+    let mut option = vec![0u8; 8];
+    option[0] = NDISC_OPTION_RDNSS;
+    option[1] = 1 + 2 * dns_servers.len() as u8;
+    BigEndian::write_u32(&mut option[4..8], ROUTER_LIFETIME as u32);
+    for dns_server in dns_servers {
+        option.extend_from_slice(dns_server.as_bytes());
+    }
+    icmp_bytes.extend_from_slice(&option[..]);
+
+    // Routers advertise from their link-local address
+    Some(icmpv6_frame(gw_mac, dst_mac, link_local_addr(&gw_mac), dst_addr, icmp_bytes))
+}
+
+/// Parses a DHCPv6 message sent by a client (returning its MAC and IP
+/// addresses along with the message)
+pub fn parse_dhcpv6(pck: &[u8]) -> Option<(EthernetAddress, Ipv6Address, Dhcpv6Repr)>
+{
+    let frame_mac = EthernetFrame::new_checked(pck).ok()?;
+    if frame_mac.ethertype() != EthernetProtocol::Ipv6 {
+        return None;
+    }
+    let frame_ip = Ipv6Packet::new_checked(frame_mac.payload()).ok()?;
+    if frame_ip.next_header() != IpProtocol::Udp {
+        return None;
+    }
+    let frame_udp = UdpPacket::new_checked(frame_ip.payload()).ok()?;
+    if frame_udp.dst_port() != DHCPV6_SERVER_PORT {
+        return None;
+    }
+    let repr = Dhcpv6Repr::parse(frame_udp.payload()).ok()?;
+    Some((frame_mac.src_addr(), frame_ip.src_addr(), repr))
+}
+
+/// Builds the frame that carries a DHCPv6 message from the gateway to a client
+pub fn dhcpv6_frame(gw_mac: EthernetAddress, dst_mac: EthernetAddress, dst_addr: Ipv6Address, repr: &Dhcpv6Repr) -> Vec<u8>
+{
+    let dhcp_payload = repr.emit();
+    let src_addr = link_local_addr(&gw_mac);
+
+    // Build the UDP payload
+    let udp_repr = UdpRepr {
+        src_port: DHCPV6_SERVER_PORT,
+        dst_port: DHCPV6_CLIENT_PORT,
+    };
+    let mut udp_bytes = vec![0xff; udp_repr.header_len() + dhcp_payload.len()];
+    let mut udp_packet = UdpPacket::new_unchecked(&mut udp_bytes[..]);
+    udp_repr.emit(
+        &mut udp_packet,
+        &IpAddress::Ipv6(src_addr),
+        &IpAddress::Ipv6(dst_addr),
+        dhcp_payload.len(),
+        |buf| buf.copy_from_slice(&dhcp_payload[..]),
+        &ChecksumCapabilities::default(),
+    );
+    udp_packet.fill_checksum(&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr));
+
+    build_frame(gw_mac, dst_mac, src_addr, dst_addr, IpProtocol::Udp, 64, &udp_bytes[..])
+}
+
+/// Picks one DHCPv6 address in each of the IPv6 CIDRs - reserved addresses
+/// are kept unless they have since been taken (e.g. allocated by another
+/// node or declined by a client) in which case the first free one is used
+pub fn allocate_addrs(cidrs: &[IpCidr], reserved: &[Ipv6Addr], taken: &HashSet<Ipv6Addr>) -> Vec<Ipv6Addr>
+{
+    let mut ret = Vec::new();
+    for cidr in cidrs.iter() {
+        let ip = match cidr.address() {
+            IpAddress::Ipv6(ip) => ip,
+            _ => { continue; }
+        };
+
+        let existing = reserved
+            .iter()
+            .filter(|ip| cidr.contains_addr(&IpAddress::Ipv6((**ip).into())))
+            .filter(|ip| taken.contains(*ip) == false)
+            .next();
+        if let Some(existing) = existing {
+            ret.push(existing.clone());
+            continue;
+        }
+
+        let range = 128 - cidr.prefix_len();
+        if range < 2 {
+            continue;
+        }
+        // (we do not need to scan the whole of a large subnet)
+        let range = 2u128.pow(range.min(16) as u32) - 3;
+
+        // The network address and the gateway (::1) are never handed out
+        let start: Ipv6Addr = ip.into();
+        let start = u128::from(start) + 2;
+        let end = start + range;
+        for ip in start..end {
+            let ip: Ipv6Addr = ip.into();
+            if taken.contains(&ip) {
+                continue;
+            }
+            ret.push(ip);
+            break;
+        }
+    }
+    ret
+}
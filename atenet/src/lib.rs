@@ -7,4 +7,6 @@ pub mod common;
 pub mod udp;
 pub mod gateway;
 pub mod factory;
-pub mod raw;
\ No newline at end of file
+pub mod raw;
+pub mod ipv6;
+pub mod dhcpv6;
\ No newline at end of file
@@ -51,6 +51,8 @@ use tracing::{debug, error, info, instrument, span, trace, warn, Level};
 use super::port::*;
 use super::udp::*;
 use super::gateway::*;
+use super::ipv6;
+use super::dhcpv6::*;
 
 #[derive(Debug)]
 pub enum Destination
@@ -102,6 +104,10 @@ pub struct DataPlane {
 pub struct ControlPlane {
     pub(crate) inst: DaoMut<ServiceInstance>,
     pub(crate) me_node_id: PrimaryKey,
+    /// IPv6 addresses that clients declined because something else on the
+    /// link already uses them (these are not handed out again for a while)
+    #[derivative(Debug = "ignore")]
+    pub(crate) declined6: TtlCache<Ipv6Addr, ()>,
 }
 
 impl ControlPlane
@@ -133,6 +139,14 @@ pub struct DhcpMessage
     switch: Weak<Switch>,
 }
 
+pub struct Dhcpv6Message
+{
+    src_mac: EthernetAddress,
+    src_ip: Ipv6Address,
+    repr: Dhcpv6Repr,
+    switch: Weak<Switch>,
+}
+
 impl Destination
 {
     pub fn send(&self, switch: &Switch, pck: Vec<u8>, allow_forward: bool) {
@@ -183,6 +197,7 @@ pub struct Switch
     pub(crate) control_plane: RwLock<ControlPlane>,
     pub(crate) mac_drop: mpsc::Sender<HardwareAddress>,
     pub(crate) dhcp_msg: mpsc::Sender<DhcpMessage>,
+    pub(crate) dhcp6_msg: mpsc::Sender<Dhcpv6Message>,
     pub(crate) me_node_key: PrimaryKey,
     #[allow(dead_code)]
     pub(crate) me_node_addr: IpAddr,
@@ -195,6 +210,8 @@ impl Switch
 {
     pub const MAC_SNOOP_MAX: usize = u16::MAX as usize;
     pub const MAC_SNOOP_TTL: Duration = Duration::from_secs(14400); // 4 hours (CISCO default)
+    pub const DECLINED_MAX: usize = 1024;
+    pub const DECLINED_TTL: Duration = Duration::from_secs(3600);
 
     pub async fn new(accessor: Arc<FileAccessor>, cidrs: Vec<IpCidr>, udp: UdpPeerHandle, gateway: Arc<Gateway>) -> Result<Arc<Switch>, AteError> {
         let (inst, bus, me_node) = {
@@ -237,6 +254,7 @@ impl Switch
         access_tokens.push(inst.subnet.network_token.clone());
 
         let (dhcp_msg_tx, dhcp_msg_rx) = mpsc::channel(100);
+        let (dhcp6_msg_tx, dhcp6_msg_rx) = mpsc::channel(100);
         let (mac_drop_tx, mac_drop_rx) = mpsc::channel(100);
         let switch = Arc::new(Switch {
             id,
@@ -263,10 +281,12 @@ impl Switch
                 ControlPlane {
                     inst,
                     me_node_id: me_node.key().clone(),
+                    declined6: TtlCache::new(Self::DECLINED_MAX),
                 }
             ),
             mac_drop: mac_drop_tx,
             dhcp_msg: dhcp_msg_tx,
+            dhcp6_msg: dhcp6_msg_tx,
             gateway,
             access_tokens,
         });
@@ -274,7 +294,7 @@ impl Switch
         {
             let switch = switch.clone();
             tokio::task::spawn(async move {
-                switch.run(bus, mac_drop_rx, dhcp_msg_rx).await;
+                switch.run(bus, mac_drop_rx, dhcp_msg_rx, dhcp6_msg_rx).await;
             });
         }
 
@@ -365,8 +385,14 @@ impl Switch
             return true;
         }
 
+        // The IPv6 equivalent of ARP is neighbor discovery
+        if self.gateway.process_ndp_reply(pck, self, state) == true {
+            return true;
+        }
+
         // If its a DHCP request then we should respond to it
-        self.process_dhcp_request(pck)
+        self.process_dhcp_request(pck) ||
+        self.process_dhcpv6_request(pck)
     }
 
     fn process_dhcp_request(self: &Arc<Switch>, pck: &[u8]) -> bool
@@ -419,6 +445,23 @@ impl Switch
         false
     }
 
+    fn process_dhcpv6_request(self: &Arc<Switch>, pck: &[u8]) -> bool
+    {
+        if let Some((src_mac, src_ip, repr)) = ipv6::parse_dhcpv6(pck)
+        {
+            // Pass the DHCPv6 message on to be processed by
+            // the asynchronous processing loop
+            let _ = self.dhcp6_msg.try_send(Dhcpv6Message {
+                src_mac,
+                src_ip,
+                repr,
+                switch: Arc::downgrade(self),
+            });
+            return true;
+        }
+        false
+    }
+
     async fn allocate_ipv4(&self, mac: EthernetAddress) -> Option<Ipv4Address>
     {
         let mac = wasmer_deploy_cli::model::HardwareAddress::from_bytes(mac.as_bytes());
@@ -444,7 +487,10 @@ impl Switch
                 for (k, v) in node.dhcp_reservation.iter() {
                     let ip: Ipv4Addr = v.addr4.clone().into();
                     if k == &mac_str && &control_plane.me_node_id == node.key() {
-                        found = Some(ip)
+                        // Reservations that only hold IPv6 addresses have no IPv4 address yet
+                        if ip.is_unspecified() == false {
+                            found = Some(ip)
+                        }
                     } else {                    
                         already4.insert(ip);
                     }
@@ -501,10 +547,14 @@ impl Switch
                         }
                         if let Some(mut me_node) = control_plane.me_node().await {
                             let mut me_node = me_node.as_mut();
+                            let addr6 = me_node.dhcp_reservation
+                                .get(&mac_str)
+                                .map(|r| r.addr6.clone())
+                                .unwrap_or_default();
                             me_node.dhcp_reservation.insert(mac_str, DhcpReservation {
                                 mac,
                                 addr4: ip,
-                                addr6: Vec::new(),
+                                addr6,
                             });
                         } else {
                             continue;
@@ -521,7 +571,129 @@ impl Switch
         None
     }
 
-    async fn tick(&self)
+    /// Leases one address from each of the IPv6 CIDRs to a MAC, reusing
+    /// its existing reservation on this node where possible
+    pub async fn allocate_ipv6(&self, mac: EthernetAddress) -> Vec<Ipv6Address>
+    {
+        let mac = wasmer_deploy_cli::model::HardwareAddress::from_bytes(mac.as_bytes());
+        let mac_str = hex::encode(mac.as_bytes()).to_uppercase();
+        let cidrs = self.cidrs()
+            .into_iter()
+            .filter(|cidr| matches!(cidr.address(), IpAddress::Ipv6(_)))
+            .collect::<Vec<_>>();
+        if cidrs.is_empty() {
+            return Vec::new();
+        }
+
+        let mut control_plane = self.control_plane.write().await;
+        let dio = control_plane.inst.dio_mut();
+
+        // Force a sync which is needed so we can handle the race conditions
+        if let Err(err) = dio.chain().sync().await {
+            warn!("failed to sync before doing a DHCPv6 allocation - {}", err);
+        }
+
+        // Build a list of all the IPs that are already allocated and the
+        // ones that are reserved on this node for this MAC
+        let mut already6 = HashSet::new();
+        let mut found = Vec::new();
+        if let Ok(nodes) = control_plane.inst.mesh_nodes.iter().await
+        {
+            for node in nodes {
+                for (k, v) in node.dhcp_reservation.iter() {
+                    if k == &mac_str && &control_plane.me_node_id == node.key() {
+                        found = v.addr6.clone();
+                    } else {
+                        already6.extend(v.addr6.iter().cloned());
+                    }
+                }
+            }
+        }
+
+        // Each of the IPv6 CIDRs gets one address, reservations are honored
+        // unless another node has allocated the same IP (race condition) or
+        // the client declined it in which case we pick another one
+        already6.extend(control_plane.declined6.iter().map(|(ip, _)| ip.clone()));
+        let ret = ipv6::allocate_addrs(&cidrs[..], &found[..], &already6);
+
+        // Update the reservation if it has changed
+        if ret != found {
+            if let Some(mut me_node) = control_plane.me_node().await {
+                let mut me_node = me_node.as_mut();
+                if let Some(reservation) = me_node.dhcp_reservation.get_mut(&mac_str) {
+                    reservation.addr6 = ret.clone();
+                } else {
+                    me_node.dhcp_reservation.insert(mac_str, DhcpReservation {
+                        mac,
+                        addr4: Ipv4Addr::UNSPECIFIED,
+                        addr6: ret.clone(),
+                    });
+                }
+            } else {
+                return Vec::new();
+            }
+            if let Err(err) = dio.commit().await {
+                warn!("failed to commit the DHCPv6 reservation - {}", err);
+                return Vec::new();
+            }
+        }
+
+        ret.into_iter()
+            .map(|ip| ip.into())
+            .collect()
+    }
+
+    /// Frees the addresses that a client released or declined - only the
+    /// addresses that are reserved for its MAC are accepted (the server ID
+    /// of the gateway is well known so it proves nothing on its own)
+    pub async fn release_ipv6(&self, mac: EthernetAddress, addrs: Vec<Ipv6Address>, declined: bool)
+    {
+        let mac_str = hex::encode(mac.as_bytes()).to_uppercase();
+        let addrs = addrs
+            .into_iter()
+            .map(|ip| ip.into())
+            .collect::<Vec<Ipv6Addr>>();
+        if addrs.is_empty() {
+            return;
+        }
+
+        // Remove the addresses from the reservation so that they are freed
+        let mut control_plane = self.control_plane.write().await;
+        let returned = if let Some(mut me_node) = control_plane.me_node().await {
+            let mut me_node = me_node.as_mut();
+            if let Some(reservation) = me_node.dhcp_reservation.get_mut(&mac_str) {
+                let returned = reservation.addr6
+                    .iter()
+                    .filter(|ip| addrs.contains(ip))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if returned.is_empty() {
+                    return;
+                }
+                reservation.addr6.retain(|ip| returned.contains(ip) == false);
+                returned
+            } else {
+                return;
+            }
+        } else {
+            return;
+        };
+
+        // Declined addresses are in use by something else on the link so
+        // they must not be leased to anyone else for a while
+        if declined {
+            for ip in returned {
+                control_plane.declined6.insert(ip, (), Self::DECLINED_TTL);
+            }
+        }
+
+        let dio = control_plane.inst.dio_mut();
+        if let Err(err) = dio.commit().await {
+            warn!("failed to commit the DHCPv6 release - {}", err);
+        }
+    }
+
+    async fn tick(self: &Arc<Switch>)
     {
         let subnet = {
             let control_plane = self.control_plane.read().await;
@@ -530,6 +702,16 @@ impl Switch
 
         let mut data_plane = self.data_plane.lock().unwrap();
         data_plane.cidrs = super::common::subnet_to_cidrs(&subnet);
+
+        // Periodically advertise the gateway as a router so that the
+        // IPv6 clients keep their SLAAC addresses and default route
+        let all_nodes = Ipv6Address::LINK_LOCAL_ALL_NODES;
+        if let Some(pck) = ipv6::router_advert(Gateway::MAC, ipv6::multicast_mac(&all_nodes), all_nodes, &data_plane.cidrs[..]) {
+            #[cfg(feature="tcpdump")]
+            tcpdump(self.me_node_addr, self.name.as_str(), &pck[..]);
+
+            self.process_broadcast(&mut data_plane, &Gateway::MAC, &pck[..], false);
+        }
     }
 
     pub(crate) fn process_arp_request(self: &Arc<Self>, src_mac: EthernetAddress, src_ip: Ipv4Address, dst_ip: Ipv4Address)
@@ -711,6 +893,37 @@ impl Switch
         self.process_unicast(&Gateway::MAC, &msg.src_mac, eth_bytes, false);
     }
 
+    async fn dhcpv6_process_internal(self: &Arc<Switch>, msg: Dhcpv6Message)
+    {
+        // The gateway is also the DHCPv6 server
+        let server_id = duid_ll(&Gateway::MAC);
+
+        // Free any addresses that the client released or declined (only
+        // when the message was meant for this server)
+        if msg.repr.server_id.as_ref() == Some(&server_id) {
+            let declined = msg.repr.message_type == Dhcpv6MessageType::Decline;
+            self.release_ipv6(msg.src_mac, msg.repr.returned_addrs(), declined).await;
+        }
+
+        // Determine the IP addresses for this particular MAC address
+        let addrs = match msg.repr.wants_addrs() {
+            true => self.allocate_ipv6(msg.src_mac).await,
+            false => Vec::new()
+        };
+
+        // Build the DHCPv6 reply
+        let reply = match server_reply(&msg.repr, &server_id[..], &addrs[..], &ipv6::dns_servers()[..]) {
+            Some(a) => a,
+            None => {
+                return;
+            }
+        };
+        let eth_bytes = ipv6::dhcpv6_frame(Gateway::MAC, msg.src_mac, msg.src_ip, &reply);
+
+        // Send the response to the caller
+        self.process_unicast(&Gateway::MAC, &msg.src_mac, eth_bytes, false);
+    }
+
     pub fn process(self: &Arc<Switch>, pck: Vec<u8>, allow_forward: bool, set_peer: Option<&IpAddr>) {
         // This should use unicast for destination MAC's that are unicast - other
         // MAC addresses such as multicast and broadcast should use broadcast
@@ -728,8 +941,8 @@ impl Switch
                     let mut state = self.data_plane.lock().unwrap();
                     self.process_snoop(&mut state, &pck[..], set_peer);
 
-                    // If its a broadcast then we reuse the locked mutex and send the packet on
-                    if dst.is_broadcast() {
+                    // If its a broadcast (or multicast) then we reuse the locked mutex and send the packet on
+                    if dst.is_broadcast() || dst.is_multicast() {
                         self.process_broadcast(&mut state, &src, &pck[..], allow_forward);
                         return;
                     }
@@ -825,6 +1038,11 @@ impl Switch
             self.process_promiscuous(&mut state, dst_mac, &pck[..], allow_forward);
         }
 
+        // Neighbor solicitations can also be sent directly to the gateway
+        if self.gateway.process_ndp_reply(&pck[..], self, &mut state) {
+            return;
+        }
+
         // Maybe the gateway needs to respond to an ICMP packet
         if self.gateway.process_icmp_reply(&pck[..], self, &mut state) {
             return;
@@ -1094,7 +1312,7 @@ impl Switch
         }
     }
 
-    pub async fn run(&self, mut bus: Bus<MeshNode>, mut mac_drop: mpsc::Receiver<HardwareAddress>, mut dhcp_msg_rx: mpsc::Receiver<DhcpMessage>)
+    pub async fn run(self: &Arc<Switch>, mut bus: Bus<MeshNode>, mut mac_drop: mpsc::Receiver<HardwareAddress>, mut dhcp_msg_rx: mpsc::Receiver<DhcpMessage>, mut dhcp6_msg_rx: mpsc::Receiver<Dhcpv6Message>)
     {
         debug!("control thread initializing");
 
@@ -1161,6 +1379,16 @@ impl Switch
                         debug!("control thread closing (3)");
                         break;
                     }
+                },
+                msg = dhcp6_msg_rx.recv() => {
+                    if let Some(msg) = msg {
+                        if let Some(switch) = msg.switch.upgrade() {
+                            switch.dhcpv6_process_internal(msg).await;
+                        }
+                    } else {
+                        debug!("control thread closing (4)");
+                        break;
+                    }
                 }
             }
         }
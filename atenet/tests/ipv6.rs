@@ -0,0 +1,402 @@
+use std::collections::HashSet;
+use std::net::Ipv6Addr;
+use byteorder::BigEndian;
+use byteorder::ByteOrder;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::wire::EthernetAddress;
+use smoltcp::wire::EthernetFrame;
+use smoltcp::wire::EthernetProtocol;
+use smoltcp::wire::EthernetRepr;
+use smoltcp::wire::Icmpv6Message;
+use smoltcp::wire::Icmpv6Packet;
+use smoltcp::wire::Icmpv6Repr;
+use smoltcp::wire::IpAddress;
+use smoltcp::wire::IpCidr;
+use smoltcp::wire::IpProtocol;
+use smoltcp::wire::Ipv4Address;
+use smoltcp::wire::Ipv6Address;
+use smoltcp::wire::Ipv6Packet;
+use smoltcp::wire::Ipv6Repr;
+use smoltcp::wire::NdiscNeighborFlags;
+use smoltcp::wire::NdiscRepr;
+use smoltcp::wire::UdpPacket;
+use smoltcp::wire::UdpRepr;
+
+use atenet::dhcpv6::*;
+use atenet::gateway::Gateway;
+use atenet::ipv6::*;
+
+const CLIENT_MAC: EthernetAddress = EthernetAddress([0x06, 0x00, 0x00, 0x00, 0x00, 0x42]);
+
+fn gateway_ip() -> Ipv6Address {
+    Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 1)
+}
+
+fn cidrs() -> Vec<IpCidr> {
+    vec![
+        IpCidr::new(IpAddress::Ipv4(Ipv4Address::new(10, 0, 0, 0)), 24),
+        IpCidr::new(IpAddress::Ipv6(Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 0)), 64),
+    ]
+}
+
+fn frame(src_addr: Ipv6Address, dst_addr: Ipv6Address, next_header: IpProtocol, hop_limit: u8, payload: &[u8]) -> Vec<u8> {
+    let ip_repr = Ipv6Repr {
+        src_addr,
+        dst_addr,
+        next_header,
+        payload_len: payload.len(),
+        hop_limit,
+    };
+    let eth_repr = EthernetRepr {
+        src_addr: CLIENT_MAC,
+        dst_addr: match dst_addr.is_multicast() {
+            true => multicast_mac(&dst_addr),
+            false => Gateway::MAC,
+        },
+        ethertype: EthernetProtocol::Ipv6,
+    };
+    let mut pck = vec![0u8; eth_repr.buffer_len() + ip_repr.buffer_len() + payload.len()];
+    let mut frame_mac = EthernetFrame::new_unchecked(&mut pck[..]);
+    eth_repr.emit(&mut frame_mac);
+    let mut frame_ip = Ipv6Packet::new_unchecked(frame_mac.payload_mut());
+    ip_repr.emit(&mut frame_ip);
+    frame_ip.payload_mut().copy_from_slice(payload);
+    pck
+}
+
+fn ndisc_frame(src_addr: Ipv6Address, dst_addr: Ipv6Address, hop_limit: u8, repr: NdiscRepr) -> Vec<u8> {
+    let repr = Icmpv6Repr::Ndisc(repr);
+    let mut icmp_bytes = vec![0u8; repr.buffer_len()];
+    let mut icmp_packet = Icmpv6Packet::new_unchecked(&mut icmp_bytes[..]);
+    repr.emit(
+        &IpAddress::Ipv6(src_addr),
+        &IpAddress::Ipv6(dst_addr),
+        &mut icmp_packet,
+        &ChecksumCapabilities::default(),
+    );
+    frame(src_addr, dst_addr, IpProtocol::Icmpv6, hop_limit, &icmp_bytes[..])
+}
+
+fn dhcpv6_request(repr: &Dhcpv6Repr) -> Vec<u8> {
+    let src_addr = link_local_addr(&CLIENT_MAC);
+    let payload = repr.emit();
+    let udp_repr = UdpRepr {
+        src_port: DHCPV6_CLIENT_PORT,
+        dst_port: DHCPV6_SERVER_PORT,
+    };
+    let mut udp_bytes = vec![0u8; udp_repr.header_len() + payload.len()];
+    let mut udp_packet = UdpPacket::new_unchecked(&mut udp_bytes[..]);
+    udp_repr.emit(
+        &mut udp_packet,
+        &IpAddress::Ipv6(src_addr),
+        &IpAddress::Ipv6(ALL_DHCP_SERVERS),
+        payload.len(),
+        |buf| buf.copy_from_slice(&payload[..]),
+        &ChecksumCapabilities::default(),
+    );
+    frame(src_addr, ALL_DHCP_SERVERS, IpProtocol::Udp, 1, &udp_bytes[..])
+}
+
+/// Checks the headers of a reply and returns its addresses and payload
+fn open_reply(pck: &[u8], next_header: IpProtocol) -> (EthernetAddress, Ipv6Address, Ipv6Address, Vec<u8>) {
+    let frame_mac = EthernetFrame::new_checked(pck).unwrap();
+    assert_eq!(frame_mac.src_addr(), Gateway::MAC);
+    assert_eq!(frame_mac.ethertype(), EthernetProtocol::Ipv6);
+    let frame_ip = Ipv6Packet::new_checked(frame_mac.payload()).unwrap();
+    assert_eq!(frame_ip.next_header(), next_header);
+    (
+        frame_mac.dst_addr(),
+        frame_ip.src_addr(),
+        frame_ip.dst_addr(),
+        frame_ip.payload().to_vec(),
+    )
+}
+
+fn open_dhcpv6_reply(pck: &[u8]) -> Dhcpv6Repr {
+    let (dst_mac, src_addr, dst_addr, payload) = open_reply(pck, IpProtocol::Udp);
+    assert_eq!(dst_mac, CLIENT_MAC);
+    assert_eq!(dst_addr, link_local_addr(&CLIENT_MAC));
+    let frame_udp = UdpPacket::new_checked(&payload[..]).unwrap();
+    assert!(frame_udp.verify_checksum(&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr)));
+    assert_eq!(frame_udp.src_port(), DHCPV6_SERVER_PORT);
+    assert_eq!(frame_udp.dst_port(), DHCPV6_CLIENT_PORT);
+    Dhcpv6Repr::parse(frame_udp.payload()).unwrap()
+}
+
+fn solicit(rapid_commit: bool) -> Dhcpv6Repr {
+    Dhcpv6Repr {
+        message_type: Dhcpv6MessageType::Solicit,
+        transaction_id: 0x123456,
+        client_id: Some(duid_ll(&CLIENT_MAC)),
+        server_id: None,
+        ia_na: Some(Dhcpv6IaNa {
+            iaid: 7,
+            t1: 0,
+            t2: 0,
+            addrs: Vec::new(),
+            status: None,
+        }),
+        rapid_commit,
+        dns_servers: Vec::new(),
+        status: None,
+    }
+}
+
+#[test]
+fn neighbor_solicit_for_gateway() {
+    let client_ip = Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 2);
+    let pck = ndisc_frame(client_ip, gateway_ip().solicited_node(), 255, NdiscRepr::NeighborSolicit {
+        target_addr: gateway_ip(),
+        lladdr: None,
+    });
+
+    let (dst_mac, reply) = answer_neighbor_solicit(&pck[..], Gateway::MAC, &[IpAddress::Ipv6(gateway_ip())]).unwrap();
+    assert_eq!(dst_mac, CLIENT_MAC);
+
+    let (dst_mac, src_addr, dst_addr, payload) = open_reply(&reply[..], IpProtocol::Icmpv6);
+    assert_eq!(dst_mac, CLIENT_MAC);
+    assert_eq!(src_addr, gateway_ip());
+    assert_eq!(dst_addr, client_ip);
+
+    let packet = Icmpv6Packet::new_checked(&payload[..]).unwrap();
+    let repr = Icmpv6Repr::parse(&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr), &packet, &ChecksumCapabilities::default()).unwrap();
+    match repr {
+        Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert { flags, target_addr, lladdr }) => {
+            assert_eq!(target_addr, gateway_ip());
+            assert!(flags.contains(NdiscNeighborFlags::ROUTER | NdiscNeighborFlags::SOLICITED | NdiscNeighborFlags::OVERRIDE));
+            assert_eq!(lladdr.map(|a| a.as_bytes().to_vec()), Some(Gateway::MAC.as_bytes().to_vec()));
+        }
+        repr => panic!("expected a neighbor advertisement - {:?}", repr),
+    }
+}
+
+#[test]
+fn neighbor_solicit_for_gateway_link_local() {
+    let target_addr = link_local_addr(&Gateway::MAC);
+    let pck = ndisc_frame(link_local_addr(&CLIENT_MAC), target_addr.solicited_node(), 255, NdiscRepr::NeighborSolicit {
+        target_addr,
+        lladdr: None,
+    });
+    assert!(answer_neighbor_solicit(&pck[..], Gateway::MAC, &[]).is_some());
+}
+
+#[test]
+fn duplicate_address_detection_is_answered_to_all_nodes() {
+    let pck = ndisc_frame(Ipv6Address::UNSPECIFIED, gateway_ip().solicited_node(), 255, NdiscRepr::NeighborSolicit {
+        target_addr: gateway_ip(),
+        lladdr: None,
+    });
+
+    let (dst_mac, reply) = answer_neighbor_solicit(&pck[..], Gateway::MAC, &[IpAddress::Ipv6(gateway_ip())]).unwrap();
+    assert_eq!(dst_mac, multicast_mac(&Ipv6Address::LINK_LOCAL_ALL_NODES));
+
+    let (_, src_addr, dst_addr, payload) = open_reply(&reply[..], IpProtocol::Icmpv6);
+    assert_eq!(dst_addr, Ipv6Address::LINK_LOCAL_ALL_NODES);
+    let packet = Icmpv6Packet::new_checked(&payload[..]).unwrap();
+    let repr = Icmpv6Repr::parse(&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr), &packet, &ChecksumCapabilities::default()).unwrap();
+    match repr {
+        Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert { flags, .. }) => {
+            assert!(flags.contains(NdiscNeighborFlags::SOLICITED) == false);
+        }
+        repr => panic!("expected a neighbor advertisement - {:?}", repr),
+    }
+}
+
+#[test]
+fn neighbor_solicit_for_other_hosts_is_ignored() {
+    let other_ip = Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 3);
+    let pck = ndisc_frame(link_local_addr(&CLIENT_MAC), other_ip.solicited_node(), 255, NdiscRepr::NeighborSolicit {
+        target_addr: other_ip,
+        lladdr: None,
+    });
+    assert!(answer_neighbor_solicit(&pck[..], Gateway::MAC, &[IpAddress::Ipv6(gateway_ip())]).is_none());
+
+    // Neighbor discovery that has passed through a router is invalid
+    let pck = ndisc_frame(link_local_addr(&CLIENT_MAC), gateway_ip().solicited_node(), 64, NdiscRepr::NeighborSolicit {
+        target_addr: gateway_ip(),
+        lladdr: None,
+    });
+    assert!(answer_neighbor_solicit(&pck[..], Gateway::MAC, &[IpAddress::Ipv6(gateway_ip())]).is_none());
+}
+
+#[test]
+fn router_solicit_is_answered_with_prefixes() {
+    let pck = ndisc_frame(Ipv6Address::UNSPECIFIED, Ipv6Address::LINK_LOCAL_ALL_ROUTERS, 255, NdiscRepr::RouterSolicit {
+        lladdr: None,
+    });
+
+    let (dst_mac, reply) = answer_router_solicit(&pck[..], Gateway::MAC, &cidrs()[..]).unwrap();
+    assert_eq!(dst_mac, multicast_mac(&Ipv6Address::LINK_LOCAL_ALL_NODES));
+
+    let (_, src_addr, dst_addr, payload) = open_reply(&reply[..], IpProtocol::Icmpv6);
+    assert_eq!(src_addr, link_local_addr(&Gateway::MAC));
+    assert_eq!(dst_addr, Ipv6Address::LINK_LOCAL_ALL_NODES);
+
+    let packet = Icmpv6Packet::new_checked(&payload[..]).unwrap();
+    assert_eq!(packet.msg_type(), Icmpv6Message::RouterAdvert);
+    assert!(packet.verify_checksum(&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr)));
+    assert_eq!(BigEndian::read_u16(&payload[6..8]), ROUTER_LIFETIME);
+
+    // Walk the options (smoltcp does not parse the DNS servers)
+    let mut lladdr = None;
+    let mut prefixes = Vec::new();
+    let mut dns = Vec::new();
+    let mut options = &payload[16..];
+    while options.len() > 0 {
+        let len = options[1] as usize * 8;
+        match options[0] {
+            1 => lladdr = Some(EthernetAddress::from_bytes(&options[2..8])),
+            3 => prefixes.push((options[2], options[3], Ipv6Address::from_bytes(&options[16..32]))),
+            25 => dns.extend(options[8..len].chunks(16).map(Ipv6Address::from_bytes)),
+            _ => {}
+        }
+        options = &options[len..];
+    }
+    assert_eq!(lladdr, Some(Gateway::MAC));
+    assert_eq!(prefixes, vec![(64, 0xc0, Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 0))]);
+    assert_eq!(dns, dns_servers());
+}
+
+#[test]
+fn router_advert_needs_ipv6_cidrs() {
+    let cidrs = vec![IpCidr::new(IpAddress::Ipv4(Ipv4Address::new(10, 0, 0, 0)), 24)];
+    let all_nodes = Ipv6Address::LINK_LOCAL_ALL_NODES;
+    assert!(router_advert(Gateway::MAC, multicast_mac(&all_nodes), all_nodes, &cidrs[..]).is_none());
+}
+
+#[test]
+fn dhcpv6_solicit_and_request() {
+    let server_id = duid_ll(&Gateway::MAC);
+    let leased = vec![Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 2)];
+
+    // Solicit -> Advertise
+    let pck = dhcpv6_request(&solicit(false));
+    let (src_mac, src_ip, request) = parse_dhcpv6(&pck[..]).unwrap();
+    assert_eq!(src_mac, CLIENT_MAC);
+    assert!(request.wants_addrs());
+    let reply = server_reply(&request, &server_id[..], &leased[..], &dns_servers()[..]).unwrap();
+    let advertise = open_dhcpv6_reply(&dhcpv6_frame(Gateway::MAC, src_mac, src_ip, &reply)[..]);
+    assert_eq!(advertise.message_type, Dhcpv6MessageType::Advertise);
+    assert_eq!(advertise.transaction_id, 0x123456);
+    assert_eq!(advertise.client_id, Some(duid_ll(&CLIENT_MAC)));
+    assert_eq!(advertise.server_id, Some(server_id.clone()));
+    assert_eq!(advertise.dns_servers, dns_servers());
+    let ia_na = advertise.ia_na.unwrap();
+    assert_eq!(ia_na.iaid, 7);
+    assert_eq!(ia_na.addrs, leased);
+
+    // Request -> Reply
+    let mut request = solicit(false);
+    request.message_type = Dhcpv6MessageType::Request;
+    request.server_id = Some(server_id.clone());
+    let pck = dhcpv6_request(&request);
+    let (src_mac, src_ip, request) = parse_dhcpv6(&pck[..]).unwrap();
+    let reply = server_reply(&request, &server_id[..], &leased[..], &dns_servers()[..]).unwrap();
+    let reply = open_dhcpv6_reply(&dhcpv6_frame(Gateway::MAC, src_mac, src_ip, &reply)[..]);
+    assert_eq!(reply.message_type, Dhcpv6MessageType::Reply);
+    assert_eq!(reply.ia_na.unwrap().addrs, leased);
+}
+
+#[test]
+fn dhcpv6_rapid_commit() {
+    let server_id = duid_ll(&Gateway::MAC);
+    let leased = vec![Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 2)];
+
+    let (_, _, request) = parse_dhcpv6(&dhcpv6_request(&solicit(true))[..]).unwrap();
+    let reply = server_reply(&request, &server_id[..], &leased[..], &dns_servers()[..]).unwrap();
+    assert_eq!(reply.message_type, Dhcpv6MessageType::Reply);
+    assert!(reply.rapid_commit);
+    assert_eq!(reply.ia_na.unwrap().addrs, leased);
+}
+
+#[test]
+fn dhcpv6_without_addresses() {
+    let server_id = duid_ll(&Gateway::MAC);
+
+    let reply = server_reply(&solicit(false), &server_id[..], &[], &dns_servers()[..]).unwrap();
+    let ia_na = reply.ia_na.unwrap();
+    assert!(ia_na.addrs.is_empty());
+    assert_eq!(ia_na.status, Some(Dhcpv6Status::NoAddrsAvail));
+}
+
+#[test]
+fn dhcpv6_for_other_servers_is_ignored() {
+    let mut request = solicit(false);
+    request.message_type = Dhcpv6MessageType::Request;
+    request.server_id = Some(duid_ll(&CLIENT_MAC));
+    assert!(server_reply(&request, &duid_ll(&Gateway::MAC)[..], &[], &dns_servers()[..]).is_none());
+}
+
+#[test]
+fn dhcpv6_release_and_decline() {
+    let server_id = duid_ll(&Gateway::MAC);
+    let leased = vec![Ipv6Address::new(0xfd00, 0x1, 0, 0, 0, 0, 0, 2)];
+
+    for message_type in [Dhcpv6MessageType::Release, Dhcpv6MessageType::Decline] {
+        let mut request = solicit(false);
+        request.message_type = message_type;
+        request.ia_na.as_mut().unwrap().addrs = leased.clone();
+        assert!(!request.wants_addrs());
+
+        // (these must name the server they are meant for)
+        assert!(server_reply(&request, &server_id[..], &[], &dns_servers()[..]).is_none());
+
+        request.server_id = Some(server_id.clone());
+        let (_, _, request) = parse_dhcpv6(&dhcpv6_request(&request)[..]).unwrap();
+        assert_eq!(request.returned_addrs(), leased);
+        let reply = server_reply(&request, &server_id[..], &[], &dns_servers()[..]).unwrap();
+        assert_eq!(reply.message_type, Dhcpv6MessageType::Reply);
+        assert_eq!(reply.status, Some(Dhcpv6Status::Success));
+    }
+
+    // Other messages do not return any addresses
+    let mut request = solicit(false);
+    request.ia_na.as_mut().unwrap().addrs = leased.clone();
+    assert!(request.returned_addrs().is_empty());
+}
+
+#[test]
+fn allocate_addrs_picks_the_first_free_address() {
+    let cidrs = cidrs();
+    let first: Ipv6Addr = "fd00:1::2".parse().unwrap();
+    let second: Ipv6Addr = "fd00:1::3".parse().unwrap();
+
+    // The network address and the gateway are skipped
+    assert_eq!(allocate_addrs(&cidrs[..], &[], &HashSet::new()), vec![first]);
+
+    // Addresses that are taken (or declined) are skipped
+    let taken = vec![first].into_iter().collect::<HashSet<_>>();
+    assert_eq!(allocate_addrs(&cidrs[..], &[], &taken), vec![second]);
+}
+
+#[test]
+fn allocate_addrs_honors_reservations() {
+    let cidrs = cidrs();
+    let reserved: Ipv6Addr = "fd00:1::99".parse().unwrap();
+    let elsewhere: Ipv6Addr = "fd00:2::99".parse().unwrap();
+
+    assert_eq!(allocate_addrs(&cidrs[..], &[elsewhere, reserved], &HashSet::new()), vec![reserved]);
+
+    // A reservation that was taken in the meantime is replaced
+    let taken = vec![reserved].into_iter().collect::<HashSet<_>>();
+    assert_eq!(allocate_addrs(&cidrs[..], &[reserved], &taken), vec!["fd00:1::2".parse::<Ipv6Addr>().unwrap()]);
+}
+
+#[test]
+fn allocate_addrs_ignores_ipv4_and_tiny_subnets() {
+    let cidrs = vec![
+        IpCidr::new(IpAddress::Ipv4(Ipv4Address::new(10, 0, 0, 0)), 24),
+        IpCidr::new(IpAddress::Ipv6(Ipv6Address::new(0xfd00, 0x3, 0, 0, 0, 0, 0, 0)), 127),
+    ];
+    assert!(allocate_addrs(&cidrs[..], &[], &HashSet::new()).is_empty());
+}
+
+#[test]
+fn allocate_addrs_gives_up_when_the_subnet_is_full() {
+    let cidrs = vec![IpCidr::new(IpAddress::Ipv6(Ipv6Address::new(0xfd00, 0x3, 0, 0, 0, 0, 0, 0)), 126)];
+    let only: Ipv6Addr = "fd00:3::2".parse().unwrap();
+    assert_eq!(allocate_addrs(&cidrs[..], &[], &HashSet::new()), vec![only]);
+
+    let taken = vec![only].into_iter().collect::<HashSet<_>>();
+    assert!(allocate_addrs(&cidrs[..], &[], &taken).is_empty());
+}
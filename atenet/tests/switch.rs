@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+use async_trait::async_trait;
+use ate::prelude::*;
+use ate_files::accessor::FileAccessor;
+use ate_files::repo::Repository;
+use ate_files::repo::RepositorySessionFactory;
+use smoltcp::wire::EthernetAddress;
+use smoltcp::wire::IpAddress;
+use smoltcp::wire::IpCidr;
+use smoltcp::wire::Ipv6Address;
+use wasmer_deploy_cli::model::InstanceSubnet;
+use wasmer_deploy_cli::model::ServiceInstance;
+use wasmer_deploy_cli::model::INSTANCE_ROOT_ID;
+
+use atenet::factory::SwitchFactory;
+use atenet::gateway::Gateway;
+use atenet::switch::Switch;
+use atenet::udp::UdpPeer;
+
+struct LocalSessions;
+
+#[async_trait]
+impl RepositorySessionFactory for LocalSessions
+{
+    async fn create(&self, _sni: String, _key: ChainKey) -> Result<AteSessionType, AteError> {
+        Ok(AteSessionType::User(AteSessionUser::new()))
+    }
+}
+
+fn prefix() -> Ipv6Addr {
+    Ipv6Addr::new(0xfd00, 1, 0, 0, 0, 0, 0, 0)
+}
+
+fn addr(n: u16) -> Ipv6Address {
+    Ipv6Address::from(Ipv6Addr::new(0xfd00, 1, 0, 0, 0, 0, 0, n))
+}
+
+fn mac(n: u8) -> EthernetAddress {
+    EthernetAddress([0x02, 0, 0, 0, 0, n])
+}
+
+async fn create_switch(name: &str) -> Arc<Switch> {
+    ate::utils::bootstrap_test_env();
+
+    let mut conf = ConfAte::default();
+    conf.configured_for(ConfiguredFor::BestPerformance);
+    let builder = ChainBuilder::new(&conf).await.build();
+    let chain = builder
+        .open(&ChainKey::default().with_temp_name(name.to_string()))
+        .await
+        .unwrap();
+
+    let accessor = Arc::new(FileAccessor::new(
+        chain,
+        None,
+        AteSessionType::User(AteSessionUser::new()),
+        TransactionScope::Local,
+        TransactionScope::Local,
+        true,
+        false,
+    )
+    .await);
+
+    // The switch expects the instance to already exist on the chain
+    let dio = accessor.dio.clone().as_mut().await;
+    dio.store_with_key(
+        ServiceInstance {
+            id: fastrand::u128(..),
+            chain: name.to_string(),
+            subnet: InstanceSubnet {
+                cidrs: vec![wasmer_deploy_cli::model::IpCidr {
+                    ip: IpAddr::V6(prefix()),
+                    prefix: 64,
+                }],
+                network_token: AteHash::generate().to_hex_string(),
+                peerings: Vec::new(),
+            },
+            admin_token: AteHash::generate().to_hex_string(),
+            exports: DaoVec::new(),
+            mesh_nodes: DaoVec::new(),
+            triggers: DaoVec::new(),
+            secrets: DaoVec::new(),
+        },
+        PrimaryKey::from(INSTANCE_ROOT_ID),
+    )
+    .unwrap();
+    dio.commit().await.unwrap();
+
+    let registry = Arc::new(Registry::new(&conf).await);
+    let repo = Repository::new(
+        &registry,
+        url::Url::parse("ws://localhost/db").unwrap(),
+        url::Url::parse("ws://localhost/auth").unwrap(),
+        Box::new(LocalSessions),
+        Duration::from_secs(300),
+    )
+    .await
+    .unwrap();
+
+    let switches = Arc::new(RwLock::new(HashMap::default()));
+    let udp = UdpPeer::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0, switches.clone()).await;
+    let factory = Arc::new(SwitchFactory::new(repo, udp.clone(), "localhost".to_string(), switches));
+    let gateway = Arc::new(Gateway::new(0, Vec::new(), &factory));
+
+    let cidrs = vec![IpCidr::new(IpAddress::Ipv6(prefix().into()), 64)];
+    Switch::new(accessor, cidrs, udp, gateway).await.unwrap()
+}
+
+#[tokio::test]
+async fn dhcpv6_release_frees_the_lease() {
+    let switch = create_switch("atenet-switch-release").await;
+
+    // Reservations are sticky for the same MAC
+    assert_eq!(switch.allocate_ipv6(mac(1)).await, vec![addr(2)]);
+    assert_eq!(switch.allocate_ipv6(mac(1)).await, vec![addr(2)]);
+
+    // Clients can not release leases that belong to someone else
+    switch.release_ipv6(mac(2), vec![addr(2)], false).await;
+    assert_eq!(switch.allocate_ipv6(mac(2)).await, vec![addr(3)]);
+
+    // Once released the address goes back into the pool
+    switch.release_ipv6(mac(1), vec![addr(2)], false).await;
+    assert_eq!(switch.allocate_ipv6(mac(3)).await, vec![addr(2)]);
+}
+
+#[tokio::test]
+async fn dhcpv6_decline_holds_back_the_address() {
+    let switch = create_switch("atenet-switch-decline").await;
+
+    assert_eq!(switch.allocate_ipv6(mac(1)).await, vec![addr(2)]);
+
+    // Declines for addresses that the client was never given are ignored
+    // (otherwise anyone could drain the pool)
+    switch.release_ipv6(mac(2), vec![addr(2), addr(3)], true).await;
+    assert_eq!(switch.allocate_ipv6(mac(2)).await, vec![addr(3)]);
+    assert_eq!(switch.allocate_ipv6(mac(1)).await, vec![addr(2)]);
+
+    // A declined address is not handed to anyone, including the client
+    // that declined it
+    switch.release_ipv6(mac(1), vec![addr(2)], true).await;
+    assert_eq!(switch.allocate_ipv6(mac(1)).await, vec![addr(4)]);
+    assert_eq!(switch.allocate_ipv6(mac(3)).await, vec![addr(5)]);
+}
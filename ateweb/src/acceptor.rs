@@ -14,6 +14,7 @@ use tracing::{debug, error, info, instrument, span, trace, warn, Level};
 use hyper;
 
 use super::acme::*;
+use super::rate_limit::*;
 use super::stream::*;
 
 pub struct HyperAcceptor
@@ -23,12 +24,18 @@ where
     pub tcp: TcpListener,
     pub tls: Option<TlsAcceptor>,
     pub acme: Arc<AcmeResolver>,
+    pub limiter: Arc<RateLimiter>,
     pub accepting:
         Vec<Pin<Box<dyn Future<Output = Result<HyperStream, Box<dyn std::error::Error>>> + Send>>>,
 }
 
 impl HyperAcceptor {
-    pub fn new(listener: TcpListener, acme: Arc<AcmeResolver>, enable_tls: bool) -> HyperAcceptor {
+    pub fn new(
+        listener: TcpListener,
+        acme: Arc<AcmeResolver>,
+        limiter: Arc<RateLimiter>,
+        enable_tls: bool,
+    ) -> HyperAcceptor {
         let tls = match enable_tls {
             false => None,
             true => {
@@ -50,6 +57,7 @@ impl HyperAcceptor {
             tcp: listener,
             tls,
             acme,
+            limiter,
             accepting: Vec::new(),
         }
     }
@@ -57,6 +65,7 @@ impl HyperAcceptor {
     pub async fn accept(
         tls: TlsAcceptor,
         acme: Arc<AcmeResolver>,
+        limiter: Arc<RateLimiter>,
         socket: TcpStream,
         addr: SocketAddr,
     ) -> Result<HyperStream, Box<dyn std::error::Error>> {
@@ -149,6 +158,17 @@ impl HyperAcceptor {
                 }
             };
 
+            // Connections are also limited per host (ACME challenges are exempt)
+            if alpn == false {
+                if let Err(err) = limiter.check_host_connection(addr.ip(), sni.as_ref()) {
+                    debug!("connection refused (addr={}, sni={}) - {:?}", addr, sni, err);
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        "too many connections for this host",
+                    )));
+                }
+            }
+
             // Load the object
             if alpn {
                 trace!("alpn challenge for SNI: {}", sni);
@@ -184,6 +204,14 @@ impl hyper::server::accept::Accept for HyperAcceptor {
                     return Poll::Ready(Some(Err(err)));
                 }
                 Poll::Ready(Ok((socket, addr))) => {
+                    // Clients that are denied or have opened too many connections
+                    // are dropped straight away
+                    if let Err(err) = self.limiter.check_connection(addr.ip()) {
+                        debug!("connection refused (addr={}) - {:?}", addr, err);
+                        drop(socket);
+                        continue;
+                    }
+
                     // For HTTP streams there is nothing more to do
                     let tls = match &self.tls {
                         None => {
@@ -194,7 +222,8 @@ impl hyper::server::accept::Accept for HyperAcceptor {
 
                     // Otherwise its time to accept the TLS connection
                     let acme = self.acme.clone();
-                    let accept = HyperAcceptor::accept(tls, acme, socket, addr);
+                    let limiter = self.limiter.clone();
+                    let accept = HyperAcceptor::accept(tls, acme, limiter, socket, addr);
                     self.accepting.push(Box::pin(accept));
                 }
             };
@@ -0,0 +1,179 @@
+use chrono::DateTime;
+use chrono::Utc;
+use hyper::body::HttpBody;
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, HOST, REFERER, USER_AGENT};
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use serde::*;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+use super::helper::*;
+use super::model::*;
+
+/// Details of a request that are captured before it is processed
+#[derive(Debug, Clone)]
+pub struct AccessRequest {
+    pub time: DateTime<Utc>,
+    pub peer: SocketAddr,
+    pub host: Option<String>,
+    pub user: Option<String>,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessRequest {
+    pub fn new(req: &Request<Body>, peer: SocketAddr) -> AccessRequest {
+        let headers = Some(req.headers());
+        let host = req
+            .uri()
+            .host()
+            .or_else(|| header_str(headers, HOST))
+            .map(|a| a.split(":").next().unwrap_or(a).to_lowercase());
+
+        // Only the user name of basic authentication is ever logged
+        let user = header_str(headers, AUTHORIZATION)
+            .and_then(|a| a.trim().strip_prefix("Basic "))
+            .and_then(|a| base64::decode(a.trim()).ok())
+            .and_then(|a| String::from_utf8(a).ok())
+            .and_then(|a| a.split(":").next().map(|a| a.to_string()));
+
+        AccessRequest {
+            time: Utc::now(),
+            peer,
+            host,
+            user,
+            method: req.method().to_string(),
+            uri: req
+                .uri()
+                .path_and_query()
+                .map(|a| a.to_string())
+                .unwrap_or_else(|| "/".to_string()),
+            version: format!("{:?}", req.version()),
+            referer: header_str(headers, REFERER).map(|a| a.to_string()),
+            user_agent: header_str(headers, USER_AGENT).map(|a| a.to_string()),
+        }
+    }
+}
+
+/// Single line of the access logs
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    pub time: String,
+    pub host: Option<String>,
+    pub peer: String,
+    pub user: Option<String>,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub status: u16,
+    pub bytes: Option<u64>,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub duration_ms: u64,
+    #[serde(skip)]
+    clf_time: String,
+}
+
+impl AccessLogEntry {
+    pub fn new(req: AccessRequest, resp: &Response<Body>, elapsed: Duration) -> AccessLogEntry {
+        AccessLogEntry {
+            time: req.time.to_rfc3339(),
+            clf_time: req.time.format("%d/%b/%Y:%H:%M:%S %z").to_string(),
+            host: req.host,
+            peer: req.peer.ip().to_string(),
+            user: req.user,
+            method: req.method,
+            uri: req.uri,
+            version: req.version,
+            status: resp.status().as_u16(),
+            // Streamed responses report the size that was announced to the client
+            bytes: header_str(Some(resp.headers()), CONTENT_LENGTH)
+                .and_then(|a| a.parse::<u64>().ok())
+                .or_else(|| resp.body().size_hint().exact()),
+            referer: req.referer,
+            user_agent: req.user_agent,
+            duration_ms: elapsed.as_millis() as u64,
+        }
+    }
+
+    /// Formats the entry as a single line (including the line break)
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        let mut ret = match format {
+            AccessLogFormat::Common => self.format_common(),
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                self.format_common(),
+                clf_escape(self.referer.as_deref().unwrap_or("-")),
+                clf_escape(self.user_agent.as_deref().unwrap_or("-"))
+            ),
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        };
+        ret.push('\n');
+        ret
+    }
+
+    fn format_common(&self) -> String {
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {}",
+            self.peer,
+            clf_escape(self.user.as_deref().unwrap_or("-")),
+            self.clf_time,
+            clf_escape(self.method.as_str()),
+            clf_escape(self.uri.as_str()),
+            self.version,
+            self.status,
+            self.bytes
+                .map(|a| a.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        )
+    }
+}
+
+fn clf_escape(val: &str) -> String {
+    let mut ret = String::with_capacity(val.len());
+    for c in val.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            c if c.is_control() => ret.push_str(format!("\\x{:02x}", c as u32).as_str()),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Appends the access logs of all the sites to a local file
+pub struct AccessLogFile {
+    format: AccessLogFormat,
+    file: Mutex<File>,
+}
+
+impl AccessLogFile {
+    pub fn open(path: &Path, format: AccessLogFormat) -> std::io::Result<AccessLogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AccessLogFile {
+            format,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let line = entry.format(self.format);
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            warn!("failed to write access log - {}", err);
+        }
+    }
+}
@@ -193,6 +193,27 @@ impl Account {
 
         Ok((challenge, certified_key, cert_pem, pk_pem))
     }
+
+    /// Returns the DNS-01 challenge along with the value that must be placed
+    /// in the `_acme-challenge` TXT record of the domain
+    pub fn dns_01<'a>(
+        &self,
+        challenges: &'a Vec<Challenge>,
+    ) -> Result<(&'a Challenge, String), AcmeError> {
+        let challenge = challenges
+            .iter()
+            .filter(|c| c.typ == ChallengeType::Dns01)
+            .next();
+
+        let challenge = match challenge {
+            Some(challenge) => challenge,
+            None => return Err(AcmeErrorKind::NoDns01Challenge.into()),
+        };
+
+        let key_auth = key_authorization_sha256(&self.key_pair, &*challenge.token)?;
+        let value = base64::encode_config(key_auth.as_ref(), URL_SAFE_NO_PAD);
+        Ok((challenge, value))
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -207,7 +228,12 @@ pub struct Directory {
 
 impl Directory {
     pub async fn discover(url: &str) -> Result<Self, AcmeError> {
-        let insecure = url == PEBBLE_DIRECTORY;
+        Self::discover_with(url, url == PEBBLE_DIRECTORY).await
+    }
+
+    /// Discovers the directory of an ACME server, optionally accepting an
+    /// invalid TLS certificate (e.g. a private CA used for testing)
+    pub async fn discover_with(url: &str, insecure: bool) -> Result<Self, AcmeError> {
         let (body, _) = api_call(url, Method::GET, None, insecure).await?;
         let mut ret: Directory = serde_json::from_str(body.as_str())?;
         ret.insecure = insecure;
@@ -254,6 +280,10 @@ pub enum Auth {
     Pending {
         identifier: Identifier,
         challenges: Vec<Challenge>,
+        /// Set for the authorizations of wildcard domains (the identifier
+        /// holds the base domain)
+        #[serde(default)]
+        wildcard: bool,
     },
     Valid,
     Invalid,
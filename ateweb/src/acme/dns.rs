@@ -0,0 +1,31 @@
+use async_trait::async_trait;
+use std::fmt::Debug;
+
+use crate::error::*;
+
+/// Name of the TXT record that holds the DNS-01 challenge for a domain
+/// (wildcard domains use the record of their base domain)
+pub fn dns_01_record_name(domain: &str) -> String {
+    let domain = domain.strip_prefix("*.").unwrap_or(domain);
+    format!("_acme-challenge.{}", domain.trim_end_matches('.'))
+}
+
+/// Source of DNS updates used to answer DNS-01 challenges, which allows
+/// certificates to be issued for wildcard domains and for sites that are
+/// not directly reachable (e.g. behind a load balancer)
+#[async_trait]
+pub trait DnsProvider: Send + Sync + Debug {
+    /// Returns true if this provider is able to update the records of a domain
+    fn handles(&self, domain: &str) -> bool;
+
+    /// Adds a TXT record (existing values for the same name are kept)
+    async fn set_txt(&self, name: &str, value: &str) -> Result<(), DnsError>;
+
+    /// Removes a TXT record that was previously added
+    async fn remove_txt(&self, name: &str, value: &str) -> Result<(), DnsError>;
+
+    /// Waits until a newly added TXT record is visible to the ACME server
+    async fn wait_for_txt(&self, _name: &str, _value: &str) -> Result<(), DnsError> {
+        Ok(())
+    }
+}
@@ -1,7 +1,11 @@
 mod acme;
+mod dns;
 mod resolver;
+mod rfc2136;
 mod security;
 
 pub use acme::*;
+pub use dns::*;
 pub use resolver::*;
+pub use rfc2136::*;
 pub use security::*;
@@ -1,14 +1,14 @@
 use super::acme::{
     Account,
     Auth,
+    Challenge,
     Directory,
     Identifier,
     Order,
     ACME_TLS_ALPN_NAME,
-    LETS_ENCRYPT_PRODUCTION_DIRECTORY,
-    //LETS_ENCRYPT_STAGING_DIRECTORY,
-    //PEBBLE_DIRECTORY,
+    PEBBLE_DIRECTORY,
 };
+use super::dns::*;
 use ate::prelude::*;
 use bytes::Bytes;
 use futures::future::try_join_all;
@@ -32,6 +32,7 @@ use ttl_cache::TtlCache;
 use x509_parser::parse_x509_certificate;
 use ate_files::repo::*;
 
+use crate::conf::AcmeConf;
 use crate::error::*;
 use crate::model::*;
 
@@ -39,19 +40,94 @@ use crate::model::*;
 pub struct AcmeState {
     err_cnt: i64,
     next_try: Option<chrono::DateTime<chrono::Utc>>,
+    /// Manually uploaded certificates are never renewed, instead they are
+    /// periodically reloaded in case a replacement was uploaded
+    next_manual_check: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Identifies the certificate that serves a particular host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertName {
+    /// Name the certificate is cached under (e.g. `*.example.com`)
+    pub name: String,
+    /// Host whose files chain stores the certificate
+    pub host: String,
+    pub wildcard: bool,
+}
+
+impl CertName {
+    /// Determines the certificate of a host, where the hosts directly under
+    /// a wildcard domain (and the domain itself) share its certificate
+    pub fn new(wildcard_domains: &[String], sni: &str) -> CertName {
+        let sni = sni.trim_end_matches('.').to_lowercase();
+        for domain in wildcard_domains.iter() {
+            let covered = sni == *domain
+                || sni
+                    .strip_suffix(domain.as_str())
+                    .and_then(|a| a.strip_suffix('.'))
+                    .map(|a| a.len() > 0 && a.contains('.') == false)
+                    .unwrap_or(false);
+            if covered {
+                return CertName {
+                    name: format!("*.{}", domain),
+                    host: domain.clone(),
+                    wildcard: true,
+                };
+            }
+        }
+        CertName {
+            name: sni.clone(),
+            host: sni,
+            wildcard: false,
+        }
+    }
+
+    fn domains(&self) -> Vec<String> {
+        match self.wildcard {
+            true => vec![self.name.clone(), self.host.clone()],
+            false => vec![self.host.clone()],
+        }
+    }
+
+    fn files(&self) -> (&'static str, &'static str) {
+        match self.wildcard {
+            true => (WEB_CONF_FILES_WILDCARD_CERT, WEB_CONF_FILES_WILDCARD_KEY),
+            false => (WEB_CONF_FILES_WEB_CERT, WEB_CONF_FILES_WEB_KEY),
+        }
+    }
+}
+
+/// Splits a PEM bundle (as uploaded by an operator) into the certificate
+/// chain and the private key
+pub fn split_pem_bundle(bundle: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let (keys, certs): (Vec<_>, Vec<_>) = pem::parse_many(bundle)
+        .into_iter()
+        .partition(|a| a.tag.ends_with("PRIVATE KEY"));
+    if keys.len() != 1 || certs.len() <= 0 {
+        return None;
+    }
+    Some((
+        pem::encode_many(&certs[..]).into_bytes(),
+        pem::encode(&keys[0]).into_bytes(),
+    ))
 }
 
 pub struct AcmeResolver {
     pub repo: Arc<Repository>,
+    pub conf: AcmeConf,
     pub certs: StdRwLock<TtlCache<String, CertifiedKey>>,
     pub auths: StdRwLock<TtlCache<String, CertifiedKey>>,
     pub locks: StdMutex<FxHashMap<String, Arc<Mutex<AcmeState>>>>,
 }
 
 impl AcmeResolver {
-    pub async fn new(repo: &Arc<Repository>) -> Result<Arc<AcmeResolver>, AteError> {
+    pub async fn new(
+        repo: &Arc<Repository>,
+        conf: AcmeConf,
+    ) -> Result<Arc<AcmeResolver>, AteError> {
         let ret = AcmeResolver {
             repo: Arc::clone(repo),
+            conf,
             certs: StdRwLock::new(TtlCache::new(65536usize)),
             auths: StdRwLock::new(TtlCache::new(1024usize)),
             locks: StdMutex::new(FxHashMap::default()),
@@ -129,16 +205,96 @@ impl AcmeResolver {
         Ok(())
     }
 
+    /// Determines which certificate serves a host (the hosts under a wildcard
+    /// domain share the certificate of that domain)
+    pub fn cert_name(&self, sni: &str) -> CertName {
+        CertName::new(&self.conf.wildcard_domains[..], sni)
+    }
+
+    /// Returns the provider that answers DNS-01 challenges for a domain (if any)
+    fn dns_provider(&self, domain: &str) -> Option<&Arc<dyn DnsProvider>> {
+        self.conf
+            .dns_providers
+            .iter()
+            .filter(|a| a.handles(domain))
+            .next()
+    }
+
+    /// Stores a manually uploaded certificate for a host which is then served
+    /// in preference to (and never renewed by) ACME
+    pub async fn upload_cert(
+        &self,
+        host: &str,
+        cert_pem: &[u8],
+        key_pem: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let cert = self.cert_name(host);
+        let cert_key = self
+            .process_cert(
+                cert.name.as_str(),
+                Bytes::copy_from_slice(cert_pem),
+                Bytes::copy_from_slice(key_pem),
+            )
+            .await?;
+        let cert_key = match cert_key {
+            Some(a) => a,
+            None => {
+                return Err(format!("invalid certificate or private key for {}", cert.name).into());
+            }
+        };
+
+        let web_key = ChainKey::from(format!("{}/www", cert.host));
+        self.repo
+            .set_file(&web_key, cert.host.as_str(), WEB_CONF_FILES_MANUAL_CERT, cert_pem)
+            .await?;
+        self.repo
+            .set_file(&web_key, cert.host.as_str(), WEB_CONF_FILES_MANUAL_KEY, key_pem)
+            .await?;
+
+        let mut guard = self.certs.write().unwrap();
+        guard.insert(cert.name, cert_key, Duration::from_secs(3600));
+        Ok(())
+    }
+
+    /// Loads a manually uploaded certificate (if one exists)
+    async fn load_manual(
+        &self,
+        cert: &CertName,
+    ) -> Result<Option<CertifiedKey>, Box<dyn std::error::Error>> {
+        let web_key = ChainKey::from(format!("{}/www", cert.host));
+        let cert_pem = self
+            .repo
+            .get_file(&web_key, cert.host.as_str(), WEB_CONF_FILES_MANUAL_CERT)
+            .await?;
+        let key_pem = self
+            .repo
+            .get_file(&web_key, cert.host.as_str(), WEB_CONF_FILES_MANUAL_KEY)
+            .await?;
+        match (cert_pem, key_pem) {
+            (Some(cert_pem), Some(key_pem)) => {
+                self.process_cert(cert.name.as_str(), cert_pem, key_pem)
+                    .await
+            }
+            (Some(_), None) => {
+                warn!("missing manual certificate private key for {}", cert.name);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
     pub async fn touch_web(
         &self,
         sni: String,
         renewal: chrono::Duration,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let cert = self.cert_name(sni.as_str());
+
         // Fast path
         {
             let guard = self.certs.read().unwrap();
-            if let Some(cert) = guard.get(&sni) {
-                let d = self.duration_until_renewal_attempt(cert, renewal);
+            if let Some(cert_key) = guard.get(&cert.name) {
+                let d = self.duration_until_renewal_attempt(cert_key, renewal);
                 if d.as_secs() > 0 {
                     trace!("next renewal attempt in {}s", d.as_secs());
                     return Ok(());
@@ -146,11 +302,12 @@ impl AcmeResolver {
             }
         }
 
-        let web_key = ChainKey::from(format!("{}/www", sni));
+        let web_key = ChainKey::from(format!("{}/www", cert.host));
+        let (cert_file, key_file) = cert.files();
 
         let lock = {
             let mut guard = self.locks.lock().unwrap();
-            match guard.entry(sni.clone()) {
+            match guard.entry(cert.name.clone()) {
                 Entry::Occupied(a) => Arc::clone(a.get()),
                 Entry::Vacant(a) => {
                     let ret = Arc::new(Mutex::new(AcmeState::default()));
@@ -164,8 +321,8 @@ impl AcmeResolver {
         // Slow path
         let loaded = {
             let guard = self.certs.read().unwrap();
-            if let Some(cert) = guard.get(&sni) {
-                let d = self.duration_until_renewal_attempt(cert, renewal);
+            if let Some(cert_key) = guard.get(&cert.name) {
+                let d = self.duration_until_renewal_attempt(cert_key, renewal);
                 if d.as_secs() > 0 {
                     trace!("next renewal attempt in {}s", d.as_secs());
                     return Ok(());
@@ -176,42 +333,65 @@ impl AcmeResolver {
             }
         };
 
+        // Manually uploaded certificates take precedence over those ordered
+        // from the ACME server
+        if let Some(next_check) = lock.next_manual_check {
+            if next_check.gt(&chrono::Utc::now()) {
+                trace!("aborting attempt as the certificate was manually uploaded");
+                return Ok(());
+            }
+        }
+        if let Some(cert_key) = self.load_manual(&cert).await? {
+            if self.duration_until_renewal_attempt(&cert_key, renewal).as_secs() <= 0 {
+                warn!("manually uploaded certificate for {} is about to expire", cert.name);
+            }
+            lock.next_manual_check = Some(chrono::Utc::now() + chrono::Duration::minutes(5));
+
+            let mut guard = self.certs.write().unwrap();
+            guard.insert(cert.name.clone(), cert_key, Duration::from_secs(3600));
+            return Ok(());
+        }
+        lock.next_manual_check = None;
+
         // If we have never loaded the certificates from disk then load them now
         if loaded == false {
-            let cert = self
+            let cert_pem = self
                 .repo
-                .get_file(&web_key, sni.as_str(), WEB_CONF_FILES_WEB_CERT)
+                .get_file(&web_key, cert.host.as_str(), cert_file)
                 .await?;
-            let key = self
+            let key_pem = self
                 .repo
-                .get_file(&web_key, sni.as_str(), WEB_CONF_FILES_WEB_KEY)
+                .get_file(&web_key, cert.host.as_str(), key_file)
                 .await?;
-            if let Some(cert) = cert {
-                if let Some(key) = key {
-                    if let Some(cert_key) = self.process_cert(sni.as_str(), cert, key).await? {
+            if let Some(cert_pem) = cert_pem {
+                if let Some(key_pem) = key_pem {
+                    if let Some(cert_key) = self
+                        .process_cert(cert.name.as_str(), cert_pem, key_pem)
+                        .await?
+                    {
                         let mut guard = self.certs.write().unwrap();
-                        guard.insert(sni.to_string(), cert_key.clone(), Duration::from_secs(3600));
+                        guard.insert(cert.name.clone(), cert_key.clone(), Duration::from_secs(3600));
 
                         let d = self.duration_until_renewal_attempt(&cert_key, renewal);
                         if d.as_secs() > 0 {
                             trace!("next renewal attempt in {}s", d.as_secs());
                             return Ok(());
                         } else {
-                            info!("certificate will be renewed for {}", sni);
+                            info!("certificate will be renewed for {}", cert.name);
                         }
                     } else {
                         warn!("failed to process certificate");
                     }
                 } else {
-                    warn!("missing certificate private key for {}", sni);
+                    warn!("missing certificate private key for {}", cert.name);
                 }
             } else {
-                warn!("missing certificate chain for {}", sni);
+                warn!("missing certificate chain for {}", cert.name);
             }
 
             // If the file system that backs this web site is not even in existance then we should
             // not try and generate a certificate as we have nowhere to save it
-            let accessor = self.repo.get_accessor(&web_key, sni.as_str()).await?;
+            let accessor = self.repo.get_accessor(&web_key, cert.host.as_str()).await?;
             if accessor
                 .root(&ate_files::prelude::RequestContext::default())
                 .await?
@@ -230,28 +410,25 @@ impl AcmeResolver {
             }
         }
 
-        let directory_url = LETS_ENCRYPT_PRODUCTION_DIRECTORY;
-        //let directory_url = LETS_ENCRYPT_STAGING_DIRECTORY;
-        //let directory_url = PEBBLE_DIRECTORY;
         let expires = chrono::Duration::days(90);
 
-        // Order the certificate using lets encrypt
+        // Order the certificate using the ACME server
         debug!("ordering of certificate started");
-        match self.order(&directory_url, sni.as_str(), expires).await {
+        match self.order(&cert, expires).await {
             Ok((cert_key, cert_pem, pk_pem)) => {
                 debug!("successfully ordered certificate");
                 lock.err_cnt = 0i64;
                 lock.next_try = None;
 
                 self.repo
-                    .set_file(&web_key, sni.as_str(), WEB_CONF_FILES_WEB_CERT, cert_pem.as_bytes())
+                    .set_file(&web_key, cert.host.as_str(), cert_file, cert_pem.as_bytes())
                     .await?;
                 self.repo
-                    .set_file(&web_key, sni.as_str(), WEB_CONF_FILES_WEB_KEY, pk_pem.as_bytes())
+                    .set_file(&web_key, cert.host.as_str(), key_file, pk_pem.as_bytes())
                     .await?;
 
                 let mut guard = self.certs.write().unwrap();
-                guard.insert(sni.to_string(), cert_key, Duration::from_secs(3600));
+                guard.insert(cert.name.clone(), cert_key, Duration::from_secs(3600));
             }
             Err(err) => {
                 warn!("ordering certificate failed: {}", err);
@@ -288,12 +465,11 @@ impl AcmeResolver {
 
     async fn order(
         &self,
-        directory_url: &str,
-        domain: &str,
+        cert_name: &CertName,
         duration: chrono::Duration,
     ) -> Result<(CertifiedKey, String, String), OrderError> {
-        let contacts = vec![format!("mailto:info@{}", domain)];
-        let domains = vec![domain.to_string()];
+        let contacts = vec![format!("mailto:info@{}", cert_name.host)];
+        let domains = cert_name.domains();
         let not_before = chrono::Utc::now();
         let mut not_after = not_before.clone();
         if let Some(not_after_next) = not_before.checked_add_signed(duration) {
@@ -312,7 +488,9 @@ impl AcmeResolver {
         let pk = any_supported_type(&PrivateKey(pk_bytes.clone())).unwrap();
 
         debug!("load_or_create account");
-        let directory = Directory::discover(directory_url).await?;
+        let directory_url = self.conf.directory_url.as_str();
+        let insecure = self.conf.insecure || directory_url == PEBBLE_DIRECTORY;
+        let directory = Directory::discover_with(directory_url, insecure).await?;
         let account = Account::load_or_create(directory, &contacts).await?;
 
         debug!("new order for {:?}", domains);
@@ -326,7 +504,7 @@ impl AcmeResolver {
                 } => {
                     let auth_futures = authorizations
                         .iter()
-                        .map(|url| self.authorize(&account, domain, url));
+                        .map(|url| self.authorize(&account, cert_name.host.as_str(), url));
                     try_join_all(auth_futures).await?;
                     debug!("completed all authorizations");
                     Order::Ready { finalize }
@@ -381,8 +559,24 @@ impl AcmeResolver {
             Auth::Pending {
                 identifier,
                 challenges,
+                wildcard,
             } => {
                 let Identifier::Dns(domain) = identifier;
+
+                // Wildcard domains can only be validated with DNS-01 challenges
+                // which are also preferred whenever a DNS provider is available
+                // (as the domain may not point directly at this server)
+                let provider = self.dns_provider(domain.as_str());
+                if wildcard || provider.is_some() {
+                    let provider = match provider {
+                        Some(a) => a,
+                        None => return Err(OrderErrorKind::NoDnsProvider(domain).into()),
+                    };
+                    return self
+                        .authorize_dns_01(account, url, domain, &challenges, provider)
+                        .await;
+                }
+
                 info!("trigger challenge for {}", &domain);
                 let (challenge, _auth_key, cert_pem, pk_pem) =
                     account.tls_alpn_01(&challenges, domain.clone())?;
@@ -410,6 +604,44 @@ impl AcmeResolver {
             Auth::Valid => return Ok(()),
             auth => return Err(OrderErrorKind::BadAuth(auth).into()),
         };
+        self.wait_for_auth(account, url, domain, challenge_url).await
+    }
+
+    async fn authorize_dns_01(
+        &self,
+        account: &Account,
+        url: &String,
+        domain: String,
+        challenges: &Vec<Challenge>,
+        provider: &Arc<dyn DnsProvider>,
+    ) -> Result<(), OrderError> {
+        info!("trigger dns challenge for {}", &domain);
+        let (challenge, value) = account.dns_01(challenges)?;
+        let name = dns_01_record_name(domain.as_str());
+        provider.set_txt(name.as_str(), value.as_str()).await?;
+
+        let ret = async {
+            provider.wait_for_txt(name.as_str(), value.as_str()).await?;
+            account.challenge(&challenge.url).await?;
+            self.wait_for_auth(account, url, domain, challenge.url.clone())
+                .await
+        }
+        .await;
+
+        // The record is no longer needed whether or not the challenge passed
+        if let Err(err) = provider.remove_txt(name.as_str(), value.as_str()).await {
+            warn!("failed to remove the dns challenge record ({}) - {}", name, err);
+        }
+        ret
+    }
+
+    async fn wait_for_auth(
+        &self,
+        account: &Account,
+        url: &String,
+        domain: String,
+        challenge_url: String,
+    ) -> Result<(), OrderError> {
         for i in 0u64..5 {
             ate::engine::sleep(Duration::from_secs(1 << i)).await;
             match account.auth(url).await? {
@@ -442,9 +674,10 @@ impl ResolvesServerCert for AcmeResolver {
                 }
             }
 
+            let name = self.cert_name(sni.as_str()).name;
             let guard = self.certs.read().unwrap();
 
-            return if let Some(cert) = guard.get(&sni) {
+            return if let Some(cert) = guard.get(&name) {
                 trace!("tls_hello: cert_hit={:?}", sni);
                 Some(cert.clone())
             } else {
@@ -457,3 +690,54 @@ impl ResolvesServerCert for AcmeResolver {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_cert_names() {
+        let domains = vec!["example.com".to_string(), "sites.example.org".to_string()];
+        let cases: &[(&str, &str, &str, bool)] = &[
+            ("www.example.com", "*.example.com", "example.com", true),
+            ("WWW.Example.Com.", "*.example.com", "example.com", true),
+            ("example.com", "*.example.com", "example.com", true),
+            ("a.sites.example.org", "*.sites.example.org", "sites.example.org", true),
+            // Wildcards only cover a single label
+            ("a.b.example.com", "a.b.example.com", "a.b.example.com", false),
+            ("badexample.com", "badexample.com", "badexample.com", false),
+            ("example.org", "example.org", "example.org", false),
+            ("other.net", "other.net", "other.net", false),
+        ];
+        for (sni, name, host, wildcard) in cases {
+            let cert = CertName::new(&domains[..], sni);
+            assert_eq!(cert.name, *name, "{}", sni);
+            assert_eq!(cert.host, *host, "{}", sni);
+            assert_eq!(cert.wildcard, *wildcard, "{}", sni);
+        }
+        assert_eq!(
+            CertName::new(&domains[..], "www.example.com").domains(),
+            vec!["*.example.com".to_string(), "example.com".to_string()]
+        );
+    }
+
+    #[test]
+    fn pem_bundles() {
+        let cert = pem::Pem {
+            tag: "CERTIFICATE".to_string(),
+            contents: vec![1, 2, 3],
+        };
+        let key = pem::Pem {
+            tag: "PRIVATE KEY".to_string(),
+            contents: vec![4, 5, 6],
+        };
+        let bundle = pem::encode_many(&[cert.clone(), key.clone(), cert.clone()]);
+        let (certs, found) = split_pem_bundle(bundle.as_bytes()).unwrap();
+        assert_eq!(pem::parse_many(&certs[..]), vec![cert.clone(), cert.clone()]);
+        assert_eq!(pem::parse(&found[..]).unwrap(), key);
+
+        assert!(split_pem_bundle(pem::encode(&cert).as_bytes()).is_none());
+        assert!(split_pem_bundle(pem::encode(&key).as_bytes()).is_none());
+        assert!(split_pem_bundle(b"garbage").is_none());
+    }
+}
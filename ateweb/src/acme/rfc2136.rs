@@ -0,0 +1,592 @@
+use async_trait::async_trait;
+use error_chain::bail;
+use ring::hmac;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::UdpSocket;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+
+use super::dns::*;
+use crate::error::*;
+
+const OPCODE_UPDATE: u16 = 5;
+const FLAG_QR: u8 = 0x80;
+const FLAG_TC: u8 = 0x02;
+
+const TYPE_SOA: u16 = 6;
+const TYPE_TXT: u16 = 16;
+const TYPE_TSIG: u16 = 250;
+
+const CLASS_IN: u16 = 1;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+
+/// Permitted difference (in seconds) between the clocks of the client and
+/// the DNS server when validating TSIG signatures
+const TSIG_FUDGE: u16 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match self {
+            TsigAlgorithm::HmacSha256 => hmac::HMAC_SHA256,
+            TsigAlgorithm::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+impl std::str::FromStr for TsigAlgorithm {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end_matches('.').to_lowercase().as_str() {
+            "hmac-sha256" => Ok(TsigAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(TsigAlgorithm::HmacSha512),
+            _ => Err("valid values are 'hmac-sha256' and 'hmac-sha512'"),
+        }
+    }
+}
+
+/// Shared secret that signs the updates sent to the DNS server (RFC 8945)
+#[derive(Clone)]
+pub struct TsigKey {
+    pub name: String,
+    pub algorithm: TsigAlgorithm,
+    pub secret: Vec<u8>,
+}
+
+impl TsigKey {
+    /// Creates a key from its base64 encoded secret (the format used by
+    /// `tsig-keygen` and in BIND configuration files)
+    pub fn from_base64(
+        name: &str,
+        algorithm: TsigAlgorithm,
+        secret: &str,
+    ) -> Result<TsigKey, DnsError> {
+        let secret =
+            base64::decode(secret.trim()).map_err(|err| DnsErrorKind::BadKey(err.to_string()))?;
+        Ok(TsigKey {
+            name: name.to_string(),
+            algorithm,
+            secret,
+        })
+    }
+}
+
+impl fmt::Debug for TsigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TsigKey")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+/// Answers DNS-01 challenges by sending dynamic updates (RFC 2136) to the
+/// primary server of a zone, for instance BIND, Knot or PowerDNS
+#[derive(Debug, Clone)]
+pub struct Rfc2136Provider {
+    /// Address of the primary DNS server for the zone
+    pub server: SocketAddr,
+    /// Zone that the records are updated within (e.g. `example.com`)
+    pub zone: String,
+    /// Key used to sign the updates (most servers require one)
+    pub key: Option<TsigKey>,
+    /// Time to live of the challenge records
+    pub ttl: u32,
+    /// Maximum amount of time to wait for the DNS server to respond
+    pub timeout: Duration,
+    /// Maximum amount of time to wait for a new record to become visible
+    pub propagation_timeout: Duration,
+}
+
+impl Rfc2136Provider {
+    pub fn new(server: SocketAddr, zone: &str) -> Rfc2136Provider {
+        Rfc2136Provider {
+            server,
+            zone: zone.trim_end_matches('.').to_lowercase(),
+            key: None,
+            ttl: 60,
+            timeout: Duration::from_secs(5),
+            propagation_timeout: Duration::from_secs(120),
+        }
+    }
+
+    pub fn with_tsig(mut self, key: TsigKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Queries the DNS server for the TXT records of a name
+    pub async fn query_txt(&self, name: &str) -> Result<Vec<String>, DnsError> {
+        let id = fastrand::u16(..);
+        let mut msg = Vec::new();
+        put_u16(&mut msg, id);
+        put_u16(&mut msg, 0);
+        put_u16(&mut msg, 1);
+        put_u16(&mut msg, 0);
+        put_u16(&mut msg, 0);
+        put_u16(&mut msg, 0);
+        encode_name(&mut msg, name)?;
+        put_u16(&mut msg, TYPE_TXT);
+        put_u16(&mut msg, CLASS_IN);
+
+        let resp = self.exchange(&msg[..], id).await?;
+        parse_txt_answers(&resp[..])
+    }
+
+    async fn update(&self, name: &str, value: &str, add: bool) -> Result<(), DnsError> {
+        let id = fastrand::u16(..);
+        let mut msg = self.update_message(id, name, value, add)?;
+        self.sign(&mut msg, id)?;
+        self.exchange(&msg[..], id).await?;
+        Ok(())
+    }
+
+    /// Builds the (unsigned) message that adds or deletes a TXT record
+    fn update_message(
+        &self,
+        id: u16,
+        name: &str,
+        value: &str,
+        add: bool,
+    ) -> Result<Vec<u8>, DnsError> {
+        let mut msg = Vec::new();
+        put_u16(&mut msg, id);
+        put_u16(&mut msg, OPCODE_UPDATE << 11);
+        put_u16(&mut msg, 1); // zone count
+        put_u16(&mut msg, 0); // prerequisite count
+        put_u16(&mut msg, 1); // update count
+        put_u16(&mut msg, 0); // additional count
+
+        // Zone section
+        encode_name(&mut msg, self.zone.as_str())?;
+        put_u16(&mut msg, TYPE_SOA);
+        put_u16(&mut msg, CLASS_IN);
+
+        // Update section (records are deleted by using the NONE class)
+        encode_name(&mut msg, name)?;
+        put_u16(&mut msg, TYPE_TXT);
+        match add {
+            true => {
+                put_u16(&mut msg, CLASS_IN);
+                put_u32(&mut msg, self.ttl);
+            }
+            false => {
+                put_u16(&mut msg, CLASS_NONE);
+                put_u32(&mut msg, 0);
+            }
+        }
+        let rdata = encode_txt(value);
+        put_u16(&mut msg, rdata.len() as u16);
+        msg.extend_from_slice(&rdata[..]);
+        Ok(msg)
+    }
+
+    /// Appends a TSIG record that signs the message
+    fn sign(&self, msg: &mut Vec<u8>, id: u16) -> Result<(), DnsError> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.sign_at(msg, id, time)
+    }
+
+    /// Appends a TSIG record that signs the message as of a particular time
+    /// (in seconds since the epoch)
+    fn sign_at(&self, msg: &mut Vec<u8>, id: u16, time: u64) -> Result<(), DnsError> {
+        let key = match self.key.as_ref() {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+
+        let mut key_name = Vec::new();
+        encode_name(&mut key_name, key.name.to_lowercase().as_str())?;
+        let mut algorithm = Vec::new();
+        encode_name(&mut algorithm, key.algorithm.name())?;
+
+        // The MAC covers the message followed by the TSIG variables
+        let mut vars = Vec::new();
+        vars.extend_from_slice(&key_name[..]);
+        put_u16(&mut vars, CLASS_ANY);
+        put_u32(&mut vars, 0);
+        vars.extend_from_slice(&algorithm[..]);
+        put_u48(&mut vars, time);
+        put_u16(&mut vars, TSIG_FUDGE);
+        put_u16(&mut vars, 0); // error
+        put_u16(&mut vars, 0); // other length
+
+        let hmac_key = hmac::Key::new(key.algorithm.hmac(), &key.secret[..]);
+        let mut ctx = hmac::Context::with_key(&hmac_key);
+        ctx.update(&msg[..]);
+        ctx.update(&vars[..]);
+        let mac = ctx.sign();
+        let mac = mac.as_ref();
+
+        let mut rdata = algorithm;
+        put_u48(&mut rdata, time);
+        put_u16(&mut rdata, TSIG_FUDGE);
+        put_u16(&mut rdata, mac.len() as u16);
+        rdata.extend_from_slice(mac);
+        put_u16(&mut rdata, id);
+        put_u16(&mut rdata, 0); // error
+        put_u16(&mut rdata, 0); // other length
+
+        msg.extend_from_slice(&key_name[..]);
+        put_u16(msg, TYPE_TSIG);
+        put_u16(msg, CLASS_ANY);
+        put_u32(msg, 0);
+        put_u16(msg, rdata.len() as u16);
+        msg.extend_from_slice(&rdata[..]);
+
+        let additional = u16::from_be_bytes([msg[10], msg[11]]) + 1;
+        msg[10..12].copy_from_slice(&additional.to_be_bytes());
+        Ok(())
+    }
+
+    /// Sends a message to the DNS server over UDP (falling back to TCP when
+    /// the response is truncated) and checks the response code
+    async fn exchange(&self, msg: &[u8], id: u16) -> Result<Vec<u8>, DnsError> {
+        let ret = ate::engine::timeout(self.timeout, async {
+            let bind: SocketAddr = match self.server {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            }
+            .parse()
+            .unwrap();
+            let socket = UdpSocket::bind(bind).await?;
+            socket.connect(self.server).await?;
+            socket.send(msg).await?;
+
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let n = socket.recv(&mut buf[..]).await?;
+                // Stray responses to earlier requests are ignored
+                if n < 12 || u16::from_be_bytes([buf[0], buf[1]]) != id {
+                    continue;
+                }
+                buf.truncate(n);
+                break;
+            }
+            if buf[2] & FLAG_TC != 0 {
+                trace!("dns response was truncated, retrying over tcp");
+                let mut stream = TcpStream::connect(self.server).await?;
+                let mut req = Vec::with_capacity(msg.len() + 2);
+                put_u16(&mut req, msg.len() as u16);
+                req.extend_from_slice(msg);
+                stream.write_all(&req[..]).await?;
+
+                let len = stream.read_u16().await?;
+                buf = vec![0u8; len as usize];
+                stream.read_exact(&mut buf[..]).await?;
+            }
+            Ok::<_, DnsError>(buf)
+        })
+        .await;
+
+        let resp = match ret {
+            Ok(a) => a?,
+            Err(_) => bail!(DnsErrorKind::Timeout),
+        };
+        if resp.len() < 12 || u16::from_be_bytes([resp[0], resp[1]]) != id {
+            bail!(DnsErrorKind::BadResponse(
+                "mismatched identifier".to_string()
+            ));
+        }
+        if resp[2] & FLAG_QR == 0 {
+            bail!(DnsErrorKind::BadResponse("not a response".to_string()));
+        }
+        let rcode = resp[3] & 0x0F;
+        if rcode != 0 {
+            bail!(DnsErrorKind::ResponseCode(rcode));
+        }
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl DnsProvider for Rfc2136Provider {
+    fn handles(&self, domain: &str) -> bool {
+        let domain = domain.strip_prefix("*.").unwrap_or(domain);
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        domain == self.zone || domain.ends_with(format!(".{}", self.zone).as_str())
+    }
+
+    async fn set_txt(&self, name: &str, value: &str) -> Result<(), DnsError> {
+        debug!("adding txt record (name={})", name);
+        self.update(name, value, true).await
+    }
+
+    async fn remove_txt(&self, name: &str, value: &str) -> Result<(), DnsError> {
+        debug!("removing txt record (name={})", name);
+        self.update(name, value, false).await
+    }
+
+    async fn wait_for_txt(&self, name: &str, value: &str) -> Result<(), DnsError> {
+        let start = std::time::Instant::now();
+        loop {
+            let values = self.query_txt(name).await?;
+            if values.iter().any(|a| a == value) {
+                return Ok(());
+            }
+            if start.elapsed() > self.propagation_timeout {
+                bail!(DnsErrorKind::Timeout);
+            }
+            ate::engine::sleep(Duration::from_secs(2)).await;
+        }
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, val: u16) {
+    buf.extend_from_slice(&val.to_be_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, val: u32) {
+    buf.extend_from_slice(&val.to_be_bytes());
+}
+
+fn put_u48(buf: &mut Vec<u8>, val: u64) {
+    buf.extend_from_slice(&val.to_be_bytes()[2..]);
+}
+
+fn encode_name(buf: &mut Vec<u8>, name: &str) -> Result<(), DnsError> {
+    let name = name.trim_end_matches('.');
+    let mut len = 1usize;
+    if name.len() > 0 {
+        for label in name.split('.') {
+            if label.len() <= 0 || label.len() > 63 {
+                bail!(DnsErrorKind::BadName(name.to_string()));
+            }
+            len += label.len() + 1;
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    if len > 255 {
+        bail!(DnsErrorKind::BadName(name.to_string()));
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// TXT records are made of character strings that hold at most 255 bytes
+fn encode_txt(value: &str) -> Vec<u8> {
+    let mut ret = Vec::new();
+    for chunk in value.as_bytes().chunks(255) {
+        ret.push(chunk.len() as u8);
+        ret.extend_from_slice(chunk);
+    }
+    if value.len() <= 0 {
+        ret.push(0);
+    }
+    ret
+}
+
+fn skip_name(buf: &[u8], mut pos: usize) -> Result<usize, DnsError> {
+    loop {
+        let len = match buf.get(pos) {
+            Some(a) => *a,
+            None => bail!(DnsErrorKind::BadResponse("truncated name".to_string())),
+        };
+        if len & 0xC0 == 0xC0 {
+            return Ok(pos + 2);
+        }
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        pos += 1 + len as usize;
+    }
+}
+
+fn parse_txt_answers(resp: &[u8]) -> Result<Vec<String>, DnsError> {
+    let truncated =
+        || DnsError::from_kind(DnsErrorKind::BadResponse("truncated record".to_string()));
+    let questions = u16::from_be_bytes([resp[4], resp[5]]);
+    let answers = u16::from_be_bytes([resp[6], resp[7]]);
+
+    let mut pos = 12usize;
+    for _ in 0..questions {
+        pos = skip_name(resp, pos)? + 4;
+    }
+
+    let mut ret = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(resp, pos)?;
+        let header = resp.get(pos..pos + 10).ok_or_else(truncated)?;
+        let typ = u16::from_be_bytes([header[0], header[1]]);
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+        let rdata = resp.get(pos..pos + len).ok_or_else(truncated)?;
+        pos += len;
+        if typ != TYPE_TXT {
+            continue;
+        }
+
+        let mut value = Vec::new();
+        let mut n = 0usize;
+        while n < rdata.len() {
+            let len = rdata[n] as usize;
+            let chunk = rdata.get(n + 1..n + 1 + len).ok_or_else(truncated)?;
+            value.extend_from_slice(chunk);
+            n += 1 + len;
+        }
+        ret.push(String::from_utf8_lossy(&value[..]).to_string());
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> Rfc2136Provider {
+        Rfc2136Provider::new("127.0.0.1:53".parse().unwrap(), "Example.com.")
+    }
+
+    fn test_key() -> TsigKey {
+        TsigKey::from_base64(
+            "acme-key",
+            TsigAlgorithm::HmacSha256,
+            "c2VjcmV0LWZvci10ZXN0cw==",
+        )
+        .unwrap()
+    }
+
+    const UPDATE_HEADER: &[u8] = b"\x12\x34\x28\x00\x00\x01\x00\x00\x00\x01\x00\x00";
+    const UPDATE_ZONE: &[u8] = b"\x07example\x03com\x00\x00\x06\x00\x01";
+    const UPDATE_NAME: &[u8] = b"\x0f_acme-challenge\x07example\x03com\x00\x00\x10";
+
+    #[test]
+    fn encode_name_labels() {
+        let mut buf = Vec::new();
+        encode_name(&mut buf, "www.example.com.").unwrap();
+        assert_eq!(&buf[..], b"\x03www\x07example\x03com\x00");
+
+        let mut buf = Vec::new();
+        encode_name(&mut buf, "").unwrap();
+        assert_eq!(&buf[..], b"\x00");
+
+        assert!(encode_name(&mut Vec::new(), "a..b").is_err());
+        assert!(encode_name(&mut Vec::new(), "x".repeat(64).as_str()).is_err());
+        let long = vec!["x".repeat(63); 4].join(".");
+        assert!(encode_name(&mut Vec::new(), long.as_str()).is_err());
+    }
+
+    #[test]
+    fn encode_txt_chunks() {
+        assert_eq!(encode_txt(""), vec![0u8]);
+        assert_eq!(encode_txt("abc"), b"\x03abc".to_vec());
+
+        let value = "x".repeat(300);
+        let ret = encode_txt(value.as_str());
+        assert_eq!(ret.len(), 302);
+        assert_eq!(ret[0], 255);
+        assert_eq!(ret[256], 45);
+    }
+
+    #[test]
+    fn update_message_add() {
+        let msg = provider()
+            .update_message(0x1234, "_acme-challenge.example.com", "token", true)
+            .unwrap();
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(UPDATE_HEADER);
+        expected.extend_from_slice(UPDATE_ZONE);
+        expected.extend_from_slice(UPDATE_NAME);
+        expected.extend_from_slice(b"\x00\x01\x00\x00\x00\x3c\x00\x06\x05token");
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn update_message_delete() {
+        let msg = provider()
+            .update_message(0x1234, "_acme-challenge.example.com", "token", false)
+            .unwrap();
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(UPDATE_HEADER);
+        expected.extend_from_slice(UPDATE_ZONE);
+        expected.extend_from_slice(UPDATE_NAME);
+        expected.extend_from_slice(b"\x00\xfe\x00\x00\x00\x00\x00\x06\x05token");
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn sign_without_key_is_noop() {
+        let provider = provider();
+        let mut msg = provider
+            .update_message(0x1234, "_acme-challenge.example.com", "token", true)
+            .unwrap();
+        let unsigned = msg.clone();
+        provider.sign_at(&mut msg, 0x1234, 1_600_000_000).unwrap();
+        assert_eq!(msg, unsigned);
+    }
+
+    #[test]
+    fn sign_tsig_hmac_sha256() {
+        let provider = provider().with_tsig(test_key());
+        let mut msg = provider
+            .update_message(0x1234, "_acme-challenge.example.com", "token", true)
+            .unwrap();
+        let unsigned_len = msg.len();
+        provider.sign_at(&mut msg, 0x1234, 1_600_000_000).unwrap();
+
+        // The additional count now includes the TSIG record
+        assert_eq!(&msg[10..12], b"\x00\x01");
+
+        // HMAC-SHA256 over the unsigned message and the TSIG variables
+        let mac: &[u8] = b"\x65\x38\x01\x5f\x64\x47\xd1\x48\x5c\xcb\x2a\xae\x74\xe5\x1b\x63\
+                           \x81\x90\x5f\xb7\x7c\x03\x80\x39\xdb\xcd\xbd\x0f\x2b\xf2\x22\x00";
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"\x08acme-key\x00");
+        expected.extend_from_slice(b"\x00\xfa\x00\xff\x00\x00\x00\x00");
+        expected.extend_from_slice(b"\x00\x3d");
+        expected.extend_from_slice(b"\x0bhmac-sha256\x00");
+        expected.extend_from_slice(b"\x00\x00\x5f\x5e\x10\x00\x01\x2c\x00\x20");
+        expected.extend_from_slice(mac);
+        expected.extend_from_slice(b"\x12\x34\x00\x00\x00\x00");
+        assert_eq!(&msg[unsigned_len..], &expected[..]);
+    }
+
+    #[test]
+    fn parse_txt_response() {
+        let mut resp = Vec::new();
+        resp.extend_from_slice(b"\x12\x34\x81\x80\x00\x01\x00\x03\x00\x00\x00\x00");
+        resp.extend_from_slice(b"\x03www\x07example\x03com\x00\x00\x10\x00\x01");
+        // TXT record split over two character strings (compressed name)
+        resp.extend_from_slice(b"\xc0\x0c\x00\x10\x00\x01\x00\x00\x00\x3c\x00\x08");
+        resp.extend_from_slice(b"\x03abc\x03def");
+        // Records of other types are skipped
+        resp.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04");
+        resp.extend_from_slice(b"\x7f\x00\x00\x01");
+        resp.extend_from_slice(b"\xc0\x0c\x00\x10\x00\x01\x00\x00\x00\x3c\x00\x06");
+        resp.extend_from_slice(b"\x05token");
+
+        let values = parse_txt_answers(&resp[..]).unwrap();
+        assert_eq!(values, vec!["abcdef".to_string(), "token".to_string()]);
+
+        resp.truncate(resp.len() - 2);
+        assert!(parse_txt_answers(&resp[..]).is_err());
+    }
+}
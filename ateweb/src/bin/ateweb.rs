@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use std::time::Duration;
 use ate::utils::load_node_list;
 use wasmer_auth::flow::ChainFlow;
@@ -79,13 +80,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let web_key: EncryptKey = load_key(run.web_key_path.clone(), ".read");
 
             conf.log_path = Some(run.log_path);
-            let server = ServerBuilder::new(run.remote, run.auth_url)
+            let mut builder = ServerBuilder::new(run.remote, run.auth_url)
                 .with_web_master_key(web_key)
                 .with_conf(&conf)
                 .ttl(Duration::from_secs(run.ttl))
-                .add_listener(run.listen, run.port, run.port == 443u16)
-                .build()
-                .await?;
+                .add_listener(run.listen, run.port, run.port == 443u16);
+            builder = with_acme(builder, &run.acme)?;
+            if let Some(path) = run.access_log {
+                builder = builder.with_access_log(path, run.access_log_format);
+            }
+            if let Some(port) = run.admin_port {
+                builder = builder.with_admin_listener(run.admin_listen, port);
+            }
+            if let Some(path) = run.admin_token_path {
+                let path = shellexpand::tilde(path.as_str()).to_string();
+                builder = builder.with_admin_token(std::fs::read_to_string(path)?.trim().to_string());
+            }
+            for network in run.proxy_allow {
+                builder = builder.allow_proxy_network(network);
+            }
+            let server = builder.build().await?;
             server.run().await?;
         }
 
@@ -139,14 +153,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             router.set_default_route(root);
 
             conf.log_path = Some(run.log_path);
-            let server = ServerBuilder::new(run.remote, run.auth_url)
+            let mut builder = ServerBuilder::new(run.remote, run.auth_url)
                 .with_web_master_key(web_key)
                 .with_conf(&conf)
                 .ttl(Duration::from_secs(run.ttl))
                 .with_callback(router)
-                .add_listener(run.listen, run.port, run.port == 443u16)
-                .build()
-                .await?;
+                .add_listener(run.listen, run.port, run.port == 443u16);
+            builder = with_acme(builder, &run.acme)?;
+            if let Some(path) = run.access_log {
+                builder = builder.with_access_log(path, run.access_log_format);
+            }
+            if let Some(port) = run.admin_port {
+                builder = builder.with_admin_listener(run.admin_listen, port);
+            }
+            if let Some(path) = run.admin_token_path {
+                let path = shellexpand::tilde(path.as_str()).to_string();
+                builder = builder.with_admin_token(std::fs::read_to_string(path)?.trim().to_string());
+            }
+            for network in run.proxy_allow {
+                builder = builder.allow_proxy_network(network);
+            }
+            let server = builder.build().await?;
             server.run().await?;
         }
     }
@@ -156,6 +183,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn with_acme(
+    mut builder: ServerBuilder,
+    opts: &OptsAcme,
+) -> Result<ServerBuilder, Box<dyn std::error::Error>> {
+    if let Some(url) = opts.acme_directory.as_ref() {
+        builder = builder.acme_directory(url.as_str(), opts.acme_insecure);
+    }
+    for domain in opts.wildcard_domain.iter() {
+        builder = builder.add_wildcard_domain(domain.as_str());
+    }
+    if let (Some(server), Some(zone)) = (opts.rfc2136_server, opts.rfc2136_zone.as_ref()) {
+        let mut provider = Rfc2136Provider::new(server, zone.as_str());
+        if let (Some(name), Some(path)) = (opts.rfc2136_key_name.as_ref(), opts.rfc2136_key_path.as_ref()) {
+            let path = shellexpand::tilde(path).to_string();
+            let secret = std::fs::read_to_string(path)?;
+            let key = TsigKey::from_base64(name.as_str(), opts.rfc2136_key_algorithm, secret.as_str())?;
+            provider = provider.with_tsig(key);
+        }
+        builder = builder.add_dns_provider(Arc::new(provider));
+    }
+    Ok(builder)
+}
+
 fn ctrl_channel() -> tokio::sync::watch::Receiver<bool> {
     let (sender, receiver) = tokio::sync::watch::channel(false);
     ctrlc_async::set_handler(move || {
@@ -5,7 +5,9 @@ use url::Url;
 
 use ate::prelude::*;
 
+use super::acme::DnsProvider;
 use super::conf::*;
+use super::model::{AccessLogFormat, IpCidr, RateLimitConf};
 use super::server::*;
 
 pub struct ServerBuilder {
@@ -55,6 +57,64 @@ impl ServerBuilder {
         self
     }
 
+    pub fn compression_cache_size(mut self, size: usize) -> Self {
+        self.conf.compression_cache_size = size;
+        self
+    }
+
+    pub fn with_access_log(mut self, path: String, format: AccessLogFormat) -> Self {
+        self.conf.access_log_path = Some(path);
+        self.conf.access_log_format = format;
+        self
+    }
+
+    pub fn with_admin_listener(mut self, ip: IpAddr, port: u16) -> Self {
+        self.conf.admin_listen = Some(SocketAddr::new(ip, port));
+        self
+    }
+
+    pub fn with_admin_token(mut self, token: String) -> Self {
+        self.conf.admin_token = Some(token);
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitConf) -> Self {
+        self.conf.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn allow_network(mut self, network: IpCidr) -> Self {
+        self.conf.rate_limit.allow.push(network);
+        self
+    }
+
+    pub fn deny_network(mut self, network: IpCidr) -> Self {
+        self.conf.rate_limit.deny.push(network);
+        self
+    }
+
+    pub fn allow_proxy_network(mut self, network: IpCidr) -> Self {
+        self.conf.proxy_allow.push(network);
+        self
+    }
+
+    pub fn acme_directory(mut self, url: &str, insecure: bool) -> Self {
+        self.conf.acme.directory_url = url.to_string();
+        self.conf.acme.insecure = insecure;
+        self
+    }
+
+    pub fn add_wildcard_domain(mut self, domain: &str) -> Self {
+        let domain = domain.trim_start_matches("*.").trim_end_matches('.');
+        self.conf.acme.wildcard_domains.push(domain.to_lowercase());
+        self
+    }
+
+    pub fn add_dns_provider(mut self, provider: Arc<dyn DnsProvider>) -> Self {
+        self.conf.acme.dns_providers.push(provider);
+        self
+    }
+
     pub fn add_listener(mut self, ip: IpAddr, port: u16, tls: bool) -> Self {
         self.conf.listen.push(ServerListen {
             addr: SocketAddr::new(ip, port),
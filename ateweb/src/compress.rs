@@ -0,0 +1,338 @@
+use async_compression::tokio::write::BrotliEncoder;
+use async_compression::tokio::write::GzipEncoder;
+use async_compression::tokio::write::ZstdEncoder;
+use async_compression::Level;
+use bytes::Bytes;
+use fxhash::FxHashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+
+use ate_files::prelude::*;
+
+/// Files smaller than this are not worth compressing
+pub const MIN_COMPRESS_SIZE: u64 = 256;
+/// Files larger than this are streamed uncompressed rather than being
+/// compressed in memory
+pub const MAX_COMPRESS_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Content encodings that the web server can return to clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentEncoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// Value used in the Content-Encoding header
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Zstd => "zstd",
+            ContentEncoding::Gzip => "gzip",
+        }
+    }
+
+    /// File extension of a precompressed sibling file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Zstd => "zst",
+            ContentEncoding::Gzip => "gz",
+        }
+    }
+
+    /// Returns the encodings accepted by the client (as specified by the
+    /// Accept-Encoding header) ordered from the most preferred to the least,
+    /// where ties go to the encoding with the best compression
+    pub fn accepted(header: &str) -> Vec<ContentEncoding> {
+        let mut all = [
+            ContentEncoding::Brotli,
+            ContentEncoding::Zstd,
+            ContentEncoding::Gzip,
+        ]
+        .iter()
+        .enumerate()
+        .filter_map(|(n, encoding)| {
+            let q = Self::quality(header, encoding.name())?;
+            match q > 0f32 {
+                true => Some((q, n, *encoding)),
+                false => None,
+            }
+        })
+        .collect::<Vec<_>>();
+        all.sort_by(|a, b| {
+            b.0.partial_cmp(&a.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.1.cmp(&b.1))
+        });
+        all.into_iter().map(|a| a.2).collect()
+    }
+
+    fn quality(header: &str, name: &str) -> Option<f32> {
+        let mut wildcard = None;
+        for part in header.split(",") {
+            let mut part = part.split(";");
+            let coding = part.next().unwrap_or_default().trim();
+            let q = part
+                .filter_map(|a| a.trim().strip_prefix("q="))
+                .filter_map(|a| a.trim().parse::<f32>().ok())
+                .next()
+                .unwrap_or(1f32);
+            if coding.eq_ignore_ascii_case(name)
+                || (name == "gzip" && coding.eq_ignore_ascii_case("x-gzip"))
+            {
+                return Some(q);
+            }
+            if coding == "*" {
+                wildcard = Some(q);
+            }
+        }
+        wildcard
+    }
+
+    /// Compresses a buffer of data using this encoding (the encoders only write
+    /// into memory and never wait thus this can be driven by `block_on`)
+    pub async fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let ret = Vec::with_capacity(data.len() / 2);
+        Ok(match self {
+            ContentEncoding::Brotli => {
+                // The default brotli level is far too slow for dynamic compression
+                let mut encoder = BrotliEncoder::with_quality(ret, Level::Precise(5));
+                encoder.write_all(data).await?;
+                encoder.shutdown().await?;
+                encoder.into_inner()
+            }
+            ContentEncoding::Zstd => {
+                let mut encoder = ZstdEncoder::new(ret);
+                encoder.write_all(data).await?;
+                encoder.shutdown().await?;
+                encoder.into_inner()
+            }
+            ContentEncoding::Gzip => {
+                let mut encoder = GzipEncoder::new(ret);
+                encoder.write_all(data).await?;
+                encoder.shutdown().await?;
+                encoder.into_inner()
+            }
+        })
+    }
+}
+
+/// Determines if content of a particular MIME type benefits from compression
+pub fn is_compressible(mime: &str) -> bool {
+    let mime = mime.split(";").next().unwrap_or_default().trim();
+    if mime.starts_with("text/") {
+        return true;
+    }
+    match mime {
+        "application/javascript"
+        | "application/json"
+        | "application/ld+json"
+        | "application/manifest+json"
+        | "application/xml"
+        | "application/xhtml+xml"
+        | "application/rss+xml"
+        | "application/atom+xml"
+        | "application/wasm"
+        | "application/x-sh"
+        | "application/x-csh"
+        | "application/x-httpd-php"
+        | "application/rtf"
+        | "application/vnd.ms-fontobject"
+        | "application/x-tar"
+        | "font/otf"
+        | "font/ttf"
+        | "image/svg+xml"
+        | "image/bmp"
+        | "image/vnd.microsoft.icon" => true,
+        _ => false,
+    }
+}
+
+struct CompressedEntry {
+    version: ContentVersion,
+    data: Bytes,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+struct CompressionCacheState {
+    entries: FxHashMap<(String, ContentEncoding), CompressedEntry>,
+    size: usize,
+}
+
+/// Holds recently compressed responses so that popular files are not
+/// compressed again on every request. Entries are keyed by the version of
+/// the file thus any change to the file invalidates them.
+pub struct CompressionCache {
+    max_size: usize,
+    state: Mutex<CompressionCacheState>,
+}
+
+impl CompressionCache {
+    pub fn new(max_size: usize) -> CompressionCache {
+        CompressionCache {
+            max_size,
+            state: Mutex::new(CompressionCacheState::default()),
+        }
+    }
+
+    pub fn get(
+        &self,
+        key: &str,
+        encoding: ContentEncoding,
+        version: &ContentVersion,
+    ) -> Option<Bytes> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get_mut(&(key.to_string(), encoding))?;
+        if entry.version != *version {
+            return None;
+        }
+        entry.last_used = Instant::now();
+        Some(entry.data.clone())
+    }
+
+    pub fn insert(
+        &self,
+        key: String,
+        encoding: ContentEncoding,
+        version: ContentVersion,
+        data: Bytes,
+    ) {
+        // Very large responses would just flush everything else out of the cache
+        if data.len() > self.max_size / 4 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.size += data.len();
+        let entry = CompressedEntry {
+            version,
+            data,
+            last_used: Instant::now(),
+        };
+        if let Some(old) = state.entries.insert((key, encoding), entry) {
+            state.size -= old.data.len();
+        }
+
+        // Evict the least recently used entries until we are back under the limit
+        while state.size > self.max_size {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, v)| v.last_used)
+                .map(|(k, _)| k.clone());
+            match oldest.and_then(|k| state.entries.remove(&k)) {
+                Some(old) => state.size -= old.data.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ate::prelude::AteHash;
+    use async_compression::tokio::write::BrotliDecoder;
+    use async_compression::tokio::write::GzipDecoder;
+    use async_compression::tokio::write::ZstdDecoder;
+    use ContentEncoding::*;
+
+    #[test]
+    fn accepted_encodings() {
+        let cases: &[(&str, &[ContentEncoding])] = &[
+            ("gzip, deflate, br", &[Brotli, Gzip]),
+            ("gzip, deflate, br, zstd", &[Brotli, Zstd, Gzip]),
+            ("br;q=0.5, gzip", &[Gzip, Brotli]),
+            ("gzip;q=0.8, zstd;q=0.9, br;q=0.1", &[Zstd, Gzip, Brotli]),
+            ("GZIP", &[Gzip]),
+            ("x-gzip", &[Gzip]),
+            ("*", &[Brotli, Zstd, Gzip]),
+            ("*;q=0.5, gzip", &[Gzip, Brotli, Zstd]),
+            ("*, br;q=0", &[Zstd, Gzip]),
+            ("gzip;q=0", &[]),
+            ("identity", &[]),
+            ("deflate", &[]),
+            ("", &[]),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(ContentEncoding::accepted(header), expected.to_vec(), "{}", header);
+        }
+    }
+
+    #[test]
+    fn compressible_types() {
+        let cases: &[(&str, bool)] = &[
+            ("text/html", true),
+            ("text/css; charset=utf-8", true),
+            ("application/javascript", true),
+            ("application/wasm", true),
+            ("image/svg+xml", true),
+            ("image/png", false),
+            ("video/mp4", false),
+            ("application/zip", false),
+            ("application/octet-stream", false),
+        ];
+        for (mime, expected) in cases {
+            assert_eq!(is_compressible(mime), *expected, "{}", mime);
+        }
+    }
+
+    async fn decompress(encoding: ContentEncoding, data: &[u8]) -> Vec<u8> {
+        match encoding {
+            Brotli => {
+                let mut decoder = BrotliDecoder::new(Vec::new());
+                decoder.write_all(data).await.unwrap();
+                decoder.shutdown().await.unwrap();
+                decoder.into_inner()
+            }
+            Zstd => {
+                let mut decoder = ZstdDecoder::new(Vec::new());
+                decoder.write_all(data).await.unwrap();
+                decoder.shutdown().await.unwrap();
+                decoder.into_inner()
+            }
+            Gzip => {
+                let mut decoder = GzipDecoder::new(Vec::new());
+                decoder.write_all(data).await.unwrap();
+                decoder.shutdown().await.unwrap();
+                decoder.into_inner()
+            }
+        }
+    }
+
+    #[test]
+    fn compress_round_trip() {
+        let data = "<p>hello world</p>\n".repeat(1000).into_bytes();
+        for encoding in [Brotli, Zstd, Gzip] {
+            let compressed = futures::executor::block_on(encoding.compress(&data[..])).unwrap();
+            assert!(compressed.len() < data.len() / 10, "{}", encoding.name());
+            let decompressed = futures::executor::block_on(decompress(encoding, &compressed[..]));
+            assert_eq!(decompressed, data, "{}", encoding.name());
+        }
+    }
+
+    #[test]
+    fn cache_is_keyed_by_version() {
+        let cache = CompressionCache::new(1024);
+        let v1 = ContentVersion {
+            hash: AteHash::from_bytes(b"v1"),
+            updated: 1,
+        };
+        let v2 = ContentVersion {
+            hash: AteHash::from_bytes(b"v2"),
+            updated: 2,
+        };
+        cache.insert("a".to_string(), Gzip, v1.clone(), Bytes::from_static(b"one"));
+        assert_eq!(cache.get("a", Gzip, &v1), Some(Bytes::from_static(b"one")));
+        assert_eq!(cache.get("a", Brotli, &v1), None);
+        assert_eq!(cache.get("a", Gzip, &v2), None);
+
+        // Responses larger than a quarter of the cache are never kept
+        cache.insert("b".to_string(), Gzip, v1.clone(), Bytes::from(vec![0u8; 300]));
+        assert_eq!(cache.get("b", Gzip, &v1), None);
+    }
+}
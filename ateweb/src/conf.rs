@@ -1,19 +1,73 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use ate::prelude::*;
 
+use crate::acme::DnsProvider;
+use crate::acme::LETS_ENCRYPT_PRODUCTION_DIRECTORY;
+
+use crate::model::AccessLogFormat;
+use crate::model::IpCidr;
+use crate::model::RateLimitConf;
+
 #[derive(Debug, Clone)]
 pub struct ServerListen {
     pub addr: SocketAddr,
     pub tls: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct AcmeConf {
+    /// URL of the ACME directory that certificates are ordered from (e.g. a
+    /// private certificate authority)
+    pub directory_url: String,
+    /// Accepts an invalid TLS certificate from the ACME server
+    pub insecure: bool,
+    /// Domains that share a single wildcard certificate (covering `*.domain`
+    /// and `domain`) rather than ordering a certificate for every host
+    pub wildcard_domains: Vec<String>,
+    /// Providers that answer DNS-01 challenges for the domains they manage
+    /// (required for wildcard domains)
+    pub dns_providers: Vec<Arc<dyn DnsProvider>>,
+}
+
+impl Default for AcmeConf {
+    fn default() -> Self {
+        AcmeConf {
+            directory_url: LETS_ENCRYPT_PRODUCTION_DIRECTORY.to_string(),
+            insecure: false,
+            wildcard_domains: Vec::new(),
+            dns_providers: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ServerConf {
     pub cfg_ate: ConfAte,
     pub ttl: Duration,
     pub listen: Vec<ServerListen>,
+    /// Maximum number of bytes held in the cache of compressed responses
+    pub compression_cache_size: usize,
+    /// Path to a file that the access logs of all the sites are appended to
+    pub access_log_path: Option<String>,
+    pub access_log_format: AccessLogFormat,
+    /// Address of the admin listener that serves the metrics (on `/metrics`)
+    pub admin_listen: Option<SocketAddr>,
+    /// Bearer token that the admin listener requires before it accepts
+    /// certificate uploads (on `PUT /certs/<host>`), which are refused
+    /// when there is no token
+    pub admin_token: Option<String>,
+    /// Rate limits and allow/deny lists applied to all the sites (a site may
+    /// tighten them further in its web.yaml)
+    pub rate_limit: RateLimitConf,
+    /// Networks that the reverse proxy routes of the sites may connect to even
+    /// though they are loopback, link-local or private (which are otherwise
+    /// refused so that a site can not reach into the hosting network)
+    pub proxy_allow: Vec<IpCidr>,
+    /// Settings used when ordering certificates
+    pub acme: AcmeConf,
 }
 
 impl Default for ServerConf {
@@ -22,6 +76,14 @@ impl Default for ServerConf {
             cfg_ate: ConfAte::default(),
             ttl: Duration::from_secs(60),
             listen: Vec::new(),
+            compression_cache_size: 64 * 1024 * 1024,
+            access_log_path: None,
+            access_log_format: AccessLogFormat::default(),
+            admin_listen: None,
+            admin_token: None,
+            rate_limit: RateLimitConf::default(),
+            proxy_allow: Vec::new(),
+            acme: AcmeConf::default(),
         }
     }
 }
@@ -17,6 +17,7 @@ error_chain! {
         FileSystemError(FileSystemError, FileSystemErrorKind);
     }
     foreign_links {
+        IO(std::io::Error);
         HeaderStrError(http::header::ToStrError);
         HeaderValueError(http::header::InvalidHeaderValue);
         TokioTungsteniteError(tokio_tungstenite::tungstenite::error::ProtocolError);
@@ -75,6 +76,7 @@ error_chain! {
         SerializationError(SerializationError, SerializationErrorKind);
         CommitError(CommitError, CommitErrorKind);
         AcmeError(AcmeError, AcmeErrorKind);
+        DnsError(DnsError, DnsErrorKind);
         FileSystemError(FileSystemError, FileSystemErrorKind);
     }
     errors {
@@ -102,6 +104,10 @@ error_chain! {
             description("authorization failed too many times"),
             display("authorization for {0} failed too many times", domain)
         }
+        NoDnsProvider(domain: String) {
+            description("no dns provider for the domain"),
+            display("no dns provider is able to update the records of {0}", domain)
+        }
     }
 }
 
@@ -158,6 +164,42 @@ error_chain! {
             description("no tls alpn 01 challenge"),
             display("no tls alpn 01 challenge")
         }
+        NoDns01Challenge {
+            description("no dns 01 challenge"),
+            display("no dns 01 challenge")
+        }
+    }
+}
+
+error_chain! {
+    types {
+        DnsError, DnsErrorKind, DnsResultExt, DnsResult;
+    }
+    foreign_links {
+        IO(std::io::Error);
+        Crypto(ring::error::Unspecified);
+    }
+    errors {
+        BadName(name: String) {
+            description("invalid domain name"),
+            display("invalid domain name ({})", name)
+        }
+        BadKey(err: String) {
+            description("invalid dns key"),
+            display("invalid dns key - {}", err)
+        }
+        BadResponse(err: String) {
+            description("dns server returned a bad response"),
+            display("dns server returned a bad response - {}", err)
+        }
+        ResponseCode(code: u8) {
+            description("dns server returned an error"),
+            display("dns server returned an error (rcode={})", code)
+        }
+        Timeout {
+            description("timeout while waiting for the dns server"),
+            display("timeout while waiting for the dns server")
+        }
     }
 }
 
@@ -15,3 +15,166 @@ pub fn redirect_body(target: &str) -> String {
         target, target, target
     )
 }
+
+/// Formats a timestamp (milliseconds since the epoch) as an HTTP date
+pub fn http_date(ms: u64) -> String {
+    use chrono::TimeZone;
+    chrono::Utc
+        .timestamp_millis(ms as i64)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Parses an HTTP date into the number of seconds since the epoch
+pub fn parse_http_date(val: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc2822(val.trim())
+        .ok()
+        .map(|a| a.timestamp() as u64)
+}
+
+/// Returns the value of a header as a string (if it exists and is valid)
+pub fn header_str<'a>(
+    headers: Option<&'a hyper::HeaderMap>,
+    name: hyper::header::HeaderName,
+) -> Option<&'a str> {
+    headers
+        .and_then(|a| a.get(name))
+        .and_then(|a| a.to_str().ok())
+}
+
+/// Checks if an entity tag is listed in an If-None-Match header (which uses
+/// the weak comparison function)
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(",")
+        .map(|a| a.trim())
+        .any(|a| a == "*" || a.strip_prefix("W/").unwrap_or(a) == etag)
+}
+
+/// Range of bytes requested by a client (the end is inclusive)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// Parses the Range header of a request. Multiple ranges are not supported thus
+/// the full contents are returned instead (which the specification permits).
+pub fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(a) if a.contains(",") == false => a,
+        _ => return RangeRequest::Full,
+    };
+    let (start, end) = match spec.split_once("-") {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => return RangeRequest::Full,
+    };
+
+    // Suffix ranges select the last N bytes of the file
+    if start.len() <= 0 {
+        return match end.parse::<u64>() {
+            Ok(len) if len > 0 && size > 0 => RangeRequest::Partial(ByteRange {
+                start: size - len.min(size),
+                end: size - 1,
+            }),
+            Ok(_) => RangeRequest::Unsatisfiable,
+            Err(_) => RangeRequest::Full,
+        };
+    }
+
+    let start = match start.parse::<u64>() {
+        Ok(a) => a,
+        Err(_) => return RangeRequest::Full,
+    };
+    if start >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+    let end = match end.len() {
+        0 => size - 1,
+        _ => match end.parse::<u64>() {
+            Ok(a) => a.min(size - 1),
+            Err(_) => return RangeRequest::Full,
+        },
+    };
+    if end < start {
+        return RangeRequest::Full;
+    }
+    RangeRequest::Partial(ByteRange { start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_dates() {
+        assert_eq!(http_date(784111777000), "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        let cases: &[(&str, Option<u64>)] = &[
+            ("Sun, 06 Nov 1994 08:49:37 GMT", Some(784111777)),
+            ("  Sun, 06 Nov 1994 08:49:37 GMT  ", Some(784111777)),
+            ("Sun, 06 Nov 1994 09:49:37 +0100", Some(784111777)),
+            ("Sunday, 06-Nov-94 08:49:37 GMT", None),
+            ("yesterday", None),
+            ("", None),
+        ];
+        for (val, expected) in cases {
+            assert_eq!(parse_http_date(val), *expected, "{}", val);
+        }
+        assert_eq!(parse_http_date(http_date(1234000).as_str()), Some(1234));
+    }
+
+    #[test]
+    fn etags() {
+        let cases: &[(&str, bool)] = &[
+            ("\"abc\"", true),
+            ("W/\"abc\"", true),
+            ("*", true),
+            ("\"xyz\", \"abc\"", true),
+            ("\"xyz\",W/\"abc\"", true),
+            ("\"xyz\"", false),
+            ("abc", false),
+            ("\"ABC\"", false),
+            ("", false),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(etag_matches(header, "\"abc\""), *expected, "{}", header);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        use RangeRequest::*;
+        let part = |start, end| Partial(ByteRange { start, end });
+
+        let cases: &[(&str, u64, RangeRequest)] = &[
+            ("bytes=0-99", 1000, part(0, 99)),
+            ("bytes=500-", 1000, part(500, 999)),
+            ("bytes=500-5000", 1000, part(500, 999)),
+            (" bytes=10-10 ", 1000, part(10, 10)),
+            ("bytes=-100", 1000, part(900, 999)),
+            ("bytes=-5000", 1000, part(0, 999)),
+            ("bytes=-0", 1000, Unsatisfiable),
+            ("bytes=-10", 0, Unsatisfiable),
+            ("bytes=1000-", 1000, Unsatisfiable),
+            ("bytes=0-", 0, Unsatisfiable),
+            ("bytes=99-10", 1000, Full),
+            ("bytes=0-10,20-30", 1000, Full),
+            ("bytes=a-10", 1000, Full),
+            ("bytes=0-b", 1000, Full),
+            ("bytes=10", 1000, Full),
+            ("items=0-10", 1000, Full),
+            ("", 1000, Full),
+        ];
+        for (header, size, expected) in cases {
+            assert_eq!(parse_range(header, *size), *expected, "{} of {}", header, size);
+        }
+    }
+}
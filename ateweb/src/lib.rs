@@ -1,10 +1,16 @@
+pub mod access_log;
 pub mod builder;
+pub mod compress;
 pub mod conf;
 pub mod error;
 pub mod helper;
+pub mod metrics;
 pub mod model;
 pub mod opt;
+pub mod proxy;
+pub mod rate_limit;
 pub mod server;
+pub mod webdav;
 
 pub mod acceptor;
 pub mod acme;
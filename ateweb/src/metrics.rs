@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the latency histogram buckets
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Limits the number of hosts that are tracked so that requests with random
+/// Host headers can not exhaust the memory of the server
+const MAX_HOSTS: usize = 10000;
+const OTHER_HOST: &'static str = "_other";
+
+#[derive(Debug, Default, Clone)]
+pub struct HostMetrics {
+    pub requests: u64,
+    pub bytes: u64,
+    /// Responses counted by their status class (1xx to 5xx)
+    pub status: [u64; 5],
+    /// Non-cumulative counts of the latency buckets (the last entry holds
+    /// requests slower than all the buckets)
+    pub latency: [u64; LATENCY_BUCKETS.len() + 1],
+    pub latency_sum: f64,
+}
+
+/// Traffic counters for each of the hosts served by the web server
+#[derive(Debug, Default)]
+pub struct Metrics {
+    hosts: Mutex<BTreeMap<String, HostMetrics>>,
+}
+
+impl Metrics {
+    pub fn record(&self, host: &str, status: u16, bytes: u64, elapsed: Duration) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let host = match hosts.contains_key(host) || hosts.len() < MAX_HOSTS {
+            true => host,
+            false => OTHER_HOST,
+        };
+        let metrics = hosts.entry(host.to_string()).or_default();
+
+        metrics.requests += 1;
+        metrics.bytes += bytes;
+        let class = (status / 100) as usize;
+        if class >= 1 && class <= 5 {
+            metrics.status[class - 1] += 1;
+        }
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|a| secs <= *a)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        metrics.latency[bucket] += 1;
+        metrics.latency_sum += secs;
+    }
+
+    pub fn host(&self, host: &str) -> Option<HostMetrics> {
+        self.hosts.lock().unwrap().get(host).map(|a| a.clone())
+    }
+
+    /// Renders all the counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let hosts = self.hosts.lock().unwrap();
+        let mut ret = String::new();
+
+        ret.push_str("# HELP ateweb_requests_total Number of HTTP requests processed.\n");
+        ret.push_str("# TYPE ateweb_requests_total counter\n");
+        for (host, metrics) in hosts.iter() {
+            let _ = writeln!(
+                ret,
+                "ateweb_requests_total{{host=\"{}\"}} {}",
+                escape_label(host),
+                metrics.requests
+            );
+        }
+
+        ret.push_str(
+            "# HELP ateweb_response_bytes_total Number of bytes sent in HTTP responses.\n",
+        );
+        ret.push_str("# TYPE ateweb_response_bytes_total counter\n");
+        for (host, metrics) in hosts.iter() {
+            let _ = writeln!(
+                ret,
+                "ateweb_response_bytes_total{{host=\"{}\"}} {}",
+                escape_label(host),
+                metrics.bytes
+            );
+        }
+
+        ret.push_str("# HELP ateweb_responses_total Number of HTTP responses by status class.\n");
+        ret.push_str("# TYPE ateweb_responses_total counter\n");
+        for (host, metrics) in hosts.iter() {
+            for (n, count) in metrics.status.iter().enumerate() {
+                let _ = writeln!(
+                    ret,
+                    "ateweb_responses_total{{host=\"{}\",class=\"{}xx\"}} {}",
+                    escape_label(host),
+                    n + 1,
+                    count
+                );
+            }
+        }
+
+        ret.push_str(
+            "# HELP ateweb_request_duration_seconds Time taken to respond to HTTP requests.\n",
+        );
+        ret.push_str("# TYPE ateweb_request_duration_seconds histogram\n");
+        for (host, metrics) in hosts.iter() {
+            let host = escape_label(host);
+            let mut cumulative = 0u64;
+            for (n, le) in LATENCY_BUCKETS.iter().enumerate() {
+                cumulative += metrics.latency[n];
+                let _ = writeln!(
+                    ret,
+                    "ateweb_request_duration_seconds_bucket{{host=\"{}\",le=\"{}\"}} {}",
+                    host, le, cumulative
+                );
+            }
+            cumulative += metrics.latency[LATENCY_BUCKETS.len()];
+            let _ = writeln!(
+                ret,
+                "ateweb_request_duration_seconds_bucket{{host=\"{}\",le=\"+Inf\"}} {}",
+                host, cumulative
+            );
+            let _ = writeln!(
+                ret,
+                "ateweb_request_duration_seconds_sum{{host=\"{}\"}} {}",
+                host, metrics.latency_sum
+            );
+            let _ = writeln!(
+                ret,
+                "ateweb_request_duration_seconds_count{{host=\"{}\"}} {}",
+                host, cumulative
+            );
+        }
+        ret
+    }
+}
+
+fn escape_label(val: &str) -> String {
+    val.replace("\\", "\\\\")
+        .replace("\"", "\\\"")
+        .replace("\n", "\\n")
+}
@@ -0,0 +1,33 @@
+use serde::*;
+use std::str::FromStr;
+
+/// Format that access logs are written in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// NCSA Common Log Format
+    Common,
+    /// NCSA Combined Log Format (common plus the referer and user agent)
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for AccessLogFormat {
+    fn default() -> Self {
+        AccessLogFormat::Combined
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "common" => Ok(AccessLogFormat::Common),
+            "combined" => Ok(AccessLogFormat::Combined),
+            "json" => Ok(AccessLogFormat::Json),
+            _ => Err("valid values are 'common', 'combined' and 'json'"),
+        }
+    }
+}
@@ -1,10 +1,21 @@
+mod access_log;
+mod proxy_route;
+mod rate_limit;
 mod web_conf;
 
+pub use access_log::*;
+pub use proxy_route::*;
+pub use rate_limit::*;
 pub use web_conf::*;
 
 pub const WEB_CONF_FILES: &'static str = ".conf/";
 pub const WEB_CONF_FILES_CONF: &'static str = ".conf/web.yaml";
+pub const WEB_CONF_FILES_ACCESS_LOGS: &'static str = ".conf/logs/";
 pub const WEB_CONF_FILES_WEB_CERT: &'static str = ".conf/cert.pem";
 pub const WEB_CONF_FILES_WEB_KEY: &'static str = ".conf/key.pem";
+pub const WEB_CONF_FILES_MANUAL_CERT: &'static str = ".conf/manual/cert.pem";
+pub const WEB_CONF_FILES_MANUAL_KEY: &'static str = ".conf/manual/key.pem";
+pub const WEB_CONF_FILES_WILDCARD_CERT: &'static str = ".conf/wildcard/cert.pem";
+pub const WEB_CONF_FILES_WILDCARD_KEY: &'static str = ".conf/wildcard/key.pem";
 pub const WEB_CONF_FILES_ALPN_CERT: &'static str = ".conf/alpn/cert.pem";
 pub const WEB_CONF_FILES_ALPN_KEY: &'static str = ".conf/alpn/key.pem";
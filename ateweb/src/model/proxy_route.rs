@@ -0,0 +1,117 @@
+use fxhash::FxHashMap;
+use serde::*;
+
+/// Routes all the requests under a path prefix to one or more upstream
+/// servers (web socket upgrades are also passed through)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyRoute {
+    /// Path prefix of the requests that are proxied (e.g. `/api/`)
+    pub prefix: String,
+    /// Base URLs of the upstream servers (e.g. `http://10.0.0.1:8080`) which
+    /// may include web servers exported by wasmer instances
+    pub upstreams: Vec<String>,
+    /// Removes the prefix from the path before it is passed to the upstream
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// Passes the original Host header to the upstream rather than the
+    /// authority of the upstream URL
+    #[serde(default)]
+    pub preserve_host: bool,
+    /// Method used to pick which upstream receives each request
+    #[serde(default)]
+    pub balance: ProxyBalance,
+    /// Maximum amount of time to wait for the upstream to respond
+    #[serde(default = "ProxyRoute::default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Changes made to the headers of requests sent to the upstream
+    #[serde(default)]
+    pub request_headers: HeaderRewrite,
+    /// Changes made to the headers of responses returned by the upstream
+    #[serde(default)]
+    pub response_headers: HeaderRewrite,
+}
+
+impl ProxyRoute {
+    fn default_timeout_ms() -> u64 {
+        30000
+    }
+
+    /// Prefix without any trailing slash (so `/api` and `/api/` are the same)
+    pub fn base(&self) -> &str {
+        self.prefix.trim_end_matches('/')
+    }
+
+    /// Prefixes only match whole path segments thus `/api` matches `/api`
+    /// and `/api/users` but not `/apix`
+    pub fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.base()) {
+            Some(rest) => rest.len() <= 0 || rest.starts_with("/"),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyBalance {
+    /// Each upstream is used in turn
+    RoundRobin,
+    /// Upstreams are picked at random
+    Random,
+    /// Requests from the same client IP address always use the same upstream
+    IpHash,
+}
+
+impl Default for ProxyBalance {
+    fn default() -> Self {
+        ProxyBalance::RoundRobin
+    }
+}
+
+/// List of modifications to the headers of a request or response
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HeaderRewrite {
+    /// Headers that are removed
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// Headers that are set (replacing any existing values)
+    #[serde(default)]
+    pub set: FxHashMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(prefix: &str) -> ProxyRoute {
+        ProxyRoute {
+            prefix: prefix.to_string(),
+            upstreams: Vec::new(),
+            strip_prefix: false,
+            preserve_host: false,
+            balance: ProxyBalance::default(),
+            timeout_ms: ProxyRoute::default_timeout_ms(),
+            request_headers: HeaderRewrite::default(),
+            response_headers: HeaderRewrite::default(),
+        }
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("/api", "/api", true),
+            ("/api", "/api/", true),
+            ("/api", "/api/users", true),
+            ("/api", "/apix", false),
+            ("/api", "/ap", false),
+            ("/api/", "/api", true),
+            ("/api/", "/api/users", true),
+            ("/api/", "/apix/users", false),
+            ("/", "/", true),
+            ("/", "/anything", true),
+        ];
+        for (prefix, path, expected) in cases {
+            assert_eq!(route(prefix).matches(path), *expected, "{} ~ {}", prefix, path);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use serde::*;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Token bucket that refills at a steady rate up to a maximum burst
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Number of tokens added to the bucket every second (connections,
+    /// requests or bytes depending on what is being limited)
+    pub rate: f64,
+    /// Maximum number of tokens the bucket holds (defaults to one second
+    /// worth of tokens)
+    #[serde(default)]
+    pub burst: Option<f64>,
+}
+
+impl RateLimit {
+    pub fn new(rate: f64, burst: f64) -> RateLimit {
+        RateLimit {
+            rate,
+            burst: Some(burst),
+        }
+    }
+
+    pub fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.rate).max(1.0)
+    }
+}
+
+/// Limits that are applied to a single client or host
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RateLimits {
+    /// New connections per second
+    #[serde(default)]
+    pub connections: Option<RateLimit>,
+    /// Requests per second
+    #[serde(default)]
+    pub requests: Option<RateLimit>,
+    /// Bytes per second sent in responses
+    #[serde(default)]
+    pub bandwidth: Option<RateLimit>,
+}
+
+/// Rate limiting and abuse protection settings
+///
+/// When set in the `web.yaml` of a site these are enforced on top of the
+/// global limits for requests made to that site (a site can only tighten
+/// the limits and its allow list only exempts clients from its own limits),
+/// however connection limits are always taken from the global settings as
+/// they are applied before the host of a request is known.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RateLimitConf {
+    /// Limits applied to each client IP address
+    #[serde(default)]
+    pub per_ip: RateLimits,
+    /// Limits applied to each host (connection limits for a host are only
+    /// enforced on TLS connections as they rely on the SNI)
+    #[serde(default)]
+    pub per_host: RateLimits,
+    /// Networks (e.g. `10.0.0.0/8`) that are exempt from rate limiting
+    #[serde(default)]
+    pub allow: Vec<IpCidr>,
+    /// Networks that are refused service
+    #[serde(default)]
+    pub deny: Vec<IpCidr>,
+}
+
+impl RateLimitConf {
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        self.allow.iter().any(|a| a.contains(ip))
+    }
+
+    pub fn is_denied(&self, ip: IpAddr) -> bool {
+        self.deny.iter().any(|a| a.contains(ip))
+    }
+}
+
+/// Range of IP addresses written in CIDR notation (a single address without
+/// a prefix length is also accepted)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl IpCidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients connecting to a dual stack listener appear as mapped
+        // IPv6 addresses
+        let ip = match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4() {
+                Some(v4) if v6.segments()[5] == 0xffff => IpAddr::V4(v4),
+                _ => ip,
+            },
+            ip => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let mut bits = prefix as usize;
+    for (a, b) in net.iter().zip(ip.iter()) {
+        if bits <= 0 {
+            break;
+        }
+        let mask = match bits >= 8 {
+            true => 0xffu8,
+            false => 0xffu8 << (8 - bits),
+        };
+        if (a & mask) != (b & mask) {
+            return false;
+        }
+        bits = bits.saturating_sub(8);
+    }
+    true
+}
+
+impl FromStr for IpCidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|err| format!("{} - {}", s, err))?;
+        let max = match addr {
+            IpAddr::V4(_) => 32u8,
+            IpAddr::V6(_) => 128u8,
+        };
+        let prefix = match prefix {
+            Some(prefix) => match u8::from_str(prefix) {
+                Ok(a) if a <= max => a,
+                _ => return Err(format!("{} - invalid prefix length", s)),
+            },
+            None => max,
+        };
+        Ok(IpCidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Serialize for IpCidr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for IpCidr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        IpCidr::from_str(s.as_str()).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cidrs() {
+        let cases: &[(&str, Option<&str>)] = &[
+            ("10.0.0.0/8", Some("10.0.0.0/8")),
+            (" 192.168.1.1 ", Some("192.168.1.1/32")),
+            ("0.0.0.0/0", Some("0.0.0.0/0")),
+            ("2001:db8::/32", Some("2001:db8::/32")),
+            ("::1", Some("::1/128")),
+            ("10.0.0.0/33", None),
+            ("2001:db8::/129", None),
+            ("10.0.0.0/", None),
+            ("10.0.0.0/x", None),
+            ("10.0.0", None),
+            ("", None),
+        ];
+        for (val, expected) in cases {
+            let parsed = IpCidr::from_str(val).ok().map(|a| a.to_string());
+            assert_eq!(parsed.as_deref(), *expected, "{}", val);
+        }
+    }
+
+    #[test]
+    fn match_cidrs() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("10.0.0.0/8", "10.255.1.2", true),
+            ("10.0.0.0/8", "11.0.0.1", false),
+            ("192.168.4.0/22", "192.168.7.255", true),
+            ("192.168.4.0/22", "192.168.8.0", false),
+            ("192.168.1.1", "192.168.1.1", true),
+            ("192.168.1.1", "192.168.1.2", false),
+            ("0.0.0.0/0", "203.0.113.9", true),
+            ("10.0.0.0/8", "::ffff:10.1.2.3", true),
+            ("10.0.0.0/8", "::a01:203", false),
+            ("2001:db8::/32", "2001:db8:1::1", true),
+            ("2001:db8::/32", "2001:db9::1", false),
+            ("2001:db8::/33", "2001:db8:8000::1", false),
+            ("::/0", "10.0.0.1", false),
+        ];
+        for (net, ip, expected) in cases {
+            let net = IpCidr::from_str(net).unwrap();
+            let ip = IpAddr::from_str(ip).unwrap();
+            assert_eq!(net.contains(ip), *expected, "{} ~ {}", net, ip);
+        }
+    }
+}
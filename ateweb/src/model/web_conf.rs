@@ -1,6 +1,10 @@
 use fxhash::FxHashMap;
 use serde::*;
 
+use super::AccessLogFormat;
+use super::ProxyRoute;
+use super::RateLimitConf;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WebConf {
     /// Forces the host to be redirected to a new URL
@@ -24,6 +28,84 @@ pub struct WebConf {
     /// List of the domains that this domain will reverse proxy for cors
     #[serde(default)]
     pub cors_proxy: Vec<String>,
+    /// Rules that determine the Cache-Control header returned for files (the
+    /// first rule that matches the path is used)
+    #[serde(default)]
+    pub cache_control: Vec<CacheControlRule>,
+    /// Compresses text based files when the client supports it (precompressed
+    /// `.br`, `.zst` and `.gz` siblings of a file are served in preference)
+    #[serde(default = "WebConf::default_compress")]
+    pub compress: bool,
+    /// Reverse proxy routes that pass requests on to upstream servers (the
+    /// first route whose prefix matches the path is used)
+    #[serde(default)]
+    pub proxy: Vec<ProxyRoute>,
+    /// Path prefix (e.g. `/.dav`) under which a WebDAV endpoint for the files
+    /// of this site is exposed to users with rights to the site's group
+    #[serde(default)]
+    pub webdav: Option<String>,
+    /// Stores the access logs of this site in its files chain (under
+    /// `.conf/logs/`) using a particular format
+    #[serde(default)]
+    pub access_log: Option<AccessLogFormat>,
+    /// Rate limits and allow/deny lists that are enforced on top of the global
+    /// ones for requests made to this site
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConf>,
+}
+
+/// Cache-Control header value applied to all the paths matching a pattern
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheControlRule {
+    /// Pattern that is matched against the path, where `*` matches any sequence of
+    /// characters (e.g. `*.js` or `/assets/*`)
+    pub pattern: String,
+    /// Value of the Cache-Control header (e.g. `public, max-age=31536000, immutable`)
+    pub value: String,
+}
+
+impl CacheControlRule {
+    pub fn matches(&self, path: &str) -> bool {
+        wildcard_match(self.pattern.as_bytes(), path.as_bytes())
+    }
+}
+
+fn wildcard_match(pattern: &[u8], val: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => val.is_empty(),
+        Some((b'*', rest)) => (0..=val.len()).any(|n| wildcard_match(rest, &val[n..])),
+        Some((c, rest)) => val.first() == Some(c) && wildcard_match(rest, &val[1..]),
+    }
+}
+
+impl WebConf {
+    fn default_compress() -> bool {
+        true
+    }
+
+    /// Returns the prefix of the WebDAV endpoint if the path falls under it
+    pub fn webdav_prefix(&self, path: &str) -> Option<&str> {
+        let prefix = self.webdav.as_ref()?.as_str();
+        let rest = path.strip_prefix(prefix.trim_end_matches('/'))?;
+        match rest.len() == 0 || rest.starts_with("/") {
+            true => Some(prefix),
+            false => None,
+        }
+    }
+
+    /// Returns the reverse proxy route for a particular path (if any)
+    pub fn proxy_route(&self, path: &str) -> Option<&ProxyRoute> {
+        self.proxy.iter().filter(|a| a.matches(path)).next()
+    }
+
+    /// Returns the Cache-Control header value for a particular path (if any)
+    pub fn cache_control(&self, path: &str) -> Option<&str> {
+        self.cache_control
+            .iter()
+            .filter(|a| a.matches(path))
+            .map(|a| a.value.as_str())
+            .next()
+    }
 }
 
 impl Default for WebConf {
@@ -36,6 +118,45 @@ impl Default for WebConf {
             default_page: None,
             status_pages: FxHashMap::default(),
             cors_proxy: Vec::new(),
+            cache_control: Vec::new(),
+            compress: true,
+            proxy: Vec::new(),
+            webdav: None,
+            access_log: None,
+            rate_limit: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*.css", "/assets/site.css", true),
+            ("*.css", "/assets/site.css.map", false),
+            ("/assets/*", "/assets/", true),
+            ("/assets/*", "/assets/img/logo.png", true),
+            ("/assets/*", "/other/logo.png", false),
+            ("/*/logo.*", "/img/logo.png", true),
+            ("/*/logo.*", "/img/icon.png", false),
+            ("*", "", true),
+            ("**", "/anything", true),
+            ("/index.html", "/index.html", true),
+            ("/index.html", "/index.htm", false),
+            ("", "", true),
+            ("", "/", false),
+        ];
+        for (pattern, val, expected) in cases {
+            assert_eq!(
+                wildcard_match(pattern.as_bytes(), val.as_bytes()),
+                *expected,
+                "{} ~ {}",
+                pattern,
+                val
+            );
         }
     }
 }
@@ -0,0 +1,36 @@
+use std::net::SocketAddr;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+
+use clap::Parser;
+
+use crate::acme::TsigAlgorithm;
+
+/// Settings that control how certificates are ordered
+#[derive(Parser)]
+pub struct OptsAcme {
+    /// URL of the ACME directory that certificates are ordered from (defaults to Let's Encrypt)
+    #[clap(long)]
+    pub acme_directory: Option<String>,
+    /// Accepts an invalid TLS certificate from the ACME server (e.g. a private CA)
+    #[clap(long)]
+    pub acme_insecure: bool,
+    /// Domain whose hosts share a single wildcard certificate (requires a DNS provider)
+    #[clap(long, multiple_occurrences = true)]
+    pub wildcard_domain: Vec<String>,
+    /// Address of the DNS server that receives dynamic updates (RFC 2136) for DNS-01 challenges
+    #[clap(long)]
+    pub rfc2136_server: Option<SocketAddr>,
+    /// Zone that the DNS server will update records within
+    #[clap(long)]
+    pub rfc2136_zone: Option<String>,
+    /// Name of the TSIG key that signs the dynamic updates
+    #[clap(long)]
+    pub rfc2136_key_name: Option<String>,
+    /// Algorithm of the TSIG key (hmac-sha256 or hmac-sha512)
+    #[clap(long, default_value = "hmac-sha256")]
+    pub rfc2136_key_algorithm: TsigAlgorithm,
+    /// Path to a file that holds the base64 encoded secret of the TSIG key
+    #[clap(long)]
+    pub rfc2136_key_path: Option<String>,
+}
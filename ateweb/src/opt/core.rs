@@ -5,7 +5,10 @@ use url::Url;
 
 use clap::Parser;
 
+use super::OptsAcme;
 use super::OptsAuth;
+use crate::model::AccessLogFormat;
+use crate::model::IpCidr;
 
 #[derive(Parser)]
 #[clap(version = "1.6", author = "John S. <johnathan.sharratt@gmail.com>")]
@@ -68,6 +71,28 @@ pub struct OptsWeb {
     /// Location where all the websites will be cached
     #[clap(long, default_value = "/tmp/www")]
     pub log_path: String,
+    /// Path to a local file where the access logs of all the sites will be appended
+    #[clap(long)]
+    pub access_log: Option<String>,
+    /// Format of the access log lines (common, combined or json)
+    #[clap(long, default_value = "combined")]
+    pub access_log_format: AccessLogFormat,
+    /// IP address that the admin listener (which exposes the metrics) will listen on
+    #[clap(long, default_value = "127.0.0.1")]
+    pub admin_listen: IpAddr,
+    /// Port that the admin listener will listen on (it is disabled when omitted)
+    #[clap(long)]
+    pub admin_port: Option<u16>,
+    /// Path to a file holding the bearer token that the admin listener requires
+    /// for certificate uploads (which are disabled when omitted)
+    #[clap(long)]
+    pub admin_token_path: Option<String>,
+    /// Network (e.g. 10.0.0.0/8) that the reverse proxy routes of the sites may
+    /// connect to even though it is loopback, link-local or private
+    #[clap(long)]
+    pub proxy_allow: Vec<IpCidr>,
+    #[clap(flatten)]
+    pub acme: OptsAcme,
 }
 
 /// Runs a web server that will serve content from a Wasmer file system
@@ -120,6 +145,28 @@ pub struct OptsAll {
     /// Ensures that this authentication server runs as a specific node_id
     #[clap(short, long)]
     pub node_id: Option<u32>,
+    /// Path to a local file where the access logs of all the sites will be appended
+    #[clap(long)]
+    pub access_log: Option<String>,
+    /// Format of the access log lines (common, combined or json)
+    #[clap(long, default_value = "combined")]
+    pub access_log_format: AccessLogFormat,
+    /// IP address that the admin listener (which exposes the metrics) will listen on
+    #[clap(long, default_value = "127.0.0.1")]
+    pub admin_listen: IpAddr,
+    /// Port that the admin listener will listen on (it is disabled when omitted)
+    #[clap(long)]
+    pub admin_port: Option<u16>,
+    /// Path to a file holding the bearer token that the admin listener requires
+    /// for certificate uploads (which are disabled when omitted)
+    #[clap(long)]
+    pub admin_token_path: Option<String>,
+    /// Network (e.g. 10.0.0.0/8) that the reverse proxy routes of the sites may
+    /// connect to even though it is loopback, link-local or private
+    #[clap(long)]
+    pub proxy_allow: Vec<IpCidr>,
+    #[clap(flatten)]
+    pub acme: OptsAcme,
 }
 
 #[derive(Parser)]
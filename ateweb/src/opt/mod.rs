@@ -1,9 +1,11 @@
+mod acme;
 mod all;
 mod core;
 mod web;
 mod auth;
 
 pub use self::core::*;
+pub use acme::*;
 pub use all::*;
 pub use web::*;
 pub use auth::*;
\ No newline at end of file
@@ -0,0 +1,424 @@
+use fxhash::FxHashMap;
+use fxhash::FxHasher;
+use futures::Future;
+use hyper::client::connect::dns::Name;
+use hyper::client::HttpConnector;
+use hyper::header::HeaderName;
+use hyper::header::HeaderValue;
+use hyper::header::{CONNECTION, HOST, UPGRADE};
+use hyper::Body;
+use hyper::Client;
+use hyper::HeaderMap;
+use hyper::Request;
+use hyper::Response;
+use hyper::service::Service;
+use hyper::StatusCode;
+use hyper::Uri;
+use hyper_tls::HttpsConnector;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+use ate::prelude::*;
+
+use crate::model::*;
+
+/// Headers that only apply to a single connection and thus are never
+/// passed between the client and the upstream server
+const HOP_BY_HOP_HEADERS: [&'static str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Decides which addresses the upstreams of the proxy routes may connect to
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamGuard {
+    allow: Arc<Vec<IpCidr>>,
+}
+
+impl UpstreamGuard {
+    pub fn new(allow: Vec<IpCidr>) -> UpstreamGuard {
+        UpstreamGuard {
+            allow: Arc::new(allow),
+        }
+    }
+
+    /// Sites may not reach into the hosting network (loopback, link-local
+    /// and private addresses) unless the operator allowed that network
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        is_public(ip) || self.allow.iter().any(|a| a.contains(ip))
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            let shared = octets[0] == 100 && (octets[1] & 0xc0) == 64;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => {
+            if let (Some(v4), 0xffff) = (ip.to_ipv4(), ip.segments()[5]) {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            let unique_local = (first & 0xfe00) == 0xfc00;
+            let link_local = (first & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local)
+        }
+    }
+}
+
+/// Resolves the host names of upstreams and drops any addresses that the
+/// guard does not permit. The check happens on every connect (rather than
+/// when a route is loaded) so DNS can not rebind an upstream onto the
+/// hosting network.
+#[derive(Clone)]
+struct UpstreamResolver {
+    guard: UpstreamGuard,
+}
+
+impl Service<Name> for UpstreamResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let guard = self.guard.clone();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| guard.permits(a.ip()))
+                .collect::<Vec<_>>();
+            if addrs.len() <= 0 {
+                return Err(std::io::Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("upstream ({}) only resolves to restricted addresses", name),
+                ));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+/// Passes requests that match the proxy routes of a site on to their
+/// upstream servers, streaming the bodies in both directions
+pub struct ReverseProxy {
+    client: Client<HttpsConnector<HttpConnector<UpstreamResolver>>>,
+    guard: UpstreamGuard,
+    round_robin: Mutex<FxHashMap<String, usize>>,
+}
+
+impl ReverseProxy {
+    /// Creates a proxy whose upstreams may only be public addresses or
+    /// addresses within the allowed networks
+    pub fn new(allow: Vec<IpCidr>) -> ReverseProxy {
+        let guard = UpstreamGuard::new(allow);
+        let mut http = HttpConnector::new_with_resolver(UpstreamResolver {
+            guard: guard.clone(),
+        });
+        http.enforce_http(false);
+        ReverseProxy {
+            client: Client::builder().build(HttpsConnector::new_with_connector(http)),
+            guard,
+            round_robin: Mutex::new(FxHashMap::default()),
+        }
+    }
+
+    pub async fn process(
+        &self,
+        mut req: Request<Body>,
+        sock_addr: SocketAddr,
+        tls: bool,
+        host: &str,
+        route: &ProxyRoute,
+    ) -> Response<Body> {
+        let upstream = match self.pick_upstream(host, route, sock_addr) {
+            Some(a) => a,
+            None => {
+                debug!("proxy route has no upstreams (prefix={})", route.prefix);
+                return Self::error_response(StatusCode::BAD_GATEWAY);
+            }
+        };
+        let uri = match Self::upstream_uri(upstream, route, req.uri()) {
+            Ok(a) => a,
+            Err(err) => {
+                debug!("invalid proxy upstream (url={}) - {}", upstream, err);
+                return Self::error_response(StatusCode::BAD_GATEWAY);
+            }
+        };
+
+        // Upstreams given as IP addresses never pass through the resolver
+        let literal = uri
+            .host()
+            .map(|a| a.trim_start_matches('[').trim_end_matches(']'))
+            .and_then(|a| IpAddr::from_str(a).ok());
+        if let Some(ip) = literal {
+            if self.guard.permits(ip) == false {
+                warn!("proxy upstream is a restricted address (upstream={})", uri);
+                return Self::error_response(StatusCode::FORBIDDEN);
+            }
+        }
+
+        // Web sockets (and any other protocol upgrades) are tunneled between
+        // the client and upstream once the upstream accepts the upgrade
+        let upgrade = match req.headers().get(UPGRADE).map(|a| a.clone()) {
+            Some(protocol) => Some((protocol, hyper::upgrade::on(&mut req))),
+            None => None,
+        };
+
+        let (parts, body) = req.into_parts();
+        let mut upstream_req = Request::new(body);
+        *upstream_req.method_mut() = parts.method;
+        *upstream_req.uri_mut() = uri.clone();
+
+        let headers = upstream_req.headers_mut();
+        for (name, val) in parts.headers.iter() {
+            headers.append(name.clone(), val.clone());
+        }
+        Self::strip_hop_by_hop(headers);
+        if let Some((protocol, _)) = upgrade.as_ref() {
+            headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+            headers.insert(UPGRADE, protocol.clone());
+        }
+        if route.preserve_host == false {
+            headers.remove(HOST);
+            if let Some(authority) = uri.authority() {
+                if let Ok(authority) = HeaderValue::from_str(authority.as_str()) {
+                    headers.insert(HOST, authority);
+                }
+            }
+        }
+        let forwarded_for = match parts
+            .headers
+            .get("X-Forwarded-For")
+            .and_then(|a| a.to_str().ok())
+        {
+            Some(prev) => format!("{}, {}", prev, sock_addr.ip()),
+            None => sock_addr.ip().to_string(),
+        };
+        let forwarded = [
+            ("X-Forwarded-For", forwarded_for.as_str()),
+            ("X-Forwarded-Proto", if tls { "https" } else { "http" }),
+            ("X-Forwarded-Host", host),
+        ];
+        for (name, val) in forwarded {
+            if let Ok(val) = HeaderValue::from_str(val) {
+                headers.insert(name, val);
+            }
+        }
+        Self::rewrite(headers, &route.request_headers);
+
+        trace!("perf-checkpoint: proxy request (upstream={})", uri);
+        let timeout = Duration::from_millis(route.timeout_ms);
+        let mut resp = match ate::engine::timeout(timeout, self.client.request(upstream_req)).await
+        {
+            Ok(Ok(a)) => a,
+            Ok(Err(err)) => {
+                warn!("proxy request failed (upstream={}) - {}", uri, err);
+                return Self::error_response(StatusCode::BAD_GATEWAY);
+            }
+            Err(_) => {
+                warn!("proxy request timed out (upstream={})", uri);
+                return Self::error_response(StatusCode::GATEWAY_TIMEOUT);
+            }
+        };
+        trace!("perf-checkpoint: proxy response (status={})", resp.status());
+
+        let is_upgrade = resp.status() == StatusCode::SWITCHING_PROTOCOLS;
+        if let (true, Some((_, client))) = (is_upgrade, upgrade) {
+            let upstream = hyper::upgrade::on(&mut resp);
+            TaskEngine::spawn(async move {
+                match futures::future::try_join(client, upstream).await {
+                    Ok((mut client, mut upstream)) => {
+                        let ret = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                        if let Err(err) = ret {
+                            debug!("proxy tunnel closed - {}", err);
+                        }
+                    }
+                    Err(err) => {
+                        debug!("proxy upgrade failed - {}", err);
+                    }
+                }
+            });
+        }
+
+        let (mut parts, body) = resp.into_parts();
+        if is_upgrade {
+            parts
+                .headers
+                .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        } else {
+            Self::strip_hop_by_hop(&mut parts.headers);
+        }
+        Self::rewrite(&mut parts.headers, &route.response_headers);
+
+        let body = match is_upgrade {
+            true => Body::empty(),
+            false => body,
+        };
+        Response::from_parts(parts, body)
+    }
+
+    fn pick_upstream<'a>(
+        &self,
+        host: &str,
+        route: &'a ProxyRoute,
+        sock_addr: SocketAddr,
+    ) -> Option<&'a str> {
+        let len = route.upstreams.len();
+        if len <= 0 {
+            return None;
+        }
+        let index = match route.balance {
+            ProxyBalance::RoundRobin => {
+                let mut guard = self.round_robin.lock().unwrap();
+                let next = guard
+                    .entry(format!("{}{}", host, route.prefix))
+                    .or_default();
+                let ret = *next;
+                *next = next.wrapping_add(1);
+                ret
+            }
+            ProxyBalance::Random => fastrand::usize(..len),
+            ProxyBalance::IpHash => {
+                let mut hasher = FxHasher::default();
+                sock_addr.ip().hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+        route.upstreams.get(index % len).map(|a| a.as_str())
+    }
+
+    fn upstream_uri(
+        upstream: &str,
+        route: &ProxyRoute,
+        uri: &Uri,
+    ) -> Result<Uri, http::uri::InvalidUri> {
+        // Web socket upstreams are connected to over HTTP and then upgraded
+        let upstream = upstream.trim_end_matches('/');
+        let upstream = if let Some(a) = upstream.strip_prefix("ws://") {
+            format!("http://{}", a)
+        } else if let Some(a) = upstream.strip_prefix("wss://") {
+            format!("https://{}", a)
+        } else {
+            upstream.to_string()
+        };
+
+        let mut path = uri.path();
+        if route.strip_prefix {
+            path = path.strip_prefix(route.base()).unwrap_or(path);
+        }
+        let mut ret = format!("{}/{}", upstream, path.trim_start_matches('/'));
+        if let Some(query) = uri.query() {
+            ret.push('?');
+            ret.push_str(query);
+        }
+        Uri::from_str(ret.as_str())
+    }
+
+    fn strip_hop_by_hop(headers: &mut HeaderMap) {
+        // Headers named in the Connection header are also connection specific
+        let named = headers
+            .get_all(CONNECTION)
+            .iter()
+            .filter_map(|a| a.to_str().ok())
+            .flat_map(|a| a.split(","))
+            .map(|a| a.trim().to_string())
+            .filter(|a| a.len() > 0)
+            .collect::<Vec<_>>();
+        for name in named {
+            headers.remove(name.as_str());
+        }
+        for name in HOP_BY_HOP_HEADERS {
+            headers.remove(name);
+        }
+    }
+
+    fn rewrite(headers: &mut HeaderMap, rewrite: &HeaderRewrite) {
+        for name in rewrite.remove.iter() {
+            headers.remove(name.as_str());
+        }
+        for (name, val) in rewrite.set.iter() {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(val.as_str()),
+            ) {
+                (Ok(name), Ok(val)) => {
+                    headers.insert(name, val);
+                }
+                _ => {
+                    debug!("invalid proxy header rewrite ({}: {})", name, val);
+                }
+            }
+        }
+    }
+
+    fn error_response(status: StatusCode) -> Response<Body> {
+        let mut resp = Response::new(Body::from(status.as_str().to_string()));
+        *resp.status_mut() = status;
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restricted_upstreams() {
+        let guard = UpstreamGuard::new(vec![IpCidr::from_str("10.1.0.0/16").unwrap()]);
+        let cases: &[(&str, bool)] = &[
+            ("93.184.216.34", true),
+            ("2606:2800:220:1::1", true),
+            ("127.0.0.1", false),
+            ("0.0.0.0", false),
+            ("10.0.0.1", false),
+            ("10.1.2.3", true),
+            ("172.16.0.1", false),
+            ("192.168.1.1", false),
+            ("169.254.169.254", false),
+            ("100.64.0.1", false),
+            ("255.255.255.255", false),
+            ("::1", false),
+            ("::", false),
+            ("fd00::1", false),
+            ("fe80::1", false),
+            ("::ffff:127.0.0.1", false),
+            ("::ffff:10.1.0.1", true),
+            ("::ffff:93.184.216.34", true),
+        ];
+        for (ip, expected) in cases {
+            let ip = IpAddr::from_str(ip).unwrap();
+            assert_eq!(guard.permits(ip), *expected, "{}", ip);
+        }
+    }
+}
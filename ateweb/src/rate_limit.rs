@@ -0,0 +1,390 @@
+use fxhash::FxHashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::model::*;
+
+/// Limits the number of buckets that are tracked so that a flood of clients
+/// can not exhaust the memory of the server (once reached the least recently
+/// used buckets are evicted to make room)
+const MAX_BUCKETS: usize = 100000;
+/// Number of buckets evicted in one go when the limit is reached so that a
+/// flood of new clients does not scan the buckets on every request
+const EVICT_BUCKETS: usize = MAX_BUCKETS / 10;
+
+/// Buckets that have not been touched for this long are purged
+const BUCKET_IDLE: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BucketKind {
+    Connections,
+    Requests,
+    Bandwidth,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BucketScope {
+    Ip(IpAddr),
+    Host(String),
+    /// Client of a site that has its own limits
+    HostIp(String, IpAddr),
+    /// Site that has its own limits
+    Site(String),
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            tokens: limit.capacity(),
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = f64::min(self.tokens + elapsed * limit.rate, limit.capacity());
+        self.last = now;
+    }
+
+    /// Returns how long until the bucket holds a certain number of tokens
+    fn wait(&self, limit: &RateLimit, amount: f64) -> Duration {
+        let missing = amount - self.tokens;
+        if missing <= 0.0 || limit.rate <= 0.0 {
+            return Duration::from_secs(1);
+        }
+        Duration::from_secs_f64(missing / limit.rate)
+    }
+}
+
+/// Reason that a request or connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitRejection {
+    /// The client address is on a deny list
+    Denied,
+    /// A limit was exceeded and the client should retry after a while
+    Limited(Duration),
+}
+
+/// Token bucket limits for the connections, requests and bandwidth of
+/// each client IP address and host
+pub struct RateLimiter {
+    conf: RateLimitConf,
+    buckets: Mutex<FxHashMap<(BucketScope, BucketKind), TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(conf: RateLimitConf) -> RateLimiter {
+        RateLimiter {
+            conf,
+            buckets: Mutex::new(FxHashMap::default()),
+        }
+    }
+
+    /// Checks a new connection against the deny list and the connection
+    /// limits of the client
+    pub fn check_connection(&self, ip: IpAddr) -> Result<(), RateLimitRejection> {
+        if self.conf.is_denied(ip) {
+            return Err(RateLimitRejection::Denied);
+        }
+        if self.conf.is_allowed(ip) {
+            return Ok(());
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::take(
+            &mut buckets,
+            Instant::now(),
+            BucketScope::Ip(ip),
+            BucketKind::Connections,
+            self.conf.per_ip.connections.as_ref(),
+            1.0,
+        )
+    }
+
+    /// Checks a TLS connection against the connection limits of the host
+    /// that it is for (as indicated by its SNI)
+    pub fn check_host_connection(&self, ip: IpAddr, sni: &str) -> Result<(), RateLimitRejection> {
+        if self.conf.is_allowed(ip) {
+            return Ok(());
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::take(
+            &mut buckets,
+            Instant::now(),
+            BucketScope::Host(sni.to_lowercase()),
+            BucketKind::Connections,
+            self.conf.per_host.connections.as_ref(),
+            1.0,
+        )
+    }
+
+    /// Checks a request against the deny lists and the request and bandwidth
+    /// limits (a site may provide its own limits which are enforced on top of
+    /// the global ones thus they can only ever make the limits tighter)
+    pub fn check_request(
+        &self,
+        ip: IpAddr,
+        host: &str,
+        site: Option<&RateLimitConf>,
+    ) -> Result<(), RateLimitRejection> {
+        if self.conf.is_denied(ip) || site.map(|a| a.is_denied(ip)).unwrap_or(false) {
+            return Err(RateLimitRejection::Denied);
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let scopes = self.scopes(ip, host, site);
+
+        // Bandwidth is paid for after the response is sent thus a client is
+        // refused while it is in debt
+        for (scope, limits) in scopes.iter() {
+            Self::take(
+                &mut buckets,
+                now,
+                scope.clone(),
+                BucketKind::Bandwidth,
+                limits.bandwidth.as_ref(),
+                0.0,
+            )?;
+        }
+        for (scope, limits) in scopes {
+            Self::take(
+                &mut buckets,
+                now,
+                scope,
+                BucketKind::Requests,
+                limits.requests.as_ref(),
+                1.0,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Charges the bytes of a response to the bandwidth buckets
+    pub fn record_bytes(&self, ip: IpAddr, host: &str, site: Option<&RateLimitConf>, bytes: u64) {
+        if bytes <= 0 {
+            return;
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        for (scope, limits) in self.scopes(ip, host, site) {
+            let limit = match limits.bandwidth.as_ref() {
+                Some(a) => a,
+                None => continue,
+            };
+            let bucket = Self::bucket(&mut buckets, now, scope, BucketKind::Bandwidth, limit);
+            bucket.tokens -= bytes as f64;
+        }
+    }
+
+    /// Removes any buckets that have been idle for a while
+    pub fn house_keeping(&self) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, a| now.saturating_duration_since(a.last) < BUCKET_IDLE);
+    }
+
+    /// Returns the buckets that a request is charged to along with their limits,
+    /// where the allow list of the global settings only exempts the client from
+    /// the global limits and the allow list of a site only from its own limits
+    fn scopes<'a>(
+        &'a self,
+        ip: IpAddr,
+        host: &str,
+        site: Option<&'a RateLimitConf>,
+    ) -> Vec<(BucketScope, &'a RateLimits)> {
+        let mut ret = Vec::with_capacity(4);
+        if self.conf.is_allowed(ip) == false {
+            ret.push((BucketScope::Ip(ip), &self.conf.per_ip));
+            ret.push((BucketScope::Host(host.to_string()), &self.conf.per_host));
+        }
+        if let Some(site) = site.filter(|a| a.is_allowed(ip) == false) {
+            ret.push((BucketScope::HostIp(host.to_string(), ip), &site.per_ip));
+            ret.push((BucketScope::Site(host.to_string()), &site.per_host));
+        }
+        ret
+    }
+
+    fn bucket<'a>(
+        buckets: &'a mut FxHashMap<(BucketScope, BucketKind), TokenBucket>,
+        now: Instant,
+        scope: BucketScope,
+        kind: BucketKind,
+        limit: &RateLimit,
+    ) -> &'a mut TokenBucket {
+        let key = (scope, kind);
+        if buckets.contains_key(&key) == false && buckets.len() >= MAX_BUCKETS {
+            Self::evict(buckets, now);
+        }
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(limit, now));
+        bucket.refill(limit, now);
+        bucket
+    }
+
+    /// Makes room for new buckets by purging the idle buckets and then, if
+    /// that is not enough, the least recently used ones
+    fn evict(buckets: &mut FxHashMap<(BucketScope, BucketKind), TokenBucket>, now: Instant) {
+        buckets.retain(|_, a| now.saturating_duration_since(a.last) < BUCKET_IDLE);
+        if buckets.len() < MAX_BUCKETS {
+            return;
+        }
+        debug!("rate limiter is tracking too many buckets (evicting the least recently used)");
+        let mut used = buckets.values().map(|a| a.last).collect::<Vec<_>>();
+        let (_, cutoff, _) = used.select_nth_unstable(EVICT_BUCKETS);
+        let cutoff = *cutoff;
+        buckets.retain(|_, a| a.last > cutoff);
+    }
+
+    fn take(
+        buckets: &mut FxHashMap<(BucketScope, BucketKind), TokenBucket>,
+        now: Instant,
+        scope: BucketScope,
+        kind: BucketKind,
+        limit: Option<&RateLimit>,
+        amount: f64,
+    ) -> Result<(), RateLimitRejection> {
+        let limit = match limit {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+        let bucket = Self::bucket(buckets, now, scope, kind, limit);
+        if bucket.tokens < amount {
+            return Err(RateLimitRejection::Limited(bucket.wait(limit, amount)));
+        }
+        bucket.tokens -= amount;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    fn ip(val: &str) -> IpAddr {
+        IpAddr::from_str(val).unwrap()
+    }
+
+    fn requests(rate: f64, burst: f64) -> RateLimits {
+        RateLimits {
+            requests: Some(RateLimit::new(rate, burst)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn token_bucket_refills() {
+        let limit = RateLimit::new(10.0, 20.0);
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(&limit, start);
+        assert_eq!(bucket.tokens, 20.0);
+
+        bucket.tokens = 0.0;
+        bucket.refill(&limit, start + Duration::from_millis(500));
+        assert!((bucket.tokens - 5.0).abs() < 0.001);
+        assert_eq!(bucket.wait(&limit, 10.0), Duration::from_millis(500));
+
+        // The bucket never holds more than its burst
+        bucket.refill(&limit, start + Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 20.0);
+
+        // Time going backwards adds nothing
+        bucket.tokens = 0.0;
+        bucket.refill(&limit, start);
+        assert_eq!(bucket.tokens, 0.0);
+
+        // Without a burst the bucket holds one second of tokens (but at least one)
+        assert_eq!(RateLimit { rate: 5.0, burst: None }.capacity(), 5.0);
+        assert_eq!(RateLimit { rate: 0.1, burst: None }.capacity(), 1.0);
+    }
+
+    #[test]
+    fn sites_only_tighten_the_global_limits() {
+        let limiter = RateLimiter::new(RateLimitConf {
+            per_ip: requests(0.0, 2.0),
+            allow: vec![IpCidr::from_str("10.0.0.0/8").unwrap()],
+            ..Default::default()
+        });
+        let client = ip("192.0.2.1");
+
+        // A looser site limit does not lift the global one
+        let loose = RateLimitConf {
+            per_ip: requests(0.0, 100.0),
+            ..Default::default()
+        };
+        assert!(limiter.check_request(client, "a.com", Some(&loose)).is_ok());
+        assert!(limiter.check_request(client, "a.com", Some(&loose)).is_ok());
+        assert!(limiter.check_request(client, "a.com", Some(&loose)).is_err());
+
+        // ...while a tighter one applies on top of it
+        let tight = RateLimitConf {
+            per_ip: requests(0.0, 1.0),
+            ..Default::default()
+        };
+        let other = ip("192.0.2.2");
+        assert!(limiter.check_request(other, "b.com", Some(&tight)).is_ok());
+        assert!(limiter.check_request(other, "b.com", Some(&tight)).is_err());
+
+        // The allow list of a site only exempts clients from its own limits
+        let allowing = RateLimitConf {
+            per_ip: requests(0.0, 1.0),
+            allow: vec![IpCidr::from_str("192.0.2.0/24").unwrap()],
+            ..Default::default()
+        };
+        let third = ip("192.0.2.3");
+        assert!(limiter.check_request(third, "c.com", Some(&allowing)).is_ok());
+        assert!(limiter.check_request(third, "c.com", Some(&allowing)).is_ok());
+        assert!(limiter.check_request(third, "c.com", Some(&allowing)).is_err());
+
+        // ...while the global allow list exempts them from the global limits
+        let trusted = ip("10.1.2.3");
+        for _ in 0..10 {
+            assert!(limiter.check_request(trusted, "a.com", None).is_ok());
+        }
+        assert!(limiter.check_request(trusted, "b.com", Some(&tight)).is_ok());
+        assert!(limiter.check_request(trusted, "b.com", Some(&tight)).is_err());
+
+        // Either deny list refuses the client
+        let denying = RateLimitConf {
+            deny: vec![IpCidr::from_str("192.0.2.9").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(
+            limiter.check_request(ip("192.0.2.9"), "d.com", Some(&denying)),
+            Err(RateLimitRejection::Denied)
+        );
+    }
+
+    #[test]
+    fn full_limiters_evict_rather_than_fail_open() {
+        let limiter = RateLimiter::new(RateLimitConf {
+            per_ip: RateLimits {
+                connections: Some(RateLimit::new(0.0, 1.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        for n in 0..MAX_BUCKETS as u32 {
+            let client = IpAddr::V4(Ipv4Addr::from(0x0a00_0000u32 + n));
+            assert!(limiter.check_connection(client).is_ok());
+        }
+
+        let client = ip("192.0.2.1");
+        assert!(limiter.check_connection(client).is_ok());
+        assert!(limiter.check_connection(client).is_err());
+        assert!(limiter.buckets.lock().unwrap().len() <= MAX_BUCKETS);
+    }
+}
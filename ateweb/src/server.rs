@@ -1,11 +1,14 @@
 use async_trait::async_trait;
+use bytes::Bytes;
 use error_chain::bail;
 use fxhash::FxHashMap;
 use std::collections::hash_map::Entry as StdEntry;
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::ops::Deref;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
 use std::sync::Weak;
 use std::time::Duration;
 use std::time::Instant;
@@ -17,7 +20,12 @@ use wasmer_auth::service::AuthService;
 use wasmer_auth::cmd::gather_command;
 
 use hyper;
+use hyper::body::HttpBody;
 use hyper::header::HeaderValue;
+use hyper::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, AUTHORIZATION, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_RANGE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE, RETRY_AFTER, VARY,
+};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::upgrade::Upgraded;
 pub use hyper::Body;
@@ -33,15 +41,28 @@ use ate_files::repo::*;
 
 use crate::model::WebConf;
 
+use super::access_log::*;
 use super::acceptor::*;
+use super::acme::split_pem_bundle;
 use super::acme::AcmeResolver;
 use super::builder::*;
+use super::compress::*;
 use super::conf::*;
 use super::error::WebServerError;
 use super::error::WebServerErrorKind;
+use super::helper::*;
+use super::metrics::*;
 use super::model::*;
+use super::proxy::*;
+use super::rate_limit::*;
+use super::webdav::*;
 use super::stream::*;
 
+/// Limits how much of a site's access logs are buffered between flushes
+const MAX_SITE_LOG_BUFFER: usize = 8 * 1024 * 1024;
+/// Largest certificate bundle that can be uploaded to the admin listener
+const MAX_CERT_UPLOAD: usize = 1024 * 1024;
+
 pub struct ServerWebConf {
     web_conf: WebConf,
     web_conf_when: Option<Instant>,
@@ -88,6 +109,35 @@ pub struct Server {
     server_conf: ServerConf,
     callback: Option<Arc<dyn ServerCallback + 'static>>,
     mime: FxHashMap<String, String>,
+    compressed: CompressionCache,
+    proxy: ReverseProxy,
+    webdav: WebDav,
+    metrics: Metrics,
+    access_log: Option<AccessLogFile>,
+    site_logs: StdMutex<FxHashMap<String, Vec<u8>>>,
+    limiter: Arc<RateLimiter>,
+}
+
+pub(crate) enum SiteRoute {
+    Proxy(ProxyRoute),
+    WebDav(String),
+}
+
+/// Representation of a file that is returned to the client
+enum Representation {
+    Identity,
+    Precompressed(ContentEncoding, RepositoryFile),
+    Compressed(ContentEncoding),
+}
+
+impl Representation {
+    fn encoding(&self) -> Option<ContentEncoding> {
+        match self {
+            Representation::Identity => None,
+            Representation::Precompressed(encoding, _) => Some(*encoding),
+            Representation::Compressed(encoding) => Some(*encoding),
+        }
+    }
 }
 
 async fn process(
@@ -97,13 +147,19 @@ async fn process(
     sock_addr: SocketAddr,
 ) -> Result<Response<Body>, hyper::Error> {
     trace!("perf-checkpoint: hyper process (addr={})", sock_addr);
+    let started = Instant::now();
+    let access = AccessRequest::new(&req, sock_addr);
 
     let path = req.uri().path().to_string();
-    match server.process(req, sock_addr, listen.deref()).await {
+    let ret = match server.check_rate_limit(&access).await {
+        Some(resp) => Ok(resp),
+        None => server.process(req, sock_addr, listen.deref()).await,
+    };
+    let resp = match ret {
         Ok(resp) => {
             trace!("perf-checkpoint: hyper finished");
             trace!("res: status={}", resp.status().as_u16());
-            Ok(resp)
+            resp
         }
         Err(WebServerError(
             WebServerErrorKind::FileSystemError(FileSystemErrorKind::NoAccess),
@@ -113,15 +169,51 @@ async fn process(
             let mut resp = Response::new(Body::from(err));
             *resp.status_mut() = StatusCode::FORBIDDEN;
             trace!("res: status={}", resp.status().as_u16());
-            return Ok(resp);
+            resp
         }
         Err(err) => {
             let mut resp = Response::new(Body::from(err.response_body()));
             *resp.status_mut() = err.status_code();
             trace!("res: status={}", resp.status().as_u16());
-            return Ok(resp);
+            resp
         }
-    }
+    };
+
+    server.record_access(access, &resp, started.elapsed()).await;
+    Ok(resp)
+}
+
+async fn process_admin(
+    server: Arc<Server>,
+    acme: Arc<AcmeResolver>,
+    req: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    trace!("perf-checkpoint: admin process (path={})", req.uri().path());
+
+    let upload = req
+        .uri()
+        .path()
+        .strip_prefix("/certs/")
+        .map(|a| a.to_string());
+    let resp = match (req.method(), req.uri().path(), upload) {
+        (&Method::GET, "/metrics", _) => {
+            let mut resp = Response::new(Body::from(server.metrics.render()));
+            resp.headers_mut().append(
+                "Content-Type",
+                HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            resp
+        }
+        (&Method::PUT, _, Some(host)) => server.upload_cert(&acme, host.as_str(), req).await,
+        _ => admin_response(StatusCode::NOT_FOUND, StatusCode::NOT_FOUND.as_str()),
+    };
+    Ok(resp)
+}
+
+fn admin_response(status: StatusCode, msg: &str) -> Response<Body> {
+    let mut resp = Response::new(Body::from(format!("{}\n", msg)));
+    *resp.status_mut() = status;
+    resp
 }
 
 impl Server {
@@ -149,9 +241,29 @@ impl Server {
         )
         .await?;
 
+        let access_log = match builder.conf.access_log_path.as_ref() {
+            Some(path) => {
+                let path = shellexpand::tilde(path).to_string();
+                Some(AccessLogFile::open(Path::new(path.as_str()), builder.conf.access_log_format)?)
+            }
+            None => None,
+        };
+
         Ok(Arc::new(Server {
             repo,
             web_conf: Mutex::new(FxHashMap::default()),
+            compressed: CompressionCache::new(builder.conf.compression_cache_size),
+            proxy: ReverseProxy::new(builder.conf.proxy_allow.clone()),
+            metrics: Metrics::default(),
+            access_log,
+            site_logs: StdMutex::new(FxHashMap::default()),
+            limiter: Arc::new(RateLimiter::new(builder.conf.rate_limit.clone())),
+            webdav: WebDav::new(
+                &registry,
+                builder.remote.clone(),
+                builder.auth_url.clone(),
+                builder.conf.ttl,
+            ),
             server_conf: builder.conf,
             callback: builder.callback,
             mime: Server::init_mime(),
@@ -161,7 +273,7 @@ impl Server {
     pub async fn run(self: &Arc<Self>) -> Result<(), Box<dyn std::error::Error>> {
         trace!("running web server");
 
-        let acme = AcmeResolver::new(&self.repo).await?;
+        let acme = AcmeResolver::new(&self.repo, self.server_conf.acme.clone()).await?;
 
         let mut joins = Vec::new();
         for listen in self.server_conf.listen.iter() {
@@ -182,7 +294,7 @@ impl Server {
 
             let acme = acme.clone();
             let tcp_listener = TcpListener::bind(&listen.addr).await?;
-            let acceptor = HyperAcceptor::new(tcp_listener, acme, listen.tls);
+            let acceptor = HyperAcceptor::new(tcp_listener, acme, self.limiter.clone(), listen.tls);
             let server = hyper::Server::builder(acceptor)
                 .http1_preserve_header_case(true)
                 .http1_title_case_headers(true)
@@ -219,6 +331,31 @@ impl Server {
             });
         }
 
+        // The admin listener exposes the metrics of the web server and
+        // accepts certificates that are uploaded by the operator
+        if let Some(addr) = self.server_conf.admin_listen.clone() {
+            let make_service = {
+                let server = Arc::clone(self);
+                let acme = acme.clone();
+                make_service_fn(move |_| {
+                    let server = server.clone();
+                    let acme = acme.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req| {
+                            process_admin(server.clone(), acme.clone(), req)
+                        }))
+                    }
+                })
+            };
+            let admin = hyper::Server::try_bind(&addr)?.serve(make_service);
+            println!("Admin listening on {}", addr);
+            TaskEngine::spawn(async move {
+                if let Err(e) = admin.await {
+                    eprintln!("admin server error: {}", e);
+                }
+            });
+        }
+
         for res in futures::future::join_all(joins).await {
             if let Err(e) = res {
                 eprintln!("server error: {}", e);
@@ -227,8 +364,162 @@ impl Server {
         Ok(())
     }
 
+    /// Stores a certificate bundle (the PEM certificate chain followed by its
+    /// private key) that an operator uploaded for a host
+    async fn upload_cert(
+        &self,
+        acme: &AcmeResolver,
+        host: &str,
+        req: Request<Body>,
+    ) -> Response<Body> {
+        let token = match self.server_conf.admin_token.as_ref() {
+            Some(a) => a,
+            None => {
+                return admin_response(
+                    StatusCode::FORBIDDEN,
+                    "certificate uploads require an admin token",
+                );
+            }
+        };
+        let presented = header_str(Some(req.headers()), AUTHORIZATION)
+            .and_then(|a| a.trim().strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if ring::constant_time::verify_slices_are_equal(presented.trim().as_bytes(), token.as_bytes())
+            .is_err()
+        {
+            warn!("rejected certificate upload (host={})", host);
+            return admin_response(StatusCode::UNAUTHORIZED, "invalid admin token");
+        }
+
+        let mut bundle = Vec::new();
+        let mut body = req.into_body();
+        while let Some(data) = body.data().await {
+            let data = match data {
+                Ok(a) => a,
+                Err(err) => {
+                    return admin_response(StatusCode::BAD_REQUEST, err.to_string().as_str());
+                }
+            };
+            if bundle.len() + data.len() > MAX_CERT_UPLOAD {
+                return admin_response(StatusCode::PAYLOAD_TOO_LARGE, "certificate bundle is too large");
+            }
+            bundle.extend_from_slice(&data[..]);
+        }
+
+        let (cert, key) = match split_pem_bundle(&bundle[..]) {
+            Some(a) => a,
+            None => {
+                return admin_response(
+                    StatusCode::BAD_REQUEST,
+                    "expected a PEM certificate chain and a single private key",
+                );
+            }
+        };
+        match acme.upload_cert(host, &cert[..], &key[..]).await {
+            Ok(()) => {
+                info!("uploaded certificate (host={})", host);
+                let mut resp = Response::new(Body::empty());
+                *resp.status_mut() = StatusCode::NO_CONTENT;
+                resp
+            }
+            Err(err) => {
+                warn!("certificate upload failed (host={}) - {}", host, err);
+                admin_response(StatusCode::BAD_REQUEST, err.to_string().as_str())
+            }
+        }
+    }
+
     async fn house_keeping(&self) {
         self.repo.house_keeping().await;
+        self.limiter.house_keeping();
+        self.flush_site_logs().await;
+    }
+
+    pub(crate) async fn record_access(
+        &self,
+        req: AccessRequest,
+        resp: &Response<Body>,
+        elapsed: Duration,
+    ) {
+        let ip = req.peer.ip();
+        let entry = AccessLogEntry::new(req, resp, elapsed);
+        let host = entry.host.clone().unwrap_or_else(|| "-".to_string());
+        let bytes = entry.bytes.unwrap_or_default();
+        self.metrics
+            .record(host.as_str(), entry.status, bytes, elapsed);
+        if let Some(access_log) = self.access_log.as_ref() {
+            access_log.write(&entry);
+        }
+
+        let (format, rate_limit) = match self.web_conf.lock().await.get(&host) {
+            Some(a) => (a.web_conf.access_log, a.web_conf.rate_limit.clone()),
+            None => (None, None),
+        };
+        self.limiter
+            .record_bytes(ip, host.as_str(), rate_limit.as_ref(), bytes);
+
+        // Sites may also keep their own access logs which are buffered and
+        // then periodically written to their files chain
+        if let Some(format) = format {
+            let mut site_logs = self.site_logs.lock().unwrap();
+            let buffer = site_logs.entry(host).or_default();
+            if buffer.len() < MAX_SITE_LOG_BUFFER {
+                buffer.extend_from_slice(entry.format(format).as_bytes());
+            }
+        }
+    }
+
+    /// Refuses requests from clients that are denied or have exceeded their
+    /// rate limits (the limits of a site are taken from its cached web.yaml so
+    /// that refused requests never cause it to be loaded)
+    pub(crate) async fn check_rate_limit(&self, req: &AccessRequest) -> Option<Response<Body>> {
+        let host = req.host.as_deref().unwrap_or("-");
+        let rate_limit = self
+            .web_conf
+            .lock()
+            .await
+            .get(host)
+            .and_then(|a| a.web_conf.rate_limit.clone());
+
+        match self
+            .limiter
+            .check_request(req.peer.ip(), host, rate_limit.as_ref())
+        {
+            Ok(()) => None,
+            Err(RateLimitRejection::Denied) => {
+                debug!("request denied (addr={}, host={})", req.peer, host);
+                let mut resp = Response::new(Body::from("Access Denied\n"));
+                *resp.status_mut() = StatusCode::FORBIDDEN;
+                Some(resp)
+            }
+            Err(RateLimitRejection::Limited(wait)) => {
+                debug!("request rate limited (addr={}, host={})", req.peer, host);
+                let retry_after = wait.as_secs() + if wait.subsec_nanos() > 0 { 1 } else { 0 };
+                let mut resp = Response::new(Body::from(
+                    StatusCode::TOO_MANY_REQUESTS.as_str().to_string(),
+                ));
+                *resp.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                resp.headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(u64::max(retry_after, 1)));
+                Some(resp)
+            }
+        }
+    }
+
+    async fn flush_site_logs(&self) {
+        let site_logs = std::mem::take(&mut *self.site_logs.lock().unwrap());
+        let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        for (host, data) in site_logs {
+            let key = ChainKey::from(format!("{}/www", host));
+            let path = format!("{}access-{}.log", WEB_CONF_FILES_ACCESS_LOGS, date);
+            if let Err(err) = self
+                .repo
+                .append_file(&key, host.as_str(), path.as_str(), &data[..])
+                .await
+            {
+                warn!("failed to store access logs (host={}) - {}", host, err);
+            }
+        }
     }
 
     pub(crate) fn get_host(&self, req: &Request<Body>) -> Result<String, WebServerError> {
@@ -306,6 +597,26 @@ impl Server {
         Ok(conf.web_conf.clone())
     }
 
+    pub(crate) async fn get_site_route(
+        &self,
+        req: &Request<Body>,
+        listen: &ServerListen,
+    ) -> Option<(String, SiteRoute)> {
+        let host = self.get_host(req).ok()?;
+        let conf = self.get_conf(host.as_str()).await.ok()?;
+
+        // Redirects of the host take priority over its routes
+        if conf.redirect.is_some() || (conf.force_https && listen.tls == false) {
+            return None;
+        }
+        let path = req.uri().path();
+        if let Some(prefix) = conf.webdav_prefix(path) {
+            return Some((host, SiteRoute::WebDav(prefix.to_string())));
+        }
+        let route = conf.proxy_route(path)?.clone();
+        Some((host, SiteRoute::Proxy(route)))
+    }
+
     pub(crate) async fn force_https(
         &self,
         req: Request<Body>,
@@ -400,37 +711,254 @@ impl Server {
         path: &str,
         is_head: bool,
         conf: &WebConf,
+        headers: Option<&hyper::HeaderMap>,
     ) -> Result<Option<Response<Body>>, WebServerError> {
         self.sanitize(path)?;
         let key = ChainKey::from(format!("{}/www", host));
         trace!("perf-checkpoint: get_file (path={})", path);
-        if let Some(data) = self.repo.get_file(&key, host, path).await? {
-            let len_str = data.len().to_string();
-            trace!("perf-checkpoint: got_file (data_len={})", len_str);
+        let file = match self.repo.get_file_handle(&key, host, path).await? {
+            Some(a) => a,
+            None => {
+                return Ok(None);
+            }
+        };
+        trace!("perf-checkpoint: got_file (size={})", file.size);
+
+        // Compressed content is only returned for complete responses and
+        // precompressed siblings of the file take priority
+        let compressible = conf.compress && self.mime_for(path).map(is_compressible).unwrap_or(false);
+        let encodings = match header_str(headers, ACCEPT_ENCODING) {
+            Some(val) if conf.compress && header_str(headers, RANGE).is_none() => {
+                ContentEncoding::accepted(val)
+            }
+            _ => Vec::new(),
+        };
+        let mut representation = Representation::Identity;
+        if compressible {
+            for encoding in encodings.iter() {
+                let sibling = format!("{}.{}", path, encoding.extension());
+                if let Some(sibling) = self.repo.get_file_handle(&key, host, sibling.as_str()).await? {
+                    representation = Representation::Precompressed(*encoding, sibling);
+                    break;
+                }
+            }
+        }
+        if let (Representation::Identity, Some(encoding)) = (&representation, encodings.first()) {
+            if compressible && file.size >= MIN_COMPRESS_SIZE && file.size <= MAX_COMPRESS_SIZE {
+                representation = Representation::Compressed(*encoding);
+            }
+        }
+        let vary = compressible || representation.encoding().is_some();
+
+        // The entity tag changes whenever any part of the file is modified
+        // and is unique for each encoding of the file
+        let (etag, updated) = match &representation {
+            Representation::Identity => (
+                format!("\"{}\"", file.version.hash.to_hex_string()),
+                file.version.updated,
+            ),
+            Representation::Precompressed(encoding, sibling) => (
+                format!("\"{}-{}\"", sibling.version.hash.to_hex_string(), encoding.extension()),
+                sibling.version.updated,
+            ),
+            Representation::Compressed(encoding) => (
+                format!("\"{}-{}\"", file.version.hash.to_hex_string(), encoding.extension()),
+                file.version.updated,
+            ),
+        };
+        let last_modified = updated / 1000;
+
+        // Conditional requests for content the client already holds
+        let not_modified = match header_str(headers, IF_NONE_MATCH) {
+            Some(val) => etag_matches(val, etag.as_str()),
+            None => match header_str(headers, IF_MODIFIED_SINCE).and_then(parse_http_date) {
+                Some(since) => last_modified <= since,
+                None => false,
+            },
+        };
+        if not_modified {
+            trace!("perf-checkpoint: not_modified");
+            let mut resp = Response::new(Body::empty());
+            self.apply_cache_headers(path, conf, updated, etag.as_str(), vary, &mut resp)?;
+            *resp.status_mut() = StatusCode::NOT_MODIFIED;
+            return Ok(Some(resp));
+        }
 
-            let mut resp = if is_head {
-                Response::new(Body::empty())
-            } else {
-                Response::new(Body::from(data))
-            };
-            resp.headers_mut()
-                .append("Content-Length", HeaderValue::from_str(len_str.as_str())?);
-            self.apply_mime(path, &mut resp)?;
-            if conf.coop {
-                resp.headers_mut().append(
-                    "Cross-Origin-Embedder-Policy",
-                    HeaderValue::from_str("require-corp")?,
-                );
+        // Partial content is only returned if the client copy is still current
+        let range = match header_str(headers, RANGE) {
+            Some(range) => {
+                let current = match header_str(headers, IF_RANGE) {
+                    Some(val) if val.trim().starts_with("\"") => val.trim() == etag.as_str(),
+                    Some(val) if val.trim().starts_with("W/") => false,
+                    Some(val) => parse_http_date(val) == Some(last_modified),
+                    None => true,
+                };
+                match current {
+                    true => parse_range(range, file.size),
+                    false => RangeRequest::Full,
+                }
+            }
+            None => RangeRequest::Full,
+        };
+        let (status, range) = match range {
+            RangeRequest::Full => (StatusCode::OK, None),
+            RangeRequest::Partial(range) => (StatusCode::PARTIAL_CONTENT, Some(range)),
+            RangeRequest::Unsatisfiable => {
+                trace!("perf-checkpoint: range_not_satisfiable");
+                let mut resp = Response::new(Body::empty());
                 resp.headers_mut().append(
-                    "Cross-Origin-Opener-Policy",
-                    HeaderValue::from_str("same-origin")?,
+                    CONTENT_RANGE,
+                    HeaderValue::from_str(format!("bytes */{}", file.size).as_str())?,
                 );
+                *resp.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                return Ok(Some(resp));
+            }
+        };
+
+        let (body, len) = match &representation {
+            Representation::Identity => {
+                let (offset, len) = match range {
+                    Some(range) => (range.start, range.end - range.start + 1),
+                    None => (0u64, file.size),
+                };
+                (Self::stream_file(file.clone(), offset, len), len)
+            }
+            Representation::Precompressed(_, sibling) => {
+                trace!("perf-checkpoint: precompressed (size={})", sibling.size);
+                (Self::stream_file(sibling.clone(), 0, sibling.size), sibling.size)
+            }
+            Representation::Compressed(encoding) => {
+                let data = self.compress_file(host, path, &file, *encoding).await?;
+                let len = data.len() as u64;
+                (Body::from(data), len)
             }
-            *resp.status_mut() = StatusCode::OK;
-            Ok(Some(resp))
+        };
+
+        let mut resp = if is_head {
+            Response::new(Body::empty())
         } else {
-            Ok(None)
+            Response::new(body)
+        };
+        resp.headers_mut()
+            .append("Content-Length", HeaderValue::from_str(len.to_string().as_str())?);
+        if let Some(range) = range {
+            resp.headers_mut().append(
+                CONTENT_RANGE,
+                HeaderValue::from_str(
+                    format!("bytes {}-{}/{}", range.start, range.end, file.size).as_str(),
+                )?,
+            );
+        }
+        match representation.encoding() {
+            Some(encoding) => {
+                resp.headers_mut()
+                    .append(CONTENT_ENCODING, HeaderValue::from_str(encoding.name())?);
+            }
+            None => {
+                resp.headers_mut()
+                    .append(ACCEPT_RANGES, HeaderValue::from_str("bytes")?);
+            }
+        }
+        self.apply_mime(path, &mut resp)?;
+        self.apply_cache_headers(path, conf, updated, etag.as_str(), vary, &mut resp)?;
+        if conf.coop {
+            resp.headers_mut().append(
+                "Cross-Origin-Embedder-Policy",
+                HeaderValue::from_str("require-corp")?,
+            );
+            resp.headers_mut().append(
+                "Cross-Origin-Opener-Policy",
+                HeaderValue::from_str("same-origin")?,
+            );
         }
+        *resp.status_mut() = status;
+        Ok(Some(resp))
+    }
+
+    /// Returns the compressed contents of a file, reusing the results of a
+    /// previous compression if the file has not changed since then
+    async fn compress_file(
+        &self,
+        host: &str,
+        path: &str,
+        file: &RepositoryFile,
+        encoding: ContentEncoding,
+    ) -> Result<Bytes, WebServerError> {
+        let key = format!("{}{}", host, path);
+        if let Some(data) = self.compressed.get(key.as_str(), encoding, &file.version) {
+            trace!("perf-checkpoint: compressed cache hit (size={})", data.len());
+            return Ok(data);
+        }
+
+        trace!("perf-checkpoint: compress (encoding={})", encoding.name());
+        let data = file.read(0, file.size).await?;
+
+        // Compressing several megabytes takes long enough to stall every other
+        // request on this worker thread so it runs on the blocking pool
+        let data = tokio::task::spawn_blocking(move || {
+            futures::executor::block_on(encoding.compress(&data[..]))
+        })
+        .await
+        .map_err(std::io::Error::from)??;
+        let data = Bytes::from(data);
+        trace!("perf-checkpoint: compressed (size={})", data.len());
+
+        self.compressed
+            .insert(key, encoding, file.version.clone(), data.clone());
+        Ok(data)
+    }
+
+    /// Streams a section of a file to the client one page at a time rather
+    /// than loading the whole file into memory
+    pub(crate) fn stream_file(file: RepositoryFile, offset: u64, len: u64) -> Body {
+        let end = offset + len;
+        let stream = futures::stream::unfold(offset, move |offset| {
+            let file = file.clone();
+            async move {
+                if offset >= end {
+                    return None;
+                }
+                let page_size = PAGE_SIZE as u64;
+                let size = (page_size - (offset % page_size)).min(end - offset);
+                match file.read(offset, size).await {
+                    Ok(data) if data.len() > 0 => {
+                        let next = offset + data.len() as u64;
+                        Some((Ok(data), next))
+                    }
+                    Ok(_) => None,
+                    Err(err) => {
+                        warn!("failed to read file - {}", err);
+                        let err = std::io::Error::new(std::io::ErrorKind::Other, err.to_string());
+                        Some((Err(err), end))
+                    }
+                }
+            }
+        });
+        Body::wrap_stream(stream)
+    }
+
+    pub(crate) fn apply_cache_headers(
+        &self,
+        path: &str,
+        conf: &WebConf,
+        updated: u64,
+        etag: &str,
+        vary: bool,
+        resp: &mut Response<Body>,
+    ) -> Result<(), WebServerError> {
+        let headers = resp.headers_mut();
+        headers.append(ETAG, HeaderValue::from_str(etag)?);
+        headers.append(
+            LAST_MODIFIED,
+            HeaderValue::from_str(http_date(updated).as_str())?,
+        );
+        if let Some(cache_control) = conf.cache_control(path) {
+            headers.append(CACHE_CONTROL, HeaderValue::from_str(cache_control)?);
+        }
+        if vary {
+            headers.append(VARY, HeaderValue::from_str("Accept-Encoding")?);
+        }
+        Ok(())
     }
 
     pub(crate) fn apply_mime(
@@ -438,16 +966,18 @@ impl Server {
         path: &str,
         resp: &mut Response<Body>,
     ) -> Result<(), WebServerError> {
-        if let Some(ext) = path.split(".").collect::<Vec<_>>().into_iter().rev().next() {
-            let ext = ext.to_string();
-            if let Some(mime) = self.mime.get(&ext) {
-                resp.headers_mut()
-                    .append("Content-Type", HeaderValue::from_str(mime.as_str())?);
-            }
+        if let Some(mime) = self.mime_for(path) {
+            resp.headers_mut()
+                .append("Content-Type", HeaderValue::from_str(mime)?);
         }
         Ok(())
     }
 
+    pub(crate) fn mime_for(&self, path: &str) -> Option<&str> {
+        let ext = path.split(".").collect::<Vec<_>>().into_iter().rev().next()?;
+        self.mime.get(ext).map(|a| a.as_str())
+    }
+
     fn init_mime() -> FxHashMap<String, String> {
         let mut ret = FxHashMap::default();
         ret.insert("aac".to_string(), "audio/aac".to_string());
@@ -570,6 +1100,7 @@ impl Server {
         path: &str,
         is_head: bool,
         conf: &WebConf,
+        headers: Option<&hyper::HeaderMap>,
     ) -> Result<Response<Body>, WebServerError> {
         self.sanitize(path)?;
 
@@ -582,7 +1113,7 @@ impl Server {
 
         // Attempt to get the file
         trace!("perf-checkpoint: process_get");
-        match self.process_get(host, path.as_str(), is_head, conf).await? {
+        match self.process_get(host, path.as_str(), is_head, conf, headers).await? {
             Some(a) => {
                 return Ok(a);
             }
@@ -595,7 +1126,7 @@ impl Server {
                     } else {
                         format!("{}{}", path, default_page)
                     };
-                    if let Some(ret) = self.process_get(host, path.as_str(), is_head, conf).await? {
+                    if let Some(ret) = self.process_get(host, path.as_str(), is_head, conf, headers).await? {
                         return Ok(ret);
                     }
                 }
@@ -617,6 +1148,40 @@ impl Server {
     ) -> Result<Response<Body>, WebServerError> {
         trace!("req: {:?}", req);
 
+        // Requests that match a reverse proxy route of the site are passed on
+        // to the upstream servers (including web socket upgrades) while the
+        // WebDAV endpoint of the site handles all its own methods
+        match self.get_site_route(&req, listen).await {
+            Some((host, SiteRoute::Proxy(route))) => {
+                trace!("perf-checkpoint: reverse proxy");
+                let method = req.method().clone();
+                let uri = req.uri().clone();
+                let resp = self
+                    .proxy
+                    .process(req, sock_addr, listen.tls, host.as_str(), &route)
+                    .await;
+                info!("http peer={} method={} path={} proxy={} - {}", sock_addr, method, uri, route.prefix, resp.status());
+                return Ok(resp);
+            }
+            Some((host, SiteRoute::WebDav(prefix))) => {
+                trace!("perf-checkpoint: webdav");
+                let method = req.method().clone();
+                let uri = req.uri().clone();
+
+                // Credentials are sent in the clear thus WebDAV needs TLS (unless its a local client)
+                let resp = if listen.tls || sock_addr.ip().is_loopback() {
+                    self.webdav.process(req, host.as_str(), prefix.as_str()).await
+                } else {
+                    let mut resp = Response::new(Body::from(StatusCode::FORBIDDEN.as_str()));
+                    *resp.status_mut() = StatusCode::FORBIDDEN;
+                    resp
+                };
+                info!("http peer={} method={} path={} webdav - {}", sock_addr, method, uri, resp.status());
+                return Ok(resp);
+            }
+            None => {}
+        }
+
         if hyper_tungstenite::is_upgrade_request(&req) {
             trace!("perf-checkpoint: hyper upgrade request");
             return self.process_upgrade(req, sock_addr).await;
@@ -682,7 +1247,7 @@ impl Server {
                 if let Some(page) = page {
                     trace!("perf-checkpoint: load error page");
                     if let Some(ret) = self
-                        .process_get(host.as_str(), page.as_str(), is_head, &conf)
+                        .process_get(host.as_str(), page.as_str(), is_head, &conf, None)
                         .await?
                     {
                         return Ok(ret);
@@ -849,7 +1414,7 @@ impl Server {
             &Method::OPTIONS | &Method::HEAD | &Method::GET => {
                 trace!("perf-checkpoint: options/head/get");
                 self.sanitize(path)?;
-                self.process_get_with_default(host.as_str(), path, is_head, conf)
+                self.process_get_with_default(host.as_str(), path, is_head, conf, Some(req.headers()))
                     .await
             }
             _ => {
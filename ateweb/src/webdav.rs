@@ -0,0 +1,1310 @@
+use chrono::TimeZone;
+use fxhash::FxHashMap;
+use hyper::body::HttpBody;
+use hyper::header::HeaderName;
+use hyper::header::HeaderValue;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED, WWW_AUTHENTICATE};
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Method;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+use ttl_cache::TtlCache;
+use url::Url;
+use wasmer_auth::cmd::gather_command;
+use wasmer_auth::cmd::login_command;
+
+use ate::prelude::*;
+use ate_files::codes::*;
+use ate_files::error::Result;
+use ate_files::prelude::*;
+use ate_files::repo::RepositoryFile;
+
+use super::helper::*;
+use super::server::Server;
+
+/// Length of time that a WebDAV lock is held for when the client does not
+/// specify a timeout
+const LOCK_TIMEOUT: Duration = Duration::from_secs(3600);
+/// Longest time that a client may hold a WebDAV lock for before refreshing it
+const LOCK_TIMEOUT_MAX: Duration = Duration::from_secs(86400);
+/// Maximum number of logged in WebDAV sessions that are kept in memory
+const MAX_ACCESSORS: usize = 1024;
+/// Maximum number of WebDAV locks held across all the sites
+const MAX_LOCKS: usize = 65536;
+
+const ALLOW_METHODS: &'static str =
+    "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, PROPPATCH, MKCOL, COPY, MOVE, LOCK, UNLOCK";
+
+struct DavLock {
+    token: String,
+    timeout: Duration,
+    expires: Instant,
+}
+
+/// Request that has been authenticated against the files chain of a site
+struct DavRequest<'a> {
+    accessor: Arc<FileAccessor>,
+    ctx: RequestContext,
+    host: &'a str,
+    prefix: &'a str,
+    path: String,
+}
+
+impl<'a> DavRequest<'a> {
+    fn href(&self, path: &str, is_dir: bool) -> String {
+        let mut ret = format!(
+            "{}/{}",
+            self.prefix.trim_end_matches('/'),
+            url_encode(path.trim_start_matches('/'))
+        );
+        if is_dir && ret.ends_with("/") == false {
+            ret.push('/');
+        }
+        ret
+    }
+
+    fn lock_key(&self, path: &str) -> String {
+        format!("{}:{}", self.host, path)
+    }
+}
+
+/// WebDAV endpoint that allows the files chain of a site to be browsed
+/// and edited by the file managers built into most operating systems.
+/// Users log in with the credentials of their `wasmer-auth` account which
+/// must have rights to the group that owns the site.
+pub struct WebDav {
+    registry: Arc<Registry>,
+    db_url: Url,
+    auth_url: Url,
+    ttl: Duration,
+    accessors: Mutex<TtlCache<String, Arc<FileAccessor>>>,
+    locks: Mutex<FxHashMap<String, DavLock>>,
+}
+
+impl WebDav {
+    pub fn new(registry: &Arc<Registry>, db_url: Url, auth_url: Url, ttl: Duration) -> WebDav {
+        WebDav {
+            registry: Arc::clone(registry),
+            db_url,
+            auth_url,
+            ttl,
+            accessors: Mutex::new(TtlCache::new(MAX_ACCESSORS)),
+            locks: Mutex::new(FxHashMap::default()),
+        }
+    }
+
+    pub async fn process(&self, req: Request<Body>, host: &str, prefix: &str) -> Response<Body> {
+        if req.method() == Method::OPTIONS {
+            return Self::options();
+        }
+
+        let accessor = match self.authenticate(&req, host).await {
+            Ok(a) => a,
+            Err(resp) => {
+                return resp;
+            }
+        };
+        let path = match dav_path(req.uri().path(), prefix) {
+            Some(a) => a,
+            None => {
+                return status_response(StatusCode::BAD_REQUEST);
+            }
+        };
+        let dav = DavRequest {
+            ctx: accessor.session_context(),
+            accessor,
+            host,
+            prefix,
+            path,
+        };
+        trace!("webdav method={} path={}", req.method(), dav.path);
+
+        match self.dispatch(&dav, req).await {
+            Ok(a) => a,
+            Err(err) => {
+                debug!("webdav request failed (path={}) - {}", dav.path, err);
+                status_response(error_status(&err))
+            }
+        }
+    }
+
+    async fn dispatch(&self, dav: &DavRequest<'_>, req: Request<Body>) -> Result<Response<Body>> {
+        match req.method().as_str() {
+            "PROPFIND" => self.propfind(dav, &req).await,
+            "PROPPATCH" => self.proppatch(dav).await,
+            "GET" => self.get(dav, false).await,
+            "HEAD" => self.get(dav, true).await,
+            "PUT" => self.put(dav, req).await,
+            "DELETE" => self.delete(dav, &req).await,
+            "MKCOL" => self.mkcol(dav, &req).await,
+            "COPY" => self.copy_or_move(dav, &req, false).await,
+            "MOVE" => self.copy_or_move(dav, &req, true).await,
+            "LOCK" => self.lock(dav, &req).await,
+            "UNLOCK" => Ok(self.unlock(dav, &req)),
+            _ => {
+                let mut resp = status_response(StatusCode::METHOD_NOT_ALLOWED);
+                resp.headers_mut()
+                    .append("Allow", HeaderValue::from_static(ALLOW_METHODS));
+                Ok(resp)
+            }
+        }
+    }
+
+    async fn authenticate(
+        &self,
+        req: &Request<Body>,
+        host: &str,
+    ) -> std::result::Result<Arc<FileAccessor>, Response<Body>> {
+        let (username, password) = match basic_credentials(req.headers()) {
+            Some(a) => a,
+            None => {
+                return Err(unauthorized_response(host));
+            }
+        };
+
+        // Sessions are cached so that clients do not need to log in on every request
+        let cache_key = format!("{}:{}:{}", host, username, password);
+        let cache_key = AteHash::from_bytes(cache_key.as_bytes()).to_hex_string();
+        if let Some(ret) = self.accessors.lock().unwrap().get(&cache_key) {
+            return Ok(Arc::clone(ret));
+        }
+
+        let session = login_command(
+            &self.registry,
+            username.clone(),
+            password,
+            None,
+            self.auth_url.clone(),
+            false,
+        )
+        .await
+        .map_err(|err| {
+            info!("webdav login failed (user={}) - {}", username, err);
+            unauthorized_response(host)
+        })?;
+
+        // The user must be able to gather the rights of the group that owns the site
+        let session = gather_command(
+            &self.registry,
+            host.to_string(),
+            AteSessionInner::User(session),
+            self.auth_url.clone(),
+        )
+        .await
+        .map_err(|err| {
+            info!(
+                "webdav access denied (user={}, host={}) - {}",
+                username, host, err
+            );
+            status_response(StatusCode::FORBIDDEN)
+        })?;
+
+        let key = ChainKey::from(format!("{}/www", host));
+        let chain = self
+            .registry
+            .open(&self.db_url, &key, false)
+            .await
+            .map_err(|err| {
+                warn!("webdav failed to open chain (key={}) - {}", key, err);
+                status_response(StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+        let accessor = Arc::new(
+            FileAccessor::new(
+                chain.as_arc(),
+                Some(host.to_string()),
+                AteSessionType::Group(session),
+                TransactionScope::Local,
+                TransactionScope::Local,
+                false,
+                false,
+            )
+            .await,
+        );
+
+        self.accessors
+            .lock()
+            .unwrap()
+            .insert(cache_key, Arc::clone(&accessor), self.ttl);
+        Ok(accessor)
+    }
+
+    fn options() -> Response<Body> {
+        let mut resp = Response::new(Body::empty());
+        let headers = resp.headers_mut();
+        headers.append("DAV", HeaderValue::from_static("1, 2"));
+        headers.append("MS-Author-Via", HeaderValue::from_static("DAV"));
+        headers.append("Allow", HeaderValue::from_static(ALLOW_METHODS));
+        resp
+    }
+
+    async fn propfind(&self, dav: &DavRequest<'_>, req: &Request<Body>) -> Result<Response<Body>> {
+        let attr = match dav.accessor.search(&dav.ctx, dav.path.as_str()).await? {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+
+        // Infinite depth is treated the same as a depth of one (which clients
+        // handle by walking the tree themselves)
+        let depth =
+            header_str(Some(req.headers()), HeaderName::from_static("depth")).unwrap_or("infinity");
+        let is_dir = attr.kind == FileKind::Directory;
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<D:multistatus xmlns:D=\"DAV:\">\n");
+        let name = file_name(dav.path.as_str());
+        append_response(
+            &mut xml,
+            dav.href(dav.path.as_str(), is_dir).as_str(),
+            name,
+            &attr,
+        );
+        if is_dir && depth.trim() != "0" {
+            let open = dav
+                .accessor
+                .create_open_handle(attr.ino, &dav.ctx, O_RDONLY)
+                .await?;
+            for child in open.children.iter() {
+                if child.name == "." || child.name == ".." {
+                    continue;
+                }
+                let path = join_path(dav.path.as_str(), child.name.as_str());
+                let href = dav.href(path.as_str(), child.kind == FileKind::Directory);
+                append_response(&mut xml, href.as_str(), child.name.as_str(), &child.attr);
+            }
+        }
+        xml.push_str("</D:multistatus>\n");
+
+        Ok(multistatus_response(xml))
+    }
+
+    async fn proppatch(&self, dav: &DavRequest<'_>) -> Result<Response<Body>> {
+        // Dead properties are not stored however clients (e.g. Windows) expect
+        // their updates to succeed
+        let attr = match dav.accessor.search(&dav.ctx, dav.path.as_str()).await? {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+        let href = dav.href(dav.path.as_str(), attr.kind == FileKind::Directory);
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<D:multistatus xmlns:D=\"DAV:\">\n");
+        let _ = write!(
+            xml,
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop/><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+            xml_escape(href.as_str())
+        );
+        xml.push_str("</D:multistatus>\n");
+
+        Ok(multistatus_response(xml))
+    }
+
+    async fn get(&self, dav: &DavRequest<'_>, is_head: bool) -> Result<Response<Body>> {
+        let attr = match dav.accessor.search(&dav.ctx, dav.path.as_str()).await? {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+        if attr.kind == FileKind::Directory {
+            return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+        }
+
+        let handle = dav
+            .accessor
+            .create_open_handle(attr.ino, &dav.ctx, O_RDONLY)
+            .await?;
+        let file = RepositoryFile {
+            size: handle.spec.size(),
+            handle: Arc::new(handle),
+            version: dav.accessor.content_version(attr.ino).await?,
+        };
+
+        let mut resp = match is_head {
+            true => Response::new(Body::empty()),
+            false => Response::new(Server::stream_file(file.clone(), 0, file.size)),
+        };
+        let etag = format!("\"{}\"", file.version.hash.to_hex_string());
+        let headers = resp.headers_mut();
+        append_header(headers, CONTENT_LENGTH, file.size.to_string().as_str());
+        append_header(headers, CONTENT_TYPE, content_type(dav.path.as_str()));
+        append_header(headers, ETAG, etag.as_str());
+        append_header(
+            headers,
+            LAST_MODIFIED,
+            http_date(file.version.updated).as_str(),
+        );
+        Ok(resp)
+    }
+
+    async fn put(&self, dav: &DavRequest<'_>, req: Request<Body>) -> Result<Response<Body>> {
+        if let Some(resp) = self.check_lock(dav, req.headers(), dav.path.as_str()) {
+            return Ok(resp);
+        }
+        let (parent, name) = match split_path(dav.path.as_str()) {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+            }
+        };
+        let parent = match dav.accessor.search(&dav.ctx, parent).await? {
+            Some(a) if a.kind == FileKind::Directory => a,
+            _ => {
+                return Ok(status_response(StatusCode::CONFLICT));
+            }
+        };
+
+        let flags = (O_RDWR as u32) | (O_TRUNC as u32);
+        let (handle, created) = match dav.accessor.lookup(&dav.ctx, parent.ino, name).await? {
+            Some(a) if a.kind == FileKind::Directory => {
+                return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+            }
+            Some(a) => {
+                let handle = dav.accessor.open(&dav.ctx, a.ino, flags).await?;
+                dav.accessor
+                    .fallocate(&dav.ctx, a.ino, handle.fh, 0, 0, flags)
+                    .await?;
+                (handle, false)
+            }
+            None => {
+                let handle = dav
+                    .accessor
+                    .create(&dav.ctx, parent.ino, name, 0o660)
+                    .await?;
+                (handle, true)
+            }
+        };
+
+        // The body is written as it arrives rather than being buffered
+        let ret = async {
+            let mut body = req.into_body();
+            let mut offset = 0u64;
+            while let Some(data) = body.data().await {
+                let data = data.map_err(|err| {
+                    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, err.to_string())
+                })?;
+                dav.accessor
+                    .write(&dav.ctx, handle.inode, handle.fh, offset, &data[..], flags)
+                    .await?;
+                offset += data.len() as u64;
+            }
+            dav.accessor
+                .sync(&dav.ctx, handle.inode, handle.fh, 0)
+                .await
+        }
+        .await;
+        let release = dav
+            .accessor
+            .release(&dav.ctx, handle.inode, handle.fh, flags, 0, true)
+            .await;
+        ret?;
+        release?;
+
+        Ok(status_response(match created {
+            true => StatusCode::CREATED,
+            false => StatusCode::NO_CONTENT,
+        }))
+    }
+
+    async fn delete(&self, dav: &DavRequest<'_>, req: &Request<Body>) -> Result<Response<Body>> {
+        if let Some(resp) = self.check_lock(dav, req.headers(), dav.path.as_str()) {
+            return Ok(resp);
+        }
+        let (parent, name) = match split_path(dav.path.as_str()) {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::FORBIDDEN));
+            }
+        };
+        let parent = match dav.accessor.search(&dav.ctx, parent).await? {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+        let attr = match dav.accessor.lookup(&dav.ctx, parent.ino, name).await? {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+
+        self.remove(dav, parent.ino, name, &attr).await?;
+        self.release_locks(dav, dav.path.as_str());
+        Ok(status_response(StatusCode::NO_CONTENT))
+    }
+
+    async fn mkcol(&self, dav: &DavRequest<'_>, req: &Request<Body>) -> Result<Response<Body>> {
+        if body_len(req.headers()) > 0 {
+            return Ok(status_response(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        }
+        if let Some(resp) = self.check_lock(dav, req.headers(), dav.path.as_str()) {
+            return Ok(resp);
+        }
+        let (parent, name) = match split_path(dav.path.as_str()) {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+            }
+        };
+        let parent = match dav.accessor.search(&dav.ctx, parent).await? {
+            Some(a) if a.kind == FileKind::Directory => a,
+            _ => {
+                return Ok(status_response(StatusCode::CONFLICT));
+            }
+        };
+        if dav
+            .accessor
+            .lookup(&dav.ctx, parent.ino, name)
+            .await?
+            .is_some()
+        {
+            return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+        }
+
+        dav.accessor
+            .mkdir(&dav.ctx, parent.ino, name, 0o770)
+            .await?;
+        Ok(status_response(StatusCode::CREATED))
+    }
+
+    async fn copy_or_move(
+        &self,
+        dav: &DavRequest<'_>,
+        req: &Request<Body>,
+        is_move: bool,
+    ) -> Result<Response<Body>> {
+        let headers = req.headers();
+        let dest = match header_str(Some(headers), HeaderName::from_static("destination"))
+            .and_then(|a| destination_path(a, dav.prefix))
+        {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::BAD_REQUEST));
+            }
+        };
+        let overwrite = header_str(Some(headers), HeaderName::from_static("overwrite"))
+            .map(|a| a.trim().eq_ignore_ascii_case("F") == false)
+            .unwrap_or(true);
+        let deep = header_str(Some(headers), HeaderName::from_static("depth"))
+            .map(|a| a.trim() != "0")
+            .unwrap_or(true);
+
+        // Resources can not be copied or moved onto themselves
+        let src = dav.path.trim_end_matches('/');
+        if dest == src || dest.starts_with(format!("{}/", src).as_str()) {
+            return Ok(status_response(StatusCode::FORBIDDEN));
+        }
+        if let Some(resp) = self.check_lock(dav, headers, dest.as_str()) {
+            return Ok(resp);
+        }
+        if is_move {
+            if let Some(resp) = self.check_lock(dav, headers, dav.path.as_str()) {
+                return Ok(resp);
+            }
+        }
+
+        let (src_parent, src_name) = match split_path(dav.path.as_str()) {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::FORBIDDEN));
+            }
+        };
+        let src_parent = match dav.accessor.search(&dav.ctx, src_parent).await? {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+        let attr = match dav
+            .accessor
+            .lookup(&dav.ctx, src_parent.ino, src_name)
+            .await?
+        {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::NOT_FOUND));
+            }
+        };
+
+        let (dest_parent, dest_name) = match split_path(dest.as_str()) {
+            Some(a) => a,
+            None => {
+                return Ok(status_response(StatusCode::FORBIDDEN));
+            }
+        };
+        let dest_parent = match dav.accessor.search(&dav.ctx, dest_parent).await? {
+            Some(a) if a.kind == FileKind::Directory => a,
+            _ => {
+                return Ok(status_response(StatusCode::CONFLICT));
+            }
+        };
+        let existing = dav
+            .accessor
+            .lookup(&dav.ctx, dest_parent.ino, dest_name)
+            .await?;
+        if let Some(existing) = existing.as_ref() {
+            if overwrite == false {
+                return Ok(status_response(StatusCode::PRECONDITION_FAILED));
+            }
+            self.remove(dav, dest_parent.ino, dest_name, existing)
+                .await?;
+        }
+
+        if is_move {
+            dav.accessor
+                .rename(
+                    &dav.ctx,
+                    src_parent.ino,
+                    src_name,
+                    dest_parent.ino,
+                    dest_name,
+                )
+                .await?;
+            self.release_locks(dav, dav.path.as_str());
+        } else {
+            self.copy_tree(dav, &attr, dest_parent.ino, dest_name, deep)
+                .await?;
+        }
+
+        Ok(status_response(match existing {
+            Some(_) => StatusCode::NO_CONTENT,
+            None => StatusCode::CREATED,
+        }))
+    }
+
+    async fn lock(&self, dav: &DavRequest<'_>, req: &Request<Body>) -> Result<Response<Body>> {
+        let headers = req.headers();
+        let key = dav.lock_key(dav.path.as_str());
+        let timeout = lock_timeout(headers);
+        let tokens = if_tokens(headers);
+
+        // Requests that quote the token of an existing lock refresh it
+        {
+            let mut locks = self.locks.lock().unwrap();
+            locks.retain(|_, a| a.expires > Instant::now());
+            if let Some(lock) = locks.get_mut(&key) {
+                if tokens.iter().any(|a| *a == lock.token) == false {
+                    return Ok(status_response(StatusCode::LOCKED));
+                }
+                lock.timeout = timeout;
+                lock.expires = Instant::now() + timeout;
+                let body = lock_discovery(dav, lock);
+                return Ok(lock_response(body, None, StatusCode::OK));
+            }
+            if Self::overlapping(&locks, dav.host, dav.path.as_str()).next().is_some() {
+                return Ok(status_response(StatusCode::LOCKED));
+            }
+        }
+
+        // Locking a resource that does not exist creates an empty file
+        let mut status = StatusCode::OK;
+        if dav
+            .accessor
+            .search(&dav.ctx, dav.path.as_str())
+            .await?
+            .is_none()
+        {
+            let (parent, name) = match split_path(dav.path.as_str()) {
+                Some(a) => a,
+                None => {
+                    return Ok(status_response(StatusCode::CONFLICT));
+                }
+            };
+            let parent = match dav.accessor.search(&dav.ctx, parent).await? {
+                Some(a) if a.kind == FileKind::Directory => a,
+                _ => {
+                    return Ok(status_response(StatusCode::CONFLICT));
+                }
+            };
+            dav.accessor
+                .mknod(&dav.ctx, parent.ino, name, 0o660)
+                .await?;
+            status = StatusCode::CREATED;
+        }
+
+        let hex = AteHash::generate().to_hex_string();
+        let lock = DavLock {
+            token: format!(
+                "opaquelocktoken:{}-{}-{}-{}-{}",
+                &hex[0..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..32]
+            ),
+            timeout,
+            expires: Instant::now() + timeout,
+        };
+        let body = lock_discovery(dav, &lock);
+        let token = format!("<{}>", lock.token);
+
+        let mut locks = self.locks.lock().unwrap();
+        if Self::overlapping(&locks, dav.host, dav.path.as_str()).next().is_some() {
+            return Ok(status_response(StatusCode::LOCKED));
+        }
+        if locks.len() >= MAX_LOCKS {
+            warn!("webdav lock refused as there are too many locks");
+            return Ok(status_response(StatusCode::SERVICE_UNAVAILABLE));
+        }
+        locks.insert(key, lock);
+        Ok(lock_response(body, Some(token), status))
+    }
+
+    fn unlock(&self, dav: &DavRequest<'_>, req: &Request<Body>) -> Response<Body> {
+        let token = header_str(Some(req.headers()), HeaderName::from_static("lock-token"))
+            .map(|a| a.trim().trim_start_matches('<').trim_end_matches('>'));
+        let key = dav.lock_key(dav.path.as_str());
+
+        let mut locks = self.locks.lock().unwrap();
+        match (locks.get(&key), token) {
+            (Some(lock), Some(token)) if lock.token == token => {
+                locks.remove(&key);
+                status_response(StatusCode::NO_CONTENT)
+            }
+            _ => status_response(StatusCode::CONFLICT),
+        }
+    }
+
+    /// Returns a response if the path is locked by a token that the client did not supply
+    fn check_lock(
+        &self,
+        dav: &DavRequest<'_>,
+        headers: &HeaderMap,
+        path: &str,
+    ) -> Option<Response<Body>> {
+        let tokens = if_tokens(headers);
+        let mut locks = self.locks.lock().unwrap();
+        locks.retain(|_, a| a.expires > Instant::now());
+        let mut overlapping = Self::overlapping(&locks, dav.host, path);
+        match overlapping.any(|lock| tokens.iter().any(|a| *a == lock.token) == false) {
+            true => Some(status_response(StatusCode::LOCKED)),
+            false => None,
+        }
+    }
+
+    /// Locks have an infinite depth thus a lock on a directory covers everything
+    /// within it, while a directory is held back by locks on anything within it
+    fn overlapping<'b>(
+        locks: &'b FxHashMap<String, DavLock>,
+        host: &'b str,
+        path: &'b str,
+    ) -> impl Iterator<Item = &'b DavLock> + 'b {
+        // (the keys of the locks are the host followed by the path)
+        locks
+            .iter()
+            .filter_map(move |(key, lock)| Some((key.strip_prefix(host)?.strip_prefix(':')?, lock)))
+            .filter(move |(locked, _)| path_within(path, locked) || path_within(locked, path))
+            .map(|(_, lock)| lock)
+    }
+
+    fn release_locks(&self, dav: &DavRequest<'_>, path: &str) {
+        let key = dav.lock_key(path);
+        let children = format!("{}/", key.trim_end_matches('/'));
+        self.locks
+            .lock()
+            .unwrap()
+            .retain(|k, _| *k != key && k.starts_with(children.as_str()) == false);
+    }
+
+    /// Removes a file or directory (including everything within it)
+    async fn remove(
+        &self,
+        dav: &DavRequest<'_>,
+        parent: u64,
+        name: &str,
+        attr: &FileAttr,
+    ) -> Result<()> {
+        // Directories are listed before their contents thus removing them in
+        // reverse order deletes the deepest entries first
+        let mut removals = vec![(parent, name.to_string(), attr.ino, attr.kind)];
+        let mut n = 0usize;
+        while n < removals.len() {
+            let (_, _, ino, kind) = removals[n].clone();
+            n += 1;
+            if kind != FileKind::Directory {
+                continue;
+            }
+            let open = dav
+                .accessor
+                .create_open_handle(ino, &dav.ctx, O_RDONLY)
+                .await?;
+            for child in open.children.iter() {
+                if child.name == "." || child.name == ".." {
+                    continue;
+                }
+                removals.push((ino, child.name.clone(), child.inode, child.kind));
+            }
+        }
+
+        for (parent, name, _, kind) in removals.into_iter().rev() {
+            match kind {
+                FileKind::Directory => dav.accessor.rmdir(&dav.ctx, parent, name.as_str()).await?,
+                _ => dav.accessor.unlink(&dav.ctx, parent, name.as_str()).await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn copy_tree(
+        &self,
+        dav: &DavRequest<'_>,
+        attr: &FileAttr,
+        parent: u64,
+        name: &str,
+        deep: bool,
+    ) -> Result<()> {
+        let mut stack = vec![(attr.ino, attr.kind, attr.mode, parent, name.to_string())];
+        while let Some((ino, kind, mode, parent, name)) = stack.pop() {
+            match kind {
+                FileKind::Directory => {
+                    let dir = dav
+                        .accessor
+                        .mkdir(&dav.ctx, parent, name.as_str(), mode & 0o7777)
+                        .await?;
+                    if deep == false {
+                        continue;
+                    }
+                    let open = dav
+                        .accessor
+                        .create_open_handle(ino, &dav.ctx, O_RDONLY)
+                        .await?;
+                    for child in open.children.iter() {
+                        if child.name == "." || child.name == ".." {
+                            continue;
+                        }
+                        stack.push((
+                            child.inode,
+                            child.kind,
+                            child.attr.mode,
+                            dir.ino,
+                            child.name.clone(),
+                        ));
+                    }
+                }
+                FileKind::SymLink => {
+                    let open = dav
+                        .accessor
+                        .create_open_handle(ino, &dav.ctx, O_RDONLY)
+                        .await?;
+                    if let Some(link) = open.spec.link() {
+                        dav.accessor
+                            .symlink(&dav.ctx, parent, name.as_str(), link.as_str())
+                            .await?;
+                    }
+                }
+                FileKind::RegularFile => {
+                    self.copy_file(dav, ino, mode & 0o7777, parent, name.as_str())
+                        .await?;
+                }
+                FileKind::FixedFile => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn copy_file(
+        &self,
+        dav: &DavRequest<'_>,
+        ino: u64,
+        mode: u32,
+        parent: u64,
+        name: &str,
+    ) -> Result<()> {
+        let src = dav
+            .accessor
+            .create_open_handle(ino, &dav.ctx, O_RDONLY)
+            .await?;
+        let dst = dav.accessor.create(&dav.ctx, parent, name, mode).await?;
+        let flags = O_RDWR as u32;
+
+        let ret = async {
+            let size = src.spec.size();
+            let mut offset = 0u64;
+            while offset < size {
+                let data = src.spec.read(offset, PAGE_SIZE as u64).await?;
+                if data.len() <= 0 {
+                    break;
+                }
+                dav.accessor
+                    .write(&dav.ctx, dst.inode, dst.fh, offset, &data[..], flags)
+                    .await?;
+                offset += data.len() as u64;
+            }
+            Ok::<(), FileSystemError>(())
+        }
+        .await;
+        let release = dav
+            .accessor
+            .release(&dav.ctx, dst.inode, dst.fh, flags, 0, true)
+            .await;
+        ret?;
+        release
+    }
+}
+
+fn error_status(err: &FileSystemError) -> StatusCode {
+    match err {
+        FileSystemError(FileSystemErrorKind::NoAccess, _)
+        | FileSystemError(FileSystemErrorKind::PermissionDenied, _)
+        | FileSystemError(FileSystemErrorKind::ReadOnly, _) => StatusCode::FORBIDDEN,
+        FileSystemError(FileSystemErrorKind::DoesNotExist, _)
+        | FileSystemError(FileSystemErrorKind::NoEntry, _) => StatusCode::NOT_FOUND,
+        FileSystemError(FileSystemErrorKind::NotDirectory, _)
+        | FileSystemError(FileSystemErrorKind::AlreadyExists, _) => StatusCode::CONFLICT,
+        FileSystemError(FileSystemErrorKind::IsDirectory, _) => StatusCode::METHOD_NOT_ALLOWED,
+        FileSystemError(FileSystemErrorKind::InvalidArguments, _) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::from(status.as_str().to_string()));
+    *resp.status_mut() = status;
+    resp
+}
+
+fn unauthorized_response(host: &str) -> Response<Body> {
+    let mut resp = status_response(StatusCode::UNAUTHORIZED);
+    let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", host);
+    if let Ok(challenge) = HeaderValue::from_str(challenge.as_str()) {
+        resp.headers_mut().append(WWW_AUTHENTICATE, challenge);
+    }
+    resp
+}
+
+fn multistatus_response(xml: String) -> Response<Body> {
+    let mut resp = Response::new(Body::from(xml));
+    resp.headers_mut().append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/xml; charset=utf-8"),
+    );
+    *resp.status_mut() = StatusCode::MULTI_STATUS;
+    resp
+}
+
+fn lock_response(xml: String, token: Option<String>, status: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::from(xml));
+    let headers = resp.headers_mut();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/xml; charset=utf-8"),
+    );
+    if let Some(token) = token {
+        append_header(
+            headers,
+            HeaderName::from_static("lock-token"),
+            token.as_str(),
+        );
+    }
+    *resp.status_mut() = status;
+    resp
+}
+
+fn append_header(headers: &mut HeaderMap, name: HeaderName, val: &str) {
+    if let Ok(val) = HeaderValue::from_str(val) {
+        headers.append(name, val);
+    }
+}
+
+fn lock_discovery(dav: &DavRequest<'_>, lock: &DavLock) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<D:prop xmlns:D=\"DAV:\"><D:lockdiscovery><D:activelock>");
+    xml.push_str("<D:locktype><D:write/></D:locktype>");
+    xml.push_str("<D:lockscope><D:exclusive/></D:lockscope>");
+    xml.push_str("<D:depth>infinity</D:depth>");
+    let _ = write!(
+        xml,
+        "<D:timeout>Second-{}</D:timeout>",
+        lock.timeout.as_secs()
+    );
+    let _ = write!(
+        xml,
+        "<D:locktoken><D:href>{}</D:href></D:locktoken>",
+        xml_escape(lock.token.as_str())
+    );
+    let _ = write!(
+        xml,
+        "<D:lockroot><D:href>{}</D:href></D:lockroot>",
+        xml_escape(dav.href(dav.path.as_str(), false).as_str())
+    );
+    xml.push_str("</D:activelock></D:lockdiscovery></D:prop>\n");
+    xml
+}
+
+fn append_response(xml: &mut String, href: &str, name: &str, attr: &FileAttr) {
+    let is_dir = attr.kind == FileKind::Directory;
+    let created = chrono::Utc
+        .timestamp_millis(attr.created as i64)
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    xml.push_str("<D:response>");
+    let _ = write!(xml, "<D:href>{}</D:href>", xml_escape(href));
+    xml.push_str("<D:propstat><D:prop>");
+    let _ = write!(xml, "<D:displayname>{}</D:displayname>", xml_escape(name));
+    match is_dir {
+        true => xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>"),
+        false => {
+            xml.push_str("<D:resourcetype/>");
+            let _ = write!(
+                xml,
+                "<D:getcontentlength>{}</D:getcontentlength>",
+                attr.size
+            );
+            let _ = write!(
+                xml,
+                "<D:getcontenttype>{}</D:getcontenttype>",
+                xml_escape(content_type(name))
+            );
+        }
+    }
+    let _ = write!(xml, "<D:creationdate>{}</D:creationdate>", created);
+    let _ = write!(
+        xml,
+        "<D:getlastmodified>{}</D:getlastmodified>",
+        http_date(attr.updated)
+    );
+    let _ = write!(
+        xml,
+        "<D:getetag>\"{:x}-{:x}-{:x}\"</D:getetag>",
+        attr.ino, attr.updated, attr.size
+    );
+    xml.push_str("<D:supportedlock><D:lockentry><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry></D:supportedlock>");
+    xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n");
+}
+
+fn content_type(path: &str) -> &'static str {
+    mime_guess::from_path(path)
+        .first_raw()
+        .unwrap_or("application/octet-stream")
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let val = header_str(Some(headers), hyper::header::AUTHORIZATION)?;
+    let (scheme, val) = val.trim().split_once(" ")?;
+    if scheme.eq_ignore_ascii_case("Basic") == false {
+        return None;
+    }
+    let val = base64::decode(val.trim()).ok()?;
+    let val = String::from_utf8(val).ok()?;
+    let (username, password) = val.split_once(":")?;
+    Some((username.to_string(), password.to_string()))
+}
+
+fn body_len(headers: &HeaderMap) -> u64 {
+    header_str(Some(headers), CONTENT_LENGTH)
+        .and_then(|a| a.trim().parse::<u64>().ok())
+        .unwrap_or(0)
+}
+
+fn lock_timeout(headers: &HeaderMap) -> Duration {
+    header_str(Some(headers), HeaderName::from_static("timeout"))
+        .and_then(|a| {
+            a.split(",")
+                .filter_map(|a| a.trim().strip_prefix("Second-"))
+                .filter_map(|a| a.parse::<u64>().ok())
+                .next()
+        })
+        .map(|a| Duration::from_secs(a).min(LOCK_TIMEOUT_MAX))
+        .unwrap_or(LOCK_TIMEOUT)
+}
+
+/// Returns all the lock tokens referenced by the If header of a request
+fn if_tokens(headers: &HeaderMap) -> Vec<String> {
+    let mut ret = Vec::new();
+    if let Some(val) = header_str(Some(headers), HeaderName::from_static("if")) {
+        for part in val.split("<").skip(1) {
+            if let Some((token, _)) = part.split_once(">") {
+                if token.starts_with("opaquelocktoken:") {
+                    ret.push(token.to_string());
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Converts the path of a request into a path within the files chain
+fn dav_path(path: &str, prefix: &str) -> Option<String> {
+    let path = path.strip_prefix(prefix.trim_end_matches('/'))?;
+    let path = url_decode(path)?;
+    let mut ret = String::new();
+    for comp in path.split("/") {
+        match comp {
+            "" | "." => continue,
+            ".." => {
+                return None;
+            }
+            comp => {
+                ret.push('/');
+                ret.push_str(comp);
+            }
+        }
+    }
+    if ret.len() <= 0 {
+        ret.push('/');
+    }
+    Some(ret)
+}
+
+/// Destination header is either an absolute URL or an absolute path
+fn destination_path(dest: &str, prefix: &str) -> Option<String> {
+    let dest = dest.trim();
+    let path = match dest.parse::<hyper::Uri>() {
+        Ok(uri) if uri.scheme().is_some() => uri.path().to_string(),
+        _ => dest.to_string(),
+    };
+    dav_path(path.as_str(), prefix)
+}
+
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once("/")?;
+    if name.len() <= 0 {
+        return None;
+    }
+    match parent.len() {
+        0 => Some(("/", name)),
+        _ => Some((parent, name)),
+    }
+}
+
+/// Determines if a path is the same as or falls under another path
+fn path_within(path: &str, parent: &str) -> bool {
+    let parent = parent.trim_end_matches('/');
+    match path.strip_prefix(parent) {
+        Some(rest) => rest.len() <= 0 || rest.starts_with("/"),
+        None => false,
+    }
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    format!("{}/{}", parent.trim_end_matches('/'), name)
+}
+
+fn file_name(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit("/")
+        .next()
+        .unwrap_or_default()
+}
+
+fn url_decode(val: &str) -> Option<String> {
+    let bytes = val.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut n = 0usize;
+    while n < bytes.len() {
+        match bytes[n] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(n + 1..n + 3)?).ok()?;
+                ret.push(u8::from_str_radix(hex, 16).ok()?);
+                n += 3;
+            }
+            b => {
+                ret.push(b);
+                n += 1;
+            }
+        }
+    }
+    String::from_utf8(ret).ok()
+}
+
+fn url_encode(val: &str) -> String {
+    let mut ret = String::with_capacity(val.len());
+    for b in val.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                ret.push(b as char)
+            }
+            b => {
+                let _ = write!(ret, "%{:02X}", b);
+            }
+        }
+    }
+    ret
+}
+
+fn xml_escape(val: &str) -> String {
+    val.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_webdav(name: &str) -> (WebDav, Arc<FileAccessor>) {
+        ate::utils::bootstrap_test_env();
+
+        let mut conf = ConfAte::default();
+        conf.configured_for(ConfiguredFor::BestPerformance);
+        let builder = ChainBuilder::new(&conf).await.build();
+        let chain = builder
+            .open(&ChainKey::default().with_temp_name(name.to_string()))
+            .await
+            .unwrap();
+        let accessor = Arc::new(
+            FileAccessor::new(
+                chain,
+                None,
+                AteSessionType::User(AteSessionUser::new()),
+                TransactionScope::Local,
+                TransactionScope::Local,
+                true,
+                false,
+            )
+            .await,
+        );
+        accessor.init(&accessor.session_context()).await.unwrap();
+
+        let registry = Arc::new(Registry::new(&conf).await);
+        let webdav = WebDav::new(
+            &registry,
+            Url::parse("ws://localhost/db").unwrap(),
+            Url::parse("ws://localhost/auth").unwrap(),
+            Duration::from_secs(60),
+        );
+        (webdav, accessor)
+    }
+
+    async fn send(
+        webdav: &WebDav,
+        accessor: &Arc<FileAccessor>,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> (StatusCode, HeaderMap, String) {
+        let mut req = Request::builder().method(method).uri(format!("/.dav{}", path));
+        for (name, val) in headers {
+            req = req.header(*name, *val);
+        }
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        let dav = DavRequest {
+            accessor: Arc::clone(accessor),
+            ctx: accessor.session_context(),
+            host: "example.com",
+            prefix: "/.dav",
+            path: dav_path(req.uri().path(), "/.dav").unwrap(),
+        };
+
+        let resp = webdav.dispatch(&dav, req).await.unwrap();
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn propfind_lists_a_directory() {
+        let (webdav, accessor) = create_webdav("webdav-propfind").await;
+        let (status, _, _) = send(&webdav, &accessor, "MKCOL", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _, _) = send(&webdav, &accessor, "PUT", "/docs/a%20b.txt", &[], "hello").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _, xml) =
+            send(&webdav, &accessor, "PROPFIND", "/docs", &[("Depth", "1")], "").await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(xml.contains("<D:href>/.dav/docs/</D:href>"));
+        assert!(xml.contains("<D:href>/.dav/docs/a%20b.txt</D:href>"));
+        assert!(xml.contains("<D:displayname>a b.txt</D:displayname>"));
+        assert!(xml.contains("<D:getcontentlength>5</D:getcontentlength>"));
+
+        // A depth of zero only describes the directory itself
+        let (_, _, xml) =
+            send(&webdav, &accessor, "PROPFIND", "/docs", &[("Depth", "0")], "").await;
+        assert_eq!(xml.matches("<D:response>").count(), 1);
+
+        let (status, _, _) = send(&webdav, &accessor, "PROPFIND", "/missing", &[], "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn locks_block_other_clients() {
+        let (webdav, accessor) = create_webdav("webdav-lock").await;
+
+        // Locking a missing file creates it
+        let (status, headers, xml) = send(&webdav, &accessor, "LOCK", "/notes.txt", &[], "").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(xml.contains("<D:depth>infinity</D:depth>"));
+        let token = headers.get("lock-token").unwrap().to_str().unwrap().to_string();
+        let with_token = format!("({})", token);
+
+        let (status, _, _) = send(&webdav, &accessor, "PUT", "/notes.txt", &[], "other").await;
+        assert_eq!(status, StatusCode::LOCKED);
+        let (status, _, _) = send(&webdav, &accessor, "LOCK", "/notes.txt", &[], "").await;
+        assert_eq!(status, StatusCode::LOCKED);
+        let (status, _, _) =
+            send(&webdav, &accessor, "PUT", "/notes.txt", &[("If", with_token.as_str())], "mine").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // Quoting the token refreshes the lock rather than conflicting with it
+        let (status, _, _) =
+            send(&webdav, &accessor, "LOCK", "/notes.txt", &[("If", with_token.as_str())], "").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _, _) =
+            send(&webdav, &accessor, "UNLOCK", "/notes.txt", &[("Lock-Token", "<opaquelocktoken:x>")], "").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _, _) =
+            send(&webdav, &accessor, "UNLOCK", "/notes.txt", &[("Lock-Token", token.as_str())], "").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = send(&webdav, &accessor, "PUT", "/notes.txt", &[], "other").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn directory_locks_cover_their_contents() {
+        let (webdav, accessor) = create_webdav("webdav-lock-depth").await;
+        send(&webdav, &accessor, "MKCOL", "/docs", &[], "").await;
+        send(&webdav, &accessor, "MKCOL", "/docs/inner", &[], "").await;
+
+        let (status, headers, _) = send(&webdav, &accessor, "LOCK", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::OK);
+        let token = headers.get("lock-token").unwrap().to_str().unwrap().to_string();
+        let with_token = format!("({})", token);
+
+        // Everything within the locked directory is locked with it
+        let (status, _, _) = send(&webdav, &accessor, "PUT", "/docs/inner/a.txt", &[], "a").await;
+        assert_eq!(status, StatusCode::LOCKED);
+        let (status, _, _) = send(&webdav, &accessor, "MKCOL", "/docs/other", &[], "").await;
+        assert_eq!(status, StatusCode::LOCKED);
+        let (status, _, _) = send(&webdav, &accessor, "LOCK", "/docs/inner", &[], "").await;
+        assert_eq!(status, StatusCode::LOCKED);
+        let (status, _, _) =
+            send(&webdav, &accessor, "PUT", "/docs/inner/a.txt", &[("If", with_token.as_str())], "a").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // ...and a locked file holds back the directories above it
+        send(&webdav, &accessor, "UNLOCK", "/docs", &[("Lock-Token", token.as_str())], "").await;
+        let (status, _, _) = send(&webdav, &accessor, "LOCK", "/docs/inner/a.txt", &[], "").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _, _) = send(&webdav, &accessor, "LOCK", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::LOCKED);
+        let (status, _, _) = send(&webdav, &accessor, "DELETE", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::LOCKED);
+
+        assert!(accessor.search(&accessor.session_context(), "/docs").await.unwrap().is_some());
+        assert_eq!(path_within("/docs/inner", "/docs"), true);
+        assert_eq!(path_within("/docsx", "/docs"), false);
+        assert_eq!(path_within("/docs", "/"), true);
+    }
+}
@@ -0,0 +1,277 @@
+//! Exercises the RFC 2136 DNS provider against a local DNS server that
+//! accepts dynamic updates, for instance BIND with a zone configured as
+//!
+//! ```text
+//! key "acme" { algorithm hmac-sha256; secret "<base64 secret>"; };
+//! zone "example.test" { type master; file "example.test.zone";
+//!                       update-policy { grant acme zonesub TXT; }; };
+//! ```
+//!
+//! and then run with
+//!
+//! ```text
+//! RFC2136_SERVER=127.0.0.1:53 RFC2136_ZONE=example.test \
+//! RFC2136_KEY_NAME=acme RFC2136_KEY_SECRET=<base64 secret> \
+//!     cargo test -p ateweb --test rfc2136 -- --ignored
+//! ```
+//!
+//! The remaining tests run against a small in-process server that keeps the
+//! TXT records of a single zone in memory.
+use fxhash::FxHashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+
+use ateweb::acme::*;
+use ateweb::error::*;
+
+const ZONE: &'static str = "example.test";
+const RCODE_REFUSED: u8 = 5;
+const RCODE_NOTAUTH: u8 = 9;
+
+type Records = Arc<Mutex<FxHashMap<String, Vec<String>>>>;
+
+fn read_name(buf: &[u8], mut pos: usize) -> (String, usize) {
+    let mut labels = Vec::new();
+    loop {
+        let len = buf[pos] as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        labels.push(String::from_utf8_lossy(&buf[pos..pos + len]).to_lowercase());
+        pos += len;
+    }
+    (labels.join("."), pos)
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([buf[pos], buf[pos + 1]])
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+}
+
+/// Answers TXT queries and applies signed dynamic updates to the records
+fn handle(records: &Records, msg: &[u8]) -> Vec<u8> {
+    let opcode = (read_u16(msg, 2) >> 11) & 0x0F;
+    let mut resp = msg[0..2].to_vec();
+    let mut answers = Vec::new();
+    let mut rcode = 0u8;
+
+    if opcode == 0 {
+        // Query (the question is echoed back ahead of the answers)
+        let (name, pos) = read_name(msg, 12);
+        let question = &msg[12..pos + 4];
+        let values = records.lock().unwrap().get(&name).cloned().unwrap_or_default();
+        let mut body = question.to_vec();
+        for value in values.iter() {
+            write_name(&mut body, name.as_str());
+            body.extend_from_slice(&16u16.to_be_bytes());
+            body.extend_from_slice(&1u16.to_be_bytes());
+            body.extend_from_slice(&60u32.to_be_bytes());
+            let mut rdata = Vec::new();
+            for chunk in value.as_bytes().chunks(255) {
+                rdata.push(chunk.len() as u8);
+                rdata.extend_from_slice(chunk);
+            }
+            body.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            body.extend_from_slice(&rdata[..]);
+        }
+        answers.push((1u16, values.len() as u16, body));
+    } else {
+        // Update (only signed updates within the zone are accepted)
+        let (zone, mut pos) = read_name(msg, 12);
+        pos += 4;
+        let signed = read_u16(msg, 10) == 1;
+        let mut changes = Vec::new();
+        for _ in 0..read_u16(msg, 8) {
+            let (name, next) = read_name(msg, pos);
+            let class = read_u16(msg, next + 2);
+            let len = read_u16(msg, next + 8) as usize;
+            let rdata = &msg[next + 10..next + 10 + len];
+            pos = next + 10 + len;
+
+            let mut value = Vec::new();
+            let mut n = 0usize;
+            while n < rdata.len() {
+                let len = rdata[n] as usize;
+                value.extend_from_slice(&rdata[n + 1..n + 1 + len]);
+                n += 1 + len;
+            }
+            changes.push((name, class, String::from_utf8_lossy(&value[..]).to_string()));
+        }
+
+        if signed == false {
+            rcode = RCODE_NOTAUTH;
+        } else if zone != ZONE || changes.iter().any(|a| a.0.ends_with(ZONE) == false) {
+            rcode = RCODE_REFUSED;
+        } else {
+            let mut records = records.lock().unwrap();
+            for (name, class, value) in changes {
+                let values = records.entry(name).or_default();
+                match class {
+                    1 => values.push(value),
+                    _ => values.retain(|a| *a != value),
+                }
+            }
+        }
+    }
+
+    resp.extend_from_slice(&(0x8000u16 | (opcode << 11) | rcode as u16).to_be_bytes());
+    match answers.pop() {
+        Some((questions, count, body)) => {
+            resp.extend_from_slice(&questions.to_be_bytes());
+            resp.extend_from_slice(&count.to_be_bytes());
+            resp.extend_from_slice(&[0u8; 4]);
+            resp.extend_from_slice(&body[..]);
+        }
+        None => resp.extend_from_slice(&[0u8; 8]),
+    }
+    resp
+}
+
+async fn fake_server() -> (SocketAddr, Records) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let records = Records::default();
+    {
+        let records = records.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf[..]).await {
+                let resp = handle(&records, &buf[..n]);
+                let _ = socket.send_to(&resp[..], peer).await;
+            }
+        });
+    }
+    (addr, records)
+}
+
+fn test_key() -> TsigKey {
+    TsigKey::from_base64("acme", TsigAlgorithm::HmacSha256, "c2VjcmV0LWZvci10ZXN0cw==").unwrap()
+}
+
+#[tokio::test]
+async fn set_and_remove_txt() {
+    let (addr, records) = fake_server().await;
+    let provider = Rfc2136Provider::new(addr, ZONE).with_tsig(test_key());
+    let name = dns_01_record_name("www.example.test");
+
+    provider.set_txt(name.as_str(), "first").await.unwrap();
+    provider.set_txt(name.as_str(), "second").await.unwrap();
+    provider.wait_for_txt(name.as_str(), "second").await.unwrap();
+    assert_eq!(
+        provider.query_txt(name.as_str()).await.unwrap(),
+        vec!["first".to_string(), "second".to_string()]
+    );
+
+    provider.remove_txt(name.as_str(), "first").await.unwrap();
+    assert_eq!(
+        provider.query_txt(name.as_str()).await.unwrap(),
+        vec!["second".to_string()]
+    );
+    assert_eq!(
+        records.lock().unwrap().get(&name).cloned(),
+        Some(vec!["second".to_string()])
+    );
+
+    // Values longer than a single character string are split and rejoined
+    let long = "x".repeat(600);
+    provider.set_txt(name.as_str(), long.as_str()).await.unwrap();
+    assert!(provider.query_txt(name.as_str()).await.unwrap().contains(&long));
+}
+
+#[tokio::test]
+async fn refused_updates_are_errors() {
+    let (addr, _) = fake_server().await;
+    let name = dns_01_record_name("www.example.test");
+
+    let unsigned = Rfc2136Provider::new(addr, ZONE);
+    match unsigned.set_txt(name.as_str(), "value").await {
+        Err(DnsError(DnsErrorKind::ResponseCode(rcode), _)) => assert_eq!(rcode, RCODE_NOTAUTH),
+        ret => panic!("unexpected result - {:?}", ret),
+    }
+
+    let other = Rfc2136Provider::new(addr, "other.test").with_tsig(test_key());
+    match other.set_txt("_acme-challenge.other.test", "value").await {
+        Err(DnsError(DnsErrorKind::ResponseCode(rcode), _)) => assert_eq!(rcode, RCODE_REFUSED),
+        ret => panic!("unexpected result - {:?}", ret),
+    }
+}
+
+fn provider() -> Rfc2136Provider {
+    let server: SocketAddr = std::env::var("RFC2136_SERVER")
+        .expect("RFC2136_SERVER must be set")
+        .parse()
+        .expect("RFC2136_SERVER must be an address");
+    let zone = std::env::var("RFC2136_ZONE").expect("RFC2136_ZONE must be set");
+
+    let mut ret = Rfc2136Provider::new(server, zone.as_str());
+    if let (Ok(name), Ok(secret)) = (
+        std::env::var("RFC2136_KEY_NAME"),
+        std::env::var("RFC2136_KEY_SECRET"),
+    ) {
+        let key = TsigKey::from_base64(name.as_str(), TsigAlgorithm::HmacSha256, secret.as_str())
+            .expect("RFC2136_KEY_SECRET must be base64");
+        ret = ret.with_tsig(key);
+    }
+    ret
+}
+
+#[tokio::test]
+#[ignore]
+async fn rfc2136_set_and_remove_txt() {
+    let provider = provider();
+    let domain = format!("www.{}", provider.zone);
+    assert!(provider.handles(domain.as_str()));
+    assert!(provider.handles(format!("*.{}", provider.zone).as_str()));
+    assert!(provider.handles("example.invalid") == false);
+
+    let name = dns_01_record_name(domain.as_str());
+    let value = format!("test-{}", fastrand::u64(..));
+
+    provider
+        .set_txt(name.as_str(), value.as_str())
+        .await
+        .unwrap();
+    provider
+        .wait_for_txt(name.as_str(), value.as_str())
+        .await
+        .unwrap();
+    let values = provider.query_txt(name.as_str()).await.unwrap();
+    assert!(values.contains(&value));
+
+    provider
+        .remove_txt(name.as_str(), value.as_str())
+        .await
+        .unwrap();
+    let values = provider.query_txt(name.as_str()).await.unwrap();
+    assert!(values.contains(&value) == false);
+}
+
+#[tokio::test]
+#[ignore]
+async fn rfc2136_long_txt_values() {
+    let provider = provider();
+    let name = dns_01_record_name(format!("long.{}", provider.zone).as_str());
+
+    // Values longer than a single character string are split and rejoined
+    let value = "x".repeat(300);
+    provider
+        .set_txt(name.as_str(), value.as_str())
+        .await
+        .unwrap();
+    let values = provider.query_txt(name.as_str()).await.unwrap();
+    provider
+        .remove_txt(name.as_str(), value.as_str())
+        .await
+        .unwrap();
+    assert!(values.contains(&value));
+}
@@ -184,21 +184,21 @@ for MyFile {
         FsResult::Ok(())
     }
 
-    async fn write(&self, _data: Vec<u8>) -> FsResult<u64> {
+    async fn write(&self, _data: ByteStream) -> FsResult<u64> {
         FsResult::Err(FsError::PermissionDenied)
     }
 
-    async fn read(&self, len: u64) -> FsResult<Vec<u8>> {
+    async fn read(&self, len: u64) -> FsResult<ByteStream> {
         let buf = README.as_bytes();
         let pos = self.pos.load(Ordering::Acquire) as usize;
         if pos >= buf.len() {
-            FsResult::Ok(Vec::new())
+            FsResult::Ok(ByteStream::from(Vec::new()))
         } else {
             let mut pos_end = pos + (len as usize);
             if pos_end > buf.len() {
                 pos_end = buf.len();
             }
-            FsResult::Ok(README.as_bytes()[pos..pos_end].to_vec())
+            FsResult::Ok(ByteStream::from(&README.as_bytes()[pos..pos_end]))
         }
     }
 }
@@ -25,6 +25,8 @@ use super::error::*;
 use super::handle::*;
 use super::model::*;
 use super::prelude::*;
+use super::snapshot::*;
+use super::version::*;
 
 use fxhash::FxHashMap;
 
@@ -48,6 +50,10 @@ where
     pub impersonate_uid: bool,
     pub force_sudo: bool,
     pub init_flag: AsyncMutex<bool>,
+    pub snapshot_views: Mutex<FxHashMap<String, Arc<SnapshotView>>>,
+    pub snapshot_nodes: Mutex<cached::SizedCache<u64, SnapshotNode>>,
+    pub version_views: Mutex<cached::SizedCache<u64, Arc<SnapshotView>>>,
+    pub version_nodes: Mutex<cached::SizedCache<u64, VersionNode>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -94,6 +100,10 @@ impl FileAccessor {
             impersonate_uid,
             force_sudo: false,
             init_flag: AsyncMutex::new(false),
+            snapshot_views: Mutex::new(FxHashMap::default()),
+            snapshot_nodes: Mutex::new(cached::SizedCache::with_size(MAX_SNAPSHOT_NODES)),
+            version_views: Mutex::new(cached::SizedCache::with_size(MAX_VERSION_VIEWS)),
+            version_nodes: Mutex::new(cached::SizedCache::with_size(MAX_VERSION_NODES)),
         }
     }
 
@@ -242,6 +252,13 @@ impl FileAccessor {
         req: &RequestContext,
         flags: i32,
     ) -> Result<OpenHandle> {
+        if self.is_snapshot_ino(inode) {
+            return self.create_snapshot_handle(inode, req, flags).await;
+        }
+        if self.is_version_ino(inode) {
+            return self.create_version_handle(inode, req, flags).await;
+        }
+
         let mut writable = false;
         if flags & O_TRUNC != 0 || flags & O_RDWR != 0 || flags & O_WRONLY != 0 {
             self.access_internal(&req, inode, 0o2).await?;
@@ -367,6 +384,8 @@ impl FileAccessor {
         name: &str,
         mode: u32,
     ) -> Result<DaoMut<Inode>> {
+        self.check_not_snapshot(parent)?;
+
         let key = PrimaryKey::from(parent);
         let dio = self.dio_mut_meta().await;
         let mut data = dio.load::<Inode>(&key).await?;
@@ -582,6 +601,13 @@ impl FileAccessor {
         self.tick().await?;
         trace!("access inode={} mask={:#02x}", inode, mask);
 
+        if self.is_snapshot_ino(inode) || self.is_version_ino(inode) {
+            if mask & 0o2 != 0 {
+                bail!(FileSystemErrorKind::ReadOnly);
+            }
+            return Ok(());
+        }
+
         let dao = self.load(inode).await?;
         if (dao.dentry.mode & mask) != 0 {
             trace!("access mode={:#02x} - ok", dao.dentry.mode);
@@ -639,8 +665,14 @@ impl FileAccessor {
             }
         }
 
-        let dao = self.load(inode).await?;
-        let spec = Inode::as_file_spec(inode, dao.when_created(), dao.when_updated(), dao).await;
+        let spec = if self.is_snapshot_ino(inode) {
+            self.snapshot_spec(inode).await?
+        } else if self.is_version_ino(inode) {
+            self.version_spec(inode).await?
+        } else {
+            let dao = self.load(inode).await?;
+            Inode::as_file_spec(inode, dao.when_created(), dao.when_updated(), dao).await
+        };
         Ok(match self.impersonate_uid {
             true => self.spec_as_attr_reverse(&spec, &req),
             false => FileAttr::new(&spec, spec.uid(), spec.gid()),
@@ -656,6 +688,7 @@ impl FileAccessor {
     ) -> Result<FileAttr> {
         self.tick().await?;
         trace!("setattr inode={}", inode);
+        self.check_not_snapshot(inode)?;
 
         let key = PrimaryKey::from(inode);
         let dio = self.dio_mut_meta().await;
@@ -751,6 +784,10 @@ impl FileAccessor {
         name: &str,
     ) -> Result<Option<FileAttr>> {
         self.tick().await?;
+        if parent == 1u64 && name == SNAPSHOTS_DIR {
+            let spec = self.snapshot_spec(SNAPSHOTS_INO).await?;
+            return Ok(Some(self.spec_as_attr_reverse(&spec, req)));
+        }
         let open = self.create_open_handle(parent, req, O_RDONLY).await?;
 
         if open.attr.kind != FileKind::Directory {
@@ -768,6 +805,15 @@ impl FileAccessor {
             return Ok(Some(entry.attr.clone()));
         }
 
+        // The history of the files is only exposed when no real entry has the name
+        if name == VERSIONS_DIR
+            && self.is_snapshot_ino(parent) == false
+            && self.is_version_ino(parent) == false
+        {
+            let spec = self.lookup_versions_dir(parent).await?;
+            return Ok(Some(self.spec_as_attr_reverse(&spec, req)));
+        }
+
         debug!("wasmer-dfs::lookup parent={} name={}: not found", parent, name);
         Ok(None)
     }
@@ -899,6 +945,7 @@ impl FileAccessor {
     ) -> Result<FileAttr> {
         self.tick().await?;
         debug!("wasmer-dfs::mkdir parent={}", parent);
+        self.check_not_snapshot(parent)?;
 
         let dio = self.dio.trans(self.scope_meta).await;
         let mut data = dio.load::<Inode>(&PrimaryKey::from(parent)).await?;
@@ -928,6 +975,7 @@ impl FileAccessor {
     pub async fn rmdir(&self, req: &RequestContext, parent: u64, name: &str) -> Result<()> {
         self.tick().await?;
         debug!("wasmer-dfs::rmdir parent={}", parent);
+        self.check_not_snapshot(parent)?;
 
         let open = self.create_open_handle(parent, req, O_RDONLY).await?;
 
@@ -1029,6 +1077,7 @@ impl FileAccessor {
     pub async fn unlink(&self, _req: &RequestContext, parent: u64, name: &str) -> Result<()> {
         self.tick().await?;
         debug!("wasmer-dfs::unlink parent={} name={}", parent, name);
+        self.check_not_snapshot(parent)?;
 
         let parent_key = PrimaryKey::from(parent);
 
@@ -1073,6 +1122,8 @@ impl FileAccessor {
     ) -> Result<()> {
         self.tick().await?;
         debug!("wasmer-dfs::rename name={} new_name={}", name, new_name);
+        self.check_not_snapshot(parent)?;
+        self.check_not_snapshot(new_parent)?;
 
         let mut parent_data = self.load_mut(parent).await?;
         if parent_data.kind != FileKind::Directory {
@@ -1303,6 +1354,7 @@ impl FileAccessor {
             }
         }
 
+        self.check_not_snapshot(inode)?;
         let mut dao = self.load_mut(inode).await?;
         dao.as_mut().size = offset + length;
         dao.trans().commit().await?;
@@ -1386,6 +1438,17 @@ impl FileAccessor {
     ) -> Result<()> {
         self.tick().await?;
 
+        if name == RESTORE_VERSION_XATTR {
+            let version = match value.trim().parse::<u64>() {
+                Ok(a) => a,
+                Err(_) => {
+                    bail!(FileSystemErrorKind::InvalidArguments);
+                }
+            };
+            let version = self.get_version(req, inode, version).await?;
+            return self.restore_version(req, inode, version.at).await;
+        }
+
         let flags = O_RDWR;
         let mut open = self.create_open_handle(inode, &req, flags).await?;
         open.spec.set_xattr(name, value).await
@@ -1409,6 +1472,10 @@ impl FileAccessor {
     ) -> Result<Option<String>> {
         self.tick().await?;
 
+        if name == VERSIONS_XATTR {
+            return Ok(Some(self.versions_text(req, inode).await?));
+        }
+
         let flags = O_RDONLY;
         let open = self.create_open_handle(inode, &req, flags).await?;
         open.spec.get_xattr(name).await
@@ -1,6 +1,7 @@
 use super::dir::Directory;
 use super::file::RegularFile;
 use super::fixed::FixedFile;
+use super::snapshot::SnapshotFile;
 use super::symlink::SymLink;
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -21,6 +22,7 @@ pub enum FileSpec {
     SymLink,
     //Socket,
     FixedFile,
+    SnapshotFile,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
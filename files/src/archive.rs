@@ -0,0 +1,603 @@
+use bytes::Bytes;
+use error_chain::bail;
+use fxhash::FxHashMap;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use tar::Archive;
+use tar::Builder;
+use tar::EntryType;
+use tar::Header;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+use zip::ZipArchive;
+
+use super::accessor::*;
+use super::api::*;
+use super::attr::*;
+use super::codes::*;
+use super::error::*;
+use super::handle::*;
+use super::model::*;
+
+/// Prefix used for PAX records that carry extended attributes (same convention as GNU tar)
+const XATTR_PAX_PREFIX: &'static str = "SCHILY.xattr.";
+/// Number of headers and pages that may be queued up for the thread that writes
+/// the tar archive
+const TAR_QUEUE_DEPTH: usize = 16;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Counters describing what was exported or imported
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveStats {
+    pub files: u64,
+    pub dirs: u64,
+    pub links: u64,
+    pub bytes: u64,
+}
+
+impl FileAccessor {
+    /// Streams the directory tree at `path` into a tar archive. File contents are
+    /// read one page at a time so large files are never held in memory.
+    pub async fn export_tar<W: Write + Send + 'static>(
+        &self,
+        req: &RequestContext,
+        path: &str,
+        writer: W,
+    ) -> Result<ArchiveStats> {
+        let root = match self.search(req, path).await? {
+            Some(a) => a,
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        if root.kind != FileKind::Directory {
+            bail!(FileSystemErrorKind::NotDirectory);
+        }
+
+        // The tar builder only takes data from a blocking reader so it runs on its
+        // own thread while the headers and pages are fed to it as they are read
+        let (tx, rx) = mpsc::channel(TAR_QUEUE_DEPTH);
+        let (done_tx, done_rx) = oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = done_tx.send(write_tar(writer, rx));
+        });
+
+        let ret = self.export_tar_parts(req, root.ino, &tx).await;
+        drop(tx);
+        let written = match done_rx.await {
+            Ok(a) => a,
+            Err(_) => Err(std::io::Error::new(
+                ErrorKind::Other,
+                "the archive writer terminated unexpectedly",
+            )),
+        };
+
+        // (errors from the writer take priority as they are the reason the
+        // export was cut short)
+        written?;
+        ret
+    }
+
+    async fn export_tar_parts(
+        &self,
+        req: &RequestContext,
+        root: u64,
+        tx: &mpsc::Sender<TarPart>,
+    ) -> Result<ArchiveStats> {
+        let mut stats = ArchiveStats::default();
+        let mut stack = vec![(root, String::new())];
+        while let Some((ino, prefix)) = stack.pop() {
+            let open = self.create_open_handle(ino, req, O_RDONLY).await?;
+            for child in open.children.iter() {
+                if child.name == "." || child.name == ".." {
+                    continue;
+                }
+                let path = match prefix.len() {
+                    0 => child.name.clone(),
+                    _ => format!("{}/{}", prefix, child.name),
+                };
+
+                match child.kind {
+                    FileKind::Directory => {
+                        let dir_path = format!("{}/", path);
+                        let part = self.tar_header(req, child, &dir_path, None).await?;
+                        send_tar_part(tx, part).await?;
+                        stack.push((child.inode, path));
+                        stats.dirs += 1;
+                    }
+                    FileKind::SymLink => {
+                        let link = self
+                            .create_open_handle(child.inode, req, O_RDONLY)
+                            .await?
+                            .spec
+                            .link()
+                            .unwrap_or_default();
+                        let part = self.tar_header(req, child, &path, Some(&link)).await?;
+                        send_tar_part(tx, part).await?;
+                        stats.links += 1;
+                    }
+                    FileKind::RegularFile => {
+                        let part = self.tar_header(req, child, &path, None).await?;
+                        send_tar_part(tx, part).await?;
+                        stats.bytes += self.tar_data(req, child, tx).await?;
+                        stats.files += 1;
+                    }
+                    FileKind::FixedFile => {
+                        continue;
+                    }
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn tar_header(
+        &self,
+        req: &RequestContext,
+        entry: &DirectoryEntry,
+        path: &str,
+        link: Option<&str>,
+    ) -> Result<TarPart> {
+        let mut pax = Vec::new();
+        for (name, value) in self.listxattr(req, entry.inode).await? {
+            pax.push((format!("{}{}", XATTR_PAX_PREFIX, name), value.into_bytes()));
+        }
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(match entry.kind {
+            FileKind::Directory => EntryType::Directory,
+            FileKind::SymLink => EntryType::Symlink,
+            _ => EntryType::Regular,
+        });
+
+        // Names that do not fit in the header are carried in PAX records instead
+        if header.set_path(path).is_err() {
+            pax.push(("path".to_string(), path.as_bytes().to_vec()));
+            truncate_into(&mut header.as_old_mut().name, path);
+        }
+        if let Some(link) = link {
+            if header.set_link_name(link).is_err() {
+                pax.push(("linkpath".to_string(), link.as_bytes().to_vec()));
+                truncate_into(&mut header.as_old_mut().linkname, link);
+            }
+        }
+
+        header.set_mode(entry.attr.mode & 0o7777);
+        header.set_uid(entry.uid as u64);
+        header.set_gid(entry.gid as u64);
+        header.set_mtime(entry.attr.updated / 1000);
+        header.set_size(match entry.kind {
+            FileKind::RegularFile => entry.attr.size,
+            _ => 0,
+        });
+        header.set_cksum();
+
+        Ok(TarPart::Entry { header, pax })
+    }
+
+    async fn tar_data(
+        &self,
+        req: &RequestContext,
+        entry: &DirectoryEntry,
+        tx: &mpsc::Sender<TarPart>,
+    ) -> Result<u64> {
+        let size = entry.attr.size;
+        let fh = self.open(req, entry.inode, O_RDONLY as u32).await?.fh;
+
+        let mut offset = 0u64;
+        while offset < size {
+            let data = self
+                .read(req, entry.inode, fh, offset, PAGE_SIZE as u32)
+                .await?;
+            if data.len() <= 0 {
+                break;
+            }
+            let len = (data.len() as u64).min(size - offset);
+            send_tar_part(tx, TarPart::Data(data.slice(..len as usize))).await?;
+            offset += len;
+        }
+        self.release(req, entry.inode, fh, 0, 0, false).await?;
+
+        // The header has already committed to a size so any shortfall is zero filled
+        while offset < size {
+            let len = (size - offset).min(PAGE_SIZE as u64);
+            send_tar_part(tx, TarPart::Data(Bytes::from(vec![0u8; len as usize]))).await?;
+            offset += len;
+        }
+        Ok(size)
+    }
+
+    /// Imports the contents of a tar archive into the directory at `path` (which is
+    /// created if it does not exist). Modes, symlinks and extended attributes are
+    /// restored, ownership is restored when the session holds the keys for it.
+    pub async fn import_tar<R: Read>(
+        &self,
+        req: &RequestContext,
+        path: &str,
+        reader: R,
+    ) -> Result<ArchiveStats> {
+        let root = self.mkdir_all(req, path, 0o770).await?;
+
+        let mut stats = ArchiveStats::default();
+        let mut dirs = FxHashMap::default();
+        dirs.insert(String::new(), root.ino);
+
+        let mut archive = Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = match normalize_path(&entry.path()?.to_string_lossy()) {
+                Some(a) => a,
+                None => {
+                    continue;
+                }
+            };
+
+            let kind = entry.header().entry_type();
+            let mode = entry.header().mode()? & 0o7777;
+            let uid = entry.header().uid()? as u32;
+            let gid = entry.header().gid()? as u32;
+
+            let mut xattr = Vec::new();
+            if let Some(extensions) = entry.pax_extensions()? {
+                for extension in extensions {
+                    let extension = extension?;
+                    if let (Ok(key), Ok(value)) = (extension.key(), extension.value()) {
+                        if let Some(name) = key.strip_prefix(XATTR_PAX_PREFIX) {
+                            xattr.push((name.to_string(), value.to_string()));
+                        }
+                    }
+                }
+            }
+
+            let ino = if kind.is_dir() {
+                stats.dirs += 1;
+                self.import_dir(req, &mut dirs, &path, mode).await?
+            } else if kind.is_symlink() {
+                let link = match entry.link_name()? {
+                    Some(a) => a.to_string_lossy().to_string(),
+                    None => {
+                        continue;
+                    }
+                };
+                stats.links += 1;
+                self.import_symlink(req, &mut dirs, &path, &link).await?
+            } else if kind.is_file() {
+                let (ino, size) = self
+                    .import_file(req, &mut dirs, &path, mode, &mut entry)
+                    .await?;
+                stats.files += 1;
+                stats.bytes += size;
+                ino
+            } else {
+                debug!("skipping unsupported archive entry {}", path);
+                continue;
+            };
+
+            self.import_owner(req, ino, uid, gid).await?;
+            for (name, value) in xattr {
+                self.setxattr(req, ino, name.as_str(), value.as_str())
+                    .await?;
+            }
+        }
+
+        self.commit().await?;
+        Ok(stats)
+    }
+
+    /// Imports the contents of a zip archive into the directory at `path` (which is
+    /// created if it does not exist). Unix modes and symlinks are restored when
+    /// the archive carries them.
+    pub async fn import_zip<R: Read + Seek>(
+        &self,
+        req: &RequestContext,
+        path: &str,
+        reader: R,
+    ) -> Result<ArchiveStats> {
+        let root = self.mkdir_all(req, path, 0o770).await?;
+
+        let mut stats = ArchiveStats::default();
+        let mut dirs = FxHashMap::default();
+        dirs.insert(String::new(), root.ino);
+
+        let mut archive = ZipArchive::new(reader).map_err(std::io::Error::from)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).map_err(std::io::Error::from)?;
+            let path = match normalize_path(file.name()) {
+                Some(a) => a,
+                None => {
+                    continue;
+                }
+            };
+
+            let unix_mode = file.unix_mode();
+            if file.is_dir() {
+                let mode = unix_mode.map(|a| a & 0o7777).unwrap_or(0o770);
+                self.import_dir(req, &mut dirs, &path, mode).await?;
+                stats.dirs += 1;
+            } else if unix_mode.map(|a| (a & S_IFMT) == S_IFLNK).unwrap_or(false) {
+                let mut link = String::new();
+                file.read_to_string(&mut link)?;
+                self.import_symlink(req, &mut dirs, &path, &link).await?;
+                stats.links += 1;
+            } else {
+                let mode = unix_mode.map(|a| a & 0o7777).unwrap_or(0o660);
+                let (_, size) = self
+                    .import_file(req, &mut dirs, &path, mode, &mut file)
+                    .await?;
+                stats.files += 1;
+                stats.bytes += size;
+            }
+        }
+
+        self.commit().await?;
+        Ok(stats)
+    }
+
+    /// Creates all the directories along a path (if they do not already exist)
+    pub async fn mkdir_all(&self, req: &RequestContext, path: &str, mode: u32) -> Result<FileAttr> {
+        let mut ret = self.getattr(req, 1u64, None, 0u32).await?;
+        for comp in path.split("/").filter(|a| a.len() > 0) {
+            let parent = ret.ino;
+            ret = match self.lookup(req, parent, comp).await? {
+                Some(a) => a,
+                None => self.mkdir(req, parent, comp, mode).await?,
+            };
+        }
+        if ret.kind != FileKind::Directory {
+            bail!(FileSystemErrorKind::NotDirectory);
+        }
+        Ok(ret)
+    }
+
+    /// Returns the parent directory of an archive entry, archives do not always
+    /// hold entries for every directory so any that are missing are created
+    async fn import_parent<'a>(
+        &self,
+        req: &RequestContext,
+        dirs: &mut FxHashMap<String, u64>,
+        path: &'a str,
+    ) -> Result<(u64, &'a str)> {
+        let (parent, name) = match path.rsplit_once("/") {
+            Some(a) => a,
+            None => ("", path),
+        };
+        if let Some(ino) = dirs.get(parent) {
+            return Ok((*ino, name));
+        }
+
+        let mut ino = dirs[""];
+        let mut walked = String::new();
+        for comp in parent.split("/") {
+            if walked.len() > 0 {
+                walked.push('/');
+            }
+            walked.push_str(comp);
+            ino = match dirs.get(&walked) {
+                Some(a) => *a,
+                None => {
+                    let ino = match self.lookup(req, ino, comp).await? {
+                        Some(a) if a.kind == FileKind::Directory => a.ino,
+                        Some(_) => {
+                            bail!(FileSystemErrorKind::NotDirectory);
+                        }
+                        None => self.mkdir(req, ino, comp, 0o770).await?.ino,
+                    };
+                    dirs.insert(walked.clone(), ino);
+                    ino
+                }
+            };
+        }
+        Ok((ino, name))
+    }
+
+    async fn import_dir(
+        &self,
+        req: &RequestContext,
+        dirs: &mut FxHashMap<String, u64>,
+        path: &str,
+        mode: u32,
+    ) -> Result<u64> {
+        let (parent, name) = self.import_parent(req, dirs, path).await?;
+        let attr = match self.lookup(req, parent, name).await? {
+            Some(a) if a.kind == FileKind::Directory => a,
+            Some(_) => {
+                bail!(FileSystemErrorKind::NotDirectory);
+            }
+            None => self.mkdir(req, parent, name, mode).await?,
+        };
+        if (attr.mode & 0o7777) != mode {
+            let mut set_attr = SetAttr::default();
+            set_attr.mode = Some(mode);
+            self.setattr(req, attr.ino, None, set_attr).await?;
+        }
+        dirs.insert(path.to_string(), attr.ino);
+        Ok(attr.ino)
+    }
+
+    async fn import_symlink(
+        &self,
+        req: &RequestContext,
+        dirs: &mut FxHashMap<String, u64>,
+        path: &str,
+        link: &str,
+    ) -> Result<u64> {
+        let (parent, name) = self.import_parent(req, dirs, path).await?;
+        if self.lookup(req, parent, name).await?.is_some() {
+            self.unlink(req, parent, name).await?;
+        }
+        Ok(self.symlink(req, parent, name, link).await?.ino)
+    }
+
+    async fn import_file<R: Read>(
+        &self,
+        req: &RequestContext,
+        dirs: &mut FxHashMap<String, u64>,
+        path: &str,
+        mode: u32,
+        reader: &mut R,
+    ) -> Result<(u64, u64)> {
+        let (parent, name) = self.import_parent(req, dirs, path).await?;
+        let ino = match self.lookup(req, parent, name).await? {
+            Some(a) if a.kind == FileKind::RegularFile => {
+                if (a.mode & 0o7777) != mode {
+                    let mut set_attr = SetAttr::default();
+                    set_attr.mode = Some(mode);
+                    self.setattr(req, a.ino, None, set_attr).await?;
+                }
+                a.ino
+            }
+            Some(a) if a.kind == FileKind::Directory => {
+                bail!(FileSystemErrorKind::IsDirectory);
+            }
+            Some(_) => {
+                self.unlink(req, parent, name).await?;
+                self.mknod(req, parent, name, mode).await?.ino
+            }
+            None => self.mknod(req, parent, name, mode).await?.ino,
+        };
+
+        let fh = self.open(req, ino, O_RDWR as u32).await?.fh;
+        let mut buf = vec![0u8; PAGE_SIZE];
+        let mut offset = 0u64;
+        loop {
+            let mut len = 0usize;
+            while len < buf.len() {
+                let read = reader.read(&mut buf[len..])?;
+                if read <= 0 {
+                    break;
+                }
+                len += read;
+            }
+            if len <= 0 {
+                break;
+            }
+            self.write(req, ino, fh, offset, &buf[..len], 0).await?;
+            offset += len as u64;
+        }
+        self.fallocate(req, ino, fh, 0, offset, 0).await?;
+        self.release(req, ino, fh, 0, 0, false).await?;
+        Ok((ino, offset))
+    }
+
+    async fn import_owner(&self, req: &RequestContext, ino: u64, uid: u32, gid: u32) -> Result<()> {
+        let mut set_attr = SetAttr::default();
+        set_attr.uid = Some(uid);
+        set_attr.gid = Some(gid);
+        match self.setattr(req, ino, None, set_attr).await {
+            Ok(_) => Ok(()),
+            Err(FileSystemError(FileSystemErrorKind::NoAccess, _)) => {
+                debug!(
+                    "keeping the current owner of inode {} as the session lacks the keys for uid={} gid={}",
+                    ino, uid, gid
+                );
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Parts of a tar archive that are passed to the thread that writes it
+enum TarPart {
+    Entry {
+        header: Header,
+        pax: Vec<(String, Vec<u8>)>,
+    },
+    Data(Bytes),
+}
+
+async fn send_tar_part(tx: &mpsc::Sender<TarPart>, part: TarPart) -> Result<()> {
+    if tx.send(part).await.is_err() {
+        bail!(std::io::Error::new(
+            ErrorKind::BrokenPipe,
+            "the archive writer has stopped"
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the parts of the archive as they arrive (runs on its own thread)
+fn write_tar<W: Write>(writer: W, mut rx: mpsc::Receiver<TarPart>) -> std::io::Result<()> {
+    let mut builder = Builder::new(writer);
+    while let Some(part) = rx.blocking_recv() {
+        let (header, pax) = match part {
+            TarPart::Entry { header, pax } => (header, pax),
+            TarPart::Data(_) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    "archive data arrived without a header",
+                ));
+            }
+        };
+        if pax.len() > 0 {
+            builder.append_pax_extensions(pax.iter().map(|(k, v)| (k.as_str(), &v[..])))?;
+        }
+        let data = TarData {
+            rx: &mut rx,
+            buf: Bytes::new(),
+            remaining: header.size()?,
+        };
+        builder.append(&header, data)?;
+    }
+    builder.into_inner()?.flush()
+}
+
+/// Reader that hands the pages of a file to the tar builder as they arrive
+struct TarData<'a> {
+    rx: &'a mut mpsc::Receiver<TarPart>,
+    buf: Bytes,
+    remaining: u64,
+}
+
+impl<'a> Read for TarData<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.buf.is_empty() {
+            if self.remaining <= 0 {
+                return Ok(0);
+            }
+            match self.rx.blocking_recv() {
+                Some(TarPart::Data(data)) => self.buf = data,
+                _ => {
+                    return Err(std::io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "the archive entry ended early",
+                    ));
+                }
+            }
+        }
+        let len = (buf.len() as u64).min(self.buf.len() as u64).min(self.remaining) as usize;
+        buf[..len].copy_from_slice(&self.buf.split_to(len)[..]);
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+}
+
+/// Strips any leading, trailing or relative components from an archive path,
+/// entries that attempt to escape the destination are rejected
+fn normalize_path(path: &str) -> Option<String> {
+    let mut comps = Vec::new();
+    for comp in path.split("/") {
+        match comp {
+            "" | "." => continue,
+            ".." => {
+                warn!("skipping archive entry outside of the destination ({})", path);
+                return None;
+            }
+            comp => comps.push(comp),
+        }
+    }
+    match comps.len() {
+        0 => None,
+        _ => Some(comps.join("/")),
+    }
+}
+
+fn truncate_into(field: &mut [u8], val: &str) {
+    let len = val.len().min(field.len());
+    field[..len].copy_from_slice(&val.as_bytes()[..len]);
+}
@@ -55,6 +55,10 @@ error_chain! {
             description("the function is not implemented"),
             display("the function is not implemented")
         }
+        HistoryCompacted {
+            description("the history needed for this snapshot has been compacted away"),
+            display("the history needed for this snapshot has been compacted away")
+        }
     }
 }
 
@@ -1,5 +1,6 @@
 pub mod accessor;
 pub mod api;
+pub mod archive;
 pub mod attr;
 pub mod codes;
 pub mod dir;
@@ -9,5 +10,7 @@ pub mod fixed;
 pub mod handle;
 pub mod model;
 pub mod prelude;
+pub mod snapshot;
 pub mod symlink;
+pub mod version;
 pub mod repo;
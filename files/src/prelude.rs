@@ -9,6 +9,7 @@ pub use crate::attr::SetAttr;
 
 pub use crate::accessor::FileAccessor;
 pub use crate::accessor::RequestContext;
+pub use crate::archive::ArchiveStats;
 pub use crate::dir::Directory;
 pub use crate::file::FileState;
 pub use crate::file::RegularFile;
@@ -16,4 +17,8 @@ pub use crate::fixed::FixedFile;
 pub use crate::handle::DirectoryEntry;
 pub use crate::handle::OpenHandle;
 pub use crate::model::*;
+pub use crate::snapshot::FileSnapshot;
+pub use crate::snapshot::SnapshotFile;
 pub use crate::symlink::SymLink;
+pub use crate::version::ContentVersion;
+pub use crate::version::FileVersion;
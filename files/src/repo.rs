@@ -148,6 +148,46 @@ impl Repository {
         })
     }
 
+    /// Opens a file for streaming rather than reading it all into memory
+    pub async fn get_file_handle(
+        &self,
+        key: &ChainKey,
+        sni: &str,
+        path: &str,
+    ) -> Result<Option<RepositoryFile>, FileSystemError> {
+        let context = RequestContext::default();
+
+        trace!("perf-checkpoint: get_accessor (key={}, sni={})", key, sni);
+        let chain = self.get_accessor(key, sni).await?;
+
+        trace!("perf-checkpoint: search (path={})", path);
+        let attr = match chain.search(&context, path).await {
+            Ok(Some(a)) if a.kind == FileKind::RegularFile => a,
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(FileSystemError(FileSystemErrorKind::IsDirectory, _))
+            | Err(FileSystemError(FileSystemErrorKind::DoesNotExist, _))
+            | Err(FileSystemError(FileSystemErrorKind::NoEntry, _)) => {
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err.into());
+            }
+        };
+
+        let flags = crate::codes::O_RDONLY;
+        trace!("perf-checkpoint: open (ino={}, flags={})", attr.ino, flags);
+        let handle = chain.create_open_handle(attr.ino, &context, flags).await?;
+        let version = chain.content_version(attr.ino).await?;
+
+        Ok(Some(RepositoryFile {
+            size: handle.spec.size(),
+            handle: Arc::new(handle),
+            version,
+        }))
+    }
+
     pub async fn set_file(
         &self,
         key: &ChainKey,
@@ -172,12 +212,50 @@ impl Repository {
         Ok(written)
     }
 
+    /// Appends data to the end of a file (creating it if it does not exist)
+    pub async fn append_file(
+        &self,
+        key: &ChainKey,
+        sni: &str,
+        path: &str,
+        data: &[u8],
+    ) -> Result<u64, FileSystemError> {
+        let context = RequestContext::default();
+
+        let chain = self.get_accessor(key, sni).await?;
+        let file = chain.touch(&context, path).await?;
+        let flags = crate::codes::O_RDWR as u32;
+        let oh = chain.open(&context, file.ino, flags).await?;
+        let ret = chain
+            .write(&context, file.ino, oh.fh, oh.spec.size(), data, flags)
+            .await;
+        chain.sync(&context, file.ino, oh.fh, 0).await?;
+        chain
+            .release(&context, file.ino, oh.fh, flags, 0, false)
+            .await?;
+        ret
+    }
+
     pub async fn house_keeping(&self) {
         let mut lock = self.chains.lock().await;
         lock.iter(); // this will run the remove_expired function
     }
 }
 
+/// File that has been opened by the repository so that it can be streamed
+#[derive(Debug, Clone)]
+pub struct RepositoryFile {
+    pub handle: Arc<OpenHandle>,
+    pub size: u64,
+    pub version: ContentVersion,
+}
+
+impl RepositoryFile {
+    pub async fn read(&self, offset: u64, size: u64) -> Result<Bytes, FileSystemError> {
+        self.handle.spec.read(offset, size).await
+    }
+}
+
 #[async_trait]
 pub trait RepositorySessionFactory
 where Self: Send + Sync
@@ -0,0 +1,728 @@
+use async_trait::async_trait;
+use ate::prelude::*;
+use bytes::Bytes;
+use cached::Cached;
+use error_chain::bail;
+use fxhash::FxHashSet;
+use serde::de::DeserializeOwned;
+use serde::*;
+use std::ops::Deref;
+use std::sync::Arc;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+
+use super::accessor::*;
+use super::api::*;
+use super::attr::*;
+use super::codes::*;
+use super::error::*;
+use super::fixed::FixedFile;
+use super::handle::*;
+use super::model::*;
+
+/// Name of the hidden directory under the root that exposes the snapshots
+pub const SNAPSHOTS_DIR: &'static str = ".snapshots";
+/// Collection attached to the root inode that holds the named snapshots
+pub const SNAPSHOTS_VEC_ID: u64 = 0x4a1c5e33d0b8f2a7u64;
+/// Virtual inode number of the `/.snapshots` directory
+pub const SNAPSHOTS_INO: u64 = 0x8d1f6be2c55a9e01u64;
+/// Maximum number of entries inside the snapshots that are remembered by inode number
+/// (the least recently used are forgotten first)
+pub const MAX_SNAPSHOT_NODES: usize = 65536;
+
+/// Named point in time of a files chain that can be browsed or rolled back to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileSnapshot {
+    pub name: String,
+    pub at: u64,
+    pub uid: u32,
+    pub gid: u32,
+    /// Number of objects in the chain when the snapshot was taken, the history
+    /// must still reproduce this many for the snapshot to be trusted (zero
+    /// means the view is not verified)
+    #[serde(default)]
+    pub objects: u64,
+    /// Hash of the last event that is part of the snapshot, events written in
+    /// the same millisecond but after it are not included
+    #[serde(default)]
+    pub last_event: Option<AteHash>,
+}
+
+/// Reference from a virtual inode number to the inode within a snapshot
+#[derive(Debug, Clone)]
+pub struct SnapshotNode {
+    pub name: String,
+    pub key: PrimaryKey,
+}
+
+/// Computes the virtual inode number of an inode when it is viewed inside a snapshot
+pub fn snapshot_ino(name: &str, key: &PrimaryKey) -> u64 {
+    fxhash::hash64(&(name, key.as_u64()))
+}
+
+/// Read-only view of the file system as it stood when a snapshot was taken. The
+/// indexes are rebuilt from the chain history so no data is ever copied.
+#[derive(Debug)]
+pub struct SnapshotView {
+    pub snapshot: FileSnapshot,
+    pub dio: Arc<Dio>,
+    pub pit: ChainPointInTime,
+}
+
+impl SnapshotView {
+    pub async fn new(dio: &Arc<Dio>, snapshot: FileSnapshot) -> Result<SnapshotView> {
+        let pit = dio
+            .point_in_time(ChainTimestamp::from(snapshot.at), snapshot.last_event.clone())
+            .await?;
+
+        // Compaction removes the events that the snapshot is rebuilt from which
+        // would show (and roll back to) a tree that is missing objects
+        if (pit.len() as u64) < snapshot.objects {
+            warn!(
+                "snapshot {} expected {} objects but the history only has {}",
+                snapshot.name,
+                snapshot.objects,
+                pit.len()
+            );
+            bail!(FileSystemErrorKind::HistoryCompacted);
+        }
+
+        Ok(SnapshotView {
+            snapshot,
+            dio: Arc::clone(dio),
+            pit,
+        })
+    }
+
+    pub fn ino(&self, key: &PrimaryKey) -> u64 {
+        snapshot_ino(self.snapshot.name.as_str(), key)
+    }
+
+    pub async fn load<D>(&self, key: &PrimaryKey) -> Result<Dao<D>>
+    where
+        D: DeserializeOwned,
+    {
+        Ok(self.dio.load_at(&self.pit, key).await?)
+    }
+
+    pub async fn children_keys(&self, parent: &PrimaryKey, collection_id: u64) -> Vec<PrimaryKey> {
+        self.dio
+            .children_keys_at(&self.pit, parent.clone(), collection_id)
+            .await
+    }
+
+    pub async fn children(&self, inode: &Dao<Inode>) -> Result<Vec<Dao<Inode>>> {
+        let mut ret = Vec::new();
+        for key in self
+            .children_keys(inode.key(), inode.children.vec_id())
+            .await
+        {
+            ret.push(self.load::<Inode>(&key).await?);
+        }
+        Ok(ret)
+    }
+
+    pub async fn read(
+        &self,
+        bundles: &Vec<Option<PrimaryKey>>,
+        file_size: u64,
+        mut offset: u64,
+        size: u64,
+    ) -> Result<Bytes> {
+        if offset >= file_size {
+            return Ok(Bytes::from(Vec::new()));
+        }
+        let mut size = size.min(file_size - offset);
+
+        let stride_page = PAGE_SIZE as u64;
+        let stride_bundle = PAGES_PER_BUNDLE as u64 * stride_page;
+        let mut ret = Vec::with_capacity(size as usize);
+
+        let mut last_bundle: Option<Dao<PageBundle>> = None;
+        while size > 0 {
+            let sub_offset = offset % stride_page;
+            let sub_size = size.min(stride_page - sub_offset);
+
+            // Find the page (holes are read as zeros)
+            let bundle_index = (offset / stride_bundle) as usize;
+            let page_index = ((offset % stride_bundle) / stride_page) as usize;
+            let page = match bundles.get(bundle_index).map(|a| a.clone()).flatten() {
+                Some(bundle) => {
+                    let bundle = match last_bundle.take() {
+                        Some(a) if *a.key() == bundle => a,
+                        _ => self.load::<PageBundle>(&bundle).await?,
+                    };
+                    let page = bundle.pages.get(page_index).map(|a| a.clone()).flatten();
+                    last_bundle = Some(bundle);
+                    match page {
+                        Some(page) => Some(self.load::<Page>(&page).await?),
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+
+            // Copy the bytes and pad the remainder with zeros
+            let start = ret.len();
+            if let Some(page) = page {
+                let buf = &page.buf;
+                let from = (sub_offset as usize).min(buf.len());
+                let to = ((sub_offset + sub_size) as usize).min(buf.len());
+                ret.extend_from_slice(&buf[from..to]);
+            }
+            ret.resize(start + sub_size as usize, 0u8);
+
+            size = size - sub_size;
+            offset = offset + sub_size;
+        }
+
+        Ok(Bytes::from(ret))
+    }
+}
+
+/// File, directory or symbolic link as it was when the snapshot was taken
+#[derive(Debug)]
+pub struct SnapshotFile {
+    pub ino: u64,
+    pub kind: FileKind,
+    pub created: u64,
+    pub updated: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub name: String,
+    pub size: u64,
+    pub link: Option<String>,
+    pub bundles: Vec<Option<PrimaryKey>>,
+    pub view: Option<Arc<SnapshotView>>,
+}
+
+impl SnapshotFile {
+    pub fn new(ino: u64, inode: &Dao<Inode>, view: &Arc<SnapshotView>) -> SnapshotFile {
+        SnapshotFile {
+            ino,
+            kind: inode.kind,
+            created: inode.when_created(),
+            updated: inode.when_updated(),
+            uid: inode.dentry.uid,
+            gid: inode.dentry.gid,
+            mode: inode.dentry.mode & !0o222,
+            name: inode.dentry.name.clone(),
+            size: inode.size,
+            link: inode.link.clone(),
+            bundles: inode.bundles.clone(),
+            view: Some(Arc::clone(view)),
+        }
+    }
+
+    pub fn directory(ino: u64, name: String, uid: u32, gid: u32, at: u64) -> SnapshotFile {
+        SnapshotFile {
+            ino,
+            kind: FileKind::Directory,
+            created: at,
+            updated: at,
+            uid,
+            gid,
+            mode: 0o555,
+            name,
+            size: 0,
+            link: None,
+            bundles: Vec::new(),
+            view: None,
+        }
+    }
+}
+
+#[async_trait]
+impl FileApi for SnapshotFile {
+    fn kind(&self) -> FileKind {
+        self.kind
+    }
+
+    fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    fn size(&self) -> u64 {
+        match self.kind {
+            FileKind::SymLink => self.link.as_ref().map(|a| a.len() as u64).unwrap_or(0),
+            _ => self.size,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn created(&self) -> u64 {
+        self.created
+    }
+
+    fn updated(&self) -> u64 {
+        self.updated
+    }
+
+    fn accessed(&self) -> u64 {
+        self.updated
+    }
+
+    fn link(&self) -> Option<String> {
+        self.link.clone()
+    }
+
+    async fn fallocate(&self, _size: u64) -> Result<()> {
+        bail!(FileSystemErrorKind::ReadOnly);
+    }
+
+    async fn read(&self, offset: u64, size: u64) -> Result<Bytes> {
+        match (&self.view, self.kind) {
+            (Some(view), FileKind::RegularFile) => {
+                view.read(&self.bundles, self.size, offset, size).await
+            }
+            _ => Ok(Bytes::from(Vec::new())),
+        }
+    }
+
+    async fn write(&self, _offset: u64, _data: &[u8]) -> Result<u64> {
+        bail!(FileSystemErrorKind::ReadOnly);
+    }
+
+    async fn set_xattr(&mut self, _name: &str, _value: &str) -> Result<()> {
+        bail!(FileSystemErrorKind::ReadOnly);
+    }
+
+    async fn remove_xattr(&mut self, _name: &str) -> Result<bool> {
+        bail!(FileSystemErrorKind::ReadOnly);
+    }
+}
+
+impl FileAccessor {
+    fn snapshots_mut(&self, dio: &Arc<DioMut>) -> DaoVec<FileSnapshot> {
+        DaoVec::new_orphaned_mut(dio, PrimaryKey::from(1), SNAPSHOTS_VEC_ID)
+    }
+
+    /// Returns all the named snapshots of this file system
+    pub async fn list_snapshots(&self) -> Result<Vec<FileSnapshot>> {
+        let snapshots: DaoVec<FileSnapshot> =
+            DaoVec::new_orphaned(&self.dio, PrimaryKey::from(1), SNAPSHOTS_VEC_ID);
+        let mut ret = snapshots
+            .iter()
+            .await?
+            .map(|a| a.take())
+            .collect::<Vec<_>>();
+        ret.sort_by_key(|a| a.at);
+        Ok(ret)
+    }
+
+    pub async fn get_snapshot(&self, name: &str) -> Result<Option<FileSnapshot>> {
+        Ok(self
+            .list_snapshots()
+            .await?
+            .into_iter()
+            .filter(|a| a.name == name)
+            .next())
+    }
+
+    /// Captures the current state of the file system under a particular name.
+    /// Snapshots are rebuilt from the history of the chain so they can only be
+    /// taken when compaction is disabled (`CompactMode::Never`).
+    pub async fn create_snapshot(&self, req: &RequestContext, name: &str) -> Result<FileSnapshot> {
+        self.tick().await?;
+        debug!("wasmer-dfs::create_snapshot name={}", name);
+
+        if name.len() <= 0 || name.contains("/") || name == "." || name == ".." {
+            bail!(FileSystemErrorKind::InvalidArguments);
+        }
+        if self.get_snapshot(name).await?.is_some() {
+            bail!(FileSystemErrorKind::AlreadyExists);
+        }
+
+        // Snapshots are rebuilt from the event history which compaction removes
+        if self.chain.compact_mode() != CompactMode::Never {
+            warn!("snapshots are not supported on chains with compaction enabled");
+            bail!(FileSystemErrorKind::HistoryCompacted);
+        }
+
+        // Everything that has been written so far must be part of the snapshot
+        self.commit().await?;
+        let (at, last_event) = self.dio.timeline_anchor().await;
+        let pit = self.dio.point_in_time(at.clone(), last_event.clone()).await?;
+
+        let root = self.load(1u64).await?;
+        let snapshot = FileSnapshot {
+            name: name.to_string(),
+            at: at.time_since_epoch_ms,
+            uid: self.translate_uid(req.uid, req),
+            gid: self.translate_gid(req.gid, req),
+            objects: pit.len() as u64,
+            last_event,
+        };
+
+        let dio = self.dio_mut_meta().await;
+        let mut dao = self.snapshots_mut(&dio).push(snapshot.clone())?;
+        self.updwasmer_auth(
+            root.dentry.mode,
+            root.dentry.uid,
+            root.dentry.gid,
+            dao.auth_mut(),
+        )?;
+        dio.commit().await?;
+
+        Ok(snapshot)
+    }
+
+    /// Removes a named snapshot (the history it references is left untouched)
+    pub async fn delete_snapshot(&self, _req: &RequestContext, name: &str) -> Result<()> {
+        self.tick().await?;
+        debug!("wasmer-dfs::delete_snapshot name={}", name);
+
+        let dio = self.dio_mut_meta().await;
+        let snapshots = self.snapshots_mut(&dio);
+        let key = match snapshots
+            .iter()
+            .await?
+            .filter(|a| a.name == name)
+            .map(|a| a.key().clone())
+            .next()
+        {
+            Some(a) => a,
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        dio.delete(&key).await?;
+        dio.commit().await?;
+
+        // (the nodes of the deleted snapshot are left to age out of the cache so
+        // that they remain read-only)
+        self.snapshot_views.lock().unwrap().remove(name);
+        Ok(())
+    }
+
+    pub async fn snapshot_view(&self, name: &str) -> Result<Arc<SnapshotView>> {
+        if let Some(view) = self.snapshot_views.lock().unwrap().get(name) {
+            return Ok(Arc::clone(view));
+        }
+
+        let snapshot = match self.get_snapshot(name).await? {
+            Some(a) => a,
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        let view = Arc::new(SnapshotView::new(&self.dio, snapshot).await?);
+
+        let mut guard = self.snapshot_views.lock().unwrap();
+        Ok(Arc::clone(
+            guard.entry(name.to_string()).or_insert(view),
+        ))
+    }
+
+    /// Returns true if the inode number refers to something inside `/.snapshots`
+    pub fn is_snapshot_ino(&self, ino: u64) -> bool {
+        ino == SNAPSHOTS_INO || self.snapshot_nodes.lock().unwrap().cache_get(&ino).is_some()
+    }
+
+    /// Snapshots (and old versions of files) are read-only so any attempt to modify
+    /// them is rejected
+    pub fn check_not_snapshot(&self, ino: u64) -> Result<()> {
+        if self.is_snapshot_ino(ino) || self.is_version_ino(ino) {
+            debug!("wasmer-dfs::snapshot ino={} is read-only", ino);
+            bail!(FileSystemErrorKind::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn register_snapshot_node(&self, name: &str, key: &PrimaryKey) -> u64 {
+        let ino = snapshot_ino(name, key);
+        self.snapshot_nodes.lock().unwrap().cache_set(
+            ino,
+            SnapshotNode {
+                name: name.to_string(),
+                key: key.clone(),
+            },
+        );
+        ino
+    }
+
+    pub async fn snapshot_spec(&self, ino: u64) -> Result<FileSpec> {
+        if ino == SNAPSHOTS_INO {
+            let root = self.load(1u64).await?;
+            return Ok(FileSpec::SnapshotFile(SnapshotFile::directory(
+                SNAPSHOTS_INO,
+                SNAPSHOTS_DIR.to_string(),
+                root.dentry.uid,
+                root.dentry.gid,
+                root.when_updated(),
+            )));
+        }
+
+        let node = match self.snapshot_nodes.lock().unwrap().cache_get(&ino) {
+            Some(a) => a.clone(),
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        let view = self.snapshot_view(node.name.as_str()).await?;
+        let inode = view.load::<Inode>(&node.key).await?;
+
+        let mut spec = SnapshotFile::new(ino, &inode, &view);
+        if node.key.as_u64() == 1u64 {
+            spec.name = node.name.clone();
+        }
+        Ok(FileSpec::SnapshotFile(spec))
+    }
+
+    async fn snapshot_children(&self, ino: u64) -> Result<Vec<FileSpec>> {
+        let mut ret = Vec::new();
+        if ino == SNAPSHOTS_INO {
+            for snapshot in self.list_snapshots().await? {
+                let ino = self.register_snapshot_node(snapshot.name.as_str(), &PrimaryKey::from(1));
+                ret.push(FileSpec::SnapshotFile(SnapshotFile::directory(
+                    ino,
+                    snapshot.name.clone(),
+                    snapshot.uid,
+                    snapshot.gid,
+                    snapshot.at,
+                )));
+            }
+            return Ok(ret);
+        }
+
+        let node = match self.snapshot_nodes.lock().unwrap().cache_get(&ino) {
+            Some(a) => a.clone(),
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        let view = self.snapshot_view(node.name.as_str()).await?;
+        let inode = view.load::<Inode>(&node.key).await?;
+        for child in view.children(&inode).await? {
+            let ino = self.register_snapshot_node(node.name.as_str(), child.key());
+            ret.push(FileSpec::SnapshotFile(SnapshotFile::new(ino, &child, &view)));
+        }
+        Ok(ret)
+    }
+
+    pub async fn create_snapshot_handle(
+        &self,
+        inode: u64,
+        req: &RequestContext,
+        flags: i32,
+    ) -> Result<OpenHandle> {
+        if flags & O_TRUNC != 0 || flags & O_RDWR != 0 || flags & O_WRONLY != 0 {
+            bail!(FileSystemErrorKind::ReadOnly);
+        }
+
+        let spec = self.snapshot_spec(inode).await?;
+        let children = match spec.kind() {
+            FileKind::Directory => self.snapshot_children(inode).await?,
+            _ => Vec::new(),
+        };
+        Ok(self.virtual_open_handle(inode, req, spec, children))
+    }
+
+    /// Builds a read-only handle for an entry that does not exist as an inode
+    /// (e.g. something inside `/.snapshots`)
+    pub(crate) fn virtual_open_handle(
+        &self,
+        inode: u64,
+        req: &RequestContext,
+        spec: FileSpec,
+        children: Vec<FileSpec>,
+    ) -> OpenHandle {
+        let uid = spec.uid();
+        let gid = spec.gid();
+
+        let mut entries = Vec::new();
+        if spec.kind() == FileKind::Directory {
+            let fixed = FixedFile::new(inode, ".".to_string(), FileKind::Directory)
+                .uid(uid)
+                .gid(gid)
+                .created(spec.created())
+                .updated(spec.updated());
+            entries.push(FileSpec::FixedFile(fixed));
+
+            let fixed = FixedFile::new(inode, "..".to_string(), FileKind::Directory)
+                .uid(uid)
+                .gid(gid)
+                .created(spec.created())
+                .updated(spec.updated());
+            entries.push(FileSpec::FixedFile(fixed));
+
+            entries.extend(children);
+        }
+
+        let mut open = OpenHandle {
+            inode,
+            read_only: true,
+            fh: fastrand::u64(..),
+            attr: FileAttr::new(&spec, uid, gid),
+            kind: spec.kind(),
+            spec: spec,
+            children: Vec::new(),
+            dirty: seqlock::SeqLock::new(false),
+        };
+
+        for child in entries.into_iter() {
+            let (uid, gid) = match self.impersonate_uid {
+                true => {
+                    let uid = self.reverse_uid(child.uid(), req);
+                    let gid = self.reverse_gid(child.gid(), req);
+                    (uid, gid)
+                }
+                false => (child.uid(), child.gid()),
+            };
+            open.add_child(&child, uid, gid);
+        }
+
+        open
+    }
+
+    /// Rolls the live file system back to the state it was in when the named
+    /// snapshot was taken. Only the objects that changed since then are rewritten
+    /// (using the data from the events in the chain history) and anything that was
+    /// created afterwards is deleted.
+    pub async fn rollback_snapshot(&self, _req: &RequestContext, name: &str) -> Result<()> {
+        self.tick().await?;
+        self.commit().await?;
+        debug!("wasmer-dfs::rollback_snapshot name={}", name);
+
+        let view = self.snapshot_view(name).await?;
+        let dio = self.dio_mut_meta().await;
+        let multi = self.chain.multi().await;
+
+        // Restore everything that made up the tree when the snapshot was taken
+        let mut keep = FxHashSet::default();
+        let mut stack = vec![PrimaryKey::from(1)];
+        while let Some(key) = stack.pop() {
+            let inode = self
+                .restore_object_version::<Inode>(&view, &multi, &dio, &key)
+                .await?;
+            keep.insert(key.clone());
+
+            for bundle in inode.bundles.iter().filter_map(|a| a.clone()) {
+                let bundle = self
+                    .restore_object_version::<PageBundle>(&view, &multi, &dio, &bundle)
+                    .await?;
+                keep.insert(bundle.key().clone());
+
+                for page in bundle.pages.iter().filter_map(|a| a.clone()) {
+                    self.restore_object_version::<Page>(&view, &multi, &dio, &page)
+                        .await?;
+                    keep.insert(page);
+                }
+            }
+
+            for xattr in view.children_keys(&key, inode.xattr.vec_id()).await {
+                self.restore_object_version::<String>(&view, &multi, &dio, &xattr)
+                    .await?;
+                keep.insert(xattr);
+            }
+
+            stack.extend(view.children_keys(&key, inode.children.vec_id()).await);
+        }
+
+        // Delete anything in the live tree that did not exist in the snapshot
+        let mut stack = vec![PrimaryKey::from(1)];
+        while let Some(key) = stack.pop() {
+            let inode = match self.dio.load::<Inode>(&key).await {
+                Ok(a) => a,
+                Err(LoadError(LoadErrorKind::NotFound(_), _)) => continue,
+                Err(err) => {
+                    bail!(err);
+                }
+            };
+
+            let mut garbage = Vec::new();
+            for bundle in inode.bundles.iter().filter_map(|a| a.clone()) {
+                if let Ok(bundle) = self.dio.load::<PageBundle>(&bundle).await {
+                    garbage.extend(bundle.pages.iter().filter_map(|a| a.clone()));
+                }
+                garbage.push(bundle);
+            }
+            garbage.extend(
+                self.dio
+                    .children_keys(key.clone(), inode.xattr.vec_id())
+                    .await?,
+            );
+            if keep.contains(&key) == false {
+                garbage.push(key.clone());
+            }
+            for key in garbage {
+                if keep.contains(&key) == false && self.dio.exists(&key).await {
+                    dio.delete(&key).await?;
+                }
+            }
+
+            stack.extend(
+                self.dio
+                    .children_keys(key.clone(), inode.children.vec_id())
+                    .await?,
+            );
+        }
+
+        dio.commit().await?;
+        Ok(())
+    }
+
+    async fn restore_object_version<D>(
+        &self,
+        view: &SnapshotView,
+        multi: &ChainMultiUser,
+        dio: &Arc<DioMut>,
+        key: &PrimaryKey,
+    ) -> Result<Dao<D>>
+    where
+        D: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        let leaf = match view.pit.lookup_primary(key) {
+            Some(a) => a,
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        let old = self.dio.load_version::<D>(leaf).await?;
+
+        match multi.lookup_primary(key).await {
+            // Nothing has changed since the snapshot was taken
+            Some(current) if current.record == leaf.record => {}
+            // It was modified so write the old version back over the top
+            Some(_) => {
+                let mut dao = dio.load::<D>(key).await?;
+                *dao.as_mut() = old.deref().clone();
+                if let Some(parent) = old.parent() {
+                    if dao.parent() != Some(parent.clone()) {
+                        dao.attach_ext(parent.parent_id, parent.collection_id)?;
+                    }
+                }
+                let mut auth = dao.auth_mut();
+                auth.read = old.auth().read.clone();
+                auth.write = old.auth().write.clone();
+            }
+            // It was deleted so bring it back with the same key
+            None => {
+                let mut dao = dio.store_with_key(old.deref().clone(), key.clone())?;
+                if let Some(parent) = old.parent() {
+                    dao.attach_ext(parent.parent_id, parent.collection_id)?;
+                }
+                let mut auth = dao.auth_mut();
+                auth.read = old.auth().read.clone();
+                auth.write = old.auth().write.clone();
+            }
+        }
+        Ok(old)
+    }
+}
@@ -0,0 +1,121 @@
+use ate::prelude::*;
+use ate_files::codes::*;
+use ate_files::prelude::*;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+
+async fn create_accessor(name: &str) -> FileAccessor {
+    ate::utils::bootstrap_test_env();
+
+    let mut conf = ConfAte::default();
+    conf.configured_for(ConfiguredFor::BestPerformance);
+    let builder = ChainBuilder::new(&conf).await.build();
+    let chain = builder
+        .open(&ChainKey::default().with_temp_name(name.to_string()))
+        .await
+        .unwrap();
+
+    FileAccessor::new(
+        chain,
+        None,
+        AteSessionType::User(AteSessionUser::new()),
+        TransactionScope::Local,
+        TransactionScope::Local,
+        true,
+        false,
+    )
+    .await
+}
+
+async fn write_file(accessor: &FileAccessor, req: &RequestContext, parent: u64, name: &str, mode: u32, data: &[u8]) -> u64 {
+    let ino = accessor.mknod(req, parent, name, mode).await.unwrap().ino;
+    let fh = accessor.open(req, ino, O_RDWR as u32).await.unwrap().fh;
+    accessor.write(req, ino, fh, 0, data, 0).await.unwrap();
+    accessor.release(req, ino, fh, 0, 0, true).await.unwrap();
+    ino
+}
+
+async fn read_file(accessor: &FileAccessor, req: &RequestContext, path: &str) -> (FileAttr, Vec<u8>) {
+    let attr = accessor.search(req, path).await.unwrap().unwrap();
+    let fh = accessor.open(req, attr.ino, O_RDONLY as u32).await.unwrap().fh;
+    let mut ret = Vec::new();
+    while (ret.len() as u64) < attr.size {
+        let data = accessor
+            .read(req, attr.ino, fh, ret.len() as u64, PAGE_SIZE as u32)
+            .await
+            .unwrap();
+        assert!(data.len() > 0);
+        ret.extend_from_slice(&data[..]);
+    }
+    accessor.release(req, attr.ino, fh, 0, 0, false).await.unwrap();
+    (attr, ret)
+}
+
+#[tokio::test]
+async fn export_and_import_round_trip() {
+    let source = create_accessor("files-archive-src").await;
+    let req = source.session_context();
+    source.init(&req).await.unwrap();
+
+    // A tree with pages that span several pages, a symlink, an extended
+    // attribute and a name that is too long for a plain tar header
+    let big = (0..(PAGE_SIZE * 2 + 10)).map(|a| (a % 251) as u8).collect::<Vec<_>>();
+    let long_name = "n".repeat(120);
+    let site = source.mkdir(&req, 1, "site", 0o755).await.unwrap().ino;
+    let index = write_file(&source, &req, site, "index.html", 0o640, &big[..]).await;
+    source.setxattr(&req, index, "user.note", "hello").await.unwrap();
+    source.symlink(&req, site, "home.html", "index.html").await.unwrap();
+    let assets = source.mkdir(&req, site, "assets", 0o750).await.unwrap().ino;
+    write_file(&source, &req, assets, long_name.as_str(), 0o600, b"body {}").await;
+
+    let path = std::env::temp_dir().join(format!("files-archive-{}.tar", fastrand::u64(..)));
+    let stats = source
+        .export_tar(&req, "/site", BufWriter::new(File::create(&path).unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.dirs, 1);
+    assert_eq!(stats.links, 1);
+    assert_eq!(stats.bytes, big.len() as u64 + 7);
+
+    let dest = create_accessor("files-archive-dst").await;
+    let req = dest.session_context();
+    dest.init(&req).await.unwrap();
+    let stats = dest
+        .import_tar(&req, "/restored", BufReader::new(File::open(&path).unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.dirs, 1);
+    assert_eq!(stats.links, 1);
+    std::fs::remove_file(&path).unwrap();
+
+    let (attr, data) = read_file(&dest, &req, "/restored/index.html").await;
+    assert_eq!(data, big);
+    assert_eq!(attr.mode & 0o7777, 0o640);
+    assert_eq!(
+        dest.getxattr(&req, attr.ino, "user.note").await.unwrap(),
+        Some("hello".to_string())
+    );
+
+    let (attr, data) = read_file(&dest, &req, format!("/restored/assets/{}", long_name).as_str()).await;
+    assert_eq!(data, b"body {}".to_vec());
+    assert_eq!(attr.mode & 0o7777, 0o600);
+    let attr = dest.search(&req, "/restored/assets").await.unwrap().unwrap();
+    assert_eq!(attr.mode & 0o7777, 0o750);
+
+    let attr = dest.search(&req, "/restored/home.html").await.unwrap().unwrap();
+    assert_eq!(attr.kind, FileKind::SymLink);
+    let open = dest.create_open_handle(attr.ino, &req, O_RDONLY).await.unwrap();
+    assert_eq!(open.spec.link(), Some("index.html".to_string()));
+}
+
+#[tokio::test]
+async fn export_needs_a_directory() {
+    let accessor = create_accessor("files-archive-missing").await;
+    let req = accessor.session_context();
+    accessor.init(&req).await.unwrap();
+
+    assert!(accessor.export_tar(&req, "/missing", Vec::new()).await.is_err());
+}
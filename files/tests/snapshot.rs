@@ -0,0 +1,86 @@
+use ate::prelude::*;
+use ate_files::prelude::*;
+use ate_files::snapshot::SNAPSHOTS_DIR;
+
+async fn create_accessor(name: &str) -> FileAccessor {
+    ate::utils::bootstrap_test_env();
+
+    let mut conf = ConfAte::default();
+    conf.configured_for(ConfiguredFor::BestPerformance);
+    let builder = ChainBuilder::new(&conf).await.build();
+    let chain = builder
+        .open(&ChainKey::default().with_temp_name(name.to_string()))
+        .await
+        .unwrap();
+
+    FileAccessor::new(
+        chain,
+        None,
+        AteSessionType::User(AteSessionUser::new()),
+        TransactionScope::Local,
+        TransactionScope::Local,
+        true,
+        false,
+    )
+    .await
+}
+
+async fn list(accessor: &FileAccessor, req: &RequestContext, ino: u64) -> Vec<String> {
+    let handle = accessor.opendir(req, ino, 0).await.unwrap();
+    let ret = handle
+        .children
+        .iter()
+        .map(|a| a.name.clone())
+        .filter(|a| a != "." && a != "..")
+        .collect();
+    accessor.releasedir(req, ino, handle.fh, 0).await.unwrap();
+    ret
+}
+
+#[tokio::test]
+async fn snapshot_readdir_and_rollback() {
+    let accessor = create_accessor("files-snapshot").await;
+    let req = RequestContext::default();
+    accessor.init(&req).await.unwrap();
+
+    accessor.mkdir(&req, 1, "kept", 0o755).await.unwrap();
+    accessor.create_snapshot(&req, "before").await.unwrap();
+
+    // (written straight away so it lands in the same millisecond as the snapshot)
+    accessor.mkdir(&req, 1, "added", 0o755).await.unwrap();
+
+    // The snapshot only shows what existed when it was taken
+    let snapshots = accessor.lookup(&req, 1, SNAPSHOTS_DIR).await.unwrap().unwrap();
+    assert_eq!(list(&accessor, &req, snapshots.ino).await, vec!["before".to_string()]);
+    let before = accessor
+        .lookup(&req, snapshots.ino, "before")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(list(&accessor, &req, before.ino).await, vec!["kept".to_string()]);
+
+    // Snapshots are read-only
+    assert!(accessor.mkdir(&req, before.ino, "nope", 0o755).await.is_err());
+
+    let mut live = list(&accessor, &req, 1).await;
+    live.sort();
+    assert_eq!(live, vec!["added".to_string(), "kept".to_string()]);
+
+    // Rolling back removes what was created afterwards
+    accessor.rollback_snapshot(&req, "before").await.unwrap();
+    assert_eq!(list(&accessor, &req, 1).await, vec!["kept".to_string()]);
+}
+
+#[tokio::test]
+async fn snapshot_names_are_unique() {
+    let accessor = create_accessor("files-snapshot-names").await;
+    let req = RequestContext::default();
+    accessor.init(&req).await.unwrap();
+
+    accessor.create_snapshot(&req, "one").await.unwrap();
+    assert!(accessor.create_snapshot(&req, "one").await.is_err());
+    assert!(accessor.create_snapshot(&req, "a/b").await.is_err());
+
+    accessor.delete_snapshot(&req, "one").await.unwrap();
+    assert!(accessor.get_snapshot("one").await.unwrap().is_none());
+}
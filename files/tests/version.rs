@@ -0,0 +1,124 @@
+use ate::prelude::*;
+use ate_files::codes::*;
+use ate_files::prelude::*;
+use ate_files::version::*;
+use std::time::Duration;
+
+async fn create_accessor(name: &str) -> FileAccessor {
+    ate::utils::bootstrap_test_env();
+
+    let mut conf = ConfAte::default();
+    conf.configured_for(ConfiguredFor::BestPerformance);
+    let builder = ChainBuilder::new(&conf).await.build();
+    let chain = builder
+        .open(&ChainKey::default().with_temp_name(name.to_string()))
+        .await
+        .unwrap();
+
+    FileAccessor::new(
+        chain,
+        None,
+        AteSessionType::User(AteSessionUser::new()),
+        TransactionScope::Local,
+        TransactionScope::Local,
+        true,
+        false,
+    )
+    .await
+}
+
+async fn write(accessor: &FileAccessor, req: &RequestContext, ino: u64, data: &[u8]) {
+    let fh = accessor.open(req, ino, O_RDWR as u32).await.unwrap().fh;
+    accessor.write(req, ino, fh, 0, data, 0).await.unwrap();
+    accessor.fallocate(req, ino, fh, 0, data.len() as u64, 0).await.unwrap();
+    accessor.release(req, ino, fh, 0, 0, true).await.unwrap();
+
+    // (writes that are close together are coalesced into the same version)
+    tokio::time::sleep(Duration::from_millis(VERSION_COALESCE_MS + 100)).await;
+}
+
+async fn read(accessor: &FileAccessor, req: &RequestContext, ino: u64) -> Vec<u8> {
+    let open = accessor.open(req, ino, O_RDONLY as u32).await.unwrap();
+    let ret = accessor
+        .read(req, ino, open.fh, 0, open.attr.size as u32)
+        .await
+        .unwrap();
+    accessor.release(req, ino, open.fh, 0, 0, false).await.unwrap();
+    ret.to_vec()
+}
+
+async fn list(accessor: &FileAccessor, req: &RequestContext, ino: u64) -> Vec<String> {
+    let handle = accessor.opendir(req, ino, 0).await.unwrap();
+    let ret = handle
+        .children
+        .iter()
+        .map(|a| a.name.clone())
+        .filter(|a| a != "." && a != "..")
+        .collect();
+    accessor.releasedir(req, ino, handle.fh, 0).await.unwrap();
+    ret
+}
+
+#[tokio::test]
+async fn versions_are_listed_and_read() {
+    let accessor = create_accessor("files-versions").await;
+    let req = RequestContext::default();
+    accessor.init(&req).await.unwrap();
+
+    let ino = accessor.mknod(&req, 1, "page.html", 0o644).await.unwrap().ino;
+    write(&accessor, &req, ino, b"first").await;
+    write(&accessor, &req, ino, b"second!").await;
+
+    let versions = accessor.versions(&req, ino).await.unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].version, 1);
+    assert_eq!(versions[0].size, 5);
+    assert_eq!(versions[1].version, 2);
+    assert_eq!(versions[1].size, 7);
+    assert!(versions[0].at < versions[1].at);
+
+    // Old contents can be read straight from the history
+    let old = accessor
+        .read_version(&req, ino, versions[0].at, 0, 100)
+        .await
+        .unwrap();
+    assert_eq!(&old[..], b"first");
+    let text = accessor.getxattr(&req, ino, VERSIONS_XATTR).await.unwrap().unwrap();
+    assert_eq!(text.lines().count(), 2);
+
+    // ...or browsed through the hidden `.versions` directory
+    let dir = accessor.lookup(&req, 1, VERSIONS_DIR).await.unwrap().unwrap();
+    assert_eq!(dir.kind, FileKind::Directory);
+    assert_eq!(list(&accessor, &req, dir.ino).await, vec!["page.html".to_string()]);
+    let file = accessor.lookup(&req, dir.ino, "page.html").await.unwrap().unwrap();
+    assert_eq!(
+        list(&accessor, &req, file.ino).await,
+        vec!["1".to_string(), "2".to_string()]
+    );
+    let first = accessor.lookup(&req, file.ino, "1").await.unwrap().unwrap();
+    assert_eq!(read(&accessor, &req, first.ino).await, b"first".to_vec());
+
+    // The old versions are read-only
+    assert!(accessor.open(&req, first.ino, O_RDWR as u32).await.is_err());
+    assert!(accessor.mknod(&req, file.ino, "3", 0o644).await.is_err());
+
+    // Restoring writes the old contents back as a new version
+    accessor.restore_version(&req, ino, versions[0].at).await.unwrap();
+    assert_eq!(read(&accessor, &req, ino).await, b"first".to_vec());
+    assert_eq!(accessor.versions(&req, ino).await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn real_entries_hide_the_versions_directory() {
+    let accessor = create_accessor("files-versions-shadow").await;
+    let req = RequestContext::default();
+    accessor.init(&req).await.unwrap();
+
+    let ino = accessor.mknod(&req, 1, VERSIONS_DIR, 0o644).await.unwrap().ino;
+    write(&accessor, &req, ino, b"not history").await;
+
+    let found = accessor.lookup(&req, 1, VERSIONS_DIR).await.unwrap().unwrap();
+    assert_eq!(found.ino, ino);
+    assert_eq!(found.kind, FileKind::RegularFile);
+    assert_eq!(read(&accessor, &req, ino).await, b"not history".to_vec());
+}
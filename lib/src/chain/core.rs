@@ -88,6 +88,10 @@ impl<'a> Chain {
         self.remote_addr.as_ref()
     }
 
+    pub fn compact_mode(&'a self) -> crate::compact::CompactMode {
+        self.cfg_ate.compact_mode
+    }
+
     pub async fn single(&'a self) -> ChainSingleUser<'a> {
         ChainSingleUser::new(self).await
     }
@@ -278,6 +278,94 @@ impl Dio {
         }
     }
 
+    /// Loads an older version of a data object directly from an event that
+    /// is in the history of the chain (the result is not cached)
+    pub async fn load_version<D>(self: &Arc<Self>, leaf: EventLeaf) -> Result<Dao<D>, LoadError>
+    where
+        D: DeserializeOwned,
+    {
+        self.run_async(self.__load_version(leaf)).await
+    }
+
+    pub(super) async fn __load_version<D>(
+        self: &Arc<Self>,
+        leaf: EventLeaf,
+    ) -> Result<Dao<D>, LoadError>
+    where
+        D: DeserializeOwned,
+    {
+        let evt = self.multi.load(leaf).await?;
+        let header = evt.header.as_header()?;
+        let mut data = evt.data;
+
+        let session = self.session();
+        data.data_bytes = match data.data_bytes {
+            Some(data) => Some(self.multi.data_as_overlay(&header.meta, data, session.as_ref())?),
+            None => None,
+        };
+
+        let (row_header, row) = Row::from_event(self, &data, leaf.created, leaf.updated)?;
+        Ok(Dao::new(self, row_header, row))
+    }
+
+    /// Loads a data object as it was at a particular point in time
+    pub async fn load_at<D>(
+        self: &Arc<Self>,
+        pit: &ChainPointInTime,
+        key: &PrimaryKey,
+    ) -> Result<Dao<D>, LoadError>
+    where
+        D: DeserializeOwned,
+    {
+        let leaf = match pit.lookup_primary(key) {
+            Some(a) => a,
+            None => bail!(LoadErrorKind::NotFound(key.clone())),
+        };
+        self.load_version(leaf).await
+    }
+
+    /// Returns all the events that wrote a data object in the order they were written
+    pub async fn history(self: &Arc<Self>, key: &PrimaryKey) -> Result<Vec<EventHistory>, LoadError> {
+        Ok(self.multi.lookup_history(key).await?)
+    }
+
+    /// Returns the history of many data objects using a single pass over the chain
+    pub async fn history_many(
+        self: &Arc<Self>,
+        keys: &FxHashSet<PrimaryKey>,
+    ) -> Result<FxHashMap<PrimaryKey, Vec<EventHistory>>, LoadError> {
+        Ok(self.multi.lookup_history_many(keys).await?)
+    }
+
+    /// Captures the indexes of the chain as they stood at a point in time
+    /// (optionally stopping at a particular event within that millisecond)
+    pub async fn point_in_time(
+        self: &Arc<Self>,
+        at: ChainTimestamp,
+        until: Option<AteHash>,
+    ) -> Result<ChainPointInTime, LoadError> {
+        Ok(self.multi.point_in_time(at, until).await?)
+    }
+
+    /// Returns the timestamp and hash of the most recent event in the chain
+    pub async fn timeline_anchor(self: &Arc<Self>) -> (ChainTimestamp, Option<AteHash>) {
+        self.multi.timeline_anchor().await
+    }
+
+    pub async fn children_keys_at(
+        self: &Arc<Self>,
+        pit: &ChainPointInTime,
+        parent_id: PrimaryKey,
+        collection_id: u64,
+    ) -> Vec<PrimaryKey> {
+        let collection_key = MetaCollection {
+            parent_id,
+            collection_id,
+        };
+        pit.lookup_secondary_raw(&collection_key)
+            .unwrap_or_default()
+    }
+
     pub async fn children_keys(
         self: &Arc<Self>,
         parent_id: PrimaryKey,
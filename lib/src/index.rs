@@ -178,6 +178,85 @@ impl BinaryTreeIndexer {
     }
 }
 
+/// Describes one of the events that wrote a particular data object at some
+/// point in the history of the chain (used to browse and restore old versions)
+#[derive(Debug, Clone)]
+pub struct EventHistory {
+    pub leaf: EventLeaf,
+    pub timestamp: ChainTimestamp,
+    pub author: Option<String>,
+    pub tombstone: bool,
+}
+
+impl EventHistory {
+    pub(crate) fn from_header(header: &EventHeader, key: &PrimaryKey, timestamp: ChainTimestamp) -> Option<EventHistory> {
+        let mut is_match = false;
+        let mut tombstone = false;
+        let mut author = None;
+        for core in header.meta.core.iter() {
+            match core {
+                CoreMetadata::Data(a) if a == key => {
+                    is_match = header.raw.data_hash.is_some();
+                }
+                CoreMetadata::Tombstone(a) if a == key => {
+                    is_match = true;
+                    tombstone = true;
+                }
+                CoreMetadata::Author(a) => {
+                    author = Some(a.clone());
+                }
+                _ => {}
+            }
+        }
+        if is_match == false {
+            return None;
+        }
+
+        Some(EventHistory {
+            leaf: EventLeaf {
+                record: header.raw.event_hash.clone(),
+                created: timestamp.time_since_epoch_ms,
+                updated: timestamp.time_since_epoch_ms,
+            },
+            timestamp,
+            author,
+            tombstone,
+        })
+    }
+}
+
+/// Represents the indexes of a chain exactly as they stood at a particular
+/// moment in its history, which allows older trees of data objects to be
+/// walked without copying any of the data
+#[derive(Debug)]
+pub struct ChainPointInTime {
+    pub at: ChainTimestamp,
+    pub(crate) pointers: BinaryTreeIndexer,
+}
+
+impl ChainPointInTime {
+    pub fn lookup_primary(&self, key: &PrimaryKey) -> Option<EventLeaf> {
+        self.pointers.lookup_primary(key)
+    }
+
+    pub fn lookup_parent(&self, key: &PrimaryKey) -> Option<MetaParent> {
+        self.pointers.lookup_parent(key)
+    }
+
+    pub fn lookup_secondary_raw(&self, key: &MetaCollection) -> Option<Vec<PrimaryKey>> {
+        self.pointers.lookup_secondary_raw(key)
+    }
+
+    pub fn exists(&self, key: &PrimaryKey) -> bool {
+        self.pointers.contains_key(key)
+    }
+
+    /// Number of data objects that existed at this point in time
+    pub fn len(&self) -> usize {
+        self.pointers.primary.len()
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct UselessIndexer {}
 
@@ -19,6 +19,8 @@ use super::pipe::*;
 use super::spec::MessageFormat;
 use super::transaction::*;
 use super::trust::*;
+use super::time::ChainTimestamp;
+use super::crypto::AteHash;
 use super::event::MessageBytes;
 
 use bytes::Bytes;
@@ -176,6 +178,81 @@ impl ChainMultiUser {
             .roots_raw()
     }
 
+    /// Returns the timestamp and hash of the most recent event in the chain
+    /// which together identify an exact position in its history
+    pub async fn timeline_anchor(&self) -> (ChainTimestamp, Option<AteHash>) {
+        let guard = self.inside_async.read().await;
+        match guard.range(..).next_back() {
+            Some((timestamp, raw)) => (timestamp.clone(), Some(raw.event_hash.clone())),
+            None => (ChainTimestamp::from(0u64), None),
+        }
+    }
+
+    /// Returns every event in the history of the chain that wrote (or
+    /// deleted) the supplied data object, oldest first
+    pub async fn lookup_history(
+        &self,
+        key: &PrimaryKey,
+    ) -> Result<Vec<EventHistory>, SerializationError> {
+        let guard = self.inside_async.read().await;
+        let mut ret = Vec::new();
+        for (timestamp, raw) in guard.range(..) {
+            let header = raw.as_header()?;
+            if let Some(evt) = EventHistory::from_header(&header, key, timestamp.clone()) {
+                ret.push(evt);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the history of many data objects at once (oldest first) which
+    /// only needs a single pass over the chain
+    pub async fn lookup_history_many(
+        &self,
+        keys: &fxhash::FxHashSet<PrimaryKey>,
+    ) -> Result<fxhash::FxHashMap<PrimaryKey, Vec<EventHistory>>, SerializationError> {
+        let guard = self.inside_async.read().await;
+        let mut ret = fxhash::FxHashMap::default();
+        if keys.is_empty() {
+            return Ok(ret);
+        }
+        for (timestamp, raw) in guard.range(..) {
+            let header = raw.as_header()?;
+            for core in header.meta.core.iter() {
+                let key = match core {
+                    CoreMetadata::Data(a) | CoreMetadata::Tombstone(a) if keys.contains(a) => a,
+                    _ => continue,
+                };
+                if let Some(evt) = EventHistory::from_header(&header, key, timestamp.clone()) {
+                    ret.entry(key.clone()).or_insert_with(Vec::new).push(evt);
+                }
+                break;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Rebuilds the indexes of the chain as they stood at a particular point
+    /// in time by replaying the event history up to (and including) it. When
+    /// the hash of the last event is supplied then any events that share its
+    /// timestamp but came after it are left out.
+    pub async fn point_in_time(
+        &self,
+        at: ChainTimestamp,
+        until: Option<AteHash>,
+    ) -> Result<ChainPointInTime, SerializationError> {
+        let guard = self.inside_async.read().await;
+        let mut pointers = BinaryTreeIndexer::default();
+        for (timestamp, raw) in guard.range(..=at.clone()) {
+            let header = raw.as_header()?;
+            pointers.feed(&header);
+            if *timestamp == at && until.as_ref() == Some(&raw.event_hash) {
+                break;
+            }
+        }
+        Ok(ChainPointInTime { at, pointers })
+    }
+
     #[allow(dead_code)]
     pub(crate) fn metadata_lint_many<'a>(
         &self,
@@ -74,6 +74,10 @@ pub use crate::dio::DioSessionGuard;
 pub use crate::dio::DioSessionGuardMut;
 
 pub use crate::multi::ChainMultiUser;
+pub use crate::index::ChainPointInTime;
+pub use crate::index::EventHistory;
+pub use crate::index::EventLeaf;
+pub use crate::time::ChainTimestamp;
 pub use crate::session::AteGroup;
 pub use crate::session::AteGroupRole;
 pub use crate::session::AteRolePurpose;
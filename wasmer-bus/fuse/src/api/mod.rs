@@ -1,6 +1,8 @@
 use serde::*;
 use std::io;
 use std::sync::Arc;
+use wasmer_bus::abi::BusError;
+pub use wasmer_bus::abi::ByteStream;
 #[allow(unused_imports)]
 use wasmer_bus::macros::*;
 
@@ -34,8 +36,8 @@ pub trait OpenedFile {
 pub trait FileIO {
     async fn seek(&self, from: SeekFrom) -> FsResult<u64>;
     async fn flush(&self) -> FsResult<()>;
-    async fn write(&self, data: Vec<u8>) -> FsResult<u64>;
-    async fn read(&self, len: u64) -> FsResult<Vec<u8>>;
+    async fn write(&self, data: ByteStream) -> FsResult<u64>;
+    async fn read(&self, len: u64) -> FsResult<ByteStream>;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -206,6 +208,22 @@ impl Into<io::Error> for FsError {
     }
 }
 
+/// Errors that occur part way through a stream are carried by the stream
+/// itself (as a bus error)
+impl From<FsError> for BusError {
+    fn from(err: FsError) -> BusError {
+        match err {
+            FsError::PermissionDenied => BusError::AccessDenied,
+            FsError::InvalidData | FsError::InvalidInput => BusError::BadRequest,
+            FsError::TimedOut => BusError::DeadlineExceeded,
+            FsError::BrokenPipe | FsError::ConnectionAborted | FsError::ConnectionReset => {
+                BusError::Aborted
+            }
+            _ => BusError::InternalFailure,
+        }
+    }
+}
+
 impl Into<Box<dyn std::error::Error>> for FsError {
     fn into(self) -> Box<dyn std::error::Error> {
         let kind: io::ErrorKind = self.into();
@@ -1894,8 +1912,8 @@ where
 {
     async fn seek(&self, from: SeekFrom) -> FsResult<u64>;
     async fn flush(&self) -> FsResult<()>;
-    async fn write(&self, data: Vec<u8>) -> FsResult<u64>;
-    async fn read(&self, len: u64) -> FsResult<Vec<u8>>;
+    async fn write(&self, data: ByteStream) -> FsResult<u64>;
+    async fn read(&self, len: u64) -> FsResult<ByteStream>;
 }
 #[wasmer_bus::async_trait]
 impl<T> FileIO for T
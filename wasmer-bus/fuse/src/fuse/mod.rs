@@ -2,6 +2,7 @@
 use std::io;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, trace, warn};
 
@@ -12,6 +13,10 @@ pub use crate::api::FsError;
 pub use crate::api::FsResult;
 pub use crate::api::Metadata;
 
+/// Calls to a mounted file system fail (rather than hang) if the process
+/// serving it stops responding for this long
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct FileSystem {
     fs: Arc<dyn api::FileSystem>,
@@ -20,6 +25,7 @@ pub struct FileSystem {
 impl FileSystem {
     pub async fn mount(wapm: &str, name: &str) -> FsResult<FileSystem> {
         let fs = api::FuseClient::new(wapm)
+            .with_timeout(Some(DEFAULT_TIMEOUT))
             .mount(name.to_string())
             .await
             .map_err(|err| {
@@ -40,6 +46,7 @@ impl FileSystem {
         name: &str,
     ) -> FsResult<FileSystem> {
         let fs = api::FuseClient::new_with_instance(wapm, instance, access_token)
+            .with_timeout(Some(DEFAULT_TIMEOUT))
             .mount(name.to_string())
             .await
             .map_err(|err| {
@@ -268,7 +275,7 @@ impl io::Seek for VirtualFile {
 impl io::Write for VirtualFile {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.io
-            .blocking_write(buf.to_vec())
+            .blocking_write(api::ByteStream::from(buf))
             .map_err(|err| err.into_io_error())?
             .map_err(|err| err.into())
             .map(|a| a as usize)
@@ -284,20 +291,12 @@ impl io::Write for VirtualFile {
 
 impl io::Read for VirtualFile {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let data: Result<_, io::Error> = self
-            .io
+        self.io
             .blocking_read(buf.len() as u64)
             .map_err(|err| err.into_io_error())?
-            .map_err(|err| err.into());
-
-        let data = data?;
-        if data.len() <= 0 {
-            return Ok(0usize);
-        }
-
-        let dst = &mut buf[..data.len()];
-        dst.copy_from_slice(&data[..]);
-        Ok(data.len())
+            .map_err(|err| -> io::Error { err.into() })?
+            .blocking_read_full(buf)
+            .map_err(|err| err.into_io_error())
     }
 }
 
@@ -350,7 +349,7 @@ impl VirtualFile {
 
     pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.io
-            .write(buf.to_vec())
+            .write(api::ByteStream::from(buf))
             .await
             .map_err(|err| err.into_io_error())?
             .map_err(|err| err.into())
@@ -366,20 +365,33 @@ impl VirtualFile {
     }
 
     pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let data: Result<_, io::Error> = self
-            .io
+        self.io
             .read(buf.len() as u64)
             .await
             .map_err(|err| err.into_io_error())?
-            .map_err(|err| err.into());
+            .map_err(|err| -> io::Error { err.into() })?
+            .read_full(buf)
+            .await
+            .map_err(|err| err.into_io_error())
+    }
 
-        let data = data?;
-        if data.len() <= 0 {
-            return Ok(0usize);
-        }
+    /// Streams the file from the current position (up to `len` bytes)
+    /// without holding it in memory all at once
+    pub async fn read_stream(&mut self, len: u64) -> io::Result<api::ByteStream> {
+        self.io
+            .read(len)
+            .await
+            .map_err(|err| err.into_io_error())?
+            .map_err(|err| err.into())
+    }
 
-        let dst = &mut buf[..data.len()];
-        dst.copy_from_slice(&data[..]);
-        Ok(data.len())
+    /// Writes a stream to the file at the current position, returning how
+    /// many bytes were written
+    pub async fn write_stream(&mut self, data: api::ByteStream) -> io::Result<u64> {
+        self.io
+            .write(data)
+            .await
+            .map_err(|err| err.into_io_error())?
+            .map_err(|err| err.into())
     }
 }
@@ -9,3 +9,4 @@ pub use crate::fuse::VirtualFile;
 pub use async_trait::async_trait;
 pub use wasmer_bus;
 pub use wasmer_bus::abi::BusError;
+pub use wasmer_bus::abi::ByteStream;
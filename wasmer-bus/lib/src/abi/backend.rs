@@ -0,0 +1,59 @@
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use super::*;
+
+static BACKEND: Lazy<RwLock<Option<Arc<dyn BusBackend>>>> = Lazy::new(|| RwLock::new(None));
+
+/// System calls that the bus is built on, by default these are made to
+/// the runtime (which panics on platforms that have no bus) however a
+/// backend can be installed at runtime to host the bus some other way
+/// (e.g. the in-process `MockBus` that tests run on)
+pub trait BusBackend: Send + Sync {
+    /// Processes the events that are waiting (returns how many there were)
+    fn bus_poll_once(&self, timeout: Duration) -> usize;
+
+    fn bus_open_local(&self, name: &str, reuse: bool) -> Result<BusHandle, BusError>;
+
+    fn bus_open_remote(
+        &self,
+        name: &str,
+        reuse: bool,
+        instance: &str,
+        token: &str,
+    ) -> Result<BusHandle, BusError>;
+
+    fn bus_call(
+        &self,
+        bid: BusHandle,
+        topic_hash: u128,
+        request: &[u8],
+        format: SerializationFormat,
+    ) -> Result<CallHandle, BusError>;
+
+    fn bus_subcall(
+        &self,
+        parent: CallHandle,
+        topic_hash: u128,
+        request: &[u8],
+        format: SerializationFormat,
+    ) -> Result<CallHandle, BusError>;
+
+    fn call_close(&self, handle: CallHandle);
+
+    fn call_fault(&self, handle: CallHandle, error: BusError);
+
+    fn call_reply(&self, handle: CallHandle, response: &[u8], format: SerializationFormat);
+}
+
+/// Routes the system calls of the bus to this backend (or back to the
+/// runtime when `None` is passed)
+pub fn set_backend(backend: Option<Arc<dyn BusBackend>>) {
+    *BACKEND.write().unwrap() = backend;
+}
+
+pub(crate) fn backend() -> Option<Arc<dyn BusBackend>> {
+    BACKEND.read().unwrap().clone()
+}
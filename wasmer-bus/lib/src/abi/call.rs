@@ -12,6 +12,8 @@ use std::task::{
     Context,
     Poll
 };
+use std::time::Duration;
+use std::time::Instant;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, trace, warn};
 
@@ -202,12 +204,17 @@ impl CallOps for Call {
     }
 }
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 #[must_use = "you must 'invoke' the builder for it to actually call anything"]
 pub struct CallBuilder {
     call: Option<Call>,
     format: SerializationFormat,
     request: Data,
+    deadline: Option<Instant>,
+    metadata: bool,
+    #[derivative(Debug = "ignore")]
+    on_invoke: Vec<Box<dyn FnOnce(CallHandle) + Send + 'static>>,
 }
 
 impl CallBuilder {
@@ -216,6 +223,9 @@ impl CallBuilder {
             call: Some(call),
             format,
             request,
+            deadline: None,
+            metadata: true,
+            on_invoke: Vec::new(),
         }
     }
 }
@@ -236,19 +246,86 @@ impl CallBuilder {
         self
     }
 
+    /// The call fails with `DeadlineExceeded` (and is cancelled) if it
+    /// has not finished by this time - calls made while processing another
+    /// call also inherit its deadline
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(match self.deadline {
+            Some(a) => a.min(deadline),
+            None => deadline,
+        });
+        self
+    }
+
+    /// Limits how long the call may take (no limit when `None`)
+    pub fn timeout(self, timeout: impl Into<Option<Duration>>) -> Self {
+        match timeout.into() {
+            Some(timeout) => self.deadline(Instant::now() + timeout),
+            None => self,
+        }
+    }
+
+    /// Frames of a stream are part of the call that the stream belongs to
+    /// and hence they carry no metadata of their own
+    pub(crate) fn without_metadata(mut self) -> Self {
+        self.metadata = false;
+        self
+    }
+
+    /// Passes a stream as an argument of the call, the items are sent after
+    /// the call is made as fast as the callee is willing to receive them
+    pub fn send_stream<T>(self, stream: MessageStream<T>) -> Self
+    where
+        T: Serialize + Send + 'static,
+    {
+        let (credits, callback) = stream_credits();
+        let mut ret = self.callback(callback);
+        let format = ret.format;
+        ret.on_invoke.push(Box::new(move |handle| {
+            crate::task::spawn(pump_stream(handle, format, stream, credits));
+        }));
+        ret
+    }
+
+    /// Invokes a call that returns a stream, the stream fails if the call
+    /// does
+    pub fn invoke_stream<T>(self) -> MessageStream<T>
+    where
+        T: de::DeserializeOwned + Send + 'static,
+    {
+        let format = self.format;
+        let (callback, tx, rx) = receive_stream::<T>(format);
+        let call = self.callback(callback).invoke();
+        returned_stream(call, format, tx, rx)
+    }
+
+    /// Invokes a call that returns either a stream or the error that stopped
+    /// the method from producing one (e.g. `Result<ByteStream, E>`)
+    pub async fn invoke_result_stream<T, E>(self) -> Result<Result<MessageStream<T>, E>, BusError>
+    where
+        T: de::DeserializeOwned + Send + 'static,
+        E: de::DeserializeOwned + Send + 'static,
+    {
+        let format = self.format;
+        let (callback, tx, rx) = receive_stream::<T>(format);
+        let call = self.callback(callback).invoke();
+        returned_result_stream(call, format, tx, rx).await
+    }
+
     // Invokes the call and detaches it so that it can be
-    // using a contextual session
+    // using a contextual session (which lives on after the call
+    // returns so it has no deadline)
     pub fn detach(mut self) -> Result<CallHandle, BusError>
     {
         let mut call = self.call.take().unwrap();
-        let handle = self.invoke_internal(&mut call)?;
+        let handle = self.invoke_internal(&mut call, true)?;
         Ok(handle)
     }
 
     /// Invokes the call with the specified callbacks
     pub fn invoke(mut self) -> Call {
         let mut call = self.call.take().unwrap();
-        match self.invoke_internal(&mut call) {
+        match self.invoke_internal(&mut call, false) {
             Ok(scope) => {
                 call.handle.replace(scope);
             },
@@ -259,7 +336,17 @@ impl CallBuilder {
         call
     }
 
-    fn invoke_internal(&self, call: &mut Call) -> Result<CallHandle, BusError> {
+    fn invoke_internal(&mut self, call: &mut Call, detached: bool) -> Result<CallHandle, BusError> {
+        let mut metadata = CallMetadata::new(self.deadline);
+        if detached {
+            metadata.deadline = None;
+        }
+
+        // The deadline is checked up front as the call would only time out
+        if metadata.remaining() == Some(Duration::ZERO) {
+            return Err(BusError::DeadlineExceeded);
+        }
+
         let handle = match &self.request {
             Data::Prepared(req) => {
                 match &call.ctx {
@@ -312,13 +399,57 @@ impl CallBuilder {
         };
 
         if let Ok(scope) = &handle {
-            let mut state = crate::engine::BusEngine::write();
-            state.handles.insert(scope.clone());
-            state.calls.insert(scope.clone(), Arc::new(call.clone()));
-            call.handle.replace(scope.clone());
+            {
+                let mut state = crate::engine::BusEngine::write();
+                state.handles.insert(scope.clone());
+                state.calls.insert(scope.clone(), Arc::new(call.clone()));
+                call.handle.replace(scope.clone());
+            }
+            // Callbacks to our own caller are not calls in their own right
+            let is_callback = match &call.ctx {
+                CallContext::SubCall { parent } => crate::engine::BusEngine::is_inbound(parent),
+                _ => false,
+            };
+            if is_callback == false && self.metadata {
+                if let Some(deadline) = metadata.deadline_instant() {
+                    crate::engine::watch_deadline(scope.clone(), deadline, crate::engine::DeadlineAction::Fail);
+                }
+                Self::send_metadata(scope.clone(), &metadata);
+            }
+            for on_invoke in self.on_invoke.drain(..) {
+                on_invoke(scope.clone());
+            }
         }
         handle
     }
+
+    /// Sends the metadata to the callee (callees that do not understand it
+    /// simply fault the sub-call which is ignored)
+    fn send_metadata(handle: CallHandle, metadata: &CallMetadata) {
+        trace!(
+            "wasmer_bus_call (handle={}, trace_id={:032x}, span_id={:016x}, parent_span_id={}, deadline={:?})",
+            handle.id,
+            metadata.trace_id,
+            metadata.span_id,
+            metadata.parent_span_id.map(|a| format!("{:016x}", a)).unwrap_or_else(|| "none".to_string()),
+            metadata.remaining()
+        );
+        let data = match METADATA_FORMAT.serialize(metadata) {
+            Ok(a) => a,
+            Err(err) => {
+                debug!("failed to serialize the call metadata - {}", err);
+                return;
+            }
+        };
+        if let Err(err) = crate::abi::syscall::bus_subcall(
+            handle,
+            crate::engine::metadata_topic_hash(),
+            &data[..],
+            METADATA_FORMAT,
+        ) {
+            trace!("failed to send the call metadata (handle={}) - {}", handle.id, err);
+        }
+    }
 }
 
 impl Call {
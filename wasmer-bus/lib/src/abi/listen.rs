@@ -62,7 +62,7 @@ impl ListenService {
         let res = callback.as_ref()(handle, request);
 
         let mut leak = false;
-        match res.await {
+        match crate::abi::scope_call(handle, res).await {
             ListenAction::Response(a) => {
                 crate::abi::syscall::call_reply(handle, &a[..], format);
             }
@@ -0,0 +1,241 @@
+use serde::*;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+use tracing::Instrument;
+
+use super::*;
+
+/// Format that the metadata of a call is sent in
+pub(crate) const METADATA_FORMAT: SerializationFormat = SerializationFormat::Json;
+
+tokio::task_local! {
+    /// Call that the current task is processing (set while a listener or
+    /// responder is running)
+    static CURRENT_CALL: CallHandle;
+}
+
+/// Metadata that travels with a call (as a sub-call made immediately
+/// after it, as the bus ABI has no room for it in the call itself)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CallMetadata {
+    /// Time (in milliseconds since the unix epoch) after which the caller
+    /// is no longer waiting for the response
+    pub deadline: Option<u64>,
+    /// Identifies the tree of calls that this call is a part of
+    pub trace_id: u128,
+    /// Identifies this particular call
+    pub span_id: u64,
+    /// Call that made this call (when it was made while processing another)
+    pub parent_span_id: Option<u64>,
+}
+
+impl CallMetadata {
+    /// Creates the metadata for a new call, which continues the trace of
+    /// the call being processed by the current task (if there is one)
+    pub(crate) fn new(deadline: Option<Instant>) -> CallMetadata {
+        let parent = current_metadata();
+        let deadline = deadline.map(to_unix_millis);
+        let deadline = match (deadline, parent.as_ref().and_then(|p| p.deadline)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        CallMetadata {
+            deadline,
+            trace_id: match parent.as_ref() {
+                Some(parent) => parent.trace_id,
+                None => ((new_id() as u128) << 64) | (new_id() as u128),
+            },
+            span_id: new_id(),
+            parent_span_id: parent.map(|p| p.span_id),
+        }
+    }
+
+    /// Time left until the deadline passes (zero when it already has)
+    pub fn remaining(&self) -> Option<Duration> {
+        let deadline = UNIX_EPOCH + Duration::from_millis(self.deadline?);
+        Some(
+            deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+
+    /// The deadline as an instant on the local clock
+    pub fn deadline_instant(&self) -> Option<Instant> {
+        self.remaining().map(|r| Instant::now() + r)
+    }
+}
+
+fn to_unix_millis(instant: Instant) -> u64 {
+    let now = Instant::now();
+    let time = match instant.checked_duration_since(now) {
+        Some(remaining) => SystemTime::now() + remaining,
+        None => SystemTime::now() - now.duration_since(instant),
+    };
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn new_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+    );
+    hasher.finish()
+}
+
+/// Span that the processing of a call is logged under, the IDs let the
+/// logs of different processes be stitched together into a call tree
+fn call_span(handle: CallHandle) -> tracing::Span {
+    match call_metadata(handle) {
+        Some(metadata) => tracing::debug_span!(
+            "bus_call",
+            handle = handle.id,
+            trace_id = %format!("{:032x}", metadata.trace_id),
+            span_id = %format!("{:016x}", metadata.span_id),
+            parent_span_id = ?metadata.parent_span_id.map(|a| format!("{:016x}", a)),
+        ),
+        None => tracing::debug_span!("bus_call", handle = handle.id),
+    }
+}
+
+/// Runs a handler with the call it is processing made available to it
+/// (and to any calls that it makes in turn)
+pub(crate) async fn scope_call<F>(handle: CallHandle, task: F) -> F::Output
+where
+    F: Future,
+{
+    let span = call_span(handle);
+    CURRENT_CALL.scope(handle, task.instrument(span)).await
+}
+
+/// Returns the call that the current task is processing
+pub fn current_call() -> Option<CallHandle> {
+    CURRENT_CALL.try_with(|h| h.clone()).ok()
+}
+
+/// Returns the metadata that the caller sent along with a call
+pub fn call_metadata(handle: CallHandle) -> Option<CallMetadata> {
+    crate::engine::BusEngine::metadata(handle)
+}
+
+/// Returns the metadata of the call that the current task is processing
+pub fn current_metadata() -> Option<CallMetadata> {
+    current_call().and_then(call_metadata)
+}
+
+/// Returns true if the caller has given up on the call that the current
+/// task is processing (it closed the call or its deadline has passed)
+pub fn is_cancelled() -> bool {
+    match current_call() {
+        Some(handle) => crate::engine::BusEngine::is_cancelled(handle),
+        None => false,
+    }
+}
+
+/// Completes when the caller gives up on the call that the current task
+/// is processing - long running handlers should select on this so that
+/// they can stop early (cancellation is cooperative)
+pub async fn cancelled() {
+    let handle = match current_call() {
+        Some(a) => a,
+        None => {
+            std::future::pending::<()>().await;
+            return;
+        }
+    };
+    let mut rx = match crate::engine::BusEngine::cancellation(handle) {
+        Some(a) => a,
+        None => return,
+    };
+    while *rx.borrow() == false {
+        if rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{BusEngine, InboundCall};
+    use tokio::sync::watch;
+
+    fn processing(handle: CallHandle, metadata: CallMetadata) {
+        let (cancel, _) = watch::channel(false);
+        let inbound = InboundCall {
+            metadata: Some(metadata),
+            cancel,
+        };
+        BusEngine::write().inbound.insert(handle, inbound);
+    }
+
+    #[test]
+    fn deadlines_round_trip_through_unix_time() {
+        for offset in [Duration::from_millis(500), Duration::from_secs(3600)] {
+            let deadline = Instant::now() + offset;
+            let metadata = CallMetadata::new(Some(deadline));
+            let back = metadata.deadline_instant().unwrap();
+            let skew = match back > deadline {
+                true => back - deadline,
+                false => deadline - back,
+            };
+            assert!(skew < Duration::from_millis(10), "skew of {:?}", skew);
+        }
+    }
+
+    #[test]
+    fn passed_deadlines_have_nothing_remaining() {
+        let past = Instant::now() - Duration::from_secs(1);
+        let metadata = CallMetadata::new(Some(past));
+        assert_eq!(metadata.remaining(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn new_calls_start_a_trace() {
+        let a = CallMetadata::new(None);
+        let b = CallMetadata::new(None);
+        assert_eq!(a.deadline, None);
+        assert_eq!(a.parent_span_id, None);
+        assert_ne!(a.trace_id, b.trace_id);
+        assert_ne!(a.span_id, b.span_id);
+    }
+
+    #[tokio::test]
+    async fn nested_calls_continue_the_trace() {
+        let handle: CallHandle = (u64::MAX - 100).into();
+        let parent = CallMetadata::new(Some(Instant::now() + Duration::from_secs(1)));
+        processing(handle, parent.clone());
+
+        let (sooner, later) = CURRENT_CALL
+            .scope(handle, async {
+                (
+                    CallMetadata::new(Some(Instant::now() + Duration::from_millis(100))),
+                    CallMetadata::new(Some(Instant::now() + Duration::from_secs(60))),
+                )
+            })
+            .await;
+        BusEngine::write().inbound.remove(&handle);
+
+        for child in [&sooner, &later] {
+            assert_eq!(child.trace_id, parent.trace_id);
+            assert_eq!(child.parent_span_id, Some(parent.span_id));
+            assert_ne!(child.span_id, parent.span_id);
+        }
+
+        // The caller stops waiting at the earlier of the two deadlines
+        assert!(sooner.deadline < parent.deadline);
+        assert_eq!(later.deadline, parent.deadline);
+    }
+}
@@ -1,31 +1,36 @@
+mod backend;
 mod call;
 mod data;
 mod finish;
 mod handle;
 mod listen;
+mod metadata;
 mod reply;
 mod respond_to;
 mod session;
-#[cfg(target_os = "wasi")]
+mod stream;
 pub(crate) mod syscall;
 #[cfg(not(target_os = "wasi"))]
-pub(crate) mod unsupported;
-
-#[cfg(not(target_os = "wasi"))]
-pub(crate) use unsupported as syscall;
+mod unsupported;
+#[cfg(target_os = "wasi")]
+mod wasix;
 
 use std::any::type_name;
 use std::borrow::Cow;
 
+pub use backend::BusBackend;
+pub use backend::set_backend;
 pub use call::*;
 pub use data::*;
 pub use finish::*;
 pub use handle::*;
 pub use listen::*;
+pub use metadata::*;
 pub use reply::*;
 pub use respond_to::*;
 use serde::Serialize;
 pub use session::*;
+pub use stream::*;
 
 pub use wasmer_bus_types::*;
 
@@ -82,7 +82,7 @@ impl RespondToService {
 
         let mut leak = false;
         let res = callback.as_ref()(handle, request);
-        match res.await {
+        match crate::abi::scope_call(handle, res).await {
             RespondAction::Response(a) => {
                 crate::abi::syscall::call_reply(handle, &a[..], format);
             }
@@ -0,0 +1,800 @@
+use serde::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use tokio::sync::Semaphore;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use super::*;
+
+/// Stream of messages that is passed to (or returned from) a method of
+/// another module - items are sent as they are produced and the sender
+/// is held back when the reader falls behind
+pub struct MessageStream<T> {
+    pending: VecDeque<T>,
+    rx: Option<mpsc::Receiver<Result<T, BusError>>>,
+    /// Invoked whenever an item is taken from the channel (grants more
+    /// credit to the sender on the other end of the bus)
+    on_read: Option<Box<dyn FnMut() + Send + Sync + 'static>>,
+}
+
+impl<T> MessageStream<T> {
+    /// Creates a stream that is fed by the returned sender, the sender will
+    /// wait when the stream holds `STREAM_WINDOW` items that are unread
+    pub fn channel() -> (StreamSender<T>, MessageStream<T>) {
+        let (tx, rx) = mpsc::channel(STREAM_WINDOW as usize);
+        (StreamSender { tx }, MessageStream::new(rx, None))
+    }
+
+    /// Creates a stream that fails as soon as it is read
+    pub fn error(err: BusError) -> MessageStream<T> {
+        let (tx, rx) = mpsc::channel(1);
+        let _ = tx.try_send(Err(err));
+        MessageStream::new(rx, None)
+    }
+
+    fn new(
+        rx: mpsc::Receiver<Result<T, BusError>>,
+        on_read: Option<Box<dyn FnMut() + Send + Sync + 'static>>,
+    ) -> MessageStream<T> {
+        MessageStream {
+            pending: VecDeque::new(),
+            rx: Some(rx),
+            on_read,
+        }
+    }
+
+    /// Returns the next item of the stream or `None` when it has ended,
+    /// after an error is returned the stream has also ended
+    pub async fn next(&mut self) -> Option<Result<T, BusError>> {
+        if let Some(item) = self.pending.pop_front() {
+            return Some(Ok(item));
+        }
+        let ret = match self.rx.as_mut() {
+            Some(rx) => rx.recv().await,
+            None => None,
+        };
+        match &ret {
+            Some(Ok(_)) => {
+                if let Some(on_read) = self.on_read.as_mut() {
+                    on_read();
+                }
+            }
+            _ => {
+                self.rx.take();
+                self.on_read.take();
+            }
+        }
+        ret
+    }
+
+    pub fn blocking_next(&mut self) -> Option<Result<T, BusError>> {
+        crate::task::block_on(self.next())
+    }
+
+    /// Reads the rest of the stream into memory
+    pub async fn collect(mut self) -> Result<Vec<T>, BusError> {
+        let mut ret = Vec::new();
+        while let Some(item) = self.next().await {
+            ret.push(item?);
+        }
+        Ok(ret)
+    }
+
+    /// Puts an item back so that it is the next one that is read
+    fn push_front(&mut self, item: T) {
+        self.pending.push_front(item);
+    }
+}
+
+impl<T> From<Vec<T>> for MessageStream<T> {
+    fn from(items: Vec<T>) -> MessageStream<T> {
+        MessageStream {
+            pending: items.into(),
+            rx: None,
+            on_read: None,
+        }
+    }
+}
+
+impl<T> fmt::Debug for MessageStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageStream")
+            .field("pending", &self.pending.len())
+            .field("open", &self.rx.is_some())
+            .finish()
+    }
+}
+
+/// Feeds items into a `MessageStream`, the stream ends when all the
+/// senders are dropped
+pub struct StreamSender<T> {
+    tx: mpsc::Sender<Result<T, BusError>>,
+}
+
+impl<T> StreamSender<T> {
+    /// Sends an item, waiting while the stream is full - fails with
+    /// `Aborted` once the reader has dropped the stream
+    pub async fn send(&self, item: T) -> Result<(), BusError> {
+        self.tx.send(Ok(item)).await.map_err(|_| BusError::Aborted)
+    }
+
+    pub fn blocking_send(&self, item: T) -> Result<(), BusError> {
+        crate::task::block_on(self.send(item))
+    }
+
+    /// Ends the stream with an error (which the reader receives after the
+    /// items that were already sent)
+    pub async fn fail(self, err: BusError) {
+        let _ = self.tx.send(Err(err)).await;
+    }
+
+    /// Returns true if the reader has dropped the stream
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl<T> Clone for StreamSender<T> {
+    fn clone(&self) -> Self {
+        StreamSender {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for StreamSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamSender")
+            .field("closed", &self.tx.is_closed())
+            .finish()
+    }
+}
+
+/// Stream of bytes which is sent over the bus in chunks of at most
+/// `STREAM_CHUNK_SIZE` (rather than as one large buffer)
+#[derive(Debug)]
+pub struct ByteStream {
+    inner: MessageStream<Vec<u8>>,
+}
+
+impl ByteStream {
+    /// Creates a stream that is fed by the returned writer
+    pub fn channel() -> (ByteStreamWriter, ByteStream) {
+        let (tx, rx) = MessageStream::channel();
+        (ByteStreamWriter { inner: tx }, ByteStream { inner: rx })
+    }
+
+    /// Creates a stream that fails as soon as it is read
+    pub fn error(err: BusError) -> ByteStream {
+        ByteStream {
+            inner: MessageStream::error(err),
+        }
+    }
+
+    /// Returns the next chunk of bytes or `None` when the stream has ended
+    pub async fn next_chunk(&mut self) -> Option<Result<Vec<u8>, BusError>> {
+        self.inner.next().await
+    }
+
+    /// Reads some bytes into the buffer returning how many were read (zero
+    /// means that the stream has ended)
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, BusError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.inner.next().await {
+                Some(Ok(mut chunk)) => {
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    let amt = chunk.len().min(buf.len());
+                    buf[..amt].copy_from_slice(&chunk[..amt]);
+                    if amt < chunk.len() {
+                        self.inner.push_front(chunk.split_off(amt));
+                    }
+                    return Ok(amt);
+                }
+                Some(Err(err)) => return Err(err),
+                None => return Ok(0),
+            }
+        }
+    }
+
+    /// Fills as much of the buffer as the stream has bytes for
+    pub async fn read_full(&mut self, buf: &mut [u8]) -> Result<usize, BusError> {
+        let mut total = 0usize;
+        while total < buf.len() {
+            let amt = self.read(&mut buf[total..]).await?;
+            if amt == 0 {
+                break;
+            }
+            total += amt;
+        }
+        Ok(total)
+    }
+
+    pub fn blocking_read_full(&mut self, buf: &mut [u8]) -> Result<usize, BusError> {
+        crate::task::block_on(self.read_full(buf))
+    }
+
+    /// Reads the rest of the stream into memory
+    pub async fn read_to_end(mut self) -> Result<Vec<u8>, BusError> {
+        let mut ret = Vec::new();
+        while let Some(chunk) = self.inner.next().await {
+            ret.extend_from_slice(&chunk?[..]);
+        }
+        Ok(ret)
+    }
+}
+
+impl From<Vec<u8>> for ByteStream {
+    fn from(data: Vec<u8>) -> ByteStream {
+        let chunks = data
+            .chunks(STREAM_CHUNK_SIZE)
+            .map(|a| a.to_vec())
+            .collect::<Vec<_>>();
+        ByteStream {
+            inner: chunks.into(),
+        }
+    }
+}
+
+impl From<&[u8]> for ByteStream {
+    fn from(data: &[u8]) -> ByteStream {
+        data.to_vec().into()
+    }
+}
+
+impl From<MessageStream<Vec<u8>>> for ByteStream {
+    fn from(inner: MessageStream<Vec<u8>>) -> ByteStream {
+        ByteStream { inner }
+    }
+}
+
+impl From<ByteStream> for MessageStream<Vec<u8>> {
+    fn from(stream: ByteStream) -> MessageStream<Vec<u8>> {
+        stream.inner
+    }
+}
+
+/// Feeds bytes into a `ByteStream`
+#[derive(Debug, Clone)]
+pub struct ByteStreamWriter {
+    inner: StreamSender<Vec<u8>>,
+}
+
+impl ByteStreamWriter {
+    /// Writes bytes to the stream (split into chunks), waiting while the
+    /// stream is full
+    pub async fn write(&self, data: &[u8]) -> Result<(), BusError> {
+        for chunk in data.chunks(STREAM_CHUNK_SIZE) {
+            self.inner.send(chunk.to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    pub fn blocking_write(&self, data: &[u8]) -> Result<(), BusError> {
+        crate::task::block_on(self.write(data))
+    }
+
+    /// Ends the stream with an error
+    pub async fn fail(self, err: BusError) {
+        self.inner.fail(err).await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+}
+
+/// Sends a frame of a stream (these are part of the call that the stream
+/// belongs to so they carry no metadata of their own)
+pub(crate) fn send_frame<T>(handle: CallHandle, format: SerializationFormat, frame: T)
+where
+    T: Serialize,
+{
+    let _ = subcall(handle, format, frame).without_metadata().invoke();
+}
+
+/// Returns a function that grants the sender more credit as the reader
+/// consumes the frames (in batches so that every frame does not need a
+/// reply of its own)
+fn grant_credits(
+    handle: CallHandle,
+    format: SerializationFormat,
+) -> Box<dyn FnMut() + Send + Sync + 'static> {
+    batch_credits(move |frames| send_frame(handle, format, StreamCredit { frames }))
+}
+
+/// Counts the frames that were read and passes them to `grant` once half
+/// of the window has been consumed
+fn batch_credits<F>(mut grant: F) -> Box<dyn FnMut() + Send + Sync + 'static>
+where
+    F: FnMut(u32) + Send + Sync + 'static,
+{
+    let mut consumed = 0u32;
+    Box::new(move || {
+        consumed += 1;
+        if consumed >= STREAM_WINDOW / 2 {
+            grant(consumed);
+            consumed = 0;
+        }
+    })
+}
+
+/// Sender of a stream that is fed by frames arriving over the bus, if it
+/// is dropped before the stream ended properly (e.g. the call was
+/// closed) then the reader receives an error rather than a truncated
+/// stream that looks complete
+struct InboundStream<T> {
+    tx: Mutex<Option<mpsc::Sender<Result<T, BusError>>>>,
+}
+
+impl<T> InboundStream<T>
+where
+    T: Send + 'static,
+{
+    fn send(&self, item: Result<T, BusError>) {
+        if let Some(tx) = self.tx.lock().unwrap().as_ref() {
+            crate::task::send(tx, item);
+        }
+    }
+
+    fn end(&self, error: Option<BusError>) {
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            if let Some(err) = error {
+                crate::task::send(&tx, Err(err));
+            }
+        }
+    }
+}
+
+impl<T> Drop for InboundStream<T> {
+    fn drop(&mut self) {
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            let _ = tx.try_send(Err(BusError::Aborted));
+        }
+    }
+}
+
+/// Accepts a stream that the caller passes as an argument of a call that
+/// is being processed - the caller is only sent credit (and hence only
+/// starts sending) once the frames can be received
+pub fn accept_stream<T>(handle: CallHandle, format: SerializationFormat) -> MessageStream<T>
+where
+    T: de::DeserializeOwned + Send + 'static,
+{
+    // One extra slot for the error that ends the stream
+    let (tx, rx) = mpsc::channel(STREAM_WINDOW as usize + 1);
+    let inbound = Arc::new(InboundStream {
+        tx: Mutex::new(Some(tx)),
+    });
+
+    {
+        let inbound = inbound.clone();
+        crate::task::respond_to(handle, format, move |_, frame: StreamData| {
+            inbound.send(format.deserialize(frame.data));
+            async move { RespondActionTyped::Response(()) }
+        });
+    }
+    crate::task::respond_to(handle, format, move |_, end: StreamEnd| {
+        inbound.end(end.error);
+        async move { RespondActionTyped::Response(()) }
+    });
+
+    send_frame(
+        handle,
+        format,
+        StreamCredit {
+            frames: STREAM_WINDOW,
+        },
+    );
+    MessageStream::new(rx, Some(grant_credits(handle, format)))
+}
+
+/// Sends a stream that is returned by a call that is being processed back
+/// to the caller, this completes once the whole stream was sent (after
+/// which the call should be answered so that the caller knows it ended)
+pub async fn serve_stream<T>(
+    handle: CallHandle,
+    format: SerializationFormat,
+    mut stream: MessageStream<T>,
+) -> Result<(), BusError>
+where
+    T: Serialize + Send,
+{
+    // The caller registered for the frames before it made the call so we
+    // start off with a full window of credit
+    let credits = Arc::new(Semaphore::new(STREAM_WINDOW as usize));
+    {
+        let credits = credits.clone();
+        crate::task::respond_to(handle, format, move |_, credit: StreamCredit| {
+            credits.add_permits(credit.frames as usize);
+            async move { RespondActionTyped::Response(()) }
+        });
+    }
+
+    let pump = async move {
+        loop {
+            credits
+                .acquire()
+                .await
+                .map_err(|_| BusError::Aborted)?
+                .forget();
+            match stream.next().await {
+                Some(Ok(item)) => {
+                    let data = format.serialize(item)?;
+                    send_frame(handle, format, StreamData { data });
+                }
+                Some(Err(err)) => return Err(err),
+                None => return Ok(()),
+            }
+        }
+    };
+    tokio::select! {
+        ret = pump => ret,
+        _ = crate::abi::cancelled() => Err(BusError::Aborted),
+    }
+}
+
+/// Closes the credit of a stream when the callbacks of its call are
+/// dropped so that the pump stops waiting for credit that never comes
+struct CloseOnDrop(Arc<Semaphore>);
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Returns the semaphore that the pump of a stream which is an argument of
+/// a call waits on, along with the callback that the callee grants the
+/// credit to
+pub(crate) fn stream_credits() -> (
+    Arc<Semaphore>,
+    impl Fn(StreamCredit) + Send + Sync + 'static,
+) {
+    let credits = Arc::new(Semaphore::new(0));
+    let guard = CloseOnDrop(credits.clone());
+    let callback = move |credit: StreamCredit| {
+        guard.0.add_permits(credit.frames as usize);
+    };
+    (credits, callback)
+}
+
+/// Sends a stream that is an argument of a call as the callee grants the
+/// credit for it (the callee always speaks first so that the frames are
+/// not sent before it is ready for them)
+pub(crate) async fn pump_stream<T>(
+    handle: CallHandle,
+    format: SerializationFormat,
+    mut stream: MessageStream<T>,
+    credits: Arc<Semaphore>,
+) where
+    T: Serialize + Send + 'static,
+{
+    let error = loop {
+        match credits.acquire().await {
+            Ok(permit) => permit.forget(),
+            Err(_) => {
+                trace!("stream abandoned (handle={})", handle.id);
+                return;
+            }
+        }
+        match stream.next().await {
+            Some(Ok(item)) => match format.serialize(item) {
+                Ok(data) => send_frame(handle, format, StreamData { data }),
+                Err(err) => break Some(err),
+            },
+            Some(Err(err)) => break Some(err),
+            None => break None,
+        }
+    };
+    send_frame(handle, format, StreamEnd { error });
+}
+
+/// Receives the stream that a call returns - the frames arrive as
+/// callbacks and the stream fails if the call does
+pub(crate) fn receive_stream<T>(
+    format: SerializationFormat,
+) -> (
+    impl Fn(StreamData) + Send + Sync + 'static,
+    mpsc::Sender<Result<T, BusError>>,
+    mpsc::Receiver<Result<T, BusError>>,
+)
+where
+    T: de::DeserializeOwned + Send + 'static,
+{
+    // One extra slot for the error that ends the stream
+    let (tx, rx) = mpsc::channel(STREAM_WINDOW as usize + 1);
+    let callback = {
+        let tx = tx.clone();
+        move |frame: StreamData| {
+            crate::task::send(&tx, format.deserialize(frame.data));
+        }
+    };
+    (callback, tx, rx)
+}
+
+/// Finishes off the stream that a call returns once the call is made
+pub(crate) fn returned_stream<T>(
+    call: Call,
+    format: SerializationFormat,
+    tx: mpsc::Sender<Result<T, BusError>>,
+    rx: mpsc::Receiver<Result<T, BusError>>,
+) -> MessageStream<T>
+where
+    T: Send + 'static,
+{
+    let handle = match call.handle() {
+        Some(a) => a,
+        None => {
+            let err = match call.state.lock().unwrap().result.take() {
+                Some(Err(err)) => err,
+                _ => BusError::BusInvocationFailed,
+            };
+            return MessageStream::error(err);
+        }
+    };
+
+    crate::task::spawn(async move {
+        let ret = match call.join::<()>() {
+            Ok(join) => join.await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = ret {
+            crate::task::send(&tx, Err(err));
+        }
+    });
+    MessageStream::new(rx, Some(grant_credits(handle, format)))
+}
+
+/// Finishes off the stream that a call returns when the method may fail
+/// before it has a stream to return - the callee either answers with the
+/// error straight away or sends the frames of the stream (the call is
+/// only answered once the whole stream has been sent)
+pub(crate) async fn returned_result_stream<T, E>(
+    call: Call,
+    format: SerializationFormat,
+    tx: mpsc::Sender<Result<T, BusError>>,
+    rx: mpsc::Receiver<Result<T, BusError>>,
+) -> Result<Result<MessageStream<T>, E>, BusError>
+where
+    T: Send + 'static,
+    E: de::DeserializeOwned + Send + 'static,
+{
+    let handle = match call.handle() {
+        Some(a) => a,
+        None => {
+            return match call.state.lock().unwrap().result.take() {
+                Some(Err(err)) => Err(err),
+                _ => Err(BusError::BusInvocationFailed),
+            };
+        }
+    };
+    let mut join = Box::pin(call.join::<Result<(), E>>()?);
+    let mut stream = MessageStream::new(rx, Some(grant_credits(handle, format)));
+
+    // The frames are sent before the answer so the first frame means that
+    // the method succeeded
+    tokio::select! {
+        biased;
+        first = stream.next() => match first {
+            Some(Ok(item)) => stream.push_front(item),
+            Some(Err(err)) => return Err(err),
+            None => return Err(BusError::Aborted),
+        },
+        ret = &mut join => {
+            return match ret? {
+                Ok(()) => {
+                    // (the stream was empty or was sent in its entirety)
+                    drop(tx);
+                    Ok(Ok(stream))
+                }
+                Err(err) => Ok(Err(err)),
+            };
+        }
+    }
+
+    crate::task::spawn(async move {
+        if let Err(err) = join.await {
+            crate::task::send(&tx, Err(err));
+        }
+    });
+    Ok(Ok(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn is_pending<F: std::future::Future>(future: F) -> bool {
+        tokio::time::timeout(Duration::from_millis(50), future)
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn sender_waits_when_the_window_is_full() {
+        let (tx, mut rx) = MessageStream::<u32>::channel();
+        for n in 0..STREAM_WINDOW {
+            tx.send(n).await.unwrap();
+        }
+        assert!(is_pending(tx.send(STREAM_WINDOW)).await);
+
+        // Reading an item frees up a slot
+        assert_eq!(rx.next().await.unwrap().unwrap(), 0);
+        tx.send(STREAM_WINDOW).await.unwrap();
+        drop(tx);
+
+        let items = rx.collect().await.unwrap();
+        assert_eq!(items, (1..=STREAM_WINDOW).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn credits_are_granted_in_batches() {
+        let granted = Arc::new(Mutex::new(Vec::new()));
+        let mut on_read = {
+            let granted = granted.clone();
+            batch_credits(move |frames| granted.lock().unwrap().push(frames))
+        };
+        for _ in 0..(STREAM_WINDOW / 2 - 1) {
+            on_read();
+        }
+        assert!(granted.lock().unwrap().is_empty());
+        on_read();
+        assert_eq!(*granted.lock().unwrap(), vec![STREAM_WINDOW / 2]);
+        for _ in 0..STREAM_WINDOW {
+            on_read();
+        }
+        assert_eq!(*granted.lock().unwrap(), vec![STREAM_WINDOW / 2; 3]);
+    }
+
+    #[tokio::test]
+    async fn credits_are_only_granted_for_items() {
+        let (tx, rx) = mpsc::channel(STREAM_WINDOW as usize + 1);
+        let granted = Arc::new(Mutex::new(0u32));
+        let on_read: Box<dyn FnMut() + Send + Sync + 'static> = {
+            let granted = granted.clone();
+            Box::new(move || *granted.lock().unwrap() += 1)
+        };
+        let mut stream = MessageStream::<u32>::new(rx, Some(on_read));
+
+        tx.send(Ok(1)).await.unwrap();
+        tx.send(Ok(2)).await.unwrap();
+        tx.send(Err(BusError::Aborted)).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert_eq!(stream.next().await.unwrap().unwrap(), 2);
+        assert!(matches!(stream.next().await.unwrap(), Err(BusError::Aborted)));
+        assert_eq!(*granted.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn stream_credit_adds_permits_and_closes_on_drop() {
+        let (credits, grant) = stream_credits();
+        assert!(is_pending(credits.acquire()).await);
+
+        grant(StreamCredit { frames: 2 });
+        credits.acquire().await.unwrap().forget();
+        credits.acquire().await.unwrap().forget();
+        assert!(is_pending(credits.acquire()).await);
+
+        // Once the callee goes away the pump stops waiting for credit
+        drop(grant);
+        assert!(credits.acquire().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn errors_end_the_stream_after_the_items() {
+        let (tx, mut rx) = MessageStream::<u32>::channel();
+        let other = tx.clone();
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        tx.fail(BusError::SerializationFailed).await;
+
+        assert_eq!(rx.next().await.unwrap().unwrap(), 1);
+        assert_eq!(rx.next().await.unwrap().unwrap(), 2);
+        assert!(matches!(rx.next().await.unwrap(), Err(BusError::SerializationFailed)));
+
+        // Nothing is read after the error (even if another sender is alive)
+        assert!(rx.next().await.is_none());
+        assert!(matches!(other.send(3).await, Err(BusError::Aborted)));
+    }
+
+    #[tokio::test]
+    async fn collect_fails_on_a_mid_stream_error() {
+        let (tx, rx) = ByteStream::channel();
+        tx.write(b"partial").await.unwrap();
+        tx.fail(BusError::Aborted).await;
+        assert!(matches!(rx.read_to_end().await, Err(BusError::Aborted)));
+
+        let err = MessageStream::<u32>::error(BusError::BusInvocationFailed);
+        assert!(matches!(err.collect().await, Err(BusError::BusInvocationFailed)));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_reader_closes_the_sender() {
+        let (tx, rx) = MessageStream::<u32>::channel();
+        assert!(tx.is_closed() == false);
+        drop(rx);
+        assert!(tx.is_closed());
+        assert!(matches!(tx.send(1).await, Err(BusError::Aborted)));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_senders_ends_the_stream() {
+        let (tx, mut rx) = MessageStream::<u32>::channel();
+        let other = tx.clone();
+        tx.send(1).await.unwrap();
+        drop(tx);
+        other.send(2).await.unwrap();
+        drop(other);
+
+        assert_eq!(rx.next().await.unwrap().unwrap(), 1);
+        assert_eq!(rx.next().await.unwrap().unwrap(), 2);
+        assert!(rx.next().await.is_none());
+        assert!(rx.next().await.is_none());
+    }
+
+    fn inbound() -> (Arc<InboundStream<u32>>, MessageStream<u32>) {
+        let (tx, rx) = mpsc::channel(STREAM_WINDOW as usize + 1);
+        let inbound = Arc::new(InboundStream {
+            tx: Mutex::new(Some(tx)),
+        });
+        (inbound, MessageStream::new(rx, None))
+    }
+
+    #[tokio::test]
+    async fn inbound_stream_that_ends_properly() {
+        let (inbound, stream) = inbound();
+        inbound.send(Ok(1));
+        inbound.send(Ok(2));
+        inbound.end(None);
+
+        // Frames that arrive after the end are ignored
+        inbound.send(Ok(3));
+        drop(inbound);
+        assert_eq!(stream.collect().await.unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn inbound_stream_that_ends_with_an_error() {
+        let (inbound, mut stream) = inbound();
+        inbound.send(Ok(1));
+        inbound.end(Some(BusError::InternalFailure));
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert!(matches!(stream.next().await.unwrap(), Err(BusError::InternalFailure)));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn inbound_stream_dropped_before_the_end_is_aborted() {
+        let (inbound, mut stream) = inbound();
+        inbound.send(Ok(1));
+        drop(inbound);
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert!(matches!(stream.next().await.unwrap(), Err(BusError::Aborted)));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn byte_stream_splits_chunks_across_reads() {
+        let (tx, mut rx) = ByteStream::channel();
+        tx.write(b"hello world").await.unwrap();
+        drop(tx);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(rx.read(&mut buf[..]).await.unwrap(), 4);
+        assert_eq!(&buf[..], b"hell");
+        let mut rest = [0u8; 16];
+        assert_eq!(rx.read_full(&mut rest[..]).await.unwrap(), 7);
+        assert_eq!(&rest[..7], b"o world");
+        assert_eq!(rx.read(&mut rest[..]).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,431 @@
+//! Generates client stubs from the schema of bus interfaces so that
+//! programs that are not written in Rust can invoke them
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::abi::*;
+use crate::schema::topic_hash;
+
+/// Splits a list of types on the commas that are not nested inside
+/// another type
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0usize;
+    for (n, c) in text.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                ret.push(text[start..n].trim());
+                start = n + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = text[start..].trim();
+    if last.len() > 0 {
+        ret.push(last);
+    }
+    ret
+}
+
+fn camel_case(name: &str) -> String {
+    let mut ret = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = ret.len() > 0;
+            continue;
+        }
+        match upper {
+            true => ret.extend(c.to_uppercase()),
+            false => ret.push(c),
+        }
+        upper = false;
+    }
+    ret
+}
+
+/// Converts a Rust type (as it appears in the schema) into the TypeScript
+/// type of its serialized form - types that are defined by the service
+/// are collected into `externals` so that they can be declared
+fn typescript_type(ty: &str, externals: &mut BTreeSet<String>) -> String {
+    let mut ty = ty.trim();
+    if let Some(inner) = ty.strip_prefix('&') {
+        ty = inner.trim_start();
+        if ty.starts_with('\'') {
+            ty = ty.splitn(2, ' ').nth(1).unwrap_or("").trim_start();
+        }
+    }
+
+    // Tuples are serialized as arrays
+    if let Some(inner) = ty.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        let elems = split_top_level(inner);
+        if elems.is_empty() {
+            return "null".to_string();
+        }
+        let elems = elems
+            .into_iter()
+            .map(|e| typescript_type(e, externals))
+            .collect::<Vec<_>>();
+        return format!("[{}]", elems.join(", "));
+    }
+    if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let elem = inner.splitn(2, ';').next().unwrap_or(inner);
+        return format!("{}[]", typescript_type(elem, externals));
+    }
+
+    // Split the path from its generic arguments
+    let (path, args) = match ty.find('<') {
+        Some(n) if ty.ends_with('>') => (&ty[..n], split_top_level(&ty[n + 1..ty.len() - 1])),
+        _ => (ty, Vec::new()),
+    };
+    let name = path.rsplit("::").next().unwrap_or(path).trim();
+    let args = args
+        .into_iter()
+        .filter(|a| a.starts_with('\'') == false)
+        .collect::<Vec<_>>();
+    let mut args = args.into_iter().map(|a| typescript_type(a, externals));
+    match (name, args.len()) {
+        ("bool", 0) => "boolean".to_string(),
+        ("u8" | "u16" | "u32" | "u64" | "u128" | "usize", 0) => "number".to_string(),
+        ("i8" | "i16" | "i32" | "i64" | "i128" | "isize", 0) => "number".to_string(),
+        ("f32" | "f64", 0) => "number".to_string(),
+        ("String" | "str" | "char", 0) => "string".to_string(),
+        ("Box" | "Arc" | "Rc" | "Cow", 1) => args.next().unwrap(),
+        ("Option", 1) => format!("{} | null", args.next().unwrap()),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", 1) => {
+            let elem = args.next().unwrap();
+            match elem.contains(' ') {
+                true => format!("({})[]", elem),
+                false => format!("{}[]", elem),
+            }
+        }
+        ("HashMap" | "BTreeMap", 2) => {
+            format!("{{ [key: string]: {} }}", args.nth(1).unwrap())
+        }
+        ("Result", 2) => {
+            let ok = args.next().unwrap();
+            let err = args.next().unwrap();
+            format!("{{ Ok: {} }} | {{ Err: {} }}", ok, err)
+        }
+        _ => {
+            externals.insert(name.to_string());
+            name.to_string()
+        }
+    }
+}
+
+/// Generates TypeScript client classes for a set of interfaces (normally
+/// an interface and its sub-interfaces, see `schema::closure`)
+///
+/// The classes invoke methods through a `BusCall` which the host
+/// implements on top of the bus of the runtime - the request objects are
+/// serialized using the format named in each call and the topics are the
+/// hashes that the bus expects
+pub fn typescript(schemas: &[InterfaceSchema]) -> String {
+    let mut externals = BTreeSet::new();
+    let mut classes = String::new();
+    for schema in schemas.iter() {
+        let format = schema.format.to_string();
+        let _ = writeln!(classes, "/** Client for `{}` */", schema.path());
+        let _ = writeln!(classes, "export class {}Client {{", schema.name);
+        let _ = writeln!(classes, "  constructor(readonly bus: BusCall) {{}}");
+
+        for method in schema.methods.iter() {
+            if method.has_streams() {
+                let _ = writeln!(classes);
+                let _ = writeln!(
+                    classes,
+                    "  // `{}` passes streams which are not supported by the generated clients",
+                    method.topic
+                );
+                continue;
+            }
+
+            let mut params = Vec::new();
+            let mut fields = Vec::new();
+            let mut callbacks = Vec::new();
+            for arg in method.args.iter() {
+                let ty = typescript_type(arg.ty.as_str(), &mut externals);
+                match &arg.callback {
+                    Some(topic) => {
+                        params.push(format!("{}: (data: {}) => void", arg.name, ty));
+                        callbacks.push(format!(
+                            "\"{}\": (data: unknown) => {}(data as {})",
+                            topic_hash(topic.as_str()),
+                            arg.name,
+                            ty
+                        ));
+                    }
+                    None => {
+                        params.push(format!("{}: {}", arg.name, ty));
+                        fields.push(arg.name.clone());
+                    }
+                }
+            }
+            let request = match fields.is_empty() {
+                true => "{}".to_string(),
+                false => format!("{{ {} }}", fields.join(", ")),
+            };
+            let callbacks = match callbacks.is_empty() {
+                true => "{}".to_string(),
+                false => format!("{{ {} }}", callbacks.join(", ")),
+            };
+            let topic = topic_hash(method.topic.as_str());
+
+            let _ = writeln!(classes);
+            let _ = writeln!(classes, "  /** `{}` */", method.topic);
+            let name = camel_case(method.name.as_str());
+            let params = params.join(", ");
+            match &method.returns {
+                ReturnSchema::Interface { name: sub } => {
+                    let known = schemas.iter().any(|s| &s.name == sub);
+                    let ret = match known {
+                        true => format!("{}Client", sub),
+                        false => "BusCall".to_string(),
+                    };
+                    let _ = writeln!(classes, "  async {}({}): Promise<{}> {{", name, params, ret);
+                    let call = format!(
+                        "await this.bus.open(\"{}\", \"{}\", {}, {})",
+                        topic, format, request, callbacks
+                    );
+                    match known {
+                        true => {
+                            let _ = writeln!(classes, "    return new {}({});", ret, call);
+                        }
+                        false => {
+                            let _ = writeln!(classes, "    return {};", call);
+                        }
+                    }
+                }
+                returns => {
+                    let ret = match returns {
+                        ReturnSchema::Message { ty } => typescript_type(ty.as_str(), &mut externals),
+                        _ => "void".to_string(),
+                    };
+                    let _ = writeln!(classes, "  async {}({}): Promise<{}> {{", name, params, ret);
+                    let _ = writeln!(
+                        classes,
+                        "    return (await this.bus.invoke(\"{}\", \"{}\", {}, {})) as {};",
+                        topic, format, request, callbacks, ret
+                    );
+                }
+            }
+            let _ = writeln!(classes, "  }}");
+        }
+        let _ = writeln!(classes, "}}");
+        let _ = writeln!(classes);
+    }
+
+    let mut ret = String::new();
+    let _ = writeln!(ret, "// Generated from the wasmer-bus interface schema - do not edit");
+    let _ = writeln!(ret);
+    let _ = writeln!(ret, "export type BusCallbacks = {{ [topic: string]: (data: unknown) => void }};");
+    let _ = writeln!(ret);
+    let _ = writeln!(ret, "export interface BusCall {{");
+    let _ = writeln!(ret, "  /** Invokes a method and resolves with its deserialized response */");
+    let _ = writeln!(
+        ret,
+        "  invoke(topic: string, format: string, request: unknown, callbacks: BusCallbacks): Promise<unknown>;"
+    );
+    let _ = writeln!(ret, "  /** Invokes a method that returns another interface */");
+    let _ = writeln!(
+        ret,
+        "  open(topic: string, format: string, request: unknown, callbacks: BusCallbacks): Promise<BusCall>;"
+    );
+    let _ = writeln!(ret, "}}");
+    let _ = writeln!(ret);
+    if externals.is_empty() == false {
+        let _ = writeln!(ret, "// Types defined by the services");
+        for name in externals {
+            let _ = writeln!(ret, "export type {} = unknown;", name);
+        }
+        let _ = writeln!(ret);
+    }
+    ret.push_str(classes.as_str());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(ty: &str) -> String {
+        typescript_type(ty, &mut BTreeSet::new())
+    }
+
+    #[test]
+    fn primitive_types() {
+        assert_eq!(ts("bool"), "boolean");
+        assert_eq!(ts("u64"), "number");
+        assert_eq!(ts("f32"), "number");
+        assert_eq!(ts("String"), "string");
+        assert_eq!(ts("std::string::String"), "string");
+    }
+
+    #[test]
+    fn option_and_collections() {
+        assert_eq!(ts("Option<String>"), "string | null");
+        assert_eq!(ts("Vec<u8>"), "number[]");
+        assert_eq!(ts("Vec<Option<u32>>"), "(number | null)[]");
+        assert_eq!(ts("Vec<Vec<bool>>"), "boolean[][]");
+        assert_eq!(ts("HashMap<String, Vec<u8>>"), "{ [key: string]: number[] }");
+        assert_eq!(ts("Box<Option<bool>>"), "boolean | null");
+    }
+
+    #[test]
+    fn results() {
+        let mut externals = BTreeSet::new();
+        assert_eq!(
+            typescript_type("Result<Vec<u8>, FsError>", &mut externals),
+            "{ Ok: number[] } | { Err: FsError }"
+        );
+        assert_eq!(externals.into_iter().collect::<Vec<_>>(), vec!["FsError".to_string()]);
+        assert_eq!(ts("Result<(), String>"), "{ Ok: null } | { Err: string }");
+    }
+
+    #[test]
+    fn tuples_and_arrays() {
+        assert_eq!(ts("()"), "null");
+        assert_eq!(ts("(u32, String)"), "[number, string]");
+        assert_eq!(ts("(u32, (bool, Vec<u8>))"), "[number, [boolean, number[]]]");
+        assert_eq!(ts("[u8; 32]"), "number[]");
+    }
+
+    #[test]
+    fn references_and_lifetimes() {
+        let mut externals = BTreeSet::new();
+        assert_eq!(typescript_type("&str", &mut externals), "string");
+        assert_eq!(typescript_type("&'a str", &mut externals), "string");
+        assert_eq!(typescript_type("&[u8]", &mut externals), "number[]");
+        assert_eq!(typescript_type("Cow<'static, str>", &mut externals), "string");
+        assert!(externals.is_empty());
+    }
+
+    #[test]
+    fn externals_are_collected() {
+        let mut externals = BTreeSet::new();
+        assert_eq!(typescript_type("crate::api::Entry", &mut externals), "Entry");
+        assert_eq!(typescript_type("Option<Metadata>", &mut externals), "Metadata | null");
+        assert_eq!(
+            externals.into_iter().collect::<Vec<_>>(),
+            vec!["Entry".to_string(), "Metadata".to_string()]
+        );
+    }
+
+    #[test]
+    fn method_names_are_camel_case() {
+        assert_eq!(camel_case("list_entries"), "listEntries");
+        assert_eq!(camel_case("_private"), "private");
+        assert_eq!(camel_case("add"), "add");
+    }
+
+    fn method(name: &str, topic: &str, args: Vec<ArgSchema>, returns: ReturnSchema) -> MethodSchema {
+        MethodSchema {
+            name: name.to_string(),
+            topic: topic.to_string(),
+            args,
+            returns,
+        }
+    }
+
+    fn arg(name: &str, ty: &str, callback: Option<&str>, stream: bool) -> ArgSchema {
+        ArgSchema {
+            name: name.to_string(),
+            ty: ty.to_string(),
+            callback: callback.map(|a| a.to_string()),
+            stream,
+        }
+    }
+
+    #[test]
+    fn typescript_golden() {
+        let schema = InterfaceSchema {
+            name: "Counter".to_string(),
+            module: "demo::api".to_string(),
+            format: SerializationFormat::Raw,
+            methods: vec![
+                method(
+                    "add",
+                    "demo::api::CounterAddRequest",
+                    vec![
+                        arg("amount", "u32", None, false),
+                        arg("on_change", "u64", Some("demo::api::CounterOnChangeCallback"), false),
+                    ],
+                    ReturnSchema::Message {
+                        ty: "Result<u64, String>".to_string(),
+                    },
+                ),
+                method(
+                    "list_entries",
+                    "demo::api::CounterListEntriesRequest",
+                    vec![arg("filter", "Option<String>", None, false)],
+                    ReturnSchema::Message {
+                        ty: "Vec<Entry>".to_string(),
+                    },
+                ),
+                method("reset", "demo::api::CounterResetRequest", vec![], ReturnSchema::Nothing),
+                method(
+                    "session",
+                    "demo::api::CounterSessionRequest",
+                    vec![arg("name", "String", None, false)],
+                    ReturnSchema::Interface {
+                        name: "Session".to_string(),
+                    },
+                ),
+                method(
+                    "watch",
+                    "demo::api::CounterWatchRequest",
+                    vec![],
+                    ReturnSchema::Stream { ty: "u64".to_string() },
+                ),
+            ],
+        };
+
+        let expected = r#"// Generated from the wasmer-bus interface schema - do not edit
+
+export type BusCallbacks = { [topic: string]: (data: unknown) => void };
+
+export interface BusCall {
+  /** Invokes a method and resolves with its deserialized response */
+  invoke(topic: string, format: string, request: unknown, callbacks: BusCallbacks): Promise<unknown>;
+  /** Invokes a method that returns another interface */
+  open(topic: string, format: string, request: unknown, callbacks: BusCallbacks): Promise<BusCall>;
+}
+
+// Types defined by the services
+export type Entry = unknown;
+
+/** Client for `demo::api::Counter` */
+export class CounterClient {
+  constructor(readonly bus: BusCall) {}
+
+  /** `demo::api::CounterAddRequest` */
+  async add(amount: number, on_change: (data: number) => void): Promise<{ Ok: number } | { Err: string }> {
+    return (await this.bus.invoke("70090553769385672910279946326680280015", "raw", { amount }, { "321986869536570828699591978515128342493": (data: unknown) => on_change(data as number) })) as { Ok: number } | { Err: string };
+  }
+
+  /** `demo::api::CounterListEntriesRequest` */
+  async listEntries(filter: string | null): Promise<Entry[]> {
+    return (await this.bus.invoke("312947523436274119647363746605208970338", "raw", { filter }, {})) as Entry[];
+  }
+
+  /** `demo::api::CounterResetRequest` */
+  async reset(): Promise<void> {
+    return (await this.bus.invoke("106304643718675468496988500651910900528", "raw", {}, {})) as void;
+  }
+
+  /** `demo::api::CounterSessionRequest` */
+  async session(name: string): Promise<BusCall> {
+    return await this.bus.open("202480002421834871864742612192653120190", "raw", { name }, {});
+  }
+
+  // `demo::api::CounterWatchRequest` passes streams which are not supported by the generated clients
+}
+
+"#;
+        assert_eq!(typescript(&[schema]), expected);
+    }
+}
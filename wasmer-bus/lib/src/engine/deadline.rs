@@ -0,0 +1,206 @@
+use once_cell::sync::Lazy;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::Once;
+use std::time::Instant;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::abi::*;
+
+use super::BusEngine;
+
+/// What happens to a call when its deadline passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum DeadlineAction {
+    /// A call that we made is failed (and closed so the callee knows)
+    Fail,
+    /// A call that we are processing is marked as cancelled
+    Cancel,
+}
+
+#[derive(Default)]
+struct WatchdogState {
+    queue: BTreeSet<(Instant, CallHandle, DeadlineAction)>,
+    handles: HashMap<CallHandle, (Instant, DeadlineAction)>,
+}
+
+/// Thread that enforces the deadlines of calls (as nothing else will
+/// wake up a caller whose callee has hung)
+#[derive(Default)]
+struct Watchdog {
+    state: Mutex<WatchdogState>,
+    condvar: Condvar,
+}
+
+static WATCHDOG: Lazy<Watchdog> = Lazy::new(|| Watchdog::default());
+#[allow(dead_code)]
+static WATCHDOG_THREAD: Once = Once::new();
+
+pub(crate) fn watch_deadline(handle: CallHandle, deadline: Instant, action: DeadlineAction) {
+    start_watchdog();
+
+    let mut state = WATCHDOG.state.lock().unwrap();
+    if let Some(existing) = state.handles.insert(handle, (deadline, action)) {
+        state.queue.remove(&(existing.0, handle, existing.1));
+    }
+    state.queue.insert((deadline, handle, action));
+    WATCHDOG.condvar.notify_one();
+}
+
+pub(crate) fn unwatch_deadline(handle: &CallHandle) {
+    let mut state = WATCHDOG.state.lock().unwrap();
+    if let Some(existing) = state.handles.remove(handle) {
+        state.queue.remove(&(existing.0, *handle, existing.1));
+    }
+}
+
+/// Threads can only be started natively or on the wasmer runtime (the same
+/// as the reactors of the engine)
+#[cfg(any(not(target_family = "wasm"), all(target_os = "wasi", target_vendor = "wasmer")))]
+fn start_watchdog() {
+    WATCHDOG_THREAD.call_once(|| {
+        std::thread::Builder::new()
+            .name("wasmer-bus-deadline".to_string())
+            .spawn(run)
+            .expect("failed to start the deadline watchdog");
+    });
+}
+
+/// Elsewhere the deadlines are only enforced when calls are polled
+#[cfg(not(any(not(target_family = "wasm"), all(target_os = "wasi", target_vendor = "wasmer"))))]
+fn start_watchdog() {}
+
+/// Expires the calls whose deadline has passed (only needed when there is
+/// no watchdog thread to do it)
+#[cfg(any(not(target_family = "wasm"), all(target_os = "wasi", target_vendor = "wasmer")))]
+pub(crate) fn poll_deadlines() {}
+
+#[cfg(not(any(not(target_family = "wasm"), all(target_os = "wasi", target_vendor = "wasmer"))))]
+pub(crate) fn poll_deadlines() {
+    loop {
+        let due = {
+            let mut state = WATCHDOG.state.lock().unwrap();
+            take_due(&mut state, Instant::now())
+        };
+        match due {
+            Some((handle, action)) => expire(handle, action),
+            None => break,
+        }
+    }
+}
+
+/// Removes the next call whose deadline has passed from the queue
+fn take_due(state: &mut WatchdogState, now: Instant) -> Option<(CallHandle, DeadlineAction)> {
+    let (deadline, handle, action) = state.queue.iter().next().cloned()?;
+    if deadline > now {
+        return None;
+    }
+    state.queue.remove(&(deadline, handle, action));
+    state.handles.remove(&handle);
+    Some((handle, action))
+}
+
+#[allow(dead_code)]
+fn run() {
+    let mut state = WATCHDOG.state.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        if let Some((handle, action)) = take_due(&mut state, now) {
+            drop(state);
+            expire(handle, action);
+            state = WATCHDOG.state.lock().unwrap();
+            continue;
+        }
+        state = match state.queue.iter().next().map(|a| a.0) {
+            Some(deadline) => WATCHDOG.condvar.wait_timeout(state, deadline - now).unwrap().0,
+            None => WATCHDOG.condvar.wait(state).unwrap(),
+        };
+    }
+}
+
+fn expire(handle: CallHandle, action: DeadlineAction) {
+    match action {
+        DeadlineAction::Fail => {
+            debug!("wasmer_bus_deadline (handle={}, call has timed out)", handle.id);
+            BusEngine::error(handle, BusError::DeadlineExceeded);
+            crate::abi::syscall::call_close(handle);
+            BusEngine::close(&handle, "deadline exceeded");
+        }
+        DeadlineAction::Cancel => {
+            debug!("wasmer_bus_deadline (handle={}, request was cancelled)", handle.id);
+            BusEngine::cancel(handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::InboundCall;
+    use std::time::Duration;
+    use tokio::sync::watch;
+
+    // Handles that no real call will ever be given (calls that we made are
+    // failed by the tests of the mock bus as closing them needs a backend)
+    fn test_handle(n: u64) -> CallHandle {
+        (u64::MAX - n).into()
+    }
+
+    fn inbound(handle: CallHandle) -> watch::Receiver<bool> {
+        let (cancel, rx) = watch::channel(false);
+        BusEngine::write().inbound.insert(handle, InboundCall { metadata: None, cancel });
+        rx
+    }
+
+    fn wait_until(what: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if what() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn expired_calls_we_process_are_cancelled() {
+        let handle = test_handle(2);
+        let rx = inbound(handle);
+
+        watch_deadline(handle, Instant::now() + Duration::from_millis(20), DeadlineAction::Cancel);
+        assert!(wait_until(|| *rx.borrow()));
+        assert!(BusEngine::is_cancelled(handle));
+
+        // (the call is only cancelled, it is up to the handler to finish it)
+        assert!(BusEngine::read().inbound.contains_key(&handle));
+        BusEngine::close(&handle, "test");
+    }
+
+    #[test]
+    fn unwatched_deadlines_do_not_expire() {
+        let handle = test_handle(3);
+        let rx = inbound(handle);
+
+        watch_deadline(handle, Instant::now() + Duration::from_millis(20), DeadlineAction::Cancel);
+        unwatch_deadline(&handle);
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(*rx.borrow() == false);
+        BusEngine::close(&handle, "test");
+    }
+
+    #[test]
+    fn watching_again_replaces_the_deadline() {
+        let handle = test_handle(4);
+        let rx = inbound(handle);
+
+        watch_deadline(handle, Instant::now() + Duration::from_secs(3600), DeadlineAction::Cancel);
+        watch_deadline(handle, Instant::now() + Duration::from_millis(20), DeadlineAction::Cancel);
+        assert!(wait_until(|| *rx.borrow()));
+        assert_eq!(WATCHDOG.state.lock().unwrap().handles.contains_key(&handle), false);
+        BusEngine::close(&handle, "test");
+    }
+}
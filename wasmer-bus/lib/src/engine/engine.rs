@@ -12,13 +12,35 @@ use std::sync::RwLockWriteGuard;
 use std::sync::{Arc, MutexGuard};
 use std::task::{Context, Waker};
 use std::{collections::HashMap, collections::HashSet, sync::Mutex};
+use tokio::sync::watch;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, trace, warn};
 
 use crate::abi::*;
 
+use super::deadline::*;
+
 static GLOBAL_ENGINE: Lazy<BusEngine> = Lazy::new(|| BusEngine::default());
 
+static METADATA_TOPIC_HASH: Lazy<u128> =
+    Lazy::new(|| hash_topic(&type_name::<CallMetadata>().into()));
+
+/// Call that this process is processing on behalf of a caller
+#[derive(Debug)]
+pub struct InboundCall {
+    pub metadata: Option<CallMetadata>,
+    pub cancel: watch::Sender<bool>,
+}
+
+impl InboundCall {
+    fn new() -> InboundCall {
+        InboundCall {
+            metadata: None,
+            cancel: watch::channel(false).0,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct BusEngineState {
     pub handles: HashSet<CallHandle>,
@@ -26,15 +48,34 @@ pub struct BusEngineState {
     pub children: HashMap<CallHandle, Vec<CallHandle>>,
     pub listening: HashMap<u128, ListenService>,
     pub respond_to: HashMap<u128, RespondToService>,
+    pub inbound: HashMap<CallHandle, InboundCall>,
+    pub early_metadata: HashMap<CallHandle, CallMetadata>,
     pub initialized_reactors: bool,
 }
 
+impl BusEngineState {
+    /// Starts tracking a call that we are about to process
+    fn accept_inbound(&mut self, handle: CallHandle) {
+        let mut inbound = InboundCall::new();
+        inbound.metadata = self.early_metadata.remove(&handle);
+        if let Some(deadline) = inbound.metadata.as_ref().and_then(|m| m.deadline_instant()) {
+            watch_deadline(handle, deadline, DeadlineAction::Cancel);
+        }
+        self.inbound.insert(handle, inbound);
+    }
+}
+
 #[derive(Default)]
 pub struct BusEngine {
     state: RwLock<BusEngineState>,
     wakers: Mutex<HashMap<CallHandle, Waker>>,
 }
 
+/// Hash of the topic that the metadata of calls is sent on
+pub(crate) fn metadata_topic_hash() -> u128 {
+    *METADATA_TOPIC_HASH
+}
+
 // Function that hashes the topic using SHA256
 pub(crate) fn hash_topic(topic: &Cow<'static, str>) -> u128 {
     use sha2::{Sha256, Digest};
@@ -82,6 +123,20 @@ impl BusEngine {
         request: Vec<u8>,
         format: SerializationFormat,
     ) -> Result<(), BusError> {
+        if let Some(parent) = parent {
+            // The metadata of a call arrives as a sub-call of it
+            if topic_hash == metadata_topic_hash() {
+                syscall::call_close(handle);
+                return match format.deserialize(request) {
+                    Ok(metadata) => {
+                        BusEngine::record_metadata(parent, metadata);
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                };
+            }
+        }
+
         let state = BusEngine::read();
         if let Some(parent) = parent {
             if let Some(parent) = state.calls.get(&parent) {
@@ -102,6 +157,7 @@ impl BusEngine {
                 let mut state = BusEngine::write();
                 if state.handles.contains(&handle) == false {
                     state.handles.insert(handle);
+                    state.accept_inbound(handle);
                     drop(state);
 
                     crate::task::spawn(async move {
@@ -121,6 +177,7 @@ impl BusEngine {
             let mut state = BusEngine::write();
             if state.handles.contains(&handle) == false {
                 state.handles.insert(handle);
+                state.accept_inbound(handle);
                 drop(state);
 
                 crate::task::spawn(async move {
@@ -175,6 +232,7 @@ impl BusEngine {
         response: Vec<u8>,
         format: SerializationFormat,
     ) {
+        unwatch_deadline(&handle);
         {
             let mut state = BusEngine::write();
             if let Some(call) = state.calls.remove(&handle) {
@@ -202,6 +260,7 @@ impl BusEngine {
     }
 
     pub fn error(handle: CallHandle, err: BusError) {
+        unwatch_deadline(&handle);
         {
             let mut state = BusEngine::write();
             if let Some(call) = state.calls.remove(&handle) {
@@ -232,8 +291,13 @@ impl BusEngine {
 
     pub fn subscribe(handle: &CallHandle, cx: &mut Context<'_>) {
         let waker = cx.waker().clone();
-        let mut wakers = Self::wakers();
-        wakers.insert(handle.clone(), waker);
+        {
+            let mut wakers = Self::wakers();
+            wakers.insert(handle.clone(), waker);
+        }
+
+        // (the waker must be in place as expiring the call wakes it)
+        poll_deadlines();
     }
 
     pub fn add_callback(handle: CallHandle, child: CallHandle) {
@@ -245,6 +309,7 @@ impl BusEngine {
     }
 
     pub fn close(handle: &CallHandle, reason: &'static str) {
+        unwatch_deadline(handle);
         let mut children = Vec::new();
         {
             let mut delayed_drop1 = Vec::new();
@@ -253,6 +318,10 @@ impl BusEngine {
             {
                 let mut state = BusEngine::write();
                 state.handles.remove(handle);
+                if let Some(inbound) = state.inbound.remove(handle) {
+                    // Closing a call that we are processing cancels it
+                    inbound.cancel.send_replace(true);
+                }
                 if let Some(mut c) = state.children.remove(handle) {
                     children.append(&mut c);
                 }
@@ -286,6 +355,82 @@ impl BusEngine {
         wakers.remove(handle);
     }
 
+    /// Records the metadata that a caller sent for a call that we are
+    /// processing (the metadata of a batch of events is processed before
+    /// the calls so it is normally here before the call is started)
+    pub(crate) fn record_metadata(handle: CallHandle, metadata: CallMetadata) {
+        trace!(
+            "wasmer_bus_metadata (handle={}, trace_id={:032x}, span_id={:016x}, parent_span_id={}, deadline={:?})",
+            handle.id,
+            metadata.trace_id,
+            metadata.span_id,
+            metadata.parent_span_id.map(|a| format!("{:016x}", a)).unwrap_or_else(|| "none".to_string()),
+            metadata.remaining()
+        );
+        let mut state = BusEngine::write();
+        match state.inbound.get_mut(&handle) {
+            Some(inbound) => {
+                if let Some(deadline) = metadata.deadline_instant() {
+                    watch_deadline(handle, deadline, DeadlineAction::Cancel);
+                }
+                inbound.metadata = Some(metadata);
+            }
+            None => {
+                // The call is normally in the same batch of events
+                state.early_metadata.insert(handle, metadata);
+            }
+        }
+    }
+
+    /// Drops any metadata whose call never arrived (called after each
+    /// batch of events)
+    #[cfg(target_os = "wasi")]
+    pub(crate) fn clear_early_metadata() {
+        let mut state = BusEngine::write();
+        state.early_metadata.clear();
+    }
+
+    /// Forgets all the calls, services and callbacks (the mock bus starts
+    /// every test with a clean engine)
+    #[cfg(feature = "mock")]
+    pub(crate) fn reset() {
+        let state = std::mem::take(&mut *BusEngine::write());
+        Self::wakers().clear();
+        drop(state);
+    }
+
+    /// Returns true if this is a call that we are processing
+    pub fn is_inbound(handle: &CallHandle) -> bool {
+        let state = BusEngine::read();
+        state.inbound.contains_key(handle)
+    }
+
+    pub fn metadata(handle: CallHandle) -> Option<CallMetadata> {
+        let state = BusEngine::read();
+        state.inbound.get(&handle).and_then(|a| a.metadata.clone())
+    }
+
+    /// Marks a call that we are processing as cancelled
+    pub(crate) fn cancel(handle: CallHandle) {
+        let state = BusEngine::read();
+        if let Some(inbound) = state.inbound.get(&handle) {
+            inbound.cancel.send_replace(true);
+        }
+    }
+
+    pub fn is_cancelled(handle: CallHandle) -> bool {
+        let state = BusEngine::read();
+        match state.inbound.get(&handle) {
+            Some(inbound) => *inbound.cancel.borrow(),
+            None => true,
+        }
+    }
+
+    pub fn cancellation(handle: CallHandle) -> Option<watch::Receiver<bool>> {
+        let state = BusEngine::read();
+        state.inbound.get(&handle).map(|a| a.cancel.subscribe())
+    }
+
     pub(crate) fn listen_internal<F, Fut>(
         format: SerializationFormat,
         topic: String,
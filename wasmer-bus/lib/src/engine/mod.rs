@@ -1,3 +1,5 @@
+mod deadline;
 mod engine;
 
+pub(crate) use deadline::*;
 pub(crate) use engine::*;
@@ -1,6 +1,10 @@
 pub mod abi;
+pub mod codegen;
 pub mod engine;
+#[cfg(all(not(target_os = "wasi"), feature = "mock"))]
+pub mod mock;
 pub mod prelude;
+pub mod schema;
 pub mod task;
 pub use async_trait::async_trait;
 
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::abi::*;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use super::bus::*;
+use super::*;
+
+fn bus_open(name: &str) -> Result<BusHandle, BusError> {
+    let mut state = state();
+    if state.events.is_none() {
+        debug!("wasmer_bus_mock (bus_open failed as no mock bus is running)");
+        return Err(BusError::Unsupported);
+    }
+    if let Some(err) = state.unavailable.get(name) {
+        return Err(*err);
+    }
+    let bid = BusHandle::from(state.buses.len() as u32 + 1);
+    state.buses.insert(bid, name.to_string());
+    Ok(bid)
+}
+
+/// Backend that passes the calls between the clients and the services
+/// of this process (installed when a `MockBus` is started)
+#[derive(Debug)]
+pub(crate) struct MockBackend;
+
+impl BusBackend for MockBackend {
+    /// Events are delivered by the dispatcher of the mock bus instead
+    fn bus_poll_once(&self, _timeout: Duration) -> usize {
+        0
+    }
+
+    fn bus_open_local(
+        &self,
+        name: &str,
+        _reuse: bool,
+    ) -> Result<BusHandle, BusError> {
+        bus_open(name)
+    }
+
+    fn bus_open_remote(
+        &self,
+        name: &str,
+        _reuse: bool,
+        _instance: &str,
+        _token: &str,
+    ) -> Result<BusHandle, BusError> {
+        bus_open(name)
+    }
+
+    fn bus_call(
+        &self,
+        bid: BusHandle,
+        topic_hash: u128,
+        request: &[u8],
+        format: SerializationFormat
+    ) -> Result<CallHandle, BusError> {
+        let mut state = state();
+        let wapm = state.buses.get(&bid).cloned().ok_or(BusError::InvalidHandle)?;
+
+        if let Some(handler) = state.mocks.get(&topic_hash).cloned() {
+            let handle = next_handle();
+            state.record(CallKind::Call, handle, None, Some(wapm), topic_hash, format, request, None);
+            state.mocked.insert(handle);
+            state.send(MockEvent::Mocked {
+                handle,
+                request: request.to_vec(),
+                format,
+                handler,
+            });
+            return Ok(handle);
+        }
+
+        let (caller, callee) = state.pair();
+        state.record(CallKind::Call, caller, None, Some(wapm), topic_hash, format, request, None);
+        state.send(MockEvent::Start {
+            topic_hash,
+            parent: None,
+            caller,
+            handle: callee,
+            request: request.to_vec(),
+            format,
+        });
+        Ok(caller)
+    }
+
+    fn bus_subcall(
+        &self,
+        parent: CallHandle,
+        topic_hash: u128,
+        request: &[u8],
+        format: SerializationFormat
+    ) -> Result<CallHandle, BusError> {
+        // The metadata is recorded straight away (rather than as an event)
+        // so that it is in place before the call it belongs to is started,
+        // the same as the runtime delivers it ahead of the call
+        if topic_hash == crate::engine::metadata_topic_hash() {
+            let peer = {
+                let state = state();
+                match state.mocked.contains(&parent) {
+                    // (mocks only answer the call itself)
+                    true => None,
+                    false => Some(*state.peers.get(&parent).ok_or(BusError::InvalidHandle)?),
+                }
+            };
+            if let Some(peer) = peer {
+                let metadata = format.deserialize(request.to_vec())?;
+                BusEngine::record_metadata(peer, metadata);
+            }
+            return Ok(next_handle());
+        }
+
+        let mut state = state();
+        if state.mocked.contains(&parent) {
+            let handle = next_handle();
+            state.record(CallKind::SubCall, handle, Some(parent), None, topic_hash, format, request, None);
+            state.mocked.insert(handle);
+            state.send(MockEvent::Mocked {
+                handle,
+                request: request.to_vec(),
+                format,
+                handler: Arc::new(|_, _| Err(BusError::InvalidTopic)),
+            });
+            return Ok(handle);
+        }
+
+        let peer = match state.peers.get(&parent) {
+            Some(a) => *a,
+            None => {
+                return Err(BusError::InvalidHandle);
+            }
+        };
+        let (caller, callee) = state.pair();
+        state.record(CallKind::SubCall, caller, Some(parent), None, topic_hash, format, request, None);
+        state.send(MockEvent::Start {
+            topic_hash,
+            parent: Some(peer),
+            caller,
+            handle: callee,
+            request: request.to_vec(),
+            format,
+        });
+        Ok(caller)
+    }
+
+    fn call_close(&self, handle: CallHandle) {
+        let mut state = state();
+        state.mocked.remove(&handle);
+        if let Some(peer) = state.unpair(&handle) {
+            state.send(MockEvent::Close { handle: peer });
+        }
+    }
+
+    fn call_fault(&self, handle: CallHandle, error: BusError) {
+        let mut state = state();
+        match state.unpair(&handle) {
+            Some(peer) => {
+                let topic_hash = state.topic_of(&peer);
+                state.record(CallKind::Fault, peer, None, None, topic_hash, SerializationFormat::Raw, &[], Some(error));
+                state.send(MockEvent::Fault { handle: peer, error });
+            }
+            None => {
+                trace!("wasmer_bus_mock_fault (handle={}, orphaned)", handle.id);
+            }
+        }
+    }
+
+    fn call_reply(
+        &self,
+        handle: CallHandle,
+        response: &[u8],
+        format: SerializationFormat
+    ) {
+        let mut state = state();
+        match state.unpair(&handle) {
+            Some(peer) => {
+                let topic_hash = state.topic_of(&peer);
+                state.record(CallKind::Reply, peer, None, None, topic_hash, format, response, None);
+                state.send(MockEvent::Result {
+                    handle: peer,
+                    response: response.to_vec(),
+                    format,
+                });
+            }
+            None => {
+                trace!("wasmer_bus_mock_reply (handle={}, orphaned)", handle.id);
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::abi::*;
+use crate::engine::BusEngine;
+
+use super::*;
+
+/// How long a reply is held back waiting for the caller to register the
+/// call it made (which happens straight after `bus_call` returns)
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Answers the calls on a topic in place of a service
+pub(crate) type MockHandler =
+    Arc<dyn Fn(Vec<u8>, SerializationFormat) -> Result<Vec<u8>, BusError> + Send + Sync>;
+
+/// Events that the operating system would deliver to the process
+pub(crate) enum MockEvent {
+    /// A call (or sub-call) arrived for the callee, the caller is the
+    /// other end of it
+    Start {
+        topic_hash: u128,
+        parent: Option<CallHandle>,
+        caller: CallHandle,
+        handle: CallHandle,
+        request: Vec<u8>,
+        format: SerializationFormat,
+    },
+    /// A call that is answered by a mock
+    Mocked {
+        handle: CallHandle,
+        request: Vec<u8>,
+        format: SerializationFormat,
+        handler: MockHandler,
+    },
+    Result {
+        handle: CallHandle,
+        response: Vec<u8>,
+        format: SerializationFormat,
+    },
+    Fault {
+        handle: CallHandle,
+        error: BusError,
+    },
+    Close {
+        handle: CallHandle,
+    },
+}
+
+#[derive(Default)]
+pub(crate) struct MockState {
+    pub buses: HashMap<BusHandle, String>,
+    /// Both ends of every open call (the handle of the caller maps to the
+    /// handle of the callee and vice versa)
+    pub peers: HashMap<CallHandle, CallHandle>,
+    /// Calls that are being answered by a mock
+    pub mocked: HashSet<CallHandle>,
+    pub mocks: HashMap<u128, MockHandler>,
+    pub unavailable: HashMap<String, BusError>,
+    /// Readable names of the topics (for assertion failures)
+    pub names: HashMap<u128, String>,
+    pub records: Vec<CallRecord>,
+    pub events: Option<mpsc::Sender<MockEvent>>,
+}
+
+static MOCK: Lazy<Mutex<MockState>> = Lazy::new(|| Mutex::new(MockState::default()));
+
+// Handles are never reused (not even between tests) so that stray events
+// from a previous test can not hit the calls of the next one
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) fn state<'a>() -> MutexGuard<'a, MockState> {
+    MOCK.lock().unwrap()
+}
+
+pub(crate) fn next_handle() -> CallHandle {
+    NEXT_HANDLE.fetch_add(1, Ordering::SeqCst).into()
+}
+
+impl MockState {
+    /// Opens a call between a caller and a callee
+    pub fn pair(&mut self) -> (CallHandle, CallHandle) {
+        let caller = next_handle();
+        let callee = next_handle();
+        self.peers.insert(caller, callee);
+        self.peers.insert(callee, caller);
+        (caller, callee)
+    }
+
+    /// Closes a call and returns the other end of it
+    pub fn unpair(&mut self, handle: &CallHandle) -> Option<CallHandle> {
+        let peer = self.peers.remove(handle)?;
+        self.peers.remove(&peer);
+        Some(peer)
+    }
+
+    pub fn is_open(&self, handle: &CallHandle) -> bool {
+        self.peers.contains_key(handle) || self.mocked.contains(handle)
+    }
+
+    pub fn send(&self, event: MockEvent) {
+        match self.events.as_ref() {
+            Some(events) => {
+                let _ = events.send(event);
+            }
+            None => {
+                trace!("wasmer_bus_mock (event dropped as no mock bus is running)");
+            }
+        }
+    }
+
+    /// Topic of a call that was made (used to record replies and faults)
+    pub fn topic_of(&self, handle: &CallHandle) -> u128 {
+        self.records
+            .iter()
+            .rev()
+            .find(|r| r.handle == *handle && (r.kind == CallKind::Call || r.kind == CallKind::SubCall))
+            .map(|r| r.topic_hash)
+            .unwrap_or_default()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &mut self,
+        kind: CallKind,
+        handle: CallHandle,
+        parent: Option<CallHandle>,
+        wapm: Option<String>,
+        topic_hash: u128,
+        format: SerializationFormat,
+        data: &[u8],
+        error: Option<BusError>,
+    ) {
+        self.records.push(CallRecord {
+            kind,
+            handle,
+            parent,
+            wapm,
+            topic_hash,
+            format,
+            data: data.to_vec(),
+            error,
+        });
+    }
+}
+
+/// Delivers the events of the mock bus in order (the operating system
+/// does the same for a real process when it polls the bus)
+pub(crate) fn run(events: mpsc::Receiver<MockEvent>, runtime: tokio::runtime::Handle) {
+    let _guard = runtime.enter();
+    // (the early metadata is not cleared after each event as it is recorded
+    // as soon as it is sent, the engine is reset when the bus is dropped)
+    while let Ok(event) = events.recv() {
+        dispatch(event);
+    }
+    trace!("wasmer_bus_mock (dispatcher has stopped)");
+}
+
+fn dispatch(event: MockEvent) {
+    match event {
+        MockEvent::Start {
+            topic_hash,
+            parent,
+            caller,
+            handle,
+            request,
+            format,
+        } => {
+            wait_registered(caller);
+            if parent.is_none() {
+                wait_metadata(caller, handle);
+            }
+            trace!(
+                "wasmer_bus_mock_start (parent={:?}, handle={}, request={} bytes)",
+                parent,
+                handle,
+                request.len()
+            );
+            if let Err(err) = BusEngine::start(topic_hash, parent, handle, request, format) {
+                crate::abi::syscall::call_fault(handle, err);
+            }
+        }
+        MockEvent::Mocked {
+            handle,
+            request,
+            format,
+            handler,
+        } => {
+            wait_registered(handle);
+            let res = handler(request, format);
+            {
+                let mut state = state();
+                state.mocked.remove(&handle);
+                let topic_hash = state.topic_of(&handle);
+                match &res {
+                    Ok(response) => state.record(CallKind::Reply, handle, None, None, topic_hash, format, &response[..], None),
+                    Err(err) => state.record(CallKind::Fault, handle, None, None, topic_hash, format, &[], Some(*err)),
+                }
+            }
+            match res {
+                Ok(response) => BusEngine::result(handle, response, format),
+                Err(err) => BusEngine::error(handle, err),
+            }
+        }
+        MockEvent::Result {
+            handle,
+            response,
+            format,
+        } => {
+            BusEngine::result(handle, response, format);
+        }
+        MockEvent::Fault { handle, error } => {
+            BusEngine::error(handle, error);
+        }
+        MockEvent::Close { handle } => {
+            BusEngine::close(&handle, "mock_notification");
+        }
+    }
+}
+
+/// New calls always send their metadata straight after they have been
+/// registered, the callee must not be started before it has arrived (or
+/// the handler would not see its deadline or trace)
+fn wait_metadata(caller: CallHandle, callee: CallHandle) {
+    let start = Instant::now();
+    loop {
+        if BusEngine::read().early_metadata.contains_key(&callee) {
+            return;
+        }
+        if state().is_open(&caller) == false {
+            return;
+        }
+        if start.elapsed() > REGISTER_TIMEOUT {
+            debug!("wasmer_bus_mock (handle={}, call sent no metadata)", caller.id);
+            return;
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// A process only hears about the calls it made once `bus_call` has
+/// returned and the call is registered with the engine, replies that
+/// beat the registration would otherwise be orphaned
+fn wait_registered(caller: CallHandle) {
+    let start = Instant::now();
+    loop {
+        if BusEngine::read().handles.contains(&caller) {
+            return;
+        }
+        if state().is_open(&caller) == false {
+            return;
+        }
+        if start.elapsed() > REGISTER_TIMEOUT {
+            debug!("wasmer_bus_mock (handle={}, call was never registered)", caller.id);
+            return;
+        }
+        std::thread::yield_now();
+    }
+}
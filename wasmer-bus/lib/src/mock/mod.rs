@@ -0,0 +1,280 @@
+//! In-process bus that hosts services and their clients natively so that
+//! they can be tested with `cargo test` (enabled by the `mock` feature)
+//!
+//! Starting a `MockBus` installs a backend (see `abi::set_backend`) that
+//! passes the calls between the clients and the services of this process
+//! using the same serialization as a real bus, every call is recorded so
+//! that tests can assert what was called and in which order
+//!
+//! ```rust,ignore
+//! #[tokio::test(flavor = "multi_thread")]
+//! async fn hello() {
+//!     let bus = MockBus::start();
+//!     WorldService::listen(Arc::new(HelloService::default()));
+//!
+//!     let world = WorldClient::new("hello");
+//!     assert_eq!(world.hello().await.unwrap(), "hello");
+//!
+//!     bus.expect()
+//!         .call::<WorldHelloRequest>()
+//!         .reply::<WorldHelloRequest>()
+//!         .verify();
+//! }
+//! ```
+mod backend;
+mod bus;
+mod record;
+
+use once_cell::sync::Lazy;
+use serde::*;
+use std::any::type_name;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::abi::*;
+use crate::engine::BusEngine;
+
+pub use record::*;
+
+// The engine is global to the process so only one mock bus can run at a
+// time (tests that run in parallel queue up behind each other)
+static RUNNING: Lazy<(Mutex<bool>, Condvar)> = Lazy::new(|| (Mutex::new(false), Condvar::new()));
+
+/// Hosts the services and clients of a test on an in-process bus, the bus
+/// is torn down (and the engine cleared) when this is dropped
+#[derive(Debug)]
+pub struct MockBus {
+    _private: (),
+}
+
+impl MockBus {
+    /// Starts a mock bus that processes calls on the current tokio runtime
+    /// (blocking calls need a multi-threaded runtime)
+    ///
+    /// Panics if it is not called within a tokio runtime
+    pub fn start() -> MockBus {
+        let runtime = tokio::runtime::Handle::current();
+        {
+            let (lock, condvar) = &*RUNNING;
+            let mut running = lock.lock().unwrap();
+            while *running {
+                running = condvar.wait(running).unwrap();
+            }
+            *running = true;
+        }
+
+        BusEngine::reset();
+        crate::abi::set_backend(Some(Arc::new(backend::MockBackend)));
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut state = bus::state();
+            *state = Default::default();
+            state.events = Some(tx);
+        }
+        std::thread::Builder::new()
+            .name("wasmer-bus-mock".to_string())
+            .spawn(move || bus::run(rx, runtime))
+            .expect("failed to start the mock bus");
+
+        MockBus { _private: () }
+    }
+
+    /// Answers calls with this request type (from any module) rather than
+    /// passing them to a service, which is used to stand in for the
+    /// services that the code under test depends on
+    pub fn mock<REQ, RES, F>(&self, handler: F) -> &Self
+    where
+        REQ: de::DeserializeOwned,
+        RES: Serialize,
+        F: Fn(REQ) -> Result<RES, BusError>,
+        F: Send + Sync + 'static,
+    {
+        let topic_hash = topic_hash::<REQ>();
+        let handler: bus::MockHandler = Arc::new(move |request, format| {
+            let request = format.deserialize(request).map_err(|err| {
+                debug!("failed to deserialize the mocked request (type={}, format={}) - {}", type_name::<REQ>(), format, err);
+                BusError::DeserializationFailed
+            })?;
+            format.serialize(handler(request)?)
+        });
+
+        let mut state = bus::state();
+        state.names.insert(topic_hash, type_name::<REQ>().to_string());
+        state.mocks.insert(topic_hash, handler);
+        self
+    }
+
+    /// Fails calls made to this module (e.g. `BusError::FetchFailed` as if
+    /// it could not be downloaded)
+    pub fn unavailable(&self, wapm: &str, error: BusError) -> &Self {
+        let mut state = bus::state();
+        state.unavailable.insert(wapm.to_string(), error);
+        self
+    }
+
+    /// Returns everything that has passed over the bus so far
+    pub fn calls(&self) -> Vec<CallRecord> {
+        bus::state().records.clone()
+    }
+
+    /// Returns the requests of all the calls that were made with this type
+    pub fn requests<T>(&self) -> Vec<T>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.calls()
+            .iter()
+            .filter(|r| r.is::<T>())
+            .filter(|r| r.kind == CallKind::Call || r.kind == CallKind::SubCall)
+            .filter_map(|r| r.decode().ok())
+            .collect()
+    }
+
+    /// Forgets the calls recorded so far
+    pub fn clear(&self) {
+        bus::state().records.clear();
+    }
+
+    /// Panics unless a call was made with this request type
+    pub fn assert_called<T>(&self) {
+        if self.requests_of(topic_hash::<T>()) == 0 {
+            panic!("expected a call to {} but it was not called\n{}", type_name::<T>(), self.describe());
+        }
+    }
+
+    /// Panics if a call was made with this request type
+    pub fn assert_not_called<T>(&self) {
+        let n = self.requests_of(topic_hash::<T>());
+        if n > 0 {
+            panic!("expected no calls to {} but it was called {} times\n{}", type_name::<T>(), n, self.describe());
+        }
+    }
+
+    /// Starts describing a sequence of calls that must have happened (in
+    /// this order but not necessarily back to back)
+    pub fn expect(&self) -> CallSequence<'_> {
+        CallSequence {
+            bus: self,
+            expected: Vec::new(),
+        }
+    }
+
+    fn requests_of(&self, topic_hash: u128) -> usize {
+        bus::state()
+            .records
+            .iter()
+            .filter(|r| r.topic_hash == topic_hash)
+            .filter(|r| r.kind == CallKind::Call || r.kind == CallKind::SubCall)
+            .count()
+    }
+
+    /// Lists the recorded calls in a readable form for assertion failures
+    fn describe(&self) -> String {
+        let state = bus::state();
+
+        // The interfaces that were registered name most of the topics
+        let mut names: HashMap<u128, String> = state.names.clone();
+        for schema in crate::schema::interfaces() {
+            for method in schema.methods {
+                names.insert(crate::schema::topic_hash(method.topic.as_str()), method.topic);
+            }
+        }
+
+        let mut ret = "recorded calls:\n".to_string();
+        for record in state.records.iter() {
+            let name = names
+                .get(&record.topic_hash)
+                .cloned()
+                .unwrap_or_else(|| format!("{:032x}", record.topic_hash));
+            ret.push_str(format!("  {} {} (handle={}", record.kind, name, record.handle.id).as_str());
+            if let Some(parent) = record.parent {
+                ret.push_str(format!(", parent={}", parent.id).as_str());
+            }
+            if let Some(wapm) = record.wapm.as_ref() {
+                ret.push_str(format!(", wapm={}", wapm).as_str());
+            }
+            if let Some(err) = record.error.as_ref() {
+                ret.push_str(format!(", error={}", err).as_str());
+            }
+            ret.push_str(")\n");
+        }
+        ret
+    }
+}
+
+impl Drop for MockBus {
+    fn drop(&mut self) {
+        // Dropping the sender stops the dispatcher (the backend stays in
+        // place so that calls which outlive the test fail rather than
+        // reaching a runtime that does not exist)
+        {
+            let mut state = bus::state();
+            *state = Default::default();
+        }
+        BusEngine::reset();
+
+        let (lock, condvar) = &*RUNNING;
+        let mut running = lock.lock().unwrap();
+        *running = false;
+        condvar.notify_one();
+    }
+}
+
+/// Sequence of calls that a test expects to have passed over the bus
+#[derive(Debug)]
+#[must_use = "the sequence is only checked when you 'verify' it"]
+pub struct CallSequence<'a> {
+    bus: &'a MockBus,
+    expected: Vec<(CallKind, u128, &'static str)>,
+}
+
+impl<'a> CallSequence<'a> {
+    fn then<T>(mut self, kind: CallKind) -> Self {
+        let topic_hash = topic_hash::<T>();
+        bus::state().names.insert(topic_hash, type_name::<T>().to_string());
+        self.expected.push((kind, topic_hash, type_name::<T>()));
+        self
+    }
+
+    /// A new call was made with this request type
+    pub fn call<T>(self) -> Self {
+        self.then::<T>(CallKind::Call)
+    }
+
+    /// A sub-call (callback, session method or stream frame) was made with
+    /// this request type
+    pub fn subcall<T>(self) -> Self {
+        self.then::<T>(CallKind::SubCall)
+    }
+
+    /// The call with this request type was replied to
+    pub fn reply<T>(self) -> Self {
+        self.then::<T>(CallKind::Reply)
+    }
+
+    /// The call with this request type failed
+    pub fn fault<T>(self) -> Self {
+        self.then::<T>(CallKind::Fault)
+    }
+
+    /// Panics if the calls did not happen in this order
+    pub fn verify(self) {
+        let records = self.bus.calls();
+        let mut records = records.iter();
+        for (kind, topic_hash, name) in self.expected.iter() {
+            if records.any(|r| r.kind == *kind && r.topic_hash == *topic_hash) == false {
+                panic!(
+                    "expected a {} of {} (in sequence) but it did not happen\n{}",
+                    kind,
+                    name,
+                    self.bus.describe()
+                );
+            }
+        }
+    }
+}
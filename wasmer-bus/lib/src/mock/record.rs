@@ -0,0 +1,65 @@
+use serde::*;
+use std::any::type_name;
+use std::fmt;
+
+use crate::abi::*;
+
+/// Hash of the topic that calls with this request type are sent on
+pub fn topic_hash<T>() -> u128 {
+    crate::schema::topic_hash(type_name::<T>())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CallKind {
+    /// A new call to a service (e.g. from a client)
+    Call,
+    /// A call made within another call (callbacks, sessions and streams)
+    SubCall,
+    /// The callee replied to the call
+    Reply,
+    /// The callee failed the call
+    Fault,
+}
+
+impl fmt::Display for CallKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallKind::Call => write!(f, "call"),
+            CallKind::SubCall => write!(f, "subcall"),
+            CallKind::Reply => write!(f, "reply"),
+            CallKind::Fault => write!(f, "fault"),
+        }
+    }
+}
+
+/// Something that passed over the mock bus
+#[derive(Debug, Clone)]
+pub struct CallRecord {
+    pub kind: CallKind,
+    /// Handle of the call as the caller sees it (replies and faults carry
+    /// the handle of the call they finish)
+    pub handle: CallHandle,
+    pub parent: Option<CallHandle>,
+    /// Name of the module that was called (only set for new calls)
+    pub wapm: Option<String>,
+    pub topic_hash: u128,
+    pub format: SerializationFormat,
+    /// The request (for calls) or the response (for replies)
+    pub data: Vec<u8>,
+    pub error: Option<BusError>,
+}
+
+impl CallRecord {
+    /// Returns true if this is a call (or its reply) with this request type
+    pub fn is<T>(&self) -> bool {
+        self.topic_hash == topic_hash::<T>()
+    }
+
+    /// Deserializes the request or response that was sent
+    pub fn decode<T>(&self) -> Result<T, BusError>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.format.deserialize(self.data.clone())
+    }
+}
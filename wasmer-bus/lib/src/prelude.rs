@@ -11,6 +11,8 @@ pub use crate::task::respond_to;
 pub use wasmer_bus_macros::*;
 
 pub use crate::abi::BusError;
+pub use crate::abi::ByteStream;
 pub use crate::abi::CallHandle;
+pub use crate::abi::MessageStream;
 pub use crate::abi::WasmBusSession;
 pub use async_trait::async_trait;
@@ -0,0 +1,69 @@
+#![allow(dead_code)]
+//! Registry of the interfaces that are served or used by this process
+//! (the `wasmer_bus` macro registers an interface when it starts
+//! listening) which tooling can query at runtime
+use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::abi::*;
+
+static SCHEMAS: Lazy<RwLock<BTreeMap<String, InterfaceSchema>>> =
+    Lazy::new(|| RwLock::new(BTreeMap::default()));
+
+/// Adds an interface to the registry, returns false if it was already
+/// registered (in which case the existing schema is kept)
+pub fn register(schema: InterfaceSchema) -> bool {
+    let mut guard = SCHEMAS.write().unwrap();
+    let path = schema.path();
+    if guard.contains_key(&path) {
+        return false;
+    }
+    guard.insert(path, schema);
+    true
+}
+
+/// Finds an interface by its fully qualified path (e.g.
+/// `wasmer_bus_fuse::api::FileSystem`) or else by its name
+pub fn lookup(name: &str) -> Option<InterfaceSchema> {
+    let guard = SCHEMAS.read().unwrap();
+    if let Some(schema) = guard.get(name) {
+        return Some(schema.clone());
+    }
+    guard.values().find(|s| s.name == name).map(|s| s.clone())
+}
+
+/// Returns all the interfaces in the registry ordered by their path
+pub fn interfaces() -> Vec<InterfaceSchema> {
+    let guard = SCHEMAS.read().unwrap();
+    guard.values().map(|s| s.clone()).collect()
+}
+
+/// Returns an interface along with all the interfaces that are reachable
+/// from it (e.g. `FileSystem` also returns `OpenedFile` and `FileIO`)
+pub fn closure(name: &str) -> Vec<InterfaceSchema> {
+    let mut ret: Vec<InterfaceSchema> = Vec::new();
+    let mut pending = vec![name.to_string()];
+    while let Some(name) = pending.pop() {
+        if ret.iter().any(|s| s.name == name || s.path() == name) {
+            continue;
+        }
+        if let Some(schema) = lookup(name.as_str()) {
+            pending.extend(schema.sub_interfaces().into_iter().map(|s| s.to_string()));
+            ret.push(schema);
+        }
+    }
+    ret
+}
+
+/// Serializes all the interfaces in the registry (e.g. as JSON) so that
+/// they can be exported to other tools
+pub fn export(format: SerializationFormat) -> Result<Vec<u8>, BusError> {
+    format.serialize(interfaces())
+}
+
+/// Hash of a topic as it is sent over the bus when a method is invoked
+pub fn topic_hash(topic: &str) -> u128 {
+    crate::engine::hash_topic(&Cow::Owned(topic.to_string()))
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use wasmer_bus::abi::cancelled;
+use wasmer_bus::abi::current_metadata;
+use wasmer_bus::abi::BusError;
+use wasmer_bus::abi::CallMetadata;
+use wasmer_bus::macros::*;
+use wasmer_bus::mock::*;
+
+#[wasmer_bus(format = "json")]
+pub trait Tracer {
+    async fn trace(&self, timeouts: Vec<u64>) -> Vec<CallMetadata>;
+    async fn sleep(&self, millis: u64) -> bool;
+}
+
+/// Returns the metadata of each call in a chain of calls back into
+/// itself (one per timeout), and reports whether each sleep was cut short
+#[derive(Debug)]
+struct TracerImpl {
+    outcomes: mpsc::UnboundedSender<bool>,
+}
+
+#[async_trait]
+impl TracerSimplified for TracerImpl {
+    async fn trace(&self, timeouts: Vec<u64>) -> Vec<CallMetadata> {
+        let mut ret = vec![current_metadata().expect("the call has no metadata")];
+        if let Some((first, rest)) = timeouts.split_first() {
+            let tracer = TracerClient::new("tracer").with_timeout(Some(Duration::from_millis(*first)));
+            ret.extend(tracer.trace(rest.to_vec()).await.unwrap());
+        }
+        ret
+    }
+
+    async fn sleep(&self, millis: u64) -> bool {
+        let cancelled = tokio::select! {
+            _ = cancelled() => true,
+            _ = tokio::time::sleep(Duration::from_millis(millis)) => false,
+        };
+        let _ = self.outcomes.send(cancelled);
+        cancelled
+    }
+}
+
+fn listen() -> mpsc::UnboundedReceiver<bool> {
+    let (outcomes, rx) = mpsc::unbounded_channel();
+    TracerService::listen(Arc::new(TracerImpl { outcomes }));
+    rx
+}
+
+async fn outcome(rx: &mut mpsc::UnboundedReceiver<bool>) -> bool {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("the handler never finished")
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nested_calls_inherit_the_trace_and_deadline() {
+    let _bus = MockBus::start();
+    let _rx = listen();
+
+    let tracer = TracerClient::new("tracer").with_timeout(Some(Duration::from_secs(60)));
+    let chain = tracer.trace(vec![1000, 60000]).await.unwrap();
+    assert_eq!(chain.len(), 3);
+
+    assert_eq!(chain[0].parent_span_id, None);
+    for pair in chain.windows(2) {
+        assert_eq!(pair[1].trace_id, pair[0].trace_id);
+        assert_eq!(pair[1].parent_span_id, Some(pair[0].span_id));
+    }
+
+    // A nested call can shorten the deadline but never extend it
+    assert!(chain[1].deadline < chain[0].deadline);
+    assert_eq!(chain[2].deadline, chain[1].deadline);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn calls_fail_when_the_deadline_passes() {
+    let _bus = MockBus::start();
+    let mut rx = listen();
+
+    let tracer = TracerClient::new("tracer").with_timeout(Some(Duration::from_millis(50)));
+    let ret = tracer.sleep(10000).await;
+    assert!(matches!(ret, Err(BusError::DeadlineExceeded)), "{:?}", ret);
+
+    // The handler is told to give up as well
+    assert!(outcome(&mut rx).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn closing_a_call_cancels_the_handler() {
+    let _bus = MockBus::start();
+    let mut rx = listen();
+
+    let tracer = TracerClient::new("tracer");
+    let ret = tokio::time::timeout(Duration::from_millis(100), tracer.sleep(10000)).await;
+    assert!(ret.is_err());
+    assert!(outcome(&mut rx).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handlers_run_to_completion_without_a_deadline() {
+    let _bus = MockBus::start();
+    let mut rx = listen();
+
+    let tracer = TracerClient::new("tracer");
+    assert_eq!(tracer.sleep(10).await.unwrap(), false);
+    assert_eq!(outcome(&mut rx).await, false);
+}
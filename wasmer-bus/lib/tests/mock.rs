@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use wasmer_bus::abi::BusError;
+use wasmer_bus::abi::ByteStream;
+use wasmer_bus::abi::MessageStream;
+use wasmer_bus::macros::*;
+use wasmer_bus::mock::*;
+
+#[wasmer_bus(format = "json")]
+pub trait Counter {
+    async fn add(&self, amount: u64) -> u64;
+    async fn count(&self, to: u32) -> MessageStream<u32>;
+}
+
+#[wasmer_bus(format = "bincode")]
+pub trait Greeter {
+    async fn greet(&self, name: String) -> String;
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum FilesError {
+    NotFound,
+}
+
+pub type FilesResult<T> = Result<T, FilesError>;
+
+#[wasmer_bus(format = "bincode")]
+pub trait Files {
+    async fn read(&self, name: String) -> FilesResult<ByteStream>;
+}
+
+#[derive(Debug, Default)]
+struct CounterImpl {
+    total: Mutex<u64>,
+}
+
+#[async_trait]
+impl CounterSimplified for CounterImpl {
+    async fn add(&self, amount: u64) -> u64 {
+        let mut total = self.total.lock().unwrap();
+        *total += amount;
+        *total
+    }
+
+    async fn count(&self, to: u32) -> MessageStream<u32> {
+        MessageStream::from((1..=to).collect::<Vec<_>>())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn calls_reach_the_service() {
+    let bus = MockBus::start();
+    CounterService::listen(Arc::new(CounterImpl::default()));
+
+    let counter = CounterClient::new("counter");
+    assert_eq!(counter.add(2).await.unwrap(), 2);
+    assert_eq!(counter.blocking_add(3).unwrap(), 5);
+
+    let amounts: Vec<_> = bus
+        .requests::<CounterAddRequest>()
+        .into_iter()
+        .map(|r| r.amount)
+        .collect();
+    assert_eq!(amounts, vec![2, 3]);
+
+    bus.expect()
+        .call::<CounterAddRequest>()
+        .reply::<CounterAddRequest>()
+        .call::<CounterAddRequest>()
+        .reply::<CounterAddRequest>()
+        .verify();
+    bus.assert_not_called::<GreeterGreetRequest>();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streams_are_passed_through() {
+    let bus = MockBus::start();
+    CounterService::listen(Arc::new(CounterImpl::default()));
+
+    let counter = CounterClient::new("counter");
+    let items = counter.count(3).await.unwrap().collect().await.unwrap();
+    assert_eq!(items, vec![1, 2, 3]);
+
+    bus.expect()
+        .call::<CounterCountRequest>()
+        .subcall::<wasmer_bus::abi::StreamData>()
+        .reply::<CounterCountRequest>()
+        .verify();
+}
+
+#[derive(Debug)]
+struct FilesImpl;
+
+#[async_trait]
+impl FilesSimplified for FilesImpl {
+    async fn read(&self, name: String) -> FilesResult<ByteStream> {
+        match name.as_str() {
+            "readme" => Ok(ByteStream::from(&b"hello"[..])),
+            _ => Err(FilesError::NotFound),
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streams_can_fail_before_they_start() {
+    let _bus = MockBus::start();
+    FilesService::listen(Arc::new(FilesImpl));
+
+    let files = FilesClient::new("files");
+    let data = files.read("readme".to_string()).await.unwrap().unwrap();
+    assert_eq!(data.read_to_end().await.unwrap(), b"hello".to_vec());
+
+    let ret = files.read("missing".to_string()).await.unwrap();
+    assert_eq!(ret.err(), Some(FilesError::NotFound));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mocks_stand_in_for_services() {
+    let bus = MockBus::start();
+    bus.mock(|req: GreeterGreetRequest| Ok(format!("hello {}", req.name)));
+
+    let greeter = GreeterClient::new("greeter");
+    assert_eq!(greeter.greet("bob".to_string()).await.unwrap(), "hello bob");
+
+    bus.assert_called::<GreeterGreetRequest>();
+    assert_eq!(bus.calls()[0].wapm.as_deref(), Some("greeter"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mocks_can_fail_calls() {
+    let bus = MockBus::start();
+    bus.mock(|_: CounterAddRequest| -> Result<u64, BusError> { Err(BusError::AccessDenied) });
+
+    let counter = CounterClient::new("counter");
+    assert!(counter.add(1).await.is_err());
+
+    bus.expect()
+        .call::<CounterAddRequest>()
+        .fault::<CounterAddRequest>()
+        .verify();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unavailable_modules_fail() {
+    let bus = MockBus::start();
+    bus.unavailable("counter", BusError::FetchFailed);
+
+    let counter = CounterClient::new("counter");
+    assert!(counter.add(1).await.is_err());
+    bus.assert_not_called::<CounterAddRequest>();
+}
@@ -12,6 +12,8 @@ use wasmer_bus_types::SerializationFormat;
 
 use super::method_inputs::*;
 use super::method_output::*;
+use super::schema::*;
+use super::stream::*;
 
 #[rustfmt::skip]
 pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
@@ -64,6 +66,8 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
             let mut service_attach_points = Vec::new();
             let mut passthru_client_methods = Vec::new();
             let mut passthru_simplified_methods = Vec::new();
+            let mut schema_methods = Vec::new();
+            let mut schema_sub_interfaces = Vec::new();
             let mut output = proc_macro2::TokenStream::new();
 
             // We process all the methods in the trait and emit code that supports
@@ -106,6 +110,8 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                     let mut field_idents: Punctuated<_, Token![,]> = Punctuated::new();
                     let mut field_idents_plus: Punctuated<_, Token![,]> = Punctuated::new();
                     let mut fields: Punctuated<Field, Token![,]> = Punctuated::new();
+                    let mut method_schema_args = Vec::new();
+                    let mut method_streams = 0usize;
                     for input in method_inputs.inputs {
                         let attrs = input.attrs.clone();
                         let name = input.ident.clone();
@@ -197,6 +203,37 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
 
                             field_idents_plus.push(name.clone());
 
+                            // The schema describes what the callback receives
+                            method_schema_args.push(callback_schema(
+                                name.to_string().as_str(),
+                                &quote! { #callback_field_type },
+                                &quote! { #callback_name }
+                            ));
+
+                        } else if let Some(item) = stream_item(&ty) {
+                            // Streams are sent as frames after the call is made (one
+                            // per direction) rather than in the request object
+                            method_streams += 1;
+                            if method_streams > 1 {
+                                output.extend(Error::new(span, "bus methods only support a single stream argument").to_compile_error());
+                                continue;
+                            }
+
+                            // The client sends the stream as the callee grants it credit
+                            method_callbacks.push(quote! {
+                                .send_stream(wasmer_bus::abi::MessageStream::<#item>::from(#name))
+                            });
+
+                            // The service receives the frames into the stream that is passed on
+                            method_lets.push(quote! {
+                                let #name: #ty = wasmer_bus::abi::accept_stream::<#item>(wasm_handle.clone(), #format).into();
+                            });
+
+                            method_transformed_inputs.push(FnArg::Typed(input.pat_type));
+                            field_idents_plus.push(name.clone());
+
+                            method_schema_args.push(stream_arg_schema(name.to_string().as_str(), &item));
+
                         } else {
                             fields.push(
                                 Field::parse_named
@@ -214,6 +251,8 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                             method_lets.push(quote! {
                                 let #name = wasm_req.#name;
                             });
+
+                            method_schema_args.push(arg_schema(name.to_string().as_str(), &quote! { #ty }));
                         }
                     }
 
@@ -239,9 +278,78 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                         }
                     };
 
+                    // Describe the method in the schema of the interface
+                    let method_name = method_ident.to_string();
+                    let method_returns = return_schema(&method_ret);
+                    schema_methods.push(quote! {
+                        wasmer_bus::abi::MethodSchema {
+                            name: #method_name.to_string(),
+                            topic: std::any::type_name::<#request_name>().to_string(),
+                            args: vec![ #( #method_schema_args ),* ],
+                            returns: #method_returns,
+                        }
+                    });
+
+                    // Methods that return a stream keep the call open until the
+                    // whole stream has been sent
+                    let method_ret_stream = method_ret.stream_item();
+                    let method_ret_result_stream = method_ret.result_stream_item();
+                    let (listen_response, respond_response) = match (&method_ret_stream, &method_ret_result_stream) {
+                        // Methods that can fail before there is a stream answer with the
+                        // error straight away, otherwise the answer follows the stream
+                        (None, Some(item)) => (
+                            quote! {
+                                match res {
+                                    Ok(stream) => match wasmer_bus::abi::serve_stream::<#item>(wasm_handle, #format, stream.into()).await {
+                                        Ok(()) => wasmer_bus::abi::ListenActionTyped::Response(Ok(())),
+                                        Err(err) => wasmer_bus::abi::ListenActionTyped::Fault(err)
+                                    },
+                                    Err(err) => wasmer_bus::abi::ListenActionTyped::Response(Err(err))
+                                }
+                            },
+                            quote! {
+                                match res {
+                                    Ok(stream) => match wasmer_bus::abi::serve_stream::<#item>(wasm_handle, #format, stream.into()).await {
+                                        Ok(()) => wasmer_bus::abi::RespondActionTyped::Response(Ok(())),
+                                        Err(err) => wasmer_bus::abi::RespondActionTyped::Fault(err)
+                                    },
+                                    Err(err) => wasmer_bus::abi::RespondActionTyped::Response(Err(err))
+                                }
+                            }
+                        ),
+                        (Some(item), _) => (
+                            quote! {
+                                match wasmer_bus::abi::serve_stream::<#item>(wasm_handle, #format, res.into()).await {
+                                    Ok(()) => wasmer_bus::abi::ListenActionTyped::Response(()),
+                                    Err(err) => wasmer_bus::abi::ListenActionTyped::Fault(err)
+                                }
+                            },
+                            quote! {
+                                match wasmer_bus::abi::serve_stream::<#item>(wasm_handle, #format, res.into()).await {
+                                    Ok(()) => wasmer_bus::abi::RespondActionTyped::Response(()),
+                                    Err(err) => wasmer_bus::abi::RespondActionTyped::Fault(err)
+                                }
+                            }
+                        ),
+                        (None, None) => (
+                            quote! { wasmer_bus::abi::ListenActionTyped::Response(res) },
+                            quote! { wasmer_bus::abi::RespondActionTyped::Response(res) }
+                        )
+                    };
+
+                    // Streams stay open for as long as they are being read so the
+                    // timeout of the client does not apply to them
+                    let method_timeout = match method_streams > 0 {
+                        true => quote! {},
+                        false => quote! { .timeout(self.timeout) }
+                    };
+
                     // Attempt to parse the type into an object
                     if method_ret.is_trait() {
                         let svc = method_ret.ident_service();
+                        schema_sub_interfaces.push(quote! {
+                            #svc::register_schema();
+                        });
                         service_attach_points.push(quote! {
                             {
                                 let wasm_me = wasm_me.clone();
@@ -287,7 +395,7 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                                         async move {
                                             #( #method_callback_handlers )*
                                             match wasm_me.#method_ident(#field_idents_plus).await {
-                                                Ok(res) => wasmer_bus::abi::RespondActionTyped::Response(res),
+                                                Ok(res) => #respond_response,
                                                 Err(err) => wasmer_bus::abi::RespondActionTyped::Fault(err)
                                             }
                                         }
@@ -345,7 +453,7 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                                     )
                                     #( #method_callbacks )*
                                     .detach()?;
-                                Ok(Arc::new(#ret_client::attach(handle)))
+                                Ok(Arc::new(#ret_client::attach(handle).with_timeout(self.timeout)))
                             }
                         });
                         blocking_methods.push(quote! {
@@ -379,13 +487,13 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                                 wasmer_bus::task::listen(
                                     #format,
                                     #[allow(unused_variables)]
-                                    move |_wasm_handle: wasmer_bus::abi::CallHandle, wasm_req: #request_name| {
+                                    move |wasm_handle: wasmer_bus::abi::CallHandle, wasm_req: #request_name| {
                                         let wasm_me = wasm_me.clone();
                                         #( #method_lets )*
                                         async move {
                                             #( #method_callback_handlers )*
                                             match wasm_me.#method_ident(#field_idents_plus).await {
-                                                Ok(res) => wasmer_bus::abi::ListenActionTyped::Response(res),
+                                                Ok(res) => #listen_response,
                                                 Err(err) => wasmer_bus::abi::ListenActionTyped::Fault(err)
                                             }
                                         }
@@ -401,22 +509,56 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                         trait_simplified_methods.push(quote! {
                             async fn #method_ident ( &self, #method_transformed_inputs ) -> #ret;
                         });
-                        client_method_impls.push(quote! {
-                            pub async fn #method_ident ( &self, #method_transformed_inputs ) -> std::result::Result<#ret, wasmer_bus::abi::BusError> {
-                                let request = #request_name {
-                                    #field_idents
-                                };
-                                wasmer_bus::abi::call(
-                                        self.ctx.clone(),
-                                        #format,
-                                        request
-                                    )
-                                    #( #method_callbacks )*
-                                    .invoke()
-                                    .join()?
-                                    .await
-                            }
-                        });
+                        match (&method_ret_stream, &method_ret_result_stream) {
+                            (None, Some(item)) => client_method_impls.push(quote! {
+                                pub async fn #method_ident ( &self, #method_transformed_inputs ) -> std::result::Result<#ret, wasmer_bus::abi::BusError> {
+                                    let request = #request_name {
+                                        #field_idents
+                                    };
+                                    Ok(wasmer_bus::abi::call(
+                                            self.ctx.clone(),
+                                            #format,
+                                            request
+                                        )
+                                        #( #method_callbacks )*
+                                        .invoke_result_stream::<#item, _>()
+                                        .await?
+                                        .map(|stream| stream.into()))
+                                }
+                            }),
+                            (Some(item), _) => client_method_impls.push(quote! {
+                                pub async fn #method_ident ( &self, #method_transformed_inputs ) -> std::result::Result<#ret, wasmer_bus::abi::BusError> {
+                                    let request = #request_name {
+                                        #field_idents
+                                    };
+                                    Ok(wasmer_bus::abi::call(
+                                            self.ctx.clone(),
+                                            #format,
+                                            request
+                                        )
+                                        #( #method_callbacks )*
+                                        .invoke_stream::<#item>()
+                                        .into())
+                                }
+                            }),
+                            (None, None) => client_method_impls.push(quote! {
+                                pub async fn #method_ident ( &self, #method_transformed_inputs ) -> std::result::Result<#ret, wasmer_bus::abi::BusError> {
+                                    let request = #request_name {
+                                        #field_idents
+                                    };
+                                    wasmer_bus::abi::call(
+                                            self.ctx.clone(),
+                                            #format,
+                                            request
+                                        )
+                                        #( #method_callbacks )*
+                                        #method_timeout
+                                        .invoke()
+                                        .join()?
+                                        .await
+                                }
+                            })
+                        }
                         blocking_methods.push(quote! {
                             fn #blocking_method_ident ( &self, #method_transformed_inputs ) -> std::result::Result<#ret, wasmer_bus::abi::BusError>;
                         });
@@ -502,12 +644,30 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                         }
 
                         pub fn listen(wasm_me: std::sync::Arc<dyn #trait_ident>) {
+                            Self::register_schema();
                             #( #listens )*
                         }
     
                         pub async fn serve() {
                             wasmer_bus::task::serve().await;
                         }
+
+                        pub fn schema() -> wasmer_bus::abi::InterfaceSchema {
+                            wasmer_bus::abi::InterfaceSchema {
+                                name: #trait_name.to_string(),
+                                module: module_path!().to_string(),
+                                format: #format,
+                                methods: vec![ #( #schema_methods ),* ],
+                            }
+                        }
+
+                        /// Adds the schema of this interface (and of the interfaces
+                        /// it returns) to the registry
+                        pub fn register_schema() {
+                            if wasmer_bus::schema::register(Self::schema()) {
+                                #( #schema_sub_interfaces )*
+                            }
+                        }
                     }
                 }
             );
@@ -522,6 +682,7 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                         ctx: wasmer_bus::abi::CallContext,
                         task: Option<wasmer_bus::abi::Call>,
                         join: Option<wasmer_bus::abi::CallJoin<()>>,
+                        timeout: Option<std::time::Duration>,
                     }
 
                     impl #trait_client_ident {
@@ -533,6 +694,7 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                                 },
                                 task: None,
                                 join: None,
+                                timeout: None,
                             }
                         }
 
@@ -544,6 +706,7 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                                 },
                                 task: None,
                                 join: None,
+                                timeout: None,
                             }
                         }
 
@@ -553,9 +716,17 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                                 ctx: wasmer_bus::abi::CallContext::OwnedSubCall { parent: handle },
                                 task: None,
                                 join: None,
+                                timeout: None,
                             }
                         }
                         
+                        /// Limits how long each call made by this client (and the
+                        /// clients of the interfaces it returns) may take
+                        pub fn with_timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+                            self.timeout = timeout;
+                            self
+                        }
+
                         pub fn wait(self) -> Result<(), wasmer_bus::abi::BusError> {
                             if let Some(join) = self.join {
                                 join.wait()?;
@@ -577,6 +748,10 @@ pub fn convert(args: Args, input: Item) -> proc_macro::TokenStream {
                             }
                         }
 
+                        pub fn schema() -> wasmer_bus::abi::InterfaceSchema {
+                            #trait_service_ident::schema()
+                        }
+
                         #( #client_method_impls )*
 
                         #( #blocking_client_method_impls )*
@@ -18,6 +18,8 @@ mod method_output;
 mod parse;
 mod receiver;
 mod return_trait;
+mod schema;
+mod stream;
 
 use crate::args::Args;
 use crate::convert::convert;
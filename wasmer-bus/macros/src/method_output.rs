@@ -6,6 +6,7 @@ use syn::parse::{Parse, ParseStream};
 use syn::*;
 
 use super::return_trait::*;
+use super::stream::*;
 
 #[derive(Debug, Clone)]
 pub enum MethodOutput {
@@ -23,6 +24,24 @@ impl MethodOutput {
         }
     }
 
+    /// Returns the type of the items of the stream that the method returns
+    /// (if it returns one)
+    pub fn stream_item(&self) -> Option<TokenStream> {
+        match self {
+            MethodOutput::Message(a) => stream_item_path(&a.path),
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the items of the stream that the method returns
+    /// when it can also fail before there is a stream to return
+    pub fn result_stream_item(&self) -> Option<TokenStream> {
+        match self {
+            MethodOutput::Message(a) => result_stream_item_path(&a.path),
+            _ => None,
+        }
+    }
+
     pub fn ident(&self) -> TokenStream {
         match self {
             MethodOutput::Trait(a) => {
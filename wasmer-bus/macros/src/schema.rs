@@ -0,0 +1,122 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use super::method_output::*;
+
+/// Renders a type the way it would be written in code (the token stream
+/// puts spaces between every token, e.g. `Vec < u8 >`)
+pub fn type_string(tokens: &TokenStream) -> String {
+    let joins = |c: char| "<>:,;()[]& ".contains(c);
+    let mut ret = String::new();
+    let mut space = false;
+    for c in tokens.to_string().chars() {
+        if c == ' ' {
+            space = true;
+            continue;
+        }
+        if space && ret.ends_with(|p: char| joins(p)) == false && joins(c) == false {
+            ret.push(' ');
+        }
+        space = false;
+        ret.push(c);
+        if c == ',' || c == ';' {
+            ret.push(' ');
+        }
+    }
+    ret
+}
+
+/// Emits the schema of a normal argument of a method
+pub fn arg_schema(name: &str, ty: &TokenStream) -> TokenStream {
+    let ty = type_string(ty);
+    quote! {
+        wasmer_bus::abi::ArgSchema {
+            name: #name.to_string(),
+            ty: #ty.to_string(),
+            callback: None,
+            stream: false,
+        }
+    }
+}
+
+/// Emits the schema of a stream argument where `item` is the type of the
+/// items that it carries
+pub fn stream_arg_schema(name: &str, item: &TokenStream) -> TokenStream {
+    let ty = type_string(item);
+    quote! {
+        wasmer_bus::abi::ArgSchema {
+            name: #name.to_string(),
+            ty: #ty.to_string(),
+            callback: None,
+            stream: true,
+        }
+    }
+}
+
+/// Emits the schema of a callback argument where `ty` is the type that
+/// is passed to the callback
+pub fn callback_schema(name: &str, ty: &TokenStream, callback: &TokenStream) -> TokenStream {
+    let ty = type_string(ty);
+    quote! {
+        wasmer_bus::abi::ArgSchema {
+            name: #name.to_string(),
+            ty: #ty.to_string(),
+            callback: Some(std::any::type_name::<#callback>().to_string()),
+            stream: false,
+        }
+    }
+}
+
+/// Emits the schema of what a method returns
+pub fn return_schema(output: &MethodOutput) -> TokenStream {
+    match output {
+        MethodOutput::Trait(a) => {
+            let name = a.ident.to_string();
+            quote! {
+                wasmer_bus::abi::ReturnSchema::Interface { name: #name.to_string() }
+            }
+        }
+        MethodOutput::Message(_) if output.stream_item().or(output.result_stream_item()).is_some() => {
+            let ty = type_string(&output.stream_item().or(output.result_stream_item()).unwrap());
+            quote! {
+                wasmer_bus::abi::ReturnSchema::Stream { ty: #ty.to_string() }
+            }
+        }
+        MethodOutput::Message(a) => {
+            let path = a.path.clone();
+            let ty = type_string(&quote! { #path });
+            quote! {
+                wasmer_bus::abi::ReturnSchema::Message { ty: #ty.to_string() }
+            }
+        }
+        MethodOutput::Nothing => quote! { wasmer_bus::abi::ReturnSchema::Nothing },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_string_joins_tokens() {
+        assert_eq!(type_string(&quote! { u32 }), "u32");
+        assert_eq!(type_string(&quote! { Vec<u8> }), "Vec<u8>");
+        assert_eq!(type_string(&quote! { Option<Vec<String>> }), "Option<Vec<String>>");
+        assert_eq!(type_string(&quote! { std::collections::HashMap<String, u64> }), "std::collections::HashMap<String, u64>");
+    }
+
+    #[test]
+    fn type_string_separates_arguments() {
+        assert_eq!(type_string(&quote! { Result<Vec<u8>, FsError> }), "Result<Vec<u8>, FsError>");
+        assert_eq!(type_string(&quote! { (u32, String) }), "(u32, String)");
+        assert_eq!(type_string(&quote! { () }), "()");
+        assert_eq!(type_string(&quote! { [u8; 32] }), "[u8; 32]");
+    }
+
+    #[test]
+    fn type_string_keeps_words_apart() {
+        assert_eq!(type_string(&quote! { &str }), "&str");
+        assert_eq!(type_string(&quote! { &'a str }), "&'a str");
+        assert_eq!(type_string(&quote! { Box<dyn Error + Send> }), "Box<dyn Error + Send>");
+    }
+}
@@ -0,0 +1,48 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::*;
+
+/// Returns the type of the items that a stream carries (a `ByteStream`
+/// carries chunks of bytes) or `None` when the path is not a stream
+pub fn stream_item_path(path: &Path) -> Option<TokenStream> {
+    let last = path.segments.last()?;
+    match last.ident.to_string().as_str() {
+        "ByteStream" => Some(quote! { Vec<u8> }),
+        "MessageStream" => match &last.arguments {
+            PathArguments::AngleBracketed(angle) if angle.args.len() == 1 => {
+                match angle.args.first() {
+                    Some(GenericArgument::Type(ty)) => Some(quote! { #ty }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the type of the items of a stream that is wrapped in a result
+/// (e.g. `Result<ByteStream, E>` or an alias such as `FsResult<ByteStream>`)
+pub fn result_stream_item_path(path: &Path) -> Option<TokenStream> {
+    let last = path.segments.last()?;
+    if last.ident.to_string().ends_with("Result") == false {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(angle) => match angle.args.first() {
+            Some(GenericArgument::Type(Type::Path(inner))) if inner.qself.is_none() => {
+                stream_item_path(&inner.path)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the type of the items that a stream argument carries
+pub fn stream_item(ty: &Type) -> Option<TokenStream> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => stream_item_path(&path.path),
+        _ => None,
+    }
+}
@@ -1,5 +1,9 @@
 mod error;
 mod format;
+mod schema;
+mod stream;
 
 pub use error::*;
-pub use format::*;
\ No newline at end of file
+pub use format::*;
+pub use schema::*;
+pub use stream::*;
\ No newline at end of file
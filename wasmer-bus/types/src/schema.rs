@@ -0,0 +1,96 @@
+use serde::*;
+
+use crate::SerializationFormat;
+
+/// Machine readable definition of a bus interface (emitted by the
+/// `wasmer_bus` macro for every trait) so that clients written in other
+/// languages and tooling can discover the methods of a service
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceSchema {
+    /// Name of the trait (e.g. `FileSystem`)
+    pub name: String,
+    /// Module path of the trait (e.g. `wasmer_bus_fuse::api`)
+    pub module: String,
+    /// Format used to serialize the requests and responses
+    pub format: SerializationFormat,
+    pub methods: Vec<MethodSchema>,
+}
+
+impl InterfaceSchema {
+    /// Fully qualified name of the interface
+    pub fn path(&self) -> String {
+        format!("{}::{}", self.module, self.name)
+    }
+
+    /// Returns the names of the interfaces that are returned by the
+    /// methods of this interface (e.g. `Arc<dyn OpenedFile>`)
+    pub fn sub_interfaces(&self) -> Vec<&str> {
+        let mut ret = Vec::new();
+        for method in self.methods.iter() {
+            if let ReturnSchema::Interface { name } = &method.returns {
+                if ret.contains(&name.as_str()) == false {
+                    ret.push(name.as_str());
+                }
+            }
+        }
+        ret
+    }
+
+    pub fn method(&self, name: &str) -> Option<&MethodSchema> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+impl MethodSchema {
+    /// Returns true if any of the arguments or the return value is a stream
+    pub fn has_streams(&self) -> bool {
+        self.args.iter().any(|a| a.stream) || matches!(self.returns, ReturnSchema::Stream { .. })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MethodSchema {
+    pub name: String,
+    /// Topic of the request object (the hash of this is what is sent
+    /// over the bus when the method is invoked)
+    pub topic: String,
+    pub args: Vec<ArgSchema>,
+    pub returns: ReturnSchema,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ArgSchema {
+    pub name: String,
+    /// Rust type of the argument (for callbacks this is the type passed
+    /// to the callback)
+    pub ty: String,
+    /// Topic of the callback object when this argument is a callback
+    pub callback: Option<String>,
+    /// The argument is a stream of `ty` items which are sent after the
+    /// call is made (rather than in the request object)
+    #[serde(default)]
+    pub stream: bool,
+}
+
+impl ArgSchema {
+    pub fn is_callback(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    pub fn is_stream(&self) -> bool {
+        self.stream
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReturnSchema {
+    /// The method returns nothing
+    Nothing,
+    /// The method returns a serialized object of this Rust type
+    Message { ty: String },
+    /// The method returns a stream of serialized objects of this Rust type
+    Stream { ty: String },
+    /// The method returns another interface (`Arc<dyn Name>`) which
+    /// further calls are made on
+    Interface { name: String },
+}
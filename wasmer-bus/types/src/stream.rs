@@ -0,0 +1,34 @@
+use serde::*;
+
+use crate::BusError;
+
+/// Number of frames that the sender of a stream may have in flight
+/// before it must wait for the receiver to grant it more credit
+pub const STREAM_WINDOW: u32 = 16;
+
+/// Largest chunk of bytes that is sent in a single frame of a byte stream
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Frame of a stream that carries one item (serialized using the format
+/// of the call that the stream belongs to)
+///
+/// Frames travel as sub-calls of the call that the stream is an argument
+/// or a return value of, the receiver grants credit for more frames as it
+/// consumes them which bounds how much data can be buffered
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StreamData {
+    pub data: Vec<u8>,
+}
+
+/// Allows the sender of a stream to send more frames
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StreamCredit {
+    pub frames: u32,
+}
+
+/// Marks the end of a stream that is an argument of a call (a stream that
+/// is returned ends when the call itself finishes)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StreamEnd {
+    pub error: Option<BusError>,
+}
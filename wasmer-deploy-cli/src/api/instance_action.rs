@@ -4,14 +4,17 @@ use error_chain::bail;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 use std::ops::Deref;
+use std::sync::Arc;
 
 use crate::error::*;
-use crate::model::{INSTANCE_ROOT_ID, ServiceInstance, WalletInstance, MasterAuthority, MASTER_AUTHORITY_ID};
+use crate::model::{INSTANCE_ROOT_ID, ServiceInstance, WalletInstance, MasterAuthority, MASTER_AUTHORITY_ID, InstanceUsage, instance_usage_chain, instance_usage_key};
 
 use super::*;
 
 impl DeployApi {
-    pub async fn instance_load(&self, wallet_instance: &WalletInstance) -> Result<DaoMut<ServiceInstance>, LoadError>
+    /// Opens the chain of an instance and builds the session that has
+    /// the rights of its owner
+    async fn instance_session(&self, wallet_instance: &WalletInstance) -> Result<(Arc<Chain>, AteSessionGroup), LoadError>
     {
         // Get the sudo rights from the session (as we will use these for the wallet)
         let sudo_private_read = match self.session().private_read_keys(AteSessionKeyCategory::SudoKeys).next() {
@@ -39,12 +42,36 @@ impl DeployApi {
         chain_session.add_group_gid(&AteRolePurpose::Contributor, 0);
         chain_session.add_group_read_key(&AteRolePurpose::Observer, &master_authority.read);
         chain_session.add_group_write_key(&AteRolePurpose::Contributor, &master_authority.write);
+        Ok((chain.as_arc(), chain_session))
+    }
+
+    pub async fn instance_load(&self, wallet_instance: &WalletInstance) -> Result<DaoMut<ServiceInstance>, LoadError>
+    {
+        let (chain, chain_session) = self.instance_session(wallet_instance).await?;
         
         // Load the instance
         let chain_dio = chain.dio_full(&chain_session).await;
         chain_dio.load::<ServiceInstance>(&PrimaryKey::from(INSTANCE_ROOT_ID)).await
     }
 
+    /// Loads the usage and limits that the operator of the instance servers
+    /// has recorded for an instance (if it has run yet)
+    pub async fn instance_usage(&self, wallet_instance: &WalletInstance, instance_authority: &str) -> Result<Option<InstanceUsage>, LoadError>
+    {
+        // The record is readable with the same key as the instance chain
+        let (_, chain_session) = self.instance_session(wallet_instance).await?;
+
+        let db_url: Result<_, LoadError> = self.db_url.clone().ok_or_else(|| LoadErrorKind::IO("the db_url is not set which is required to access instances".to_string()).into());
+        let chain = self.registry.open(&db_url?, &instance_usage_chain(instance_authority), true).await?;
+        let dio = chain.dio(&chain_session).await;
+
+        let key = instance_usage_key(&ChainKey::from(wallet_instance.chain.clone()));
+        if dio.exists(&key).await == false {
+            return Ok(None);
+        }
+        Ok(Some(dio.load::<InstanceUsage>(&key).await?.take()))
+    }
+
     pub async fn instance_action(
         &mut self,
         name: &str,
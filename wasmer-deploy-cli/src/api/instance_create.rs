@@ -159,6 +159,8 @@ impl DeployApi {
                 admin_token,
                 exports: DaoVec::new(),
                 mesh_nodes: DaoVec::new(),
+                triggers: DaoVec::new(),
+                secrets: DaoVec::new(),
             },
             PrimaryKey::from(INSTANCE_ROOT_ID),
         )?;
@@ -5,6 +5,7 @@ use std::sync::Arc;
 use std::sync::Mutex;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, trace, warn};
+use wasmer_bus::abi::STREAM_CHUNK_SIZE;
 use wasmer_bus_fuse::api;
 use wasmer_bus_fuse::prelude::*;
 
@@ -25,6 +26,48 @@ impl FileIo {
             append,
         }
     }
+
+    /// Writes bytes at the current offset (or the end when appending)
+    pub async fn write_bytes(&self, data: &[u8]) -> FsResult<u64> {
+        let file = self.handle.as_ref();
+        let offset = {
+            let mut offset = self.offset.lock().unwrap();
+            if self.append {
+                *offset = file.spec.size();
+            }
+            *offset
+        };
+
+        let written = file.spec.write(offset, data).await.map_err(|err| {
+            debug!("write failed - {}", err);
+            FsError::IOError
+        })?;
+        {
+            let mut guard = self.offset.lock().unwrap();
+            *guard = offset + written;
+        }
+        Ok(written)
+    }
+
+    /// Reads up to `len` bytes from the current offset
+    pub async fn read_bytes(&self, len: u64) -> FsResult<Vec<u8>> {
+        let file = self.handle.as_ref();
+        let offset = { self.offset.lock().unwrap().clone() };
+        let ret = file
+            .spec
+            .read(offset, len)
+            .await
+            .map_err(|err| {
+                debug!("read failed - {}", err);
+                FsError::IOError
+            })
+            .map(|a| a.to_vec())?;
+        {
+            let mut guard = self.offset.lock().unwrap();
+            *guard = offset + (ret.len() as u64);
+        }
+        Ok(ret)
+    }
 }
 
 #[async_trait]
@@ -78,43 +121,51 @@ impl api::FileIOSimplified for FileIo {
         })
     }
 
-    async fn write(&self, data: Vec<u8>) -> FsResult<u64> {
-        let file = self.handle.as_ref();
-        let offset = {
-            let mut offset = self.offset.lock().unwrap();
-            if self.append {
-                *offset = file.spec.size();
-            }
-            *offset
-        };
-
-        let written = file.spec.write(offset, &data[..]).await.map_err(|err| {
-            debug!("write failed - {}", err);
-            FsError::IOError
-        })?;
-        {
-            let mut guard = self.offset.lock().unwrap();
-            *guard = offset + written;
+    async fn write(&self, mut data: ByteStream) -> FsResult<u64> {
+        let mut written = 0u64;
+        while let Some(chunk) = data.next_chunk().await {
+            let chunk = chunk.map_err(|err| {
+                debug!("write failed - {}", err);
+                FsError::IOError
+            })?;
+            written += self.write_bytes(&chunk[..]).await?;
         }
         Ok(written)
     }
 
-    async fn read(&self, len: u64) -> FsResult<Vec<u8>> {
-        let file = self.handle.as_ref();
-        let offset = { self.offset.lock().unwrap().clone() };
-        let ret = file
-            .spec
-            .read(offset, len)
-            .await
-            .map_err(|err| {
-                debug!("read failed - {}", err);
-                FsError::IOError
-            })
-            .map(|a| a.to_vec())?;
-        {
-            let mut guard = self.offset.lock().unwrap();
-            *guard = offset + (ret.len() as u64);
+    async fn read(&self, len: u64) -> FsResult<ByteStream> {
+        // The first chunk is read up front so that the caller gets the error
+        // if the file can not be read at all
+        let first = self.read_bytes(len.min(STREAM_CHUNK_SIZE as u64)).await?;
+        if first.is_empty() {
+            return Ok(ByteStream::from(first));
         }
-        Ok(ret)
+
+        // The rest of the file is read a chunk at a time as the caller consumes it
+        let (tx, rx) = ByteStream::channel();
+        let io = self.clone();
+        wasmer_bus::task::spawn(async move {
+            let mut remaining = len - first.len() as u64;
+            if tx.write(&first[..]).await.is_err() {
+                return;
+            }
+            while remaining > 0 {
+                let chunk = remaining.min(STREAM_CHUNK_SIZE as u64);
+                match io.read_bytes(chunk).await {
+                    Ok(data) if data.is_empty() => return,
+                    Ok(data) => {
+                        remaining -= data.len() as u64;
+                        if tx.write(&data[..]).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        tx.fail(err.into()).await;
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(rx)
     }
 }
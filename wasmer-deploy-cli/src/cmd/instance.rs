@@ -104,6 +104,13 @@ pub async fn main_opts_instance_details(
 
     if let Ok(service_instance) = api.instance_load(instance.deref()).await {
         println!("{}", serde_json::to_string_pretty(&service_instance.subnet).unwrap());
+        if service_instance.triggers.len().await? > 0 {
+            println!("Triggers");
+            for trigger in service_instance.triggers.iter().await? {
+                println!("- {} ({}) -> {}", trigger.name, trigger.kind, trigger.binary);
+            }
+            println!("");
+        }
 
         if service_instance.exports.len().await? > 0 {
             let id = service_instance.id_str();
@@ -439,6 +446,46 @@ pub async fn main_opts_instance_peering(
     Ok(())
 }
 
+pub async fn main_opts_instance_limits(
+    api: &mut DeployApi,
+    name: &str,
+    instance_authority: &str,
+    action: OptsLimitsAction,
+) -> Result<(), InstanceError> {
+    let (_, wallet_instance) = api.instance_action(name).await?;
+    
+    main_opts_limits(api, wallet_instance.deref(), instance_authority, action).await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_instance_trigger(
+    api: &mut DeployApi,
+    inst_url: url::Url,
+    name: &str,
+    action: OptsTriggerAction,
+) -> Result<(), InstanceError> {
+    let (instance, _) = api.instance_action(name).await?;
+    let instance = instance?;
+    
+    main_opts_trigger(instance, &inst_url, action).await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_instance_secret(
+    api: &mut DeployApi,
+    name: &str,
+    action: OptsSecretAction,
+) -> Result<(), InstanceError> {
+    let (instance, _) = api.instance_action(name).await?;
+    let instance = instance?;
+    
+    main_opts_secret(instance, action).await?;
+
+    Ok(())
+}
+
 pub async fn main_opts_instance_reset(
     api: &mut DeployApi,
     name: &str,
@@ -546,6 +593,21 @@ pub async fn main_opts_instance(
             let name = name.unwrap();
             main_opts_instance_reset(&mut context.api, name.as_str()).await?;
         }
+        OptsInstanceAction::Limits(opts_limits) => {
+            if name.is_none() { bail!(InstanceErrorKind::InvalidInstance); }
+            let name = name.unwrap();
+            main_opts_instance_limits(&mut context.api, name.as_str(), instance_authority.as_str(), opts_limits.action).await?;
+        }
+        OptsInstanceAction::Trigger(opts_trigger) => {
+            if name.is_none() { bail!(InstanceErrorKind::InvalidInstance); }
+            let name = name.unwrap();
+            main_opts_instance_trigger(&mut context.api, inst_url, name.as_str(), opts_trigger.action).await?;
+        }
+        OptsInstanceAction::Secret(opts_secret) => {
+            if name.is_none() { bail!(InstanceErrorKind::InvalidInstance); }
+            let name = name.unwrap();
+            main_opts_instance_secret(&mut context.api, name.as_str(), opts_secret.action).await?;
+        }
     }
 
     Ok(())
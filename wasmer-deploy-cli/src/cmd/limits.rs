@@ -0,0 +1,52 @@
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::api::DeployApi;
+use crate::error::*;
+use crate::model::WalletInstance;
+use crate::opt::*;
+
+pub async fn main_opts_limits_show(
+    api: &mut DeployApi,
+    wallet_instance: &WalletInstance,
+    instance_authority: &str,
+) -> Result<(), InstanceError> {
+    // The limits and usage are recorded by the operator of the instance servers
+    let usage = match api.instance_usage(wallet_instance, instance_authority).await? {
+        Some(a) => a,
+        None => {
+            println!("no usage has been recorded for this instance yet");
+            return Ok(());
+        }
+    };
+
+    println!("|-------limit-------|");
+    print!("{}", usage.limits);
+
+    let metrics = &usage.metrics;
+    println!("");
+    println!("|-------usage-------|");
+    println!("{:<24}{}", "fuel", metrics.current_accumilated_fuel);
+    println!("{:<24}{}", "compute-micros", metrics.current_accumilated_compute);
+    println!("{:<24}{}", "download-bytes", metrics.current_accumilated_download);
+    println!("{:<24}{}", "upload-bytes", metrics.current_accumilated_upload);
+
+    Ok(())
+}
+
+pub async fn main_opts_limits(
+    api: &mut DeployApi,
+    wallet_instance: &WalletInstance,
+    instance_authority: &str,
+    action: OptsLimitsAction,
+) -> Result<(), InstanceError>
+{
+    // Determine what we need to do
+    match action {
+        OptsLimitsAction::Show => {
+            main_opts_limits_show(api, wallet_instance, instance_authority).await?;
+        }
+    }
+
+    Ok(())
+}
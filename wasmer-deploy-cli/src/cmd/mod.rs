@@ -23,6 +23,9 @@ mod wallet;
 mod withdraw;
 mod instance;
 mod cidr;
+mod limits;
+mod trigger;
+mod secret;
 mod peering;
 pub(crate) mod network;
 
@@ -53,5 +56,8 @@ pub use wallet::*;
 pub use withdraw::*;
 pub use instance::*;
 pub use cidr::*;
+pub use limits::*;
+pub use trigger::*;
+pub use secret::*;
 pub use peering::*;
 pub use network::*;
\ No newline at end of file
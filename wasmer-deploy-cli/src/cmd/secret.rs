@@ -0,0 +1,117 @@
+use std::io::Read;
+use ate::prelude::*;
+use error_chain::bail;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::error::*;
+use crate::model::{ServiceInstance, InstanceSecret};
+use crate::opt::*;
+
+pub async fn main_opts_secret_list(
+    instance: DaoMut<ServiceInstance>,
+) -> Result<(), InstanceError> {
+    println!("|-------name-------|-------updated-------|");
+    for secret in instance.secrets.iter().await? {
+        println!(
+            "- {:<16} - {}",
+            secret.name, secret.updated.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn main_opts_secret_set(
+    mut instance: DaoMut<ServiceInstance>,
+    opts: OptsSecretSet,
+) -> Result<(), InstanceError> {
+    if InstanceSecret::is_valid_name(opts.key.as_str()) == false {
+        bail!(InstanceErrorKind::InvalidSecretName(opts.key));
+    }
+
+    // If no value was supplied then its read from stdin (which keeps
+    // it out of the shell history)
+    let value = match opts.value {
+        Some(a) => a,
+        None => {
+            let mut value = String::new();
+            std::io::stdin()
+                .lock()
+                .read_to_string(&mut value)
+                .map_err(|_| InstanceErrorKind::NoInput)?;
+            value.trim_end_matches(|c| c == '\r' || c == '\n').to_string()
+        }
+    };
+
+    // Secrets are encrypted with the read key of the group that owns the
+    // instance so that only its owner and the instance can read them
+    let dio = instance.dio_mut();
+    let read_key = dio.session()
+        .read_keys(AteSessionKeyCategory::GroupKeys)
+        .next()
+        .map(|a| a.clone())
+        .ok_or_else(|| InstanceErrorKind::Unauthorized)?;
+
+    let existing = instance.as_mut().secrets.iter_mut().await?
+        .filter(|s| s.name == opts.key)
+        .next();
+    let mut secret = match existing {
+        Some(mut secret) => {
+            {
+                let mut secret = secret.as_mut();
+                secret.value = value;
+                secret.updated = chrono::Utc::now();
+            }
+            secret
+        }
+        None => {
+            instance.as_mut().secrets.push(InstanceSecret {
+                name: opts.key.clone(),
+                value,
+                updated: chrono::Utc::now(),
+            })?
+        }
+    };
+    secret.auth_mut().read = ReadOption::from_key(&read_key);
+    dio.commit().await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_secret_remove(
+    mut instance: DaoMut<ServiceInstance>,
+    opts: OptsSecretRemove,
+) -> Result<(), InstanceError> {
+    let dio = instance.dio_mut();
+
+    let secret = instance.as_mut().secrets.iter_mut().await?
+        .filter(|s| s.name == opts.key)
+        .next()
+        .ok_or_else(|| InstanceErrorKind::InvalidSecret(opts.key.clone()))?;
+    secret.delete()?;
+    dio.commit().await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_secret(
+    instance: DaoMut<ServiceInstance>,
+    action: OptsSecretAction,
+) -> Result<(), InstanceError>
+{
+    // Determine what we need to do
+    match action {
+        OptsSecretAction::List => {
+            main_opts_secret_list(instance).await?;
+        }
+        OptsSecretAction::Set(set) => {
+            main_opts_secret_set(instance, set).await?;
+        }
+        OptsSecretAction::Rm(remove) => {
+            main_opts_secret_remove(instance, remove).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::io::Read;
+use std::str::FromStr;
+use ate::prelude::*;
+use ate::chain::ChainKey;
+use ate::crypto::AteHash;
+use error_chain::bail;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::error::*;
+use crate::model::{ServiceInstance, InstanceTrigger, TriggerKind, TriggerEvent, CronSchedule};
+use crate::opt::*;
+
+pub async fn main_opts_trigger_list(
+    instance: DaoMut<ServiceInstance>,
+) -> Result<(), InstanceError> {
+    println!("|-------name-------|-------binary-------|-enabled-|-kind");
+    for trigger in instance.triggers.iter().await? {
+        println!(
+            "- {:<16} - {:<18} - {:<7} - {}",
+            trigger.name, trigger.binary, trigger.enabled, trigger.kind
+        );
+    }
+
+    Ok(())
+}
+
+async fn main_opts_trigger_add(
+    mut instance: DaoMut<ServiceInstance>,
+    trigger: String,
+    binary: String,
+    args: Vec<String>,
+    kind: TriggerKind,
+    max_retries: u32,
+    retry_delay_ms: u64,
+) -> Result<(), InstanceError> {
+    if instance.triggers.iter().await?.any(|t| t.name.eq_ignore_ascii_case(trigger.as_str())) {
+        bail!(InstanceErrorKind::TriggerAlreadyExists(trigger));
+    }
+    if instance.exports.iter().await?.any(|e| e.binary.eq_ignore_ascii_case(binary.as_str())) == false {
+        bail!(InstanceErrorKind::NotExported);
+    }
+
+    let dio = instance.dio_mut();
+    instance.as_mut().triggers.push(InstanceTrigger {
+        name: trigger,
+        binary,
+        args,
+        kind,
+        enabled: true,
+        max_retries,
+        retry_delay_ms,
+        events: DaoVec::new(),
+        runs: DaoVec::new(),
+    })?;
+    dio.commit().await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_trigger_add_schedule(
+    instance: DaoMut<ServiceInstance>,
+    opts: OptsTriggerAddSchedule,
+) -> Result<(), InstanceError> {
+    let schedule = CronSchedule::from_str(opts.schedule.as_str())
+        .map_err(|err| InstanceErrorKind::InvalidSchedule(err))?;
+    if let Some(next) = schedule.next_after(chrono::Utc::now()) {
+        println!("Next run: {}", next.format("%Y-%m-%d %H:%M:%S UTC"));
+    } else {
+        warn!("the schedule ({}) will never fire", schedule);
+    }
+
+    main_opts_trigger_add(instance, opts.trigger, opts.binary, opts.args, TriggerKind::Schedule(schedule), opts.retries, opts.retry_delay_ms).await
+}
+
+pub async fn main_opts_trigger_add_chain(
+    instance: DaoMut<ServiceInstance>,
+    opts: OptsTriggerAdd,
+) -> Result<(), InstanceError> {
+    main_opts_trigger_add(instance, opts.trigger, opts.binary, opts.args, TriggerKind::ChainEvent, opts.retries, opts.retry_delay_ms).await
+}
+
+pub async fn main_opts_trigger_add_webhook(
+    instance: DaoMut<ServiceInstance>,
+    inst_url: &url::Url,
+    opts: OptsTriggerAdd,
+) -> Result<(), InstanceError> {
+    let chain = ChainKey::from(instance.chain.clone());
+    let token = AteHash::generate().to_hex_string();
+    let url = compute_webhook_url(inst_url, &chain, opts.trigger.as_str());
+
+    main_opts_trigger_add(instance, opts.trigger, opts.binary, opts.args, TriggerKind::Webhook { token: token.clone() }, opts.retries, opts.retry_delay_ms).await?;
+
+    println!("Authorization: {}", token);
+    println!("POST: {}", url);
+    Ok(())
+}
+
+fn compute_webhook_url(inst_url: &url::Url, chain: &ChainKey, trigger: &str) -> String
+{
+    let domain = inst_url.domain().unwrap_or_else(|| "localhost");
+    format!("https://{}/hook/{}/{}", domain, chain.to_string(), trigger)
+}
+
+pub async fn main_opts_trigger_remove(
+    mut instance: DaoMut<ServiceInstance>,
+    opts: OptsTriggerName,
+) -> Result<(), InstanceError> {
+    let dio = instance.dio_mut();
+
+    let mut trigger = instance.as_mut().triggers.iter_mut().await?
+        .filter(|t| t.name.eq_ignore_ascii_case(opts.trigger.as_str()))
+        .next()
+        .ok_or_else(|| InstanceErrorKind::InvalidTrigger(opts.trigger.clone()))?;
+    {
+        let mut trigger = trigger.as_mut();
+        trigger.events.clear().await?;
+        trigger.runs.clear().await?;
+    }
+    trigger.delete()?;
+    dio.commit().await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_trigger_enable(
+    mut instance: DaoMut<ServiceInstance>,
+    opts: OptsTriggerName,
+    enabled: bool,
+) -> Result<(), InstanceError> {
+    let dio = instance.dio_mut();
+
+    let mut trigger = instance.as_mut().triggers.iter_mut().await?
+        .filter(|t| t.name.eq_ignore_ascii_case(opts.trigger.as_str()))
+        .next()
+        .ok_or_else(|| InstanceErrorKind::InvalidTrigger(opts.trigger.clone()))?;
+    trigger.as_mut().enabled = enabled;
+    dio.commit().await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_trigger_fire(
+    mut instance: DaoMut<ServiceInstance>,
+    opts: OptsTriggerName,
+) -> Result<(), InstanceError> {
+    let mut data = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut data)
+        .map_err(|_| InstanceErrorKind::NoInput)?;
+
+    let dio = instance.dio_mut();
+
+    let mut trigger = instance.as_mut().triggers.iter_mut().await?
+        .filter(|t| t.name.eq_ignore_ascii_case(opts.trigger.as_str()))
+        .next()
+        .ok_or_else(|| InstanceErrorKind::InvalidTrigger(opts.trigger.clone()))?;
+    if matches!(trigger.kind, TriggerKind::ChainEvent) == false {
+        eprintln!("Only chain triggers can be fired with an event.");
+        std::process::exit(1);
+    }
+    trigger.as_mut().events.push(TriggerEvent {
+        when: chrono::Utc::now(),
+        data,
+    })?;
+    dio.commit().await?;
+
+    Ok(())
+}
+
+pub async fn main_opts_trigger_history(
+    instance: DaoMut<ServiceInstance>,
+    opts: OptsTriggerName,
+) -> Result<(), InstanceError> {
+    let trigger = instance.triggers.iter().await?
+        .filter(|t| t.name.eq_ignore_ascii_case(opts.trigger.as_str()))
+        .next()
+        .ok_or_else(|| InstanceErrorKind::InvalidTrigger(opts.trigger.clone()))?;
+
+    let mut runs = trigger.runs.iter().await?
+        .map(|r| r.take())
+        .collect::<Vec<_>>();
+    runs.sort_by(|a, b| b.when.cmp(&a.when));
+
+    println!("|-------when-------|-attempts-|-duration-|-cause / outcome");
+    for run in runs {
+        println!(
+            "- {} - {:<8} - {:>6}ms - {} / {}",
+            run.when.format("%Y-%m-%d %H:%M:%S"), run.attempts, run.duration_ms, run.cause, run.outcome
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn main_opts_trigger(
+    instance: DaoMut<ServiceInstance>,
+    inst_url: &url::Url,
+    action: OptsTriggerAction,
+) -> Result<(), InstanceError>
+{
+    // Determine what we need to do
+    match action {
+        OptsTriggerAction::List => {
+            main_opts_trigger_list(instance).await?;
+        }
+        OptsTriggerAction::AddSchedule(add) => {
+            main_opts_trigger_add_schedule(instance, add).await?;
+        }
+        OptsTriggerAction::AddChain(add) => {
+            main_opts_trigger_add_chain(instance, add).await?;
+        }
+        OptsTriggerAction::AddWebhook(add) => {
+            main_opts_trigger_add_webhook(instance, inst_url, add).await?;
+        }
+        OptsTriggerAction::Remove(remove) => {
+            main_opts_trigger_remove(instance, remove).await?;
+        }
+        OptsTriggerAction::Enable(name) => {
+            main_opts_trigger_enable(instance, name, true).await?;
+        }
+        OptsTriggerAction::Disable(name) => {
+            main_opts_trigger_enable(instance, name, false).await?;
+        }
+        OptsTriggerAction::Fire(name) => {
+            main_opts_trigger_fire(instance, name).await?;
+        }
+        OptsTriggerAction::History(name) => {
+            main_opts_trigger_history(instance, name).await?;
+        }
+    }
+
+    Ok(())
+}
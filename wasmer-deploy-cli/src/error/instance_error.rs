@@ -48,6 +48,26 @@ error_chain! {
             description("the operation is not yet supported")
             display("the operation is not yet supported")
         }
+        InvalidTrigger(name: String) {
+            description("the trigger with this name could not be found")
+            display("the trigger with this name could not be found - {}", name)
+        }
+        TriggerAlreadyExists(name: String) {
+            description("a trigger with this name already exists")
+            display("a trigger with this name already exists - {}", name)
+        }
+        InvalidSecret(name: String) {
+            description("the secret with this name could not be found")
+            display("the secret with this name could not be found - {}", name)
+        }
+        InvalidSecretName(name: String) {
+            description("secret names must be valid environment variable names")
+            display("secret names must be valid environment variable names - {}", name)
+        }
+        InvalidSchedule(err: String) {
+            description("the schedule is not a valid cron expression")
+            display("the schedule is not a valid cron expression - {}", err)
+        }
     }
 }
 
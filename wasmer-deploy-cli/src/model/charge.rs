@@ -10,6 +10,7 @@ pub enum ChargeMetric {
     UploadBandwidth,
     DataStorage,
     Compute,
+    Fuel,
 }
 
 impl fmt::Display for ChargeMetric {
@@ -19,6 +20,7 @@ impl fmt::Display for ChargeMetric {
             ChargeMetric::UploadBandwidth => write!(f, "uploaded bandwidth"),
             ChargeMetric::DataStorage => write!(f, "data storage usage"),
             ChargeMetric::Compute => write!(f, "compute usage"),
+            ChargeMetric::Fuel => write!(f, "fuel usage"),
         }
     }
 }
@@ -39,6 +41,10 @@ pub enum ChargeUnits {
     GigaBytes(ChargeMetric),
     TeraBytes(ChargeMetric),
     PetaBytes(ChargeMetric),
+
+    Units(ChargeMetric),
+    Millions(ChargeMetric),
+    Billions(ChargeMetric),
 }
 
 impl ChargeUnits {
@@ -58,6 +64,10 @@ impl ChargeUnits {
             ChargeUnits::Hours(_) => Decimal::from(3600u64),
             ChargeUnits::Days(_) => Decimal::from(86400u64),
             ChargeUnits::Weeks(_) => Decimal::from(604800u64),
+
+            ChargeUnits::Units(_) => Decimal::from(1u64),
+            ChargeUnits::Millions(_) => Decimal::from(1000000u64),
+            ChargeUnits::Billions(_) => Decimal::from(1000000000u64),
         }
     }
 
@@ -77,6 +87,10 @@ impl ChargeUnits {
             ChargeUnits::Hours(_) => "h",
             ChargeUnits::Days(_) => "d",
             ChargeUnits::Weeks(_) => "w",
+
+            ChargeUnits::Units(_) => "",
+            ChargeUnits::Millions(_) => "M",
+            ChargeUnits::Billions(_) => "G",
         }
     }
 
@@ -96,6 +110,10 @@ impl ChargeUnits {
             ChargeUnits::Hours(a) => Some(a.clone()),
             ChargeUnits::Days(a) => Some(a.clone()),
             ChargeUnits::Weeks(a) => Some(a.clone()),
+
+            ChargeUnits::Units(a) => Some(a.clone()),
+            ChargeUnits::Millions(a) => Some(a.clone()),
+            ChargeUnits::Billions(a) => Some(a.clone()),
         }
     }
 }
@@ -117,6 +135,10 @@ impl fmt::Display for ChargeUnits {
             ChargeUnits::Hours(a) => write!(f, "hours {}", a),
             ChargeUnits::Days(a) => write!(f, "days {}", a),
             ChargeUnits::Weeks(a) => write!(f, "weeks {}", a),
+
+            ChargeUnits::Units(a) => write!(f, "units {}", a),
+            ChargeUnits::Millions(a) => write!(f, "millions {}", a),
+            ChargeUnits::Billions(a) => write!(f, "billions {}", a),
         }
     }
 }
@@ -2,6 +2,8 @@ use chrono::DateTime;
 use chrono::Utc;
 use serde::*;
 
+use super::ChargeMetric;
+
 /// Metrics are used to track provider services so that charges can
 /// be made to the consumer at appropriate moments
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -19,6 +21,9 @@ pub struct ContractMetrics {
     pub last_per_stored_gigabyte: Option<DateTime<Utc>>,
     /// Last time the compute charge waas incurred
     pub last_per_compute_second: Option<DateTime<Utc>>,
+    /// Last time the fuel charge was incurred
+    #[serde(default)]
+    pub last_per_fuel_billion: Option<DateTime<Utc>>,
 
     /// Current amount of compute usage accumilated since the last charge was made
     /// (measured in microseconds)
@@ -29,7 +34,25 @@ pub struct ContractMetrics {
     /// Current amount of upload bandwidth accumilated since the last charge was made
     /// (measured in bytes)
     pub current_accumilated_upload: u64,
+    /// Current amount of fuel consumed by metered processes since the last charge
+    /// was made (measured in instructions)
+    #[serde(default)]
+    pub current_accumilated_fuel: u64,
     /// Current amount of storage capacity that is being consumed
     /// (measured in bytes)
     pub current_storage: u64,
 }
+
+impl ContractMetrics {
+    /// Amount of a particular metric that has been accumilated since the
+    /// last charge was made (in the units the metric is measured in)
+    pub fn accumilated(&self, metric: ChargeMetric) -> u64 {
+        match metric {
+            ChargeMetric::DownloadBandwidth => self.current_accumilated_download,
+            ChargeMetric::UploadBandwidth => self.current_accumilated_upload,
+            ChargeMetric::DataStorage => self.current_storage,
+            ChargeMetric::Compute => self.current_accumilated_compute,
+            ChargeMetric::Fuel => self.current_accumilated_fuel,
+        }
+    }
+}
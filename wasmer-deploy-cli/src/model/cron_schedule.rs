@@ -0,0 +1,278 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Cron schedules are checked this far into the future before they
+/// are considered to never fire again (e.g. 30th of February)
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+/// Schedule expressed in the classic five field cron syntax
+/// (minute hour day-of-month month day-of-week) in UTC time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    /// Returns the next time (strictly after the supplied time) that
+    /// this schedule fires
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after
+            .with_second(0)?
+            .with_nanosecond(0)?
+            + Duration::minutes(1);
+        let end = start + Duration::days(MAX_LOOKAHEAD_DAYS);
+
+        let mut next = start;
+        while next < end {
+            if Self::is_set(self.months, next.month()) == false {
+                next = match next.month() {
+                    12 => Utc.ymd(next.year() + 1, 1, 1).and_hms(0, 0, 0),
+                    m => Utc.ymd(next.year(), m + 1, 1).and_hms(0, 0, 0),
+                };
+                continue;
+            }
+            if self.matches_day(&next) == false {
+                next = next.date().and_hms(0, 0, 0) + Duration::days(1);
+                continue;
+            }
+            if Self::is_set(self.hours, next.hour()) == false {
+                next = next.date().and_hms(next.hour(), 0, 0) + Duration::hours(1);
+                continue;
+            }
+            if Self::is_set(self.minutes, next.minute()) == false {
+                next = next + Duration::minutes(1);
+                continue;
+            }
+            return Some(next);
+        }
+        None
+    }
+
+    /// When both the day of the month and the day of the week are
+    /// restricted then a match on either of them is enough
+    fn matches_day(&self, when: &DateTime<Utc>) -> bool {
+        let dom = Self::is_set(self.days_of_month, when.day());
+        let dow = Self::is_set(self.days_of_week, when.weekday().num_days_from_sunday());
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => dow,
+            (false, true) => dom,
+            (false, false) => dom || dow,
+        }
+    }
+
+    fn is_set(mask: u64, val: u32) -> bool {
+        mask & (1u64 << val) != 0
+    }
+
+    fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+        let mut mask = 0u64;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step = u32::from_str(step)
+                        .map_err(|_| format!("invalid step ({})", part))?;
+                    if step == 0 {
+                        return Err(format!("invalid step ({})", part));
+                    }
+                    (range, step)
+                }
+                None => (part, 1),
+            };
+            let (from, to) = if range == "*" {
+                (min, max)
+            } else if let Some((from, to)) = range.split_once('-') {
+                let from = u32::from_str(from)
+                    .map_err(|_| format!("invalid range ({})", part))?;
+                let to = u32::from_str(to)
+                    .map_err(|_| format!("invalid range ({})", part))?;
+                (from, to)
+            } else {
+                let val = u32::from_str(range)
+                    .map_err(|_| format!("invalid value ({})", part))?;
+                match step {
+                    1 => (val, val),
+                    _ => (val, max),
+                }
+            };
+            if from < min || to > max || from > to {
+                return Err(format!("value out of range ({}) - must be between {} and {}", part, min, max));
+            }
+            let mut n = from;
+            while n <= to {
+                mask |= 1u64 << n;
+                n += step;
+            }
+        }
+        Ok(mask)
+    }
+}
+
+impl FromStr
+for CronSchedule
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expr = s.trim();
+        let expanded = match expr {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            a => a,
+        };
+
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(format!("cron schedules must have five fields (minute hour day-of-month month day-of-week) - found {}", fields.len()));
+        }
+
+        let mut days_of_week = Self::parse_field(fields[4], 0, 7)?;
+        if Self::is_set(days_of_week, 7) {
+            // Sunday can be written as either 0 or 7
+            days_of_week = (days_of_week & !(1u64 << 7)) | 1u64;
+        }
+
+        Ok(CronSchedule {
+            expr: expr.to_string(),
+            minutes: Self::parse_field(fields[0], 0, 59)?,
+            hours: Self::parse_field(fields[1], 0, 23)?,
+            days_of_month: Self::parse_field(fields[2], 1, 31)?,
+            months: Self::parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            any_day_of_month: fields[2] == "*",
+            any_day_of_week: fields[4] == "*",
+        })
+    }
+}
+
+impl fmt::Display
+for CronSchedule
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl Serialize
+for CronSchedule
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        serializer.serialize_str(self.expr.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de>
+for CronSchedule
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>
+    {
+        let expr = String::deserialize(deserializer)?;
+        CronSchedule::from_str(expr.as_str())
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.ymd(y, m, d).and_hms(h, min, 0)
+    }
+
+    fn next(expr: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        CronSchedule::from_str(expr).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn steps_and_ranges() {
+        assert_eq!(CronSchedule::parse_field("10-20/5", 0, 59).unwrap(), (1 << 10) | (1 << 15) | (1 << 20));
+        assert_eq!(CronSchedule::parse_field("5/20", 0, 59).unwrap(), (1 << 5) | (1 << 25) | (1 << 45));
+        assert_eq!(CronSchedule::parse_field("1,3-4", 0, 59).unwrap(), (1 << 1) | (1 << 3) | (1 << 4));
+
+        // Friday evening rolls over the weekend to Monday morning
+        let expr = "*/15 9-17 * * 1-5";
+        assert_eq!(next(expr, at(2024, 1, 5, 17, 20)), Some(at(2024, 1, 5, 17, 30)));
+        assert_eq!(next(expr, at(2024, 1, 5, 17, 50)), Some(at(2024, 1, 8, 9, 0)));
+    }
+
+    #[test]
+    fn sunday_as_seven() {
+        let seven = CronSchedule::from_str("0 0 * * 7").unwrap();
+        let zero = CronSchedule::from_str("0 0 * * 0").unwrap();
+        assert_eq!(seven.days_of_week, zero.days_of_week);
+        assert_eq!(seven.next_after(at(2024, 1, 1, 0, 0)), Some(at(2024, 1, 7, 0, 0)));
+
+        let weekend = CronSchedule::from_str("0 0 * * 6-7").unwrap();
+        assert_eq!(weekend.days_of_week, (1 << 0) | (1 << 6));
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // When both are restricted either one is enough (the 13th or any Friday)
+        let expr = "0 0 13 * 5";
+        assert_eq!(next(expr, at(2024, 1, 1, 0, 0)), Some(at(2024, 1, 5, 0, 0)));
+        assert_eq!(next(expr, at(2024, 1, 12, 0, 0)), Some(at(2024, 1, 13, 0, 0)));
+
+        // When only one is restricted then only it applies
+        assert_eq!(next("0 0 13 * *", at(2024, 1, 1, 0, 0)), Some(at(2024, 1, 13, 0, 0)));
+        assert_eq!(next("0 0 * * 5", at(2024, 1, 6, 0, 0)), Some(at(2024, 1, 12, 0, 0)));
+    }
+
+    #[test]
+    fn shorthands() {
+        let now = at(2024, 1, 1, 10, 30);
+        assert_eq!(next("@hourly", now), Some(at(2024, 1, 1, 11, 0)));
+        assert_eq!(next("@daily", now), Some(at(2024, 1, 2, 0, 0)));
+        assert_eq!(next("@midnight", now), Some(at(2024, 1, 2, 0, 0)));
+        assert_eq!(next("@weekly", now), Some(at(2024, 1, 7, 0, 0)));
+        assert_eq!(next("@monthly", now), Some(at(2024, 2, 1, 0, 0)));
+        assert_eq!(next("@yearly", now), Some(at(2025, 1, 1, 0, 0)));
+        assert_eq!(next("@annually", now), Some(at(2025, 1, 1, 0, 0)));
+
+        // The original expression is what gets displayed and stored
+        assert_eq!(CronSchedule::from_str("@hourly").unwrap().to_string(), "@hourly");
+    }
+
+    #[test]
+    fn next_is_strictly_after() {
+        assert_eq!(next("0 0 * * *", at(2024, 1, 1, 0, 0)), Some(at(2024, 1, 2, 0, 0)));
+        let now = Utc.ymd(2024, 1, 1).and_hms(9, 59, 30);
+        assert_eq!(next("0 10 * * *", now), Some(at(2024, 1, 1, 10, 0)));
+    }
+
+    #[test]
+    fn never_fires() {
+        assert_eq!(next("0 0 30 2 *", at(2024, 1, 1, 0, 0)), None);
+        assert_eq!(next("0 0 31 4,6,9,11 *", at(2024, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(CronSchedule::from_str("* * * *").is_err());
+        assert!(CronSchedule::from_str("60 * * * *").is_err());
+        assert!(CronSchedule::from_str("* 24 * * *").is_err());
+        assert!(CronSchedule::from_str("* * 0 * *").is_err());
+        assert!(CronSchedule::from_str("* * * 13 *").is_err());
+        assert!(CronSchedule::from_str("* * * * 8").is_err());
+        assert!(CronSchedule::from_str("*/0 * * * *").is_err());
+        assert!(CronSchedule::from_str("5-1 * * * *").is_err());
+        assert!(CronSchedule::from_str("a * * * *").is_err());
+    }
+}
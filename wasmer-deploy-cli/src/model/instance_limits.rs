@@ -0,0 +1,57 @@
+use serde::*;
+use std::fmt;
+
+/// Quotas that are enforced on the processes running within a
+/// particular service instance (limits that are not set are unbounded),
+/// these are configured by the operator of the instance servers
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct InstanceLimits {
+    /// Maximum amount of fuel (metered wasm instructions) that a single
+    /// process may consume before it is terminated
+    #[serde(default)]
+    pub max_fuel: Option<u64>,
+    /// Maximum number of memory pages (64KB each) that a process may use
+    #[serde(default)]
+    pub max_memory_pages: Option<u32>,
+    /// Maximum number of files that a process may have open at the
+    /// same time
+    #[serde(default)]
+    pub max_open_files: Option<u32>,
+    /// Maximum amount of time (in milliseconds) that a process or call
+    /// may run for before it is terminated
+    #[serde(default)]
+    pub max_wall_time_ms: Option<u64>,
+    /// Maximum number of calls that may be running on the instance at
+    /// the same time
+    #[serde(default)]
+    pub max_concurrent_calls: Option<u32>,
+    /// Maximum number of bytes (requests plus responses) that may be
+    /// transferred by the instance every minute
+    #[serde(default)]
+    pub max_bandwidth_per_minute: Option<u64>,
+}
+
+impl InstanceLimits {
+    pub fn is_unbounded(&self) -> bool {
+        self == &InstanceLimits::default()
+    }
+}
+
+impl fmt::Display
+for InstanceLimits
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn limit<T: fmt::Display>(f: &mut fmt::Formatter<'_>, name: &str, val: &Option<T>) -> fmt::Result {
+            match val {
+                Some(a) => writeln!(f, "{:<24}{}", name, a),
+                None => writeln!(f, "{:<24}unbounded", name),
+            }
+        }
+        limit(f, "fuel", &self.max_fuel)?;
+        limit(f, "memory-pages", &self.max_memory_pages)?;
+        limit(f, "open-files", &self.max_open_files)?;
+        limit(f, "wall-time-ms", &self.max_wall_time_ms)?;
+        limit(f, "concurrent-calls", &self.max_concurrent_calls)?;
+        limit(f, "bandwidth-per-minute", &self.max_bandwidth_per_minute)
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::DateTime;
+use chrono::Utc;
+use serde::*;
+use std::fmt;
+
+/// Secrets are values (such as API keys) that are passed to the processes
+/// of an instance as environment variables, they are encrypted so that only
+/// the owner of the instance and the instance itself can read them
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstanceSecret {
+    /// Name of the environment variable that holds the secret
+    pub name: String,
+    /// Value of the secret
+    pub value: String,
+    /// When the secret was last changed
+    pub updated: DateTime<Utc>,
+}
+
+impl InstanceSecret {
+    /// Secrets must be valid environment variable names
+    pub fn is_valid_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+            _ => return false,
+        }
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+}
+
+impl fmt::Debug
+for InstanceSecret
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instance-secret(name={}, updated={})", self.name, self.updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names() {
+        let cases: &[(&str, bool)] = &[
+            ("API_KEY", true),
+            ("_private", true),
+            ("key2", true),
+            ("a", true),
+            ("", false),
+            ("2KEY", false),
+            ("API-KEY", false),
+            ("API KEY", false),
+            ("KEY=1", false),
+            ("KÉY", false),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(InstanceSecret::is_valid_name(name), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn debug_hides_the_value() {
+        let secret = InstanceSecret {
+            name: "API_KEY".to_string(),
+            value: "hunter2".to_string(),
+            updated: Utc::now(),
+        };
+        let dbg = format!("{:?}", secret);
+        assert!(dbg.contains("API_KEY"));
+        assert!(dbg.contains("hunter2") == false);
+    }
+}
@@ -0,0 +1,115 @@
+use ate::prelude::DaoVec;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::*;
+use std::fmt;
+
+use super::CronSchedule;
+
+/// Maximum number of runs that are kept in the history of a trigger
+pub const MAX_TRIGGER_RUNS: usize = 50;
+
+/// Triggers invoke an exported binary of an instance without a client
+/// having to call it (i.e. on a schedule or when something happens)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InstanceTrigger {
+    /// Name of the trigger which is unique within the instance
+    pub name: String,
+    /// Name of the binary that will be invoked when the trigger fires
+    pub binary: String,
+    /// Arguments passed to the binary when its invoked
+    pub args: Vec<String>,
+    /// What causes this trigger to fire
+    pub kind: TriggerKind,
+    /// Disabled triggers remain attached to the instance but never fire
+    pub enabled: bool,
+    /// Number of times a failed invocation will be retried
+    pub max_retries: u32,
+    /// Amount of time to wait between each retry (doubled on every attempt
+    /// up to a maximum of one hour)
+    pub retry_delay_ms: u64,
+    /// Queue of events that will fire the trigger (for chain triggers)
+    pub events: DaoVec<TriggerEvent>,
+    /// History of the most recent invocations of this trigger
+    pub runs: DaoVec<TriggerRun>,
+}
+
+/// Determines what causes a trigger to fire
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum TriggerKind {
+    /// Fires on a cron-style schedule (a run may execute more than once when
+    /// several nodes host the instance so it should be idempotent)
+    Schedule(CronSchedule),
+    /// Fires exactly once for every event that is written to the chain of
+    /// the instance under this trigger (the event is passed on stdin)
+    ChainEvent,
+    /// Fires when an HTTP POST is made to the webhook URL of the trigger
+    /// with the token as its authorization (the body is passed on stdin and
+    /// the request is accepted before the binary runs)
+    Webhook {
+        token: String,
+    },
+}
+
+impl fmt::Display
+for TriggerKind
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerKind::Schedule(schedule) => write!(f, "schedule({})", schedule),
+            TriggerKind::ChainEvent => write!(f, "chain-event"),
+            TriggerKind::Webhook { .. } => write!(f, "webhook"),
+        }
+    }
+}
+
+/// Event written to the chain of an instance that will fire a trigger
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TriggerEvent {
+    /// When the event was raised
+    pub when: DateTime<Utc>,
+    /// Data that will be passed to the binary on stdin
+    pub data: Vec<u8>,
+}
+
+/// Record of a particular invocation of a trigger
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TriggerRun {
+    /// When the trigger fired
+    pub when: DateTime<Utc>,
+    /// What caused the trigger to fire
+    pub cause: String,
+    /// Number of attempts that were made to invoke the binary
+    pub attempts: u32,
+    /// Total amount of time the run took including all its retries
+    pub duration_ms: u64,
+    /// Result of the run
+    pub outcome: TriggerOutcome,
+}
+
+/// Result of a particular run of a trigger
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum TriggerOutcome {
+    /// The run is still in progress
+    Running,
+    /// The binary exited with a zero exit code
+    Succeeded,
+    /// The binary failed on every attempt
+    Failed {
+        exit_code: Option<u32>,
+        error: String,
+    },
+}
+
+impl fmt::Display
+for TriggerOutcome
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerOutcome::Running => write!(f, "running"),
+            TriggerOutcome::Succeeded => write!(f, "succeeded"),
+            TriggerOutcome::Failed { exit_code: Some(code), error } => write!(f, "failed (exit_code={}) - {}", code, error),
+            TriggerOutcome::Failed { exit_code: None, error } => write!(f, "failed - {}", error),
+        }
+    }
+}
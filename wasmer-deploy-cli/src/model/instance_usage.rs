@@ -0,0 +1,27 @@
+use ate::prelude::*;
+use serde::*;
+
+use super::{ContractMetrics, InstanceLimits};
+
+/// Usage of a service instance along with the limits that were enforced
+/// on it, this is recorded by the instance servers on a chain that is
+/// owned by their operator (the owner of the instance may read it but
+/// only the operator may change it)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InstanceUsage {
+    /// Limits that the operator currently enforces on the instance
+    pub limits: InstanceLimits,
+    /// Resources that the instance has consumed
+    pub metrics: ContractMetrics,
+}
+
+/// Chain that the instance servers of a particular instance authority
+/// record the usage of all their instances on
+pub fn instance_usage_chain(instance_authority: &str) -> ChainKey {
+    ChainKey::from(format!("{}/usage", instance_authority))
+}
+
+/// Key of the usage record of a particular instance (by its chain)
+pub fn instance_usage_key(instance_chain: &ChainKey) -> PrimaryKey {
+    PrimaryKey::from(instance_chain.hash())
+}
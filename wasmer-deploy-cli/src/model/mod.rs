@@ -28,6 +28,11 @@ mod wallet_instance;
 mod instance_command;
 mod instance_hello;
 mod instance_export;
+mod instance_limits;
+mod instance_usage;
+mod instance_trigger;
+mod instance_secret;
+mod cron_schedule;
 mod instance_subnet;
 mod mesh_node;
 
@@ -61,6 +66,11 @@ pub use wallet_instance::*;
 pub use instance_command::*;
 pub use instance_hello::*;
 pub use instance_export::*;
+pub use instance_limits::*;
+pub use instance_usage::*;
+pub use instance_trigger::*;
+pub use instance_secret::*;
+pub use cron_schedule::*;
 pub use instance_subnet::*;
 pub use mesh_node::*;
 
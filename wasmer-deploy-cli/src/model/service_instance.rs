@@ -1,7 +1,7 @@
 use ate::{prelude::DaoVec};
 use serde::*;
 
-use super::{InstanceExport, InstanceSubnet, MeshNode};
+use super::{InstanceExport, InstanceSecret, InstanceSubnet, InstanceTrigger, MeshNode};
 
 /// Running instance of a particular web assembly application
 /// within the hosting environment
@@ -20,6 +20,14 @@ pub struct ServiceInstance {
     pub exports: DaoVec<InstanceExport>,
     /// List of active nodes currently partipating in the mesh
     pub mesh_nodes: DaoVec<MeshNode>,
+    /// Triggers that invoke binaries of this instance on a schedule
+    /// or when particular events occur
+    #[serde(default)]
+    pub triggers: DaoVec<InstanceTrigger>,
+    /// Encrypted values that are passed to the processes of this
+    /// instance as environment variables
+    #[serde(default)]
+    pub secrets: DaoVec<InstanceSecret>,
 }
 
 impl ServiceInstance
@@ -119,6 +119,15 @@ pub enum OptsInstanceAction {
     /// Resets an instance
     #[clap()]
     Reset(OptsInstanceReset),
+    /// Shows the resource limits and usage of an instance
+    #[clap()]
+    Limits(OptsInstanceLimits),
+    /// List, add or remove triggers that invoke binaries in the instance
+    #[clap()]
+    Trigger(OptsInstanceTrigger),
+    /// List, set or remove secrets that are passed to the processes in the instance
+    #[clap()]
+    Secret(OptsInstanceSecret),
 }
 
 impl OptsInstanceAction
@@ -153,6 +162,9 @@ impl OptsInstanceAction
             OptsInstanceAction::Cidr(opts) => Some(opts.name.clone()),
             OptsInstanceAction::Peering(opts) => Some(opts.name.clone()),
             OptsInstanceAction::Reset(opts) => Some(opts.name.clone()),
+            OptsInstanceAction::Limits(opts) => Some(opts.name.clone()),
+            OptsInstanceAction::Trigger(opts) => Some(opts.name.clone()),
+            OptsInstanceAction::Secret(opts) => Some(opts.name.clone()),
         }
     }
 }
@@ -363,6 +375,164 @@ pub struct OptsPeeringRemove {
     pub peer: String
 }
 
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsInstanceLimits {
+    /// Name of the instance
+    #[clap(index = 1)]
+    pub name: String,
+    /// Action to perform on the limits
+    #[clap(subcommand)]
+    pub action: OptsLimitsAction,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub enum OptsLimitsAction {
+    /// Shows the limits of this instance and the usage it has accumilated
+    /// (the limits themselves are set by the operator of the instance servers)
+    #[clap()]
+    Show,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsInstanceTrigger {
+    /// Name of the instance
+    #[clap(index = 1)]
+    pub name: String,
+    /// Action to perform on the triggers
+    #[clap(subcommand)]
+    pub action: OptsTriggerAction,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub enum OptsTriggerAction {
+    /// Lists all the triggers for this instance
+    #[clap()]
+    List,
+    /// Adds a trigger that invokes a binary on a cron schedule (in UTC)
+    #[clap()]
+    AddSchedule(OptsTriggerAddSchedule),
+    /// Adds a trigger that invokes a binary for every event written to the chain
+    #[clap()]
+    AddChain(OptsTriggerAdd),
+    /// Adds a trigger that invokes a binary when its webhook is called
+    #[clap()]
+    AddWebhook(OptsTriggerAdd),
+    /// Removes a trigger from this instance
+    #[clap()]
+    Remove(OptsTriggerName),
+    /// Enables a trigger that was previously disabled
+    #[clap()]
+    Enable(OptsTriggerName),
+    /// Disables a trigger so that it no longer fires
+    #[clap()]
+    Disable(OptsTriggerName),
+    /// Writes an event to the chain that fires a chain trigger (the data is read from stdin)
+    #[clap()]
+    Fire(OptsTriggerName),
+    /// Shows the most recent runs of a trigger
+    #[clap()]
+    History(OptsTriggerName),
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsTriggerAddSchedule {
+    /// Name of the new trigger
+    #[clap(index = 1)]
+    pub trigger: String,
+    /// Cron schedule (minute hour day-of-month month day-of-week) or a macro such as @hourly
+    #[clap(index = 2)]
+    pub schedule: String,
+    /// Name of the binary that will be invoked
+    #[clap(index = 3)]
+    pub binary: String,
+    /// Arguments passed to the binary
+    #[clap(index = 4)]
+    pub args: Vec<String>,
+    /// Number of times a failed invocation will be retried
+    #[clap(long, default_value = "3")]
+    pub retries: u32,
+    /// Number of milliseconds to wait before the first retry
+    #[clap(long, default_value = "1000")]
+    pub retry_delay_ms: u64,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsTriggerAdd {
+    /// Name of the new trigger
+    #[clap(index = 1)]
+    pub trigger: String,
+    /// Name of the binary that will be invoked
+    #[clap(index = 2)]
+    pub binary: String,
+    /// Arguments passed to the binary
+    #[clap(index = 3)]
+    pub args: Vec<String>,
+    /// Number of times a failed invocation will be retried
+    #[clap(long, default_value = "3")]
+    pub retries: u32,
+    /// Number of milliseconds to wait before the first retry
+    #[clap(long, default_value = "1000")]
+    pub retry_delay_ms: u64,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsTriggerName {
+    /// Name of the trigger
+    #[clap(index = 1)]
+    pub trigger: String,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsInstanceSecret {
+    /// Name of the instance
+    #[clap(index = 1)]
+    pub name: String,
+    /// Action to perform on the secrets
+    #[clap(subcommand)]
+    pub action: OptsSecretAction,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub enum OptsSecretAction {
+    /// Lists the names of all the secrets for this instance
+    #[clap()]
+    List,
+    /// Sets the value of a secret (the value is read from stdin if its not supplied)
+    #[clap()]
+    Set(OptsSecretSet),
+    /// Removes a secret from this instance
+    #[clap()]
+    Rm(OptsSecretRemove),
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsSecretSet {
+    /// Name of the environment variable that will hold the secret
+    #[clap(index = 1)]
+    pub key: String,
+    /// Value of the secret (supplying it here will leave it in your shell history)
+    #[clap(index = 2)]
+    pub value: Option<String>,
+}
+
+#[derive(Parser, Clone)]
+#[clap()]
+pub struct OptsSecretRemove {
+    /// Name of the secret to be removed
+    #[clap(index = 1)]
+    pub key: String,
+}
+
 impl OptsPurpose<OptsInstanceAction> for OptsInstanceFor {
     fn purpose(&self) -> Purpose<OptsInstanceAction> {
         match self {
@@ -0,0 +1,89 @@
+use ate::prelude::*;
+use ate_files::error::Result;
+use ate_files::prelude::*;
+use error_chain::bail;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+
+use crate::helper::open_accessor;
+use crate::opts::*;
+
+pub async fn main_export(
+    opts: OptsExport,
+    conf: ConfAte,
+    group: Option<String>,
+    session: AteSessionType,
+) -> Result<()> {
+    let (_registry, accessor) = open_accessor(
+        &opts.remote,
+        opts.remote_name.as_str(),
+        conf,
+        group,
+        session,
+        RecoveryMode::ReadOnlySync,
+    )
+    .await?;
+    let req = accessor.session_context();
+    accessor.init(&req).await?;
+
+    let archive_path = shellexpand::tilde(&opts.archive_path).to_string();
+    let file = BufWriter::new(File::create(archive_path.as_str())?);
+    let stats = accessor
+        .export_tar(&req, opts.remote_path.as_str(), file)
+        .await?;
+
+    print_stats("Exported", &stats);
+    Ok(())
+}
+
+pub async fn main_import(
+    opts: OptsImport,
+    conf: ConfAte,
+    group: Option<String>,
+    session: AteSessionType,
+) -> Result<()> {
+    let archive_path = shellexpand::tilde(&opts.archive_path).to_string();
+    let format = match opts
+        .format
+        .or_else(|| ArchiveFormat::from_path(archive_path.as_str()))
+    {
+        Some(a) => a,
+        None => {
+            eprintln!("Unable to determine the archive format from the file name, please supply the --format argument");
+            bail!(FileSystemErrorKind::InvalidArguments);
+        }
+    };
+    let file = BufReader::new(File::open(archive_path.as_str())?);
+
+    let (_registry, accessor) = open_accessor(
+        &opts.remote,
+        opts.remote_name.as_str(),
+        conf,
+        group,
+        session,
+        RecoveryMode::Sync,
+    )
+    .await?;
+    let req = accessor.session_context();
+    accessor.init(&req).await?;
+
+    let path = opts.remote_path.as_str();
+    let stats = match format {
+        ArchiveFormat::Tar => accessor.import_tar(&req, path, file).await?,
+        ArchiveFormat::Zip => accessor.import_zip(&req, path, file).await?,
+    };
+    accessor.sync_all().await?;
+
+    print_stats("Imported", &stats);
+    Ok(())
+}
+
+fn print_stats(action: &str, stats: &ArchiveStats) {
+    println!(
+        "{} {} files ({} bytes), {} directories and {} links",
+        action, stats.files, stats.bytes, stats.dirs, stats.links
+    );
+}
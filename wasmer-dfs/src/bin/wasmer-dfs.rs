@@ -2,7 +2,10 @@ use ate::prelude::*;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, instrument, span, trace, warn, Level};
 
+use wasmer_dfs::main_export;
+use wasmer_dfs::main_import;
 use wasmer_dfs::main_mount;
+use wasmer_dfs::main_sync;
 use wasmer_dfs::opts::*;
 
 use wasmer_auth::cmd::*;
@@ -13,6 +16,38 @@ use wasmer_deploy_cli::cmd::{
 };
 
 use clap::Parser;
+use url::Url;
+
+/// Loads the session used to access a remotely hosted file system, including any
+/// additional permissions of the group that owns it
+async fn remote_session(
+    remote_name: &str,
+    token: Option<String>,
+    token_path: Option<String>,
+    auth: Url,
+) -> Result<(Option<String>, AteSessionType), Box<dyn std::error::Error>> {
+    // Derive the group from the remote name
+    let group = remote_name
+        .split_once("/")
+        .map(|(group_str, _)| group_str.to_string());
+
+    // Load the session via the token or the authentication server
+    let session_user = main_session_user(token, token_path, Some(auth.clone())).await?;
+
+    // Attempt to grab additional permissions for the group (if it has any)
+    let session: AteSessionType = if group.is_some() {
+        match main_gather(group.clone(), session_user.clone().into(), auth, "Group").await {
+            Ok(a) => a.into(),
+            Err(err) => {
+                debug!("Group authentication failed: {} - falling back to user level authorization", err);
+                session_user.into()
+            }
+        }
+    } else {
+        session_user.into()
+    };
+    Ok((group, session))
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -145,6 +180,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Mount the file system
             main_mount(mount, conf, group, session, opts.no_auth).await?;
         }
+        SubCommand::Sync(sync) => {
+            let (group, session) =
+                remote_session(&sync.remote_name, opts.token, token_path, opts.auth).await?;
+            main_sync(sync, conf, group, session).await?;
+        }
+        SubCommand::Export(export) => {
+            let (group, session) =
+                remote_session(&export.remote_name, opts.token, token_path, opts.auth).await?;
+            main_export(export, conf, group, session).await?;
+        }
+        SubCommand::Import(import) => {
+            let (group, session) =
+                remote_session(&import.remote_name, opts.token, token_path, opts.auth).await?;
+            main_import(import, conf, group, session).await?;
+        }
     }
 
     info!("wasmer-dfs::shutdown");
@@ -9,6 +9,9 @@ use tokio::select;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, instrument, span, trace, warn, Level};
 
+use ate_files::prelude::*;
+use url::Url;
+
 use crate::fs::AteFS;
 use crate::opts::*;
 use crate::umount;
@@ -249,3 +252,60 @@ pub async fn main_mount(
         }
     }
 }
+
+/// Opens a remote file system and returns an accessor that can manipulate it
+/// directly (i.e. without mounting it through FUSE). The registry is returned
+/// alongside the accessor as it must remain alive while the chain is in use.
+pub async fn open_accessor(
+    remote: &Url,
+    remote_name: &str,
+    conf: ConfAte,
+    group: Option<String>,
+    session: AteSessionType,
+    recovery_mode: RecoveryMode,
+) -> Result<(Arc<Registry>, FileAccessor), AteError> {
+    let mut conf = conf.clone();
+    conf.recovery_mode = recovery_mode;
+    info!("remote: {}", remote_name);
+
+    // Create a progress bar loader
+    let mut progress_local = LoadProgress::new(std::io::stdout());
+    let mut progress_remote = LoadProgress::new(std::io::stdout());
+    progress_local.units = pbr::Units::Bytes;
+    progress_local.msg_done = "Downloading latest events from server...".to_string();
+    progress_remote.msg_done = "Loaded the remote chain-of-trust.".to_string();
+    print!("Loading the chain-of-trust...");
+
+    let registry = Arc::new(Registry::new(&conf).await.temporal(true));
+    let chain = match registry
+        .open_ext(
+            remote,
+            &ChainKey::from(remote_name),
+            false,
+            progress_local,
+            progress_remote,
+        )
+        .await
+    {
+        Ok(a) => a.as_arc(),
+        Err(ChainCreationError(
+            ChainCreationErrorKind::ServerRejected(FatalTerminate::Denied { reason }),
+            _,
+        )) => {
+            println!("Access to this file system was denied by the server");
+            println!("---");
+            println!("{}", reason);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            bail!(err);
+        }
+    };
+
+    let scope = match recovery_mode.is_sync() {
+        true => TransactionScope::Full,
+        false => TransactionScope::Local,
+    };
+    let accessor = FileAccessor::new(chain, group, session, scope, scope, false, false).await;
+    Ok((registry, accessor))
+}
@@ -1,8 +1,13 @@
+pub mod archive;
 pub mod error;
 pub mod fs;
 pub mod fuse;
 pub mod helper;
 pub mod opts;
+pub mod sync;
 pub mod umount;
 
+pub use archive::main_export;
+pub use archive::main_import;
 pub use helper::main_mount;
+pub use sync::main_sync;
@@ -101,6 +101,19 @@ pub enum SubCommand {
     /// to the service which will consume funds from the wallet.
     #[clap()]
     Mount(OptsMount),
+    /// Synchronizes a local directory with a remote file system (e.g. ws://wasmer.sh/db)
+    /// without needing to mount it. Only the pages of files that have changed are
+    /// transferred, similar to how rsync operates.
+    #[clap()]
+    Sync(OptsSync),
+    /// Exports a remote file system (or part of it) to a tar archive without needing
+    /// to mount it.
+    #[clap()]
+    Export(OptsExport),
+    /// Imports the contents of a tar or zip archive into a remote file system without
+    /// needing to mount it.
+    #[clap()]
+    Import(OptsImport),
 }
 
 /// Mounts a particular directory as an ATE file system
@@ -179,7 +192,7 @@ pub struct OptsMount {
     /// Forces the compaction of the local redo-log before it streams in the latest values
     #[clap(long)]
     pub compact_now: bool,
-    /// Mode that the compaction will run under (valid modes are 'never', 'modified', 'timer', 'factor', 'size', 'factor-or-timer', 'size-or-timer')
+    /// Mode that the compaction will run under (valid modes are 'never', 'modified', 'timer', 'factor', 'size', 'factor-or-timer', 'size-or-timer') - snapshots can only be taken in the 'never' mode as they are rebuilt from the history of the log
     #[clap(long, default_value = "factor-or-timer")]
     pub compact_mode: CompactMode,
     /// Time in seconds between compactions of the log file (default: 1 hour) - this argument is ignored if you select a compact_mode that has no timer
@@ -192,3 +205,135 @@ pub struct OptsMount {
     #[clap(long, default_value = "104857600")]
     pub compact_threshold_size: u64,
 }
+
+/// Direction that the synchronization will copy data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncDirection {
+    /// Copies the local directory up to the remote file system
+    Push,
+    /// Copies the remote file system down to the local directory
+    Pull,
+}
+
+impl std::str::FromStr for SyncDirection {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "push" => Ok(SyncDirection::Push),
+            "pull" => Ok(SyncDirection::Pull),
+            _ => Err("valid values are 'push' and 'pull'"),
+        }
+    }
+}
+
+/// Synchronizes a local directory with a remote ATE file system
+#[derive(Parser)]
+pub struct OptsSync {
+    /// Direction of the synchronization ('push' uploads the local directory while 'pull'
+    /// downloads the remote file system)
+    #[clap(index = 1)]
+    pub direction: SyncDirection,
+    /// Path to the local directory that will be synchronized
+    #[clap(index = 2)]
+    pub local_path: String,
+    /// Name of the remote file-system to be synchronized (e.g. myfs)
+    #[clap(index = 3)]
+    pub remote_name: String,
+    /// Path within the remote file-system that will be synchronized
+    #[clap(long, default_value = "/")]
+    pub remote_path: String,
+    /// URL where the data is remotely stored on a distributed commit log.
+    #[clap(short, long, default_value = "ws://wasmer.sh/db")]
+    pub remote: Url,
+    /// Files and directories that exist on the destination but not on the source
+    /// will be deleted
+    #[clap(long)]
+    pub delete: bool,
+    /// Displays what would be transferred without actually changing anything
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+    /// Always compares the contents of files page-by-page rather than skipping files
+    /// whose size and modification time match
+    #[clap(short, long)]
+    pub checksum: bool,
+    /// Displays a progress bar rather than listing every file that is transferred
+    #[clap(short, long)]
+    pub progress: bool,
+    /// Determines how the file-system will react while it is nominal and when it is
+    /// recovering from a communication failure (valid options are 'async', 'readonly-async',
+    /// 'readonly-sync' or 'sync')
+    #[clap(long, default_value = "sync")]
+    pub recovery_mode: RecoveryMode,
+}
+
+/// Format of an archive that is imported into a file system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Determines the format from the file extension of the archive
+    pub fn from_path(path: &str) -> Option<ArchiveFormat> {
+        let path = path.to_lowercase();
+        if path.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if path.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err("valid values are 'tar' and 'zip'"),
+        }
+    }
+}
+
+/// Exports a remote ATE file system to a tar archive
+#[derive(Parser)]
+pub struct OptsExport {
+    /// Name of the remote file-system to be exported (e.g. myfs)
+    #[clap(index = 1)]
+    pub remote_name: String,
+    /// Path to the tar archive that will be written
+    #[clap(index = 2)]
+    pub archive_path: String,
+    /// Path within the remote file-system that will be exported
+    #[clap(long, default_value = "/")]
+    pub remote_path: String,
+    /// URL where the data is remotely stored on a distributed commit log.
+    #[clap(short, long, default_value = "ws://wasmer.sh/db")]
+    pub remote: Url,
+}
+
+/// Imports a tar or zip archive into a remote ATE file system
+#[derive(Parser)]
+pub struct OptsImport {
+    /// Name of the remote file-system that the archive will be imported into (e.g. myfs)
+    #[clap(index = 1)]
+    pub remote_name: String,
+    /// Path to the tar or zip archive that will be read
+    #[clap(index = 2)]
+    pub archive_path: String,
+    /// Path within the remote file-system that the archive will be extracted to
+    #[clap(long, default_value = "/")]
+    pub remote_path: String,
+    /// URL where the data is remotely stored on a distributed commit log.
+    #[clap(short, long, default_value = "ws://wasmer.sh/db")]
+    pub remote: Url,
+    /// Format of the archive ('tar' or 'zip'), if not specified then it will be
+    /// determined from the file extension
+    #[clap(short, long)]
+    pub format: Option<ArchiveFormat>,
+}
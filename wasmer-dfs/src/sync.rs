@@ -0,0 +1,911 @@
+use ate::prelude::*;
+use ate_files::codes::*;
+use ate_files::error::Result;
+use ate_files::model::PAGE_SIZE;
+use ate_files::prelude::*;
+use error_chain::bail;
+use pbr::ProgressBar;
+use pbr::Units;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+
+use crate::helper::open_accessor;
+use crate::opts::*;
+
+/// Extended attribute that holds the modification time of the local file that
+/// was last pushed, this allows unchanged files to be skipped without reading them
+pub const SYNC_MTIME_XATTR: &'static str = "user.wasmer.sync.mtime";
+
+#[derive(Debug, Clone)]
+struct SyncEntry {
+    kind: FileKind,
+    size: u64,
+    mtime: u64,
+    mode: u32,
+    link: Option<String>,
+    ino: u64,
+}
+
+#[derive(Debug, Default)]
+struct SyncStats {
+    files: u64,
+    dirs: u64,
+    links: u64,
+    deleted: u64,
+    skipped: u64,
+    pages: u64,
+    bytes: u64,
+}
+
+struct Synchronizer {
+    accessor: FileAccessor,
+    req: RequestContext,
+    local_root: PathBuf,
+    remote_path: String,
+    delete: bool,
+    dry_run: bool,
+    checksum: bool,
+    stats: SyncStats,
+    bar: Option<ProgressBar<std::io::Stdout>>,
+}
+
+pub async fn main_sync(
+    opts: OptsSync,
+    conf: ConfAte,
+    group: Option<String>,
+    session: AteSessionType,
+) -> Result<()> {
+    let (_registry, accessor) = open_accessor(
+        &opts.remote,
+        opts.remote_name.as_str(),
+        conf,
+        group,
+        session,
+        opts.recovery_mode,
+    )
+    .await?;
+
+    let req = accessor.session_context();
+    accessor.init(&req).await?;
+
+    let mut sync = Synchronizer {
+        accessor,
+        req,
+        local_root: PathBuf::from(shellexpand::tilde(&opts.local_path).to_string()),
+        remote_path: opts.remote_path.clone(),
+        delete: opts.delete,
+        dry_run: opts.dry_run,
+        checksum: opts.checksum,
+        stats: SyncStats::default(),
+        bar: None,
+    };
+    if opts.progress {
+        let mut bar = ProgressBar::on(std::io::stdout(), 0);
+        bar.set_units(Units::Bytes);
+        bar.format("╢█▌░╟");
+        sync.bar = Some(bar);
+    }
+
+    match opts.direction {
+        SyncDirection::Push => sync.push().await?,
+        SyncDirection::Pull => sync.pull().await?,
+    }
+
+    if let Some(mut bar) = sync.bar.take() {
+        bar.finish();
+    }
+    if sync.dry_run == false {
+        sync.accessor.sync_all().await?;
+    }
+
+    let stats = &sync.stats;
+    println!(
+        "{}{} files, {} directories and {} links updated, {} deleted, {} unchanged",
+        if sync.dry_run { "(dry-run) " } else { "" },
+        stats.files,
+        stats.dirs,
+        stats.links,
+        stats.deleted,
+        stats.skipped
+    );
+    println!(
+        "{} pages ({} bytes) transferred",
+        stats.pages, stats.bytes
+    );
+    Ok(())
+}
+
+impl Synchronizer {
+    /// Uploads the local directory to the remote file system
+    async fn push(&mut self) -> Result<()> {
+        let local_root = self.local_root.clone();
+        let local = local_tree(local_root.as_path())?;
+
+        let root = self.remote_root(true).await?;
+        let mut remote = match root {
+            Some(ino) => self.remote_tree(ino).await?,
+            None => BTreeMap::new(),
+        };
+        self.start_progress(&local);
+
+        // Anything that no longer matches the source is removed first (children
+        // before their parents so that nothing is left orphaned)
+        for path in self.removals(&local, &remote) {
+            if let Some(entry) = remote.remove(&path) {
+                self.report("deleting", path.as_str());
+                self.stats.deleted += 1;
+                if self.dry_run == false {
+                    let parent = self.remote_parent(&path, root, &remote);
+                    let name = file_name(&path);
+                    match entry.kind {
+                        FileKind::Directory => self.accessor.rmdir(&self.req, parent, name).await?,
+                        _ => self.accessor.unlink(&self.req, parent, name).await?,
+                    }
+                }
+                let prefix = format!("{}/", path);
+                remote.retain(|k, _| k.starts_with(prefix.as_str()) == false);
+            }
+        }
+
+        let mut dirs = BTreeMap::new();
+        dirs.insert(String::new(), root.unwrap_or_default());
+
+        for (path, entry) in local.iter() {
+            let parent = dirs
+                .get(parent_path(path))
+                .cloned()
+                .unwrap_or_default();
+            let name = file_name(path);
+            let existing = remote.get(path);
+
+            match entry.kind {
+                FileKind::Directory => {
+                    let ino = match existing {
+                        Some(existing) => {
+                            self.push_mode(entry, existing).await?;
+                            existing.ino
+                        }
+                        None => {
+                            self.report("creating", path.as_str());
+                            self.stats.dirs += 1;
+                            match self.dry_run {
+                                true => 0u64,
+                                false => self.accessor.mkdir(&self.req, parent, name, entry.mode).await?.ino,
+                            }
+                        }
+                    };
+                    dirs.insert(path.clone(), ino);
+                }
+                FileKind::SymLink => {
+                    if existing.map(|a| a.link == entry.link).unwrap_or(false) {
+                        self.stats.skipped += 1;
+                        continue;
+                    }
+                    self.report("linking", path.as_str());
+                    self.stats.links += 1;
+                    if self.dry_run == false {
+                        if existing.is_some() {
+                            self.accessor.unlink(&self.req, parent, name).await?;
+                        }
+                        let link = entry.link.clone().unwrap_or_default();
+                        self.accessor.symlink(&self.req, parent, name, link.as_str()).await?;
+                    }
+                }
+                _ => {
+                    self.push_file(path.as_str(), parent, entry, existing).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn push_file(
+        &mut self,
+        path: &str,
+        parent: u64,
+        local: &SyncEntry,
+        existing: Option<&SyncEntry>,
+    ) -> Result<()> {
+        // Quick check on the size and modification time
+        if let Some(remote) = existing {
+            if self.checksum == false && remote.size == local.size && remote.mtime == local.mtime
+            {
+                self.stats.skipped += 1;
+                self.advance(local.size);
+                return Ok(());
+            }
+        }
+
+        let flags = match self.dry_run {
+            true => O_RDONLY,
+            false => O_RDWR,
+        } as u32;
+        let ino = match existing {
+            Some(remote) => remote.ino,
+            None if self.dry_run => 0u64,
+            None => {
+                self.accessor
+                    .mknod(&self.req, parent, file_name(path), local.mode)
+                    .await?
+                    .ino
+            }
+        };
+        let fh = match ino {
+            0 => None,
+            ino => Some(self.accessor.open(&self.req, ino, flags).await?.fh),
+        };
+
+        // Compare the file page by page and only write the pages that differ
+        let mut file = File::open(self.local_root.join(path))?;
+        let mut buf = vec![0u8; PAGE_SIZE];
+        let mut changed = 0u64;
+        let mut offset = 0u64;
+        loop {
+            let len = read_page(&mut file, &mut buf[..])?;
+            if len <= 0 {
+                break;
+            }
+            let data = &buf[..len];
+
+            let same = match fh {
+                Some(fh) => {
+                    let remote = self
+                        .accessor
+                        .read(&self.req, ino, fh, offset, len as u32)
+                        .await?;
+                    &remote[..] == data
+                }
+                None => false,
+            };
+            if same == false {
+                changed += 1;
+                self.stats.pages += 1;
+                self.stats.bytes += len as u64;
+                if let (Some(fh), false) = (fh, self.dry_run) {
+                    self.accessor
+                        .write(&self.req, ino, fh, offset, data, 0)
+                        .await?;
+                }
+            }
+
+            offset += len as u64;
+            self.advance(len as u64);
+        }
+
+        // Truncate the remote file if its now shorter
+        let truncate = existing.map(|a| a.size > offset).unwrap_or(false);
+        if let Some(fh) = fh {
+            if truncate && self.dry_run == false {
+                self.accessor
+                    .fallocate(&self.req, ino, fh, 0, offset, 0)
+                    .await?;
+            }
+            self.accessor
+                .release(&self.req, ino, fh, 0, 0, false)
+                .await?;
+        }
+
+        if changed > 0 || truncate || existing.is_none() {
+            self.report("sending", path);
+            self.stats.files += 1;
+        } else {
+            self.stats.skipped += 1;
+        }
+
+        if self.dry_run == false {
+            let mtime = local.mtime.to_string();
+            self.accessor
+                .setxattr(&self.req, ino, SYNC_MTIME_XATTR, mtime.as_str())
+                .await?;
+            if let Some(existing) = existing {
+                self.push_mode(local, existing).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn push_mode(&mut self, local: &SyncEntry, remote: &SyncEntry) -> Result<()> {
+        if local.mode != remote.mode && self.dry_run == false {
+            let mut attr = SetAttr::default();
+            attr.mode = Some(local.mode);
+            self.accessor
+                .setattr(&self.req, remote.ino, None, attr)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Downloads the remote file system into the local directory
+    async fn pull(&mut self) -> Result<()> {
+        let root = match self.remote_root(false).await? {
+            Some(a) => a,
+            None => {
+                bail!(FileSystemErrorKind::NoEntry);
+            }
+        };
+        let remote = self.remote_tree(root).await?;
+
+        let local_root = self.local_root.clone();
+        let mut local = match local_root.exists() {
+            true => local_tree(local_root.as_path())?,
+            false => BTreeMap::new(),
+        };
+        if self.dry_run == false {
+            std::fs::create_dir_all(local_root.as_path())?;
+        }
+        self.start_progress(&remote);
+
+        for path in self.removals(&remote, &local) {
+            if let Some(entry) = local.remove(&path) {
+                self.report("deleting", path.as_str());
+                self.stats.deleted += 1;
+                if self.dry_run == false {
+                    let local_path = local_root.join(&path);
+                    match entry.kind {
+                        FileKind::Directory => std::fs::remove_dir_all(local_path)?,
+                        _ => std::fs::remove_file(local_path)?,
+                    }
+                }
+                let prefix = format!("{}/", path);
+                local.retain(|k, _| k.starts_with(prefix.as_str()) == false);
+            }
+        }
+
+        for (path, entry) in remote.iter() {
+            let local_path = local_root.join(path);
+            let existing = local.get(path);
+
+            match entry.kind {
+                FileKind::Directory => {
+                    if existing.is_none() {
+                        self.report("creating", path.as_str());
+                        self.stats.dirs += 1;
+                        if self.dry_run == false {
+                            std::fs::create_dir(&local_path)?;
+                        }
+                    }
+                    self.pull_mode(local_path.as_path(), entry, existing.map(|a| a.mode))?;
+                }
+                FileKind::SymLink => {
+                    if existing.map(|a| a.link == entry.link).unwrap_or(false) {
+                        self.stats.skipped += 1;
+                        continue;
+                    }
+                    self.report("linking", path.as_str());
+                    self.stats.links += 1;
+                    if self.dry_run == false {
+                        if existing.is_some() {
+                            std::fs::remove_file(&local_path)?;
+                        }
+                        let link = entry.link.clone().unwrap_or_default();
+                        std::os::unix::fs::symlink(link, &local_path)?;
+                    }
+                }
+                _ => {
+                    self.pull_file(path.as_str(), entry, existing).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn pull_file(
+        &mut self,
+        path: &str,
+        remote: &SyncEntry,
+        existing: Option<&SyncEntry>,
+    ) -> Result<()> {
+        // Quick check on the size and modification time
+        if let Some(local) = existing {
+            if self.checksum == false && remote.size == local.size && remote.mtime == local.mtime
+            {
+                self.stats.skipped += 1;
+                self.advance(remote.size);
+                return Ok(());
+            }
+        }
+
+        // Read-only files must be made writable while they are updated (their
+        // mode is always put back afterwards)
+        let local_path = self.local_root.join(path);
+        let mut local_mode = existing.map(|a| a.mode);
+        if let (Some(mode), false) = (local_mode, self.dry_run) {
+            if mode & 0o200 == 0 {
+                std::fs::set_permissions(&local_path, std::fs::Permissions::from_mode(mode | 0o200))?;
+                local_mode = None;
+            }
+        }
+        let mut file = match self.dry_run {
+            true => File::open(&local_path).ok(),
+            false => Some(
+                std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .open(&local_path)?,
+            ),
+        };
+
+        // Compare the file page by page and only write the pages that differ
+        let open = self
+            .accessor
+            .open(&self.req, remote.ino, O_RDONLY as u32)
+            .await?;
+        let mut buf = vec![0u8; PAGE_SIZE];
+        let mut changed = 0u64;
+        let mut offset = 0u64;
+        while offset < remote.size {
+            let data = self
+                .accessor
+                .read(&self.req, remote.ino, open.fh, offset, PAGE_SIZE as u32)
+                .await?;
+            if data.len() <= 0 {
+                break;
+            }
+
+            let same = match file.as_mut() {
+                Some(file) => {
+                    file.seek(SeekFrom::Start(offset))?;
+                    let len = read_page(file, &mut buf[..data.len()])?;
+                    &buf[..len] == &data[..]
+                }
+                None => false,
+            };
+            if same == false {
+                changed += 1;
+                self.stats.pages += 1;
+                self.stats.bytes += data.len() as u64;
+                if let (Some(file), false) = (file.as_mut(), self.dry_run) {
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.write_all(&data[..])?;
+                }
+            }
+
+            offset += data.len() as u64;
+            self.advance(data.len() as u64);
+        }
+        self.accessor
+            .release(&self.req, remote.ino, open.fh, 0, 0, false)
+            .await?;
+
+        let truncate = existing.map(|a| a.size > offset).unwrap_or(false);
+        if changed > 0 || truncate || existing.is_none() {
+            self.report("receiving", path);
+            self.stats.files += 1;
+        } else {
+            self.stats.skipped += 1;
+        }
+
+        if let (Some(file), false) = (file, self.dry_run) {
+            file.set_len(offset)?;
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_millis(remote.mtime))?;
+        }
+        self.pull_mode(local_path.as_path(), remote, local_mode)?;
+        Ok(())
+    }
+
+    fn pull_mode(&self, path: &Path, remote: &SyncEntry, local_mode: Option<u32>) -> Result<()> {
+        if self.dry_run == false && local_mode != Some(remote.mode) {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(remote.mode))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the inode of the remote path that is being synchronized (optionally
+    /// creating it if it does not yet exist)
+    async fn remote_root(&self, create: bool) -> Result<Option<u64>> {
+        let mut ret = self.accessor.getattr(&self.req, 1u64, None, 0u32).await?;
+        for comp in self.remote_path.split("/").filter(|a| a.len() > 0) {
+            let parent = ret.ino;
+            ret = match self.accessor.lookup(&self.req, parent, comp).await? {
+                Some(a) => a,
+                None if create && self.dry_run == false => {
+                    self.accessor.mkdir(&self.req, parent, comp, 0o770).await?
+                }
+                None => {
+                    return Ok(None);
+                }
+            };
+        }
+        if ret.kind != FileKind::Directory {
+            bail!(FileSystemErrorKind::NotDirectory);
+        }
+        Ok(Some(ret.ino))
+    }
+
+    /// Walks the remote file system and builds a list of all the entries within it
+    async fn remote_tree(&self, root: u64) -> Result<BTreeMap<String, SyncEntry>> {
+        let mut ret = BTreeMap::new();
+        let mut stack = vec![(root, String::new())];
+        while let Some((ino, prefix)) = stack.pop() {
+            let open = self
+                .accessor
+                .create_open_handle(ino, &self.req, O_RDONLY)
+                .await?;
+            for child in open.children.iter() {
+                if child.name == "." || child.name == ".." {
+                    continue;
+                }
+                let path = join_path(prefix.as_str(), child.name.as_str());
+
+                let mut mtime = child.attr.updated;
+                let mut link = None;
+                match child.kind {
+                    FileKind::Directory => {
+                        stack.push((child.inode, path.clone()));
+                    }
+                    FileKind::SymLink => {
+                        let open = self
+                            .accessor
+                            .create_open_handle(child.inode, &self.req, O_RDONLY)
+                            .await?;
+                        link = open.spec.link();
+                    }
+                    FileKind::RegularFile => {
+                        if let Some(val) = self
+                            .accessor
+                            .getxattr(&self.req, child.inode, SYNC_MTIME_XATTR)
+                            .await?
+                        {
+                            mtime = val.parse().unwrap_or(mtime);
+                        }
+                    }
+                    FileKind::FixedFile => {
+                        continue;
+                    }
+                }
+
+                ret.insert(
+                    path,
+                    SyncEntry {
+                        kind: child.kind,
+                        size: child.attr.size,
+                        mtime,
+                        mode: child.attr.mode & 0o7777,
+                        link,
+                        ino: child.inode,
+                    },
+                );
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the inode of the parent directory of a remote path
+    fn remote_parent(
+        &self,
+        path: &str,
+        root: Option<u64>,
+        remote: &BTreeMap<String, SyncEntry>,
+    ) -> u64 {
+        match remote.get(parent_path(path)) {
+            Some(a) => a.ino,
+            None => root.unwrap_or_default(),
+        }
+    }
+
+    /// Determines which entries on the destination must be removed, either because
+    /// they no longer exist on the source (only when deleting) or because they have
+    /// changed kind. The list is returned deepest first.
+    fn removals(
+        &self,
+        source: &BTreeMap<String, SyncEntry>,
+        dest: &BTreeMap<String, SyncEntry>,
+    ) -> Vec<String> {
+        dest.iter()
+            .rev()
+            .filter(|(path, entry)| match source.get(*path) {
+                Some(a) => a.kind != entry.kind,
+                None => self.delete,
+            })
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    fn start_progress(&mut self, source: &BTreeMap<String, SyncEntry>) {
+        if let Some(bar) = self.bar.as_mut() {
+            let total = source
+                .values()
+                .filter(|a| a.kind == FileKind::RegularFile)
+                .map(|a| a.size)
+                .sum();
+            bar.total = total;
+        }
+    }
+
+    fn advance(&mut self, amount: u64) {
+        if let Some(bar) = self.bar.as_mut() {
+            bar.add(amount);
+        }
+    }
+
+    fn report(&self, action: &str, path: &str) {
+        if self.bar.is_none() {
+            println!("{:<10} {}", action, path);
+        }
+    }
+}
+
+/// Walks a local directory and builds a list of all the entries within it
+fn local_tree(root: &Path) -> Result<BTreeMap<String, SyncEntry>> {
+    let mut ret = BTreeMap::new();
+    let mut stack = vec![String::new()];
+    while let Some(prefix) = stack.pop() {
+        for entry in std::fs::read_dir(root.join(prefix.as_str()))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = join_path(prefix.as_str(), name.as_str());
+
+            let meta = std::fs::symlink_metadata(entry.path())?;
+            let file_type = meta.file_type();
+            let mut link = None;
+            let kind = if file_type.is_symlink() {
+                link = Some(
+                    std::fs::read_link(entry.path())?
+                        .to_string_lossy()
+                        .to_string(),
+                );
+                FileKind::SymLink
+            } else if file_type.is_dir() {
+                stack.push(path.clone());
+                FileKind::Directory
+            } else if file_type.is_file() {
+                FileKind::RegularFile
+            } else {
+                continue;
+            };
+
+            let mtime = meta
+                .modified()
+                .ok()
+                .and_then(|a| a.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|a| a.as_millis() as u64)
+                .unwrap_or_default();
+
+            ret.insert(
+                path,
+                SyncEntry {
+                    kind,
+                    size: meta.len(),
+                    mtime,
+                    mode: meta.permissions().mode() & 0o7777,
+                    link,
+                    ino: 0u64,
+                },
+            );
+        }
+    }
+    Ok(ret)
+}
+
+/// Reads a full page from the file (or whatever remains of it)
+fn read_page(file: &mut File, buf: &mut [u8]) -> Result<usize> {
+    let mut total = 0usize;
+    while total < buf.len() {
+        let read = file.read(&mut buf[total..])?;
+        if read <= 0 {
+            break;
+        }
+        total += read;
+    }
+    Ok(total)
+}
+
+fn join_path(prefix: &str, name: &str) -> String {
+    match prefix.len() {
+        0 => name.to_string(),
+        _ => format!("{}/{}", prefix, name),
+    }
+}
+
+fn parent_path(path: &str) -> &str {
+    match path.rsplit_once("/") {
+        Some((parent, _)) => parent,
+        None => "",
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    match path.rsplit_once("/") {
+        Some((_, name)) => name,
+        None => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_sync(name: &str, local_root: PathBuf) -> Synchronizer {
+        ate::utils::bootstrap_test_env();
+
+        let mut conf = ConfAte::default();
+        conf.configured_for(ConfiguredFor::BestPerformance);
+        let builder = ChainBuilder::new(&conf).await.build();
+        let chain = builder
+            .open(&ChainKey::default().with_temp_name(name.to_string()))
+            .await
+            .unwrap();
+
+        let accessor = FileAccessor::new(
+            chain,
+            None,
+            AteSessionType::User(AteSessionUser::new()),
+            TransactionScope::Local,
+            TransactionScope::Local,
+            true,
+            false,
+        )
+        .await;
+        let req = accessor.session_context();
+        accessor.init(&req).await.unwrap();
+
+        Synchronizer {
+            accessor,
+            req,
+            local_root,
+            remote_path: "/site".to_string(),
+            delete: false,
+            dry_run: false,
+            checksum: false,
+            stats: SyncStats::default(),
+            bar: None,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let ret = std::env::temp_dir().join(format!("{}-{}", name, fastrand::u64(..)));
+        std::fs::create_dir_all(&ret).unwrap();
+        ret
+    }
+
+    fn set_mode(path: &Path, mode: u32) {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn entry(kind: FileKind) -> SyncEntry {
+        SyncEntry {
+            kind,
+            size: 0,
+            mtime: 0,
+            mode: 0o644,
+            link: None,
+            ino: 0,
+        }
+    }
+
+    #[test]
+    fn paths_are_split_into_parents_and_names() {
+        assert_eq!(join_path("", "a"), "a");
+        assert_eq!(join_path("a/b", "c"), "a/b/c");
+        assert_eq!(parent_path("a"), "");
+        assert_eq!(parent_path("a/b/c"), "a/b");
+        assert_eq!(file_name("a"), "a");
+        assert_eq!(file_name("a/b/c"), "c");
+    }
+
+    #[tokio::test]
+    async fn removals_are_deepest_first() {
+        let mut sync = create_sync("sync-removals", temp_dir("sync-removals")).await;
+
+        let mut source = BTreeMap::new();
+        source.insert("a".to_string(), entry(FileKind::Directory));
+        source.insert("b".to_string(), entry(FileKind::RegularFile));
+
+        let mut dest = BTreeMap::new();
+        dest.insert("a".to_string(), entry(FileKind::Directory));
+        dest.insert("b".to_string(), entry(FileKind::Directory));
+        dest.insert("b/c".to_string(), entry(FileKind::RegularFile));
+        dest.insert("d".to_string(), entry(FileKind::RegularFile));
+
+        // Without deleting only the entries that changed kind are removed
+        assert_eq!(sync.removals(&source, &dest), vec!["b".to_string()]);
+
+        sync.delete = true;
+        assert_eq!(
+            sync.removals(&source, &dest),
+            vec!["d".to_string(), "b/c".to_string(), "b".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn push_only_sends_the_pages_that_changed() {
+        let local = temp_dir("sync-push");
+        let mut data = vec![7u8; PAGE_SIZE * 2 + 100];
+        std::fs::create_dir_all(local.join("sub")).unwrap();
+        std::fs::write(local.join("index.html"), b"hello").unwrap();
+        std::fs::write(local.join("sub/big.bin"), &data[..]).unwrap();
+        std::os::unix::fs::symlink("index.html", local.join("home.html")).unwrap();
+
+        let mut sync = create_sync("sync-push", local.clone()).await;
+        sync.push().await.unwrap();
+        assert_eq!(sync.stats.files, 2);
+        assert_eq!(sync.stats.dirs, 1);
+        assert_eq!(sync.stats.links, 1);
+        assert_eq!(sync.stats.pages, 4);
+
+        // Nothing is sent when nothing has changed
+        sync.stats = SyncStats::default();
+        sync.push().await.unwrap();
+        assert_eq!(sync.stats.files, 0);
+        assert_eq!(sync.stats.pages, 0);
+        assert_eq!(sync.stats.skipped, 3);
+
+        // Only the page that was modified is sent again
+        data[PAGE_SIZE + 1] = 8u8;
+        std::fs::write(local.join("sub/big.bin"), &data[..]).unwrap();
+        sync.stats = SyncStats::default();
+        sync.checksum = true;
+        sync.push().await.unwrap();
+        assert_eq!(sync.stats.files, 1);
+        assert_eq!(sync.stats.pages, 1);
+        assert_eq!(sync.stats.bytes, PAGE_SIZE as u64);
+
+        let root = sync.remote_root(false).await.unwrap().unwrap();
+        let remote = sync.remote_tree(root).await.unwrap();
+        assert_eq!(remote.get("sub/big.bin").unwrap().size, data.len() as u64);
+        assert_eq!(remote.get("home.html").unwrap().link, Some("index.html".to_string()));
+
+        // Files that were removed locally are only deleted when asked
+        std::fs::remove_file(local.join("index.html")).unwrap();
+        sync.stats = SyncStats::default();
+        sync.push().await.unwrap();
+        assert_eq!(sync.stats.deleted, 0);
+        sync.delete = true;
+        sync.push().await.unwrap();
+        assert_eq!(sync.stats.deleted, 1);
+
+        std::fs::remove_dir_all(local).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pull_updates_read_only_files() {
+        let local = temp_dir("sync-pull-src");
+        std::fs::write(local.join("readme.txt"), b"first").unwrap();
+        set_mode(local.join("readme.txt").as_path(), 0o444);
+
+        let mut sync = create_sync("sync-pull", local.clone()).await;
+        sync.push().await.unwrap();
+
+        // Pull into a fresh directory
+        let dest = temp_dir("sync-pull-dst");
+        sync.local_root = dest.clone();
+        sync.pull().await.unwrap();
+        let path = dest.join("readme.txt");
+        assert_eq!(std::fs::read(&path).unwrap(), b"first".to_vec());
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o444);
+
+        // Change the remote copy and pull it over the read-only local file
+        set_mode(local.join("readme.txt").as_path(), 0o644);
+        std::fs::write(local.join("readme.txt"), b"second").unwrap();
+        set_mode(local.join("readme.txt").as_path(), 0o444);
+        sync.local_root = local.clone();
+        sync.checksum = true;
+        sync.push().await.unwrap();
+
+        sync.local_root = dest.clone();
+        sync.stats = SyncStats::default();
+        sync.pull().await.unwrap();
+        assert_eq!(sync.stats.files, 1);
+        assert_eq!(std::fs::read(&path).unwrap(), b"second".to_vec());
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o444);
+
+        set_mode(local.join("readme.txt").as_path(), 0o644);
+        set_mode(path.as_path(), 0o644);
+        std::fs::remove_dir_all(local).unwrap();
+        std::fs::remove_dir_all(dest).unwrap();
+    }
+}
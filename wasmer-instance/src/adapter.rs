@@ -276,7 +276,7 @@ impl Seek for FileAccessorVirtualFile {
 
 impl Write for FileAccessorVirtualFile {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let buf = buf.to_vec();
+        let buf = backend::ByteStream::from(buf);
         let io = self.io.clone();
         
         let ret = self.fs.block_on(async move {
@@ -309,10 +309,12 @@ impl Read for FileAccessorVirtualFile {
         let io = self.io.clone();
         
         let data = self.fs.block_on(async move {
-            io.read(len as u64).await
+            match io.read(len as u64).await {
+                Ok(stream) => stream.read_to_end().await.map_err(|err| err.into_io_error()),
+                Err(err) => Err(conv_io_err(err)),
+            }
         })
-        .map_err(|_| conv_io_err(backend::FsError::ConnectionAborted))?
-        .map_err(conv_io_err)?;
+        .map_err(|_| conv_io_err(backend::FsError::ConnectionAborted))??;
 
         if data.len() <= 0 {
             return Ok(0usize);
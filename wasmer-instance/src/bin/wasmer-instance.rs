@@ -2,6 +2,7 @@
 use ate::mesh::MeshHashTable;
 use ate::utils::load_node_list;
 use wasmer_instance::server::Server;
+use wasmer_instance::limits::LimitsConf;
 use tokio::sync::watch;
 #[allow(unused_imports, dead_code)]
 use tracing::{info, error, debug, trace, warn};
@@ -93,6 +94,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>>
                     instance_authority = "wasmer.sh".to_string();
                 }
 
+                // The limits of the instances are set by the operator
+                let limits = match solo.limits_path.as_ref() {
+                    Some(path) => LimitsConf::load(path.as_str())?,
+                    None => LimitsConf::default(),
+                };
+
                 let compiled_modules = Arc::new(CachedCompiledModules::new(Some(solo.compiler_cache_path.clone())));
                 let instance_server = Server::new(
                     solo.db_url.clone(),
@@ -103,6 +110,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>>
                     solo.compiler.clone(),
                     compiled_modules.clone(),
                     ttl,
+                    limits,
                 ).await?;
 
                 let mut router = ate::comms::StreamRouter::new(
@@ -115,10 +123,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>>
                 );
                 
                 let route = Arc::new(instance_server);
+                route.start_triggers();
                 router.add_socket_route("/sess", route.clone()).await;
                 router.add_socket_route("/inst", route.clone()).await;
                 router.add_post_route("/sess", route.clone()).await;
                 router.add_post_route("/inst", route.clone()).await;
+                router.add_post_route("/hook", route.clone()).await;
                 router.add_put_route("/sess", route.clone()).await;
                 router.add_put_route("/inst", route.clone()).await;
 
@@ -5,6 +5,8 @@ pub mod handler;
 pub mod relay;
 pub mod adapter;
 pub mod fixed_reader;
+pub mod limits;
+pub mod triggers;
 
 pub use wasmer_term;
 pub use wasmer_auth;
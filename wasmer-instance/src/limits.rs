@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use ate::prelude::*;
+use serde::*;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+use wasmer_deploy_cli::model::ContractMetrics;
+use wasmer_deploy_cli::model::InstanceLimits;
+use wasmer_deploy_cli::model::InstanceUsage;
+use wasmer_ssh::wasmer_os;
+use wasmer_os::eval::ProcessLimits;
+use wasmer_os::eval::ProcessMeter;
+
+/// Length of the window that the bandwidth quota applies to
+const BANDWIDTH_WINDOW: Duration = Duration::from_secs(60);
+
+/// How often the accumilated usage is written to the usage chain
+pub const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits that the operator enforces on the instances that run on this
+/// server (the owners of the instances can not change these)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LimitsConf {
+    /// Limits of the instances that are not listed below
+    #[serde(default)]
+    pub default: InstanceLimits,
+    /// Limits of particular instances (by the key of their chain)
+    #[serde(default)]
+    pub instances: HashMap<String, InstanceLimits>,
+}
+
+impl LimitsConf {
+    /// Loads the limits from a JSON file
+    pub fn load(path: &str) -> Result<LimitsConf, Box<dyn std::error::Error>> {
+        let path = shellexpand::tilde(path).to_string();
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(data.as_str())?)
+    }
+
+    pub fn for_instance(&self, key: &ChainKey) -> InstanceLimits {
+        self.instances
+            .get(&key.to_string())
+            .unwrap_or(&self.default)
+            .clone()
+    }
+}
+
+/// Chain owned by the operator that the usage of the instances is
+/// recorded on (so that it can be charged for) along with other state
+/// that the instances must not be able to change
+pub struct UsageLedger {
+    chain: Arc<Chain>,
+    session: AteSessionGroup,
+    write_key: PrivateSignKey,
+}
+
+impl UsageLedger {
+    pub fn new(chain: Arc<Chain>, session: AteSessionGroup, write_key: PrivateSignKey) -> UsageLedger {
+        UsageLedger {
+            chain,
+            session,
+            write_key,
+        }
+    }
+
+    pub async fn dio(&self) -> Arc<Dio> {
+        self.chain.dio(&self.session).await
+    }
+
+    pub async fn dio_mut(&self) -> Arc<DioMut> {
+        self.chain.dio_mut(&self.session).await
+    }
+
+    /// Key that the records of the operator are protected with
+    pub fn write_key(&self) -> &PrivateSignKey {
+        &self.write_key
+    }
+}
+
+/// Record on the usage ledger that a particular instance is metered into
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    /// Key of the record (derived from the chain of the instance)
+    pub key: PrimaryKey,
+    /// Identifier of the instance
+    pub related_to: String,
+    /// Read key of the instance chain which lets its owner read the record
+    pub read_key: EncryptKey,
+}
+
+/// Reason that a call was refused by the quotas of an instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    ConcurrentCalls(u32),
+    Bandwidth(u64),
+}
+
+impl fmt::Display
+for QuotaExceeded
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaExceeded::ConcurrentCalls(max) => write!(f, "too many concurrent calls (max={})", max),
+            QuotaExceeded::Bandwidth(max) => write!(f, "bandwidth quota exceeded (max={} bytes per minute)", max),
+        }
+    }
+}
+
+struct BandwidthWindow {
+    started: Instant,
+    bytes: u64,
+}
+
+/// Enforces the quotas of a service instance and accumilates the resources
+/// that it consumes so they can be recorded against its metrics
+pub struct InstanceMeter {
+    limits: InstanceLimits,
+    process: Arc<ProcessMeter>,
+    concurrent: Arc<AtomicU32>,
+    window: Mutex<BandwidthWindow>,
+    /// Bytes sent to the instance in requests
+    upload: AtomicU64,
+    /// Bytes sent by the instance in responses
+    download: AtomicU64,
+}
+
+impl fmt::Debug
+for InstanceMeter
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instance-meter(concurrent={}, limits={:?})", self.concurrent.load(Ordering::Acquire), self.limits)
+    }
+}
+
+impl InstanceMeter {
+    pub fn new(limits: InstanceLimits) -> InstanceMeter {
+        InstanceMeter {
+            limits,
+            process: Arc::new(ProcessMeter::default()),
+            concurrent: Arc::new(AtomicU32::new(0)),
+            window: Mutex::new(BandwidthWindow {
+                started: Instant::now(),
+                bytes: 0,
+            }),
+            upload: AtomicU64::new(0),
+            download: AtomicU64::new(0),
+        }
+    }
+
+    /// Limits that are applied to each process started in the instance
+    pub fn process_limits(&self) -> ProcessLimits {
+        ProcessLimits {
+            max_fuel: self.limits.max_fuel,
+            max_memory_pages: self.limits.max_memory_pages,
+            max_wall_time: self.max_wall_time(),
+            max_open_files: self.limits.max_open_files,
+            meter: Some(self.process.clone()),
+        }
+    }
+
+    pub fn max_wall_time(&self) -> Option<Duration> {
+        self.limits.max_wall_time_ms.map(Duration::from_millis)
+    }
+
+    /// Checks the quotas without consuming any of them
+    pub fn check(&self) -> Result<(), QuotaExceeded> {
+        if let Some(max) = self.limits.max_concurrent_calls {
+            if self.concurrent.load(Ordering::Acquire) >= max {
+                return Err(QuotaExceeded::ConcurrentCalls(max));
+            }
+        }
+        if let Some(max) = self.limits.max_bandwidth_per_minute {
+            let mut window = self.window.lock().unwrap();
+            Self::roll(&mut window);
+            if window.bytes >= max {
+                return Err(QuotaExceeded::Bandwidth(max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a call on the instance which counts against the concurrent
+    /// calls until the returned guard is dropped
+    pub fn begin_call(&self, request_len: usize) -> Result<CallGuard, QuotaExceeded> {
+        self.check()?;
+
+        let concurrent = self.concurrent.fetch_add(1, Ordering::AcqRel);
+        let guard = CallGuard {
+            concurrent: self.concurrent.clone(),
+        };
+        if let Some(max) = self.limits.max_concurrent_calls {
+            if concurrent >= max {
+                return Err(QuotaExceeded::ConcurrentCalls(max));
+            }
+        }
+
+        self.record_upload(request_len);
+        Ok(guard)
+    }
+
+    pub fn record_upload(&self, bytes: usize) {
+        self.upload.fetch_add(bytes as u64, Ordering::AcqRel);
+        self.record_bandwidth(bytes);
+    }
+
+    pub fn record_download(&self, bytes: usize) {
+        self.download.fetch_add(bytes as u64, Ordering::AcqRel);
+        self.record_bandwidth(bytes);
+    }
+
+    fn record_bandwidth(&self, bytes: usize) {
+        if self.limits.max_bandwidth_per_minute.is_some() {
+            let mut window = self.window.lock().unwrap();
+            Self::roll(&mut window);
+            window.bytes += bytes as u64;
+        }
+    }
+
+    fn roll(window: &mut BandwidthWindow) {
+        if window.started.elapsed() >= BANDWIDTH_WINDOW {
+            window.started = Instant::now();
+            window.bytes = 0;
+        }
+    }
+
+    pub fn limits(&self) -> &InstanceLimits {
+        &self.limits
+    }
+
+    /// Writes the usage accumilated since the last flush into the record of
+    /// the instance on the usage ledger (which is where it is picked up for
+    /// billing)
+    pub async fn flush(&self, ledger: &UsageLedger, record: &UsageRecord) -> Result<(), AteError> {
+        let (fuel, compute) = self.process.take_usage();
+        let download = self.download.swap(0, Ordering::AcqRel);
+        let upload = self.upload.swap(0, Ordering::AcqRel);
+        if fuel <= 0 && compute <= 0 && download <= 0 && upload <= 0 {
+            return Ok(());
+        }
+
+        let ret = self.flush_internal(ledger, record, fuel, compute, download, upload).await;
+        if ret.is_err() {
+            // Put the usage back so that it is not lost
+            self.process.record_fuel(fuel);
+            self.process.record_compute(Duration::from_micros(compute));
+            self.download.fetch_add(download, Ordering::AcqRel);
+            self.upload.fetch_add(upload, Ordering::AcqRel);
+        }
+        ret
+    }
+
+    async fn flush_internal(&self, ledger: &UsageLedger, record: &UsageRecord, fuel: u64, compute: u64, download: u64, upload: u64) -> Result<(), AteError> {
+        let dio = ledger.dio_mut().await;
+        let mut usage = if dio.exists(&record.key).await {
+            dio.load::<InstanceUsage>(&record.key).await?
+        } else {
+            let mut usage = dio.store_with_key(
+                InstanceUsage {
+                    limits: self.limits.clone(),
+                    metrics: ContractMetrics {
+                        related_to: record.related_to.clone(),
+                        ..Default::default()
+                    },
+                },
+                record.key.clone(),
+            )?;
+            usage.auth_mut().read = ReadOption::from_key(&record.read_key);
+            usage.auth_mut().write = WriteOption::Specific(ledger.write_key().hash());
+            usage
+        };
+
+        {
+            let mut usage = usage.as_mut();
+            usage.limits = self.limits.clone();
+            let metrics = &mut usage.metrics;
+            metrics.current_accumilated_fuel += fuel;
+            metrics.current_accumilated_compute += compute;
+            metrics.current_accumilated_download += download;
+            metrics.current_accumilated_upload += upload;
+        }
+        dio.commit().await?;
+
+        debug!("recorded usage for {} (fuel={}, compute={}us, download={}, upload={})", record.related_to, fuel, compute, download, upload);
+        Ok(())
+    }
+}
+
+/// Holds a slot in the concurrent calls of an instance
+pub struct CallGuard {
+    concurrent: Arc<AtomicU32>,
+}
+
+impl Drop
+for CallGuard
+{
+    fn drop(&mut self) {
+        self.concurrent.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Completes when the wall time of a call has elapsed (or never if
+/// there is no limit)
+pub async fn wall_time_elapsed(max_wall_time: Option<Duration>) {
+    match max_wall_time {
+        Some(a) => tokio::time::sleep(a).await,
+        None => futures::future::pending::<()>().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    fn limits(max_concurrent_calls: Option<u32>, max_bandwidth_per_minute: Option<u64>) -> InstanceLimits {
+        InstanceLimits {
+            max_concurrent_calls,
+            max_bandwidth_per_minute,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn concurrent_calls_are_capped() {
+        let meter = InstanceMeter::new(limits(Some(2), None));
+
+        let a = meter.begin_call(0).unwrap();
+        let _b = meter.begin_call(0).unwrap();
+        assert_eq!(meter.begin_call(0).err(), Some(QuotaExceeded::ConcurrentCalls(2)));
+        assert_eq!(meter.check(), Err(QuotaExceeded::ConcurrentCalls(2)));
+
+        // Finishing a call frees its slot (and refused calls never held one)
+        drop(a);
+        assert_eq!(meter.concurrent.load(Ordering::Acquire), 1);
+        let _c = meter.begin_call(0).unwrap();
+    }
+
+    #[test]
+    fn racing_calls_do_not_exceed_the_cap() {
+        const THREADS: usize = 16;
+        let meter = Arc::new(InstanceMeter::new(limits(Some(4), None)));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let release = Arc::new(Barrier::new(THREADS));
+
+        let threads = (0..THREADS)
+            .map(|_| {
+                let meter = meter.clone();
+                let barrier = barrier.clone();
+                let release = release.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let guard = meter.begin_call(0);
+                    let ret = guard.is_ok();
+                    // Hold the slots until every thread has tried
+                    release.wait();
+                    ret
+                })
+            })
+            .collect::<Vec<_>>();
+        let accepted = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|a| *a)
+            .count();
+
+        assert_eq!(accepted, 4);
+        assert_eq!(meter.concurrent.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn bandwidth_window_rolls_over() {
+        let meter = InstanceMeter::new(limits(None, Some(100)));
+
+        let _a = meter.begin_call(60).unwrap();
+        meter.record_download(40);
+        assert_eq!(meter.check(), Err(QuotaExceeded::Bandwidth(100)));
+        assert_eq!(meter.begin_call(1).err(), Some(QuotaExceeded::Bandwidth(100)));
+
+        // Once the window has passed the quota is available again
+        meter.window.lock().unwrap().started = Instant::now()
+            .checked_sub(BANDWIDTH_WINDOW)
+            .unwrap();
+        assert_eq!(meter.check(), Ok(()));
+        let _b = meter.begin_call(60).unwrap();
+        assert_eq!(meter.window.lock().unwrap().bytes, 60);
+
+        // (the usage that is billed is not affected by the window)
+        assert_eq!(meter.upload.load(Ordering::Acquire), 120);
+        assert_eq!(meter.download.load(Ordering::Acquire), 40);
+    }
+
+    #[test]
+    fn process_limits_follow_the_instance() {
+        let meter = InstanceMeter::new(InstanceLimits {
+            max_fuel: Some(1000),
+            max_memory_pages: Some(16),
+            max_open_files: Some(8),
+            max_wall_time_ms: Some(1500),
+            ..Default::default()
+        });
+
+        let limits = meter.process_limits();
+        assert_eq!(limits.max_fuel, Some(1000));
+        assert_eq!(limits.max_memory_pages, Some(16));
+        assert_eq!(limits.max_open_files, Some(8));
+        assert_eq!(limits.max_wall_time, Some(Duration::from_millis(1500)));
+        assert!(limits.meter.is_some());
+    }
+
+    #[test]
+    fn operator_limits_by_instance() {
+        let conf: LimitsConf = serde_json::from_str(
+            r#"{
+                "default": { "max_fuel": 1000 },
+                "instances": {
+                    "wasmer.sh/big": { "max_fuel": 5000, "max_open_files": 64 }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let big = conf.for_instance(&ChainKey::from("wasmer.sh/big"));
+        assert_eq!(big.max_fuel, Some(5000));
+        assert_eq!(big.max_open_files, Some(64));
+        let other = conf.for_instance(&ChainKey::from("wasmer.sh/other"));
+        assert_eq!(other.max_fuel, Some(1000));
+        assert_eq!(other.max_open_files, None);
+    }
+}
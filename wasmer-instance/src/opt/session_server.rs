@@ -49,4 +49,8 @@ pub struct OptsSessionServer {
     /// Time-to-live for sessions that are initiated
     #[clap(long, default_value = "300")]
     pub ttl: u64,
+    /// JSON file that holds the limits enforced on the instances (a default
+    /// plus overrides for particular instances by the key of their chain)
+    #[clap(long)]
+    pub limits_path: Option<String>,
 }
\ No newline at end of file
@@ -29,9 +29,12 @@ use url::Url;
 use percent_encoding::{percent_decode};
 use wasmer_deploy_cli::model::MasterAuthority;
 use wasmer_deploy_cli::model::ServiceInstance;
+use wasmer_deploy_cli::model::TriggerKind;
 use wasmer_deploy_cli::model::InstanceReply;
 use wasmer_deploy_cli::model::INSTANCE_ROOT_ID;
 use wasmer_deploy_cli::model::MASTER_AUTHORITY_ID;
+use wasmer_deploy_cli::model::instance_usage_chain;
+use wasmer_deploy_cli::model::instance_usage_key;
 #[allow(unused_imports)]
 use wasmer_deploy_cli::model::InstanceCall;
 use wasmer_ssh::wasmer_os;
@@ -57,6 +60,16 @@ use http::StatusCode;
 use crate::adapter::FileAccessorAdapter;
 use crate::session::Session;
 use crate::fixed_reader::FixedReader;
+use crate::limits::InstanceMeter;
+use crate::limits::LimitsConf;
+use crate::limits::UsageLedger;
+use crate::limits::UsageRecord;
+use crate::limits::USAGE_FLUSH_INTERVAL;
+use crate::triggers::TriggerArming;
+use crate::triggers::TriggerInvocation;
+use crate::triggers::arm_known_instances;
+use crate::triggers::run_trigger;
+use crate::triggers::watch_triggers;
 
 #[derive(Clone)]
 pub struct SessionBasics {
@@ -64,7 +77,8 @@ pub struct SessionBasics {
     pub bins: BinFactory,
     pub reactor: Arc<RwLock<Reactor>>,
     pub service_instance: DaoMut<ServiceInstance>,
-    pub multiplexer: SubProcessMultiplexer
+    pub multiplexer: SubProcessMultiplexer,
+    pub meter: Arc<InstanceMeter>,
 }
 
 pub struct Server
@@ -80,6 +94,10 @@ pub struct Server
     pub instance_authority: String,
     pub sessions: RwLock<TtlCache<ChainKey, SessionBasics>>,
     pub ttl: Duration,
+    pub triggers: TriggerArming,
+    pub limits: LimitsConf,
+    session_factory: SessionFactory,
+    usage_ledger: tokio::sync::OnceCell<Arc<UsageLedger>>,
 }
 
 impl Server
@@ -93,6 +111,7 @@ impl Server
         compiler: wasmer_os::eval::Compiler,
         compiled_modules: Arc<CachedCompiledModules>,
         ttl: Duration,
+        limits: LimitsConf,
     ) -> Result<Self, Box<dyn std::error::Error>>
     {
         // Build a session factory that will load the session for this instance using the broker key
@@ -109,7 +128,7 @@ impl Server
             &registry,
             db_url.clone(),
             auth_url.clone(),
-            Box::new(session_factory),
+            Box::new(session_factory.clone()),
             ttl,
         )
         .await?;
@@ -129,9 +148,77 @@ impl Server
             instance_authority,
             sessions,
             ttl,
+            triggers: TriggerArming::new(),
+            limits,
+            session_factory,
+            usage_ledger: tokio::sync::OnceCell::new(),
         })
     }
 
+    /// Opens the chain that the usage of the instances is recorded on, it
+    /// belongs to the instance authority rather than to the instances
+    pub(crate) async fn usage_ledger(&self) -> Result<Arc<UsageLedger>, AteError> {
+        let ret = self.usage_ledger.get_or_try_init(|| async {
+            let session = self.session_factory.edge_session().await?;
+            let write_key = match session
+                .get_group_role(&AteRolePurpose::Owner)
+                .and_then(|r| r.write_keys().next())
+            {
+                Some(a) => a.clone(),
+                None => {
+                    error!("the edge session does not own the instance authority");
+                    let err: wasmer_auth::error::GatherError = wasmer_auth::error::GatherErrorKind::NoMasterKey.into();
+                    return Err(AteError::from(err));
+                }
+            };
+            let key = instance_usage_chain(self.instance_authority.as_str());
+            let chain = self.registry.open(&self.db_url, &key, false).await?;
+            Ok(Arc::new(UsageLedger::new(chain.as_arc(), session, write_key)))
+        }).await?;
+        Ok(ret.clone())
+    }
+
+    /// Starts the background threads that watch the triggers of every
+    /// instance that has them and that run the webhooks
+    pub fn start_triggers(self: &Arc<Self>) {
+        let (mut rx, mut run_rx) = match (self.triggers.take_receiver(), self.triggers.take_run_receiver()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => { return; }
+        };
+        let server = Arc::clone(self);
+        self.system.fork_shared(move || async move {
+            while let Some(key) = rx.recv().await {
+                let server = server.clone();
+                server.system.clone().fork_shared(move || async move {
+                    watch_triggers(server, key).await;
+                });
+            }
+        });
+
+        let server = Arc::clone(self);
+        self.system.fork_shared(move || async move {
+            while let Some((chain, invocation)) = run_rx.recv().await {
+                let server = server.clone();
+                server.system.clone().fork_shared(move || async move {
+                    match run_trigger(&server, &chain, invocation).await {
+                        Ok(Some((outcome, _))) => debug!("webhook trigger on {} finished - {}", chain, outcome),
+                        Ok(None) => { }
+                        Err(err) => warn!("webhook trigger failed on {} - {}", chain, err),
+                    }
+                });
+            }
+        });
+
+        // Instances are armed when the server starts (rather than waiting
+        // for them to be loaded) so that their schedules and events fire
+        let server = Arc::clone(self);
+        self.system.fork_shared(move || async move {
+            if let Err(err) = arm_known_instances(&server).await {
+                warn!("failed to arm the triggers of the known instances - {}", err);
+            }
+        });
+    }
+
     pub async fn get_or_create_session_basics(&self, key: ChainKey) -> Result<(SessionBasics, bool), CommsError> {
         // Check the cache
         {
@@ -155,6 +242,21 @@ impl Server
         trace!("loading service instance with key {}", PrimaryKey::from(INSTANCE_ROOT_ID));
         let service_instance = chain_dio.load::<ServiceInstance>(&PrimaryKey::from(INSTANCE_ROOT_ID)).await?;
 
+        // The usage is recorded on the ledger of the operator but it is
+        // encrypted with the read key of the instance so its owner can see it
+        let ledger = self.usage_ledger().await
+            .map_err(|err| CommsErrorKind::InternalError(err.to_string()))?;
+        let read_key = accessor.dio.session()
+            .read_keys(AteSessionKeyCategory::AllKeys)
+            .next()
+            .cloned()
+            .ok_or_else(|| CommsErrorKind::InternalError("the instance session has no read key".to_string()))?;
+        let record = UsageRecord {
+            key: instance_usage_key(&key),
+            related_to: service_instance.id_str(),
+            read_key,
+        };
+
         // Enter a write lock and check again
         let mut guard = self.sessions.write().await;
         if let Some(ret) = guard.get(&key) {
@@ -165,6 +267,25 @@ impl Server
         let bins = BinFactory::new(self.compiled_modules.clone());
         let reactor = Arc::new(RwLock::new(Reactor::new()));
         let multiplexer = SubProcessMultiplexer::new();
+
+        // The meter enforces the limits that the operator set for the instance
+        // and periodically records its usage on the usage ledger
+        let meter = Arc::new(InstanceMeter::new(self.limits.for_instance(&key)));
+        {
+            let meter = meter.clone();
+            self.system.fork_shared(move || async move {
+                loop {
+                    tokio::time::sleep(USAGE_FLUSH_INTERVAL).await;
+                    let finished = Arc::strong_count(&meter) <= 1;
+                    if let Err(err) = meter.flush(&ledger, &record).await {
+                        warn!("failed to record usage for {} - {}", record.related_to, err);
+                    }
+                    if finished {
+                        break;
+                    }
+                }
+            });
+        }
         
         // Build the basics
         let basics = SessionBasics {
@@ -173,11 +294,15 @@ impl Server
             reactor,
             service_instance,
             multiplexer,
+            meter,
         };
 
         // Cache and and return it
         let ret = basics.clone();
         guard.insert(key.clone(), basics, self.ttl);
+
+        // Make sure the triggers of this instance are being watched
+        self.triggers.arm(&key);
         Ok((ret, true))
     }
 
@@ -242,6 +367,95 @@ impl Server
         });
         Ok(())
     }
+
+    async fn accepted_webhook(
+        &self,
+        uri: http::Uri,
+        headers: http::HeaderMap,
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, (Vec<u8>, StatusCode)>
+    {
+        // Get the chain and the trigger
+        let path = std::path::PathBuf::from(uri.path().to_string());
+        let (chain, trigger) = {
+            let mut path_iter = path.iter().map(|a| a.to_string_lossy().to_string());
+            path_iter.next();
+            path_iter.next();
+            let identity = path_iter.next();
+            let db = path_iter.next();
+            let trigger = path_iter.next();
+
+            if identity.is_none() || db.is_none() || trigger.is_none() {
+                let msg = format!("The URL path is malformed").as_bytes().to_vec();
+                return Err((msg, StatusCode::BAD_REQUEST));
+            }
+
+            let chain = format!("{}/{}", identity.unwrap(), db.unwrap());
+            (chain, trigger.unwrap())
+        };
+        let chain = ChainKey::new(chain);
+
+        // Get the authorization
+        if headers.contains_key(http::header::AUTHORIZATION) == false {
+            let msg = format!("Missing the Authorization header").as_bytes().to_vec();
+            return Err((msg, StatusCode::UNAUTHORIZED));
+        }
+        let auth = headers[http::header::AUTHORIZATION].clone();
+        let auth = auth.to_str().unwrap_or_default().trim();
+        let auth = auth.strip_prefix("Bearer ").unwrap_or(auth);
+
+        debug!("accept-webhook: uri: {}", uri);
+
+        // Find the trigger that this webhook belongs to
+        let (basics, _) = self.get_or_create_session_basics(chain.clone())
+            .await
+            .map_err(|err| {
+                debug!("webhook failed - {}", err);
+                (Vec::new(), StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+        let trigger = basics.service_instance.triggers
+            .iter()
+            .await
+            .map_err(|err| {
+                debug!("webhook failed - {}", err);
+                (Vec::new(), StatusCode::INTERNAL_SERVER_ERROR)
+            })?
+            .filter(|t| t.name.eq_ignore_ascii_case(trigger.as_str()))
+            .filter(|t| match &t.kind {
+                TriggerKind::Webhook { token } => {
+                    // (compared in constant time so the token can not be guessed
+                    //  one byte at a time)
+                    ring::constant_time::verify_slices_are_equal(token.as_bytes(), auth.as_bytes()).is_ok()
+                },
+                _ => false,
+            })
+            .next()
+            .ok_or_else(|| {
+                let msg = format!("Access Denied (Invalid Token)").as_bytes().to_vec();
+                (msg, StatusCode::UNAUTHORIZED)
+            })?;
+        if trigger.enabled == false {
+            let msg = format!("The trigger is disabled").as_bytes().to_vec();
+            return Err((msg, StatusCode::SERVICE_UNAVAILABLE));
+        }
+
+        // The trigger runs in the background (with its retries) and its
+        // outcome is recorded in the run history
+        let queued = self.triggers.queue_run(chain, TriggerInvocation {
+            trigger: trigger.key().clone(),
+            cause: "webhook".to_string(),
+            stdin: body,
+            claim: None,
+        });
+        if queued == false {
+            let msg = format!("Too many triggers are waiting to run").as_bytes().to_vec();
+            return Err((msg, StatusCode::SERVICE_UNAVAILABLE));
+        }
+
+        // (the router only carries a status code with the error path)
+        let msg = format!("Accepted").as_bytes().to_vec();
+        Err((msg, StatusCode::ACCEPTED))
+    }
 }
 
 #[async_trait]
@@ -445,6 +659,12 @@ for Server
                 (msg, StatusCode::INTERNAL_SERVER_ERROR)
             })?;
 
+        // Check the quotas of the instance
+        if let Err(err) = basics.meter.check() {
+            let msg = format!("Quota exceeded - {}", err).as_bytes().to_vec();
+            return Err((msg, StatusCode::TOO_MANY_REQUESTS));
+        }
+
         // Create a fixed reader
         let rx: Box<dyn StreamReadable + Send + Sync + Unpin + 'static> = Box::new(FixedReader::new(Vec::new()));
         
@@ -529,6 +749,11 @@ for Server
         body: Vec<u8>,
     ) -> Result<Vec<u8>, (Vec<u8>, StatusCode)>
     {
+        // Webhooks invoke the trigger they belong to
+        if uri.path().starts_with("/hook/") {
+            return self.accepted_webhook(uri, headers, body).await;
+        }
+
         // Get the chain and the binary
         let mut args = Vec::new();
         let mut redirects = Vec::new();
@@ -635,6 +860,13 @@ for Server
 
         debug!("accept-raw-post-request: uri: {}", uri);
 
+        // Enforce the quotas of the instance
+        let _guard = basics.meter.begin_call(body.len())
+            .map_err(|err| {
+                let msg = format!("Quota exceeded - {}", err).as_bytes().to_vec();
+                (msg, StatusCode::TOO_MANY_REQUESTS)
+            })?;
+
         // Build an environment from the query string
         let mut env = Environment::default();
         if let Some(query) = uri.query() {
@@ -667,6 +899,7 @@ for Server
         // Read all the data
         let ret = read_to_end(ret_rx).await;
         debug!("eval returned {} bytes", ret.len());
+        basics.meter.record_download(ret.len());
         
         // Convert the error code to a status code
         match exit_code {
@@ -679,7 +912,7 @@ for Server
     }
 }
 
-async fn read_to_end(mut rx: mpsc::Receiver<FdMsg>) -> Vec<u8>
+pub(crate) async fn read_to_end(mut rx: mpsc::Receiver<FdMsg>) -> Vec<u8>
 {
     let mut ret = Vec::new();
     while let Some(msg) = rx.recv().await {
@@ -693,6 +926,7 @@ async fn read_to_end(mut rx: mpsc::Receiver<FdMsg>) -> Vec<u8>
     ret
 }
 
+#[derive(Clone)]
 struct SessionFactory
 {
     db_url: url::Url,
@@ -703,36 +937,43 @@ struct SessionFactory
     edge_session_cache: Arc<tokio::sync::Mutex<Option<AteSessionGroup>>>,
 }
 
+impl SessionFactory
+{
+    /// Session that has the rights of the instance authority
+    async fn edge_session(&self) -> Result<AteSessionGroup, AteError>
+    {
+        let mut edge_session_cache = self.edge_session_cache.lock().await;
+        if edge_session_cache.is_none() {
+            // First we need to get the edge session that has the rights to
+            // access this domain
+            let path = shellexpand::tilde(self.token_path.as_str()).to_string();
+            let session = if let Ok(token) = std::fs::read_to_string(path) {
+                b64_to_session(token)
+            } else {
+                let err: wasmer_auth::error::GatherError = wasmer_auth::error::GatherErrorKind::NoMasterKey.into();
+                return Err(err.into());
+            };
+
+            // Now we gather the rights to the instance domain that is capable of running these instances
+            let edge_session = impersonate_command(
+                &self.registry,
+                self.instance_authority.clone(),
+                session.clone_inner(),
+                self.auth_url.clone(),
+            ).await?;
+            edge_session_cache.replace(edge_session);
+        }
+        Ok(edge_session_cache.clone().unwrap())
+    }
+}
+
 #[async_trait]
 impl RepositorySessionFactory
 for SessionFactory
 {
     async fn create(&self, sni: String, key: ChainKey) -> Result<AteSessionType, AteError>
     {
-        let edge_session = {
-            let mut edge_session_cache = self.edge_session_cache.lock().await;
-            if edge_session_cache.is_none() {
-                // First we need to get the edge session that has the rights to
-                // access this domain
-                let path = shellexpand::tilde(self.token_path.as_str()).to_string();
-                let session = if let Ok(token) = std::fs::read_to_string(path) {
-                    b64_to_session(token)
-                } else {
-                    let err: wasmer_auth::error::GatherError = wasmer_auth::error::GatherErrorKind::NoMasterKey.into();
-                    return Err(err.into());
-                };
-
-                // Now we gather the rights to the instance domain that is capable of running these instances
-                let edge_session = impersonate_command(
-                    &self.registry,
-                    self.instance_authority.clone(),
-                    session.clone_inner(),
-                    self.auth_url.clone(),
-                ).await?;
-                edge_session_cache.replace(edge_session);
-            }
-            edge_session_cache.clone().unwrap()
-        };
+        let edge_session = self.edge_session().await?;
 
         // Now we read the chain of trust and attempt to get the master authority object
         let chain = self.registry.open(&self.db_url, &key, false).await?;
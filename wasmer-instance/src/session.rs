@@ -2,6 +2,7 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::collections::HashMap;
+use std::num::NonZeroU32;
 use tokio::sync::Mutex as AsyncMutex;
 use ate::prelude::*;
 use ate::comms::*;
@@ -27,6 +28,7 @@ use wasmer_os::environment::Environment;
 use wasmer_os::api::System;
 use wasmer_os::api::SystemAbiExt;
 use wasmer_os::common::MAX_MPSC;
+use wasmer_os::err::ERR_ETIMEDOUT;
 use wasmer_os::fd::WeakFd;
 use wasmer_os::api::AsyncResult;
 use wasmer_os::fd::Fd;
@@ -35,6 +37,9 @@ use wasmer_os::grammar::ast::Redirect;
 use super::handler::SessionHandler;
 use super::handler::SessionTx;
 use super::server::SessionBasics;
+use super::limits::CallGuard;
+use super::limits::InstanceMeter;
+use super::limits::wall_time_elapsed;
 
 pub struct Session
 {
@@ -96,6 +101,23 @@ impl Session
             basics.reactor.clone(),
         );
 
+        // All the processes started by this session are subject to the
+        // limits of the instance
+        console.set_limits(basics.meter.process_limits());
+
+        // Secrets are passed to all the processes as environment variables
+        // (their values are redacted from anything that is logged)
+        match basics.service_instance.secrets.iter().await {
+            Ok(secrets) => {
+                for secret in secrets {
+                    console.set_secret(secret.name.as_str(), secret.value.clone());
+                }
+            }
+            Err(err) => {
+                warn!("failed to load the secrets for instance ({}) - {}", id_str, err);
+            }
+        }
+
         // If its the first init
         if first_init {
             console.init().await;
@@ -135,7 +157,12 @@ impl Session
         // Create the job and context
         let exec_factory = self.console.exec_factory();
         let job = self.console.new_job().await?;
-        let ctx = exec_factory.create_context(self.console.new_spawn_context(&job));
+        let mut spawn = self.console.new_spawn_context(&job);
+
+        // Processes behind a bus factory serve many calls thus the wall time
+        // is instead enforced on each of the calls
+        spawn.limits.max_wall_time = None;
+        let ctx = exec_factory.create_context(spawn);
         let multiplexer = self.basics.multiplexer.clone();
 
         // Create the process factory that used by this process to create sub-processes
@@ -265,7 +292,13 @@ impl Session
         ctx.stdin = stdin;
         ctx.stdout = stdout;
         ctx.stderr = stderr;
-        ctx.env = env;
+        // The variables supplied are layered over the environment of the
+        // console (which holds the secrets of the instance)
+        for (key, val) in env.iter() {
+            if let Some(var_eq) = val.var_eq.clone() {
+                ctx.env.set_vareq_with_key(key.clone(), var_eq);
+            }
+        }
         ctx.extra_args = args;
         ctx.extra_redirects = redirects;
         let exec = self.console.exec_factory();
@@ -313,6 +346,7 @@ impl Session
             sys: System::default(),
             tx_reply,
             handle: call.handle.into(),
+            meter: self.basics.meter.clone(),
         };
         let feeder = this_callback.clone();
 
@@ -330,6 +364,17 @@ impl Session
             return Ok(());
         }
 
+        // Enforce the quotas of the instance
+        let guard = match self.basics.meter.begin_call(request.len()) {
+            Ok(a) => a,
+            Err(err) => {
+                warn!("call to {}@{} refused - {}", call.binary, self.hello_instance.chain, err);
+                this_callback.error(BusError::Aborted);
+                return Ok(());
+            }
+        };
+        let max_wall_time = self.basics.meter.max_wall_time();
+
         // Create the context
         let caller_ctx = WasmCallerContext::default();
 
@@ -374,6 +419,11 @@ impl Session
                     _ = abort_rx.recv() => {
                         Err(BusError::Aborted)
                     }
+                    _ = wall_time_elapsed(max_wall_time) => {
+                        debug!("call exceeded its wall time");
+                        caller_ctx.terminate(NonZeroU32::new(ERR_ETIMEDOUT).unwrap());
+                        Err(BusError::Aborted)
+                    }
                 }
             })
         };
@@ -385,6 +435,7 @@ impl Session
             result,
             sessions,
             _abort_tx: abort_tx,
+            _guard: guard,
         });
         Ok(())
     }
@@ -396,6 +447,7 @@ pub struct SessionInvocation
     result: AsyncResult<Result<InvokeResult, BusError>>,
     sessions: Arc<Mutex<HashMap<CallHandle, Box<dyn bus::Session>>>>,
     _abort_tx: mpsc::Sender<()>,
+    _guard: CallGuard,
 }
 
 #[derive(Default, Clone)]
@@ -443,6 +495,7 @@ pub struct SessionFeeder {
     sys: System,
     tx_reply: mpsc::Sender<InstanceReply>,
     handle: CallHandle,
+    meter: Arc<InstanceMeter>,
 }
 
 impl SessionFeeder {
@@ -455,6 +508,7 @@ impl BusStatelessFeeder
 for SessionFeeder {
     fn feed_bytes(&self, format: SerializationFormat, data: Vec<u8>) {
         trace!("feed-bytes(handle={}, data={} bytes)", self.handle, data.len());
+        self.meter.record_download(data.len());
         self.send(InstanceReply::FeedBytes {
             handle: self.handle,
             format,
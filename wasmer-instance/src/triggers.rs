@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use ate::prelude::*;
+use ate::comms::HelloMetadata;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::*;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, instrument, span, trace, warn, Level};
+use wasmer_deploy_cli::model::InstanceHello;
+use wasmer_deploy_cli::model::InstanceTrigger;
+use wasmer_deploy_cli::model::ServiceInstance;
+use wasmer_deploy_cli::model::TriggerEvent;
+use wasmer_deploy_cli::model::TriggerKind;
+use wasmer_deploy_cli::model::TriggerOutcome;
+use wasmer_deploy_cli::model::TriggerRun;
+use wasmer_deploy_cli::model::INSTANCE_ROOT_ID;
+use wasmer_deploy_cli::model::MAX_TRIGGER_RUNS;
+use wasmer_ssh::wasmer_os;
+use wasmer_os::api::ConsoleRect;
+use wasmer_os::api::SystemAbiExt;
+use wasmer_os::environment::Environment;
+use wasmer_os::fd::FdFlag;
+use wasmer_os::fd::FdMsg;
+use wasmer_os::pipe::pipe_in;
+use wasmer_os::pipe::pipe_out;
+use wasmer_os::pipe::ReceiverMode;
+
+use crate::fixed_reader::FixedReader;
+use crate::server::Server;
+use crate::session::Session;
+
+/// How often the triggers of an armed instance are reloaded from its chain
+const TRIGGER_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum number of bytes of stderr that are recorded against a failed run
+const MAX_RUN_ERROR_LEN: usize = 1024;
+
+/// Longest amount of time to wait between the retries of a failed run
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Maximum number of webhook runs that may be waiting to start
+const MAX_QUEUED_RUNS: usize = 1000;
+
+/// Collection on the operator chain that remembers which instances have
+/// triggers so they can be armed again when the server starts
+const ARMED_INSTANCES_ID: u64 = 11823569240781127463u64;
+
+/// Instance that has triggers armed (stored on the operator chain)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ArmedInstance {
+    chain: String,
+}
+
+fn armed_instance_key(chain: &ChainKey) -> PrimaryKey {
+    PrimaryKey::from(format!("armed-triggers://{}", chain))
+}
+
+/// Keeps track of which instances have their triggers armed on this node
+/// (an instance is armed when the server starts or when it is first loaded)
+pub struct TriggerArming {
+    armed: Mutex<HashSet<ChainKey>>,
+    tx: mpsc::Sender<ChainKey>,
+    rx: Mutex<Option<mpsc::Receiver<ChainKey>>>,
+    run_tx: mpsc::Sender<(ChainKey, TriggerInvocation)>,
+    run_rx: Mutex<Option<mpsc::Receiver<(ChainKey, TriggerInvocation)>>>,
+}
+
+impl TriggerArming {
+    pub fn new() -> TriggerArming {
+        let (tx, rx) = mpsc::channel(1000);
+        let (run_tx, run_rx) = mpsc::channel(MAX_QUEUED_RUNS);
+        TriggerArming {
+            armed: Mutex::new(HashSet::new()),
+            tx,
+            rx: Mutex::new(Some(rx)),
+            run_tx,
+            run_rx: Mutex::new(Some(run_rx)),
+        }
+    }
+
+    pub fn arm(&self, key: &ChainKey) {
+        let mut guard = self.armed.lock().unwrap();
+        if guard.insert(key.clone()) {
+            if let Err(err) = self.tx.try_send(key.clone()) {
+                warn!("failed to arm the triggers of {} - {}", key, err);
+                guard.remove(key);
+            }
+        }
+    }
+
+    pub fn disarm(&self, key: &ChainKey) {
+        let mut guard = self.armed.lock().unwrap();
+        guard.remove(key);
+    }
+
+    pub fn take_receiver(&self) -> Option<mpsc::Receiver<ChainKey>> {
+        let mut guard = self.rx.lock().unwrap();
+        guard.take()
+    }
+
+    /// Queues a trigger to be run in the background, returning false if
+    /// too many runs are already waiting
+    pub fn queue_run(&self, chain: ChainKey, invocation: TriggerInvocation) -> bool {
+        self.run_tx.try_send((chain, invocation)).is_ok()
+    }
+
+    pub fn take_run_receiver(&self) -> Option<mpsc::Receiver<(ChainKey, TriggerInvocation)>> {
+        let mut guard = self.run_rx.lock().unwrap();
+        guard.take()
+    }
+}
+
+/// Arms the triggers of every instance that had them armed before
+pub async fn arm_known_instances(server: &Server) -> Result<(), AteError>
+{
+    let ledger = server.usage_ledger().await?;
+    let dio = ledger.dio().await;
+    let armed = dio.children_ext::<ArmedInstance>(PrimaryKey::from(ARMED_INSTANCES_ID), 0, true, true).await?;
+    debug!("arming the triggers of {} known instances", armed.len());
+    for instance in armed {
+        server.triggers.arm(&ChainKey::from(instance.chain.clone()));
+    }
+    Ok(())
+}
+
+async fn remember_armed(server: &Server, chain: &ChainKey) -> Result<(), AteError>
+{
+    let ledger = server.usage_ledger().await?;
+    let dio = ledger.dio_mut().await;
+    let key = armed_instance_key(chain);
+    if dio.exists(&key).await {
+        return Ok(());
+    }
+    let mut armed = dio.store_with_key(ArmedInstance { chain: chain.to_string() }, key)?;
+    armed.auth_mut().write = WriteOption::Specific(ledger.write_key().hash());
+    armed.attach_orphaned(&PrimaryKey::from(ARMED_INSTANCES_ID))?;
+    dio.commit().await?;
+    Ok(())
+}
+
+async fn forget_armed(server: &Server, chain: &ChainKey) -> Result<(), AteError>
+{
+    let ledger = server.usage_ledger().await?;
+    let dio = ledger.dio_mut().await;
+    let key = armed_instance_key(chain);
+    if dio.exists(&key).await {
+        dio.delete(&key).await?;
+        dio.commit().await?;
+    }
+    Ok(())
+}
+
+/// Request to run a trigger once
+pub struct TriggerInvocation {
+    /// Primary key of the trigger that will be run
+    pub trigger: PrimaryKey,
+    /// Reason the trigger fired (recorded in the run history)
+    pub cause: String,
+    /// Data passed to the binary on stdin
+    pub stdin: Vec<u8>,
+    /// Runs that carry a claim are skipped by nodes that can already see a
+    /// run recorded under this key, however the check is not atomic so nodes
+    /// that fire at the same moment may both execute it (at-least-once)
+    pub claim: Option<PrimaryKey>,
+}
+
+/// Watches the triggers of an instance and fires them on their schedule or
+/// when events are written to the chain, until the instance has no triggers
+pub async fn watch_triggers(server: Arc<Server>, chain: ChainKey)
+{
+    debug!("arming triggers for {}", chain);
+    let mut schedules: HashMap<PrimaryKey, Option<DateTime<Utc>>> = HashMap::new();
+    let mut listeners: HashMap<PrimaryKey, watch::Sender<bool>> = HashMap::new();
+    let mut remembered = false;
+
+    loop {
+        let triggers = match load_triggers(&server, &chain).await {
+            Ok(a) => a,
+            Err(err) => {
+                warn!("failed to load the triggers of {} - {}", chain, err);
+                break;
+            }
+        };
+        if triggers.is_empty() {
+            if let Err(err) = forget_armed(&server, &chain).await {
+                warn!("failed to forget the triggers of {} - {}", chain, err);
+            }
+            break;
+        }
+        if remembered == false {
+            match remember_armed(&server, &chain).await {
+                Ok(()) => remembered = true,
+                Err(err) => warn!("failed to remember the triggers of {} - {}", chain, err),
+            }
+        }
+
+        // Listeners that stopped (their receiver is gone) are started again
+        listeners.retain(|_, exit| exit.is_closed() == false);
+
+        let now = Utc::now();
+        let mut active = HashSet::new();
+        for (key, trigger) in triggers {
+            if trigger.enabled == false {
+                continue;
+            }
+            active.insert(key.clone());
+
+            match &trigger.kind {
+                TriggerKind::Schedule(schedule) => {
+                    let next = schedules
+                        .entry(key.clone())
+                        .or_insert_with(|| schedule.next_after(now));
+                    if let Some(due) = next.clone() {
+                        if due <= now {
+                            *next = schedule.next_after(due);
+
+                            let invocation = TriggerInvocation {
+                                trigger: key.clone(),
+                                cause: format!("schedule {}", due.format("%Y-%m-%d %H:%M")),
+                                stdin: Vec::new(),
+                                claim: Some(PrimaryKey::from(format!("trigger-run://{}/{}", key, due.timestamp()))),
+                            };
+                            let server = server.clone();
+                            let chain = chain.clone();
+                            server.system.clone().fork_shared(move || async move {
+                                if let Err(err) = run_trigger(&server, &chain, invocation).await {
+                                    warn!("scheduled trigger failed on {} - {}", chain, err);
+                                }
+                            });
+                        }
+                    }
+                }
+                TriggerKind::ChainEvent => {
+                    if listeners.contains_key(&key) == false {
+                        let (exit_tx, exit_rx) = watch::channel(false);
+                        listeners.insert(key.clone(), exit_tx);
+
+                        let server = server.clone();
+                        let chain = chain.clone();
+                        server.system.clone().fork_shared(move || async move {
+                            if let Err(err) = listen_events(&server, &chain, key, exit_rx).await {
+                                warn!("chain trigger stopped listening on {} - {}", chain, err);
+                            }
+                        });
+                    }
+                }
+                TriggerKind::Webhook { .. } => { }
+            }
+        }
+
+        // Triggers that were removed or disabled are stopped (dropping the
+        // exit sender will stop the listener)
+        schedules.retain(|k, _| active.contains(k));
+        listeners.retain(|k, _| active.contains(k));
+
+        // Wait until the next schedule is due or its time to reload
+        let mut wait = TRIGGER_REFRESH_INTERVAL;
+        for next in schedules.values().filter_map(|a| a.clone()) {
+            let until = (next - Utc::now()).to_std().unwrap_or_default();
+            wait = wait.min(until);
+        }
+        tokio::time::sleep(wait).await;
+    }
+
+    debug!("disarming triggers for {}", chain);
+    server.triggers.disarm(&chain);
+}
+
+async fn load_triggers(server: &Server, chain: &ChainKey) -> Result<Vec<(PrimaryKey, InstanceTrigger)>, CommsError>
+{
+    let accessor = server.repo.get_accessor(chain, server.instance_authority.as_str()).await
+        .map_err(|err| CommsErrorKind::InternalError(err.to_string()))?;
+    let service_instance = accessor.dio.load::<ServiceInstance>(&PrimaryKey::from(INSTANCE_ROOT_ID)).await?;
+    let ret = service_instance.triggers
+        .iter()
+        .await?
+        .map(|t| (t.key().clone(), t.take()))
+        .collect();
+    Ok(ret)
+}
+
+/// Processes the events written under a chain trigger (each event is
+/// consumed by one node at a time and only removed once its run succeeds)
+async fn listen_events(server: &Server, chain: &ChainKey, key: PrimaryKey, mut exit: watch::Receiver<bool>) -> Result<(), CommsError>
+{
+    let accessor = server.repo.get_accessor(chain, server.instance_authority.as_str()).await
+        .map_err(|err| CommsErrorKind::InternalError(err.to_string()))?;
+    let trigger = accessor.dio.load::<InstanceTrigger>(&key).await?;
+    let mut bus = trigger.events
+        .bus()
+        .await
+        .map_err(|err| CommsErrorKind::InternalError(err.to_string()))?;
+
+    // Events that are still queued (because their run failed or nobody was
+    // listening when they were written) are processed first
+    let queued = trigger.events
+        .iter()
+        .await?
+        .map(|e| e.key().clone())
+        .collect::<Vec<_>>();
+    for evt_key in queued {
+        let dio = accessor.dio.clone().as_mut().await;
+        let mut evt = match dio.load::<TriggerEvent>(&evt_key).await {
+            Ok(a) => a,
+            Err(err) => {
+                debug!("queued event ({}) is gone - {}", evt_key, err);
+                continue;
+            }
+        };
+        let locked = evt.try_lock_then_delete()
+            .await
+            .map_err(|err| CommsErrorKind::InternalError(err.to_string()))?;
+        if locked {
+            consume_event(server, chain, &key, dio, evt).await;
+        }
+    }
+
+    loop {
+        let dio = accessor.dio.clone().as_mut().await;
+        let evt = tokio::select! {
+            evt = bus.process(&dio) => {
+                evt.map_err(|err| CommsErrorKind::InternalError(err.to_string()))?
+            }
+            _ = exit.changed() => {
+                break;
+            }
+        };
+        consume_event(server, chain, &key, dio, evt).await;
+    }
+    Ok(())
+}
+
+/// Runs a chain trigger for one of its events, the event is removed from
+/// the queue when the run succeeds or otherwise left there to be retried
+async fn consume_event(server: &Server, chain: &ChainKey, trigger: &PrimaryKey, dio: Arc<DioMut>, evt: DaoMut<TriggerEvent>)
+{
+    let evt_key = evt.key().clone();
+    let ret = run_trigger(server, chain, TriggerInvocation {
+        trigger: trigger.clone(),
+        cause: format!("chain event {}", evt.when.format("%Y-%m-%d %H:%M:%S")),
+        stdin: evt.data.clone(),
+        claim: None,
+    }).await;
+
+    let succeeded = match ret {
+        Ok(Some((TriggerOutcome::Succeeded, _))) => true,
+        Ok(Some((outcome, _))) => {
+            warn!("chain trigger on {} did not succeed - {}", chain, outcome);
+            false
+        }
+        Ok(None) => true,
+        Err(err) => {
+            warn!("chain trigger failed on {} - {}", chain, err);
+            false
+        }
+    };
+
+    if succeeded {
+        let ret = match evt.delete() {
+            Ok(()) => dio.commit().await.map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = ret {
+            warn!("failed to remove the event ({}) on {} - {}", evt_key, chain, err);
+        }
+    } else {
+        // The event stays queued
+        dio.cancel();
+    }
+
+    // Release the lock that was taken when the event was received
+    if let Err(err) = dio.unlock(evt_key.clone()).await {
+        warn!("failed to release the event ({}) on {} - {}", evt_key, chain, err);
+    }
+}
+
+/// Runs a trigger (retrying it if it fails) and records the run in its
+/// history, returning the outcome and the output of the final attempt
+/// (or nothing if another node already claimed the run - claims only
+/// narrow the window for duplicates so triggers must be idempotent)
+pub async fn run_trigger(server: &Server, chain: &ChainKey, invocation: TriggerInvocation) -> Result<Option<(TriggerOutcome, Vec<u8>)>, CommsError>
+{
+    let accessor = server.repo.get_accessor(chain, server.instance_authority.as_str()).await
+        .map_err(|err| CommsErrorKind::InternalError(err.to_string()))?;
+    let dio = accessor.dio.clone().as_mut().await;
+
+    // Record the run (which also claims it)
+    let mut trigger = dio.load::<InstanceTrigger>(&invocation.trigger).await?;
+    let run = TriggerRun {
+        when: Utc::now(),
+        cause: invocation.cause.clone(),
+        attempts: 0,
+        duration_ms: 0,
+        outcome: TriggerOutcome::Running,
+    };
+    let mut run = match invocation.claim {
+        Some(claim) => {
+            if dio.exists(&claim).await {
+                debug!("trigger run ({}) was already claimed", claim);
+                return Ok(None);
+            }
+            trigger.as_mut().runs.push_with_key(run, claim)?
+        }
+        None => trigger.as_mut().runs.push(run)?
+    };
+    dio.commit().await?;
+    debug!("running trigger ({}) on {} - {}", trigger.name, chain, invocation.cause);
+
+    // Invoke the binary until it succeeds or we run out of retries
+    let started = Instant::now();
+    let mut delay = Duration::from_millis(trigger.retry_delay_ms);
+    let mut attempts = 0u32;
+    let (outcome, output) = loop {
+        attempts += 1;
+        let (outcome, output) = invoke_trigger(server, chain, trigger.deref(), attempts, invocation.stdin.clone()).await;
+        if outcome == TriggerOutcome::Succeeded || attempts > trigger.max_retries {
+            break (outcome, output);
+        }
+        debug!("trigger ({}) attempt {} failed - {}", trigger.name, attempts, outcome);
+        tokio::time::sleep(delay).await;
+        delay = delay.checked_mul(2).unwrap_or(MAX_RETRY_DELAY).min(MAX_RETRY_DELAY);
+    };
+
+    // Record the outcome of the run
+    {
+        let mut run = run.as_mut();
+        run.attempts = attempts;
+        run.duration_ms = started.elapsed().as_millis() as u64;
+        run.outcome = outcome.clone();
+    }
+    trim_history(&mut trigger).await?;
+    dio.commit().await?;
+
+    Ok(Some((outcome, output)))
+}
+
+/// Makes a single attempt at invoking the binary of a trigger
+async fn invoke_trigger(server: &Server, chain: &ChainKey, trigger: &InstanceTrigger, attempt: u32, request: Vec<u8>) -> (TriggerOutcome, Vec<u8>)
+{
+    let failed = |exit_code: Option<u32>, error: String| {
+        (TriggerOutcome::Failed { exit_code, error }, Vec::new())
+    };
+
+    let (basics, first_init) = match server.get_or_create_session_basics(chain.clone()).await {
+        Ok(a) => a,
+        Err(err) => { return failed(None, err.to_string()); }
+    };
+
+    // Triggers are subject to the same quotas as calls
+    let _guard = match basics.meter.begin_call(request.len()) {
+        Ok(a) => a,
+        Err(err) => { return failed(None, format!("quota exceeded - {}", err)); }
+    };
+
+    // Triggers run without a client so the session is built from
+    // a hello that represents the instance itself
+    let hello = HelloMetadata {
+        client_id: NodeId::generate_client_id(),
+        server_id: NodeId::Unknown,
+        path: format!("/trigger/{}/{}", chain, trigger.name),
+        encryption: None,
+        wire_format: SerializationFormat::Json,
+    };
+    let hello_instance = InstanceHello {
+        access_token: String::new(),
+        chain: chain.clone(),
+    };
+    let mut session = Session::new(
+        Box::new(FixedReader::new(Vec::new())),
+        None,
+        hello,
+        hello_instance,
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        None,
+        Arc::new(Mutex::new(ConsoleRect { cols: 80, rows: 25 })),
+        server.engine.clone(),
+        server.compiler.clone(),
+        basics.clone(),
+        first_init
+    ).await;
+
+    let mut env = Environment::default();
+    env.set_var("TRIGGER_NAME", trigger.name.clone());
+    env.set_var("TRIGGER_KIND", trigger.kind.to_string());
+    env.set_var("TRIGGER_ATTEMPT", attempt.to_string());
+
+    let (stdin, body_tx) = pipe_in(ReceiverMode::Stream, FdFlag::Stdin(false));
+    let _ = body_tx.send(FdMsg::Data { data: request, flag: FdFlag::Stdin(false) }).await;
+    let _ = body_tx.send(FdMsg::Data { data: Vec::new(), flag: FdFlag::Stdin(false) }).await;
+    drop(body_tx);
+
+    let (mut stdout, ret_rx) = pipe_out(FdFlag::Stdout(false));
+    let (mut stderr, err_rx) = pipe_out(FdFlag::Stdout(false));
+    stdout.set_ignore_flush(true);
+    stderr.set_ignore_flush(true);
+
+    let exit_code = session.eval(trigger.binary.clone(), env, trigger.args.clone(), Vec::new(), stdin, stdout, stderr)
+        .await
+        .map_err(|err| err.to_string());
+    drop(session);
+
+    let output = crate::server::read_to_end(ret_rx).await;
+    basics.meter.record_download(output.len());
+
+    match exit_code {
+        Ok(0) => (TriggerOutcome::Succeeded, output),
+        Ok(code) => {
+            let mut error = crate::server::read_to_end(err_rx).await;
+            error.truncate(MAX_RUN_ERROR_LEN);
+            failed(Some(code), String::from_utf8_lossy(&error[..]).trim().to_string())
+        }
+        Err(err) => failed(None, err),
+    }
+}
+
+/// Removes the oldest runs of a trigger so that its history does not grow forever
+async fn trim_history(trigger: &mut DaoMut<InstanceTrigger>) -> Result<(), CommsError>
+{
+    let mut runs = trigger.as_mut().runs.iter_mut().await?.collect::<Vec<_>>();
+    if runs.len() <= MAX_TRIGGER_RUNS {
+        return Ok(());
+    }
+    runs.sort_by(|a, b| a.when.cmp(&b.when));
+
+    let excess = runs.len() - MAX_TRIGGER_RUNS;
+    for run in runs.into_iter().take(excess) {
+        run.delete()?;
+    }
+    Ok(())
+}
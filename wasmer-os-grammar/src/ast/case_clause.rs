@@ -0,0 +1,13 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub struct CaseClause<'a> {
+    pub word: &'a str,
+    pub items: Vec<CaseItem<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CaseItem<'a> {
+    pub patterns: Vec<&'a str>,
+    pub body: Option<CompleteCommand<'a>>,
+}
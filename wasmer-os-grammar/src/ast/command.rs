@@ -8,12 +8,19 @@ pub enum Command<'a> {
         args: Vec<Arg<'a>>,
         redirect: Vec<Redirect>,
     },
+    Compound {
+        body: Compound<'a>,
+        redirect: Vec<Redirect>,
+    },
+    Function(FunctionDef<'a>),
 }
 
 impl<'a> Command<'a> {
     pub fn redirect(&mut self) -> &mut Vec<Redirect> {
         match self {
             Command::Simple { redirect, .. } => redirect,
+            Command::Compound { redirect, .. } => redirect,
+            Command::Function(f) => f.body.redirect(),
         }
     }
 }
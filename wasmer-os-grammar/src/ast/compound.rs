@@ -0,0 +1,13 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub enum Compound<'a> {
+    /// { list; }
+    BraceGroup(CompleteCommand<'a>),
+    /// ( list )
+    Subshell(CompleteCommand<'a>),
+    If(IfClause<'a>),
+    For(ForClause<'a>),
+    While(WhileClause<'a>),
+    Case(CaseClause<'a>),
+}
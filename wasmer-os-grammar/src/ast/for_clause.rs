@@ -0,0 +1,11 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub struct ForClause<'a> {
+    pub name: &'a str,
+    /// Words are kept exactly as they were written (including quotes) as
+    /// they are split into fields when evaluated, when there is no `in`
+    /// the loop runs over the positional parameters
+    pub words: Option<Vec<&'a str>>,
+    pub body: CompleteCommand<'a>,
+}
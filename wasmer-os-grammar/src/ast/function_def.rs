@@ -0,0 +1,10 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub struct FunctionDef<'a> {
+    pub name: &'a str,
+    pub body: Box<Command<'a>>,
+    /// Text of the body as it appeared in the script (functions outlive the
+    /// script that defined them so they are stored and parsed again when called)
+    pub source: &'a str,
+}
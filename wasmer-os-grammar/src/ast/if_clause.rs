@@ -0,0 +1,26 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub struct IfClause<'a> {
+    /// The `if` and `elif` branches in the order they are tested
+    pub branches: Vec<(CompleteCommand<'a>, CompleteCommand<'a>)>,
+    pub otherwise: Option<CompleteCommand<'a>>,
+}
+
+impl<'a> IfClause<'a> {
+    pub fn new(condition: CompleteCommand<'a>, body: CompleteCommand<'a>, rest: Option<IfClause<'a>>) -> IfClause<'a> {
+        let mut ret = rest.unwrap_or_else(|| IfClause {
+            branches: Vec::new(),
+            otherwise: None,
+        });
+        ret.branches.insert(0, (condition, body));
+        ret
+    }
+
+    pub fn otherwise(body: CompleteCommand<'a>) -> IfClause<'a> {
+        IfClause {
+            branches: Vec::new(),
+            otherwise: Some(body),
+        }
+    }
+}
@@ -1,19 +1,31 @@
 mod and_or;
 mod arg;
+mod case_clause;
 mod command;
 mod complete_command;
 mod complete_commands;
+mod compound;
+mod for_clause;
+mod function_def;
+mod if_clause;
 mod pipeline;
 mod program;
 mod redirect;
 mod term_op;
+mod while_clause;
 
 pub use and_or::*;
 pub use arg::*;
+pub use case_clause::*;
 pub use command::*;
 pub use complete_command::*;
 pub use complete_commands::*;
+pub use compound::*;
+pub use for_clause::*;
+pub use function_def::*;
+pub use if_clause::*;
 pub use pipeline::*;
 pub use program::*;
 pub use redirect::*;
 pub use term_op::*;
+pub use while_clause::*;
@@ -16,6 +16,7 @@ pub enum RedirectionType {
     APPEND,  // fd >> fname
     TOFD,    // fd >& dupfd
     FROMFD,  // fd <& dupfd
+    HEREDOC, // fd << document (the filename holds the text of the document)
 }
 
 impl FromStr for RedirectionType {
@@ -30,6 +31,7 @@ impl FromStr for RedirectionType {
             ">>" => Ok(RedirectionType::APPEND),
             ">&" => Ok(RedirectionType::TOFD),
             "<&" => Ok(RedirectionType::FROMFD),
+            "<<" => Ok(RedirectionType::HEREDOC),
             _ => Err(()),
         }
     }
@@ -41,6 +43,7 @@ impl RedirectionType {
             RedirectionType::FROM => true,
             RedirectionType::FROMTO => true,
             RedirectionType::FROMFD => true,
+            RedirectionType::HEREDOC => true,
             _ => false,
         }
     }
@@ -71,6 +74,13 @@ impl RedirectionType {
         }
     }
 
+    pub fn heredoc(&self) -> bool {
+        match self {
+            RedirectionType::HEREDOC => true,
+            _ => false,
+        }
+    }
+
     pub fn clobber(&self) -> bool {
         match self {
             RedirectionType::CLOBBER => true,
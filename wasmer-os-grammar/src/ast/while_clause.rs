@@ -0,0 +1,9 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub struct WhileClause<'a> {
+    pub condition: CompleteCommand<'a>,
+    pub body: CompleteCommand<'a>,
+    /// `until` loops run while the condition fails
+    pub until: bool,
+}
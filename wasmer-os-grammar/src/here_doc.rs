@@ -0,0 +1,191 @@
+//! Here-documents can not be recognised by the lexer (the end of the document
+//! depends on a delimiter chosen by the script) thus before a script is parsed
+//! each document is removed from the lines that follow its operator and folded
+//! into the operator itself (`<<EOF` becomes `<<:` followed by the encoded text)
+//! so that the grammar sees it as a normal redirect.
+
+/// Folds all the here-documents in a script into the redirects that use them,
+/// returns `None` if a document is not terminated (i.e. more input is needed)
+pub fn inline_here_docs(script: &str) -> Option<String> {
+    if script.contains("<<") == false {
+        return Some(script.to_string());
+    }
+
+    let mut ret = String::with_capacity(script.len());
+    let mut quote = None;
+    let mut lines = script.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let docs = find_here_docs(line, &mut quote);
+        if docs.is_empty() {
+            ret.push_str(line);
+            continue;
+        }
+
+        // The documents follow the line in the same order as their operators
+        let mut last = 0usize;
+        for doc in docs {
+            let body = read_body(&mut lines, &doc)?;
+            ret.push_str(&line[last..doc.start]);
+            ret.push_str(encode(body.as_str(), doc.raw).as_str());
+            last = doc.end;
+        }
+        ret.push_str(&line[last..]);
+    }
+    Some(ret)
+}
+
+/// Decodes the text of a here-document that was folded into its redirect
+pub fn decode_here_doc(encoded: &str) -> String {
+    let encoded = encoded.strip_prefix(':').unwrap_or(encoded).as_bytes();
+    let mut ret = Vec::with_capacity(encoded.len());
+    let mut n = 0usize;
+    while n < encoded.len() {
+        let hex = match encoded[n] {
+            b'%' => encoded.get(n + 1..n + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok()),
+            _ => None,
+        };
+        match hex {
+            Some(b) => {
+                ret.push(b);
+                n += 3;
+            }
+            None => {
+                ret.push(encoded[n]);
+                n += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&ret[..]).to_string()
+}
+
+struct HereDoc {
+    start: usize,
+    end: usize,
+    delimiter: String,
+    strip_tabs: bool,
+    raw: bool,
+}
+
+fn find_here_docs(line: &str, quote: &mut Option<char>) -> Vec<HereDoc> {
+    let mut ret = Vec::new();
+    let bytes = line.as_bytes();
+    let mut n = 0usize;
+    while n < bytes.len() {
+        let c = bytes[n];
+        match *quote {
+            Some(q) => {
+                if c == b'\\' && q == '"' {
+                    n += 1;
+                } else if c == q as u8 {
+                    *quote = None;
+                }
+                n += 1;
+                continue;
+            }
+            None => {}
+        }
+        match c {
+            b'\\' => {
+                n += 2;
+                continue;
+            }
+            b'\'' | b'"' | b'`' => {
+                *quote = Some(c as char);
+                n += 1;
+                continue;
+            }
+            b'#' if n == 0 || bytes[n - 1].is_ascii_whitespace() => {
+                break;
+            }
+            b'<' if line[n..].starts_with("<<") && line[n..].starts_with("<<<") == false => {
+                let start = n;
+                n += 2;
+                let strip_tabs = bytes.get(n) == Some(&b'-');
+                if strip_tabs {
+                    n += 1;
+                }
+                while n < bytes.len() && (bytes[n] == b' ' || bytes[n] == b'\t') {
+                    n += 1;
+                }
+
+                // Quoting any part of the delimiter stops the document from being expanded
+                let mut delimiter = Vec::new();
+                let mut raw = false;
+                let mut delim_quote = None;
+                while n < bytes.len() {
+                    let c = bytes[n] as char;
+                    match delim_quote {
+                        Some(q) if c == q => delim_quote = None,
+                        Some(_) => delimiter.push(bytes[n]),
+                        None if c == '\'' || c == '"' => {
+                            raw = true;
+                            delim_quote = Some(c);
+                        }
+                        None if c == '\\' => raw = true,
+                        None if c.is_ascii_whitespace() || ";&|<>()".contains(c) => break,
+                        None => delimiter.push(bytes[n]),
+                    }
+                    n += 1;
+                }
+                if delimiter.is_empty() {
+                    continue;
+                }
+                ret.push(HereDoc {
+                    start,
+                    end: n,
+                    delimiter: String::from_utf8_lossy(&delimiter[..]).to_string(),
+                    strip_tabs,
+                    raw,
+                });
+                continue;
+            }
+            _ => {}
+        }
+        n += 1;
+    }
+    ret
+}
+
+fn read_body<'a>(lines: &mut impl Iterator<Item = &'a str>, doc: &HereDoc) -> Option<String> {
+    let mut body = String::new();
+    loop {
+        let line = lines.next()?;
+        let line = match doc.strip_tabs {
+            true => line.trim_start_matches('\t'),
+            false => line,
+        };
+        if line.trim_end_matches(|c| c == '\r' || c == '\n') == doc.delimiter {
+            return Some(body);
+        }
+        if line.ends_with('\n') == false {
+            return None;
+        }
+        body.push_str(line);
+    }
+}
+
+fn encode(body: &str, raw: bool) -> String {
+    let mut ret = String::with_capacity(body.len() + 3);
+    ret.push_str("<<:");
+    for c in body.chars() {
+        match c {
+            // Documents with a quoted delimiter are never expanded
+            '\\' | '$' | '`' if raw => {
+                ret.push('\\');
+                ret.push(c);
+            }
+            // Anything that would end the redirect token is escaped
+            c if c.is_whitespace() || "%;&|<>()".contains(c) => {
+                let mut buf = [0u8; 4];
+                for b in c.encode_utf8(&mut buf).as_bytes() {
+                    ret.push_str(format!("%{:02X}", b).as_str());
+                }
+            }
+            c => ret.push(c),
+        }
+    }
+    ret.push(' ');
+    ret
+}
@@ -8,6 +8,7 @@ lalrpop_mod!(
 );
 
 pub mod ast;
+pub mod here_doc;
 
 pub use grammar::*;
 pub use lalrpop_util::*;
@@ -0,0 +1,150 @@
+use wasmer_os_grammar::ast::*;
+use wasmer_os_grammar::here_doc::inline_here_docs;
+use wasmer_os_grammar::programParser;
+
+fn parse(script: &str) -> Vec<Command<'static>> {
+    let script: &'static str = Box::leak(inline_here_docs(script).expect("unterminated here-document").into_boxed_str());
+    let program = programParser::new().parse(script).expect("failed to parse");
+    program
+        .commands
+        .complete_commands
+        .into_iter()
+        .flat_map(|cc| cc.and_ors.into_iter())
+        .flat_map(|(_, and_or)| and_or.pipelines.into_iter())
+        .flat_map(|(_, pipeline)| pipeline.commands.into_iter())
+        .collect()
+}
+
+fn single(script: &str) -> Command<'static> {
+    let mut commands = parse(script);
+    assert_eq!(commands.len(), 1, "expected a single command");
+    commands.remove(0)
+}
+
+fn compound(script: &str) -> Compound<'static> {
+    match single(script) {
+        Command::Compound { body, .. } => body,
+        other => panic!("not a compound command - {:?}", other),
+    }
+}
+
+#[test]
+fn if_clause() {
+    match compound("if true; then echo a; elif false\nthen echo b\nelse echo c; fi") {
+        Compound::If(clause) => {
+            assert_eq!(clause.branches.len(), 2);
+            assert!(clause.otherwise.is_some());
+        }
+        other => panic!("not an if clause - {:?}", other),
+    }
+}
+
+#[test]
+fn for_clause() {
+    match compound("for i in a \"b c\" $(echo d); do echo $i; done") {
+        Compound::For(clause) => {
+            assert_eq!(clause.name, "i");
+            assert_eq!(clause.words, Some(vec!["a", "\"b c\"", "$(echo d)"]));
+        }
+        other => panic!("not a for clause - {:?}", other),
+    }
+    match compound("for arg\ndo\n  echo $arg\ndone") {
+        Compound::For(clause) => assert_eq!(clause.words, None),
+        other => panic!("not a for clause - {:?}", other),
+    }
+}
+
+#[test]
+fn while_and_until() {
+    match compound("while false\ndo\n  echo x\ndone > out.txt") {
+        Compound::While(clause) => assert_eq!(clause.until, false),
+        other => panic!("not a while clause - {:?}", other),
+    }
+    match single("until true; do :; done > out.txt") {
+        Command::Compound { body: Compound::While(clause), redirect } => {
+            assert_eq!(clause.until, true);
+            assert_eq!(redirect.len(), 1);
+            assert_eq!(redirect[0].filename, "out.txt");
+        }
+        other => panic!("not an until clause - {:?}", other),
+    }
+}
+
+#[test]
+fn case_clause() {
+    match compound("case $x in\n a|b) echo ab;;\n (*) echo other\nesac") {
+        Compound::Case(clause) => {
+            assert_eq!(clause.word, "$x");
+            assert_eq!(clause.items.len(), 2);
+            assert_eq!(clause.items[0].patterns, vec!["a", "b"]);
+            assert_eq!(clause.items[1].patterns, vec!["*"]);
+        }
+        other => panic!("not a case clause - {:?}", other),
+    }
+}
+
+#[test]
+fn groups_and_subshells() {
+    assert!(matches!(compound("{ echo a; echo b; }"), Compound::BraceGroup(_)));
+    assert!(matches!(compound("(cd /tmp; ls)"), Compound::Subshell(_)));
+}
+
+#[test]
+fn function_definition() {
+    let commands = parse("greet() {\n  echo hello $1\n}\ngreet world");
+    assert_eq!(commands.len(), 2);
+    match &commands[0] {
+        Command::Function(def) => {
+            assert_eq!(def.name, "greet");
+            assert_eq!(def.source, "{\n  echo hello $1\n}");
+        }
+        other => panic!("not a function - {:?}", other),
+    }
+}
+
+#[test]
+fn here_document() {
+    match single("cat <<EOF\nhello $x\n  a; b | c\nEOF\n") {
+        Command::Simple { redirect, .. } => {
+            assert_eq!(redirect[0].op, RedirectionType::HEREDOC);
+            assert_eq!(redirect[0].filename, "hello $x\n  a; b | c\n");
+        }
+        other => panic!("not a simple command - {:?}", other),
+    }
+    match single("cat <<-'EOF'\n\thello $x\n\tEOF\n") {
+        Command::Simple { redirect, .. } => {
+            assert_eq!(redirect[0].filename, "hello \\$x\n");
+        }
+        other => panic!("not a simple command - {:?}", other),
+    }
+    assert_eq!(inline_here_docs("cat <<EOF\nnot finished\n"), None);
+}
+
+#[test]
+fn negation_and_comments() {
+    let script: &'static str = "# comment\n! true && echo x # trailing\n";
+    let program = programParser::new().parse(script).unwrap();
+    let and_or = &program.commands.complete_commands[0].and_ors[0].1;
+    assert_eq!(and_or.pipelines[0].1.negated, true);
+    assert_eq!(and_or.pipelines[1].1.negated, false);
+}
+
+#[test]
+fn words_are_kept_as_written() {
+    match single("echo \"a b\" 'c' ~/x {a,b}.txt ${VAR:-d e} $(( (1 + 2) * 3 )) *.rs") {
+        Command::Simple { args, .. } => {
+            let args = args
+                .iter()
+                .map(|a| match a {
+                    Arg::Arg(a) => *a,
+                    Arg::Backquote(_) => panic!("unexpected backquote"),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                args,
+                vec!["\"a b\"", "'c'", "~/x", "{a,b}.txt", "${VAR:-d e}", "$(( (1 + 2) * 3 ))", "*.rs"]
+            );
+        }
+        other => panic!("not a simple command - {:?}", other),
+    }
+}
@@ -12,6 +12,8 @@ use std::collections::HashSet;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::cell::RefCell;
@@ -73,26 +75,196 @@ pub struct AliasConfig {
     pub envs: HashMap<String, String>,
 }
 
+/// Default amount of memory that compiled modules may hold before the least
+/// recently used ones are evicted
+pub const DEFAULT_MODULE_CACHE_MEMORY: u64 = 512 * 1024 * 1024;
+/// Default amount of disk space that compiled modules may use
+pub const DEFAULT_MODULE_CACHE_DISK: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Every compiled module stored on disk starts with these bytes followed by
+/// the SHA-256 of the (compressed) module that comes after it
+const MODULE_CACHE_MAGIC: &'static [u8; 8] = b"WASMOSC1";
+
+/// Size limits (in bytes) of the compiled module cache
+#[derive(Debug, Clone, Copy)]
+pub struct ModuleCacheLimits {
+    pub max_memory: u64,
+    pub max_disk: u64,
+}
+
+impl Default for ModuleCacheLimits {
+    fn default() -> ModuleCacheLimits {
+        ModuleCacheLimits {
+            max_memory: DEFAULT_MODULE_CACHE_MEMORY,
+            max_disk: DEFAULT_MODULE_CACHE_DISK,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModuleCacheStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub memory_evictions: u64,
+    pub disk_evictions: u64,
+    /// Modules on disk that failed their integrity check (and were removed)
+    pub corrupt: u64,
+    pub memory_entries: usize,
+    pub memory_bytes: u64,
+    pub disk_entries: usize,
+    pub disk_bytes: u64,
+    pub disk_enabled: bool,
+    pub limits: ModuleCacheLimits,
+}
+
+#[derive(Debug)]
+struct LruEntry {
+    size: u64,
+    last_used: u64,
+}
+
+/// Tracks the size and the last use of the cached modules so that the least
+/// recently used ones can be evicted once the cache grows too big
+#[derive(Debug, Default)]
+struct LruIndex {
+    entries: HashMap<String, LruEntry>,
+    total: u64,
+    tick: u64,
+}
+
+impl LruIndex {
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.last_used = self.tick;
+        }
+    }
+
+    fn insert(&mut self, key: &str, size: u64) {
+        self.remove(key);
+        self.tick += 1;
+        self.total += size;
+        self.entries.insert(
+            key.to_string(),
+            LruEntry {
+                size,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total -= entry.size;
+        }
+    }
+
+    /// Removes the least recently used entries until the cache fits within
+    /// its limit (the entry that was just added is always kept)
+    fn evict(&mut self, limit: u64, keep: &str) -> Vec<String> {
+        let mut ret = Vec::new();
+        while self.total > limit {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(key, _)| key.as_str() != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => {
+                    self.remove(key.as_str());
+                    ret.push(key);
+                }
+                None => break,
+            }
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    memory: LruIndex,
+    /// Modules in the cache directory (loaded the first time it is used)
+    disk: Option<LruIndex>,
+    stats: ModuleCacheStats,
+}
+
 #[derive(Debug)]
 pub struct CachedCompiledModules {
     #[cfg(feature = "sys")]
     modules: RwLock<HashMap<String, Module>>,
     cache_dir: Option<String>,
+    limits: ModuleCacheLimits,
+    state: Mutex<CacheState>,
 }
 
+// Modules are only cached per thread when they can not be shared between
+// threads, otherwise the shared cache is their only owner so that evicted
+// modules are actually released (idle threads would keep their copies)
+#[cfg(not(feature = "sys"))]
 thread_local! {
-    static THREAD_LOCAL_CACHED_MODULES: std::cell::RefCell<HashMap<String, Module>> 
-        = RefCell::new(HashMap::new());
+    static THREAD_LOCAL_CACHED_MODULES: std::cell::RefCell<(u64, HashMap<String, Module>)>
+        = RefCell::new((0, HashMap::new()));
+}
+
+/// Incremented whenever modules are evicted so that the thread local caches
+/// know to drop their references to them
+#[cfg(not(feature = "sys"))]
+static MODULE_CACHE_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(not(feature = "sys"))]
+fn thread_local_get(key: &str) -> Option<Module> {
+    let epoch = MODULE_CACHE_EPOCH.load(Ordering::Acquire);
+    THREAD_LOCAL_CACHED_MODULES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.0 != epoch {
+            cache.0 = epoch;
+            cache.1.clear();
+        }
+        cache.1.get(key).map(|m| m.clone())
+    })
+}
+
+#[cfg(not(feature = "sys"))]
+fn thread_local_set(key: String, module: Module) {
+    let epoch = MODULE_CACHE_EPOCH.load(Ordering::Acquire);
+    THREAD_LOCAL_CACHED_MODULES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.0 != epoch {
+            cache.0 = epoch;
+            cache.1.clear();
+        }
+        cache.1.insert(key, module);
+    });
+}
+
+#[cfg(not(feature = "sys"))]
+fn thread_local_remove(keys: &[String]) {
+    THREAD_LOCAL_CACHED_MODULES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        for key in keys {
+            cache.1.remove(key);
+        }
+    });
 }
 
 impl CachedCompiledModules
 {
     pub fn new(cache_dir: Option<String>) -> CachedCompiledModules {
+        Self::with_limits(cache_dir, ModuleCacheLimits::default())
+    }
+
+    pub fn with_limits(cache_dir: Option<String>, limits: ModuleCacheLimits) -> CachedCompiledModules {
         let cache_dir = cache_dir.map(|a| shellexpand::tilde(&a).to_string());
         CachedCompiledModules {
             #[cfg(feature = "sys")]
             modules: RwLock::new(HashMap::default()),
             cache_dir,
+            limits,
+            state: Mutex::new(CacheState::default()),
         }
     }
 
@@ -100,89 +272,265 @@ impl CachedCompiledModules
         let key = format!("{}-{}", data_hash, compiler);
         
         // fastest path
-        {
-            let module = THREAD_LOCAL_CACHED_MODULES.with(|cache| {
-                let cache = cache.borrow();
-                cache.get(&key).map(|m| m.clone())
-            });
-            if let Some(module) = module {
-                return Some(module);
-            }
+        #[cfg(not(feature = "sys"))]
+        if let Some(module) = thread_local_get(key.as_str()) {
+            let mut state = self.state.lock().unwrap();
+            state.memory.touch(key.as_str());
+            state.stats.memory_hits += 1;
+            return Some(module);
         }
 
         // fast path
         #[cfg(feature = "sys")]
         {
-            let cache = self.modules.read().await;
-            if let Some(module) = cache.get(&key) {
-                THREAD_LOCAL_CACHED_MODULES.with(|cache| {
-                    let mut cache = cache.borrow_mut();
-                    cache.insert(key.clone(), module.clone());
-                });
-                return Some(module.clone());
+            let module = {
+                let cache = self.modules.read().await;
+                cache.get(&key).map(|m| m.clone())
+            };
+            if let Some(module) = module {
+                let mut state = self.state.lock().unwrap();
+                state.memory.touch(key.as_str());
+                state.stats.memory_hits += 1;
+                return Some(module);
             }
         }
 
         // slow path
-        if let Some(cache_dir) = &self.cache_dir {
-            let path = std::path::Path::new(cache_dir.as_str()).join(format!("{}.bin", key).as_str());
-            if let Ok(data) = std::fs::read(path) {
-                let mut decoder = weezl::decode::Decoder::new(weezl::BitOrder::Msb, 8);
-                if let Ok(data) = decoder.decode(&data[..]) {
-                    let module_bytes = Bytes::from(data);
-
-                    // Load the module
-                    let module = unsafe { Module::deserialize(store, &module_bytes[..])
-                        .unwrap()
-                    };
-
-                    #[cfg(feature = "sys")]
-                    {
-                        let mut cache = self.modules.write().await;
-                        cache.insert(key.clone(), module.clone());
-                    }
-                    THREAD_LOCAL_CACHED_MODULES.with(|cache| {
-                        let mut cache = cache.borrow_mut();
-                        cache.insert(key.clone(), module.clone());
-                    });
-                    return Some(module);
-                }
-            }
+        if let Some((module, size)) = self.load_from_disk(store, key.as_str()) {
+            self.state.lock().unwrap().stats.disk_hits += 1;
+            self.insert_memory(key.as_str(), &module, size).await;
+            return Some(module);
         }
 
         // Not found
+        self.state.lock().unwrap().stats.misses += 1;
         None
     }
 
     pub async fn set_compiled_module(&self, data_hash: String, compiler: Compiler, module: &Module) {
         let key = format!("{}-{}", data_hash, compiler);
-        
-        // Add the module to the local thread cache
-        THREAD_LOCAL_CACHED_MODULES.with(|cache| {
-            let mut cache = cache.borrow_mut();
-            let cache = cache.deref_mut();
-            cache.insert(key.clone(), module.clone());
-        });
-
-        // Serialize the compiled module into bytes and insert it into the cache
+
+        // The size of the serialized module is what the cache is bounded by
+        // (modules that can not be serialized are only kept in memory)
+        let compiled_bytes = match module.serialize() {
+            Ok(a) => Some(a),
+            Err(err) => {
+                debug!("failed to serialize the compiled module - {}", err);
+                None
+            }
+        };
+        let size = compiled_bytes.as_ref().map(|a| a.len() as u64).unwrap_or_default();
+
+        self.state.lock().unwrap().stats.inserts += 1;
+        self.insert_memory(key.as_str(), module, size).await;
+
+        // We should also attempt to store it in the cache directory
+        if let Some(compiled_bytes) = compiled_bytes {
+            self.store_on_disk(key.as_str(), &compiled_bytes[..]);
+        }
+    }
+
+    /// Adds a module to the memory cache and evicts the least recently used
+    /// modules if it has grown too big
+    async fn insert_memory(&self, key: &str, module: &Module, size: u64) {
+        let evicted = {
+            let mut state = self.state.lock().unwrap();
+            state.memory.insert(key, size);
+            let evicted = state.memory.evict(self.limits.max_memory, key);
+            state.stats.memory_evictions += evicted.len() as u64;
+            evicted
+        };
+        if evicted.len() > 0 {
+            debug!("evicted {} compiled modules from memory", evicted.len());
+        }
+
         #[cfg(feature = "sys")]
         {
             let mut cache = self.modules.write().await;
-            cache.insert(key.clone(), module.clone());
+            for key in evicted.iter() {
+                cache.remove(key);
+            }
+            cache.insert(key.to_string(), module.clone());
         }
-        
-        // We should also attempt to store it in the cache directory
-        if let Some(cache_dir) = &self.cache_dir {
-            let compiled_bytes = module.serialize().unwrap();
-
-            let path = std::path::Path::new(cache_dir.as_str()).join(format!("{}.bin", key).as_str());
-            let _ = std::fs::create_dir_all(path.parent().unwrap().clone());
-            let mut encoder = weezl::encode::Encoder::new(weezl::BitOrder::Msb, 8);
-            if let Ok(compiled_bytes) = encoder.encode(&compiled_bytes[..]) {
-                let _ = std::fs::write(path, &compiled_bytes[..]);
+        #[cfg(not(feature = "sys"))]
+        {
+            if evicted.len() > 0 {
+                thread_local_remove(&evicted[..]);
+                MODULE_CACHE_EPOCH.fetch_add(1, Ordering::AcqRel);
+            }
+            thread_local_set(key.to_string(), module.clone());
+        }
+    }
+
+    fn disk_path(&self, key: &str) -> Option<std::path::PathBuf> {
+        let cache_dir = self.cache_dir.as_ref()?;
+        Some(std::path::Path::new(cache_dir.as_str()).join(format!("{}.bin", key).as_str()))
+    }
+
+    /// Loads a module from the cache directory, modules that fail their
+    /// integrity check are removed so that they will be compiled again
+    fn load_from_disk(&self, store: &impl AsStoreRef, key: &str) -> Option<(Module, u64)> {
+        let path = self.disk_path(key)?;
+        let data = std::fs::read(&path).ok()?;
+
+        let err = match decode_cached_module(&data[..]) {
+            Ok(module_bytes) => match unsafe { Module::deserialize(store, &module_bytes[..]) } {
+                Ok(module) => {
+                    let mut state = self.state.lock().unwrap();
+                    let state = &mut *state;
+                    if let Some(disk) = self.disk_index(&mut state.disk) {
+                        disk.touch(key);
+                    }
+                    return Some((module, module_bytes.len() as u64));
+                }
+                Err(err) => err.to_string(),
+            },
+            Err(err) => err,
+        };
+
+        warn!("compiled module is corrupt ({}) - {}", path.display(), err);
+        let _ = std::fs::remove_file(&path);
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        if let Some(disk) = self.disk_index(&mut state.disk) {
+            disk.remove(key);
+        }
+        state.stats.corrupt += 1;
+        None
+    }
+
+    /// Stores a module in the cache directory and evicts the least recently
+    /// used modules if the directory has grown too big
+    fn store_on_disk(&self, key: &str, compiled_bytes: &[u8]) {
+        let path = match self.disk_path(key) {
+            Some(a) => a,
+            None => return,
+        };
+        let data = match encode_cached_module(compiled_bytes) {
+            Ok(a) => a,
+            Err(err) => {
+                warn!("failed to compress the compiled module - {}", err);
+                return;
+            }
+        };
+
+        // The module is written to a temporary file which is then renamed so
+        // that a partially written module is never seen by anyone
+        let _ = std::fs::create_dir_all(path.parent().unwrap().clone());
+        let temp = path.with_extension(format!("tmp{}", fastrand::u32(..)));
+        if let Err(err) = std::fs::write(&temp, &data[..]).and_then(|_| std::fs::rename(&temp, &path)) {
+            warn!("failed to store the compiled module ({}) - {}", path.display(), err);
+            let _ = std::fs::remove_file(&temp);
+            return;
+        }
+
+        let evicted = {
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            let evicted = match self.disk_index(&mut state.disk) {
+                Some(disk) => {
+                    disk.insert(key, data.len() as u64);
+                    disk.evict(self.limits.max_disk, key)
+                }
+                None => Vec::new(),
+            };
+            state.stats.disk_evictions += evicted.len() as u64;
+            evicted
+        };
+        for key in evicted {
+            debug!("evicted compiled module from disk ({})", key);
+            if let Some(path) = self.disk_path(key.as_str()) {
+                let _ = std::fs::remove_file(path);
             }
         }
     }
+
+    /// Returns the index of the modules in the cache directory which is
+    /// built from its contents the first time it is needed
+    fn disk_index<'a>(&self, disk: &'a mut Option<LruIndex>) -> Option<&'a mut LruIndex> {
+        let cache_dir = self.cache_dir.as_ref()?;
+        Some(disk.get_or_insert_with(|| scan_cache_dir(cache_dir.as_str())))
+    }
+
+    pub fn limits(&self) -> ModuleCacheLimits {
+        self.limits
+    }
+
+    pub fn stats(&self) -> ModuleCacheStats {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let mut ret = state.stats.clone();
+        ret.memory_entries = state.memory.entries.len();
+        ret.memory_bytes = state.memory.total;
+        if let Some(disk) = self.disk_index(&mut state.disk) {
+            ret.disk_enabled = true;
+            ret.disk_entries = disk.entries.len();
+            ret.disk_bytes = disk.total;
+        }
+        ret.limits = self.limits;
+        ret
+    }
+}
+
+/// Builds the index of the modules in the cache directory, the least
+/// recently modified modules are the first to be evicted
+fn scan_cache_dir(cache_dir: &str) -> LruIndex {
+    let mut files = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(cache_dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().map(|e| e == "bin").unwrap_or(false) == false {
+                continue;
+            }
+            let key = match path.file_stem().and_then(|n| n.to_str()) {
+                Some(a) => a.to_string(),
+                None => continue,
+            };
+            if let Ok(meta) = entry.metadata() {
+                let modified = meta.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+                files.push((modified, key, meta.len()));
+            }
+        }
+    }
+    files.sort();
+
+    let mut ret = LruIndex::default();
+    for (_, key, size) in files {
+        ret.insert(key.as_str(), size);
+    }
+    ret
+}
+
+fn encode_cached_module(compiled_bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut encoder = weezl::encode::Encoder::new(weezl::BitOrder::Msb, 8);
+    let payload = encoder.encode(compiled_bytes).map_err(|err| err.to_string())?;
+
+    let mut hasher = Sha256::default();
+    hasher.update(&payload[..]);
+    let hash = hasher.finalize();
+
+    let mut ret = Vec::with_capacity(MODULE_CACHE_MAGIC.len() + hash.len() + payload.len());
+    ret.extend_from_slice(&MODULE_CACHE_MAGIC[..]);
+    ret.extend_from_slice(&hash[..]);
+    ret.extend_from_slice(&payload[..]);
+    Ok(ret)
+}
+
+fn decode_cached_module(data: &[u8]) -> Result<Vec<u8>, String> {
+    let header = MODULE_CACHE_MAGIC.len() + 32;
+    if data.len() < header || &data[..MODULE_CACHE_MAGIC.len()] != &MODULE_CACHE_MAGIC[..] {
+        return Err("unrecognized format".to_string());
+    }
+    let payload = &data[header..];
+
+    let mut hasher = Sha256::default();
+    hasher.update(payload);
+    if &hasher.finalize()[..] != &data[MODULE_CACHE_MAGIC.len()..header] {
+        return Err("checksum mismatch".to_string());
+    }
+
+    let mut decoder = weezl::decode::Decoder::new(weezl::BitOrder::Msb, 8);
+    decoder.decode(payload).map_err(|err| err.to_string())
 }
 
 #[derive(Debug, Clone)]
@@ -279,6 +627,10 @@ impl BinFactory {
             .await
     }
 
+    pub fn cache_stats(&self) -> ModuleCacheStats {
+        self.compiled_modules.stats()
+    }
+
     pub async fn alias(&self, name: &str) -> Option<AliasConfig> {
         let mut name = name.to_string();
 
@@ -331,3 +683,78 @@ pub fn hash_of_binary(data: &Bytes) -> String {
     let hash = hasher.finalize();
     hex::encode(&hash[..])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut index = LruIndex::default();
+        index.insert("a", 10);
+        index.insert("b", 10);
+        index.insert("c", 10);
+        index.touch("a");
+        index.insert("d", 10);
+        assert_eq!(index.total, 40);
+
+        // "b" is now the oldest followed by "c" (as "a" was used again)
+        assert_eq!(index.evict(20, "d"), vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(index.total, 20);
+        assert!(index.entries.contains_key("a"));
+        assert!(index.entries.contains_key("d"));
+
+        // Nothing is evicted when it already fits
+        assert!(index.evict(20, "d").is_empty());
+    }
+
+    #[test]
+    fn eviction_keeps_the_new_entry() {
+        let mut index = LruIndex::default();
+        index.insert("a", 10);
+        index.insert("big", 100);
+        assert_eq!(index.evict(50, "big"), vec!["a".to_string()]);
+        assert_eq!(index.total, 100);
+        assert!(index.entries.contains_key("big"));
+    }
+
+    #[test]
+    fn reinserting_replaces_the_size() {
+        let mut index = LruIndex::default();
+        index.insert("a", 10);
+        index.insert("a", 30);
+        assert_eq!(index.total, 30);
+        index.remove("a");
+        assert_eq!(index.total, 0);
+        assert!(index.entries.is_empty());
+    }
+
+    #[test]
+    fn cached_module_roundtrip() {
+        let module = b"compiled module bytes compiled module bytes".to_vec();
+        let data = encode_cached_module(&module[..]).unwrap();
+        assert_eq!(&data[..MODULE_CACHE_MAGIC.len()], &MODULE_CACHE_MAGIC[..]);
+        assert_eq!(decode_cached_module(&data[..]).unwrap(), module);
+    }
+
+    #[test]
+    fn corrupt_cached_modules_are_rejected() {
+        let data = encode_cached_module(b"compiled module bytes").unwrap();
+
+        let mut flipped = data.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0x01;
+        assert_eq!(decode_cached_module(&flipped[..]), Err("checksum mismatch".to_string()));
+
+        let mut bad_hash = data.clone();
+        bad_hash[MODULE_CACHE_MAGIC.len()] ^= 0x01;
+        assert_eq!(decode_cached_module(&bad_hash[..]), Err("checksum mismatch".to_string()));
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(decode_cached_module(&bad_magic[..]), Err("unrecognized format".to_string()));
+
+        let truncated = &data[..MODULE_CACHE_MAGIC.len() + 16];
+        assert_eq!(decode_cached_module(truncated), Err("unrecognized format".to_string()));
+    }
+}
@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::stdio::*;
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&'static str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} {}", bytes, UNITS[unit]),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+pub(super) fn cachestat(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    if args.len() > 1 {
+        return Box::pin(async move {
+            let _ = stdio
+                .stderr
+                .write(format!("cachestat: too many arguments\r\n").as_bytes())
+                .await;
+            ExecResponse::Immediate(ctx, 1)
+        });
+    }
+
+    let stats = ctx.bins.cache_stats();
+    let line_end = match stdio.stdout.is_tty() {
+        true => "\r\n",
+        false => "\n",
+    };
+    let disk = match stats.disk_enabled {
+        true => format!(
+            "{} modules, {} of {}",
+            stats.disk_entries,
+            format_size(stats.disk_bytes),
+            format_size(stats.limits.max_disk)
+        ),
+        false => "disabled".to_string(),
+    };
+    let lines = [
+        "compiled module cache".to_string(),
+        format!(
+            "  memory:   {} modules, {} of {}",
+            stats.memory_entries,
+            format_size(stats.memory_bytes),
+            format_size(stats.limits.max_memory)
+        ),
+        format!("  disk:     {}", disk),
+        format!("  hits:     {} (memory), {} (disk)", stats.memory_hits, stats.disk_hits),
+        format!("  misses:   {}", stats.misses),
+        format!("  inserts:  {}", stats.inserts),
+        format!(
+            "  evicted:  {} (memory), {} (disk)",
+            stats.memory_evictions, stats.disk_evictions
+        ),
+        format!("  corrupt:  {}", stats.corrupt),
+    ];
+    let mut output = lines.join(line_end);
+    output.push_str(line_end);
+
+    Box::pin(async move {
+        let _ = stdio.stdout.write(output.as_bytes()).await;
+        ExecResponse::Immediate(ctx, 0)
+    })
+}
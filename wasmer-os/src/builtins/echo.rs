@@ -0,0 +1,32 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::stdio::*;
+
+pub(super) fn echo(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let mut args = &args[1..];
+    let mut newline = true;
+    if args.first().map(|a| a.as_str()) == Some("-n") {
+        newline = false;
+        args = &args[1..];
+    }
+
+    // Terminals need a carriage return while files and pipes do not
+    let mut output = args.join(" ");
+    if newline {
+        output.push_str(match stdio.stdout.is_tty() {
+            true => "\r\n",
+            false => "\n",
+        });
+    }
+    Box::pin(async move {
+        let _ = stdio.stdout.write(output.as_bytes()).await;
+        ExecResponse::Immediate(ctx, 0)
+    })
+}
@@ -0,0 +1,52 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::stdio::*;
+
+const USAGE: &'static str = "history: usage: history [-c] [n]\r\n";
+
+pub(super) fn history(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let mut clear = false;
+    let mut count = None;
+    for arg in args.iter().skip(1) {
+        match arg.as_str() {
+            "-c" => clear = true,
+            a => match a.parse::<usize>() {
+                Ok(n) if count.is_none() => count = Some(n),
+                _ => {
+                    return Box::pin(async move {
+                        let _ = stdio.stderr.write(USAGE.as_bytes()).await;
+                        ExecResponse::Immediate(ctx, 2)
+                    });
+                }
+            },
+        }
+    }
+
+    Box::pin(async move {
+        // The console writes the history file once the command has finished
+        if clear {
+            stdio.tty.clear_history().await;
+            return ExecResponse::Immediate(ctx, 0);
+        }
+
+        let line_end = match stdio.stdout.is_tty() {
+            true => "\r\n",
+            false => "\n",
+        };
+        let history = stdio.tty.history().await;
+        let skip = history.len() - count.unwrap_or(history.len()).min(history.len());
+        let mut output = String::new();
+        for (n, cmd) in history.iter().enumerate().skip(skip) {
+            output.push_str(format!("{:>5}  {}{}", n + 1, cmd, line_end).as_str());
+        }
+        let _ = stdio.stdout.write(output.as_bytes()).await;
+        ExecResponse::Immediate(ctx, 0)
+    })
+}
@@ -0,0 +1,256 @@
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::pin::Pin;
+use tokio::select;
+
+use crate::api::*;
+use crate::err;
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::job::*;
+use crate::stdio::*;
+use crate::tty::TtyMode;
+
+fn line_end(stdio: &Stdio) -> &'static str {
+    match stdio.stdout.is_tty() {
+        true => "\r\n",
+        false => "\n",
+    }
+}
+
+fn closed_code() -> NonZeroU32 {
+    NonZeroU32::new(err::ERR_TERMINATED).unwrap()
+}
+
+/// Finds the job that `fg` and `bg` act on (the current job by default)
+async fn find_job(ctx: &EvalContext, name: &str, spec: Option<&String>) -> Result<Job, String> {
+    let reactor = ctx.reactor.read().await;
+    match spec {
+        Some(spec) => reactor
+            .find_job(spec.as_str())
+            .ok_or_else(|| format!("{}: {}: no such job\r\n", name, spec)),
+        None => reactor
+            .find_job("%+")
+            .ok_or_else(|| format!("{}: current: no such job\r\n", name)),
+    }
+}
+
+/// Waits for a job to exit, unless the job running the builtin is
+/// terminated first (e.g. Ctrl-C) in which case its exit code is returned
+/// as the error
+async fn wait_for(ctx: &EvalContext, job: &Job) -> Result<u32, u32> {
+    let mut exit = Box::pin(job.wait_for_exit());
+    loop {
+        select! {
+            code = &mut exit => return Ok(code),
+            _ = ctx.system.sleep(100) => {
+                if let Some(code) = ctx.job.should_terminate() {
+                    return Err(code);
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn jobs(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let mut args = &args[1..];
+    let mut long = false;
+    let mut pids_only = false;
+    while let Some(arg) = args.first().filter(|a| a.starts_with('-') && a.len() > 1) {
+        for c in arg.chars().skip(1) {
+            match c {
+                'l' => long = true,
+                'p' => pids_only = true,
+                _ => {
+                    let msg = format!("jobs: -{}: invalid option\r\n", c);
+                    return Box::pin(async move {
+                        let _ = stdio.stderr.write(msg.as_bytes()).await;
+                        ExecResponse::Immediate(ctx, 2)
+                    });
+                }
+            }
+        }
+        args = &args[1..];
+    }
+    let specs = args.to_vec();
+
+    Box::pin(async move {
+        let mut ret = 0;
+        let mut output = String::new();
+        let mut errors = String::new();
+        {
+            let mut reactor = ctx.reactor.write().await;
+            let jobs = match specs.is_empty() {
+                true => reactor.background_jobs(),
+                false => specs
+                    .iter()
+                    .filter_map(|spec| match reactor.find_job(spec.as_str()) {
+                        Some(job) => Some(job),
+                        None => {
+                            errors.push_str(format!("jobs: {}: no such job\r\n", spec).as_str());
+                            ret = 1;
+                            None
+                        }
+                    })
+                    .collect(),
+            };
+
+            for job in jobs {
+                let marker = reactor.job_marker(job.id);
+                let pid = job.pids().first().cloned().unwrap_or_default();
+                let line = match (pids_only, long) {
+                    (true, _) => pid.to_string(),
+                    (false, true) => format!(
+                        "[{}]{} {:>5} {:<24}{}",
+                        job.id,
+                        marker,
+                        pid,
+                        job.status().to_string(),
+                        job.command()
+                    ),
+                    (false, false) => job.describe(marker),
+                };
+                output.push_str(line.as_str());
+                output.push_str(line_end(&stdio));
+
+                // Jobs that have finished are only reported once
+                if let JobStatus::Done(_) = job.status() {
+                    reactor.close_job(job, closed_code());
+                }
+            }
+        }
+        let _ = stdio.stdout.write(output.as_bytes()).await;
+        let _ = stdio.stderr.write(errors.as_bytes()).await;
+        ExecResponse::Immediate(ctx, ret)
+    })
+}
+
+pub(super) fn fg(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let args = args.to_vec();
+    Box::pin(async move {
+        let job = match find_job(&ctx, "fg", args.get(1)).await {
+            Ok(a) => a,
+            Err(msg) => {
+                let _ = stdio.stderr.write(msg.as_bytes()).await;
+                return ExecResponse::Immediate(ctx, 1);
+            }
+        };
+        let msg = format!("{}{}", job.command(), line_end(&stdio));
+        let _ = stdio.stdout.write(msg.as_bytes()).await;
+        {
+            let reactor = ctx.reactor.read().await;
+            job.resume(&reactor);
+        }
+
+        // The job gets the terminal (and with it the keyboard) until it
+        // either finishes or is stopped again
+        let last = stdio.tty.switch_mode(TtyMode::StdIn(job.clone())).await;
+        let ret = job.wait().await;
+        stdio.tty.switch_mode(last).await;
+
+        match ret {
+            Some(code) => {
+                let mut reactor = ctx.reactor.write().await;
+                reactor.close_job(job, closed_code());
+                ExecResponse::Immediate(ctx, code)
+            }
+            None => ExecResponse::Immediate(ctx, 128 + Signal::Tstp.number()),
+        }
+    })
+}
+
+pub(super) fn bg(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let args = args.to_vec();
+    Box::pin(async move {
+        let job = match find_job(&ctx, "bg", args.get(1)).await {
+            Ok(a) => a,
+            Err(msg) => {
+                let _ = stdio.stderr.write(msg.as_bytes()).await;
+                return ExecResponse::Immediate(ctx, 1);
+            }
+        };
+        if job.status() != JobStatus::Stopped {
+            let msg = format!("bg: job {} already in background\r\n", job.id);
+            let _ = stdio.stderr.write(msg.as_bytes()).await;
+            return ExecResponse::Immediate(ctx, 0);
+        }
+
+        let marker = {
+            let mut reactor = ctx.reactor.write().await;
+            job.resume(&reactor);
+            reactor.background_job(&job);
+            reactor.job_marker(job.id)
+        };
+        let mut command = job.command();
+        if command.ends_with('&') == false {
+            command.push_str(" &");
+        }
+        let msg = format!("[{}]{} {}{}", job.id, marker, command, line_end(&stdio));
+        let _ = stdio.stdout.write(msg.as_bytes()).await;
+        ExecResponse::Immediate(ctx, 0)
+    })
+}
+
+pub(super) fn wait(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let args = args.to_vec();
+    Box::pin(async move {
+        // Without any arguments all the background jobs are waited on
+        let mut targets = Vec::new();
+        {
+            let reactor = ctx.reactor.read().await;
+            if args.len() <= 1 {
+                targets.extend(reactor.background_jobs().into_iter().map(Ok));
+            }
+            for arg in args.iter().skip(1) {
+                let job = match arg.parse::<u32>() {
+                    _ if arg.starts_with('%') => reactor
+                        .find_job(arg.as_str())
+                        .ok_or_else(|| (format!("wait: {}: no such job\r\n", arg), 127)),
+                    Ok(pid) => reactor
+                        .find_job_of_process(pid)
+                        .ok_or_else(|| (format!("wait: pid {} is not a child of this shell\r\n", pid), 127)),
+                    Err(_) => Err((format!("wait: `{}': not a pid or valid job spec\r\n", arg), 2)),
+                };
+                targets.push(job);
+            }
+        }
+
+        let mut ret = 0;
+        for target in targets {
+            ret = match target {
+                Ok(job) => match wait_for(&ctx, &job).await {
+                    Ok(code) => {
+                        let mut reactor = ctx.reactor.write().await;
+                        reactor.close_job(job, closed_code());
+                        code
+                    }
+                    Err(code) => return ExecResponse::Immediate(ctx, code),
+                },
+                Err((msg, code)) => {
+                    let _ = stdio.stderr.write(msg.as_bytes()).await;
+                    code
+                }
+            };
+        }
+        if args.len() <= 1 {
+            ret = 0;
+        }
+        ExecResponse::Immediate(ctx, ret)
+    })
+}
@@ -0,0 +1,103 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::err;
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::eval::Jump;
+use crate::stdio::*;
+
+fn parse_count(name: &str, args: &[String], default: u32) -> Result<u32, String> {
+    match args.len() {
+        0 | 1 => Ok(default),
+        2 => args[1]
+            .parse::<u32>()
+            .map_err(|_| format!("{}: {}: numeric argument required\r\n", name, args[1])),
+        _ => Err(format!("{}: too many arguments\r\n", name)),
+    }
+}
+
+async fn jump(
+    name: &str,
+    args: &[String],
+    mut ctx: EvalContext,
+    mut stdio: Stdio,
+    to: impl FnOnce(u32) -> Jump,
+) -> ExecResponse {
+    match parse_count(name, args, 1) {
+        Ok(0) => {
+            let _ = stdio
+                .stderr
+                .write(format!("{}: loop count out of range\r\n", name).as_bytes())
+                .await;
+            ExecResponse::Immediate(ctx, 1)
+        }
+        Ok(n) => {
+            ctx.jump = Some(to(n));
+            ExecResponse::Immediate(ctx, 0)
+        }
+        Err(msg) => {
+            let _ = stdio.stderr.write(msg.as_bytes()).await;
+            ExecResponse::Immediate(ctx, err::ERR_EINVAL)
+        }
+    }
+}
+
+pub(super) fn break_(
+    args: &[String],
+    ctx: EvalContext,
+    stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let args = args.to_vec();
+    Box::pin(async move { jump("break", &args[..], ctx, stdio, Jump::Break).await })
+}
+
+pub(super) fn continue_(
+    args: &[String],
+    ctx: EvalContext,
+    stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let args = args.to_vec();
+    Box::pin(async move { jump("continue", &args[..], ctx, stdio, Jump::Continue).await })
+}
+
+pub(super) fn return_(
+    args: &[String],
+    mut ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let code = parse_count("return", args, ctx.last_return);
+    Box::pin(async move {
+        match code {
+            Ok(code) => {
+                ctx.jump = Some(Jump::Return);
+                ExecResponse::Immediate(ctx, code)
+            }
+            Err(msg) => {
+                let _ = stdio.stderr.write(msg.as_bytes()).await;
+                ExecResponse::Immediate(ctx, err::ERR_EINVAL)
+            }
+        }
+    })
+}
+
+pub(super) fn shift(
+    args: &[String],
+    mut ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let count = parse_count("shift", args, 1);
+    Box::pin(async move {
+        match count {
+            Ok(n) if (n as usize) <= ctx.positional.len() => {
+                ctx.positional.drain(..n as usize);
+                ExecResponse::Immediate(ctx, 0)
+            }
+            Ok(_) => ExecResponse::Immediate(ctx, 1),
+            Err(msg) => {
+                let _ = stdio.stderr.write(msg.as_bytes()).await;
+                ExecResponse::Immediate(ctx, err::ERR_EINVAL)
+            }
+        }
+    })
+}
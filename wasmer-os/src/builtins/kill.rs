@@ -0,0 +1,133 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::job::*;
+use crate::stdio::*;
+
+const USAGE: &'static str = "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]\r\n";
+
+fn line_end(stdio: &Stdio) -> &'static str {
+    match stdio.stdout.is_tty() {
+        true => "\r\n",
+        false => "\n",
+    }
+}
+
+/// Lists the signals (or converts signal numbers into names and back)
+fn list(args: &[String], stdio: &Stdio) -> Result<String, String> {
+    let mut ret = String::new();
+    if args.is_empty() {
+        for signal in Signal::ALL.iter() {
+            ret.push_str(format!("{:>2}) SIG{}{}", signal.number(), signal.name(), line_end(stdio)).as_str());
+        }
+    }
+    for arg in args {
+        let signal = Signal::parse(arg.as_str())
+            .ok_or_else(|| format!("kill: {}: invalid signal specification\r\n", arg))?;
+        match arg.parse::<u32>() {
+            Ok(_) => ret.push_str(signal.name()),
+            Err(_) => ret.push_str(signal.number().to_string().as_str()),
+        }
+        ret.push_str(line_end(stdio));
+    }
+    Ok(ret)
+}
+
+pub(super) fn kill(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let mut args = &args[1..];
+    let parsed = match args.first().map(|a| a.as_str()) {
+        Some("-l") | Some("-L") => {
+            let ret = list(&args[1..], &stdio);
+            return Box::pin(async move {
+                match ret {
+                    Ok(output) => {
+                        let _ = stdio.stdout.write(output.as_bytes()).await;
+                        ExecResponse::Immediate(ctx, 0)
+                    }
+                    Err(msg) => {
+                        let _ = stdio.stderr.write(msg.as_bytes()).await;
+                        ExecResponse::Immediate(ctx, 1)
+                    }
+                }
+            });
+        }
+        Some("-s") | Some("-n") => {
+            let spec = args.get(1).cloned().unwrap_or_default();
+            args = &args[args.len().min(2)..];
+            Signal::parse(spec.as_str()).ok_or(spec)
+        }
+        Some(a) if a.starts_with('-') && a.len() > 1 => {
+            args = &args[1..];
+            Signal::parse(&a[1..]).ok_or(a[1..].to_string())
+        }
+        _ => Ok(Signal::Term),
+    };
+    let targets = args.to_vec();
+
+    Box::pin(async move {
+        let signal = match parsed {
+            Ok(a) => a,
+            Err(spec) => {
+                let msg = format!("kill: {}: invalid signal specification\r\n", spec);
+                let _ = stdio.stderr.write(msg.as_bytes()).await;
+                return ExecResponse::Immediate(ctx, 1);
+            }
+        };
+        if targets.is_empty() {
+            let _ = stdio.stderr.write(USAGE.as_bytes()).await;
+            return ExecResponse::Immediate(ctx, 2);
+        }
+
+        // Jobs receive the signal as a whole (their process group) while
+        // a process ID only signals that one process
+        let mut ret = 0;
+        let mut errors = String::new();
+        {
+            let mut reactor = ctx.reactor.write().await;
+            for target in targets.iter() {
+                if target.starts_with('%') {
+                    match reactor.find_job(target.as_str()) {
+                        Some(job) => job.signal(&mut reactor, signal),
+                        None => {
+                            errors.push_str(format!("kill: {}: no such job\r\n", target).as_str());
+                            ret = 1;
+                        }
+                    }
+                    continue;
+                }
+                let pid = match target.parse::<u32>() {
+                    Ok(a) => a,
+                    Err(_) => {
+                        errors.push_str(
+                            format!("kill: {}: arguments must be process or job IDs\r\n", target).as_str(),
+                        );
+                        ret = 1;
+                        continue;
+                    }
+                };
+                match reactor.get_process(pid) {
+                    Some(process) => match signal {
+                        Signal::Stop | Signal::Tstp => process.ctx.stop(),
+                        Signal::Cont => process.ctx.resume(),
+                        signal => {
+                            process.ctx.resume();
+                            reactor.close_process(pid, signal.exit_code().get());
+                        }
+                    },
+                    None => {
+                        errors.push_str(format!("kill: ({}) - No such process\r\n", pid).as_str());
+                        ret = 1;
+                    }
+                }
+            }
+        }
+        let _ = stdio.stderr.write(errors.as_bytes()).await;
+        ExecResponse::Immediate(ctx, ret)
+    })
+}
@@ -1,28 +1,44 @@
 mod about;
+mod cachestat;
 mod cd;
+mod echo;
 mod exit;
 mod export;
 mod help;
+mod history;
+mod jobs;
+mod jump;
+mod kill;
 mod mount;
 mod pwd;
 mod readonly;
 mod reset;
 mod source;
+mod test;
+mod truth;
 mod umount;
 mod unset;
 mod wax;
 mod call;
 
 use about::*;
+use cachestat::*;
 use cd::*;
+use echo::*;
 use exit::*;
 use export::*;
 use help::*;
+use history::*;
+use jobs::*;
+use jump::*;
+use kill::*;
 use mount::*;
 use pwd::*;
 use readonly::*;
 use reset::*;
 use source::*;
+use test::*;
+use truth::*;
 use umount::*;
 use unset::*;
 use wax::*;
@@ -53,7 +69,25 @@ impl Builtins {
         b.insert("unset", unset);
         b.insert("help", help);
         b.insert("about", about);
+        b.insert("history", history);
+        b.insert("cachestat", cachestat);
         b.insert("source", source);
+        b.insert(".", source);
+        b.insert("echo", echo);
+        b.insert("true", true_);
+        b.insert("false", false_);
+        b.insert(":", true_);
+        b.insert("test", test);
+        b.insert("[", test);
+        b.insert("break", break_);
+        b.insert("continue", continue_);
+        b.insert("return", return_);
+        b.insert("shift", shift);
+        b.insert("jobs", jobs);
+        b.insert("fg", fg);
+        b.insert("bg", bg);
+        b.insert("wait", wait);
+        b.insert("kill", kill);
         b.insert("pwd", pwd);
         b.insert("reset", reset);
         b.insert("mount", mount);
@@ -72,4 +106,11 @@ impl Builtins {
     pub fn get(&self, key: &String) -> Option<&Command> {
         self.commands.get(key)
     }
+
+    /// Names of all the builtin commands (sorted)
+    pub fn names(&self) -> Vec<String> {
+        let mut ret = self.commands.keys().cloned().collect::<Vec<_>>();
+        ret.sort();
+        ret
+    }
 }
@@ -12,6 +12,8 @@ use crate::bus::SubProcessMultiplexer;
 use crate::eval::EvalContext;
 use crate::eval::ExecResponse;
 use crate::fs::FuseFileSystem;
+use crate::fs::MountedFileSystem;
+use crate::fs::TmpFileSystem;
 use crate::stdio::*;
 use crate::tty::*;
 
@@ -20,21 +22,36 @@ pub(super) fn mount(
     mut ctx: EvalContext,
     mut stdio: Stdio,
 ) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
-    let wapm: String;
+    // Overlay mounts put a writable file system over an existing directory
+    let mut args = &args[1..];
+    let mut lower = None;
+    if args.len() >= 2 && args[0] == "--overlay" {
+        let mut path = args[1].clone();
+        if path.starts_with("/") == false {
+            path = format!("{}/{}", ctx.working_dir.trim_end_matches('/'), path);
+        }
+        lower = Some(path);
+        args = &args[2..];
+    }
+
+    // The upper file system is either served by a WAPM program or (for
+    // overlays only) kept in memory
     let mountpoint: String;
-    let target: String;
+    let upper: Option<(String, String)>;
     match args.len() {
+        1 if lower.is_some() => {
+            mountpoint = args[0].clone();
+            upper = None;
+        }
+        2 => {
+            mountpoint = args[0].clone();
+            upper = Some(("tok".to_string(), args[1].clone()));
+        }
         3 => {
-            wapm = "tok".to_string();
             mountpoint = args[1].clone();
-            target = args[2].clone();
+            upper = Some((args[0].clone(), args[2].clone()));
         }
-        4 => {
-            wapm = args[1].clone();
-            mountpoint = args[2].clone();
-            target = args[3].clone();
-        }
-        a if a > 4 => {
+        a if a > 3 => {
             return Box::pin(async move {
                 print(format!("mount: too many arguments\r\n"), &mut stdio, true).await;
                 ExecResponse::Immediate(ctx, 0)
@@ -84,57 +101,89 @@ pub(super) fn mount(
             .await;
             return ExecResponse::Immediate(ctx, 1);
         }
-
-        print(
-            format!("Mounting {}@{} at {}\r\n", target, wapm, mountpoint),
-            &mut stdio,
-            false,
-        )
-        .await;
-
-        let launch_env = ctx.launch_env();
-        let factory = SubProcessFactory::new(factory, multiplexer);
-        let sub_process = match factory
-            .get_or_create(wapm.as_str(), &launch_env, StdioMode::Inherit, StdioMode::Log)
-            .await
-        {
-            Ok(a) => a,
-            Err(_) => {
+        if let Some(lower) = lower.as_ref() {
+            if let Err(err) = ctx.root.read_dir(Path::new(lower.as_str())) {
                 print(
-                    format!("mount: wapm program not found\r\n"),
+                    format!("mount: the lower directory is invalid: {}\r\n", err),
                     &mut stdio,
                     true,
                 )
                 .await;
                 return ExecResponse::Immediate(ctx, 1);
             }
-        };
-
-        print(format!("Executing the mount\r\n"), &mut stdio, false).await;
+        }
 
-        let fs = match FuseFileSystem::new(sub_process, target.as_str(), stdio.clone()).await {
-            Ok(a) => a,
-            Err(err) => {
+        let (name, fs): (String, Box<dyn MountedFileSystem>) = match upper {
+            None => ("memory".to_string(), Box::new(TmpFileSystem::new())),
+            Some((wapm, target)) => {
                 print(
-                    format!("mount: mount call failed ({})\r\n", err),
+                    format!("Mounting {}@{} at {}\r\n", target, wapm, mountpoint),
                     &mut stdio,
-                    true,
+                    false,
                 )
                 .await;
-                return ExecResponse::Immediate(ctx, 1);
+
+                let launch_env = ctx.launch_env();
+                let factory = SubProcessFactory::new(factory, multiplexer);
+                let sub_process = match factory
+                    .get_or_create(wapm.as_str(), &launch_env, StdioMode::Inherit, StdioMode::Log)
+                    .await
+                {
+                    Ok(a) => a,
+                    Err(_) => {
+                        print(
+                            format!("mount: wapm program not found\r\n"),
+                            &mut stdio,
+                            true,
+                        )
+                        .await;
+                        return ExecResponse::Immediate(ctx, 1);
+                    }
+                };
+
+                print(format!("Executing the mount\r\n"), &mut stdio, false).await;
+
+                let fs = match FuseFileSystem::new(sub_process, target.as_str(), stdio.clone()).await {
+                    Ok(a) => a,
+                    Err(err) => {
+                        print(
+                            format!("mount: mount call failed ({})\r\n", err),
+                            &mut stdio,
+                            true,
+                        )
+                        .await;
+                        return ExecResponse::Immediate(ctx, 1);
+                    }
+                };
+                let _ = stdio.stdout.flush_async().await;
+
+                print(format!("\rSuccessfully mounted\r\n"), &mut stdio, false).await;
+                (format!("{}({})", wapm, target), Box::new(fs))
             }
         };
-        let _ = stdio.stdout.flush_async().await;
-
-        print(format!("\rSuccessfully mounted\r\n"), &mut stdio, false).await;
 
-        ctx.root.mount(
-            format!("{}({})", wapm, target).as_str(),
-            mountpoint.as_str(),
-            false,
-            Box::new(fs),
-            None,
-        );
+        match lower {
+            Some(lower) => {
+                // The lower layer is a snapshot of the mounts that are there now
+                let lower_fs = ctx.root.subdir(lower.as_str());
+                ctx.root.mount_overlay(
+                    format!("overlay({} over {})", name, lower).as_str(),
+                    mountpoint.as_str(),
+                    false,
+                    fs,
+                    Box::new(lower_fs),
+                );
+                print(
+                    format!("Mounted an overlay of {} at {}\r\n", lower, mountpoint),
+                    &mut stdio,
+                    false,
+                )
+                .await;
+            }
+            None => {
+                ctx.root.mount(name.as_str(), mountpoint.as_str(), false, fs, None);
+            }
+        }
 
         ExecResponse::Immediate(ctx, 0)
     });
@@ -1,4 +1,3 @@
-use std::collections::VecDeque;
 use std::future::Future;
 use std::path::Path;
 use std::pin::Pin;
@@ -18,12 +17,13 @@ pub(super) fn source(
     mut ctx: EvalContext,
     mut stdio: Stdio,
 ) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
-    if args.len() != 2 {
+    if args.len() < 2 {
         return Box::pin(async move { ExecResponse::Immediate(ctx, err::ERR_EINVAL) });
     }
 
     // Read the script
     let script = args[1].clone();
+    let positional = args[2..].to_vec();
     return Box::pin(async move {
         let script = AsyncifyFileSystem::new(ctx.root.clone())
             .new_open_options()
@@ -54,10 +54,15 @@ pub(super) fn source(
             }
         };
 
-        let script = process_script(script, &ctx);
+        let script = process_script(script);
 
+        // Any extra arguments become the positional parameters of the script
         ctx.stdio = stdio;
-        
+        let saved_positional = match positional.is_empty() {
+            true => None,
+            false => Some(std::mem::replace(&mut ctx.positional, positional)),
+        };
+
         let cmd = script;
 
         let mut stdout = ctx.stdio.stdout.clone();
@@ -78,7 +83,10 @@ pub(super) fn source(
             }
         };
 
-        let ctx = result.ctx;
+        let mut ctx = result.ctx;
+        if let Some(positional) = saved_positional {
+            ctx.positional = positional;
+        }
         match result.status {
             EvalStatus::Executed { code, show_result } => {
                 debug!("exec executed (code={})", code);
@@ -111,68 +119,10 @@ pub(super) fn source(
     });
 }
 
-fn process_script(script: String, ctx: &EvalContext) -> String {
-    // Currently the script engine requires a ";" on every line
-    // TODO: make a more advanced script processor
-    let script = script
+fn process_script(script: String) -> String {
+    // The parser understands newlines (and expands the variables itself) so
+    // only the line endings need to be made consistent
+    script
         .replace("\r\n", "\n")
         .replace("\r", "\n")
-        .replace("\n", ";\n")
-        .replace(";;", ";")
-        .replace(";\n;", ";")
-        .to_string();
-
-    // We also need to apply the environment variables
-    let script = apply_env_vars(script, ctx);
-
-    script
-}
-
-fn apply_env_vars(script: String, ctx: &EvalContext) -> String {
-    // Replace all the environment variables with real values
-    let mut script_parts = script.split("$").collect::<VecDeque<_>>();
-
-    let mut ret = String::new();
-    if let Some(first) = script_parts.pop_front() {
-        ret.push_str(first);
-    }
-
-    for script_part in script_parts {
-        // Find the end of the variable name
-        // If it has a curly then just look for the next curcle
-        let mut start = 0usize;
-        let mut end1 = script_part.len();
-        let mut end2 = script_part.len();
-        if script_part.starts_with("{") {
-            start = 1;
-            let x: &[_] = &['}', '\n', '\r'];
-            if let Some(e) = script_part.find(x) {
-                end1 = e;
-                end2 = e + 1;
-            }
-        } else {
-            let x: &[_] = &[
-                ' ', '\t', '.', ',', ';', ':', '\n', '\r', '\'', '"', '&', '*', '@', '!', '(', ')',
-                '<', '>', '`', '/', '\\',
-            ];
-            if let Some(e) = script_part.find(x) {
-                end1 = e;
-                end2 = e;
-            }
-        }
-
-        // Replace the variable with data
-        if start < end1 {
-            let name = &script_part[start..end1];
-            if let Some(val) = ctx.env.get(name) {
-                ret.push_str(val.as_str());
-            }
-        }
-
-        // Add the remainder (if there is any)
-        if end2 < script_part.len() {
-            ret.push_str(&script_part[end2..]);
-        }
-    }
-    ret
 }
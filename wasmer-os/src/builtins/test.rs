@@ -0,0 +1,103 @@
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::fs::AsyncifyFileSystem;
+use crate::stdio::*;
+
+/// Evaluates a conditional expression (`test EXPR` or `[ EXPR ]`), the exit
+/// code is 0 when the expression is true, 1 when its false and 2 on errors
+pub(super) fn test(
+    args: &[String],
+    ctx: EvalContext,
+    mut stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    let mut args = args.to_vec();
+    if args[0] == "[" {
+        if args.last().map(|a| a.as_str()) != Some("]") {
+            return Box::pin(async move {
+                let _ = stdio.stderr.write(format!("[: missing ']'\r\n").as_bytes()).await;
+                ExecResponse::Immediate(ctx, 2)
+            });
+        }
+        args.pop();
+    }
+    let name = args.remove(0);
+
+    Box::pin(async move {
+        match expression(&ctx, &args[..]).await {
+            Ok(true) => ExecResponse::Immediate(ctx, 0),
+            Ok(false) => ExecResponse::Immediate(ctx, 1),
+            Err(msg) => {
+                let _ = stdio
+                    .stderr
+                    .write(format!("{}: {}\r\n", name, msg).as_bytes())
+                    .await;
+                ExecResponse::Immediate(ctx, 2)
+            }
+        }
+    })
+}
+
+async fn expression(ctx: &EvalContext, args: &[String]) -> Result<bool, String> {
+    let mut args = args.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+
+    // A leading `!` negates the rest of the expression
+    let mut negate = false;
+    while args.len() > 1 && args[0] == "!" {
+        negate = !negate;
+        args.remove(0);
+    }
+    let ret = match args[..] {
+        [] => false,
+        [a] => a.len() > 0,
+        [op, a] => unary(ctx, op, a).await?,
+        [a, op, b] => binary(a, op, b)?,
+        _ => return Err(format!("too many arguments")),
+    };
+    Ok(ret != negate)
+}
+
+async fn unary(ctx: &EvalContext, op: &str, arg: &str) -> Result<bool, String> {
+    match op {
+        "-z" => Ok(arg.len() == 0),
+        "-n" => Ok(arg.len() > 0),
+        "-e" | "-f" | "-d" => {
+            let mut path = arg.to_string();
+            if path.starts_with("/") == false {
+                path = format!("{}{}", ctx.working_dir, path);
+            }
+            let meta = AsyncifyFileSystem::new(ctx.root.clone())
+                .metadata(Path::new(path.as_str()))
+                .await;
+            Ok(match (op, meta) {
+                (_, Err(_)) => false,
+                ("-f", Ok(meta)) => meta.is_file(),
+                ("-d", Ok(meta)) => meta.is_dir(),
+                (_, Ok(_)) => true,
+            })
+        }
+        _ => Err(format!("{}: unary operator expected", op)),
+    }
+}
+
+fn binary(a: &str, op: &str, b: &str) -> Result<bool, String> {
+    let number = |v: &str| {
+        v.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("{}: integer expression expected", v))
+    };
+    match op {
+        "=" | "==" => Ok(a == b),
+        "!=" => Ok(a != b),
+        "-eq" => Ok(number(a)? == number(b)?),
+        "-ne" => Ok(number(a)? != number(b)?),
+        "-lt" => Ok(number(a)? < number(b)?),
+        "-le" => Ok(number(a)? <= number(b)?),
+        "-gt" => Ok(number(a)? > number(b)?),
+        "-ge" => Ok(number(a)? >= number(b)?),
+        _ => Err(format!("{}: binary operator expected", op)),
+    }
+}
@@ -0,0 +1,22 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::eval::EvalContext;
+use crate::eval::ExecResponse;
+use crate::stdio::*;
+
+pub(super) fn true_(
+    _args: &[String],
+    ctx: EvalContext,
+    _stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    Box::pin(async move { ExecResponse::Immediate(ctx, 0) })
+}
+
+pub(super) fn false_(
+    _args: &[String],
+    ctx: EvalContext,
+    _stdio: Stdio,
+) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
+    Box::pin(async move { ExecResponse::Immediate(ctx, 1) })
+}
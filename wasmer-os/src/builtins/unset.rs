@@ -10,8 +10,17 @@ pub(super) fn unset(
     mut ctx: EvalContext,
     _stdio: Stdio,
 ) -> Pin<Box<dyn Future<Output = ExecResponse> + Send>> {
-    for arg in &args[1..] {
-        ctx.env.unset(arg.as_str());
+    // `unset -f` removes functions rather than variables
+    let mut args = &args[1..];
+    let functions = args.first().map(|a| a.as_str()) == Some("-f");
+    if functions || args.first().map(|a| a.as_str()) == Some("-v") {
+        args = &args[1..];
+    }
+    for arg in args {
+        match functions {
+            true => ctx.env.unset_function(arg.as_str()),
+            false => ctx.env.unset(arg.as_str()),
+        }
     }
     Box::pin(async move { ExecResponse::Immediate(ctx, 0) })
 }
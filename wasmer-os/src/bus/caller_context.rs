@@ -91,6 +91,9 @@ for WasmCheckpoint
 #[derive(Debug, Clone)]
 pub struct WasmCallerContext {
     forced_exit: Arc<AtomicU32>,
+    // Processes that are stopped (e.g. Ctrl-Z) block at their next system
+    // call until they are resumed or terminated
+    stopped: Arc<AtomicBool>,
     // The second checkpoint is after the start method completes but before
     // all the background threads exit
     checkpoint2: Arc<WasmCheckpoint>,
@@ -102,6 +105,7 @@ impl WasmCallerContext
     {
         WasmCallerContext {
             forced_exit: Arc::new(AtomicU32::new(0)),
+            stopped: Arc::new(AtomicBool::new(false)),
             checkpoint2: checkpoint2.clone(),
         }
     }
@@ -130,6 +134,29 @@ impl WasmCallerContext {
         }
     }
 
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    pub fn resume(&self) {
+        self.stopped.store(false, Ordering::Release);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Blocks the calling thread for as long as the process is stopped
+    /// (a process that is terminated while stopped is released straight away)
+    pub fn wait_while_stopped(&self) {
+        let mut wait_time = 0u64;
+        while self.is_stopped() && self.should_terminate().is_none() {
+            wait_time += 1;
+            let wait_time = u64::min(wait_time, 50);
+            std::thread::park_timeout(std::time::Duration::from_millis(wait_time));
+        }
+    }
+
     pub fn get_forced_exit(&self) -> Arc<AtomicU32> {
         return self.forced_exit.clone();
     }
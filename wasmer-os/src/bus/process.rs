@@ -336,6 +336,7 @@ impl ProcessExecFactory {
                         engine,
                         compiler,
                     );
+                    spawn.limits = ctx.limits.clone();
                     spawn.checkpoint1 = Some((checkpoint1_tx, checkpoint1));
                     spawn.checkpoint2 = Some((checkpoint2_tx, checkpoint2));
                     spawn
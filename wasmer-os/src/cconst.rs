@@ -102,12 +102,17 @@ impl Tty {
 
     pub const MOUNT_USAGE: &'static str = r#"Usage:
  mount [<wapm-name>] <mountpoint> <target>
+ mount --overlay <lowerdir> <mountpoint> [[<wapm-name>] <target>]
 
  <wapm-name>: Name of the WAPM program that will serve the file-system (default: tok)
  <mounpoint>: Location where the file-system will be mounted to
  <target>: Target name passed to the WAPM program and is ued for the mounting
+ <lowerdir>: Read-only directory that the mounted file-system is overlayed on,
+             changes are copied up into the file-system (or memory if no
+             <target> is given) and the directory itself is left untouched
 
  Example: mount tok /www wasmer.sh/wasm
+ Example: mount --overlay /bin /bin
 "#;
 
     pub const UMOUNT_USAGE: &'static str = r#"Usage:
@@ -22,6 +22,7 @@ use super::err;
 use super::eval::*;
 use super::fd::*;
 use super::fs::*;
+use super::history::*;
 use super::job::*;
 use super::pipe::*;
 use super::reactor::*;
@@ -47,6 +48,7 @@ pub struct Console {
     #[cfg(feature = "sys")]
     engine: Option<Engine>,
     compiler: Compiler,
+    limits: ProcessLimits,
     abi: Arc<dyn ConsoleAbi>,
     wizard: Option<WizardExecutor>,
     whitelabel: bool,
@@ -157,6 +159,7 @@ impl Console {
             #[cfg(feature = "sys")]
             engine,
             compiler,
+            limits: ProcessLimits::default(),
             abi,
             wizard,
             whitelabel: false,
@@ -203,6 +206,7 @@ impl Console {
         self.tty.set_bounds(rect.cols, rect.rows).await;
 
         Console::update_prompt(false, &self.state, &self.tty).await;
+        Console::restore_history(&self.state, &self.tty).await;
     }
 
     pub async fn init(&mut self) {
@@ -332,8 +336,25 @@ impl Console {
         state.rootfs.clone()
     }
 
+    /// Sets the resource limits that are applied to all the processes
+    /// started by this console
+    pub fn set_limits(&mut self, limits: ProcessLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &ProcessLimits {
+        &self.limits
+    }
+
+    /// Sets a secret environment variable that is passed to all the
+    /// processes started by this console
+    pub fn set_secret(&mut self, key: &str, val: String) {
+        let mut state = self.state.lock().unwrap();
+        state.env.set_secret(key, val);
+    }
+
     pub fn new_spawn_context(&self, job: &Job) -> SpawnContext {
-        let ctx = {
+        let mut ctx = {
             let state = self.state.lock().unwrap();
             SpawnContext::new(
                 self.abi.clone(),
@@ -351,6 +372,7 @@ impl Console {
                 self.compiler,
             )
         };
+        ctx.limits = self.limits.clone();
         ctx
     }
 
@@ -411,6 +433,7 @@ impl Console {
 
         if cmd.len() <= 0 {
             self.tty.reset_line().await;
+            Console::report_jobs(&self.reactor, &self.tty).await;
             self.tty.draw_prompt().await;
             return;
         }
@@ -422,6 +445,8 @@ impl Console {
             return;
         };
 
+        job.set_command(cmd.as_str());
+
         // Switch the console to this particular job
         let mut tty = self.tty.clone();
         tty.reset_line().await;
@@ -447,6 +472,17 @@ impl Console {
                 let rx = process.recv().await;
                 drop(process);
 
+                // Jobs that were suspended (Ctrl-Z) no longer own the terminal
+                // so all that is left to do is to record how they finished
+                if job.is_background() {
+                    let code = match rx.as_ref().map(|rx| &rx.status) {
+                        Some(EvalStatus::Executed { code, .. }) => *code,
+                        _ => err::ERR_ECONNABORTED,
+                    };
+                    job.finish(code);
+                    return;
+                }
+
                 // Flush all the pipes
                 let _ = stdout.flush_async().await;
                 let _ = stderr.flush_async().await;
@@ -506,6 +542,9 @@ impl Console {
                         state.path = ctx.working_dir;
                         state.last_return = ctx.last_return;
                     }
+                    if record_history {
+                        Console::persist_history(&state, &tty).await;
+                    }
                 } else {
                     debug!("eval recv erro");
                     tty.draw(format!("term: command failed\r\n").as_str()).await;
@@ -513,12 +552,61 @@ impl Console {
 
                 // Now draw the prompt ready for the next
                 tty.reset_line().await;
+                Console::report_jobs(&reactor, &tty).await;
                 Console::update_prompt(multiline_input, &state, &tty).await;
                 tty.draw_prompt().await;
             }
         });
     }
 
+    /// Reports the background jobs that have finished since the last prompt
+    async fn report_jobs(reactor: &Arc<RwLock<Reactor>>, tty: &Tty) {
+        let finished = {
+            let mut reactor = reactor.write().await;
+            reactor.reap_jobs()
+        };
+        let mut tty = tty.clone();
+        for (marker, job) in finished {
+            tty.draw(format!("{}\r\n", job.describe(marker)).as_str()).await;
+        }
+    }
+
+    /// Loads the history file of the user unless it was already loaded (the
+    /// file changes with HOME or HISTFILE, e.g. after logging in)
+    async fn restore_history(state: &Arc<Mutex<ConsoleState>>, tty: &Tty) {
+        // The file is claimed while the state is locked but read after the
+        // lock is released (file systems may block)
+        let (rootfs, path) = {
+            let mut state = state.lock().unwrap();
+            let path = history_file(&state.env);
+            if state.history_file.as_ref() == Some(&path) {
+                return;
+            }
+            state.history_file = Some(path.clone());
+            (state.rootfs.clone(), path)
+        };
+        let older = compact_history(&rootfs, path.as_str());
+        tty.merge_history(older).await;
+    }
+
+    /// Appends the command that was just run to the history file
+    async fn persist_history(state: &Arc<Mutex<ConsoleState>>, tty: &Tty) {
+        Console::restore_history(state, tty).await;
+        let cmd = tty.history().await.pop();
+        let (rootfs, path) = {
+            let state = state.lock().unwrap();
+            match state.history_file.clone() {
+                Some(path) => (state.rootfs.clone(), path),
+                None => return,
+            }
+        };
+        match cmd {
+            Some(cmd) => append_history(&rootfs, path.as_str(), cmd.as_str()),
+            // The history was cleared (`history -c`) so the file is as well
+            None => save_history(&rootfs, path.as_str(), &[]),
+        }
+    }
+
     async fn update_prompt(multiline_input: bool, state: &Arc<Mutex<ConsoleState>>, tty: &Tty) {
         let (prompt, prompt_color) = {
             let state = state.lock().unwrap();
@@ -540,9 +628,128 @@ impl Console {
         self.abi.cls().await;
     }
 
-    pub async fn on_tab(&mut self, _job: Option<Job>) {
-        // Later we need to implement auto-complete here when the process is not running
-        // and implement spaces when it is
+    pub async fn on_tab(&mut self, job: Option<Job>) {
+        // Lines that are typed into a running process are not completed
+        if job.is_some() {
+            return;
+        }
+
+        let (line, cursor_pos) = self.tty.line().await;
+        let left = &line[..cursor_pos];
+        let start = left
+            .rfind(|c: char| c.is_whitespace() || ";|&()<>".contains(c))
+            .map(|n| n + 1)
+            .unwrap_or(0);
+        let word = left[start..].to_string();
+        let before = left[..start].trim_end();
+        let is_command = word.contains('/') == false
+            && (before.len() <= 0 || before.ends_with(|c: char| ";|&(".contains(c)));
+
+        let candidates = match is_command {
+            true => self.complete_command(word.as_str()).await,
+            false => self.complete_path(word.as_str()),
+        };
+        if candidates.len() == 1 {
+            let mut text = candidates[0][word.len()..].to_string();
+            if text.ends_with('/') == false {
+                text.push(' ');
+            }
+            self.tty.add(text.as_str()).await;
+            return;
+        }
+        let prefix = common_prefix(&candidates[..]);
+        if prefix.len() > word.len() {
+            self.tty.add(&prefix[word.len()..]).await;
+            return;
+        }
+        if candidates.len() <= 0 {
+            return;
+        }
+
+        // Nothing more can be completed so the choices are listed instead
+        let choices = candidates
+            .iter()
+            .map(|c| completion_name(c.as_str()))
+            .collect::<Vec<_>>()
+            .join("  ");
+        self.tty.draw(format!("\r\n{}\r\n", choices).as_str()).await;
+        self.tty.draw_prompt().await;
+        self.tty.draw_line().await;
+    }
+
+    /// Finds the commands (builtins and binaries) that start with some text
+    async fn complete_command(&self, word: &str) -> Vec<String> {
+        let mut ret = Builtins::new()
+            .names()
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .collect::<Vec<_>>();
+        {
+            let alias = self.bins.alias.read().await;
+            ret.extend(
+                alias
+                    .iter()
+                    .filter(|(name, alias)| alias.is_some() && name.starts_with(word))
+                    .map(|(name, _)| name.clone()),
+            );
+        }
+        {
+            let cache = self.bins.cache.read().await;
+            ret.extend(
+                cache
+                    .iter()
+                    .filter(|(name, binary)| binary.is_some() && name.starts_with(word))
+                    .map(|(name, _)| name.clone()),
+            );
+        }
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    /// Finds the paths that start with some text, directories end with a
+    /// slash so that completion can carry on into them
+    fn complete_path(&self, word: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let (dir, prefix) = match word.rfind('/') {
+            Some(n) => (&word[..n + 1], &word[n + 1..]),
+            None => ("", word),
+        };
+        let expanded = match dir.strip_prefix("~/") {
+            Some(rest) => {
+                let home = state.env.get("HOME").unwrap_or_else(|| "/".to_string());
+                format!("{}/{}", home.trim_end_matches('/'), rest)
+            }
+            None => dir.to_string(),
+        };
+        let path = match expanded.starts_with('/') {
+            true => expanded,
+            false => format!("{}/{}", state.path.trim_end_matches('/'), expanded),
+        };
+
+        let entries = match state.rootfs.read_dir(Path::new(path.as_str())) {
+            Ok(a) => a,
+            Err(_) => return Vec::new(),
+        };
+        let mut ret = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            let entry_path = entry.path();
+            let name = match entry_path.file_name().and_then(|n| n.to_str()) {
+                Some(a) => a,
+                None => continue,
+            };
+            // Hidden files are only completed when asked for
+            if name.starts_with(prefix) == false || (name.starts_with('.') && prefix.starts_with('.') == false) {
+                continue;
+            }
+            let suffix = match entry.metadata() {
+                Ok(meta) if meta.is_dir() => "/",
+                _ => "",
+            };
+            ret.push(format!("{}{}{}", dir, name, suffix));
+        }
+        ret.sort();
+        ret
     }
 
     pub async fn on_page_up(&mut self) {}
@@ -591,6 +798,32 @@ impl Console {
         }
     }
 
+    pub async fn on_ctrl_z(&mut self, job: Job) {
+        if self.wizard.is_some() {
+            return;
+        }
+        self.tty.draw("^Z\r\n").await;
+
+        // Jobs that were already in the background are being waited on by
+        // the `fg` builtin which takes the terminal back itself
+        let owned = job.is_background() == false;
+        let msg = {
+            let mut reactor = self.reactor.write().await;
+            job.stop(&reactor);
+            reactor.background_job(&job);
+            job.describe(reactor.job_marker(job.id))
+        };
+        self.tty.draw(format!("{}\r\n", msg).as_str()).await;
+
+        if owned {
+            self.tty.switch_mode(TtyMode::Console).await;
+            self.tty.reset_paragraph().await;
+            self.tty.reset_line().await;
+            Console::update_prompt(false, &self.state, &self.tty).await;
+            self.tty.draw_prompt().await;
+        }
+    }
+
     pub async fn on_resize(&mut self) {
         let rect = self.abi.console_rect().await;
         self.tty.set_bounds(rect.cols, rect.rows).await;
@@ -598,6 +831,21 @@ impl Console {
 
     pub async fn on_parse(&mut self, data: &str, job: Option<Job>) {
         //error!("on_parse {}", data.as_bytes().iter().map(|byte| format!("\\u{{{:04X}}}", byte).to_owned()).collect::<Vec<String>>().join(""));
+
+        // Text typed during a reverse search refines it while any other key
+        // ends the search (and is then handled as usual)
+        if job.is_none() && self.tty.is_searching().await {
+            match data {
+                "\u{0012}" => return self.tty.search_history().await,
+                "\u{007F}" | "\u{0008}" => return self.tty.search_backspace().await,
+                "\u{0007}" => return self.tty.search_abort().await,
+                data if data.chars().all(|c| c.is_control() == false) => {
+                    return self.tty.search_add(data).await;
+                }
+                _ => self.tty.search_accept().await,
+            }
+        }
+
         match data {
             "\r" | "\u{000A}" => {
                 self.on_enter().await;
@@ -606,30 +854,75 @@ impl Console {
                 // Ctrl-C
                 self.on_ctrl_c(job).await;
             }
-            "\u{007F}" => {
+            "\u{007F}" | "\u{0008}" => {
+                // Backspace and Ctrl-H
                 self.tty.backspace().await;
             }
+            "\u{0004}" | "\u{001B}\u{005B}\u{0033}\u{007E}" => {
+                // Ctrl-D and Delete
+                self.tty.delete().await;
+            }
             "\u{0009}" if self.wizard.is_none() => {
                 self.on_tab(job).await;
             }
-            "\u{001B}\u{005B}\u{0044}" => {
+            "\u{0012}" if self.wizard.is_none() => {
+                // Ctrl-R
+                if job.is_none() {
+                    self.tty.search_history().await;
+                }
+            }
+            "\u{0002}" | "\u{001B}\u{005B}\u{0044}" => {
                 self.tty.cursor_left().await;
             }
-            "\u{001B}\u{005B}\u{0043}" => {
+            "\u{0006}" | "\u{001B}\u{005B}\u{0043}" => {
                 self.tty.cursor_right().await;
             }
-            "\u{0001}" | "\u{001B}\u{005B}\u{0048}" => {
+            "\u{001B}\u{0062}" | "\u{001B}\u{005B}\u{0031}\u{003B}\u{0035}\u{0044}" => {
+                // Alt-B and Ctrl-Left
+                self.tty.cursor_word_left().await;
+            }
+            "\u{001B}\u{0066}" | "\u{001B}\u{005B}\u{0031}\u{003B}\u{0035}\u{0043}" => {
+                // Alt-F and Ctrl-Right
+                self.tty.cursor_word_right().await;
+            }
+            "\u{0001}"
+            | "\u{001B}\u{005B}\u{0048}"
+            | "\u{001B}\u{004F}\u{0048}"
+            | "\u{001B}\u{005B}\u{0031}\u{007E}" => {
                 self.tty.set_cursor_to_start().await;
             }
-            "\u{001B}\u{005B}\u{0046}" => {
+            "\u{0005}"
+            | "\u{001B}\u{005B}\u{0046}"
+            | "\u{001B}\u{004F}\u{0046}"
+            | "\u{001B}\u{005B}\u{0034}\u{007E}" => {
                 self.tty.set_cursor_to_end().await;
             }
-            "\u{001B}\u{005B}\u{0041}" if self.wizard.is_none() => {
+            "\u{000B}" => {
+                // Ctrl-K
+                self.tty.kill_to_end().await;
+            }
+            "\u{0015}" => {
+                // Ctrl-U
+                self.tty.kill_to_start().await;
+            }
+            "\u{0017}" | "\u{001B}\u{007F}" => {
+                // Ctrl-W and Alt-Backspace
+                self.tty.kill_word_left().await;
+            }
+            "\u{001B}\u{0064}" => {
+                // Alt-D
+                self.tty.kill_word_right().await;
+            }
+            "\u{0019}" => {
+                // Ctrl-Y
+                self.tty.yank().await;
+            }
+            "\u{0010}" | "\u{001B}\u{005B}\u{0041}" if self.wizard.is_none() => {
                 if job.is_none() {
                     self.tty.cursor_up().await;
                 }
             }
-            "\u{001B}\u{005B}\u{0042}" if self.wizard.is_none() => {
+            "\u{000E}" | "\u{001B}\u{005B}\u{0042}" if self.wizard.is_none() => {
                 if job.is_none() {
                     self.tty.cursor_down().await;
                 }
@@ -680,10 +973,13 @@ impl Console {
                 // which allows the line to be 'edited' in the terminal before its submitted
                 if self.tty.is_buffering()
                 {
-                    // Ctrl-C is not fed to the process and always actioned
+                    // Ctrl-C and Ctrl-Z are not fed to the process and always actioned
                     if data == "\u{0003}" {
                         self.on_ctrl_c(Some(job)).await
                     }
+                    else if data == "\u{001A}" {
+                        self.on_ctrl_z(job).await
+                    }
                     else {
                         self.on_parse(&data, Some(job)).await
                     }
@@ -711,3 +1007,61 @@ impl Console {
         }
     }
 }
+
+/// Returns the longest prefix that all the completions share
+fn common_prefix(candidates: &[String]) -> String {
+    let mut ret = match candidates.first() {
+        Some(a) => a.clone(),
+        None => return String::new(),
+    };
+    for candidate in candidates.iter().skip(1) {
+        let len = ret
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map(|((n, a), _)| n + a.len_utf8())
+            .unwrap_or(0);
+        ret.truncate(len);
+    }
+    ret
+}
+
+/// Name a completion is listed under (the last component of a path)
+fn completion_name(candidate: &str) -> &str {
+    let trimmed = candidate.strip_suffix('/').unwrap_or(candidate);
+    let start = trimmed.rfind('/').map(|n| n + 1).unwrap_or(0);
+    &candidate[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn common_prefix_of_completions() {
+        let cases: &[(&[&str], &str)] = &[
+            (&[], ""),
+            (&["echo"], "echo"),
+            (&["export", "exit", "exec"], "ex"),
+            (&["/bin/", "/bin/ls"], "/bin/"),
+            (&["abc", "xyz"], ""),
+            (&["héllo", "hélp"], "hél"),
+            (&["é", "è"], ""),
+        ];
+        for (candidates, expected) in cases {
+            assert_eq!(common_prefix(&strings(candidates)[..]), *expected, "{:?}", candidates);
+        }
+    }
+
+    #[test]
+    fn completions_are_listed_by_name() {
+        assert_eq!(completion_name("/usr/bin/ls"), "ls");
+        assert_eq!(completion_name("/usr/bin/"), "bin/");
+        assert_eq!(completion_name("file"), "file");
+    }
+}
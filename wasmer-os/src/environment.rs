@@ -4,17 +4,41 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Clone, Default)]
 pub struct Val {
     pub var_eq: Option<String>,
     pub export: bool,
     pub readonly: bool,
+    /// Secret values are hidden whenever the environment is formatted
+    /// for debugging (so they do not end up in the logs)
+    pub secret: bool,
+}
+
+impl fmt::Debug
+for Val
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let var_eq = match (&self.var_eq, self.secret) {
+            (Some(_), true) => Some("[redacted]"),
+            (Some(a), false) => Some(a.as_str()),
+            (None, _) => None,
+        };
+        f.debug_struct("Val")
+            .field("var_eq", &var_eq)
+            .field("export", &self.export)
+            .field("readonly", &self.readonly)
+            .field("secret", &self.secret)
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Environment {
     vars: HashMap<String, Val>,
+    /// Shell functions (stored as the source code of their body)
+    functions: HashMap<String, String>,
 }
 
 impl Environment {
@@ -22,6 +46,18 @@ impl Environment {
         self.set_vareq_with_key(key.to_string(), format!("{}={}", key, val));
     }
 
+    /// Sets an exported variable whose value will never be shown when
+    /// the environment is logged (secrets are also read-only so that
+    /// processes can not replace them)
+    pub fn set_secret(&mut self, key: &str, val: String) {
+        self.vars.insert(key.to_string(), Val {
+            var_eq: Some(format!("{}={}", key, val)),
+            export: true,
+            readonly: true,
+            secret: true,
+        });
+    }
+
     pub fn set_vareq(&mut self, var_eq: String) {
         let key: String = self.parse_key(&var_eq);
         self.set_vareq_with_key(key, var_eq);
@@ -86,6 +122,18 @@ impl Environment {
         };
     }
 
+    pub fn set_function(&mut self, name: &str, body: String) {
+        self.functions.insert(name.to_string(), body);
+    }
+
+    pub fn unset_function(&mut self, name: &str) {
+        self.functions.remove(name);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&String> {
+        self.functions.get(name)
+    }
+
     pub fn into_exported(self) -> Vec<String> {
         self.vars
             .into_iter()
@@ -107,6 +155,7 @@ impl Environment {
 pub fn empty() -> Environment {
     Environment {
         vars: HashMap::new(),
+        functions: HashMap::new(),
     }
 }
 
@@ -118,3 +167,45 @@ pub fn from_system() -> Environment {
     });
     e
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let mut env = Environment::default();
+        env.set_var("HOME", "/home/user".to_string());
+        env.set_secret("API_KEY", "hunter2".to_string());
+
+        let dbg = format!("{:?}", env);
+        assert!(dbg.contains("hunter2") == false, "{}", dbg);
+        assert!(dbg.contains("[redacted]"), "{}", dbg);
+        assert!(dbg.contains("HOME=/home/user"), "{}", dbg);
+
+        // (the value is still available to the processes)
+        assert_eq!(env.get("API_KEY"), Some("hunter2".to_string()));
+        assert!(env.into_exported().contains(&"API_KEY=hunter2".to_string()));
+    }
+
+    #[test]
+    fn secrets_are_read_only() {
+        let mut env = Environment::default();
+        env.set_secret("API_KEY", "hunter2".to_string());
+
+        env.set_var("API_KEY", "other".to_string());
+        env.unset("API_KEY");
+        assert_eq!(env.get("API_KEY"), Some("hunter2".to_string()));
+    }
+
+    #[test]
+    fn unset_values_are_not_redacted() {
+        let val = Val {
+            var_eq: None,
+            secret: true,
+            ..Default::default()
+        };
+        let dbg = format!("{:?}", val);
+        assert!(dbg.contains("var_eq: None"), "{}", dbg);
+    }
+}
@@ -14,6 +14,9 @@ pub(super) async fn andor_list<'a>(
         ctx = c;
         ret = r;
         ctx.last_return = ret;
+        if ctx.jump.is_some() {
+            break;
+        }
 
         match op {
             ast::AndOrOp::And => {
@@ -0,0 +1,373 @@
+//! Arithmetic expansion (`$((...))`) evaluates C style integer expressions
+//! where variables are referred to by their name (without a `$`)
+use std::collections::HashMap;
+
+use crate::environment::Environment;
+
+/// Variables that can be read and assigned by an arithmetic expression
+pub trait ArithEnv {
+    fn get(&self, name: &str) -> Option<String>;
+    fn set(&mut self, name: &str, value: i64);
+}
+
+impl ArithEnv for Environment {
+    fn get(&self, name: &str) -> Option<String> {
+        Environment::get(self, name)
+    }
+
+    fn set(&mut self, name: &str, value: i64) {
+        self.set_var(name, value.to_string());
+    }
+}
+
+impl ArithEnv for HashMap<String, String> {
+    fn get(&self, name: &str) -> Option<String> {
+        HashMap::get(self, name).cloned()
+    }
+
+    fn set(&mut self, name: &str, value: i64) {
+        self.insert(name.to_string(), value.to_string());
+    }
+}
+
+/// Evaluates an arithmetic expression, an empty expression is zero
+pub fn arith_eval(expr: &str, env: &mut dyn ArithEnv) -> Result<i64, String> {
+    eval_depth(expr, env, 0)
+}
+
+// Variables may hold expressions themselves which is limited so that a
+// variable that refers to itself can not recurse forever
+const MAX_DEPTH: u32 = 16;
+
+fn eval_depth(expr: &str, env: &mut dyn ArithEnv, depth: u32) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("{}: expression recursion level exceeded", expr.trim()));
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let ast = parser.expression()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("{}: syntax error in expression", expr.trim()));
+    }
+    ast.eval(env, depth)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+// Longer operators come first so that they are matched in preference
+const OPERATORS: [&'static str; 37] = [
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=",
+    "%=", "&=", "|=", "^=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~", "=",
+    "?", ":", "(", ")", ",",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut ret = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| c.is_ascii_alphanumeric() == false && c != '_' && c != '#')
+                .unwrap_or(rest.len());
+            ret.push(Token::Num(parse_number(&rest[..len])?));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| c.is_ascii_alphanumeric() == false && c != '_')
+                .unwrap_or(rest.len());
+            ret.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    ret.push(Token::Op(op));
+                    rest = &rest[op.len()..];
+                }
+                None => {
+                    return Err(format!("{}: syntax error: invalid arithmetic operator (error token is \"{}\")", expr.trim(), rest));
+                }
+            }
+        }
+        rest = rest.trim_start();
+    }
+    Ok(ret)
+}
+
+/// Parses a number as a shell would (`0x` is hex, a leading `0` is octal
+/// and `base#digits` uses any base from 2 to 36)
+fn parse_number(text: &str) -> Result<i64, String> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if base >= 2 && base <= 36 => (base, digits),
+            _ => return Err(format!("{}: invalid arithmetic base", text)),
+        }
+    } else if text.starts_with("0x") || text.starts_with("0X") {
+        (16, &text[2..])
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    i64::from_str_radix(digits, base).map_err(|_| format!("{}: value too great for base", text))
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Assign(&'static str, String, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+/// Binding power of the binary operators (higher binds tighter)
+fn binary_power(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 3,
+        "&&" => 4,
+        "|" => 5,
+        "^" => 6,
+        "&" => 7,
+        "==" | "!=" => 8,
+        "<" | "<=" | ">" | ">=" => 9,
+        "<<" | ">>" => 10,
+        "+" | "-" => 11,
+        "*" | "/" | "%" => 12,
+        "**" => 13,
+        _ => return None,
+    })
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.peek_op() {
+            Some(o) if o == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(format!("syntax error: `{}' expected", op)),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut left = self.assignment()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            let right = self.assignment()?;
+            left = Expr::Comma(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let (Some(Token::Ident(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if op.ends_with('=') && *op != "==" && *op != "!=" && *op != "<=" && *op != ">=" {
+                let name = name.clone();
+                let op = *op;
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(op, name, Box::new(value)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(0)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.expression()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Cond(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary(&mut self, min_power: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek_op() {
+            let power = match binary_power(op) {
+                Some(p) if p > min_power => p,
+                _ => break,
+            };
+            self.pos += 1;
+            // Exponents are right associative
+            let right = match op {
+                "**" => self.binary(power - 1)?,
+                _ => self.binary(power)?,
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek().cloned() {
+            Some(Token::Op(op)) if op == "-" || op == "+" || op == "!" || op == "~" => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let ret = self.expression()?;
+                self.expect(")")?;
+                Ok(ret)
+            }
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(Expr::Var(name))
+            }
+            Some(Token::Op(op)) => Err(format!("syntax error: operand expected (error token is \"{}\")", op)),
+            None => Err(format!("syntax error: operand expected")),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, env: &mut dyn ArithEnv, depth: u32) -> Result<i64, String> {
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => match env.get(name) {
+                Some(val) if val.trim().is_empty() == false => eval_depth(val.as_str(), env, depth + 1)?,
+                _ => 0,
+            },
+            Expr::Unary(op, a) => {
+                let a = a.eval(env, depth)?;
+                match *op {
+                    "-" => a.wrapping_neg(),
+                    "!" => (a == 0) as i64,
+                    "~" => !a,
+                    _ => a,
+                }
+            }
+            Expr::Binary("&&", a, b) => (a.eval(env, depth)? != 0 && b.eval(env, depth)? != 0) as i64,
+            Expr::Binary("||", a, b) => (a.eval(env, depth)? != 0 || b.eval(env, depth)? != 0) as i64,
+            Expr::Binary(op, a, b) => {
+                let a = a.eval(env, depth)?;
+                let b = b.eval(env, depth)?;
+                apply(op, a, b)?
+            }
+            Expr::Assign(op, name, value) => {
+                let value = value.eval(env, depth)?;
+                let value = match op.strip_suffix('=') {
+                    Some("") | None => value,
+                    Some(op) => apply(op, Expr::Var(name.clone()).eval(env, depth)?, value)?,
+                };
+                env.set(name, value);
+                value
+            }
+            Expr::Cond(c, a, b) => match c.eval(env, depth)? {
+                0 => b.eval(env, depth)?,
+                _ => a.eval(env, depth)?,
+            },
+            Expr::Comma(a, b) => {
+                a.eval(env, depth)?;
+                b.eval(env, depth)?
+            }
+        })
+    }
+}
+
+fn apply(op: &str, a: i64, b: i64) -> Result<i64, String> {
+    Ok(match op {
+        "+" => a.wrapping_add(b),
+        "-" => a.wrapping_sub(b),
+        "*" => a.wrapping_mul(b),
+        "/" | "%" if b == 0 => return Err(format!("division by 0")),
+        "/" => a.wrapping_div(b),
+        "%" => a.wrapping_rem(b),
+        "**" if b < 0 => return Err(format!("exponent less than 0")),
+        "**" => a.wrapping_pow(b.min(u32::MAX as i64) as u32),
+        "<<" => a.wrapping_shl(b as u32),
+        ">>" => a.wrapping_shr(b as u32),
+        "<" => (a < b) as i64,
+        "<=" => (a <= b) as i64,
+        ">" => (a > b) as i64,
+        ">=" => (a >= b) as i64,
+        "==" => (a == b) as i64,
+        "!=" => (a != b) as i64,
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        _ => return Err(format!("{}: invalid operator", op)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, String> {
+        let mut env = HashMap::new();
+        env.insert("x".to_string(), "5".to_string());
+        env.insert("y".to_string(), "x * 2".to_string());
+        arith_eval(expr, &mut env)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("-3 % 2"), Ok(-1));
+        assert_eq!(eval("1 < 2 && 2 < 1 || !0"), Ok(1));
+        assert_eq!(eval("x > 3 ? 10 : 20"), Ok(10));
+        assert_eq!(eval(""), Ok(0));
+    }
+
+    #[test]
+    fn numbers_and_variables() {
+        assert_eq!(eval("0x10 + 010 + 2#101"), Ok(16 + 8 + 5));
+        assert_eq!(eval("y + 1"), Ok(11));
+        assert_eq!(eval("missing + 1"), Ok(1));
+    }
+
+    #[test]
+    fn assignments() {
+        let mut env = HashMap::new();
+        assert_eq!(arith_eval("a = 2, a += 3, a *= 2", &mut env), Ok(10));
+        assert_eq!(env.get("a").map(|a| a.as_str()), Some("10"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 $ 2").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Brace expansion turns `pre{a,b}post` into `preapost prebpost` and
+//! `{1..3}` into `1 2 3`, it happens before any other expansion and
+//! braces that are quoted (or part of a `${...}`) are left alone
+
+/// Expands all the brace expressions in a word (as it was written)
+pub fn expand_braces(word: &str) -> Vec<String> {
+    match find_brace(word) {
+        Some((start, end, alternatives)) => {
+            let prefix = &word[..start];
+            let suffix = &word[end + 1..];
+            let mut ret = Vec::new();
+            for alt in alternatives {
+                ret.extend(expand_braces(format!("{}{}{}", prefix, alt, suffix).as_str()));
+            }
+            ret
+        }
+        None => vec![word.to_string()],
+    }
+}
+
+/// Scans the word while tracking the quotes and reports the position of
+/// each character that is not quoted or escaped
+fn unquoted_chars(word: &str) -> Vec<(usize, char)> {
+    let mut ret = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for (n, c) in word.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, c) => ret.push((n, c)),
+        }
+    }
+    ret
+}
+
+/// Finds the first brace expression in the word and returns where it
+/// starts and ends along with the words it expands into
+fn find_brace(word: &str) -> Option<(usize, usize, Vec<String>)> {
+    let chars = unquoted_chars(word);
+    for (i, (start, c)) in chars.iter().enumerate() {
+        if *c != '{' || (i > 0 && chars[i - 1] == (start - 1, '$')) {
+            continue;
+        }
+
+        // Find the matching close brace and the commas at the top level
+        let mut depth = 0usize;
+        let mut commas = Vec::new();
+        let mut end = None;
+        for (n, c) in chars[i..].iter() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(*n);
+                        break;
+                    }
+                }
+                ',' if depth == 1 => commas.push(*n),
+                _ => {}
+            }
+        }
+        let end = match end {
+            Some(a) => a,
+            None => continue,
+        };
+
+        if commas.is_empty() == false {
+            let mut alternatives = Vec::new();
+            let mut last = start + 1;
+            for comma in commas {
+                alternatives.push(word[last..comma].to_string());
+                last = comma + 1;
+            }
+            alternatives.push(word[last..end].to_string());
+            return Some((*start, end, alternatives));
+        }
+        if let Some(sequence) = sequence(&word[start + 1..end]) {
+            return Some((*start, end, sequence));
+        }
+    }
+    None
+}
+
+/// Expands a sequence expression (`1..5`, `a..e` or `0..10..2`)
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let parts = text.split("..").collect::<Vec<_>>();
+    let (from, to, step) = match parts[..] {
+        [from, to] => (from, to, 1i64),
+        [from, to, step] => (from, to, step.parse::<i64>().ok()?.abs().max(1)),
+        _ => return None,
+    };
+
+    if let (Ok(a), Ok(b)) = (from.parse::<i64>(), to.parse::<i64>()) {
+        // Numbers with leading zeros are padded to the same width
+        let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = match padded(from) || padded(to) {
+            true => from.len().max(to.len()),
+            false => 0,
+        };
+        return Some(
+            range(a, b, step)
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        );
+    }
+
+    let mut from_chars = from.chars();
+    let mut to_chars = to.chars();
+    match (from_chars.next(), from_chars.next(), to_chars.next(), to_chars.next()) {
+        (Some(a), None, Some(b), None) if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => Some(
+            range(a as i64, b as i64, step)
+                .map(|n| (n as u8 as char).to_string())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn range(from: i64, to: i64, step: i64) -> impl Iterator<Item = i64> {
+    let count = ((to - from).abs() / step) as usize + 1;
+    let step = if to < from { -step } else { step };
+    (0..count).map(move |n| from + step * n as i64)
+}
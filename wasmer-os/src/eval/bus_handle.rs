@@ -4,7 +4,7 @@ use async_trait::async_trait;
 use derivative::Derivative;
 use serde::*;
 use tokio::sync::mpsc;
-use wasmer_bus::{abi::SerializationFormat, prelude::BusError};
+use wasmer_bus::{abi::{SerializationFormat, StreamCredit, StreamData, StreamEnd, STREAM_WINDOW}, prelude::BusError};
 use wasmer_vbus::{VirtualBusError, BusDataFormat, VirtualBusInvocation, BusInvocationEvent, VirtualBusScope, VirtualBusInvokable, InstantInvocation, VirtualBusInvoked};
 use crate::{bus::{conv_format, Processable, InvokeResult}, api::abi::SystemAbiExt};
 
@@ -132,24 +132,107 @@ impl RuntimeCallOutsideHandle
         Err(BusError::Aborted)
     }
 
-    pub fn block_on(mut self) -> Result<RuntimeCallResult, BusError> {
+    pub fn block_on(self) -> Result<RuntimeCallResult, BusError> {
+        self.block_on_with(|_, _, _, buf| Some(buf))
+    }
+
+    /// Blocks on a call that takes a stream argument, the items are only
+    /// produced as the callee grants credit for them (it always does so first)
+    pub fn block_on_send_stream<T, I>(self, format: SerializationFormat, items: I) -> Result<RuntimeCallResult, BusError>
+    where I: IntoIterator<Item = T>,
+          T: ser::Serialize
+    {
+        let mut items = items.into_iter().peekable();
+        let mut ended = false;
+        self.block_on_with(move |task, topic_hash, credit_format, buf| {
+            if topic_hash != type_name_hash::<StreamCredit>() {
+                return Some(buf);
+            }
+            if ended {
+                return None;
+            }
+            let credit: StreamCredit = match credit_format.deserialize(buf) {
+                Ok(a) => a,
+                Err(err) => {
+                    debug!("error while processing stream credit - {}", err);
+                    return None;
+                }
+            };
+            for _ in 0..credit.frames {
+                match items.next().map(|item| format.serialize(item)) {
+                    Some(Ok(data)) => {
+                        let _ = task.call(format, StreamData { data });
+                    }
+                    Some(Err(err)) => {
+                        let _ = task.call(format, StreamEnd { error: Some(err) });
+                        ended = true;
+                        return None;
+                    }
+                    None => break,
+                }
+            }
+            if items.peek().is_none() {
+                let _ = task.call(format, StreamEnd { error: None });
+                ended = true;
+            }
+            None
+        })
+    }
+
+    /// Blocks on a call that returns a stream, the frames are passed to the
+    /// function as they arrive (so it may write them straight to a buffer)
+    /// and the callee is granted more credit as they are
+    pub fn block_on_receive_stream<T, F>(self, format: SerializationFormat, mut f: F) -> Result<RuntimeCallResult, BusError>
+    where F: FnMut(Result<T, BusError>),
+          T: de::DeserializeOwned
+    {
+        let mut received = 0u32;
+        self.block_on_with(move |task, topic_hash, frame_format, buf| {
+            if topic_hash != type_name_hash::<StreamData>() {
+                return Some(buf);
+            }
+            match frame_format.deserialize::<StreamData>(buf) {
+                Ok(frame) => f(format.deserialize(frame.data)),
+                Err(err) => f(Err(err)),
+            }
+            received += 1;
+            if received >= STREAM_WINDOW / 2 {
+                let _ = task.call(format, StreamCredit { frames: received });
+                received = 0;
+            }
+            None
+        })
+    }
+
+    /// Blocks until the call is answered, the callbacks are first offered to
+    /// the function (which may borrow from the caller) and those that it
+    /// hands back go to the callbacks that are registered
+    fn block_on_with<F>(mut self, mut f: F) -> Result<RuntimeCallResult, BusError>
+    where F: FnMut(&RuntimeCallOutsideTask, u128, SerializationFormat, Vec<u8>) -> Option<Vec<u8>>
+    {
         loop {
-            match self.rx.try_recv() {
-                Ok(msg) => {
-                    if let Some((format, value)) = self.process_msg(msg)? {
-                        return Ok(RuntimeCallResult {
-                            handle: self,
-                            format,
-                            value,
-                        });
+            let msg = match self.rx.try_recv() {
+                Ok(RuntimeCallStateChange::Callback { topic_hash, format, buf }) => {
+                    match f(&self.task, topic_hash, format, buf) {
+                        Some(buf) => RuntimeCallStateChange::Callback { topic_hash, format, buf },
+                        None => continue,
                     }
                 },
+                Ok(msg) => msg,
                 Err(mpsc::error::TryRecvError::Disconnected) => {
                     return Err(BusError::Aborted);
                 }
                 Err(mpsc::error::TryRecvError::Empty) => {
                     std::thread::sleep(Duration::from_millis(1));
+                    continue;
                 }
+            };
+            if let Some((format, value)) = self.process_msg(msg)? {
+                return Ok(RuntimeCallResult {
+                    handle: self,
+                    format,
+                    value,
+                });
             }
         }
     }
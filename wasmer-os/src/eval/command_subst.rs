@@ -0,0 +1,90 @@
+use std::future::Future;
+use std::pin::Pin;
+use tokio::sync::mpsc::error::TryRecvError;
+
+use super::*;
+use crate::fd::*;
+use crate::pipe::*;
+
+/// Runs the commands of a command substitution and returns what they wrote
+/// to stdout (without the trailing newlines), the commands run in a subshell
+/// thus any changes they make to the shell are thrown away
+pub(super) fn command_subst<'a>(
+    ctx: &'a EvalContext,
+    builtins: &'a Builtins,
+    script: &'a str,
+) -> Pin<Box<dyn Future<Output = String> + Send + 'a>> {
+    Box::pin(async move {
+        let mut stderr = ctx.stdio.stderr.clone();
+        let script = grammar::here_doc::inline_here_docs(script).unwrap_or_default();
+        let parser = grammar::programParser::new();
+        let program = match parser.parse(script.as_str()) {
+            Ok(a) => a,
+            Err(err) => {
+                debug!("command substitution failed to parse - {}", err);
+                let _ = stderr
+                    .write(format!("sh: invalid command substitution\r\n").as_bytes())
+                    .await;
+                return String::new();
+            }
+        };
+
+        // The output is captured rather than being sent to the terminal
+        let (stdout, mut rx) = pipe_out(FdFlag::Stdout(false));
+        let mut sub_ctx = ctx.clone();
+        sub_ctx.stdio.stdout = stdout;
+        let work = async {
+            let mut ctx = sub_ctx;
+            let mut show_result = false;
+            for cc in program.commands.complete_commands.iter() {
+                let (c, _) = complete_command(ctx, builtins, cc, &mut show_result).await;
+                ctx = c;
+                if ctx.jump.is_some() {
+                    break;
+                }
+            }
+        };
+        tokio::pin!(work);
+
+        let mut output = Vec::new();
+        let mut finished = false;
+        while finished == false {
+            tokio::select! {
+                _ = &mut work => {
+                    finished = true;
+                }
+                msg = rx.recv() => {
+                    match msg {
+                        Some(FdMsg::Data { data, .. }) => {
+                            output.extend_from_slice(&data[..]);
+                        }
+                        Some(FdMsg::Flush { tx }) => {
+                            let _ = tx.send(()).await;
+                        }
+                        None => {
+                            (&mut work).await;
+                            finished = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Pick up anything that was written just before the commands finished
+        loop {
+            match rx.try_recv() {
+                Ok(FdMsg::Data { data, .. }) => output.extend_from_slice(&data[..]),
+                Ok(FdMsg::Flush { tx }) => {
+                    let _ = tx.try_send(());
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        let mut output = String::from_utf8_lossy(&output[..]).to_string();
+        while output.ends_with('\n') || output.ends_with('\r') {
+            output.pop();
+        }
+        output
+    })
+}
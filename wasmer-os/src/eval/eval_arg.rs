@@ -1,16 +1,729 @@
+//! Word expansion turns the words of a command (as they were written) into
+//! the fields that are passed to it, the expansions happen in the same order
+//! as other shells: brace, tilde, parameter, command substitution and
+//! arithmetic, field splitting, pathname expansion and finally the removal
+//! of the quotes. Text that is quoted is never split or used as a pattern.
+//!
+//! When an expansion fails its error has already been written to stderr
+//! and only the exit code of the failed command is returned.
+use std::future::Future;
+use std::pin::Pin;
+
 use super::*;
 
-pub(super) fn eval_arg(env: &Environment, last_return: u32, arg: &str) -> String {
-    if arg.as_bytes()[0] == b'$' {
-        let key: &str = &arg[1..];
-        match key {
-            "?" => format!("{}", last_return),
-            _ => match env.get(key) {
-                Some(v) => v.clone(),
-                None => String::new(),
+/// How the quotes and escapes within the text are treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WordMode {
+    /// Word of a command
+    Word,
+    /// Value of an assignment (tildes may also follow a `:` or `=`)
+    Assignment,
+    /// Body of a here-document (quotes have no special meaning)
+    HereDoc,
+}
+
+/// Part of a word after it has been parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Text { text: String, quoted: bool },
+    /// `~` or `~user` at the start of a word
+    Tilde(String),
+    /// `$NAME` or `${...}` (the expression between the braces)
+    Param { expr: String, quoted: bool },
+    /// `$(...)` or backquotes
+    Command { script: String, quoted: bool },
+    /// `$((...))`
+    Arith { expr: String, quoted: bool },
+    /// `"$@"` which expands into a field per positional parameter
+    AllArgs,
+}
+
+/// Value of a segment once it has been expanded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resolved {
+    Text { text: String, quoted: bool, split: bool },
+    Fields(Vec<String>),
+}
+
+/// Character of a field along with whether it was quoted
+type Field = Vec<(char, bool)>;
+
+fn push_text(segs: &mut Vec<Segment>, text: &str, quoted: bool) {
+    if let Some(Segment::Text { text: last, quoted: q }) = segs.last_mut() {
+        if *q == quoted {
+            last.push_str(text);
+            return;
+        }
+    }
+    segs.push(Segment::Text {
+        text: text.to_string(),
+        quoted,
+    });
+}
+
+/// Splits a word into its literal text and the expansions within it
+pub(crate) fn parse_word(word: &str, mode: WordMode) -> Vec<Segment> {
+    let mut segs = Vec::new();
+    let mut rest = word;
+    let mut in_dquote = false;
+    let mut tilde = mode != WordMode::HereDoc;
+    while let Some(c) = rest.chars().next() {
+        // Tildes are only expanded at the start of a word (or assignment)
+        if c == '~' && tilde && in_dquote == false {
+            let end = rest
+                .find(|c| c == '/' || (c == ':' && mode == WordMode::Assignment))
+                .unwrap_or(rest.len());
+            let user = &rest[1..end];
+            if user.chars().all(|c| c.is_ascii_alphanumeric() || "_-+.".contains(c)) {
+                segs.push(Segment::Tilde(user.to_string()));
+                rest = &rest[end..];
+                tilde = false;
+                continue;
+            }
+        }
+        tilde = false;
+
+        let quoted = in_dquote || mode == WordMode::HereDoc;
+        match c {
+            '\\' => {
+                let next = rest[1..].chars().next();
+                match (quoted, next) {
+                    (_, Some('\n')) => rest = &rest[2..],
+                    (false, Some(n)) => {
+                        push_text(&mut segs, &rest[1..1 + n.len_utf8()], true);
+                        rest = &rest[1 + n.len_utf8()..];
+                    }
+                    (true, Some(n)) if n == '$' || n == '`' || n == '\\' || (n == '"' && in_dquote) => {
+                        push_text(&mut segs, &rest[1..2], true);
+                        rest = &rest[2..];
+                    }
+                    _ => {
+                        push_text(&mut segs, "\\", true);
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            '\'' if quoted == false => match rest[1..].find('\'') {
+                Some(end) => {
+                    push_text(&mut segs, &rest[1..end + 1], true);
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    push_text(&mut segs, &rest[1..], true);
+                    rest = "";
+                }
             },
+            '"' if mode != WordMode::HereDoc => {
+                in_dquote = !in_dquote;
+                push_text(&mut segs, "", true);
+                rest = &rest[1..];
+            }
+            '`' => {
+                let (script, len) = backquote(rest);
+                segs.push(Segment::Command { script, quoted });
+                rest = &rest[len..];
+            }
+            '$' => {
+                let (seg, len) = dollar(rest, quoted);
+                match seg {
+                    Some(seg) => segs.push(seg),
+                    None => push_text(&mut segs, "$", quoted),
+                }
+                rest = &rest[len..];
+            }
+            c => {
+                if mode == WordMode::Assignment && quoted == false && (c == ':' || c == '=') {
+                    tilde = true;
+                }
+                push_text(&mut segs, &rest[..c.len_utf8()], quoted);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    segs
+}
+
+/// Parses a backquoted command substitution, within the backquotes a
+/// backslash only escapes `$`, `` ` `` and `\`
+fn backquote(text: &str) -> (String, usize) {
+    let mut script = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((n, c)) = chars.next() {
+        match c {
+            '`' => return (script, n + 1),
+            '\\' => match chars.next() {
+                Some((_, e)) if e == '$' || e == '`' || e == '\\' => script.push(e),
+                Some((_, e)) => {
+                    script.push('\\');
+                    script.push(e);
+                }
+                None => script.push('\\'),
+            },
+            c => script.push(c),
+        }
+    }
+    (script, text.len())
+}
+
+/// Parses the expansion at the start of the text (which starts with a `$`)
+/// and returns it along with the number of bytes it used
+fn dollar(text: &str, quoted: bool) -> (Option<Segment>, usize) {
+    let body = &text[1..];
+    if body.starts_with("((") {
+        if let Some(end) = arith_end(&body[2..]) {
+            let expr = body[2..2 + end].to_string();
+            return (Some(Segment::Arith { expr, quoted }), end + 5);
+        }
+    }
+    if body.starts_with("(") {
+        return match closing_bracket(body, '(', ')') {
+            Some(end) => {
+                let script = body[1..end].to_string();
+                (Some(Segment::Command { script, quoted }), end + 2)
+            }
+            None => (None, 1),
+        };
+    }
+    if body.starts_with("{") {
+        return match closing_bracket(body, '{', '}') {
+            Some(end) => {
+                let expr = body[1..end].to_string();
+                match quoted && expr == "@" {
+                    true => (Some(Segment::AllArgs), end + 2),
+                    false => (Some(Segment::Param { expr, quoted }), end + 2),
+                }
+            }
+            None => (None, 1),
+        };
+    }
+
+    let len = match body.chars().next() {
+        Some(c) if "?#@*$!-".contains(c) || c.is_ascii_digit() => 1,
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => body
+            .find(|c: char| c.is_ascii_alphanumeric() == false && c != '_')
+            .unwrap_or(body.len()),
+        _ => return (None, 1),
+    };
+    let expr = body[..len].to_string();
+    match quoted && expr == "@" {
+        true => (Some(Segment::AllArgs), len + 1),
+        false => (Some(Segment::Param { expr, quoted }), len + 1),
+    }
+}
+
+/// Finds the `))` that ends an arithmetic expansion
+fn arith_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (n, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' if text[n + 1..].starts_with(')') => return Some(n),
+            ')' => return None,
+            _ => {}
         }
-    } else {
-        arg.to_string()
+    }
+    None
+}
+
+/// Finds the bracket that closes the one at the start of the text
+fn closing_bracket(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for (n, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, c) if c == open => depth += 1,
+            (None, c) if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(n);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the value of a special parameter, positional parameter or variable
+pub(super) fn parameter(ctx: &EvalContext, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(format!("{}", ctx.last_return)),
+        "#" => Some(format!("{}", ctx.positional.len())),
+        "@" | "*" => Some(ctx.positional.join(" ")),
+        "$" => Some(format!("{}", ctx.job.id)),
+        "!" => Some(ctx.last_background.map(|pid| pid.to_string()).unwrap_or_default()),
+        "-" => Some(String::new()),
+        "0" => Some("sh".to_string()),
+        _ => match usize::from_str_radix(name, 10) {
+            Ok(n) => ctx.positional.get(n - 1).cloned(),
+            Err(_) => ctx.env.get(name),
+        },
+    }
+}
+
+/// Expands the segments of a word into their values
+pub(crate) fn resolve<'a>(
+    ctx: &'a mut EvalContext,
+    builtins: &'a Builtins,
+    segs: Vec<Segment>,
+) -> Pin<Box<dyn Future<Output = Result<Vec<Resolved>, u32>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut ret = Vec::with_capacity(segs.len());
+        for seg in segs {
+            ret.push(match seg {
+                Segment::Text { text, quoted } => Resolved::Text {
+                    text,
+                    quoted,
+                    split: false,
+                },
+                Segment::Tilde(user) => {
+                    let text = match user.as_str() {
+                        "" => ctx.env.get("HOME").unwrap_or_else(|| "/".to_string()),
+                        "+" => ctx.working_dir.trim_end_matches('/').to_string(),
+                        "-" => ctx.env.get("OLDPWD").unwrap_or_else(|| "~-".to_string()),
+                        _ => format!("~{}", user),
+                    };
+                    Resolved::Text {
+                        text,
+                        quoted: true,
+                        split: false,
+                    }
+                }
+                Segment::Param { expr, quoted } => Resolved::Text {
+                    text: expand_parameter(ctx, builtins, expr.as_str()).await?,
+                    quoted,
+                    split: quoted == false,
+                },
+                Segment::Command { script, quoted } => Resolved::Text {
+                    text: command_subst(ctx, builtins, script.as_str()).await,
+                    quoted,
+                    split: quoted == false,
+                },
+                Segment::Arith { expr, quoted } => {
+                    let expr = expand_single(ctx, builtins, expr.as_str()).await?;
+                    match arith_eval(expr.as_str(), &mut ctx.env) {
+                        Ok(val) => Resolved::Text {
+                            text: val.to_string(),
+                            quoted,
+                            split: quoted == false,
+                        },
+                        Err(err) => {
+                            let _ = ctx.stdio.stderr.write(format!("sh: {}\r\n", err).as_bytes()).await;
+                            return Err(1);
+                        }
+                    }
+                }
+                Segment::AllArgs => Resolved::Fields(ctx.positional.clone()),
+            });
+        }
+        Ok(ret)
+    })
+}
+
+/// Expands a `${...}` expression including its modifiers (`:-`, `:=`,
+/// `:?`, `:+`, `#`, `##`, `%`, `%%` and `${#NAME}`)
+async fn expand_parameter(ctx: &mut EvalContext, builtins: &Builtins, expr: &str) -> Result<String, u32> {
+    if expr.len() > 1 && expr.starts_with('#') {
+        let val = parameter(ctx, &expr[1..]).unwrap_or_default();
+        return Ok(format!("{}", val.chars().count()));
+    }
+
+    let name_len = match expr.chars().next() {
+        Some(c) if "?#@*$!-".contains(c) => 1,
+        Some(c) if c.is_ascii_digit() => expr.find(|c: char| c.is_ascii_digit() == false).unwrap_or(expr.len()),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => expr
+            .find(|c: char| c.is_ascii_alphanumeric() == false && c != '_')
+            .unwrap_or(expr.len()),
+        _ => 0,
+    };
+    let (name, modifier) = expr.split_at(name_len);
+    let val = parameter(ctx, name);
+    if modifier.is_empty() && name.is_empty() == false {
+        return Ok(val.unwrap_or_default());
+    }
+
+    let (op, word) = match modifier {
+        _ if name.is_empty() => ("", ""),
+        m if m.starts_with("##") || m.starts_with("%%") => (&m[..2], &m[2..]),
+        m if m.starts_with(':') && m[1..].starts_with(|c| "-=?+".contains(c)) => (&m[..2], &m[2..]),
+        m if m.starts_with(|c| "-=?+#%".contains(c)) => (&m[..1], &m[1..]),
+        _ => ("", ""),
+    };
+    if op.is_empty() {
+        let _ = ctx.stdio.stderr.write(format!("sh: ${{{}}}: bad substitution\r\n", expr).as_bytes()).await;
+        return Err(1);
+    }
+
+    // Without the colon only unset parameters are replaced
+    let is_set = match (&val, op.starts_with(':')) {
+        (Some(v), true) => v.is_empty() == false,
+        (Some(_), false) => true,
+        (None, _) => false,
+    };
+    match op.trim_start_matches(':') {
+        "-" => match is_set {
+            true => Ok(val.unwrap_or_default()),
+            false => expand_single(ctx, builtins, word).await,
+        },
+        "=" => match is_set {
+            true => Ok(val.unwrap_or_default()),
+            false => {
+                let word = expand_single(ctx, builtins, word).await?;
+                if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') == false {
+                    let _ = ctx.stdio.stderr.write(format!("sh: ${}: cannot assign in this way\r\n", name).as_bytes()).await;
+                    return Err(1);
+                }
+                ctx.env.set_var(name, word.clone());
+                Ok(word)
+            }
+        },
+        "?" => match is_set {
+            true => Ok(val.unwrap_or_default()),
+            false => {
+                let msg = match word.is_empty() {
+                    true => "parameter null or not set".to_string(),
+                    false => expand_single(ctx, builtins, word).await?,
+                };
+                let _ = ctx.stdio.stderr.write(format!("sh: {}: {}\r\n", name, msg).as_bytes()).await;
+                Err(1)
+            }
+        },
+        "+" => match is_set {
+            true => expand_single(ctx, builtins, word).await,
+            false => Ok(String::new()),
+        },
+        op => {
+            let pattern = expand_pattern(ctx, builtins, word).await?;
+            Ok(trim_pattern(val.unwrap_or_default().as_str(), pattern.as_str(), op))
+        }
+    }
+}
+
+/// Removes the shortest (`#`, `%`) or longest (`##`, `%%`) prefix or
+/// suffix of the value that matches the pattern
+pub(crate) fn trim_pattern(val: &str, pattern: &str, op: &str) -> String {
+    let mut bounds = val.char_indices().map(|(n, _)| n).collect::<Vec<_>>();
+    bounds.push(val.len());
+    match op {
+        "#" | "##" => {
+            let mut order = bounds.clone();
+            if op == "##" {
+                order.reverse();
+            }
+            for n in order {
+                if pattern_matches(pattern, &val[..n]) {
+                    return val[n..].to_string();
+                }
+            }
+        }
+        _ => {
+            let mut order = bounds.clone();
+            if op == "%" {
+                order.reverse();
+            }
+            for n in order {
+                if pattern_matches(pattern, &val[n..]) {
+                    return val[..n].to_string();
+                }
+            }
+        }
+    }
+    val.to_string()
+}
+
+/// Joins the values into fields, the values of unquoted expansions are
+/// split on whitespace while quoted text (even if empty) always ends up
+/// in a field
+pub(crate) fn split_fields(values: Vec<Resolved>, ifs: &str) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let mut cur: Option<Field> = None;
+    let mut no_args = false;
+    for value in values {
+        match value {
+            Resolved::Text { text, split: true, .. } => {
+                for c in text.chars() {
+                    if ifs.contains(c) {
+                        if let Some(field) = cur.take() {
+                            fields.push(field);
+                        }
+                    } else {
+                        cur.get_or_insert_with(Vec::new).push((c, false));
+                    }
+                }
+            }
+            Resolved::Text { text, quoted, .. } => {
+                let field = cur.get_or_insert_with(Vec::new);
+                field.extend(text.chars().map(|c| (c, quoted)));
+            }
+            Resolved::Fields(args) => {
+                no_args |= args.is_empty();
+                let count = args.len();
+                for (n, arg) in args.into_iter().enumerate() {
+                    cur.get_or_insert_with(Vec::new).extend(arg.chars().map(|c| (c, true)));
+                    if n + 1 < count {
+                        fields.push(cur.take().unwrap_or_default());
+                    }
+                }
+            }
+        }
+    }
+    if let Some(field) = cur {
+        // `"$@"` without any arguments does not even leave an empty field
+        if field.is_empty() == false || no_args == false {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+/// Converts a field into a pattern where the quoted characters are escaped
+/// so that they only match themselves
+pub(crate) fn field_pattern(field: &Field) -> String {
+    let mut ret = String::with_capacity(field.len());
+    for (c, quoted) in field.iter() {
+        if (*quoted && "*?[]\\".contains(*c)) || *c == '\\' {
+            ret.push('\\');
+        }
+        ret.push(*c);
+    }
+    ret
+}
+
+fn field_text(field: &Field) -> String {
+    field.iter().map(|(c, _)| *c).collect()
+}
+
+fn join_values(values: Vec<Resolved>) -> String {
+    let mut ret = String::new();
+    for value in values {
+        match value {
+            Resolved::Text { text, .. } => ret.push_str(text.as_str()),
+            Resolved::Fields(args) => ret.push_str(args.join(" ").as_str()),
+        }
+    }
+    ret
+}
+
+/// Expands a word into the fields that it represents (a word may expand
+/// into no fields at all or into many of them)
+pub(super) async fn expand_word(ctx: &mut EvalContext, builtins: &Builtins, word: &str) -> Result<Vec<String>, u32> {
+    let mut ret = Vec::new();
+    for word in expand_braces(word) {
+        let segs = parse_word(word.as_str(), WordMode::Word);
+        let values = resolve(ctx, builtins, segs).await?;
+        let ifs = ctx.env.get("IFS").unwrap_or_else(|| " \t\n".to_string());
+        for field in split_fields(values, ifs.as_str()) {
+            let pattern = field_pattern(&field);
+            if has_glob(pattern.as_str()) {
+                let matches = glob(ctx, pattern.as_str()).await;
+                if matches.is_empty() == false {
+                    ret.extend(matches);
+                    continue;
+                }
+            }
+            ret.push(field_text(&field));
+        }
+    }
+    Ok(ret)
+}
+
+/// Expands a word into a single value (no field splitting or pathname
+/// expansion), this is used for assignments, redirects and case words
+pub(super) async fn expand_single(ctx: &mut EvalContext, builtins: &Builtins, word: &str) -> Result<String, u32> {
+    let segs = parse_word(word, WordMode::Word);
+    Ok(join_values(resolve(ctx, builtins, segs).await?))
+}
+
+/// Expands a word into a pattern where the parts that were quoted only
+/// match themselves
+pub(super) async fn expand_pattern(ctx: &mut EvalContext, builtins: &Builtins, word: &str) -> Result<String, u32> {
+    let segs = parse_word(word, WordMode::Word);
+    let values = resolve(ctx, builtins, segs).await?;
+    let field = values
+        .into_iter()
+        .flat_map(|v| match v {
+            Resolved::Text { text, quoted, .. } => text.chars().map(|c| (c, quoted)).collect::<Vec<_>>(),
+            Resolved::Fields(args) => args.join(" ").chars().map(|c| (c, true)).collect(),
+        })
+        .collect::<Field>();
+    Ok(field_pattern(&field))
+}
+
+/// Expands an assignment (`NAME=value`) without splitting its value
+pub(super) async fn expand_assignment(ctx: &mut EvalContext, builtins: &Builtins, assign: &str) -> Result<String, u32> {
+    match assign.split_once('=') {
+        Some((key, val)) => {
+            let segs = parse_word(val, WordMode::Assignment);
+            let val = join_values(resolve(ctx, builtins, segs).await?);
+            Ok(format!("{}={}", key, val))
+        }
+        None => Ok(assign.to_string()),
+    }
+}
+
+/// Expands the parameters and command substitutions in the body of a
+/// here-document
+pub(super) async fn expand_here_doc(ctx: &mut EvalContext, builtins: &Builtins, text: &str) -> Result<String, u32> {
+    let segs = parse_word(text, WordMode::HereDoc);
+    Ok(join_values(resolve(ctx, builtins, segs).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, quoted: bool) -> Segment {
+        Segment::Text {
+            text: text.to_string(),
+            quoted,
+        }
+    }
+
+    fn param(expr: &str, quoted: bool) -> Segment {
+        Segment::Param {
+            expr: expr.to_string(),
+            quoted,
+        }
+    }
+
+    fn value(text: &str, quoted: bool, split: bool) -> Resolved {
+        Resolved::Text {
+            text: text.to_string(),
+            quoted,
+            split,
+        }
+    }
+
+    fn fields(values: Vec<Resolved>) -> Vec<String> {
+        split_fields(values, " \t\n").iter().map(field_text).collect()
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(parse_word("'$x \\n'", WordMode::Word), vec![text("$x \\n", true)]);
+        assert_eq!(parse_word("a'b c'd", WordMode::Word), vec![text("a", false), text("b c", true), text("d", false)]);
+    }
+
+    #[test]
+    fn double_quotes_expand_parameters() {
+        assert_eq!(
+            parse_word("\"a $x\\$ \\q\"", WordMode::Word),
+            vec![text("a ", true), param("x", true), text("$ \\q", true)]
+        );
+        assert_eq!(parse_word("\"'$x'\"", WordMode::Word), vec![text("'", true), param("x", true), text("'", true)]);
+        assert_eq!(parse_word("\"\"", WordMode::Word), vec![text("", true)]);
+    }
+
+    #[test]
+    fn backslashes_quote_the_next_character() {
+        assert_eq!(parse_word("\\$x", WordMode::Word), vec![text("$", true), text("x", false)]);
+        assert_eq!(parse_word("a\\ b", WordMode::Word), vec![text("a", false), text(" ", true), text("b", false)]);
+        assert_eq!(parse_word("a\\\nb", WordMode::Word), vec![text("ab", false)]);
+    }
+
+    #[test]
+    fn expansions_are_recognised() {
+        assert_eq!(
+            parse_word("${x:-a b}$1$?", WordMode::Word),
+            vec![param("x:-a b", false), param("1", false), param("?", false)]
+        );
+        assert_eq!(
+            parse_word("$(echo \")\")", WordMode::Word),
+            vec![Segment::Command { script: "echo \")\"".to_string(), quoted: false }]
+        );
+        assert_eq!(
+            parse_word("`echo \\`x\\``", WordMode::Word),
+            vec![Segment::Command { script: "echo `x`".to_string(), quoted: false }]
+        );
+        assert_eq!(
+            parse_word("$(( (1 + 2) * 3 ))", WordMode::Word),
+            vec![Segment::Arith { expr: " (1 + 2) * 3 ".to_string(), quoted: false }]
+        );
+        assert_eq!(parse_word("\"$@\"", WordMode::Word), vec![text("", true), Segment::AllArgs, text("", true)]);
+        assert_eq!(parse_word("$", WordMode::Word), vec![text("$", false)]);
+    }
+
+    #[test]
+    fn tildes_only_expand_at_the_start() {
+        assert_eq!(parse_word("~/x", WordMode::Word), vec![Segment::Tilde("".to_string()), text("/x", false)]);
+        assert_eq!(parse_word("\"~\"/x", WordMode::Word), vec![text("~", true), text("/x", false)]);
+        assert_eq!(parse_word("a~", WordMode::Word), vec![text("a~", false)]);
+        assert_eq!(
+            parse_word("~:~/bin", WordMode::Assignment),
+            vec![Segment::Tilde("".to_string()), text(":", false), Segment::Tilde("".to_string()), text("/bin", false)]
+        );
+    }
+
+    #[test]
+    fn here_documents_ignore_quotes() {
+        assert_eq!(
+            parse_word("'$x' \"y\" \\$z\n", WordMode::HereDoc),
+            vec![text("'", true), param("x", true), text("' \"y\" $z\n", true)]
+        );
+    }
+
+    #[test]
+    fn unquoted_expansions_are_split() {
+        assert_eq!(fields(vec![value("a  b ", false, true)]), vec!["a", "b"]);
+        assert_eq!(fields(vec![value("a  b ", true, false)]), vec!["a  b "]);
+        assert_eq!(fields(vec![value("x", false, false), value(" a b", false, true)]), vec!["x", "a", "b"]);
+        assert_eq!(fields(vec![value("", false, true)]), Vec::<String>::new());
+        assert_eq!(fields(vec![value("", true, false)]), vec![""]);
+    }
+
+    #[test]
+    fn all_args_keep_their_fields() {
+        let args = Resolved::Fields(vec!["a".to_string(), "b c".to_string()]);
+        assert_eq!(fields(vec![value("x", false, false), args, value("y", true, false)]), vec!["xa", "b cy"]);
+        assert_eq!(fields(vec![Resolved::Fields(Vec::new())]), Vec::<String>::new());
+        assert_eq!(
+            fields(vec![value("", true, false), Resolved::Fields(Vec::new()), value("", true, false)]),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn quoted_characters_are_not_patterns() {
+        let field = split_fields(vec![value("*.", false, false), value("*", true, false)], " ").remove(0);
+        assert_eq!(field_pattern(&field), "*.\\*");
+        assert_eq!(has_glob(field_pattern(&field).as_str()), true);
+
+        let field = split_fields(vec![value("*.rs", true, false)], " ").remove(0);
+        assert_eq!(has_glob(field_pattern(&field).as_str()), false);
+    }
+
+    #[test]
+    fn brace_expansion() {
+        assert_eq!(expand_braces("a{b,c}d"), vec!["abd", "acd"]);
+        assert_eq!(expand_braces("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand_braces("x{a,{b,c}}"), vec!["xa", "xb", "xc"]);
+        assert_eq!(expand_braces("{1..3}"), vec!["1", "2", "3"]);
+        assert_eq!(expand_braces("{3..1}"), vec!["3", "2", "1"]);
+        assert_eq!(expand_braces("{01..10..3}"), vec!["01", "04", "07", "10"]);
+        assert_eq!(expand_braces("{a..c}"), vec!["a", "b", "c"]);
+        assert_eq!(expand_braces("\"{a,b}\""), vec!["\"{a,b}\""]);
+        assert_eq!(expand_braces("${a,b}"), vec!["${a,b}"]);
+        assert_eq!(expand_braces("{a}"), vec!["{a}"]);
+    }
+
+    #[test]
+    fn trimming_patterns() {
+        assert_eq!(trim_pattern("dir/sub/file.tar.gz", "*/", "#"), "sub/file.tar.gz");
+        assert_eq!(trim_pattern("dir/sub/file.tar.gz", "*/", "##"), "file.tar.gz");
+        assert_eq!(trim_pattern("file.tar.gz", ".*", "%"), "file.tar");
+        assert_eq!(trim_pattern("file.tar.gz", ".*", "%%"), "file");
+        assert_eq!(trim_pattern("file", "x*", "#"), "file");
     }
 }
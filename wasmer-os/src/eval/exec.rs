@@ -49,6 +49,8 @@ use crate::reactor::*;
 use crate::state::*;
 use crate::stdio::*;
 use crate::wasmer::{Imports, Instance, Module, Store};
+#[cfg(feature = "wasmer-middlewares")]
+use crate::wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
 use crate::wasmer_vfs::FileSystem;
 use crate::wasmer_vfs::FsError;
 use crate::wasmer_wasi::Stdin;
@@ -73,6 +75,13 @@ pub async fn exec(
     // If there is a built in then use it
     if let Some(builtin) = builtins.get(cmd) {
         *show_result = true;
+        if redirect.is_empty() == false {
+            let fs = AsyncifyFileSystem::new(ctx.root.clone());
+            if let Err((msg, code)) = apply_redirects(&ctx, &fs, &mut stdio, &redirect[..]).await {
+                let _ = stdio.stderr.write(msg.as_bytes()).await;
+                return Err(code);
+            }
+        }
         return Ok(builtin(&args, ctx, stdio).await);
     }
 
@@ -145,18 +154,36 @@ pub async fn exec_process(
         envs.insert("PWD".to_string(), pwd.clone());
     };
 
-    // Create a store for the module and memory
+    // Assignments that prefix the command only apply to this process
+    for var in env_vars.iter() {
+        if let Some((k, v)) = var.split_once('=') {
+            envs.insert(k.to_string(), v.to_string());
+        }
+    }
+
+    // Processes that have a fuel budget must run on modules compiled
+    // with metering which are cached separately from normal modules
+    let limits = ctx.limits.clone();
     #[cfg(feature = "sys")]
-    let store = match ctx.engine.clone() {
-        Some(engine) => Store::new(engine),
-        None => Store::default()
+    let engine = match limits.is_metered() {
+        #[cfg(feature = "wasmer-compiler")]
+        true => ctx.compiler.metered_engine().or_else(|| ctx.engine.clone()),
+        _ => ctx.engine.clone(),
     };
+    let module_key = match limits.is_metered() {
+        true => format!("{}-metered", program_data_hash),
+        false => program_data_hash.clone(),
+    };
+
+    // Create a store for the module and memory
+    #[cfg(feature = "sys")]
+    let store = new_limited_store(engine.clone(), &limits);
     #[cfg(feature = "js")]
     let store = Store::default();
 
     // Load or compile the module (they are cached in thread local storage)
     // If compile caching is enabled the load the module
-    let cached_module = { ctx.bins.get_compiled_module(&store, &program_data_hash, ctx.compiler).await };
+    let cached_module = { ctx.bins.get_compiled_module(&store, &module_key, ctx.compiler).await };
 
     // This wait point is so that the main thread is created before it returns
     let (checkpoint1_tx, mut checkpoint1) = ctx.checkpoint1.take().unwrap_or_else(|| WasmCheckpoint::new());
@@ -194,110 +221,8 @@ pub async fn exec_process(
     }
 
     // Perform all the redirects
-    for redirect in redirect.iter() {
-        // If its not an absolutely path then make it one
-        let mut filename = redirect.filename.clone();
-        if filename.starts_with("/") == false {
-            filename = format!("{}{}", ctx.working_dir, filename);
-        }
-
-        // Attempt to open the file
-        let file = fs
-            .new_open_options()
-            .await
-            .create(redirect.op.write())
-            .create_new(redirect.op.write() && redirect.op.append() == false)
-            .truncate(redirect.op.write() && redirect.op.append() == false)
-            .append(redirect.op.append())
-            .read(redirect.op.read())
-            .write(redirect.op.write())
-            .open(filename)
-            .await;
-        match file {
-            Ok(mut file) => {
-                // Open a new file description
-                let (tx, mut rx, flag) = {
-                    let (fd, tx, rx) = bidirectional_with_defaults(FdFlag::None);
-
-                    // We now connect the newly opened file descriptor with the read file
-                    let mut flag = FdFlag::None;
-                    match redirect.fd {
-                        -1 => {
-                            if redirect.op.read() {
-                                let mut fd = fd.clone();
-                                flag = fd.set_flag(FdFlag::Stdin(false));
-                                stdio.stdin = fd;
-                            }
-                            if redirect.op.write() {
-                                let mut fd = fd.clone();
-                                flag = fd.set_flag(FdFlag::Stdout(false));
-                                stdio.stdout = fd;
-                            }
-                        }
-                        0 => {
-                            let mut fd = fd.clone();
-                            flag = fd.set_flag(FdFlag::Stdin(false));
-                            stdio.stdin = fd
-                        }
-                        1 => {
-                            let mut fd = fd.clone();
-                            flag = fd.set_flag(FdFlag::Stdout(false));
-                            stdio.stdout = fd
-                        }
-                        2 => {
-                            let mut fd = fd.clone();
-                            flag = fd.set_flag(FdFlag::Stderr(false));
-                            stdio.stderr = fd
-                        }
-                        _ => {
-                            return on_early_exit(Some(format!("redirecting non-standard file descriptors is not yet supported")), err::ERR_EINVAL).await;
-                        }
-                    };
-
-                    // Now we need to hook up the receiver and sender
-                    (tx, rx, flag)
-                };
-
-                // Now hook up the sender and receiver on a shared task
-                let system = ctx.system;
-                let is_read = redirect.op.read();
-                let is_write = redirect.op.write();
-                system.fork_shared(move || async move {
-                    if is_read {
-                        while let Ok(read) = file.read(4096).await {
-                            let _ = tx.send(FdMsg::new(read, flag)).await;
-                        }
-                    }
-                    if is_write {
-                        while let Some(msg) = rx.recv().await {
-                            match msg {
-                                FdMsg::Data { data, .. } => {
-                                    let _ = file.write_all(data).await;
-                                }
-                                FdMsg::Flush { tx } => {
-                                    file.flush().await;
-                                    let _ = tx.send(()).await;
-                                }
-                            }
-                        }
-                        file.flush().await;
-                    }
-                });
-            }
-            Err(err) => {
-                return on_early_exit(
-                    Some(format!(
-                        "failed to open the redirected file '{}' ({}): ",
-                        redirect.filename, err
-                    )),
-                    match err {
-                        FsError::EntityNotFound => ERR_ENOENT,
-                        _ => ERR_EIO,
-                    },
-                )
-                .await;
-            }
-        }
+    if let Err((msg, code)) = apply_redirects(&ctx, &fs, &mut stdio, &redirect[..]).await {
+        return on_early_exit(Some(msg), code).await;
     }
 
     // Extract the bits we need from the eval context
@@ -343,7 +268,7 @@ pub async fn exec_process(
                 compiled_module.name().unwrap_or_else(|| cmd.as_str())
             );
 
-            bins.set_compiled_module(program_data_hash.clone(), ctx.compiler, &compiled_module)
+            bins.set_compiled_module(module_key.clone(), ctx.compiler, &compiled_module)
                 .await;
 
             compiled_module
@@ -359,12 +284,21 @@ pub async fn exec_process(
 
     // Determine if we are going to create memory and import it or just rely on self creation of memory
     let memory_spawn = match shared_memory {
-        Some(ty) => {
-            #[cfg(feature = "sys")]
-            let style = match &ctx.engine {
-                    Some(engine) => Store::new(engine.clone()),
-                    None => Store::default()
+        Some(mut ty) => {
+            // Imported memory is capped by the memory limit of the process
+            if let Some(max_memory_pages) = limits.max_memory_pages {
+                let limit = Pages(max_memory_pages);
+                if ty.minimum > limit {
+                    return on_early_exit(Some(format!("exec-failed: memory limit exceeded ({} pages)\n", max_memory_pages)), err::ERR_ENOMEM).await;
                 }
+                ty.maximum = Some(match ty.maximum {
+                    Some(max) if max < limit => max,
+                    _ => limit,
+                });
+            }
+
+            #[cfg(feature = "sys")]
+            let style = new_limited_store(engine.clone(), &limits)
                 .tunables()
                 .memory_style(&ty);            
             SpawnType::CreateWithType(SpawnedMemory {
@@ -394,6 +328,7 @@ pub async fn exec_process(
     );
     
     let forced_exit = caller_ctx.get_forced_exit();
+    let meter = limits.meter.clone();
 
     // Create the runtime that will perform terminal specific actions
     let wasi_runtime = Arc::new(WasiRuntime::new(
@@ -405,9 +340,14 @@ pub async fn exec_process(
     // Spawn the process on a background thread
     let cmd = cmd.clone();
     let args = args.clone();
+    // Signals the wall time watchdog when the process has exited
+    let (exited_tx, mut exited_rx) = watch::channel(false);
     let process_result = {
         let wasi_runtime = wasi_runtime.clone();
         let forced_exit = Arc::clone(&forced_exit);
+        let meter = meter.clone();
+        let max_fuel = limits.max_fuel;
+        let max_open_files = limits.max_open_files;
 
         sys.spawn_wasm(move |mut store, module, memory| async move
         {
@@ -415,6 +355,12 @@ pub async fn exec_process(
             let args = args.iter().skip(1).map(|a| a.as_str()).collect::<Vec<_>>();
             let envs = envs.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect::<HashMap<_,_>>();
             
+            // Cap the number of files the process may hold open
+            let union: Box<dyn FileSystem> = match max_open_files {
+                Some(max) => Box::new(LimitedFileSystem::new(union, max)),
+                None => Box::new(union),
+            };
+
             // Create the `WasiEnv`.
             let mut wasi_env = WasiState::new(cmd.as_str());
             let mut wasi_env = wasi_env
@@ -423,7 +369,7 @@ pub async fn exec_process(
                 .stdin(Box::new(stdio.stdin.clone()))
                 .stdout(Box::new(stdio.stdout.clone()))
                 .stderr(Box::new(stdio.stderr.clone()))
-                .set_fs(Box::new(union))
+                .set_fs(union)
                 .setup_fs(Box::new(move |_, fs| {
                     fs.set_current_dir(pwd.as_str());
                     Ok(())
@@ -510,6 +456,17 @@ pub async fn exec_process(
                 }
             };
 
+            // Give the process its fuel budget (modules that were not compiled
+            // with metering are not able to be limited)
+            #[cfg(feature = "wasmer-middlewares")]
+            let max_fuel = match max_fuel {
+                Some(fuel) if instance.exports.get_global("wasmer_metering_remaining_points").is_ok() => {
+                    set_remaining_points(&mut store, &instance, fuel);
+                    Some(fuel)
+                }
+                _ => None,
+            };
+
             // Initialize the WASI environment
             if let Err(err) = wasi_env.initialize(&mut store, &instance) {
                 let _ = stderr.write(format!("instantiate error ({})\n", err.to_string()).as_bytes()).await;
@@ -547,6 +504,7 @@ pub async fn exec_process(
 
             // If there is a start function
             debug!("called main() on {}", cmd);
+            let started = std::time::Instant::now();
             let mut ret = if let Some(start) = start {
                 match start.call(&mut store, &[]) {
                     Ok(a) => err::ERR_OK,
@@ -571,6 +529,34 @@ pub async fn exec_process(
             };
             debug!("main() has exited on {}", cmd);
 
+            // Account for the resources that the process consumed
+            if let Some(meter) = meter.as_ref() {
+                meter.record_compute(started.elapsed());
+                if let Ok(memory) = instance.exports.get_memory("memory") {
+                    meter.record_memory_pages(memory.view(&store).size().0);
+                }
+            }
+            #[cfg(feature = "wasmer-middlewares")]
+            if let Some(fuel) = max_fuel {
+                let consumed = match get_remaining_points(&mut store, &instance) {
+                    MeteringPoints::Remaining(remaining) => fuel - remaining,
+                    MeteringPoints::Exhausted => {
+                        debug!("fuel exhausted on {}", cmd);
+                        let _ = stderr
+                            .write(&format!("exec-failed: fuel exhausted ({})\n", fuel).as_bytes()[..])
+                            .await;
+                        if let Some(meter) = meter.as_ref() {
+                            meter.record_terminated();
+                        }
+                        ret = err::ERR_EDQUOT;
+                        fuel
+                    }
+                };
+                if let Some(meter) = meter.as_ref() {
+                    meter.record_fuel(consumed);
+                }
+            }
+
             // The second checkpoint is after the start method completes but before
             // all the background threads have exited
             checkpoint2_tx.send(()).await;
@@ -598,6 +584,7 @@ pub async fn exec_process(
 
             // Ok we are done
             debug!("exited (name={}) with code {}", cmd, ret);
+            let _ = exited_tx.send(true);
             let ctx = ctx_taker.take_context().unwrap();
             (ctx, ret)
         }, store, module, memory_spawn)
@@ -621,5 +608,24 @@ pub async fn exec_process(
     };
     debug!("process created (pid={})", pid);
 
+    // Processes that run for too long are forced to exit (the watchdog
+    // stops as soon as the process exits)
+    if let Some(max_wall_time) = limits.max_wall_time {
+        let forced_exit = forced_exit.clone();
+        let meter = meter.clone();
+        sys.fork_shared(move || async move {
+            tokio::select! {
+                _ = sys.sleep(max_wall_time.as_millis()) => { },
+                _ = exited_rx.changed() => { return; }
+            }
+            if forced_exit.compare_exchange(0, err::ERR_ETIMEDOUT, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                debug!("wall time exceeded (pid={})", pid);
+                if let Some(meter) = meter.as_ref() {
+                    meter.record_terminated();
+                }
+            }
+        });
+    }
+
     Ok((process, process_result, wasi_runtime, checkpoint2))
 }
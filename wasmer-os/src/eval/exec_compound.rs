@@ -0,0 +1,157 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use super::*;
+use crate::ast;
+
+/// What a loop should do after one of its iterations has finished
+enum LoopNext {
+    Next,
+    Exit,
+}
+
+/// Consumes a `break` or `continue` that targets the current loop (jumps
+/// that target outer loops or functions are left for them to handle)
+fn loop_control(ctx: &mut EvalContext) -> LoopNext {
+    match ctx.jump {
+        None => LoopNext::Next,
+        Some(Jump::Break(n)) => {
+            ctx.jump = match n {
+                0 | 1 => None,
+                n => Some(Jump::Break(n - 1)),
+            };
+            LoopNext::Exit
+        }
+        Some(Jump::Continue(n)) => match n {
+            0 | 1 => {
+                ctx.jump = None;
+                LoopNext::Next
+            }
+            n => {
+                ctx.jump = Some(Jump::Continue(n - 1));
+                LoopNext::Exit
+            }
+        },
+        Some(Jump::Return) => LoopNext::Exit,
+    }
+}
+
+/// Evaluates a compound command (brace group, subshell, if, for, while,
+/// until or case) and returns the exit code of the last command it ran
+pub(super) fn exec_compound<'a>(
+    mut ctx: EvalContext,
+    builtins: &'a Builtins,
+    show_result: &'a mut bool,
+    compound: &'a ast::Compound<'a>,
+) -> Pin<Box<dyn Future<Output = (EvalContext, u32)> + Send + 'a>> {
+    Box::pin(async move {
+        match compound {
+            ast::Compound::BraceGroup(list) => complete_command(ctx, builtins, list, show_result).await,
+            ast::Compound::Subshell(list) => {
+                // Nothing that happens in a subshell affects the shell itself
+                let (sub_ctx, ret) = complete_command(ctx.clone(), builtins, list, show_result).await;
+                ctx.last_return = ret;
+                if sub_ctx.jump == Some(Jump::Return) {
+                    ctx.jump = Some(Jump::Return);
+                }
+                (ctx, ret)
+            }
+            ast::Compound::If(clause) => {
+                for (condition, body) in clause.branches.iter() {
+                    let (c, ret) = complete_command(ctx, builtins, condition, show_result).await;
+                    ctx = c;
+                    if ctx.jump.is_some() {
+                        return (ctx, ret);
+                    }
+                    if ret == 0 {
+                        return complete_command(ctx, builtins, body, show_result).await;
+                    }
+                }
+                match &clause.otherwise {
+                    Some(body) => complete_command(ctx, builtins, body, show_result).await,
+                    None => (ctx, 0),
+                }
+            }
+            ast::Compound::While(clause) => {
+                let mut ret = 0;
+                loop {
+                    if let Some(code) = ctx.job.should_terminate() {
+                        return (ctx, code);
+                    }
+                    let (c, test) = complete_command(ctx, builtins, &clause.condition, show_result).await;
+                    ctx = c;
+                    if let LoopNext::Exit = loop_control(&mut ctx) {
+                        break;
+                    }
+                    if (test == 0) == clause.until {
+                        break;
+                    }
+                    let (c, r) = complete_command(ctx, builtins, &clause.body, show_result).await;
+                    ctx = c;
+                    ret = r;
+                    if let LoopNext::Exit = loop_control(&mut ctx) {
+                        break;
+                    }
+                }
+                (ctx, ret)
+            }
+            ast::Compound::For(clause) => {
+                let words = match &clause.words {
+                    Some(words) => {
+                        let mut fields = Vec::new();
+                        for word in words.iter() {
+                            match expand_word(&mut ctx, builtins, word).await {
+                                Ok(a) => fields.extend(a),
+                                Err(code) => return (ctx, code),
+                            }
+                        }
+                        fields
+                    }
+                    None => ctx.positional.clone(),
+                };
+
+                let mut ret = 0;
+                for word in words {
+                    if let Some(code) = ctx.job.should_terminate() {
+                        return (ctx, code);
+                    }
+                    ctx.env.set_var(clause.name, word);
+                    let (c, r) = complete_command(ctx, builtins, &clause.body, show_result).await;
+                    ctx = c;
+                    ret = r;
+                    if let LoopNext::Exit = loop_control(&mut ctx) {
+                        break;
+                    }
+                }
+                (ctx, ret)
+            }
+            ast::Compound::Case(clause) => {
+                let word = match expand_single(&mut ctx, builtins, clause.word).await {
+                    Ok(a) => a,
+                    Err(code) => return (ctx, code),
+                };
+                for item in clause.items.iter() {
+                    let mut matched = false;
+                    for pattern in item.patterns.iter() {
+                        // Quoted parts of the pattern are compared literally
+                        let pattern = match expand_pattern(&mut ctx, builtins, pattern).await {
+                            Ok(a) => a,
+                            Err(code) => return (ctx, code),
+                        };
+                        matched = pattern_matches(pattern.as_str(), word.as_str());
+                        if matched {
+                            break;
+                        }
+                    }
+                    if matched {
+                        return match &item.body {
+                            Some(body) => complete_command(ctx, builtins, body, show_result).await,
+                            None => (ctx, 0),
+                        };
+                    }
+                }
+                (ctx, 0)
+            }
+        }
+    })
+}
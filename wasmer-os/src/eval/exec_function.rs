@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use super::*;
+use crate::ast;
+
+/// Remembers a function so that it can be called by later commands
+pub(super) fn define_function(ctx: &mut EvalContext, def: &ast::FunctionDef) -> u32 {
+    ctx.env.set_function(def.name, def.source.to_string());
+    0
+}
+
+/// Calls a function that was previously defined, the arguments become its
+/// positional parameters for as long as it runs
+pub(super) fn exec_function<'a>(
+    mut ctx: EvalContext,
+    builtins: &'a Builtins,
+    show_result: &'a mut bool,
+    source: String,
+    args: Vec<String>,
+) -> Pin<Box<dyn Future<Output = (EvalContext, u32)> + Send + 'a>> {
+    Box::pin(async move {
+        let parser = grammar::programParser::new();
+        let program = match parser.parse(source.as_str()) {
+            Ok(a) => a,
+            Err(err) => {
+                debug!("function failed to parse - {}", err);
+                let _ = ctx
+                    .stdio
+                    .stderr
+                    .write(format!("sh: {}: invalid function\r\n", args.first().map(|a| a.as_str()).unwrap_or("")).as_bytes())
+                    .await;
+                return (ctx, err::ERR_EINVAL);
+            }
+        };
+
+        let positional = std::mem::replace(&mut ctx.positional, args.into_iter().skip(1).collect());
+        let mut ret = 0;
+        for cc in program.commands.complete_commands.iter() {
+            let (c, r) = complete_command(ctx, builtins, cc, show_result).await;
+            ctx = c;
+            ret = r;
+            if ctx.jump.is_some() {
+                break;
+            }
+        }
+
+        // A `return` stops at the function, as do loop jumps that were not
+        // consumed by a loop within it
+        if ctx.jump == Some(Jump::Return) {
+            ret = ctx.last_return;
+        }
+        ctx.jump = None;
+        ctx.positional = positional;
+        (ctx, ret)
+    })
+}
@@ -1,3 +1,4 @@
+use std::num::NonZeroU32;
 use std::ops::Deref;
 
 use super::*;
@@ -14,11 +15,30 @@ pub(super) async fn exec_pipeline<'a>(
     show_result: &mut bool,
     pipeline: &'a ast::Pipeline<'a>,
 ) -> (EvalContext, u32) {
+    // Jobs that are stopped (Ctrl-Z) do not run anything else until resumed
+    ctx.job.wait_while_stopped().await;
+
     let mut child_list = Vec::new();
     let mut final_return: Option<u32> = None;
+
+    // Pipelines that run in the background get a job of their own (and with
+    // it a stdin that only receives input when they are brought back into
+    // the foreground)
+    let bg_job = match exec_sync {
+        true => None,
+        false => {
+            let mut reactor = ctx.reactor.write().await;
+            reactor.generate_job().ok().map(|(_, job)| job)
+        }
+    };
+    let mut description = Vec::new();
     {
-        let mut next_stdin = ctx.stdio.stdin.clone();
-        let mut cur_stdin = ctx.stdio.stdin.clone();
+        let stdin = match &bg_job {
+            Some(job) => job.stdin.clone(),
+            None => ctx.stdio.stdin.clone(),
+        };
+        let mut next_stdin = stdin.clone();
+        let mut cur_stdin = stdin;
         let mut cur_stdout = ctx.stdio.stdout.clone();
         let mut cur_stderr = ctx.stdio.stderr.clone();
         let end_stdout = ctx.stdio.stdout.clone();
@@ -33,20 +53,55 @@ pub(super) async fn exec_pipeline<'a>(
                     args,
                     redirect,
                 } => {
-                    let parsed_cmd = match cmd {
-                        ast::Arg::Arg(s) => eval_arg(&ctx.env, ctx.last_return, *s),
-                        ast::Arg::Backquote(_quoted_args) => String::new(),
-                    };
-                    let mut parsed_args: Vec<String> = vec![parsed_cmd.clone()];
-                    parsed_args.extend(args.iter().map(|a| match a {
-                        ast::Arg::Arg(s) => eval_arg(&ctx.env, ctx.last_return, *s),
-                        ast::Arg::Backquote(_quoted_args) => String::new(),
-                    }));
-                    parsed_args.extend(ctx.extra_args.clone().into_iter());
-                    let parsed_env: Vec<String> = assign.iter().map(|a| a.to_string()).collect();
+                    // The command and its arguments may each expand into
+                    // any number of fields (including none at all)
+                    let mut failed = None;
+                    let mut parsed_args: Vec<String> = Vec::new();
+                    for arg in std::iter::once(cmd).chain(args.iter()) {
+                        match arg {
+                            ast::Arg::Arg(s) => match expand_word(&mut ctx, builtins, *s).await {
+                                Ok(a) => parsed_args.extend(a),
+                                Err(code) => {
+                                    failed = Some(code);
+                                    break;
+                                }
+                            },
+                            ast::Arg::Backquote(_quoted_args) => {}
+                        }
+                    }
+                    let mut parsed_env: Vec<String> = Vec::new();
+                    for a in assign.iter() {
+                        if failed.is_some() {
+                            break;
+                        }
+                        match expand_assignment(&mut ctx, builtins, *a).await {
+                            Ok(a) => parsed_env.push(a),
+                            Err(code) => failed = Some(code),
+                        }
+                    }
+                    let mut parsed_redirects = Vec::new();
+                    if failed.is_none() {
+                        let redirects = redirect.iter().chain(ctx.extra_redirects.iter()).cloned().collect::<Vec<_>>();
+                        if let Err(code) = expand_redirects(&mut ctx, builtins, redirects, &mut parsed_redirects).await {
+                            failed = Some(code);
+                        }
+                    }
+                    if let Some(code) = failed {
+                        final_return = Some(code);
+                        continue;
+                    }
 
-                    let mut parsed_redirects = redirect.clone().into_iter().collect::<Vec<_>>();
-                    parsed_redirects.extend(ctx.extra_redirects.clone().into_iter());
+                    // Assignments without a command change the shell itself
+                    if parsed_args.is_empty() {
+                        for var in parsed_env {
+                            ctx.env.set_vareq(var);
+                        }
+                        final_return = Some(0);
+                        continue;
+                    }
+                    let parsed_cmd = parsed_args[0].clone();
+                    parsed_args.extend(ctx.extra_args.clone().into_iter());
+                    description.push(parsed_args.join(" "));
 
                     cur_stdin = next_stdin.clone();
                     if i + 1 < pipeline.commands.len() {
@@ -67,6 +122,22 @@ pub(super) async fn exec_pipeline<'a>(
                         tty: ctx.stdio.tty.clone(),
                     };
 
+                    // Functions are run by the shell itself
+                    if let Some(source) = ctx.env.function(parsed_cmd.as_str()).cloned() {
+                        let saved = match swap_stdio(&mut ctx, stdio, &parsed_redirects).await {
+                            Ok(a) => a,
+                            Err(code) => {
+                                final_return = Some(code);
+                                continue;
+                            }
+                        };
+                        let (c, ret) = exec_function(ctx, builtins, show_result, source, parsed_args).await;
+                        ctx = c;
+                        ctx.stdio = saved;
+                        final_return = Some(ret);
+                        continue;
+                    }
+
                     debug!("exec {}", parsed_cmd);
                     match exec::exec(
                         ctx.clone(),
@@ -96,16 +167,88 @@ pub(super) async fn exec_pipeline<'a>(
                         }
                     }
                 }
+                ast::Command::Compound { body, redirect } => {
+                    cur_stdin = next_stdin.clone();
+                    if i + 1 < pipeline.commands.len() {
+                        let (mut w, mut r) = pipe(ReceiverMode::Stream, end_stdout.flag());
+                        r.set_flag(FdFlag::Stdin(false));
+                        w.set_flag(FdFlag::Stdout(false));
+                        next_stdin = r;
+                        cur_stdout = w;
+                    } else {
+                        cur_stdout = end_stdout.clone();
+                    }
+
+                    let mut parsed_redirects = Vec::new();
+                    if let Err(code) = expand_redirects(&mut ctx, builtins, redirect.clone(), &mut parsed_redirects).await {
+                        final_return = Some(code);
+                        continue;
+                    }
+
+                    let stdio = Stdio {
+                        stdin: cur_stdin.clone(),
+                        stdout: cur_stdout.clone(),
+                        stderr: cur_stderr.clone(),
+                        log: ctx.stdio.log.clone(),
+                        tty: ctx.stdio.tty.clone(),
+                    };
+                    let saved = match swap_stdio(&mut ctx, stdio, &parsed_redirects).await {
+                        Ok(a) => a,
+                        Err(code) => {
+                            final_return = Some(code);
+                            continue;
+                        }
+                    };
+                    let (c, ret) = exec_compound(ctx, builtins, show_result, body).await;
+                    ctx = c;
+                    ctx.stdio = saved;
+                    final_return = Some(ret);
+                }
+                ast::Command::Function(def) => {
+                    final_return = Some(define_function(&mut ctx, def));
+                }
             }
         }
     }
 
+    let owner = bg_job.as_ref().unwrap_or(&ctx.job);
     for (child, child_result, _) in child_list.iter() {
         debug!(
             "process (pid={}) added to job (id={})",
-            child.pid, ctx.job.id
+            child.pid, owner.id
         );
-        ctx.job.job_list_tx.send(child.pid).await;
+        owner.add_process(child.pid);
+    }
+
+    if let Some(job) = bg_job {
+        let mut reactor = ctx.reactor.write().await;
+        match child_list.last() {
+            Some((child, _, _)) => {
+                job.set_command(format!("{} &", description.join(" | ")).as_str());
+                reactor.background_job(&job);
+                ctx.last_background = Some(child.pid);
+                if ctx.stdio.stderr.is_tty() {
+                    let msg = format!("[{}] {}\r\n", job.id, child.pid);
+                    let _ = ctx.stdio.stderr.write(msg.as_bytes()).await;
+                }
+
+                // The exit code of the last process becomes the exit code of the job
+                let results = child_list.drain(..).map(|(_, result, _)| result).collect::<Vec<_>>();
+                ctx.system.fork_shared(move || async move {
+                    let mut ret = None;
+                    for result in results.into_iter().rev() {
+                        let code = result.await.map(|(_, r)| r).unwrap_or(err::ERR_ECONNABORTED);
+                        ret.get_or_insert(code);
+                    }
+                    debug!("background job finished (id={})", job.id);
+                    job.finish(ret.unwrap_or(0));
+                });
+            }
+            None => {
+                // Nothing was started (e.g. a builtin) so there is nothing to track
+                reactor.close_job(job, NonZeroU32::new(err::ERR_TERMINATED).unwrap());
+            }
+        }
     }
 
     if exec_sync {
@@ -127,5 +270,43 @@ pub(super) async fn exec_pipeline<'a>(
         }
     }
 
-    (ctx, final_return.map_or_else(|| 0, |a| a))
+    let mut ret = final_return.map_or_else(|| 0, |a| a);
+    if pipeline.negated {
+        ret = match ret {
+            0 => 1,
+            _ => 0,
+        };
+    }
+    (ctx, ret)
+}
+
+/// Expands the file names of the redirects (or the text of here-documents)
+async fn expand_redirects(
+    ctx: &mut EvalContext,
+    builtins: &Builtins,
+    redirects: Vec<Redirect>,
+    parsed: &mut Vec<Redirect>,
+) -> Result<(), u32> {
+    for mut r in redirects {
+        r.filename = match r.op.heredoc() {
+            true => expand_here_doc(ctx, builtins, r.filename.as_str()).await?,
+            false => expand_single(ctx, builtins, r.filename.as_str()).await?,
+        };
+        parsed.push(r);
+    }
+    Ok(())
+}
+
+/// Commands that are evaluated by the shell itself (rather than in a process)
+/// use the standard file descriptors of their place in the pipeline, the
+/// descriptors they replace are returned so that they can be put back after
+async fn swap_stdio(ctx: &mut EvalContext, mut stdio: Stdio, redirects: &[Redirect]) -> Result<Stdio, u32> {
+    if redirects.is_empty() == false {
+        let fs = AsyncifyFileSystem::new(ctx.root.clone());
+        if let Err((msg, code)) = apply_redirects(ctx, &fs, &mut stdio, redirects).await {
+            let _ = stdio.stderr.write(msg.as_bytes()).await;
+            return Err(code);
+        }
+    }
+    Ok(std::mem::replace(&mut ctx.stdio, stdio))
 }
@@ -27,6 +27,7 @@ pub struct SpawnContext {
     pub compiler: Compiler,
     pub extra_args: Vec<String>,
     pub extra_redirects: Vec<Redirect>,    
+    pub limits: ProcessLimits,
     pub(crate) checkpoint1: Option<(mpsc::Sender<()>, Arc<WasmCheckpoint>)>,
     pub(crate) checkpoint2: Option<(mpsc::Sender<()>, Arc<WasmCheckpoint>)>,
 }
@@ -63,6 +64,7 @@ impl SpawnContext {
             compiler,
             extra_args: Vec::new(),
             extra_redirects: Vec::new(),
+            limits: ProcessLimits::default(),
             checkpoint1: None,
             checkpoint2: None,
         }
@@ -160,6 +162,10 @@ impl EvalFactory {
             compiler: ctx.compiler,
             extra_args: ctx.extra_args,
             extra_redirects: ctx.extra_redirects,            
+            limits: ctx.limits,
+            positional: Vec::new(),
+            jump: None,
+            last_background: None,
             checkpoint1: ctx.checkpoint1,
             checkpoint2: ctx.checkpoint2,
         };
@@ -0,0 +1,121 @@
+//! Pathname expansion matches the patterns in a word (`*`, `?` and `[...]`)
+//! against the entries of the file system
+use std::path::Path;
+
+use super::*;
+
+/// Returns true if the pattern has any characters that are not escaped
+/// and would match more than themselves
+pub fn has_glob(pattern: &str) -> bool {
+    let mut escaped = false;
+    for c in pattern.chars() {
+        match (escaped, c) {
+            (true, _) => escaped = false,
+            (false, '\\') => escaped = true,
+            (false, '*') | (false, '?') | (false, '[') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Removes the escapes from a pattern that has no glob characters
+pub fn unescape_pattern(pattern: &str) -> String {
+    let mut ret = String::with_capacity(pattern.len());
+    let mut escaped = false;
+    for c in pattern.chars() {
+        match (escaped, c) {
+            (false, '\\') => escaped = true,
+            _ => {
+                escaped = false;
+                ret.push(c);
+            }
+        }
+    }
+    ret
+}
+
+/// Finds all the paths that match the pattern (sorted), relative patterns
+/// are matched against the working directory and the paths they return
+/// are also relative
+pub(super) async fn glob(ctx: &EvalContext, pattern: &str) -> Vec<String> {
+    let fs = AsyncifyFileSystem::new(ctx.root.clone());
+    let working_dir = match ctx.working_dir.ends_with('/') {
+        true => ctx.working_dir.clone(),
+        false => format!("{}/", ctx.working_dir),
+    };
+    let absolute = pattern.starts_with('/');
+    let dirs_only = pattern.ends_with('/');
+    let components = pattern
+        .split('/')
+        .filter(|c| c.is_empty() == false)
+        .collect::<Vec<_>>();
+
+    // Each candidate is the path as it will be displayed
+    let mut candidates = vec![match absolute {
+        true => "/".to_string(),
+        false => String::new(),
+    }];
+    for (n, component) in components.iter().enumerate() {
+        let last = n + 1 == components.len();
+        let mut next = Vec::new();
+        for candidate in candidates {
+            if has_glob(component) == false {
+                next.push(format!("{}{}", candidate, unescape_pattern(component)));
+                continue;
+            }
+
+            let dir = match absolute {
+                true => candidate.clone(),
+                false => format!("{}{}", working_dir, candidate),
+            };
+            let entries = match fs.read_dir(Path::new(dir.as_str())).await {
+                Ok(a) => a,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                let name = match path.file_name().and_then(|n| n.to_str()) {
+                    Some(a) => a.to_string(),
+                    None => continue,
+                };
+
+                // Hidden files must be matched explicitly
+                if name.starts_with('.') && component.starts_with('.') == false {
+                    continue;
+                }
+                if pattern_matches(component, name.as_str()) == false {
+                    continue;
+                }
+                if last == false || dirs_only {
+                    match entry.metadata() {
+                        Ok(meta) if meta.is_dir() => {}
+                        _ => continue,
+                    }
+                }
+                next.push(format!("{}{}", candidate, name));
+            }
+        }
+        candidates = next
+            .into_iter()
+            .map(|c| match last && dirs_only == false {
+                true => c,
+                false => format!("{}/", c),
+            })
+            .collect();
+    }
+
+    // Paths that were not matched by a pattern must exist
+    let mut ret = Vec::new();
+    for candidate in candidates {
+        let path = match absolute {
+            true => candidate.clone(),
+            false => format!("{}{}", working_dir, candidate),
+        };
+        if fs.metadata(Path::new(path.as_str())).await.is_ok() {
+            ret.push(candidate);
+        }
+    }
+    ret.sort();
+    ret
+}
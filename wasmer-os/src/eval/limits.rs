@@ -0,0 +1,247 @@
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+#[cfg(feature = "sys")]
+use std::ptr::NonNull;
+#[cfg(feature = "sys")]
+use wasmer::vm::{MemoryError, MemoryStyle, TableStyle, VMMemory, VMMemoryDefinition, VMTable, VMTableDefinition};
+#[cfg(feature = "sys")]
+use wasmer::{BaseTunables, Engine, MemoryType, Pages, Store, TableType, Target, Tunables};
+
+/// Resource limits that are applied to every process that is started
+/// from a particular context (sub-processes inherit the same limits)
+#[derive(Debug, Clone, Default)]
+pub struct ProcessLimits {
+    /// Maximum amount of fuel (metered instructions) a process may consume
+    pub max_fuel: Option<u64>,
+    /// Maximum number of memory pages a process may allocate
+    pub max_memory_pages: Option<u32>,
+    /// Maximum amount of time a process may run for
+    pub max_wall_time: Option<Duration>,
+    /// Maximum number of files a process may have open at the same time
+    pub max_open_files: Option<u32>,
+    /// Meter that accumilates the resources consumed by the processes
+    pub meter: Option<Arc<ProcessMeter>>,
+}
+
+impl ProcessLimits {
+    pub fn is_metered(&self) -> bool {
+        self.max_fuel.is_some()
+    }
+}
+
+/// Accumilates the resources consumed by processes so that they can
+/// be reported and charged for
+#[derive(Debug, Default)]
+pub struct ProcessMeter {
+    fuel: AtomicU64,
+    compute_micros: AtomicU64,
+    peak_memory_pages: AtomicU32,
+    terminated: AtomicU64,
+}
+
+impl ProcessMeter {
+    pub fn record_fuel(&self, fuel: u64) {
+        self.fuel.fetch_add(fuel, Ordering::AcqRel);
+    }
+
+    pub fn record_compute(&self, elapsed: Duration) {
+        self.compute_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::AcqRel);
+    }
+
+    pub fn record_memory_pages(&self, pages: u32) {
+        self.peak_memory_pages.fetch_max(pages, Ordering::AcqRel);
+    }
+
+    pub fn record_terminated(&self) {
+        self.terminated.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Total fuel consumed by processes (only metered processes are counted)
+    pub fn fuel(&self) -> u64 {
+        self.fuel.load(Ordering::Acquire)
+    }
+
+    /// Total time in microseconds that processes have been running for
+    pub fn compute_micros(&self) -> u64 {
+        self.compute_micros.load(Ordering::Acquire)
+    }
+
+    /// Largest memory that any process has used
+    pub fn peak_memory_pages(&self) -> u32 {
+        self.peak_memory_pages.load(Ordering::Acquire)
+    }
+
+    /// Number of processes that were terminated for exceeding their limits
+    pub fn terminated(&self) -> u64 {
+        self.terminated.load(Ordering::Acquire)
+    }
+
+    /// Takes the fuel and compute time consumed since the last time
+    /// this method was called
+    pub fn take_usage(&self) -> (u64, u64) {
+        (
+            self.fuel.swap(0, Ordering::AcqRel),
+            self.compute_micros.swap(0, Ordering::AcqRel),
+        )
+    }
+}
+
+/// Creates a store for a process which enforces its memory limit
+#[cfg(feature = "sys")]
+pub fn new_limited_store(engine: Option<Engine>, limits: &ProcessLimits) -> Store {
+    match (engine, limits.max_memory_pages) {
+        (Some(engine), Some(pages)) => {
+            let base = BaseTunables::for_target(&Target::default());
+            Store::new_with_tunables(&engine, LimitingTunables::new(base, Pages(pages)))
+        }
+        (None, Some(pages)) => {
+            let engine = Store::default().engine().clone();
+            let base = BaseTunables::for_target(&Target::default());
+            Store::new_with_tunables(&engine, LimitingTunables::new(base, Pages(pages)))
+        }
+        (Some(engine), None) => Store::new(engine),
+        (None, None) => Store::default(),
+    }
+}
+
+/// Tunables that cap the size of every memory a process creates
+/// (including the memory that it imports)
+#[cfg(feature = "sys")]
+pub struct LimitingTunables<T: Tunables> {
+    limit: Pages,
+    base: T,
+}
+
+#[cfg(feature = "sys")]
+impl<T: Tunables> LimitingTunables<T> {
+    pub fn new(base: T, limit: Pages) -> Self {
+        Self { limit, base }
+    }
+
+    /// Memories that do not declare a maximum are given one so that
+    /// the memory style reserves the right amount of address space
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = requested.clone();
+        adjusted.maximum = Some(match requested.maximum {
+            Some(max) if max < self.limit => max,
+            _ => self.limit,
+        });
+        adjusted
+    }
+
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(format!(
+                "memory requires {} pages which exceeds the limit of {} pages",
+                ty.minimum.0, self.limit.0
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sys")]
+impl<T: Tunables> Tunables for LimitingTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        let adjusted = self.adjust_memory(memory);
+        self.base.memory_style(&adjusted)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base
+            .create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+
+    fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<VMTable, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<VMTable, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meter_takes_usage_once() {
+        let meter = ProcessMeter::default();
+        meter.record_fuel(100);
+        meter.record_fuel(20);
+        meter.record_compute(Duration::from_millis(3));
+        meter.record_memory_pages(4);
+        meter.record_memory_pages(2);
+
+        assert_eq!(meter.peak_memory_pages(), 4);
+        assert_eq!(meter.take_usage(), (120, 3000));
+        assert_eq!(meter.take_usage(), (0, 0));
+    }
+
+    #[cfg(feature = "sys")]
+    #[test]
+    fn tunables_cap_memories() {
+        let base = BaseTunables::for_target(&Target::default());
+        let tunables = LimitingTunables::new(base, Pages(10));
+
+        let cases: &[(u32, Option<u32>, Option<u32>)] = &[
+            (1, None, Some(10)),
+            (1, Some(5), Some(5)),
+            (1, Some(100), Some(10)),
+            (10, Some(10), Some(10)),
+        ];
+        for (minimum, maximum, expected) in cases {
+            let ty = MemoryType::new(Pages(*minimum), maximum.map(Pages), false);
+            assert_eq!(tunables.adjust_memory(&ty).maximum, expected.map(Pages), "{:?}", ty);
+        }
+    }
+
+    #[cfg(feature = "sys")]
+    #[test]
+    fn tunables_refuse_large_memories() {
+        let base = BaseTunables::for_target(&Target::default());
+        let tunables = LimitingTunables::new(base, Pages(10));
+
+        let small = MemoryType::new(Pages(2), None, false);
+        let style = tunables.memory_style(&small);
+        assert!(tunables.create_host_memory(&small, &style).is_ok());
+
+        let large = MemoryType::new(Pages(11), None, false);
+        let style = tunables.memory_style(&large);
+        assert!(tunables.create_host_memory(&large, &style).is_err());
+    }
+}
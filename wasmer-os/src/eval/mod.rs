@@ -2,27 +2,45 @@
 #![allow(unused)]
 
 pub(crate) mod andor_list;
+pub(crate) mod arith;
+pub(crate) mod brace;
+pub(crate) mod command_subst;
 pub(crate) mod complete_command;
 pub(crate) mod eval_arg;
 pub(crate) mod exec;
+pub(crate) mod exec_compound;
+pub(crate) mod exec_function;
 pub(crate) mod exec_pipeline;
 pub(crate) mod factory;
+pub(crate) mod glob;
+pub(crate) mod limits;
 pub(crate) mod load_bin;
+pub(crate) mod pattern;
 pub(crate) mod process;
+pub(crate) mod redirect;
 pub(crate) mod runtime;
 pub(crate) mod bus_feeder;
 pub(crate) mod bus_listener;
 pub(crate) mod bus_handle;
 
 pub use andor_list::*;
+pub use arith::*;
+pub use brace::*;
+pub use command_subst::*;
 pub use complete_command::*;
 use derivative::Derivative;
 pub use eval_arg::*;
 pub use exec::*;
+pub use exec_compound::*;
+pub use exec_function::*;
 pub use exec_pipeline::*;
 pub use factory::*;
+pub use glob::*;
+pub use limits::*;
 pub use load_bin::*;
+pub use pattern::*;
 pub use process::*;
+pub use redirect::*;
 pub use runtime::*;
 pub use bus_feeder::*;
 pub use bus_listener::*;
@@ -51,6 +69,10 @@ use crate::wasmer_compiler_llvm::LLVM;
 use crate::wasmer_compiler_singlepass::Singlepass;
 #[cfg(feature = "wasmer-compiler")]
 use crate::wasmer_compiler::EngineBuilder;
+#[cfg(feature = "wasmer-compiler")]
+use crate::wasmer_middlewares::Metering;
+#[cfg(feature = "wasmer-compiler")]
+use crate::wasmer::wasmparser::Operator;
 
 use crate::api::*;
 use crate::ast;
@@ -100,6 +122,10 @@ impl EvalResult {
     }
 }
 
+#[cfg(feature = "wasmer-compiler")]
+static METERED_ENGINES: once_cell::sync::Lazy<Mutex<std::collections::HashMap<String, Engine>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(std::collections::HashMap::new()));
+
 #[derive(Debug, Clone, Copy)]
 pub enum Compiler {
     #[cfg(feature = "singlepass")]
@@ -187,6 +213,79 @@ impl Compiler
         }
     }
 
+    /// Returns the metered engine for this compiler (which is shared by
+    /// all the processes so that they can reuse the compiled modules)
+    #[cfg(feature = "wasmer-compiler")]
+    pub fn metered_engine(&self) -> Option<Engine>
+    {
+        let mut engines = METERED_ENGINES.lock().unwrap();
+        let key = self.to_string();
+        if let Some(engine) = engines.get(&key) {
+            return Some(engine.clone());
+        }
+        let engine = self.new_metered_engine()?;
+        engines.insert(key, engine.clone());
+        Some(engine)
+    }
+
+    /// Creates an engine whose compiled modules count the instructions they
+    /// execute so that processes can be given a fuel budget (modules compiled
+    /// by this engine are not interchangeable with those of a normal engine)
+    #[cfg(feature = "wasmer-compiler")]
+    pub fn new_metered_engine(&self) -> Option<Engine>
+    {
+        // Every operator costs a single unit of fuel
+        let metering = Arc::new(Metering::new(u64::MAX, |_: &Operator| -> u64 { 1 }));
+
+        // Build the features list
+        let mut features = wasmer_compiler::Features::new();
+        features.threads(true);
+        features.memory64(true);
+        features.bulk_memory(true);
+        #[cfg(feature = "singlepass")]
+        if let Compiler::Singlepass = self {
+            features.multi_value(false);
+        }
+
+        // Choose the right compiler
+        match self {
+            #[cfg(feature = "cranelift")]
+            Compiler::Cranelift => {
+                let mut compiler = Cranelift::default();
+                compiler.push_middleware(metering);
+                Some(
+                    EngineBuilder::new(compiler)
+                        .set_features(Some(features))
+                        .engine()
+                )
+            }
+            #[cfg(feature = "llvm")]
+            Compiler::LLVM => {
+                let mut compiler = LLVM::default();
+                compiler.push_middleware(metering);
+                Some(
+                    EngineBuilder::new(compiler)
+                        .set_features(Some(features))
+                        .engine()
+                )
+            }
+            #[cfg(feature = "singlepass")]
+            Compiler::Singlepass => {
+                let mut compiler = Singlepass::default();
+                compiler.push_middleware(metering);
+                Some(
+                    EngineBuilder::new(compiler)
+                        .set_features(Some(features))
+                        .engine()
+                )
+            }
+            #[cfg(feature = "js")]
+            Compiler::Browser => {
+                None
+            }
+        }
+    }
+
     #[cfg(not(feature = "wasmer-compiler"))]
     pub fn new_store(&self) -> Store
     {
@@ -237,6 +336,15 @@ for Compiler
     }
 }
 
+/// Pending change of control flow that unwinds the commands being
+/// evaluated until it reaches the loop (or function) that it targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    Break(u32),
+    Continue(u32),
+    Return,
+}
+
 #[derive(Derivative, Clone)]
 #[derivative(Debug)]
 pub struct EvalContext {
@@ -268,6 +376,12 @@ pub struct EvalContext {
     pub compiler: Compiler,
     pub extra_args: Vec<String>,
     pub extra_redirects: Vec<Redirect>,    
+    pub limits: ProcessLimits,
+    /// Arguments of the function or script being evaluated ($1, $2, ...)
+    pub positional: Vec<String>,
+    pub jump: Option<Jump>,
+    /// Process that was most recently started in the background ($!)
+    pub last_background: Option<Pid>,
     #[derivative(Debug = "ignore")]
     pub checkpoint1: Option<(mpsc::Sender<()>, Arc<WasmCheckpoint>)>,
     #[derivative(Debug = "ignore")]
@@ -295,6 +409,16 @@ pub(crate) fn eval(cmd: String, mut ctx: EvalContext) -> mpsc::Receiver<EvalResu
 
     let work = {
         async move {
+            // Here-documents are folded into their redirects before the
+            // commands are parsed (if one is not finished then more input
+            // is needed)
+            let cmd = match grammar::here_doc::inline_here_docs(cmd.as_str()) {
+                Some(a) => a,
+                None => {
+                    tx.send(EvalResult::new(ctx, EvalStatus::MoreInput)).await;
+                    return;
+                }
+            };
             match parser.parse(cmd.as_str()) {
                 Ok(program) => {
                     let mut show_result = false;
@@ -303,7 +427,12 @@ pub(crate) fn eval(cmd: String, mut ctx: EvalContext) -> mpsc::Receiver<EvalResu
                         let (c, r) = complete_command(ctx, &builtins, &cc, &mut show_result).await;
                         ctx = c;
                         ret = r;
+                        if ctx.jump.is_some() {
+                            break;
+                        }
                     }
+                    // Jumps never escape the commands that were evaluated
+                    ctx.jump = None;
                     tx.send(EvalResult::new(
                         ctx,
                         EvalStatus::Executed {
@@ -0,0 +1,88 @@
+/// Matches text against a shell pattern (`*`, `?`, `[...]` and `\` escapes)
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    matches_from(&pattern[..], &text[..])
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let mut p = 0usize;
+    let mut t = 0usize;
+
+    // Position to resume from when the last star needs to consume more text
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1usize),
+            Some('[') => match match_class(&pattern[p..], text[t]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None if text[t] == '[' => Some(1usize),
+                None => None,
+            },
+            Some('\\') if p + 1 < pattern.len() => match pattern[p + 1] == text[t] {
+                true => Some(2usize),
+                false => None,
+            },
+            Some(c) if *c == text[t] => Some(1usize),
+            _ => None,
+        };
+        match step {
+            Some(len) => {
+                p += len;
+                t += 1;
+            }
+            None => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns if the character is within the bracket expression and the length
+/// of the expression (or `None` if the expression is not terminated)
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut n = 1usize;
+    let negate = match pattern.get(n) {
+        Some('!') | Some('^') => {
+            n += 1;
+            true
+        }
+        _ => false,
+    };
+
+    let mut found = false;
+    let mut first = true;
+    while n < pattern.len() {
+        let start = pattern[n];
+        if start == ']' && first == false {
+            return Some((found != negate, n + 1));
+        }
+        first = false;
+        if n + 2 < pattern.len() && pattern[n + 1] == '-' && pattern[n + 2] != ']' {
+            if start <= c && c <= pattern[n + 2] {
+                found = true;
+            }
+            n += 3;
+        } else {
+            if start == c {
+                found = true;
+            }
+            n += 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,161 @@
+use super::*;
+use crate::err;
+use crate::fs::*;
+use crate::pipe::*;
+use crate::wasmer_vfs::FsError;
+
+/// Connects the standard file descriptors to the files (or documents) that
+/// they have been redirected to, on failure the error message and the exit
+/// code are returned
+pub(crate) async fn apply_redirects(
+    ctx: &EvalContext,
+    fs: &AsyncifyFileSystem,
+    stdio: &mut Stdio,
+    redirects: &[Redirect],
+) -> Result<(), (String, u32)> {
+    for redirect in redirects.iter() {
+        // Here-documents are fed into stdin from memory
+        if redirect.op.heredoc() {
+            let (mut fd, tx) = pipe_in(ReceiverMode::Stream, FdFlag::Stdin(false));
+            let flag = fd.set_flag(FdFlag::Stdin(false));
+            let data = redirect.filename.clone().into_bytes();
+            ctx.system.fork_shared(move || async move {
+                for chunk in data.chunks(4096) {
+                    let _ = tx.send(FdMsg::new(chunk.to_vec(), flag)).await;
+                }
+            });
+            match redirect.fd {
+                -1 | 0 => stdio.stdin = fd,
+                _ => {
+                    return Err((format!("here-documents can only be read from stdin\r\n"), err::ERR_EINVAL));
+                }
+            }
+            continue;
+        }
+
+        // Duplicates make one standard file descriptor refer to another
+        if redirect.op.duplicate() {
+            let src = match redirect.filename.as_str() {
+                "0" => stdio.stdin.clone(),
+                "1" => stdio.stdout.clone(),
+                "2" => stdio.stderr.clone(),
+                _ => {
+                    return Err((format!("redirecting non-standard file descriptors is not yet supported\r\n"), err::ERR_EINVAL));
+                }
+            };
+            match (redirect.fd, redirect.op) {
+                (-1, RedirectionType::TOFD) | (1, _) => stdio.stdout = src,
+                (-1, _) | (0, _) => stdio.stdin = src,
+                (2, _) => stdio.stderr = src,
+                _ => {
+                    return Err((format!("redirecting non-standard file descriptors is not yet supported\r\n"), err::ERR_EINVAL));
+                }
+            }
+            continue;
+        }
+
+        // If its not an absolutely path then make it one
+        let mut filename = redirect.filename.clone();
+        if filename.starts_with("/") == false {
+            filename = format!("{}{}", ctx.working_dir, filename);
+        }
+
+        // Attempt to open the file
+        let file = fs
+            .new_open_options()
+            .await
+            .create(redirect.op.write())
+            .create_new(redirect.op.write() && redirect.op.append() == false)
+            .truncate(redirect.op.write() && redirect.op.append() == false)
+            .append(redirect.op.append())
+            .read(redirect.op.read())
+            .write(redirect.op.write())
+            .open(filename)
+            .await;
+        match file {
+            Ok(mut file) => {
+                // Open a new file description
+                let (tx, mut rx, flag) = {
+                    let (fd, tx, rx) = bidirectional_with_defaults(FdFlag::None);
+
+                    // We now connect the newly opened file descriptor with the read file
+                    let mut flag = FdFlag::None;
+                    match redirect.fd {
+                        -1 => {
+                            if redirect.op.read() {
+                                let mut fd = fd.clone();
+                                flag = fd.set_flag(FdFlag::Stdin(false));
+                                stdio.stdin = fd;
+                            }
+                            if redirect.op.write() {
+                                let mut fd = fd.clone();
+                                flag = fd.set_flag(FdFlag::Stdout(false));
+                                stdio.stdout = fd;
+                            }
+                        }
+                        0 => {
+                            let mut fd = fd.clone();
+                            flag = fd.set_flag(FdFlag::Stdin(false));
+                            stdio.stdin = fd
+                        }
+                        1 => {
+                            let mut fd = fd.clone();
+                            flag = fd.set_flag(FdFlag::Stdout(false));
+                            stdio.stdout = fd
+                        }
+                        2 => {
+                            let mut fd = fd.clone();
+                            flag = fd.set_flag(FdFlag::Stderr(false));
+                            stdio.stderr = fd
+                        }
+                        _ => {
+                            return Err((format!("redirecting non-standard file descriptors is not yet supported\r\n"), err::ERR_EINVAL));
+                        }
+                    };
+
+                    // Now we need to hook up the receiver and sender
+                    (tx, rx, flag)
+                };
+
+                // Now hook up the sender and receiver on a shared task
+                let system = ctx.system;
+                let is_read = redirect.op.read();
+                let is_write = redirect.op.write();
+                system.fork_shared(move || async move {
+                    if is_read {
+                        while let Ok(read) = file.read(4096).await {
+                            let _ = tx.send(FdMsg::new(read, flag)).await;
+                        }
+                    }
+                    if is_write {
+                        while let Some(msg) = rx.recv().await {
+                            match msg {
+                                FdMsg::Data { data, .. } => {
+                                    let _ = file.write_all(data).await;
+                                }
+                                FdMsg::Flush { tx } => {
+                                    file.flush().await;
+                                    let _ = tx.send(()).await;
+                                }
+                            }
+                        }
+                        file.flush().await;
+                    }
+                });
+            }
+            Err(err) => {
+                return Err((
+                    format!(
+                        "failed to open the redirected file '{}' ({}): ",
+                        redirect.filename, err
+                    ),
+                    match err {
+                        FsError::EntityNotFound => err::ERR_ENOENT,
+                        _ => err::ERR_EIO,
+                    },
+                ));
+            }
+        }
+    }
+    Ok(())
+}
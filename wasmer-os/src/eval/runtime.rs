@@ -129,6 +129,7 @@ for WasiRuntime
     }
     
     fn yield_now(&self, _id: WasiCallingId) -> Result<(), WasiError> {
+        self.ctx.wait_while_stopped();
         let forced_exit = self.forced_exit.load(Ordering::Acquire);
         if forced_exit != 0 {
             return Err(WasiError::Exit(forced_exit));
@@ -418,6 +418,9 @@ impl Fd {
                     return Err(std::io::ErrorKind::WouldBlock.into());
                 }
 
+                // Stopped processes stay blocked until they are resumed
+                self.ctx.wait_while_stopped();
+
                 // Check for a forced exit
                 if self.ctx.should_terminate().is_some() {
                     return Err(std::io::ErrorKind::Interrupted.into());
@@ -461,6 +464,9 @@ impl Fd {
                 return Err(std::io::ErrorKind::WouldBlock.into());
             }
 
+            // Stopped processes stay blocked until they are resumed
+            self.ctx.wait_while_stopped();
+
             // Check for a forced exit
             if self.ctx.should_terminate().is_some() {
                 return Err(std::io::ErrorKind::Interrupted.into());
@@ -10,7 +10,9 @@ use std::sync::Arc;
 use std::sync::Mutex;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, trace, warn};
+use wasmer_bus::abi::BusError;
 use wasmer_bus::abi::SerializationFormat;
+use wasmer_bus::abi::STREAM_CHUNK_SIZE;
 use wasmer_bus_fuse::api as backend;
 use wasmer_vfs::DirEntry;
 use wasmer_vfs::FileOpener;
@@ -426,23 +428,28 @@ impl Seek for FuseVirtualFile {
 
 impl Write for FuseVirtualFile {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let ret = self
+        let call = self
             .io
             .call(
                 SerializationFormat::Bincode,
-                backend::FileIoWriteRequest { data: buf.to_vec() },
+                backend::FileIoWriteRequest {},
             )
+            .map_err(|err| err.into_io_error())?;
+
+        // The chunks are only copied out of the buffer as the file system
+        // grants credit for them
+        let chunks = buf.chunks(STREAM_CHUNK_SIZE).map(|a| a.to_vec());
+        let ret = call
+            .block_on_send_stream(SerializationFormat::Bincode, chunks)
             .map_err(|err| err.into_io_error())?
-            .block_on()
-            .map_err(|err| err.into_io_error())?
-            .value::<Result<usize, backend::FsError>>()
+            .value::<Result<u64, backend::FsError>>()
             .map_err(|err| err.into_io_error())?
             .map_err(|err| {
                 let err: io::Error = err.into();
                 err
             })?;
         self.dirty = true;
-        Ok(ret)
+        Ok(ret as usize)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -468,7 +475,7 @@ impl Write for FuseVirtualFile {
 
 impl Read for FuseVirtualFile {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let data = self
+        let call = self
             .io
             .call(
                 SerializationFormat::Bincode,
@@ -476,23 +483,38 @@ impl Read for FuseVirtualFile {
                     len: buf.len() as u64,
                 },
             )
+            .map_err(|err| err.into_io_error())?;
+
+        // The file arrives in chunks which are copied straight into the buffer
+        let mut amt = 0usize;
+        let mut failed = None;
+        call
+            .block_on_receive_stream(SerializationFormat::Bincode, |chunk: Result<Vec<u8>, BusError>| {
+                match chunk {
+                    Ok(chunk) => {
+                        let len = chunk.len().min(buf.len() - amt);
+                        if len < chunk.len() {
+                            debug!("fuse_file_system::read() - file system sent more than was asked for");
+                        }
+                        buf[amt..amt + len].copy_from_slice(&chunk[..len]);
+                        amt += len;
+                    }
+                    Err(err) => {
+                        failed.get_or_insert(err);
+                    }
+                }
+            })
             .map_err(|err| err.into_io_error())?
-            .block_on()
-            .map_err(|err| err.into_io_error())?
-            .value::<Result<Vec<u8>, backend::FsError>>()
+            .value::<Result<(), backend::FsError>>()
             .map_err(|err| err.into_io_error())?
             .map_err(|err| {
                 let err: io::Error = err.into();
                 err
             })?;
-
-        if data.len() <= 0 {
-            return Ok(0usize);
+        if let Some(err) = failed {
+            return Err(err.into_io_error());
         }
-
-        let dst = &mut buf[..data.len()];
-        dst.copy_from_slice(&data[..]);
-        Ok(data.len())
+        Ok(amt)
     }
 }
 
@@ -618,3 +640,198 @@ fn conv_fs_error(err: backend::FsError) -> FsError {
         backend::FsError::UnknownError => FsError::UnknownError,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::future::Future;
+    use std::pin::Pin;
+    use tokio::sync::mpsc;
+    use wasmer::vm::VMMemory;
+    use wasmer::{Module, Store};
+    use wasmer_bus::abi::{StreamCredit, StreamData, StreamEnd, STREAM_WINDOW};
+    use wasmer_wasi::WasiThreadError;
+
+    use crate::bus::{conv_format, type_name_hash};
+    use crate::common::MAX_MPSC;
+    use crate::eval::{RuntimeCallOutsideTask, RuntimeCallStateChange, RuntimeNewCall};
+
+    /// The calls only need the system when their channels are full (which
+    /// they never are in these tests)
+    struct NoSystem;
+
+    #[async_trait]
+    impl SystemAbi for NoSystem {
+        fn task_shared(&self, _task: Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> + Send + 'static>) {
+            unimplemented!()
+        }
+        fn task_wasm(&self, _task: Box<dyn FnOnce(Store, Module, Option<VMMemory>) -> Pin<Box<dyn Future<Output = ()> + 'static>> + Send + 'static>, _store: Store, _module: Module, _spawn_type: SpawnType) -> Result<(), WasiThreadError> {
+            unimplemented!()
+        }
+        fn task_dedicated(&self, _task: Box<dyn FnOnce() + Send + 'static>) {
+            unimplemented!()
+        }
+        fn task_dedicated_async(&self, _task: Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()> + 'static>> + Send + 'static>) {
+            unimplemented!()
+        }
+        fn task_local(&self, _task: Pin<Box<dyn Future<Output = ()> + 'static>>) {
+            unimplemented!()
+        }
+        fn sleep(&self, _ms: u128) -> AsyncResult<()> {
+            unimplemented!()
+        }
+        fn fetch_file(&self, _path: &str) -> AsyncResult<Result<Vec<u8>, u32>> {
+            unimplemented!()
+        }
+        fn reqwest(&self, _url: &str, _method: &str, _options: ReqwestOptions, _headers: Vec<(String, String)>, _data: Option<Vec<u8>>) -> AsyncResult<Result<ReqwestResponse, u32>> {
+            unimplemented!()
+        }
+        async fn web_socket(&self, _url: &str) -> Result<Box<dyn WebSocketAbi>, String> {
+            unimplemented!()
+        }
+        async fn webgl(&self) -> Option<Box<dyn WebGlAbi>> {
+            unimplemented!()
+        }
+    }
+
+    static NO_SYSTEM: NoSystem = NoSystem;
+
+    fn handle(tx: mpsc::Sender<RuntimeNewCall>) -> RuntimeCallOutsideHandle {
+        let system = System { inner: &NO_SYSTEM };
+        let (_, rx) = mpsc::channel(1);
+        RuntimeCallOutsideHandle {
+            system,
+            task: RuntimeCallOutsideTask { system, tx },
+            rx,
+            callbacks: Default::default(),
+        }
+    }
+
+    fn send<T: serde::Serialize>(call: &RuntimeNewCall, format: SerializationFormat, data: T) {
+        let buf = format.serialize(data).unwrap();
+        let _ = call.tx.blocking_send(RuntimeCallStateChange::Callback {
+            topic_hash: type_name_hash::<T>(),
+            format,
+            buf,
+        });
+    }
+
+    fn reply<T: serde::Serialize>(call: &RuntimeNewCall, format: SerializationFormat, data: T) {
+        let buf = format.serialize(data).unwrap();
+        let _ = call.tx.blocking_send(RuntimeCallStateChange::Reply { format, buf });
+    }
+
+    /// File system that serves the I/O of a single file (which is missing
+    /// when it is `None`) and keeps to the credit that it is given
+    fn file(contents: Arc<Mutex<Option<Vec<u8>>>>) -> FuseVirtualFile {
+        let (tx, mut rx) = mpsc::channel::<RuntimeNewCall>(MAX_MPSC);
+        std::thread::spawn(move || {
+            while let Some(mut call) = rx.blocking_recv() {
+                let format = conv_format(call.format);
+                if call.topic_hash == type_name_hash::<backend::FileIoReadRequest>() {
+                    let req: backend::FileIoReadRequest = format.deserialize(call.data.clone()).unwrap();
+                    let data = match contents.lock().unwrap().clone() {
+                        Some(a) => a,
+                        None => {
+                            reply(&call, format, Err::<(), _>(backend::FsError::EntityNotFound));
+                            continue;
+                        }
+                    };
+                    let mut credit = STREAM_WINDOW;
+                    for chunk in data[..(req.len as usize).min(data.len())].chunks(STREAM_CHUNK_SIZE) {
+                        while credit == 0 {
+                            let sub = call.rx.blocking_recv().unwrap();
+                            assert_eq!(sub.topic_hash, type_name_hash::<StreamCredit>());
+                            credit += conv_format(sub.format).deserialize::<StreamCredit>(sub.data).unwrap().frames;
+                        }
+                        let data = format.serialize(chunk.to_vec()).unwrap();
+                        send(&call, format, StreamData { data });
+                        credit -= 1;
+                    }
+                    reply(&call, format, Ok::<(), backend::FsError>(()));
+                } else if call.topic_hash == type_name_hash::<backend::FileIoWriteRequest>() {
+                    send(&call, format, StreamCredit { frames: STREAM_WINDOW });
+                    let mut written = Vec::new();
+                    let mut received = 0u32;
+                    while let Some(sub) = call.rx.blocking_recv() {
+                        let sub_format = conv_format(sub.format);
+                        if sub.topic_hash == type_name_hash::<StreamEnd>() {
+                            break;
+                        }
+                        let frame: StreamData = sub_format.deserialize(sub.data).unwrap();
+                        written.extend(format.deserialize::<Vec<u8>>(frame.data).unwrap());
+                        received += 1;
+                        if received >= STREAM_WINDOW / 2 {
+                            send(&call, format, StreamCredit { frames: received });
+                            received = 0;
+                        }
+                    }
+                    let len = written.len() as u64;
+                    contents.lock().unwrap().replace(written);
+                    reply(&call, format, Ok::<u64, backend::FsError>(len));
+                } else {
+                    reply(&call, format, Ok::<(), backend::FsError>(()));
+                }
+            }
+        });
+
+        let (unused, _) = mpsc::channel(1);
+        FuseVirtualFile {
+            ctx: WasmCallerContext::default(),
+            task: handle(unused),
+            io: handle(tx),
+            meta: backend::Metadata {
+                ft: backend::FileType::default(),
+                accessed: 0,
+                created: 0,
+                modified: 0,
+                len: 0,
+            },
+            dirty: false,
+        }
+    }
+
+    /// Larger than the window of the stream so that more credit is needed
+    fn big_file() -> Vec<u8> {
+        (0..STREAM_CHUNK_SIZE * (STREAM_WINDOW as usize + 4) + 10)
+            .map(|a| (a % 251) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn reads_are_streamed_into_the_buffer() {
+        let data = big_file();
+        let mut file = file(Arc::new(Mutex::new(Some(data.clone()))));
+
+        let mut buf = vec![0u8; data.len() + 100];
+        assert_eq!(file.read(&mut buf[..]).unwrap(), data.len());
+        assert!(buf[..data.len()] == data[..]);
+
+        // Only as much as fits in the buffer is asked for
+        let mut buf = vec![0u8; 100];
+        assert_eq!(file.read(&mut buf[..]).unwrap(), 100);
+        assert_eq!(buf[..], data[..100]);
+    }
+
+    #[test]
+    fn reads_of_missing_files_fail() {
+        let mut file = file(Arc::new(Mutex::new(None)));
+        let mut buf = [0u8; 10];
+        let err = file.read(&mut buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn writes_are_streamed_as_credit_arrives() {
+        let data = big_file();
+        let contents = Arc::new(Mutex::new(None));
+        let mut file = file(contents.clone());
+
+        assert_eq!(file.write(&data[..]).unwrap(), data.len());
+        assert!(contents.lock().unwrap().as_ref() == Some(&data));
+
+        // (dropping the file flushes what was written)
+        drop(file);
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::wasmer_vfs::*;
+
+/// Caps the number of files that may be open through the file system at
+/// the same time (the equivalent of RLIMIT_NOFILE for a process)
+#[derive(Debug, Clone)]
+pub struct LimitedFileSystem {
+    inner: Arc<dyn FileSystem>,
+    open: Arc<AtomicU32>,
+    max_open_files: u32,
+}
+
+impl LimitedFileSystem {
+    pub fn new(inner: impl FileSystem, max_open_files: u32) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            open: Arc::new(AtomicU32::new(0)),
+            max_open_files,
+        }
+    }
+
+    /// Number of files that are currently open
+    pub fn open_files(&self) -> u32 {
+        self.open.load(Ordering::Acquire)
+    }
+}
+
+impl FileSystem for LimitedFileSystem {
+    fn read_dir(&self, path: &Path) -> Result<ReadDir> {
+        self.inner.read_dir(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn new_open_options(&self) -> OpenOptions {
+        OpenOptions::new(Box::new(LimitedFileOpener { fs: self.clone() }))
+    }
+}
+
+#[derive(Debug)]
+pub struct LimitedFileOpener {
+    fs: LimitedFileSystem,
+}
+
+impl FileOpener for LimitedFileOpener {
+    fn open(
+        &mut self,
+        path: &Path,
+        conf: &OpenOptionsConfig,
+    ) -> Result<Box<dyn VirtualFile + Send + Sync>> {
+        // The slot is reserved before the file is opened so that concurrent
+        // opens can not exceed the limit between them
+        let max = self.fs.max_open_files;
+        if self
+            .fs
+            .open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| match n < max {
+                true => Some(n + 1),
+                false => None,
+            })
+            .is_err()
+        {
+            // (there is no file system error that maps to EMFILE)
+            debug!("too many open files (max={}) - path={}", max, path.display());
+            return Err(FsError::IOError);
+        }
+        let slot = OpenFileSlot {
+            open: self.fs.open.clone(),
+        };
+
+        let inner = self
+            .fs
+            .inner
+            .new_open_options()
+            .options(conf.clone())
+            .open(path)?;
+        Ok(Box::new(LimitedFile { inner, _slot: slot }))
+    }
+}
+
+/// Releases the slot of an open file when it is dropped
+#[derive(Debug)]
+struct OpenFileSlot {
+    open: Arc<AtomicU32>,
+}
+
+impl Drop for OpenFileSlot {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[derive(Debug)]
+pub struct LimitedFile {
+    inner: Box<dyn VirtualFile + Send + Sync>,
+    _slot: OpenFileSlot,
+}
+
+impl Seek for LimitedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl Write for LimitedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Read for LimitedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl VirtualFile for LimitedFile {
+    fn last_accessed(&self) -> u64 {
+        self.inner.last_accessed()
+    }
+    fn last_modified(&self) -> u64 {
+        self.inner.last_modified()
+    }
+    fn created_time(&self) -> u64 {
+        self.inner.created_time()
+    }
+    fn size(&self) -> u64 {
+        self.inner.size()
+    }
+    fn set_len(&mut self, new_size: u64) -> Result<()> {
+        self.inner.set_len(new_size)
+    }
+    fn unlink(&mut self) -> Result<()> {
+        self.inner.unlink()
+    }
+    fn sync_to_disk(&self) -> Result<()> {
+        self.inner.sync_to_disk()
+    }
+    fn bytes_available(&self) -> Result<usize> {
+        self.inner.bytes_available()
+    }
+    fn bytes_available_read(&self) -> Result<Option<usize>> {
+        self.inner.bytes_available_read()
+    }
+    fn bytes_available_write(&self) -> Result<Option<usize>> {
+        self.inner.bytes_available_write()
+    }
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+    fn get_fd(&self) -> Option<FileDescriptor> {
+        self.inner.get_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::TmpFileSystem;
+
+    fn open(fs: &LimitedFileSystem, path: &str) -> Result<Box<dyn VirtualFile + Send + Sync>> {
+        fs.new_open_options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(Path::new(path))
+    }
+
+    #[test]
+    fn open_files_are_capped() {
+        let fs = LimitedFileSystem::new(TmpFileSystem::new(), 2);
+
+        let a = open(&fs, "/a").unwrap();
+        let _b = open(&fs, "/b").unwrap();
+        assert_eq!(fs.open_files(), 2);
+        assert!(open(&fs, "/c").is_err());
+        assert!(fs.metadata(Path::new("/c")).is_err());
+
+        // Closing a file frees its slot
+        drop(a);
+        assert_eq!(fs.open_files(), 1);
+        let _c = open(&fs, "/c").unwrap();
+        assert_eq!(fs.open_files(), 2);
+    }
+
+    #[test]
+    fn failed_opens_do_not_hold_a_slot() {
+        let fs = LimitedFileSystem::new(TmpFileSystem::new(), 1);
+
+        let missing = fs.new_open_options().read(true).open(Path::new("/missing"));
+        assert!(missing.is_err());
+        assert_eq!(fs.open_files(), 0);
+        let _a = open(&fs, "/a").unwrap();
+    }
+}
@@ -2,6 +2,8 @@ mod api;
 mod asyncify;
 mod ext;
 mod fuse;
+mod limited;
+mod overlay;
 mod proc;
 mod tmp;
 mod union;
@@ -11,6 +13,8 @@ pub use api::*;
 pub use asyncify::*;
 pub use ext::*;
 pub use fuse::*;
+pub use limited::*;
+pub use overlay::*;
 pub use proc::*;
 pub use tmp::*;
 pub use union::*;
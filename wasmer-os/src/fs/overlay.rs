@@ -0,0 +1,626 @@
+#![allow(dead_code)]
+#![allow(unused)]
+use std::collections::HashSet;
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::wasmer_vfs::*;
+
+use super::api::*;
+use crate::bus::WasmCallerContext;
+
+/// Files in the upper layer with this prefix hide (whiteout) the entry of
+/// the same name (without the prefix) in the lower layer
+const WHITEOUT_PREFIX: &'static str = ".wh.";
+/// Directories in the upper layer that contain this file do not show any
+/// of the entries of the lower layer (they replaced a deleted directory)
+const OPAQUE_MARKER: &'static str = ".wh..wh..opq";
+
+fn whiteout_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    Some(path.with_file_name(format!("{}{}", WHITEOUT_PREFIX, name.to_string_lossy())))
+}
+
+fn is_hidden_name(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy().starts_with(WHITEOUT_PREFIX))
+        .unwrap_or(false)
+}
+
+fn entry_name(entry: &DirEntry) -> Option<String> {
+    entry.path.file_name().map(|n| n.to_string_lossy().to_string())
+}
+
+/// Overlays a writable upper layer over a read-only lower layer (in the
+/// same way as overlayfs) - files are copied up into the upper layer when
+/// they are written to and deletions are recorded as whiteouts
+#[derive(Debug, Clone)]
+pub struct OverlayFileSystem {
+    upper: Arc<Box<dyn MountedFileSystem>>,
+    lower: Arc<Box<dyn MountedFileSystem>>,
+}
+
+impl OverlayFileSystem {
+    pub fn new(upper: Box<dyn MountedFileSystem>, lower: Box<dyn MountedFileSystem>) -> Self {
+        Self {
+            upper: Arc::new(upper),
+            lower: Arc::new(lower),
+        }
+    }
+
+    /// Returns true if the lower layer shows through at a path (i.e. it is
+    /// not hidden by a whiteout or an opaque directory in the upper layer)
+    fn is_lower_visible(&self, path: &Path) -> bool {
+        for ancestor in path.ancestors() {
+            if self.upper.metadata(&ancestor.join(OPAQUE_MARKER)).is_ok() {
+                return false;
+            }
+            if let Some(whiteout) = whiteout_path(ancestor) {
+                if self.upper.metadata(whiteout.as_path()).is_ok() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn lower_metadata(&self, path: &Path) -> Result<Metadata> {
+        if self.is_lower_visible(path) == false {
+            return Err(FsError::EntityNotFound);
+        }
+        self.lower.metadata(path)
+    }
+
+    /// Creates the parent directories of a path in the upper layer (they
+    /// must already exist in the overlay)
+    fn create_upper_parents(&self, path: &Path) -> Result<()> {
+        let parent = match path.parent() {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+        let mut dirs = parent.ancestors().collect::<Vec<_>>();
+        dirs.reverse();
+        for dir in dirs {
+            if self.upper.metadata(dir).is_ok() {
+                continue;
+            }
+            match self.metadata(dir) {
+                Ok(meta) if meta.is_dir() => {}
+                Ok(_) => return Err(FsError::BaseNotDirectory),
+                Err(err) => return Err(err),
+            }
+            self.upper.create_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Copies a file (or an empty directory) from the lower layer into the
+    /// upper layer so that it can be modified
+    fn copy_up(&self, path: &Path) -> Result<()> {
+        if self.upper.metadata(path).is_ok() {
+            return Ok(());
+        }
+        let meta = self.lower_metadata(path)?;
+        self.create_upper_parents(path)?;
+        if meta.is_dir() {
+            return self.upper.create_dir(path);
+        }
+        debug!("copy-up: path={}", path.display());
+
+        // (the data is streamed across so large files are not held in memory)
+        let mut src = self.lower.new_open_options().read(true).open(path)?;
+        let mut dst = self
+            .upper
+            .new_open_options()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        io::copy(&mut src, &mut dst).map_err(|_| FsError::IOError)?;
+        Ok(())
+    }
+
+    /// Copies a file or a whole directory tree into the upper layer
+    fn copy_up_all(&self, path: &Path) -> Result<()> {
+        self.copy_up(path)?;
+        if self.metadata(path)?.is_dir() {
+            for entry in self.read_dir(path)?.filter_map(|e| e.ok()) {
+                if let Some(name) = entry_name(&entry) {
+                    self.copy_up_all(path.join(name).as_path())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn whiteout(&self, path: &Path) -> Result<()> {
+        let whiteout = whiteout_path(path).ok_or(FsError::InvalidInput)?;
+        self.create_upper_parents(path)?;
+        self.upper
+            .new_open_options()
+            .create(true)
+            .write(true)
+            .open(whiteout)?;
+        Ok(())
+    }
+
+    fn remove_whiteout(&self, path: &Path) {
+        if let Some(whiteout) = whiteout_path(path) {
+            let _ = self.upper.remove_file(whiteout.as_path());
+        }
+    }
+
+    fn mark_opaque(&self, path: &Path) -> Result<()> {
+        self.upper
+            .new_open_options()
+            .create(true)
+            .write(true)
+            .open(path.join(OPAQUE_MARKER))?;
+        Ok(())
+    }
+}
+
+impl MountedFileSystem for OverlayFileSystem {
+    fn set_ctx(&self, ctx: &WasmCallerContext) {
+        self.upper.set_ctx(ctx);
+        self.lower.set_ctx(ctx);
+    }
+}
+
+impl FileSystem for OverlayFileSystem {
+    fn read_dir(&self, path: &Path) -> Result<ReadDir> {
+        debug!("read_dir: path={}", path.display());
+        let upper = self.upper.read_dir(path);
+        let lower = match self.is_lower_visible(path) {
+            true => self.lower.read_dir(path).ok(),
+            false => None,
+        };
+        let upper = match (upper, lower.is_some()) {
+            (Ok(a), _) => Some(a),
+            (Err(_), true) => None,
+            (Err(err), false) => return Err(err),
+        };
+
+        let mut ret = Vec::new();
+        let mut names = HashSet::new();
+        let mut hidden = HashSet::new();
+        for entry in upper.into_iter().flatten().filter_map(|e| e.ok()) {
+            let name = match entry_name(&entry) {
+                Some(a) => a,
+                None => continue,
+            };
+            if name == OPAQUE_MARKER {
+                continue;
+            }
+            if let Some(name) = name.strip_prefix(WHITEOUT_PREFIX) {
+                hidden.insert(name.to_string());
+                continue;
+            }
+            names.insert(name);
+            ret.push(entry);
+        }
+        for entry in lower.into_iter().flatten().filter_map(|e| e.ok()) {
+            let name = match entry_name(&entry) {
+                Some(a) => a,
+                None => continue,
+            };
+            if hidden.contains(&name) || names.insert(name) == false {
+                continue;
+            }
+            ret.push(entry);
+        }
+        Ok(ReadDir::new(ret))
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        debug!("create_dir: path={}", path.display());
+        if self.metadata(path).is_ok() {
+            return Err(FsError::AlreadyExists);
+        }
+        self.create_upper_parents(path)?;
+        self.remove_whiteout(path);
+        self.upper.create_dir(path)?;
+
+        // A directory that replaces a deleted one must not show what was in it
+        if self.lower_metadata(path).is_ok() {
+            self.mark_opaque(path)?;
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        debug!("remove_dir: path={}", path.display());
+        if self.read_dir(path)?.next().is_some() {
+            return Err(FsError::DirectoryNotEmpty);
+        }
+        let in_lower = self.lower_metadata(path).is_ok();
+        if self.upper.metadata(path).is_ok() {
+            // The whiteouts and markers in the directory go with it
+            if let Ok(entries) = self.upper.read_dir(path) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    if let Some(name) = entry_name(&entry) {
+                        let _ = self.upper.remove_file(path.join(name).as_path());
+                    }
+                }
+            }
+            self.upper.remove_dir(path)?;
+        }
+        if in_lower {
+            self.whiteout(path)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        debug!("rename: from={} to={}", from.display(), to.display());
+        let is_dir = self.metadata(from)?.is_dir();
+        self.copy_up_all(from)?;
+        self.create_upper_parents(to)?;
+        self.remove_whiteout(to);
+        self.upper.rename(from, to)?;
+
+        if self.lower_metadata(from).is_ok() {
+            self.whiteout(from)?;
+        }
+        if is_dir && self.lower_metadata(to).is_ok() {
+            self.mark_opaque(to)?;
+        }
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        if is_hidden_name(path) {
+            return Err(FsError::EntityNotFound);
+        }
+        match self.upper.metadata(path) {
+            Ok(a) => Ok(a),
+            Err(_) => self.lower_metadata(path),
+        }
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata> {
+        if is_hidden_name(path) {
+            return Err(FsError::EntityNotFound);
+        }
+        match self.upper.symlink_metadata(path) {
+            Ok(a) => Ok(a),
+            Err(_) => match self.is_lower_visible(path) {
+                true => self.lower.symlink_metadata(path),
+                false => Err(FsError::EntityNotFound),
+            },
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        debug!("remove_file: path={}", path.display());
+        let in_upper = self.upper.metadata(path).is_ok();
+        let in_lower = self.lower_metadata(path).is_ok();
+        if in_upper == false && in_lower == false {
+            return Err(FsError::EntityNotFound);
+        }
+        if in_upper {
+            self.upper.remove_file(path)?;
+        }
+        if in_lower {
+            self.whiteout(path)?;
+        }
+        Ok(())
+    }
+
+    fn new_open_options(&self) -> OpenOptions {
+        OpenOptions::new(Box::new(OverlayFileOpener { fs: self.clone() }))
+    }
+}
+
+#[derive(Debug)]
+pub struct OverlayFileOpener {
+    fs: OverlayFileSystem,
+}
+
+impl FileOpener for OverlayFileOpener {
+    fn open(
+        &mut self,
+        path: &Path,
+        conf: &OpenOptionsConfig,
+    ) -> Result<Box<dyn VirtualFile + Send + Sync>> {
+        debug!("open: path={}", path.display());
+        let fs = &self.fs;
+        if is_hidden_name(path) {
+            return Err(FsError::PermissionDenied);
+        }
+
+        // Files that are only read come from whichever layer has them
+        let modifies = conf.write() || conf.append() || conf.truncate() || conf.create() || conf.create_new();
+        if modifies == false {
+            if fs.upper.metadata(path).is_ok() {
+                let file = fs.upper.new_open_options().options(conf.clone()).open(path)?;
+                return Ok(OverlayFile::new(fs, path, file, true));
+            }
+            if fs.is_lower_visible(path) == false {
+                return Err(FsError::EntityNotFound);
+            }
+            let file = fs.lower.new_open_options().options(conf.clone()).open(path)?;
+            return Ok(OverlayFile::new(fs, path, file, false));
+        }
+
+        // Otherwise the file is copied up (unless it is about to be truncated)
+        let exists = fs.metadata(path).is_ok();
+        if exists && conf.create_new() {
+            return Err(FsError::AlreadyExists);
+        }
+        if exists == false && conf.create() == false && conf.create_new() == false {
+            return Err(FsError::EntityNotFound);
+        }
+        if exists && conf.truncate() == false {
+            fs.copy_up(path)?;
+        } else {
+            fs.create_upper_parents(path)?;
+        }
+        fs.remove_whiteout(path);
+
+        let create = fs.upper.metadata(path).is_err();
+        let file = fs
+            .upper
+            .new_open_options()
+            .options(conf.clone())
+            .create(create || conf.create())
+            .open(path)?;
+        Ok(OverlayFile::new(fs, path, file, true))
+    }
+}
+
+/// File opened through the overlay, unlinking it also hides the copy in
+/// the lower layer (otherwise it would show through again)
+#[derive(Debug)]
+pub struct OverlayFile {
+    fs: OverlayFileSystem,
+    path: PathBuf,
+    inner: Box<dyn VirtualFile + Send + Sync>,
+    upper: bool,
+}
+
+impl OverlayFile {
+    fn new(
+        fs: &OverlayFileSystem,
+        path: &Path,
+        inner: Box<dyn VirtualFile + Send + Sync>,
+        upper: bool,
+    ) -> Box<dyn VirtualFile + Send + Sync> {
+        Box::new(OverlayFile {
+            fs: fs.clone(),
+            path: path.to_path_buf(),
+            inner,
+            upper,
+        })
+    }
+}
+
+impl Seek for OverlayFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl Write for OverlayFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Read for OverlayFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl VirtualFile for OverlayFile {
+    fn last_accessed(&self) -> u64 {
+        self.inner.last_accessed()
+    }
+    fn last_modified(&self) -> u64 {
+        self.inner.last_modified()
+    }
+    fn created_time(&self) -> u64 {
+        self.inner.created_time()
+    }
+    fn size(&self) -> u64 {
+        self.inner.size()
+    }
+    fn set_len(&mut self, new_size: u64) -> Result<()> {
+        self.inner.set_len(new_size)
+    }
+    fn unlink(&mut self) -> Result<()> {
+        debug!("unlink: path={}", self.path.display());
+        // (the lower layer is read-only so it is never touched)
+        if self.upper {
+            self.inner.unlink()?;
+        }
+        if self.fs.lower_metadata(self.path.as_path()).is_ok() {
+            self.fs.whiteout(self.path.as_path())?;
+        }
+        Ok(())
+    }
+    fn sync_to_disk(&self) -> Result<()> {
+        self.inner.sync_to_disk()
+    }
+    fn bytes_available(&self) -> Result<usize> {
+        self.inner.bytes_available()
+    }
+    fn bytes_available_read(&self) -> Result<Option<usize>> {
+        self.inner.bytes_available_read()
+    }
+    fn bytes_available_write(&self) -> Result<Option<usize>> {
+        self.inner.bytes_available_write()
+    }
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+    fn get_fd(&self) -> Option<FileDescriptor> {
+        self.inner.get_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::TmpFileSystem;
+
+    /// Creates an overlay (along with its upper and lower layers) where the
+    /// lower layer holds the supplied files
+    fn overlay(files: &[(&str, &str)]) -> (OverlayFileSystem, TmpFileSystem, TmpFileSystem) {
+        let upper = TmpFileSystem::new();
+        let lower = TmpFileSystem::new();
+        for (path, data) in files {
+            let path = Path::new(path);
+            let mut dirs = path.ancestors().skip(1).collect::<Vec<_>>();
+            dirs.reverse();
+            for dir in dirs.into_iter().skip(1) {
+                let _ = lower.create_dir(dir);
+            }
+            write(&lower, path.to_str().unwrap(), data);
+        }
+        let fs = OverlayFileSystem::new(Box::new(upper.clone()), Box::new(lower.clone()));
+        (fs, upper, lower)
+    }
+
+    fn write(fs: &dyn FileSystem, path: &str, data: &str) {
+        let mut file = fs
+            .new_open_options()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(Path::new(path))
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    fn read(fs: &dyn FileSystem, path: &str) -> Option<String> {
+        let mut file = fs.new_open_options().read(true).open(Path::new(path)).ok()?;
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+        Some(data)
+    }
+
+    fn names(fs: &dyn FileSystem, path: &str) -> Vec<String> {
+        let mut ret = fs
+            .read_dir(Path::new(path))
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| entry_name(&e))
+            .collect::<Vec<_>>();
+        ret.sort();
+        ret
+    }
+
+    fn exists(fs: &dyn FileSystem, path: &str) -> bool {
+        fs.metadata(Path::new(path)).is_ok()
+    }
+
+    #[test]
+    fn read_dir_merges_the_layers() {
+        let (fs, upper, _) = overlay(&[("/etc/a", "lower"), ("/etc/b", "lower"), ("/etc/c", "lower")]);
+        write(&fs, "/etc/a", "upper");
+        write(&fs, "/etc/d", "upper");
+        fs.remove_file(Path::new("/etc/b")).unwrap();
+
+        assert_eq!(names(&fs, "/etc"), vec!["a", "c", "d"]);
+        assert_eq!(read(&fs, "/etc/a").unwrap(), "upper");
+        assert_eq!(read(&fs, "/etc/c").unwrap(), "lower");
+        assert!(read(&fs, "/etc/b").is_none());
+
+        // Whiteouts are never visible (or openable) through the overlay
+        assert!(exists(&upper, "/etc/.wh.b"));
+        assert!(exists(&fs, "/etc/.wh.b") == false);
+        assert!(read(&fs, "/etc/.wh.b").is_none());
+    }
+
+    #[test]
+    fn copy_up_keeps_the_lower_layer() {
+        let data = "x".repeat(100_000);
+        let (fs, upper, lower) = overlay(&[("/big", data.as_str())]);
+
+        let mut file = fs.new_open_options().write(true).open(Path::new("/big")).unwrap();
+        file.write_all(b"y").unwrap();
+        drop(file);
+
+        let expected = format!("y{}", &data[1..]);
+        assert_eq!(read(&fs, "/big").unwrap(), expected);
+        assert_eq!(read(&upper, "/big").unwrap(), expected);
+        assert_eq!(read(&lower, "/big").unwrap(), data);
+    }
+
+    #[test]
+    fn removed_lower_dirs_stay_hidden_when_recreated() {
+        let (fs, upper, lower) = overlay(&[("/d/x", "lower")]);
+        assert!(matches!(fs.remove_dir(Path::new("/d")), Err(FsError::DirectoryNotEmpty)));
+
+        fs.remove_file(Path::new("/d/x")).unwrap();
+        fs.remove_dir(Path::new("/d")).unwrap();
+        assert!(exists(&fs, "/d") == false);
+        assert!(exists(&upper, "/.wh.d"));
+        assert!(exists(&upper, "/d") == false);
+        assert!(names(&fs, "/").is_empty());
+
+        // Re-creating the directory must not bring back what was in it
+        fs.create_dir(Path::new("/d")).unwrap();
+        assert!(exists(&upper, "/.wh.d") == false);
+        assert!(exists(&upper, "/d/.wh..wh..opq"));
+        assert!(names(&fs, "/d").is_empty());
+        assert!(exists(&fs, "/d/x") == false);
+        assert_eq!(read(&lower, "/d/x").unwrap(), "lower");
+    }
+
+    #[test]
+    fn renamed_dirs_are_copied_up() {
+        let (fs, upper, lower) = overlay(&[("/etc/a", "a"), ("/etc/sub/b", "b")]);
+        fs.rename(Path::new("/etc"), Path::new("/conf")).unwrap();
+
+        assert_eq!(names(&fs, "/"), vec!["conf"]);
+        assert_eq!(names(&fs, "/conf"), vec!["a", "sub"]);
+        assert_eq!(read(&fs, "/conf/sub/b").unwrap(), "b");
+        assert!(exists(&fs, "/etc") == false);
+        assert!(exists(&upper, "/.wh.etc"));
+        assert_eq!(read(&lower, "/etc/a").unwrap(), "a");
+
+        // Renaming over a lower directory hides its old contents
+        let (fs, upper, _) = overlay(&[("/x/old", ""), ("/y/new", "")]);
+        fs.remove_file(Path::new("/x/old")).unwrap();
+        fs.remove_dir(Path::new("/x")).unwrap();
+        fs.rename(Path::new("/y"), Path::new("/x")).unwrap();
+        assert_eq!(names(&fs, "/x"), vec!["new"]);
+        assert!(exists(&upper, "/x/.wh..wh..opq"));
+    }
+
+    #[test]
+    fn unlinked_files_are_whited_out() {
+        let (fs, upper, lower) = overlay(&[("/a", "a"), ("/b", "b")]);
+
+        // A file that was only read from the lower layer
+        let mut file = fs.new_open_options().read(true).open(Path::new("/a")).unwrap();
+        file.unlink().unwrap();
+        assert!(exists(&fs, "/a") == false);
+        assert!(exists(&upper, "/.wh.a"));
+
+        // A file that was copied up
+        let mut file = fs.new_open_options().write(true).open(Path::new("/b")).unwrap();
+        file.unlink().unwrap();
+        assert!(exists(&fs, "/b") == false);
+        assert!(exists(&upper, "/b") == false);
+        assert!(exists(&upper, "/.wh.b"));
+
+        assert!(names(&fs, "/").is_empty());
+        assert_eq!(names(&lower, "/"), vec!["a", "b"]);
+
+        // Writing the file again brings it back
+        write(&fs, "/a", "new");
+        assert_eq!(read(&fs, "/a").unwrap(), "new");
+        assert!(exists(&upper, "/.wh.a") == false);
+    }
+}
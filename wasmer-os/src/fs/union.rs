@@ -12,6 +12,7 @@ use std::sync::Weak;
 use tracing::{debug, error, info, trace, warn};
 
 use super::api::MountedFileSystem;
+use super::overlay::OverlayFileSystem;
 use crate::bus::WasmCallerContext;
 
 #[derive(Debug)]
@@ -136,6 +137,32 @@ impl UnionFileSystem {
         });
     }
 
+    /// Mounts a writable upper file system over a read-only lower one so that
+    /// changes are copied up into the upper file system (see `OverlayFileSystem`)
+    pub fn mount_overlay(
+        &mut self,
+        name: &str,
+        path: &str,
+        should_sanitize: bool,
+        upper: Box<dyn MountedFileSystem>,
+        lower: Box<dyn MountedFileSystem>,
+    ) {
+        let fs = OverlayFileSystem::new(upper, lower);
+        self.mount(name, path, should_sanitize, Box::new(fs), None);
+    }
+
+    /// Returns a view of a directory of this file system as if it were the
+    /// root of its own (the view holds on to the mounts it sees)
+    pub fn subdir(&self, path: &str) -> UnionFileSystem {
+        let mut inner = self.clone();
+        for mount in inner.mounts.iter_mut() {
+            mount.fs = mount.fs();
+        }
+        let mut ret = UnionFileSystem::new();
+        ret.mount("subdir", "/", false, Box::new(inner), Some(path));
+        ret
+    }
+
     pub fn unmount(&mut self, path: &str) {
         let path1 = path.to_string();
         let mut path2 = path1.clone();
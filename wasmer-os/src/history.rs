@@ -0,0 +1,201 @@
+//! The shell history is kept in memory by the terminal and persisted to a
+//! file in the home directory of the user so that it survives sessions
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+#[allow(unused_imports, dead_code)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::wasmer_vfs::FileSystem;
+
+use super::environment::Environment;
+use super::fs::*;
+
+/// Maximum number of commands that are kept in the history
+pub const HISTORY_SIZE: usize = 1000;
+
+/// Returns the path of the history file (`$HISTFILE` or `~/.sh_history`)
+pub fn history_file(env: &Environment) -> String {
+    if let Some(path) = env.get("HISTFILE").filter(|a| a.len() > 0) {
+        return path;
+    }
+    let home = env.get("HOME").unwrap_or_else(|| "/".to_string());
+    format!("{}/.sh_history", home.trim_end_matches('/'))
+}
+
+/// Drops the oldest commands until the history fits within its limit
+pub fn trim_history(history: &mut Vec<String>) {
+    if history.len() > HISTORY_SIZE {
+        let excess = history.len() - HISTORY_SIZE;
+        history.drain(..excess);
+    }
+}
+
+/// Reads the history file (one command per line, oldest first) which
+/// is simply empty if it does not exist yet
+pub fn load_history(fs: &UnionFileSystem, path: &str) -> Vec<String> {
+    read_history(fs, path).0
+}
+
+/// Reads the history file along with the number of lines it holds (which
+/// is more than the number of commands when it has grown by appending)
+fn read_history(fs: &UnionFileSystem, path: &str) -> (Vec<String>, usize) {
+    let mut file = match fs.new_open_options().read(true).open(Path::new(path)) {
+        Ok(a) => a,
+        Err(_) => return (Vec::new(), 0),
+    };
+    let mut data = String::new();
+    if let Err(err) = file.read_to_string(&mut data) {
+        debug!("failed to read the history file ({}) - {}", path, err);
+        return (Vec::new(), 0);
+    }
+
+    let mut lines = 0usize;
+    let mut ret: Vec<String> = Vec::new();
+    for cmd in data.lines().filter(|l| l.trim().len() > 0) {
+        lines += 1;
+        ret.retain(|c| c != cmd);
+        ret.push(cmd.to_string());
+    }
+    trim_history(&mut ret);
+    (ret, lines)
+}
+
+/// Reads the history file and, when appending has left it holding
+/// duplicates or more than the history limit, rewrites it without them
+pub fn compact_history(fs: &UnionFileSystem, path: &str) -> Vec<String> {
+    let (history, lines) = read_history(fs, path);
+    if lines > history.len() {
+        debug!("compacting the history file ({}) - {} lines into {}", path, lines, history.len());
+        save_history(fs, path, &history[..]);
+    }
+    history
+}
+
+/// Adds a command to the end of the history file (which is cheaper than
+/// rewriting it and does not lose the commands of other sessions)
+pub fn append_history(fs: &UnionFileSystem, path: &str, cmd: &str) {
+    let mut file = match fs
+        .new_open_options()
+        .create(true)
+        .write(true)
+        .append(true)
+        .open(Path::new(path))
+    {
+        Ok(a) => a,
+        Err(err) => {
+            debug!("failed to open the history file ({}) - {}", path, err);
+            return;
+        }
+    };
+    let line = format!("{}\n", cmd);
+    if let Err(err) = file.write_all(line.as_bytes()) {
+        debug!("failed to write the history file ({}) - {}", path, err);
+    }
+}
+
+/// Writes the history file, failures are only logged as the shell works
+/// fine without it (e.g. when the home directory is read-only)
+pub fn save_history(fs: &UnionFileSystem, path: &str, history: &[String]) {
+    let mut file = match fs
+        .new_open_options()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(Path::new(path))
+    {
+        Ok(a) => a,
+        Err(err) => {
+            debug!("failed to open the history file ({}) - {}", path, err);
+            return;
+        }
+    };
+
+    let skip = history.len().saturating_sub(HISTORY_SIZE);
+    let mut data = String::new();
+    for cmd in history.iter().skip(skip) {
+        data.push_str(cmd.as_str());
+        data.push('\n');
+    }
+    if let Err(err) = file.write_all(data.as_bytes()) {
+        debug!("failed to write the history file ({}) - {}", path, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn home_fs() -> UnionFileSystem {
+        let mut fs = UnionFileSystem::new();
+        fs.mount("tmp", "/", false, Box::new(TmpFileSystem::new()), None);
+        fs
+    }
+
+    fn read_file(fs: &UnionFileSystem, path: &str) -> String {
+        let mut file = fs.new_open_options().read(true).open(Path::new(path)).unwrap();
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn history_file_location() {
+        let mut env = Environment::default();
+        assert_eq!(history_file(&env), "/.sh_history");
+        env.set_var("HOME", "/home/user/".to_string());
+        assert_eq!(history_file(&env), "/home/user/.sh_history");
+        env.set_var("HISTFILE", "/tmp/hist".to_string());
+        assert_eq!(history_file(&env), "/tmp/hist");
+    }
+
+    #[test]
+    fn trim_drops_the_oldest() {
+        let mut history = (0..HISTORY_SIZE + 5).map(|n| n.to_string()).collect::<Vec<_>>();
+        trim_history(&mut history);
+        assert_eq!(history.len(), HISTORY_SIZE);
+        assert_eq!(history.first().unwrap(), "5");
+        assert_eq!(history.last().unwrap(), &(HISTORY_SIZE + 4).to_string());
+    }
+
+    #[test]
+    fn save_then_load() {
+        let fs = home_fs();
+        assert!(load_history(&fs, "/.sh_history").is_empty());
+
+        let history = vec!["ls".to_string(), "cd /".to_string()];
+        save_history(&fs, "/.sh_history", &history[..]);
+        assert_eq!(read_file(&fs, "/.sh_history"), "ls\ncd /\n");
+        assert_eq!(load_history(&fs, "/.sh_history"), history);
+
+        // Saving again replaces the file
+        save_history(&fs, "/.sh_history", &history[1..]);
+        assert_eq!(load_history(&fs, "/.sh_history"), vec!["cd /".to_string()]);
+    }
+
+    #[test]
+    fn appends_are_compacted() {
+        let fs = home_fs();
+        for cmd in ["ls", "pwd", "ls", "", "echo"] {
+            append_history(&fs, "/.sh_history", cmd);
+        }
+        assert_eq!(read_file(&fs, "/.sh_history"), "ls\npwd\nls\n\necho\n");
+
+        // Duplicates keep their most recent position
+        let expected = vec!["pwd".to_string(), "ls".to_string(), "echo".to_string()];
+        assert_eq!(load_history(&fs, "/.sh_history"), expected);
+        assert_eq!(compact_history(&fs, "/.sh_history"), expected);
+        assert_eq!(read_file(&fs, "/.sh_history"), "pwd\nls\necho\n");
+    }
+
+    #[test]
+    fn loading_trims_to_the_limit() {
+        let fs = home_fs();
+        for n in 0..HISTORY_SIZE + 10 {
+            append_history(&fs, "/.sh_history", format!("echo {}", n).as_str());
+        }
+        let loaded = load_history(&fs, "/.sh_history");
+        assert_eq!(loaded.len(), HISTORY_SIZE);
+        assert_eq!(loaded.first().unwrap(), "echo 10");
+    }
+}
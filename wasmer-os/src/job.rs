@@ -4,10 +4,12 @@ use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::sync::Mutex;
 use tokio::sync::mpsc;
+use tokio::sync::watch;
 #[allow(unused_imports, dead_code)]
 use tracing::{debug, error, info, trace, warn};
 
 use crate::common::*;
+use crate::err;
 
 use super::environment::*;
 use super::fd::*;
@@ -16,13 +18,111 @@ use super::pipe::*;
 use super::reactor::*;
 use super::stdio::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    Stopped,
+    Done(u32),
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobStatus::Running => write!(f, "Running"),
+            JobStatus::Stopped => write!(f, "Stopped"),
+            JobStatus::Done(0) => write!(f, "Done"),
+            JobStatus::Done(code) => write!(f, "Exit {}", code),
+        }
+    }
+}
+
+/// Signals that can be delivered to the processes of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Hup,
+    Int,
+    Quit,
+    Kill,
+    Term,
+    Cont,
+    Stop,
+    Tstp,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 8] = [
+        Signal::Hup,
+        Signal::Int,
+        Signal::Quit,
+        Signal::Kill,
+        Signal::Term,
+        Signal::Cont,
+        Signal::Stop,
+        Signal::Tstp,
+    ];
+
+    pub fn number(&self) -> u32 {
+        match self {
+            Signal::Hup => 1,
+            Signal::Int => 2,
+            Signal::Quit => 3,
+            Signal::Kill => 9,
+            Signal::Term => 15,
+            Signal::Cont => 18,
+            Signal::Stop => 19,
+            Signal::Tstp => 20,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Hup => "HUP",
+            Signal::Int => "INT",
+            Signal::Quit => "QUIT",
+            Signal::Kill => "KILL",
+            Signal::Term => "TERM",
+            Signal::Cont => "CONT",
+            Signal::Stop => "STOP",
+            Signal::Tstp => "TSTP",
+        }
+    }
+
+    /// Parses a signal by its number or its name (with or without the `SIG` prefix)
+    pub fn parse(val: &str) -> Option<Signal> {
+        if let Ok(num) = val.parse::<u32>() {
+            return Signal::ALL.iter().filter(|s| s.number() == num).next().cloned();
+        }
+        let val = val.to_uppercase();
+        let val = val.strip_prefix("SIG").unwrap_or(val.as_str());
+        Signal::ALL.iter().filter(|s| s.name() == val).next().cloned()
+    }
+
+    /// Exit code that a process reports when this signal terminates it
+    /// (128 plus the signal number, the same as other shells report it)
+    pub fn exit_code(&self) -> NonZeroU32 {
+        NonZeroU32::new(128 + self.number()).unwrap()
+    }
+}
+
+#[derive(Debug)]
+pub struct JobState {
+    /// Command line that started the job
+    pub command: String,
+    /// Processes that belong to this job (its process group)
+    pub pids: Vec<Pid>,
+    /// Set once the job is no longer owned by the command line that started
+    /// it (it was started with `&` or suspended with Ctrl-Z)
+    pub background: bool,
+}
+
 #[derive(Debug)]
 pub struct Job {
     pub id: u32,
     pub stdin: Fd,
     pub stdin_tx: mpsc::Sender<FdMsg>,
-    pub job_list_tx: mpsc::Sender<Pid>,
-    pub job_list_rx: Arc<Mutex<mpsc::Receiver<Pid>>>,
+    pub state: Arc<Mutex<JobState>>,
+    pub status_tx: Arc<watch::Sender<JobStatus>>,
+    pub status_rx: watch::Receiver<JobStatus>,
 }
 
 impl Clone for Job {
@@ -31,8 +131,9 @@ impl Clone for Job {
             id: self.id,
             stdin: self.stdin.clone(),
             stdin_tx: self.stdin_tx.clone(),
-            job_list_tx: self.job_list_tx.clone(),
-            job_list_rx: self.job_list_rx.clone(),
+            state: self.state.clone(),
+            status_tx: self.status_tx.clone(),
+            status_rx: self.status_rx.clone(),
         }
     }
 }
@@ -40,22 +141,204 @@ impl Clone for Job {
 impl Job {
     pub fn new(id: u32) -> Job {
         let (stdin, stdin_tx) = pipe_in(ReceiverMode::Stream, FdFlag::Stdin(true));
-        let (job_list_tx, job_list_rx) = mpsc::channel(MAX_MPSC);
+        let (status_tx, status_rx) = watch::channel(JobStatus::Running);
         Job {
             id,
             stdin,
             stdin_tx,
-            job_list_tx,
-            job_list_rx: Arc::new(Mutex::new(job_list_rx)),
+            state: Arc::new(Mutex::new(JobState {
+                command: String::new(),
+                pids: Vec::new(),
+                background: false,
+            })),
+            status_tx: Arc::new(status_tx),
+            status_rx,
         }
     }
 
     pub fn terminate(&self, reactor: &mut Reactor, exit_code: NonZeroU32) {
         self.stdin.forced_exit(exit_code);
-        let mut rx = self.job_list_rx.lock().unwrap();
-        while let Ok(pid) = rx.try_recv() {
+        let pids = self.pids();
+        for pid in pids {
             Reactor::close_process(reactor, pid, exit_code.into());
         }
         debug!("job terminated (id={})", self.id);
     }
+
+    /// Returns the exit code if the job has been terminated
+    pub fn should_terminate(&self) -> Option<u32> {
+        self.stdin.ctx.should_terminate()
+    }
+
+    pub fn add_process(&self, pid: Pid) {
+        let mut state = self.state.lock().unwrap();
+        state.pids.push(pid);
+    }
+
+    pub fn pids(&self) -> Vec<Pid> {
+        let state = self.state.lock().unwrap();
+        state.pids.clone()
+    }
+
+    pub fn command(&self) -> String {
+        let state = self.state.lock().unwrap();
+        state.command.clone()
+    }
+
+    pub fn set_command(&self, command: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.command = command.trim().to_string();
+    }
+
+    pub fn is_background(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.background
+    }
+
+    pub fn set_background(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.background = true;
+    }
+
+    pub fn status(&self) -> JobStatus {
+        *self.status_rx.borrow()
+    }
+
+    fn set_status(&self, status: JobStatus) {
+        // Jobs that are done never come back to life
+        if let JobStatus::Done(_) = self.status() {
+            return;
+        }
+        let _ = self.status_tx.send(status);
+    }
+
+    /// Records the exit code of the job once all its processes have finished
+    pub fn finish(&self, exit_code: u32) {
+        self.set_status(JobStatus::Done(exit_code));
+    }
+
+    /// Suspends all the processes of the job
+    pub fn stop(&self, reactor: &Reactor) {
+        self.stdin.ctx.stop();
+        for pid in self.pids() {
+            if let Some(process) = reactor.get_process(pid) {
+                process.ctx.stop();
+            }
+        }
+        self.set_status(JobStatus::Stopped);
+        debug!("job stopped (id={})", self.id);
+    }
+
+    /// Resumes all the processes of the job (if it was stopped)
+    pub fn resume(&self, reactor: &Reactor) {
+        self.stdin.ctx.resume();
+        for pid in self.pids() {
+            if let Some(process) = reactor.get_process(pid) {
+                process.ctx.resume();
+            }
+        }
+        self.set_status(JobStatus::Running);
+        debug!("job resumed (id={})", self.id);
+    }
+
+    /// Delivers a signal to all the processes of the job
+    pub fn signal(&self, reactor: &mut Reactor, signal: Signal) {
+        match signal {
+            Signal::Stop | Signal::Tstp => self.stop(reactor),
+            Signal::Cont => self.resume(reactor),
+            signal => {
+                // Stopped processes must be released so that they can exit
+                self.resume(reactor);
+                self.terminate(reactor, signal.exit_code());
+            }
+        }
+    }
+
+    /// Waits until the job has finished and returns its exit code
+    pub async fn wait_for_exit(&self) -> u32 {
+        let mut rx = self.status_rx.clone();
+        loop {
+            if let JobStatus::Done(code) = *rx.borrow() {
+                return code;
+            }
+            if rx.changed().await.is_err() {
+                return err::ERR_ECONNABORTED;
+            }
+        }
+    }
+
+    /// Waits for as long as the job is stopped
+    pub async fn wait_while_stopped(&self) {
+        let mut rx = self.status_rx.clone();
+        while *rx.borrow() == JobStatus::Stopped {
+            if rx.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Describes the job the same way as the `jobs` builtin lists it
+    pub fn describe(&self, marker: char) -> String {
+        format!(
+            "[{}]{}  {:<24}{}",
+            self.id,
+            marker,
+            self.status().to_string(),
+            self.command()
+        )
+    }
+
+    /// Waits until the job has finished (returning its exit code) or until
+    /// it has been stopped (returning None)
+    pub async fn wait(&self) -> Option<u32> {
+        let mut rx = self.status_rx.clone();
+        loop {
+            let status = *rx.borrow();
+            match status {
+                JobStatus::Done(code) => return Some(code),
+                JobStatus::Stopped => return None,
+                JobStatus::Running => {}
+            }
+            if rx.changed().await.is_err() {
+                return Some(err::ERR_ECONNABORTED);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_exit_codes() {
+        let cases = [
+            (Signal::Hup, 129),
+            (Signal::Int, 130),
+            (Signal::Quit, 131),
+            (Signal::Kill, 137),
+            (Signal::Term, 143),
+        ];
+        for (signal, code) in cases {
+            assert_eq!(signal.exit_code().get(), code, "{:?}", signal);
+        }
+    }
+
+    #[test]
+    fn signals_parse_by_name_or_number() {
+        assert_eq!(Signal::parse("9"), Some(Signal::Kill));
+        assert_eq!(Signal::parse("TERM"), Some(Signal::Term));
+        assert_eq!(Signal::parse("sigstop"), Some(Signal::Stop));
+        assert_eq!(Signal::parse("BOGUS"), None);
+        assert_eq!(Signal::parse("4"), None);
+    }
+
+    #[test]
+    fn done_jobs_stay_done() {
+        let job = Job::new(1);
+        job.finish(143);
+        job.set_status(JobStatus::Running);
+        assert_eq!(job.status(), JobStatus::Done(143));
+        assert_eq!(job.describe('+'), format!("[1]+  {:<24}", "Exit 143"));
+    }
 }
@@ -11,6 +11,7 @@ pub mod console;
 pub mod environment;
 pub mod err;
 pub mod fd;
+pub mod history;
 pub mod job;
 pub mod pipe;
 pub mod poll;
@@ -34,6 +35,8 @@ pub use wasmer_compiler_cranelift;
 pub use wasmer_compiler_llvm;
 #[cfg(feature = "wasmer-compiler-singlepass")]
 pub use wasmer_compiler_singlepass;
+#[cfg(feature = "wasmer-middlewares")]
+pub use wasmer_middlewares;
 pub use wasmer_vfs;
 pub use wasmer_wasi;
 
@@ -33,6 +33,10 @@ pub struct Reactor {
     pub(crate) pid: HashMap<Pid, Process>,
     pub(crate) job: HashMap<u32, Job>,
     pub(crate) current_job: Option<u32>,
+    /// Jobs running in the background (or stopped) in the order they got
+    /// there, the last one is the current job (`%+`) of the job control
+    /// builtins and the one before it is the previous job (`%-`)
+    pub(crate) background: Vec<u32>,
 }
 
 impl Reactor {
@@ -43,6 +47,7 @@ impl Reactor {
             pid: HashMap::default(),
             job: HashMap::default(),
             current_job: None,
+            background: Vec::new(),
         }
     }
 
@@ -50,6 +55,7 @@ impl Reactor {
         self.pid.clear();
         self.job.clear();
         self.current_job.take();
+        self.background.clear();
     }
 
     pub fn get_process(&self, pid: Pid) -> Option<Process> {
@@ -111,6 +117,7 @@ impl Reactor {
         if self.current_job == Some(job_id) {
             self.current_job.take();
         }
+        self.background.retain(|id| *id != job_id);
         if let Some(job) = self.job.remove(&job_id) {
             job.terminate(self, exit_code);
             debug!("job closed: id={}", job.id);
@@ -137,4 +144,87 @@ impl Reactor {
             .filter_map(|job| self.get_job(*job))
             .next()
     }
+
+    /// Moves the job into the background (or makes it the current background
+    /// job if it is already there)
+    pub fn background_job(&mut self, job: &Job) {
+        job.set_background();
+        self.background.retain(|id| *id != job.id);
+        self.background.push(job.id);
+    }
+
+    /// Returns all the jobs in the background sorted by their ID
+    pub fn background_jobs(&self) -> Vec<Job> {
+        let mut ret = self
+            .background
+            .iter()
+            .filter_map(|id| self.get_job(*id))
+            .collect::<Vec<_>>();
+        ret.sort_by_key(|job| job.id);
+        ret
+    }
+
+    /// Returns the marker that `jobs` shows next to a background job
+    pub fn job_marker(&self, job_id: u32) -> char {
+        let mut order = self.background.iter().rev();
+        if order.next() == Some(&job_id) {
+            '+'
+        } else if order.next() == Some(&job_id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Finds a background job from a job specification (`%1`, `%%`, `%+`,
+    /// `%-`, `%name` or `%?text`)
+    pub fn find_job(&self, spec: &str) -> Option<Job> {
+        let spec = spec.strip_prefix('%').unwrap_or(spec);
+        let id = match spec {
+            "" | "%" | "+" => self.background.last().cloned(),
+            "-" => self.background.iter().rev().skip(1).next().cloned(),
+            spec => match spec.parse::<u32>() {
+                Ok(id) => self.background.iter().filter(|a| **a == id).next().cloned(),
+                Err(_) => {
+                    let (contains, text) = match spec.strip_prefix('?') {
+                        Some(text) => (true, text),
+                        None => (false, spec),
+                    };
+                    self.background_jobs()
+                        .into_iter()
+                        .rev()
+                        .filter(|job| match contains {
+                            true => job.command().contains(text),
+                            false => job.command().starts_with(text),
+                        })
+                        .map(|job| job.id)
+                        .next()
+                }
+            },
+        };
+        id.and_then(|id| self.get_job(id))
+    }
+
+    /// Finds the background job that a process belongs to
+    pub fn find_job_of_process(&self, pid: Pid) -> Option<Job> {
+        self.job
+            .values()
+            .filter(|job| job.pids().contains(&pid))
+            .next()
+            .cloned()
+    }
+
+    /// Removes the background jobs that have finished and returns them so
+    /// that they can be reported
+    pub fn reap_jobs(&mut self) -> Vec<(char, Job)> {
+        let mut ret = Vec::new();
+        for job in self.background_jobs() {
+            if let JobStatus::Done(code) = job.status() {
+                ret.push((self.job_marker(job.id), job.clone()));
+                let exit_code = NonZeroU32::new(code).unwrap_or_else(|| NonZeroU32::new(ERR_TERMINATED).unwrap());
+                self.close_job(job, exit_code);
+            }
+        }
+        ret
+    }
 }
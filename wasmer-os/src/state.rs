@@ -29,6 +29,8 @@ pub struct ConsoleState {
     pub last_return: u32,
     pub unfinished_line: Arc<AtomicBool>,
     pub rootfs: UnionFileSystem,
+    /// History file that the shell history was last loaded from
+    pub history_file: Option<String>,
 }
 
 impl ConsoleState {
@@ -40,6 +42,7 @@ impl ConsoleState {
             last_return: 0,
             unfinished_line,
             rootfs: root,
+            history_file: None,
         }
     }
 
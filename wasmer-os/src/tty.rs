@@ -16,6 +16,7 @@ use super::cconst::*;
 use super::common::*;
 use super::err;
 use super::fd::*;
+use super::history::*;
 use super::job::*;
 use super::reactor::*;
 use super::stdout::*;
@@ -41,6 +42,20 @@ struct TtyInnerAsync {
     pub prompt_color: String,
     pub cols: u32,
     pub rows: u32,
+    pub kill_buffer: String,
+    pub search: Option<HistorySearch>,
+}
+
+/// State of an incremental reverse search through the history (Ctrl-R)
+struct HistorySearch {
+    /// Text that is being searched for
+    query: String,
+    /// Position in the history of the current match (or the length of the
+    /// history when nothing matched)
+    index: usize,
+    /// Line that was being edited before the search started
+    original: String,
+    failed: bool,
 }
 
 #[derive(Debug)]
@@ -52,11 +67,61 @@ impl TtyInnerAsync {
     pub fn reset_line(&mut self) {
         self.line.clear();
         self.cursor_pos = 0;
+        self.search = None;
     }
 
     pub fn reset_history_cursor(&mut self) {
         self.cursor_history = 0;
     }
+
+    /// Finds the start of the word before the cursor
+    fn word_left(&self, is_word: fn(char) -> bool) -> usize {
+        let mut pos = self.cursor_pos;
+        let mut in_word = false;
+        for (n, c) in self.line[..self.cursor_pos].char_indices().rev() {
+            if is_word(c) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            pos = n;
+        }
+        pos
+    }
+
+    /// Finds the end of the word after the cursor
+    fn word_right(&self, is_word: fn(char) -> bool) -> usize {
+        let mut pos = self.cursor_pos;
+        let mut in_word = false;
+        for (n, c) in self.line[self.cursor_pos..].char_indices() {
+            if is_word(c) {
+                in_word = true;
+            } else if in_word {
+                return self.cursor_pos + n;
+            }
+            pos = self.cursor_pos + n + c.len_utf8();
+        }
+        pos
+    }
+
+    /// Finds the most recent command (before a point in the history) that
+    /// contains some text
+    fn search_before(&self, query: &str, before: usize) -> Option<usize> {
+        if query.len() <= 0 {
+            return None;
+        }
+        self.history[..before.min(self.history.len())]
+            .iter()
+            .rposition(|cmd| cmd.contains(query))
+    }
+}
+
+fn is_alphanumeric(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+fn is_not_whitespace(c: char) -> bool {
+    c.is_whitespace() == false
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -106,6 +171,8 @@ impl Tty {
                 prompt_color: "$".to_string(),
                 cols: 1,
                 rows: 1,
+                kill_buffer: String::new(),
+                search: None,
             })),
             inner_sync: Arc::new(TtyInnerSync {
                 buffering: AtomicBool::new(true),
@@ -243,6 +310,32 @@ impl Tty {
         debug!("add-history: {}", cmd);
         inner.history.retain(|c| c.ne(&cmd));
         inner.history.push(cmd);
+        trim_history(&mut inner.history);
+    }
+
+    pub async fn history(&self) -> Vec<String> {
+        let inner = self.inner_async.lock().await;
+        inner.history.clone()
+    }
+
+    /// Adds older commands (e.g. loaded from the history file) in front of
+    /// the commands recorded in this session
+    pub async fn merge_history(&self, older: Vec<String>) {
+        let mut inner = self.inner_async.lock().await;
+        let mut history = older;
+        for cmd in inner.history.drain(..) {
+            history.retain(|c| c.ne(&cmd));
+            history.push(cmd);
+        }
+        trim_history(&mut history);
+        inner.history = history;
+        inner.reset_history_cursor();
+    }
+
+    pub async fn clear_history(&self) {
+        let mut inner = self.inner_async.lock().await;
+        inner.history.clear();
+        inner.reset_history_cursor();
     }
 
     pub async fn get_paragraph(&self) -> String {
@@ -384,15 +477,26 @@ impl Tty {
             mode
         };
 
+        // Jobs in the background live on after they leave the terminal
         let mut reactor = reactor.write().await;
         match last_mode {
-            TtyMode::StdIn(job) => {
+            TtyMode::StdIn(job) if job.is_background() == false => {
                 reactor.close_job(job, std::num::NonZeroU32::new(err::ERR_TERMINATED).unwrap());
             }
             _ => {}
         }
     }
 
+    /// Switches the mode of the terminal without closing the job it was
+    /// attached to and returns the mode it was in (this is how jobs are
+    /// moved in and out of the foreground)
+    pub async fn switch_mode(&self, mut mode: TtyMode) -> TtyMode {
+        self.set_buffering(true);
+        let mut inner = self.inner_async.lock().await;
+        std::mem::swap(&mut inner.mode, &mut mode);
+        mode
+    }
+
     pub fn set_buffering(&self, on: bool) {
         debug!("set_buffering on={}", on);
         self.inner_sync.buffering.store(on, Ordering::Relaxed);
@@ -516,6 +620,262 @@ impl Tty {
         }
     }
 
+    /// Returns the line being edited and the position of the cursor in it
+    pub async fn line(&self) -> (String, usize) {
+        let inner = self.inner_async.lock().await;
+        (inner.line.clone(), inner.cursor_pos)
+    }
+
+    /// Replaces the line being edited and redraws it
+    pub async fn set_line(&mut self, line: String, cursor_pos: usize) {
+        let echo = self.inner_async.lock().await.echo;
+        if echo {
+            self.set_cursor_to_start().await;
+            self.draw_undo().await;
+        }
+        {
+            let mut inner = self.inner_async.lock().await;
+            inner.cursor_pos = cursor_pos.min(line.len());
+            inner.line = line;
+        }
+        if echo {
+            self.draw_line().await;
+        }
+    }
+
+    /// Draws the whole line (the cursor must be at the start of it) and
+    /// then moves the cursor back to where it is in the line
+    pub async fn draw_line(&mut self) {
+        let (line, shift_left) = {
+            let inner = self.inner_async.lock().await;
+            let shift_left = inner.line[inner.cursor_pos..].chars().count();
+            (inner.line.clone(), shift_left)
+        };
+        let mut chars = String::new();
+        chars += Tty::TERM_WRAPAROUND;
+        chars += line.as_str();
+        for _ in 0..shift_left {
+            chars += Tty::TERM_CURSOR_LEFT;
+        }
+        self.draw(chars.as_str()).await
+    }
+
+    async fn move_cursor(&mut self, pos: usize) {
+        let (chars, echo) = {
+            let mut inner = self.inner_async.lock().await;
+            let chars = if pos < inner.cursor_pos {
+                let count = inner.line[pos..inner.cursor_pos].chars().count();
+                std::iter::repeat(Tty::TERM_CURSOR_LEFT).take(count).collect::<String>()
+            } else {
+                let count = inner.line[inner.cursor_pos..pos].chars().count();
+                std::iter::repeat(Tty::TERM_CURSOR_RIGHT).take(count).collect::<String>()
+            };
+            inner.cursor_pos = pos;
+            (chars, inner.echo)
+        };
+        if echo && chars.len() > 0 {
+            self.draw(chars.as_str()).await
+        }
+    }
+
+    pub async fn cursor_word_left(&mut self) {
+        let pos = self.inner_async.lock().await.word_left(is_alphanumeric);
+        self.move_cursor(pos).await;
+    }
+
+    pub async fn cursor_word_right(&mut self) {
+        let pos = self.inner_async.lock().await.word_right(is_alphanumeric);
+        self.move_cursor(pos).await;
+    }
+
+    /// Removes part of the line and keeps it in the kill buffer so that it
+    /// can be yanked back again
+    async fn kill(&mut self, start: usize, end: usize) {
+        let line = {
+            let mut inner = self.inner_async.lock().await;
+            if start >= end {
+                return;
+            }
+            inner.kill_buffer = inner.line[start..end].to_string();
+            format!("{}{}", &inner.line[..start], &inner.line[end..])
+        };
+        self.set_line(line, start).await;
+    }
+
+    pub async fn kill_to_start(&mut self) {
+        let end = self.inner_async.lock().await.cursor_pos;
+        self.kill(0, end).await;
+    }
+
+    pub async fn kill_to_end(&mut self) {
+        let (start, end) = {
+            let inner = self.inner_async.lock().await;
+            (inner.cursor_pos, inner.line.len())
+        };
+        self.kill(start, end).await;
+    }
+
+    /// Kills the whitespace delimited word before the cursor (Ctrl-W)
+    pub async fn kill_word_left(&mut self) {
+        let (start, end) = {
+            let inner = self.inner_async.lock().await;
+            (inner.word_left(is_not_whitespace), inner.cursor_pos)
+        };
+        self.kill(start, end).await;
+    }
+
+    /// Kills the word after the cursor (Alt-D)
+    pub async fn kill_word_right(&mut self) {
+        let (start, end) = {
+            let inner = self.inner_async.lock().await;
+            (inner.cursor_pos, inner.word_right(is_alphanumeric))
+        };
+        self.kill(start, end).await;
+    }
+
+    pub async fn yank(&mut self) {
+        let text = self.inner_async.lock().await.kill_buffer.clone();
+        if text.len() > 0 {
+            self.add(text.as_str()).await;
+        }
+    }
+
+    pub async fn is_searching(&self) -> bool {
+        self.inner_async.lock().await.search.is_some()
+    }
+
+    /// Starts a reverse search through the history or, when one is already
+    /// running, moves on to the next older match (Ctrl-R)
+    pub async fn search_history(&mut self) {
+        {
+            let mut inner = self.inner_async.lock().await;
+            match inner.search.take() {
+                None => {
+                    let index = inner.history.len();
+                    let original = inner.line.clone();
+                    inner.search = Some(HistorySearch {
+                        query: String::new(),
+                        index,
+                        original,
+                        failed: false,
+                    });
+                }
+                Some(mut search) => {
+                    match inner.search_before(search.query.as_str(), search.index) {
+                        Some(index) => {
+                            search.index = index;
+                            search.failed = false;
+                            inner.line = inner.history[index].clone();
+                        }
+                        None => search.failed = search.query.len() > 0,
+                    }
+                    inner.search = Some(search);
+                }
+            }
+            inner.cursor_pos = inner.line.len();
+        }
+        self.draw_search().await;
+    }
+
+    /// Extends the text that is being searched for
+    pub async fn search_add(&mut self, data: &str) {
+        {
+            let mut inner = self.inner_async.lock().await;
+            let mut search = match inner.search.take() {
+                Some(a) => a,
+                None => return,
+            };
+            search.query.push_str(data);
+
+            // The current match is kept for as long as it still matches
+            match inner.search_before(search.query.as_str(), search.index + 1) {
+                Some(index) => {
+                    search.index = index;
+                    search.failed = false;
+                    inner.line = inner.history[index].clone();
+                }
+                None => search.failed = true,
+            }
+            inner.search = Some(search);
+            inner.cursor_pos = inner.line.len();
+        }
+        self.draw_search().await;
+    }
+
+    /// Removes the last character of the search text and searches again
+    /// from the most recent command
+    pub async fn search_backspace(&mut self) {
+        {
+            let mut inner = self.inner_async.lock().await;
+            let mut search = match inner.search.take() {
+                Some(a) => a,
+                None => return,
+            };
+            search.query.pop();
+            search.index = inner.history.len();
+            search.failed = false;
+            inner.line = search.original.clone();
+            if let Some(index) = inner.search_before(search.query.as_str(), search.index) {
+                search.index = index;
+                inner.line = inner.history[index].clone();
+            } else {
+                search.failed = search.query.len() > 0;
+            }
+            inner.search = Some(search);
+            inner.cursor_pos = inner.line.len();
+        }
+        self.draw_search().await;
+    }
+
+    /// Ends the search and leaves the matched command on the line
+    pub async fn search_accept(&mut self) {
+        if self.inner_async.lock().await.search.take().is_none() {
+            return;
+        }
+        self.draw_prompt().await;
+        self.draw_line().await;
+    }
+
+    /// Ends the search and restores the line as it was before it started
+    pub async fn search_abort(&mut self) {
+        {
+            let mut inner = self.inner_async.lock().await;
+            let search = match inner.search.take() {
+                Some(a) => a,
+                None => return,
+            };
+            inner.line = search.original;
+            inner.cursor_pos = inner.line.len();
+        }
+        self.draw_prompt().await;
+        self.draw_line().await;
+    }
+
+    async fn draw_search(&mut self) {
+        let chars = {
+            let inner = self.inner_async.lock().await;
+            let search = match inner.search.as_ref() {
+                Some(a) => a,
+                None => return,
+            };
+            let mut chars = String::new();
+            chars += "\r";
+            chars += Tty::TERM_DELETE_BELOW;
+            chars += Tty::TERM_DELETE_LINE;
+            chars += Tty::TERM_WRAPAROUND;
+            if search.failed {
+                chars += "(failed reverse-i-search)`";
+            } else {
+                chars += "(reverse-i-search)`";
+            }
+            chars += search.query.as_str();
+            chars += "': ";
+            chars += inner.line.as_str();
+            chars
+        };
+        self.draw(chars.as_str()).await;
+    }
+
     pub async fn draw_undo(&mut self) -> String {
         let mut chars = String::new();
         chars += Tty::TERM_CURSOR_SAVE;
@@ -545,3 +905,69 @@ impl Tty {
         self.stdout.flush_async().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer_bus::task::block_on;
+
+    fn tty(history: &[&str]) -> Tty {
+        // (nothing reads what is drawn so the output is simply dropped)
+        let (fd, _) = pipe_out(FdFlag::Stdout(false));
+        let tty = Tty::new(Stdout::new(fd.clone()), fd.clone(), fd, TtyOuter::Normal);
+        block_on(tty.merge_history(history.iter().map(|a| a.to_string()).collect()));
+        tty
+    }
+
+    fn line(tty: &Tty) -> (String, Option<bool>) {
+        let inner = block_on(tty.inner_async.lock());
+        (inner.line.clone(), inner.search.as_ref().map(|s| s.failed))
+    }
+
+    #[test]
+    fn reverse_search_walks_back_through_matches() {
+        let mut tty = tty(&["git status", "ls -l", "git commit", "cd /"]);
+
+        block_on(tty.search_history());
+        assert_eq!(line(&tty), ("".to_string(), Some(false)));
+        block_on(tty.search_add("git"));
+        assert_eq!(line(&tty), ("git commit".to_string(), Some(false)));
+
+        // Ctrl-R again moves to older matches until there are none left
+        block_on(tty.search_history());
+        assert_eq!(line(&tty), ("git status".to_string(), Some(false)));
+        block_on(tty.search_history());
+        assert_eq!(line(&tty), ("git status".to_string(), Some(true)));
+
+        // Narrowing the search keeps the current match when it still fits
+        block_on(tty.search_backspace());
+        assert_eq!(line(&tty), ("git commit".to_string(), Some(false)));
+        block_on(tty.search_add("t s"));
+        assert_eq!(line(&tty), ("git status".to_string(), Some(false)));
+
+        block_on(tty.search_accept());
+        assert_eq!(line(&tty), ("git status".to_string(), None));
+    }
+
+    #[test]
+    fn reverse_search_can_be_aborted() {
+        let mut tty = tty(&["ls -l"]);
+        block_on(tty.set_line("pwd".to_string(), 3));
+
+        block_on(tty.search_history());
+        block_on(tty.search_add("missing"));
+        assert_eq!(line(&tty), ("pwd".to_string(), Some(true)));
+        block_on(tty.search_backspace());
+        block_on(tty.search_abort());
+        assert_eq!(line(&tty), ("pwd".to_string(), None));
+    }
+
+    #[test]
+    fn history_merges_older_commands_first() {
+        let tty = tty(&["a", "b"]);
+        block_on(tty.record_history("c".to_string()));
+        block_on(tty.record_history("a".to_string()));
+        block_on(tty.merge_history(vec!["b".to_string(), "d".to_string()]));
+        assert_eq!(block_on(tty.history()), vec!["d", "b", "c", "a"]);
+    }
+}
@@ -0,0 +1,431 @@
+//! Runs shell scripts through the evaluator of a real console and checks
+//! what they write to stdout, only the builtins (and small programs that
+//! are written into /bin as WAT) are used so that the scripts do not need
+//! to download any binaries
+use std::path::Path;
+use std::sync::Arc;
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+use wasmer_os::bin_factory::CachedCompiledModules;
+use wasmer_os::console::Console;
+use wasmer_os::eval::Compiler;
+use wasmer_os::eval::EvalStatus;
+use wasmer_os::fd::*;
+use wasmer_os::fs::*;
+use wasmer_os::pipe::*;
+use wasmer_term::system::SysSystem;
+
+static SYSTEM: Lazy<SysSystem> = Lazy::new(|| {
+    let (tx_exit, _) = watch::channel(false);
+    let sys = SysSystem::new(None, tx_exit);
+    wasmer_os::api::set_system_abi(sys.clone());
+    sys
+});
+
+/// Runs the script (after writing the supplied files) and returns its
+/// output and exit code
+fn run_with_files(script: &str, files: &[(&str, &str)]) -> (String, u32) {
+    let sys = SYSTEM.clone();
+    let script = script.to_string();
+    let files = files
+        .iter()
+        .map(|(a, b)| (a.to_string(), b.to_string()))
+        .collect::<Vec<_>>();
+    sys.block_on(async move {
+        let mut console = Console::new(
+            "wss://localhost/?no_welcome".to_string(),
+            "noagent".to_string(),
+            Compiler::default(),
+            Arc::new(SYSTEM.clone()),
+            None,
+            create_root_fs(None),
+            Arc::new(CachedCompiledModules::new(None)),
+        );
+
+        let fs = AsyncifyFileSystem::new(console.root_fs());
+        for (path, data) in files {
+            let parents = Path::new(path.as_str()).ancestors().skip(1).collect::<Vec<_>>();
+            for parent in parents.into_iter().rev().skip(1) {
+                let _ = fs.create_dir(parent).await;
+            }
+            let mut file = fs
+                .new_open_options()
+                .await
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(Path::new(path.as_str()))
+                .await
+                .unwrap();
+            file.write_all(data.into_bytes()).await.unwrap();
+        }
+
+        let job = console.new_job().await.unwrap();
+        let mut ctx = console.new_spawn_context(&job);
+        let (stdout, mut rx) = pipe_out(FdFlag::Stdout(false));
+        ctx.stdout = stdout;
+
+        let result = console
+            .exec_factory()
+            .eval(script, ctx)
+            .recv()
+            .await
+            .unwrap();
+        let code = match result.status {
+            EvalStatus::Executed { code, .. } => code,
+            status => panic!("script was not executed - {:?}", status),
+        };
+        drop(result);
+
+        let mut output = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            if let FdMsg::Data { data, .. } = msg {
+                output.extend(data);
+            }
+        }
+        (String::from_utf8_lossy(&output[..]).to_string(), code)
+    })
+}
+
+fn run(script: &str) -> String {
+    run_with_files(script, &[]).0
+}
+
+#[test]
+fn if_elif_else() {
+    let script = r#"
+x=2
+if [ $x -eq 1 ]; then
+    echo one
+elif [ $x -eq 2 ]; then
+    echo two
+else
+    echo other
+fi
+if false; then echo yes; else echo no; fi
+"#;
+    assert_eq!(run(script), "two\nno\n");
+}
+
+#[test]
+fn for_loop_with_command_substitution() {
+    let script = r#"
+for word in $(echo a b c) "d e"; do
+    echo "[$word]"
+done
+"#;
+    assert_eq!(run(script), "[a]\n[b]\n[c]\n[d e]\n");
+}
+
+#[test]
+fn loops_with_break_and_continue() {
+    let script = r#"
+for n in 0 1 2 3 4 5 6; do
+    case $n in
+        2) continue;;
+        5) break;;
+    esac
+    echo $n
+done
+for i in 1 2; do
+    for j in a b; do
+        if [ $j = b ]; then break 2; fi
+        echo $i$j
+    done
+done
+x=a
+while [ $x != aaa ]; do x=${x}a; echo $x; done
+until [ $x = a ]; do x=a; echo reset; done
+"#;
+    assert_eq!(run(script), "0\n1\n3\n4\n1a\naa\naaa\nreset\n");
+}
+
+#[test]
+fn case_patterns() {
+    let script = r#"
+for f in main.rs lib.c README x; do
+    case $f in
+        *.rs|*.c) echo "$f source";;
+        [A-Z]*) echo "$f upper";;
+        ?) echo "$f single";;
+        *) echo "$f other"
+    esac
+done
+"#;
+    assert_eq!(
+        run(script),
+        "main.rs source\nlib.c source\nREADME upper\nx single\n"
+    );
+}
+
+#[test]
+fn functions_and_positional_arguments() {
+    let script = r#"
+greet() {
+    echo "hello $1 ($#)"
+    if [ "$2" = stop ]; then
+        return 3
+    fi
+    echo "bye $1"
+}
+greet world
+greet there stop
+echo "code $?"
+count() { for a; do echo "arg $a"; done; }
+count x "y z"
+"#;
+    assert_eq!(
+        run(script),
+        "hello world (1)\nbye world\nhello there (2)\ncode 3\narg x\narg y z\n"
+    );
+}
+
+#[test]
+fn subshell_isolates_variables() {
+    let script = r#"
+x=outer
+(x=inner; echo $x)
+echo $x
+{ x=group; }
+echo $x
+"#;
+    assert_eq!(run(script), "inner\nouter\ngroup\n");
+}
+
+#[test]
+fn here_documents() {
+    // The lines of the documents are never run as commands
+    let script = "name=world\n\
+                  echo start\n\
+                  test -n x <<EOF\n\
+                  echo $name; echo ignored\n\
+                  EOF\n\
+                  echo middle\n\
+                  test -n x <<-'EOF'\n\
+                  \t$name stays\n\
+                  \tEOF\n\
+                  echo end\n";
+    assert_eq!(run(script), "start\nmiddle\nend\n");
+}
+
+#[test]
+fn source_with_arguments() {
+    let (output, code) = run_with_files(
+        "source /script.sh first second\necho \"after $#\"\n",
+        &[("/script.sh", "echo \"$1 $2\"\nshift\necho \"$1 $#\"\nreturn\necho unreachable\n")],
+    );
+    assert_eq!(output, "first second\nsecond 1\nafter 0\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn negation_and_exit_codes() {
+    assert_eq!(run_with_files("! false", &[]).1, 0);
+    assert_eq!(run_with_files("! true", &[]).1, 1);
+    assert_eq!(run_with_files("[ 1 -lt 2 ] && [ a != b ]", &[]).1, 0);
+    assert_eq!(run_with_files("test -d /script.sh", &[("/script.sh", "")]).1, 1);
+    assert_eq!(run_with_files("test -f /script.sh", &[("/script.sh", "")]).1, 0);
+}
+
+#[test]
+fn word_expansions() {
+    let script = r#"
+HOME=/home/user
+echo ~ ~/docs "~"
+unset x
+echo "${x:-default}" "${x:=set}" $x ${#x}
+path=dir/sub/file.tar.gz
+echo ${path##*/} ${path%%.*} ${path#*/} ${path%.*}
+echo $((1 + 2 * 3)) $(( (1 + 2) * 3 )) $((x = 4, x ** 2))
+echo pre{a,b}post {1..3} '{a,b}'
+words="a  b   c"
+for w in $words; do echo "[$w]"; done
+for w in "$words"; do echo "[$w]"; done
+echo /g*.txt "/g*.txt" /missing*
+"#;
+    let (output, code) = run_with_files(script, &[("/g1.txt", ""), ("/g2.txt", ""), ("/other.md", "")]);
+    assert_eq!(
+        output,
+        "/home/user /home/user/docs ~\n\
+         default set set 3\n\
+         file.tar.gz dir/sub/file sub/file.tar.gz dir/sub/file.tar\n\
+         7 9 16\n\
+         preapost prebpost 1 2 3 {a,b}\n\
+         [a]\n[b]\n[c]\n[a  b   c]\n\
+         /g1.txt /g2.txt /g*.txt /missing*\n"
+    );
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn failed_expansions_stop_the_command() {
+    let (output, code) = run_with_files("echo ${missing:?not set}\necho $((1 / 0))\necho after\n", &[]);
+    assert_eq!(output, "after\n");
+    assert_eq!(code, 0);
+    assert_eq!(run_with_files("echo ${missing:?}", &[]).1, 1);
+}
+
+#[test]
+fn job_control_without_jobs() {
+    let script = r#"
+jobs; echo "jobs $?"
+fg; echo "fg $?"
+bg %2; echo "bg $?"
+kill -l 9 SIGTERM
+kill %1; echo "kill $?"
+kill -s BOGUS 1; echo "signal $?"
+wait; echo "wait $?"
+"#;
+    assert_eq!(
+        run(script),
+        "jobs 0\nfg 1\nbg 1\nKILL\n15\nkill 1\nsignal 1\nwait 0\n"
+    );
+}
+
+/// Process that yields forever (it only exits when it is signalled)
+const SPIN_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "sched_yield" (func $yield (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $forever
+      (drop (call $yield))
+      (br $forever))))
+"#;
+
+/// Process that yields for a while and then exits with code 7
+const COUNTDOWN_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "sched_yield" (func $yield (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start") (local $n i32)
+    (local.set $n (i32.const 200000))
+    (loop $again
+      (drop (call $yield))
+      (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+      (br_if $again (local.get $n)))
+    (call $exit (i32.const 7))))
+"#;
+
+fn run_with_jobs(script: &str) -> (String, u32) {
+    run_with_files(script, &[("/bin/spin", SPIN_WAT), ("/bin/countdown", COUNTDOWN_WAT)])
+}
+
+#[test]
+fn stopped_jobs_resume_in_the_background() {
+    let script = r#"
+spin &
+kill -STOP %1; echo "stop $?"
+jobs
+bg
+jobs
+bg %1; echo "bg $?"
+kill %1
+wait %1; echo "wait $?"
+jobs; echo "jobs $?"
+"#;
+    let (output, code) = run_with_jobs(script);
+    assert_eq!(
+        output,
+        "stop 0\n\
+         [1]+  Stopped                 spin &\n\
+         [1]+ spin &\n\
+         [1]+  Running                 spin &\n\
+         bg 0\n\
+         wait 143\n\
+         jobs 0\n"
+    );
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn stopped_jobs_resume_in_the_foreground() {
+    let script = r#"
+countdown &
+kill -s TSTP %1
+jobs
+fg %1; echo "fg $?"
+jobs
+fg; echo "fg $?"
+"#;
+    let (output, _) = run_with_jobs(script);
+    assert_eq!(
+        output,
+        "[1]+  Stopped                 countdown &\n\
+         countdown &\n\
+         fg 7\n\
+         fg 1\n"
+    );
+}
+
+#[test]
+fn killed_jobs_report_the_signal() {
+    let script = r#"
+spin &
+spin &
+jobs
+kill -9 %1
+wait %1; echo "kill %1 $?"
+kill -INT $!
+wait $!; echo "kill pid $?"
+kill %2; echo "kill %2 $?"
+"#;
+    let (output, _) = run_with_jobs(script);
+    assert_eq!(
+        output,
+        "[1]-  Running                 spin &\n\
+         [2]+  Running                 spin &\n\
+         kill %1 137\n\
+         kill pid 130\n\
+         kill %2 1\n"
+    );
+}
+
+#[test]
+fn module_cache_statistics() {
+    let output = run("cachestat");
+    assert!(output.starts_with("compiled module cache\n"));
+    assert!(output.contains("  memory:   0 modules, 0 B of 512.0 MB\n"));
+    assert!(output.contains("  disk:     disabled\n"));
+    assert!(output.contains("  misses:   0\n"));
+}
+
+#[test]
+fn overlay_mounts_copy_up_changes() {
+    let script = r#"
+mount --overlay /etc /etc
+echo 'echo upper' > /etc/a.sh
+source /etc/a.sh
+echo new > /etc/b.sh
+echo /etc/*.sh
+umount /etc
+source /etc/a.sh
+echo /etc/*.sh
+"#;
+    let (output, code) = run_with_files(script, &[("/etc/a.sh", "echo lower")]);
+    assert_eq!(output, "upper\n/etc/a.sh /etc/b.sh\nlower\n/etc/a.sh\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn relative_globs_use_the_working_directory() {
+    let script = r#"
+cd /dir
+echo *.txt
+cd sub
+echo *.txt
+cd /
+echo d*/*.md
+"#;
+    let files = [
+        ("/dir/a.txt", ""),
+        ("/dir/b.txt", ""),
+        ("/dir/c.md", ""),
+        ("/dir/sub/d.txt", ""),
+        ("/e.txt", ""),
+    ];
+    let (output, code) = run_with_files(script, &files);
+    assert_eq!(output, "a.txt b.txt\nd.txt\ndir/c.md\n");
+    assert_eq!(code, 0);
+}